@@ -149,6 +149,25 @@ pub struct Preferences {
     pub dom_testperf_enabled: bool,
     // https://testutils.spec.whatwg.org#availability
     pub dom_testutils_enabled: bool,
+    /// Enable `<script>` elements containing WebAssembly text (WAT) or binaries.
+    pub dom_wat_scripts_enabled: bool,
+    /// Comma-separated WebAssembly proposals beyond 2.0 that WAT scripts may use,
+    /// e.g. `gc,exceptions`; also limits the capabilities reported to pages.
+    pub dom_wat_scripts_features: String,
+    /// Megabytes of WebAssembly memory the WAT scripts of a document may allocate
+    /// together; 0 for no limit.
+    pub dom_wat_scripts_memory_budget_mb: i64,
+    /// Megabytes a pre-compressed `.wat.gz` or `.wat.br` source may decompress
+    /// to; 0 for no limit.
+    pub dom_wat_scripts_max_source_mb: i64,
+    /// Allow WAT scripts of any origin to use shared memory and threads.
+    pub dom_wat_scripts_threads_enabled: bool,
+    /// Comma-separated origins whose WAT scripts may use shared memory and threads
+    /// when `dom_wat_scripts_threads_enabled` is false, e.g. `https://example.com`.
+    pub dom_wat_scripts_threads_origins: String,
+    /// Comma-separated origins allowed to use WAT scripts even when
+    /// `dom_wat_scripts_enabled` is false, e.g. `https://example.com`.
+    pub dom_wat_scripts_trusted_origins: String,
     /// Enable WebGL2 APIs.
     pub dom_webgl2_enabled: bool,
     pub dom_webrtc_enabled: bool,
@@ -340,6 +359,15 @@ impl Preferences {
             dom_testing_html_input_element_select_files_enabled: false,
             dom_testperf_enabled: false,
             dom_testutils_enabled: false,
+            dom_wat_scripts_enabled: true,
+            dom_wat_scripts_features: String::from(
+                "gc,threads,exceptions,tail-call,simd,relaxed-simd,memory64,multi-memory,extended-const",
+            ),
+            dom_wat_scripts_memory_budget_mb: 1024,
+            dom_wat_scripts_max_source_mb: 64,
+            dom_wat_scripts_threads_enabled: false,
+            dom_wat_scripts_threads_origins: String::new(),
+            dom_wat_scripts_trusted_origins: String::new(),
             dom_webgl2_enabled: false,
             dom_webgpu_enabled: false,
             dom_webgpu_wgpu_backend: String::new(),
@@ -133,7 +133,11 @@ impl TimelineActor {
         pipeline_id: PipelineId,
         script_sender: GenericSender<DevtoolScriptControlMsg>,
     ) -> TimelineActor {
-        let marker_types = vec![TimelineMarkerType::Reflow, TimelineMarkerType::DOMEvent];
+        let marker_types = vec![
+            TimelineMarkerType::Reflow,
+            TimelineMarkerType::DOMEvent,
+            TimelineMarkerType::Wasm,
+        ];
 
         TimelineActor {
             name,
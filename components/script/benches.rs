@@ -0,0 +1,69 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use criterion::*;
+use script::test::wasm_compiler::{
+    compile_bench, field_names_json, generate_glue, inject_datacount_section, module_metadata,
+    parse_wat,
+};
+
+/// A GC module with `count` struct types, each with a constructor, a getter
+/// and a passive data segment read with `array.new_data`
+fn create_module(count: usize) -> String {
+    let mut source = String::from("(module\n  (type $bytes (array (mut i8)))\n");
+    for i in 0..count {
+        source.push_str(&format!(
+            r#"  (type $point{i} (struct (field $x (mut i32)) (field $y (mut i32))))
+  (data $label{i} "point number {i}")
+  (func (export "make{i}") (param $x i32) (param $y i32) (result (ref $point{i}))
+    (struct.new $point{i} (local.get $x) (i32.add (local.get $y) (i32.const {i}))))
+  (func (export "x{i}") (param $p (ref $point{i})) (result i32)
+    (struct.get $point{i} $x (local.get $p)))
+  (func (export "label{i}") (result (ref $bytes))
+    (array.new_data $bytes $label{i} (i32.const 0) (i32.const 12)))
+"#
+        ));
+    }
+    source.push(')');
+    source
+}
+
+fn bench(c: &mut Criterion) {
+    for count in [10, 100] {
+        let source = create_module(count);
+        let binary = compile_bench(&source).expect("benchmark module should compile");
+        let parsed = parse_wat(&source).expect("benchmark module should parse");
+        let metadata = module_metadata(&source, &binary);
+
+        c.bench_function(&format!("wat_parse_{count}"), |b| {
+            b.iter(|| parse_wat(black_box(&source)))
+        });
+        c.bench_function(&format!("compile_{count}"), |b| {
+            b.iter(|| compile_bench(black_box(&source)))
+        });
+        c.bench_function(&format!("field_names_{count}"), |b| {
+            b.iter(|| field_names_json(black_box(&binary)))
+        });
+        c.bench_function(&format!("module_metadata_{count}"), |b| {
+            b.iter(|| module_metadata(black_box(&source), black_box(&binary)))
+        });
+        c.bench_function(&format!("datacount_injection_{count}"), |b| {
+            b.iter_batched(
+                || parsed.clone(),
+                |mut binary| inject_datacount_section(&mut binary),
+                BatchSize::SmallInput,
+            )
+        });
+        c.bench_function(&format!("glue_generation_{count}"), |b| {
+            b.iter_batched(
+                || binary.clone(),
+                |binary| generate_glue(&source, binary, &metadata),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -4,15 +4,18 @@
 
 use std::borrow::Cow;
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::ffi::CStr;
 use std::fs::read_to_string;
 use std::path::PathBuf;
 use std::rc::Rc;
 
 use base::id::{PipelineId, WebViewId};
+use devtools_traits::{TimelineMarker, TimelineMarkerType};
 use dom_struct::dom_struct;
 use encoding_rs::Encoding;
 use html5ever::{LocalName, Prefix, local_name, ns};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
 use js::jsval::UndefinedValue;
 use js::rust::{HandleObject, Stencil};
 use net_traits::http_status::HttpStatus;
@@ -22,6 +25,7 @@ use net_traits::request::{
     RequestBuilder, RequestId,
 };
 use net_traits::{FetchMetadata, Metadata, NetworkError, ResourceFetchTiming};
+use servo_config::pref;
 use servo_url::{ImmutableOrigin, ServoUrl};
 use style::attr::AttrValue;
 use style::str::{HTML_SPACE_CHARACTERS, StaticStringVec};
@@ -55,7 +59,10 @@ use crate::dom::element::{
 use crate::dom::event::{Event, EventBubbles, EventCancelable};
 use crate::dom::globalscope::{ClassicScript, ErrorReporting, GlobalScope, RethrowErrors};
 use crate::dom::html::htmlelement::HTMLElement;
-use crate::dom::node::{ChildrenMutation, CloneChildrenFlag, Node, NodeTraits};
+use crate::dom::html::htmlmetaelement::HTMLMetaElement;
+use crate::dom::node::{
+    ChildrenMutation, CloneChildrenFlag, Node, NodeTraits, ShadowIncluding, UnbindContext,
+};
 use crate::dom::performance::performanceresourcetiming::InitiatorType;
 use crate::dom::trustedscript::TrustedScript;
 use crate::dom::trustedscripturl::TrustedScriptURL;
@@ -68,6 +75,11 @@ use crate::script_module::{
     fetch_inline_module_script, parse_an_import_map_string, register_import_map,
 };
 use crate::script_runtime::{CanGc, IntroductionType};
+use crate::wasm_compiler::registry::{self, RegisteredName};
+use crate::wasm_compiler::speculative::{self, Priority, Speculation};
+use crate::wasm_compiler::{
+    CompileOptions, PageDefaults, compressed, patch, shared, sniff, timeline,
+};
 
 /// An unique id for script element.
 #[derive(Clone, Copy, Debug, Eq, Hash, JSTraceable, PartialEq)]
@@ -116,6 +128,19 @@ pub(crate) struct HTMLScriptElement {
 
     /// Stores the script type for external scripts (used for TypeScript/WASM compilation)
     external_script_type: Cell<Option<ScriptType>>,
+
+    /// Number of clones made of this element, numbering the next one
+    clones: Cell<u32>,
+
+    /// Appended to the module name of a WAT script cloned from another, so the
+    /// instances of a template load their modules under names of their own
+    clone_suffix: DomRefCell<String>,
+
+    /// Background compilation of the module of a `data-lazy` WAT script,
+    /// cancelled if the script is removed before it starts
+    #[ignore_malloc_size_of = "Arc"]
+    #[no_trace]
+    lazy_compile: DomRefCell<Option<Speculation>>,
 }
 
 impl HTMLScriptElement {
@@ -138,6 +163,9 @@ impl HTMLScriptElement {
             from_an_external_file: Cell::new(false),
             introduction_type_override: Cell::new(None),
             external_script_type: Cell::new(None),
+            clones: Cell::new(0),
+            clone_suffix: DomRefCell::new(String::new()),
+            lazy_compile: DomRefCell::new(None),
         }
     }
 
@@ -186,6 +214,52 @@ pub(crate) static SCRIPT_JS_MIMES: StaticStringVec = &[
     "text/x-javascript",
 ];
 
+/// Types of WAT and WebAssembly scripts, in the `type` attribute or the MIME
+/// type of a `data:` URL
+static SCRIPT_WAT_MIMES: StaticStringVec = &[
+    "application/wasm",
+    "binary/wasm",
+    "text/wasm",
+    "text/wast",
+    "text/wat",
+];
+
+/// Extensions of pre-compressed WAT and WebAssembly sources
+const WAT_COMPRESSED_EXTENSIONS: [&str; 2] = [".gz", ".br"];
+
+/// Whether `url` names a WAT or WebAssembly script, by its extension, which
+/// may be followed by a compression one, or, for a `data:` URL, its MIME type
+fn is_wat_url(url: &ServoUrl) -> bool {
+    if url.scheme() == "data" {
+        let mime = url.path().split([',', ';']).next().unwrap_or_default();
+        return SCRIPT_WAT_MIMES.contains(&mime.trim().to_ascii_lowercase().as_str());
+    }
+    let path = WAT_COMPRESSED_EXTENSIONS
+        .iter()
+        .find_map(|extension| url.path().strip_suffix(extension))
+        .unwrap_or(url.path());
+    path.ends_with(".wat") || path.ends_with(".wasm")
+}
+
+/// What identifies the content of a WAT source fetched from `url` along with
+/// the URL: its strong ETag, or else its Last-Modified date
+fn wat_source_validator(url: &ServoUrl, headers: Option<&HeaderMap>) -> Option<String> {
+    let headers = headers?;
+    let etag = headers
+        .get(header::ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"));
+    let value = etag.or_else(|| headers.get(header::LAST_MODIFIED))?;
+    Some(format!("{} {}", url, value.to_str().ok()?))
+}
+
+/// The source of a `.gz` or `.br` WAT script served as is, decompressed up to
+/// the `dom_wat_scripts_max_source_mb` pref, see `wasm_compiler::compressed`
+fn decompress_wat_source(url: &ServoUrl, body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let limit = compressed::limit_from_pref(pref!(dom_wat_scripts_max_source_mb));
+    compressed::decompress(url.path(), body, limit)
+        .map_err(|error| format!("Failed to decompress {}: {}", url, error))
+}
+
 #[derive(Clone, Copy, Debug, JSTraceable, MallocSizeOf, PartialEq)]
 pub(crate) enum ScriptType {
     Classic,
@@ -220,6 +294,9 @@ pub(crate) struct ScriptOrigin {
     type_: ScriptType,
     unminified_dir: Option<String>,
     import_map: Fallible<ImportMap>,
+    /// Phases of the compilation of a WAT script, for the devtools timeline
+    #[no_trace]
+    wasm_timeline: Vec<timeline::Span>,
 }
 
 impl ScriptOrigin {
@@ -230,48 +307,59 @@ impl ScriptOrigin {
         type_: ScriptType,
         unminified_dir: Option<String>,
         import_map: Fallible<ImportMap>,
+        wasm_options: CompileOptions,
     ) -> ScriptOrigin {
+        let mut wasm_timeline = Vec::new();
         // Compile TypeScript to JavaScript if needed
-        let (code_text, actual_type) = if type_ == ScriptType::TypeScript || type_ == ScriptType::TypeScriptModule {
-            use crate::typescript_compiler;
-            let source_str = text.str().to_string();
-            match typescript_compiler::compile_typescript_to_js(&source_str, url.as_str()) {
-                Ok(js_code) => {
-                    let js_dom_string = Rc::new(DOMString::from(js_code));
-                    let new_type = if type_ == ScriptType::TypeScriptModule {
-                        ScriptType::Module
-                    } else {
-                        ScriptType::Classic
-                    };
-                    (js_dom_string, new_type)
-                },
-                Err(e) => {
-                    // On compilation error, convert to empty classic script to avoid execution
-                    warn!("TypeScript compilation error: {}", e);
-                    let empty_script = Rc::new(DOMString::from(String::new()));
-                    (empty_script, ScriptType::Classic)
+        let (code_text, actual_type) =
+            if type_ == ScriptType::TypeScript || type_ == ScriptType::TypeScriptModule {
+                use crate::typescript_compiler;
+                let source_str = text.str().to_string();
+                match typescript_compiler::compile_typescript_to_js(&source_str, url.as_str()) {
+                    Ok(js_code) => {
+                        let js_dom_string = Rc::new(DOMString::from(js_code));
+                        let new_type = if type_ == ScriptType::TypeScriptModule {
+                            ScriptType::Module
+                        } else {
+                            ScriptType::Classic
+                        };
+                        (js_dom_string, new_type)
+                    },
+                    Err(e) => {
+                        // On compilation error, convert to empty classic script to avoid execution
+                        warn!("TypeScript compilation error: {}", e);
+                        let empty_script = Rc::new(DOMString::from(String::new()));
+                        (empty_script, ScriptType::Classic)
+                    },
                 }
-            }
-        } else if type_ == ScriptType::Wasm {
-            // Compile WAT to JavaScript that loads the WASM module
-            use crate::wasm_compiler;
-            let source_str = text.str().to_string();
-            match wasm_compiler::compile_wat_to_js(&source_str, url.as_str(), None) {
-                Ok(js_code) => {
-                    let js_dom_string = Rc::new(DOMString::from(js_code));
-                    (js_dom_string, ScriptType::Classic)
-                },
-                Err(e) => {
-                    // On compilation error, emit error as console.error
-                    warn!("WASM compilation error: {}", e);
-                    let error_msg = format!("console.error('WASM compilation error: {}');", e.to_string().replace("'", "\\'"));
-                    let error_script = Rc::new(DOMString::from(error_msg));
-                    (error_script, ScriptType::Classic)
+            } else if type_ == ScriptType::Wasm {
+                // Compile WAT to JavaScript that loads the WASM module
+                use crate::wasm_compiler;
+                let source_str = text.str().to_string();
+                let filename = wasm_options.name.clone().unwrap_or_else(|| url.to_string());
+                let (compiled, spans) = timeline::capture(|| {
+                    wasm_compiler::compile_wat_to_js(&source_str, &filename, None, &wasm_options)
+                });
+                wasm_timeline = spans;
+                match compiled {
+                    Ok(js_code) => {
+                        let js_dom_string = Rc::new(DOMString::from(js_code));
+                        (js_dom_string, ScriptType::Classic)
+                    },
+                    Err(e) => {
+                        // On compilation error, emit error as console.error
+                        warn!("WASM compilation error: {}", e);
+                        let error_msg = format!(
+                            "console.error('WASM compilation error: {}');",
+                            e.to_string().replace("'", "\\'")
+                        );
+                        let error_script = Rc::new(DOMString::from(error_msg));
+                        (error_script, ScriptType::Classic)
+                    },
                 }
-            }
-        } else {
-            (text, type_)
-        };
+            } else {
+                (text, type_)
+            };
 
         ScriptOrigin {
             code: SourceCode::Text(code_text),
@@ -281,6 +369,7 @@ impl ScriptOrigin {
             type_: actual_type,
             unminified_dir,
             import_map,
+            wasm_timeline,
         }
     }
 
@@ -291,47 +380,60 @@ impl ScriptOrigin {
         type_: ScriptType,
         unminified_dir: Option<String>,
         callback: Option<String>,
+        wasm_options: CompileOptions,
     ) -> ScriptOrigin {
+        let mut wasm_timeline = Vec::new();
         // Compile TypeScript to JavaScript if needed
-        let (code_text, actual_type) = if type_ == ScriptType::TypeScript || type_ == ScriptType::TypeScriptModule {
-            use crate::typescript_compiler;
-            let source_str = text.str().to_string();
-            match typescript_compiler::compile_typescript_to_js(&source_str, url.as_str()) {
-                Ok(js_code) => {
-                    let js_dom_string = Rc::new(DOMString::from(js_code));
-                    let new_type = if type_ == ScriptType::TypeScriptModule {
-                        ScriptType::Module
-                    } else {
-                        ScriptType::Classic
-                    };
-                    (js_dom_string, new_type)
-                },
-                Err(e) => {
-                    // On compilation error, convert to empty classic script to avoid execution
-                    warn!("TypeScript compilation error: {}", e);
-                    let empty_script = Rc::new(DOMString::from(String::new()));
-                    (empty_script, ScriptType::Classic)
+        let (code_text, actual_type) =
+            if type_ == ScriptType::TypeScript || type_ == ScriptType::TypeScriptModule {
+                use crate::typescript_compiler;
+                let source_str = text.str().to_string();
+                match typescript_compiler::compile_typescript_to_js(&source_str, url.as_str()) {
+                    Ok(js_code) => {
+                        let js_dom_string = Rc::new(DOMString::from(js_code));
+                        let new_type = if type_ == ScriptType::TypeScriptModule {
+                            ScriptType::Module
+                        } else {
+                            ScriptType::Classic
+                        };
+                        (js_dom_string, new_type)
+                    },
+                    Err(e) => {
+                        // On compilation error, convert to empty classic script to avoid execution
+                        warn!("TypeScript compilation error: {}", e);
+                        let empty_script = Rc::new(DOMString::from(String::new()));
+                        (empty_script, ScriptType::Classic)
+                    },
                 }
-            }
-        } else if type_ == ScriptType::Wasm {
-            // Compile WAT to JavaScript that loads the WASM module
-            use crate::wasm_compiler;
-            let source_str = text.str().to_string();
-            let callback_ref = callback.as_deref();
-            match wasm_compiler::compile_wat_to_js(&source_str, url.as_str(), callback_ref) {
-                Ok(js_code) => {
-                    let js_dom_string = Rc::new(DOMString::from(js_code));
-                    (js_dom_string, ScriptType::Classic)
-                },
-                Err(e) => {
-                    warn!("WASM compilation error: {}", e);
-                    let empty_script = Rc::new(DOMString::from(String::new()));
-                    (empty_script, ScriptType::Classic)
+            } else if type_ == ScriptType::Wasm {
+                // Compile WAT to JavaScript that loads the WASM module
+                use crate::wasm_compiler;
+                let source_str = text.str().to_string();
+                let callback_ref = callback.as_deref();
+                let filename = wasm_options.name.clone().unwrap_or_else(|| url.to_string());
+                let (compiled, spans) = timeline::capture(|| {
+                    wasm_compiler::compile_wat_to_js(
+                        &source_str,
+                        &filename,
+                        callback_ref,
+                        &wasm_options,
+                    )
+                });
+                wasm_timeline = spans;
+                match compiled {
+                    Ok(js_code) => {
+                        let js_dom_string = Rc::new(DOMString::from(js_code));
+                        (js_dom_string, ScriptType::Classic)
+                    },
+                    Err(e) => {
+                        warn!("WASM compilation error: {}", e);
+                        let empty_script = Rc::new(DOMString::from(String::new()));
+                        (empty_script, ScriptType::Classic)
+                    },
                 }
-            }
-        } else {
-            (text, type_)
-        };
+            } else {
+                (text, type_)
+            };
 
         ScriptOrigin {
             code: SourceCode::Text(code_text),
@@ -341,6 +443,7 @@ impl ScriptOrigin {
             type_: actual_type,
             unminified_dir,
             import_map: Err(Error::NotFound(None)),
+            wasm_timeline,
         }
     }
 
@@ -492,6 +595,30 @@ impl FetchResponseListener for ClassicContext {
 
         let metadata = self.metadata.take().unwrap();
         let final_url = metadata.final_url;
+        let validator = wat_source_validator(&final_url, metadata.headers.as_deref());
+
+        // A WAT script may be pre-compressed, and a WAT script fetched before
+        // may be answered with a patch against the source it had then, see
+        // `wasm_compiler::patch`
+        if self.elem.root().external_script_type.get() == Some(ScriptType::Wasm) {
+            let source = decompress_wat_source(&final_url, std::mem::take(&mut self.data))
+                .and_then(|body| {
+                    patch::resolve(self.url.as_str(), body).map_err(|error| error.to_string())
+                });
+            match source {
+                Ok(source) => self.data = source,
+                Err(error) => {
+                    finish_fetching_a_classic_script(
+                        &self.elem.root(),
+                        self.kind,
+                        self.url.clone(),
+                        Err(NoTrace(NetworkError::Internal(error))),
+                        CanGc::note(),
+                    );
+                    return;
+                },
+            }
+        }
 
         // Step 5.3. Let potentialMIMETypeForEncoding be the result of extracting a MIME type given response's header list.
         // Step 5.4. Set encoding to the result of legacy extracting an encoding given potentialMIMETypeForEncoding and encoding.
@@ -501,7 +628,15 @@ impl FetchResponseListener for ClassicContext {
             .unwrap_or(self.character_encoding);
 
         // Step 5.5. Let sourceText be the result of decoding bodyBytes to Unicode, using encoding as the fallback encoding.
-        let (mut source_text, _, _) = encoding.decode(&self.data);
+        // A binary module would be corrupted by decoding, so it is carried
+        // in base64, see `wasm_compiler::sniff`
+        let (mut source_text, _, _) = match self.elem.root().external_script_type.get() {
+            Some(ScriptType::Wasm) => match sniff::binary_as_text(&self.data) {
+                Some(text) => (Cow::Owned(text), encoding, false),
+                None => encoding.decode(&self.data),
+            },
+            _ => encoding.decode(&self.data),
+        };
 
         let elem = self.elem.root();
         let global = elem.global();
@@ -568,6 +703,34 @@ impl FetchResponseListener for ClassicContext {
             None
         };
 
+        // A `data-lazy` or low priority WAT script waits for its module to be
+        // compiled in the background, see `wasm_compiler::speculative`, so the
+        // modules of other scripts are compiled first
+        if script_type == ScriptType::Wasm
+            && (elem
+                .upcast::<Element>()
+                .has_attribute(&LocalName::from("data-lazy"))
+                || elem.wat_priority() == Priority::Low)
+        {
+            let wasm_options = CompileOptions {
+                validator,
+                ..elem.wasm_compile_options(&final_url)
+            };
+            elem.compile_wat_lazily(
+                self.kind,
+                self.url.clone(),
+                final_url,
+                self.fetch_options.clone(),
+                source_text.into_owned(),
+                callback,
+                wasm_options,
+            );
+            if let Ok(response) = response {
+                network_listener::submit_timing(&self, &response, CanGc::note());
+            }
+            return;
+        }
+
         let load = if script_type == ScriptType::TypeScript || script_type == ScriptType::TypeScriptModule || script_type == ScriptType::Wasm {
             Script::Other(ScriptOrigin::external(
                 Rc::new(DOMString::from(source_text)),
@@ -576,6 +739,10 @@ impl FetchResponseListener for ClassicContext {
                 script_type,
                 elem.parser_document.global().unminified_js_dir(),
                 callback,
+                CompileOptions {
+                    validator,
+                    ..elem.wasm_compile_options(&final_url)
+                },
             ))
         } else {
             Script::Classic(script)
@@ -621,6 +788,73 @@ impl ResourceTimingListener for ClassicContext {
     }
 }
 
+/// Whether WAT scripts may run for `origin`, per the `dom_wat_scripts_enabled`
+/// and `dom_wat_scripts_trusted_origins` preferences
+pub(crate) fn wat_scripts_enabled(origin: &ImmutableOrigin) -> bool {
+    pref!(dom_wat_scripts_enabled) || origin_listed(&pref!(dom_wat_scripts_trusted_origins), origin)
+}
+
+/// Whether WAT scripts of `origin` may use shared memory and threads, per the
+/// `dom_wat_scripts_threads_enabled` and `dom_wat_scripts_threads_origins`
+/// preferences
+pub(crate) fn wat_threads_permitted(origin: &ImmutableOrigin) -> bool {
+    pref!(dom_wat_scripts_threads_enabled)
+        || origin_listed(&pref!(dom_wat_scripts_threads_origins), origin)
+}
+
+/// Whether `origin` is in `origins`, separated by commas
+fn origin_listed(origins: &str, origin: &ImmutableOrigin) -> bool {
+    if origins.is_empty() {
+        return false;
+    }
+    let origin = origin.ascii_serialization();
+    origins
+        .split(',')
+        .any(|listed| listed.trim().trim_end_matches('/') == origin)
+}
+
+/// Names of the `<meta>` elements that configure the WAT scripts of a page
+const WAT_META_NAMES: [&str; 3] = ["wat-compiler", "wasm-memory", "wasm-table"];
+
+/// Defaults of the WAT scripts of the page, from the first of the contents
+/// of its `<meta name="wat-compiler">` elements
+fn wat_page_defaults(contents: &[String]) -> PageDefaults {
+    contents
+        .first()
+        .map(|content| PageDefaults::parse(content))
+        .unwrap_or_default()
+}
+
+/// Memories the page shares between its WAT scripts, from the contents of
+/// its `<meta name="wasm-memory">` elements
+fn wat_shared_memories(contents: &[String]) -> Vec<shared::SharedMemory> {
+    contents
+        .iter()
+        .filter_map(|content| {
+            let memory = shared::SharedMemory::parse(content);
+            if memory.is_none() {
+                warn!("Ignoring invalid <meta name=\"wasm-memory\"> {:?}", content);
+            }
+            memory
+        })
+        .collect()
+}
+
+/// Tables the page shares between its WAT scripts, from the contents of its
+/// `<meta name="wasm-table">` elements
+fn wat_shared_tables(contents: &[String]) -> Vec<shared::SharedTable> {
+    contents
+        .iter()
+        .filter_map(|content| {
+            let table = shared::SharedTable::parse(content);
+            if table.is_none() {
+                warn!("Ignoring invalid <meta name=\"wasm-table\"> {:?}", content);
+            }
+            table
+        })
+        .collect()
+}
+
 /// Steps 1-2 of <https://html.spec.whatwg.org/multipage/#fetch-a-classic-script>
 // This function is also used to prefetch a script in `script::dom::servoparser::prefetch`.
 #[allow(clippy::too_many_arguments)]
@@ -679,7 +913,20 @@ fn fetch_a_classic_script(
         doc.has_trustworthy_ancestor_origin(),
         global.policy_container(),
     );
-    let request = doc.prepare_request(request);
+    let mut request = doc.prepare_request(request);
+
+    // Offer the server to answer with a patch against the source this WAT
+    // script had when last fetched; same-origin only, the header would need
+    // a CORS preflight otherwise
+    if script.external_script_type.get() == Some(ScriptType::Wasm)
+        && url.origin() == *doc.origin().immutable()
+        && let Some(hash) = patch::base_hash(url.as_str())
+        && let Ok(value) = HeaderValue::from_str(&hash)
+    {
+        request
+            .headers
+            .insert(HeaderName::from_static(patch::BASE_HEADER), value);
+    }
 
     // TODO: Step 3, Add custom steps to perform fetch
 
@@ -756,7 +1003,11 @@ impl HTMLScriptElement {
         // Step 5a. Let source text be el’s script text value.
         let text = self.script_text.borrow().clone();
         // Step 6. If el has no src attribute, and source text is the empty string, then return.
-        if text.is_empty() && !element.has_attribute(&local_name!("src")) {
+        // An empty WAT script may load a registered module, see `run_registered_wat_module`
+        if text.is_empty()
+            && !element.has_attribute(&local_name!("src"))
+            && !element.has_attribute(&LocalName::from("data-register"))
+        {
             return;
         }
 
@@ -954,7 +1205,12 @@ impl HTMLScriptElement {
                     ScriptType::TypeScript
                 } else if path.ends_with(".mts") {
                     ScriptType::TypeScriptModule
-                } else if path.ends_with(".wat") || path.ends_with(".wasm") {
+                } else if is_wat_url(&url) {
+                    if !self.wat_scripts_enabled() {
+                        // Not run, like a script of an unsupported type
+                        debug!("WAT scripts are disabled for this origin");
+                        return;
+                    }
                     ScriptType::Wasm
                 } else {
                     script_type
@@ -963,6 +1219,13 @@ impl HTMLScriptElement {
                 script_type
             };
 
+            if script_type == ScriptType::Wasm {
+                let callback = (!text.is_empty()).then(|| text.to_string());
+                if self.run_registered_wat_module(&url, callback, options.clone(), can_gc) {
+                    return;
+                }
+            }
+
             // Store script type for use when fetch completes
             self.external_script_type.set(Some(script_type));
 
@@ -1030,7 +1293,19 @@ impl HTMLScriptElement {
         } else {
             // Step 32. If el does not have a src content attribute:
 
-            assert!(!text.is_empty());
+            if script_type == ScriptType::Wasm
+                && self.run_registered_wat_module(&base_url, None, options.clone(), can_gc)
+            {
+                return;
+            }
+            if text.is_empty() {
+                // Only scripts with a `data-register` naming no registered
+                // module get here
+                if script_type == ScriptType::Wasm {
+                    self.queue_error_event();
+                }
+                return;
+            }
 
             let text_rc = Rc::new(text.clone());
 
@@ -1063,6 +1338,7 @@ impl HTMLScriptElement {
                     }
                 },
                 ScriptType::TypeScript | ScriptType::Wasm => {
+                    let wasm_options = self.wasm_compile_options(&base_url);
                     let result = Ok(Script::Other(ScriptOrigin::internal(
                         text_rc,
                         base_url,
@@ -1070,6 +1346,7 @@ impl HTMLScriptElement {
                         script_type,
                         self.global().unminified_js_dir(),
                         Err(Error::NotFound(None)),
+                        wasm_options,
                     )));
 
                     if was_parser_inserted &&
@@ -1235,6 +1512,22 @@ impl HTMLScriptElement {
                         // Convert the compiled ScriptOrigin code to a ClassicScript
                         if let SourceCode::Text(ref text) = script.code {
                             let window = self.owner_window();
+                            // Compiled WAT: the phases of its compilation and the
+                            // evaluation of the glue go in the devtools timeline
+                            let emit_markers = !script.wasm_timeline.is_empty()
+                                && window.need_emit_timeline_marker(TimelineMarkerType::Wasm);
+                            if emit_markers {
+                                for span in &script.wasm_timeline {
+                                    window.emit_timeline_marker(TimelineMarker {
+                                        name: format!("WASM {}", span.phase),
+                                        start_time: span.start,
+                                        start_stack: None,
+                                        end_time: span.end,
+                                        end_stack: None,
+                                    });
+                                }
+                            }
+                            let marker = TimelineMarker::start("WASM glue evaluation".to_owned());
                             let global = window.as_global_scope();
                             let classic_script = global.create_a_classic_script(
                                 text.str().to_string().into(),
@@ -1250,6 +1543,9 @@ impl HTMLScriptElement {
                                 RethrowErrors::No,
                                 can_gc,
                             );
+                            if emit_markers {
+                                window.emit_timeline_marker(marker.end());
+                            }
                         }
 
                         document.set_current_script(old_script.as_deref());
@@ -1365,6 +1661,213 @@ impl HTMLScriptElement {
         );
     }
 
+    /// Whether WAT scripts may run in this document, see [`wat_scripts_enabled`]
+    fn wat_scripts_enabled(&self) -> bool {
+        wat_scripts_enabled(self.owner_document().origin().immutable())
+    }
+
+    /// Name of a WAT script loaded from `url` in diagnostics, if the URL does
+    /// not make a good one: `data:` URLs are named after the document and line
+    /// of the element, the scripts of srcdoc documents after their iframe's
+    /// document rather than its base URL, and clones after their original
+    fn wat_script_name(&self, url: &ServoUrl) -> Option<String> {
+        let document = self.owner_document();
+        let srcdoc = document.url().as_str() == "about:srcdoc";
+        let document_name = if srcdoc {
+            format!("{}#srcdoc", document.fallback_base_url())
+        } else {
+            document.url().to_string()
+        };
+        let name = if url.scheme() == "data" {
+            Some(format!("{}:{}", document_name, self.line_number))
+        } else if srcdoc && !self.from_an_external_file.get() {
+            Some(document_name)
+        } else {
+            None
+        };
+
+        let clone_suffix = self.clone_suffix.borrow();
+        if clone_suffix.is_empty() {
+            return name;
+        }
+        Some(name.unwrap_or_else(|| url.to_string()) + &clone_suffix)
+    }
+
+    /// Name of the module this WAT script registers or loads, see
+    /// `wasm_compiler::registry`; there are none for opaque origins
+    fn wat_registered_name(&self) -> Option<RegisteredName> {
+        let name = self
+            .upcast::<Element>()
+            .get_attribute(&ns!(), &LocalName::from("data-register"))?;
+        let name = name.value().trim().to_string();
+        let origin = self.owner_document().origin().immutable().clone();
+        (!name.is_empty() && origin.is_tuple()).then(|| RegisteredName {
+            origin: origin.ascii_serialization(),
+            name,
+        })
+    }
+
+    /// Load the module registered under the name of this WAT script instead of
+    /// fetching or compiling its source
+    /// Returns whether a module is registered under that name
+    fn run_registered_wat_module(
+        &self,
+        url: &ServoUrl,
+        callback: Option<String>,
+        options: ScriptFetchOptions,
+        can_gc: CanGc,
+    ) -> bool {
+        let Some(name) = self.wat_registered_name() else {
+            return false;
+        };
+        let filename = self.wat_script_name(url).unwrap_or_else(|| url.to_string());
+        let Some(result) = registry::compile_registered(
+            &name,
+            &filename,
+            callback.as_deref(),
+            &self.wasm_compile_options(url),
+        ) else {
+            return false;
+        };
+        match result {
+            Ok(code) => {
+                let script = ScriptOrigin::internal(
+                    Rc::new(DOMString::from(code)),
+                    url.clone(),
+                    options,
+                    ScriptType::Classic,
+                    self.global().unminified_js_dir(),
+                    Err(Error::NotFound(None)),
+                    CompileOptions::default(),
+                );
+                self.execute(Ok(Script::Other(script)), can_gc);
+            },
+            Err(e) => {
+                warn!("WASM compilation error: {}", e);
+                self.queue_error_event();
+            },
+        }
+        true
+    }
+
+    /// Priority of the background compilation of a WAT script, from its
+    /// `data-priority` attribute or else its `fetchpriority` one
+    fn wat_priority(&self) -> Priority {
+        let element = self.upcast::<Element>();
+        ["data-priority", "fetchpriority"]
+            .into_iter()
+            .filter_map(|name| element.get_attribute(&ns!(), &LocalName::from(name)))
+            .find_map(|attr| Priority::parse(&attr.value()))
+            .unwrap_or_default()
+    }
+
+    /// Compile the module of a fetched `data-lazy` WAT script in the background,
+    /// then finish fetching the script, which finds its module in the cache
+    /// The script fails to load if it is removed before the compilation starts.
+    #[allow(clippy::too_many_arguments)]
+    fn compile_wat_lazily(
+        &self,
+        kind: ExternalScriptKind,
+        url: ServoUrl,
+        final_url: ServoUrl,
+        fetch_options: ScriptFetchOptions,
+        source: String,
+        callback: Option<String>,
+        options: CompileOptions,
+    ) {
+        let filename = options
+            .name
+            .clone()
+            .unwrap_or_else(|| final_url.to_string());
+        let elem = Trusted::new(self);
+        let task_source = self
+            .owner_global()
+            .task_manager()
+            .dom_manipulation_task_source()
+            .to_sendable();
+        let speculation = speculative::compile(
+            source.clone().into_bytes(),
+            filename,
+            options.clone(),
+            self.wat_priority(),
+            move |cancelled| {
+                task_source.queue(task!(finish_lazy_wat_script: move || {
+                    let elem = elem.root();
+                    elem.lazy_compile.borrow_mut().take();
+                    let load = if cancelled {
+                        Err(NoTrace(NetworkError::Internal(
+                            "WAT script removed before its module was compiled".to_owned(),
+                        )))
+                    } else {
+                        Ok(Script::Other(ScriptOrigin::external(
+                            Rc::new(DOMString::from(source)),
+                            final_url,
+                            fetch_options,
+                            ScriptType::Wasm,
+                            elem.parser_document.global().unminified_js_dir(),
+                            callback,
+                            options,
+                        )))
+                    };
+                    finish_fetching_a_classic_script(&elem, kind, url, load, CanGc::note());
+                }));
+            },
+        );
+        *self.lazy_compile.borrow_mut() = Some(speculation);
+    }
+
+    /// Contents of the `<meta>` elements of the page that configure its WAT
+    /// scripts, by name in document order, from one traversal of the document
+    fn wat_meta_contents(&self) -> HashMap<String, Vec<String>> {
+        let mut contents: HashMap<String, Vec<String>> = HashMap::new();
+        for meta in self
+            .owner_document()
+            .upcast::<Node>()
+            .traverse_preorder(ShadowIncluding::No)
+            .filter_map(DomRoot::downcast::<HTMLMetaElement>)
+        {
+            let element = meta.upcast::<Element>();
+            let Some(name) = element.get_name() else {
+                continue;
+            };
+            let name = name.to_ascii_lowercase();
+            if !WAT_META_NAMES.contains(&name.as_str()) {
+                continue;
+            }
+            if let Some(content) = element.get_attribute(&ns!(), &local_name!("content")) {
+                contents
+                    .entry(name)
+                    .or_default()
+                    .push(String::from(&**content.value()));
+            }
+        }
+        contents
+    }
+
+    /// Compile options of a WASM script loaded from `url`, from its `data-*`
+    /// attributes, or else the defaults of the page
+    fn wasm_compile_options(&self, url: &ServoUrl) -> CompileOptions {
+        let element = self.upcast::<Element>();
+        let metas = self.wat_meta_contents();
+        let meta = |name: &str| metas.get(name).map(Vec::as_slice).unwrap_or_default();
+        let defaults = wat_page_defaults(meta("wat-compiler"));
+        CompileOptions {
+            name: self.wat_script_name(url),
+            register: self.wat_registered_name(),
+            shared_memories: wat_shared_memories(meta("wasm-memory")),
+            shared_tables: wat_shared_tables(meta("wasm-table")),
+            ..CompileOptions::from_attributes(|name| {
+                element
+                    .get_attribute(&ns!(), &LocalName::from(name))
+                    .map(|attr| String::from(&**attr.value()))
+                    .or_else(|| defaults.get(name))
+            })
+        }
+        .with_prefs(wat_threads_permitted(
+            self.owner_document().origin().immutable(),
+        ))
+    }
+
     // https://html.spec.whatwg.org/multipage/#prepare-a-script Step 7.
     pub(crate) fn get_script_type(&self) -> Option<ScriptType> {
         let element = self.upcast::<Element>();
@@ -1424,8 +1927,12 @@ impl HTMLScriptElement {
                 // WebAssembly Text support
                 // Use text/wast as primary type (triggers html5ever RawData mode like text/typescript)
                 // Keep application/wasm and text/wasm for compatibility
-                if ty_trimmed == "text/wast" || ty_trimmed == "text/wasm" ||
-                   ty_trimmed == "application/wasm" || ty_trimmed == "binary/wasm" {
+                if SCRIPT_WAT_MIMES.contains(&ty_trimmed) {
+                    if !self.wat_scripts_enabled() {
+                        // Not run, like a script of an unsupported type
+                        debug!("WAT scripts are disabled for this origin");
+                        return None;
+                    }
                     return Some(ScriptType::Wasm);
                 }
 
@@ -1524,6 +2031,14 @@ impl VirtualMethods for HTMLScriptElement {
         }
     }
 
+    fn unbind_from_tree(&self, context: &UnbindContext, can_gc: CanGc) {
+        self.super_type().unwrap().unbind_from_tree(context, can_gc);
+
+        if let Some(speculation) = self.lazy_compile.borrow_mut().take() {
+            speculation.cancel();
+        }
+    }
+
     fn cloning_steps(
         &self,
         copy: &Node,
@@ -1535,12 +2050,17 @@ impl VirtualMethods for HTMLScriptElement {
             s.cloning_steps(copy, maybe_doc, clone_children, can_gc);
         }
 
+        let copy = copy.downcast::<HTMLScriptElement>().unwrap();
+
         // https://html.spec.whatwg.org/multipage/#already-started
         if self.already_started.get() {
-            copy.downcast::<HTMLScriptElement>()
-                .unwrap()
-                .set_already_started(true);
+            copy.set_already_started(true);
         }
+
+        let clones = self.clones.get() + 1;
+        self.clones.set(clones);
+        *copy.clone_suffix.borrow_mut() =
+            format!("{} (clone {})", self.clone_suffix.borrow(), clones);
     }
 }
 
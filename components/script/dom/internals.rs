@@ -0,0 +1,41 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use dom_struct::dom_struct;
+
+use crate::dom::bindings::codegen::Bindings::InternalsBinding::InternalsMethods;
+use crate::dom::bindings::reflector::{DomGlobal, Reflector, reflect_dom_object};
+use crate::dom::bindings::root::{DomRoot, MutNullableDom};
+use crate::dom::globalscope::GlobalScope;
+use crate::dom::wasminternals::WasmInternals;
+use crate::script_runtime::CanGc;
+
+/// `window.internals`, hooks for tests into the engine, exposed with the
+/// `dom_testbinding_enabled` pref
+#[dom_struct]
+pub(crate) struct Internals {
+    reflector_: Reflector,
+    wasm: MutNullableDom<WasmInternals>,
+}
+
+impl Internals {
+    fn new_inherited() -> Internals {
+        Internals {
+            reflector_: Reflector::new(),
+            wasm: Default::default(),
+        }
+    }
+
+    pub(crate) fn new(global: &GlobalScope, can_gc: CanGc) -> DomRoot<Internals> {
+        reflect_dom_object(Box::new(Internals::new_inherited()), global, can_gc)
+    }
+}
+
+impl InternalsMethods<crate::DomTypeHolder> for Internals {
+    /// <https://servo.org/internal-no-spec>
+    fn Wasm(&self) -> DomRoot<WasmInternals> {
+        self.wasm
+            .or_init(|| WasmInternals::new(&self.global(), CanGc::note()))
+    }
+}
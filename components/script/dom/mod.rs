@@ -321,6 +321,7 @@ pub(crate) use self::html::*;
 pub(crate) mod indexeddb;
 pub(crate) use self::indexeddb::*;
 pub(crate) mod inputevent;
+pub(crate) mod internals;
 pub(crate) mod intersectionobserver;
 pub(crate) mod intersectionobserverentry;
 pub(crate) mod keyboardevent;
@@ -445,6 +446,8 @@ pub(crate) mod virtualmethods;
 pub(crate) mod visibilitystateentry;
 pub(crate) mod vttcue;
 pub(crate) mod vttregion;
+pub(crate) mod wasminternals;
+pub(crate) mod watcompiler;
 pub(crate) mod webgl;
 pub(crate) use self::webgl::extensions::ext::*;
 pub(crate) use self::webgl::*;
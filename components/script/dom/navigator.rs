@@ -51,6 +51,7 @@ use crate::dom::permissions::Permissions;
 use crate::dom::pluginarray::PluginArray;
 use crate::dom::serviceworkercontainer::ServiceWorkerContainer;
 use crate::dom::servointernals::ServoInternals;
+use crate::dom::watcompiler::WatCompiler;
 #[cfg(feature = "webgpu")]
 use crate::dom::webgpu::gpu::GPU;
 use crate::dom::window::Window;
@@ -123,6 +124,7 @@ pub(crate) struct Navigator {
     /// <https://www.w3.org/TR/gamepad/#dfn-hasgamepadgesture>
     has_gamepad_gesture: Cell<bool>,
     servo_internals: MutNullableDom<ServoInternals>,
+    wat_compiler: MutNullableDom<WatCompiler>,
 }
 
 impl Navigator {
@@ -146,6 +148,7 @@ impl Navigator {
             gpu: Default::default(),
             has_gamepad_gesture: Cell::new(false),
             servo_internals: Default::default(),
+            wat_compiler: Default::default(),
         }
     }
 
@@ -535,6 +538,12 @@ impl NavigatorMethods<crate::DomTypeHolder> for Navigator {
             .or_init(|| ServoInternals::new(&self.global(), CanGc::note()))
     }
 
+    /// <https://servo.org/internal-no-spec>
+    fn WatCompiler(&self) -> DomRoot<WatCompiler> {
+        self.wat_compiler
+            .or_init(|| WatCompiler::new(&self.global(), CanGc::note()))
+    }
+
     /// <https://html.spec.whatwg.org/multipage/#dom-navigator-registerprotocolhandler>
     fn RegisterProtocolHandler(&self, scheme: DOMString, url: USVString) -> Fallible<()> {
         // Step 1. Let (normalizedScheme, normalizedURLString) be the result of
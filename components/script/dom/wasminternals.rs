@@ -0,0 +1,91 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::ptr;
+
+use dom_struct::dom_struct;
+use js::jsapi::JSObject;
+use js::typedarray::HeapUint8Array;
+use script_bindings::trace::RootedTraceableBox;
+
+use crate::dom::bindings::buffer_source::create_buffer_source;
+use crate::dom::bindings::codegen::Bindings::WasmInternalsBinding::{
+    WasmCacheStats, WasmCachedModule, WasmInternalsMethods,
+};
+use crate::dom::bindings::error::{Error, Fallible};
+use crate::dom::bindings::reflector::{Reflector, reflect_dom_object};
+use crate::dom::bindings::root::DomRoot;
+use crate::dom::bindings::str::DOMString;
+use crate::dom::globalscope::GlobalScope;
+use crate::script_runtime::{CanGc, JSContext};
+use crate::wasm_compiler::{self, internals};
+
+/// `window.internals.wasm`, the compilation cache of WAT scripts for tests;
+/// see [`wasm_compiler::internals`]
+#[dom_struct]
+pub(crate) struct WasmInternals {
+    reflector_: Reflector,
+}
+
+impl WasmInternals {
+    fn new_inherited() -> WasmInternals {
+        WasmInternals {
+            reflector_: Reflector::new(),
+        }
+    }
+
+    pub(crate) fn new(global: &GlobalScope, can_gc: CanGc) -> DomRoot<WasmInternals> {
+        reflect_dom_object(Box::new(WasmInternals::new_inherited()), global, can_gc)
+    }
+}
+
+impl WasmInternalsMethods<crate::DomTypeHolder> for WasmInternals {
+    /// <https://servo.org/internal-no-spec>
+    fn CacheStats(&self) -> WasmCacheStats {
+        let stats = internals::cache_stats();
+        WasmCacheStats {
+            entries: stats.entries as u64,
+            bytes: stats.bytes as u64,
+            compiled: stats.compiled,
+            cacheHits: stats.cache_hits,
+        }
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn Modules(&self) -> Vec<WasmCachedModule> {
+        internals::modules()
+            .into_iter()
+            .map(|module| WasmCachedModule {
+                hash: DOMString::from(module.hash),
+                filename: DOMString::from(module.filename),
+                size: module.size as u64,
+            })
+            .collect()
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn ClearCache(&self) {
+        wasm_compiler::clear_cache();
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn Bytes(
+        &self,
+        cx: JSContext,
+        hash: DOMString,
+        can_gc: CanGc,
+    ) -> Fallible<RootedTraceableBox<HeapUint8Array>> {
+        let binary = internals::binary(&hash.str()).ok_or(Error::NotFound(None))?;
+        rooted!(in(*cx) let mut js_object = ptr::null_mut::<JSObject>());
+        create_buffer_source(cx, &binary, js_object.handle_mut(), can_gc)
+            .map_err(|_| Error::JSFailed)
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn SizeReport(&self, hash: DOMString) -> Fallible<DOMString> {
+        internals::size_report(&hash.str())
+            .map(DOMString::from)
+            .ok_or(Error::NotFound(None))
+    }
+}
@@ -0,0 +1,122 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use dom_struct::dom_struct;
+use js::jsval::UndefinedValue;
+use js::rust::HandleObject;
+use script_bindings::interfaces::WatCompilerHelpers;
+use script_bindings::script_runtime::JSContext;
+
+use crate::dom::bindings::codegen::Bindings::WatCompilerBinding::WatCompilerMethods;
+use crate::dom::bindings::error::{Error, Fallible};
+use crate::dom::bindings::inheritance::Castable;
+use crate::dom::bindings::reflector::{DomGlobal, Reflector, reflect_dom_object};
+use crate::dom::bindings::root::DomRoot;
+use crate::dom::bindings::str::DOMString;
+use crate::dom::blob::Blob;
+use crate::dom::file::File;
+use crate::dom::globalscope::GlobalScope;
+use crate::dom::html::htmlscriptelement::{wat_scripts_enabled, wat_threads_permitted};
+use crate::dom::promise::Promise;
+use crate::realms::{AlreadyInRealm, InRealm};
+use crate::script_runtime::CanGc;
+use crate::wasm_compiler::{self, CompileOptions};
+
+/// `navigator.watCompiler`, compiling WAT fragments and module files against
+/// the modules of the page, and running spec tests; see
+/// [`wasm_compiler::compile_fragment`], [`wasm_compiler::compile_file`] and
+/// [`wasm_compiler::run_wast`]
+#[dom_struct]
+pub(crate) struct WatCompiler {
+    reflector_: Reflector,
+    /// Number of modules named so far, see [`WatCompiler::next_filename`]
+    modules: Cell<u32>,
+}
+
+impl WatCompiler {
+    fn new_inherited() -> WatCompiler {
+        WatCompiler {
+            reflector_: Reflector::new(),
+            modules: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn new(global: &GlobalScope, can_gc: CanGc) -> DomRoot<WatCompiler> {
+        reflect_dom_object(Box::new(WatCompiler::new_inherited()), global, can_gc)
+    }
+
+    /// Name of the next module without a name of its own
+    fn next_filename(&self, kind: &str) -> String {
+        let index = self.modules.get() + 1;
+        self.modules.set(index);
+        format!("{}#wat-{}-{}", self.global().api_base_url(), kind, index)
+    }
+
+    /// Options of the modules compiled here, which have no script element
+    fn compile_options(&self) -> CompileOptions {
+        CompileOptions::from_prefs(wat_threads_permitted(self.global().origin().immutable()))
+    }
+
+    /// Run a loader built by [`wasm_compiler::compile_fragment`],
+    /// [`wasm_compiler::compile_file`] or [`wasm_compiler::run_wast`],
+    /// returning its promise
+    fn load(&self, code: String, filename: &str, can_gc: CanGc) -> Fallible<Rc<Promise>> {
+        let cx = GlobalScope::get_cx();
+        rooted!(in(*cx) let mut rval = UndefinedValue());
+        self.global()
+            .evaluate_js_on_global(code.into(), filename, None, rval.handle_mut(), can_gc)
+            .map_err(|_| Error::Operation(None))?;
+        if !rval.is_object() {
+            return Err(Error::Operation(None));
+        }
+        rooted!(in(*cx) let promise = rval.to_object());
+        Ok(Promise::new_with_js_promise(promise.handle(), cx))
+    }
+}
+
+impl WatCompilerMethods<crate::DomTypeHolder> for WatCompiler {
+    /// <https://servo.org/internal-no-spec>
+    fn Eval(&self, fragment: DOMString, can_gc: CanGc) -> Fallible<Rc<Promise>> {
+        let filename = self.next_filename("eval");
+        let code =
+            wasm_compiler::compile_fragment(&fragment.str(), &filename, &self.compile_options())
+                .map_err(|e| Error::Syntax(Some(e.to_string())))?;
+        self.load(code, &filename, can_gc)
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn CompileFile(&self, file: &Blob, can_gc: CanGc) -> Fallible<Rc<Promise>> {
+        let bytes = file.get_bytes().map_err(|_| Error::NotReadable(None))?;
+        let filename = match file.downcast::<File>() {
+            Some(file) => file.name().to_string(),
+            None => self.next_filename("blob"),
+        };
+        let code = wasm_compiler::compile_file(&bytes, &filename, &self.compile_options())
+            .map_err(|e| Error::Syntax(Some(e.to_string())))?;
+        self.load(code, &filename, can_gc)
+    }
+
+    /// <https://servo.org/internal-no-spec>
+    fn RunWast(&self, text: DOMString, can_gc: CanGc) -> Fallible<Rc<Promise>> {
+        let filename = self.next_filename("wast");
+        let code = wasm_compiler::run_wast(&text.str(), &filename, &self.compile_options())
+            .map_err(|e| Error::Syntax(Some(e.to_string())))?;
+        self.load(code, &filename, can_gc)
+    }
+}
+
+impl WatCompilerHelpers for WatCompiler {
+    /// Exposed where WAT scripts may run
+    #[expect(unsafe_code)]
+    fn is_enabled(cx: JSContext, _global: HandleObject) -> bool {
+        unsafe {
+            let in_realm_proof = AlreadyInRealm::assert_for_cx(cx);
+            let global_scope = GlobalScope::from_context(*cx, InRealm::Already(&in_realm_proof));
+            wat_scripts_enabled(global_scope.origin().immutable())
+        }
+    }
+}
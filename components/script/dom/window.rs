@@ -155,6 +155,7 @@ use crate::dom::html::htmlcollection::{CollectionFilter, HTMLCollection};
 use crate::dom::html::htmliframeelement::HTMLIFrameElement;
 use crate::dom::idbfactory::IDBFactory;
 use crate::dom::inputevent::HitTestResult;
+use crate::dom::internals::Internals;
 use crate::dom::location::Location;
 use crate::dom::medialist::MediaList;
 use crate::dom::mediaquerylist::{MediaQueryList, MediaQueryListMatchState};
@@ -274,6 +275,8 @@ pub(crate) struct Window {
     #[ignore_malloc_size_of = "TODO: Add MallocSizeOf support to layout"]
     layout: RefCell<Box<dyn Layout>>,
     navigator: MutNullableDom<Navigator>,
+    /// `window.internals`, for tests
+    internals: MutNullableDom<Internals>,
     #[ignore_malloc_size_of = "ImageCache"]
     #[no_trace]
     image_cache: Arc<dyn ImageCache>,
@@ -1388,6 +1391,12 @@ impl WindowMethods<crate::DomTypeHolder> for Window {
         self.Navigator()
     }
 
+    /// <https://servo.org/internal-no-spec>
+    fn Internals(&self) -> DomRoot<Internals> {
+        self.internals
+            .or_init(|| Internals::new(self.as_global_scope(), CanGc::note()))
+    }
+
     /// <https://html.spec.whatwg.org/multipage/#dom-settimeout>
     fn SetTimeout(
         &self,
@@ -3482,6 +3491,7 @@ impl Window {
             image_cache_sender,
             image_cache,
             navigator: Default::default(),
+            internals: Default::default(),
             location: Default::default(),
             history: Default::default(),
             indexeddb: Default::default(),
@@ -79,6 +79,7 @@ use crate::network_listener::{
 use crate::realms::{AlreadyInRealm, InRealm, enter_realm};
 use crate::script_runtime::{CanGc, IntroductionType, JSContext as SafeJSContext};
 use crate::task::TaskBox;
+use crate::wasm_compiler::CompileOptions;
 
 fn gen_type_error(global: &GlobalScope, string: String, can_gc: CanGc) -> RethrowError {
     rooted!(in(*GlobalScope::get_cx()) let mut thrown = UndefinedValue());
@@ -1081,6 +1082,7 @@ impl ModuleOwner {
                                     ScriptType::Module,
                                     global.unminified_js_dir(),
                                     None,
+                                    CompileOptions::default(),
                                 )))
                             },
                             ModuleIdentity::ScriptId(_) => {
@@ -1091,6 +1093,7 @@ impl ModuleOwner {
                                     ScriptType::Module,
                                     global.unminified_js_dir(),
                                     Err(Error::NotFound(None)),
+                                    CompileOptions::default(),
                                 )))
                             },
                         },
@@ -1319,6 +1322,7 @@ impl FetchResponseListener for ModuleContext {
                 ScriptType::Module,
                 global.unminified_js_dir(),
                 None,
+                CompileOptions::default(),
             ))
         });
 
@@ -93,6 +93,7 @@ use crate::realms::{AlreadyInRealm, InRealm, enter_realm};
 use crate::script_module::EnsureModuleHooksInitialized;
 use crate::script_thread::trace_thread;
 use crate::task_source::TaskSourceName;
+use crate::wasm_compiler;
 
 static JOB_QUEUE_TRAPS: JobQueueTraps = JobQueueTraps {
     getHostDefinedData: Some(get_host_defined_data),
@@ -730,10 +731,9 @@ impl Runtime {
             JS_InitDestroyPrincipalsCallback(cx, Some(principals::destroy_servo_jsprincipal));
             JS_InitReadPrincipalsCallback(cx, Some(principals::read_jsprincipal));
 
-            // Needed for debug assertions about whether GC is running.
-            if cfg!(debug_assertions) {
-                JS_SetGCCallback(cx, Some(debug_gc_callback), ptr::null_mut());
-            }
+            // Needed for debug assertions about whether GC is running, and to shed
+            // the compiled WebAssembly modules under memory pressure.
+            JS_SetGCCallback(cx, Some(gc_callback), ptr::null_mut());
 
             if opts::get().debug.gc_profile {
                 SetGCSliceCallback(cx, Some(gc_slice_callback));
@@ -1094,15 +1094,31 @@ unsafe extern "C" fn gc_slice_callback(
 }
 
 #[expect(unsafe_code)]
-unsafe extern "C" fn debug_gc_callback(
+unsafe extern "C" fn gc_callback(
     _cx: *mut RawJSContext,
     status: JSGCStatus,
-    _reason: GCReason,
+    reason: GCReason,
     _data: *mut os::raw::c_void,
 ) {
     match status {
-        JSGCStatus::JSGC_BEGIN => thread_state::enter(ThreadState::IN_GC),
-        JSGCStatus::JSGC_END => thread_state::exit(ThreadState::IN_GC),
+        JSGCStatus::JSGC_BEGIN => {
+            if cfg!(debug_assertions) {
+                thread_state::enter(ThreadState::IN_GC);
+            }
+        },
+        JSGCStatus::JSGC_END => {
+            if cfg!(debug_assertions) {
+                thread_state::exit(ThreadState::IN_GC);
+            }
+            let pressure = match reason {
+                GCReason::MEM_PRESSURE => Some(wasm_compiler::memory::Pressure::Moderate),
+                GCReason::LAST_DITCH => Some(wasm_compiler::memory::Pressure::Critical),
+                _ => None,
+            };
+            if let Some(pressure) = pressure {
+                wasm_compiler::memory::shed(pressure);
+            }
+        },
     }
 }
 
@@ -158,7 +158,7 @@ use crate::script_runtime::{
 use crate::script_window_proxies::ScriptWindowProxies;
 use crate::task_queue::TaskQueue;
 use crate::webdriver_handlers::jsval_to_webdriver;
-use crate::{devtools, webdriver_handlers};
+use crate::{devtools, wasm_compiler, webdriver_handlers};
 
 thread_local!(static SCRIPT_THREAD_ROOT: Cell<Option<*const ScriptThread>> = const { Cell::new(None) });
 
@@ -907,6 +907,10 @@ impl ScriptThread {
             devtools_client_to_script_thread_sender: ipc_devtools_sender,
         };
 
+        // Report WAT script compilations and their cache to the profilers
+        wasm_compiler::telemetry::set_profiler_chan(senders.time_profiler_sender.clone());
+        wasm_compiler::memory::register_reporter(&senders.memory_profiler_sender);
+
         let microtask_queue = runtime.microtask_queue.clone();
         let js_runtime = Rc::new(runtime);
         #[cfg(feature = "webgpu")]
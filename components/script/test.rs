@@ -76,3 +76,10 @@ pub mod textinput {
         UTF16CodeUnits,
     };
 }
+
+pub mod wasm_compiler {
+    pub use crate::wasm_compiler::bench::{
+        field_names_json, generate_glue, inject_datacount_section, module_metadata, parse_wat,
+    };
+    pub use crate::wasm_compiler::{CompileError, compile_bench, print_module};
+}
@@ -0,0 +1,226 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Exported functions added for the loader
+//!
+//! JavaScript cannot look inside GC objects, so the loader reads them through
+//! small functions added to the module, see [`super::arrays`] and
+//! [`super::structs`]. Each gets a type of its own; the types and functions
+//! go after those of the module, so no index changes.
+
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{
+    CodeSection, ExportKind, ExportSection, Function, FunctionSection, Instruction, Module,
+    SectionId, TypeSection, ValType,
+};
+use wasmparser::{
+    CodeSectionReader, ExportSectionReader, FunctionSectionReader, Parser, Payload, TypeRef,
+    TypeSectionReader,
+};
+
+/// A function to add and export
+pub struct Accessor {
+    pub export: String,
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+    /// Instructions of the body, without the final `end`
+    pub body: Vec<Instruction<'static>>,
+}
+
+/// Add and export `accessors`
+pub fn add(binary: &mut Vec<u8>, accessors: &[Accessor]) -> Result<(), reencode::Error> {
+    if accessors.is_empty() {
+        return Ok(());
+    }
+    let mut types = 0;
+    let mut imported_functions = 0;
+    let mut defined_functions = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for group in reader {
+                    types += group?.types().len() as u32;
+                }
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            },
+            Payload::FunctionSection(reader) => defined_functions = reader.count(),
+            _ => {},
+        }
+    }
+
+    let mut adder = Adder {
+        accessors,
+        first_type: types,
+        first_function: imported_functions + defined_functions,
+        types_added: false,
+        functions_added: false,
+        exports_added: false,
+        code_added: false,
+    };
+    let mut module = Module::new();
+    adder.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+    Ok(())
+}
+
+struct Adder<'a> {
+    accessors: &'a [Accessor],
+    /// Type index of the first added type, after the module's types
+    first_type: u32,
+    /// Index of the first added function, after the module's functions
+    first_function: u32,
+    types_added: bool,
+    functions_added: bool,
+    exports_added: bool,
+    code_added: bool,
+}
+
+/// Position of a section in a module
+pub(super) fn section_order(id: Option<SectionId>) -> u8 {
+    match id {
+        Some(SectionId::Type) => 1,
+        Some(SectionId::Import) => 2,
+        Some(SectionId::Function) => 3,
+        Some(SectionId::Table) => 4,
+        Some(SectionId::Memory) => 5,
+        Some(SectionId::Tag) => 6,
+        Some(SectionId::Global) => 7,
+        Some(SectionId::Export) => 8,
+        Some(SectionId::Start) => 9,
+        Some(SectionId::Element) => 10,
+        Some(SectionId::DataCount) => 11,
+        Some(SectionId::Code) => 12,
+        Some(SectionId::Data) => 13,
+        _ => u8::MAX,
+    }
+}
+
+impl Adder<'_> {
+    fn add_types(&mut self, types: &mut TypeSection) {
+        for accessor in self.accessors {
+            types.ty().function(
+                accessor.params.iter().copied(),
+                accessor.results.iter().copied(),
+            );
+        }
+        self.types_added = true;
+    }
+
+    fn add_functions(&mut self, functions: &mut FunctionSection) {
+        for position in 0..self.accessors.len() as u32 {
+            functions.function(self.first_type + position);
+        }
+        self.functions_added = true;
+    }
+
+    fn add_exports(&mut self, exports: &mut ExportSection) {
+        for (position, accessor) in self.accessors.iter().enumerate() {
+            exports.export(
+                &accessor.export,
+                ExportKind::Func,
+                self.first_function + position as u32,
+            );
+        }
+        self.exports_added = true;
+    }
+
+    fn add_code(&mut self, code: &mut CodeSection) {
+        for accessor in self.accessors {
+            let mut function = Function::new([]);
+            for instruction in &accessor.body {
+                function.instruction(instruction);
+            }
+            function.instruction(&Instruction::End);
+            code.function(&function);
+        }
+        self.code_added = true;
+    }
+}
+
+impl Reencode for Adder<'_> {
+    type Error = Infallible;
+
+    fn parse_type_section(
+        &mut self,
+        types: &mut TypeSection,
+        section: TypeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_type_section(self, types, section)?;
+        self.add_types(types);
+        Ok(())
+    }
+
+    fn parse_function_section(
+        &mut self,
+        functions: &mut FunctionSection,
+        section: FunctionSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_function_section(self, functions, section)?;
+        self.add_functions(functions);
+        Ok(())
+    }
+
+    fn parse_export_section(
+        &mut self,
+        exports: &mut ExportSection,
+        section: ExportSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_export_section(self, exports, section)?;
+        self.add_exports(exports);
+        Ok(())
+    }
+
+    fn parse_code_section(
+        &mut self,
+        code: &mut CodeSection,
+        section: CodeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_code_section(self, code, section)?;
+        self.add_code(code);
+        Ok(())
+    }
+
+    // Modules without types, functions, exports or code get new sections in
+    // their place
+    fn intersperse_section_hook(
+        &mut self,
+        module: &mut Module,
+        _after: Option<SectionId>,
+        before: Option<SectionId>,
+    ) -> Result<(), reencode::Error> {
+        let before = section_order(before);
+        if !self.types_added && before > section_order(Some(SectionId::Type)) {
+            let mut types = TypeSection::new();
+            self.add_types(&mut types);
+            module.section(&types);
+        }
+        if !self.functions_added && before > section_order(Some(SectionId::Function)) {
+            let mut functions = FunctionSection::new();
+            self.add_functions(&mut functions);
+            module.section(&functions);
+        }
+        if !self.exports_added && before > section_order(Some(SectionId::Export)) {
+            let mut exports = ExportSection::new();
+            self.add_exports(&mut exports);
+            module.section(&exports);
+        }
+        if !self.code_added && before > section_order(Some(SectionId::Code)) {
+            let mut code = CodeSection::new();
+            self.add_code(&mut code);
+            module.section(&code);
+        }
+        Ok(())
+    }
+}
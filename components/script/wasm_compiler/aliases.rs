@@ -0,0 +1,211 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Export aliases
+//!
+//! Generated modules often export mangled names, which make for a poor page
+//! API. The loader installs an export under an alias instead of its name when
+//! the source gives one, with an annotation following the export in WAT:
+//!
+//! ```text
+//! (func $render (export "_ZN3app6render17h5f1c") (;@export-as renderFrame;) ...)
+//! ```
+//!
+//! or, for binary modules, with a [`SECTION_NAME`] custom section holding a
+//! vector of export name and alias pairs, encoded like the name maps of the
+//! name section.
+//!
+//! With `data-name-style="camel"`, the other exports are installed under
+//! their names in camelCase, `draw_frame` as `drawFrame`, see [`NameStyle`].
+//! The loader records the names it installed the exports under as
+//! `exportNames` of the module's entry in `window.__wasmModules`, and keeps
+//! the wrappers reachable by the names of the module as its `exports`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use wasmparser::{BinaryReader, Parser, Payload};
+
+use super::LOG_TARGET;
+
+/// Custom section holding the aliases of a binary module
+pub const SECTION_NAME: &str = "export-aliases";
+
+/// Opening of an alias annotation, closed by `;)`
+const ANNOTATION: &str = "(;@export-as";
+
+/// Aliases by export name, from the annotations of `source` and the custom
+/// section of `binary`
+fn export_aliases(source: &str, binary: &[u8]) -> BTreeMap<String, String> {
+    let mut aliases: BTreeMap<String, String> = section_aliases(binary).into_iter().collect();
+    aliases.extend(annotated_aliases(source));
+    aliases
+}
+
+/// Aliases as a JSON object keyed by export name, for the loader
+pub fn aliases_json(source: &str, binary: &[u8]) -> String {
+    serde_json::to_string(&export_aliases(source, binary)).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Names the exports without an alias are installed under, given by
+/// `data-name-style`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NameStyle {
+    /// The names of the module
+    #[default]
+    Wasm,
+    /// The names of the module in camelCase, see [`camel_case`]
+    Camel,
+}
+
+impl NameStyle {
+    pub fn parse(value: &str) -> Option<NameStyle> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "wasm" | "none" => Some(NameStyle::Wasm),
+            "camel" | "camelcase" => Some(NameStyle::Camel),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NameStyle::Wasm => "wasm",
+            NameStyle::Camel => "camel",
+        }
+    }
+}
+
+/// `aliases_json` with the exports of `binary` that have no alias renamed in
+/// `style`, as JSON
+/// An export whose new name another export has already is left as it is.
+pub fn styled_aliases_json(aliases_json: &str, binary: &[u8], style: NameStyle) -> String {
+    if style == NameStyle::Wasm {
+        return aliases_json.to_string();
+    }
+    let mut aliases: BTreeMap<String, String> =
+        serde_json::from_str(aliases_json).unwrap_or_default();
+    let exports = export_names(binary);
+    let mut taken: HashSet<String> = exports.iter().chain(aliases.values()).cloned().collect();
+    for name in exports {
+        if aliases.contains_key(&name) {
+            continue;
+        }
+        let styled = camel_case(&name);
+        if styled == name {
+            continue;
+        }
+        if taken.contains(&styled) {
+            log::warn!(
+                target: LOG_TARGET, phase = "aliases", export:% = name, name:% = styled;
+                "Keeping export {:?} as it is, {:?} is taken", name, styled
+            );
+            continue;
+        }
+        taken.insert(styled.clone());
+        aliases.insert(name, styled);
+    }
+    serde_json::to_string(&aliases).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// `snake_case` and `kebab-case` names in camelCase; leading and trailing
+/// underscores, as in `__heap_base` or `type_`, are kept
+pub fn camel_case(name: &str) -> String {
+    let separator = |c: char| c == '_' || c == '-';
+    let start = name.len() - name.trim_start_matches(separator).len();
+    let end = name.trim_end_matches(separator).len().max(start);
+    let mut result = String::with_capacity(name.len());
+    result.push_str(&name[..start]);
+    let mut upper = false;
+    for c in name[start..end].chars() {
+        if separator(c) {
+            upper = true;
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result.push_str(&name[end..]);
+    result
+}
+
+/// Names of the exports of `binary`, in order
+pub fn export_names(binary: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        if let Ok(Payload::ExportSection(reader)) = payload {
+            names.extend(
+                reader
+                    .into_iter()
+                    .flatten()
+                    .map(|export| export.name.to_string()),
+            );
+        }
+    }
+    names
+}
+
+/// Aliases given by annotations, each for the nearest export before it
+fn annotated_aliases(source: &str) -> Vec<(String, String)> {
+    let mut aliases = Vec::new();
+    for (start, _) in source.match_indices(ANNOTATION) {
+        let rest = &source[start + ANNOTATION.len()..];
+        let Some(end) = rest.find(";)") else {
+            continue;
+        };
+        let alias = rest[..end].trim();
+        match preceding_export(&source[..start]) {
+            Some(name) if !alias.is_empty() => aliases.push((name.to_string(), alias.to_string())),
+            _ => log::warn!(
+                target: LOG_TARGET, phase = "aliases", alias;
+                "Ignoring export alias {:?} without an export", alias
+            ),
+        }
+    }
+    aliases
+}
+
+/// Name of the last `(export "name"` in `text`
+fn preceding_export(text: &str) -> Option<&str> {
+    let start = text.rfind("(export")? + "(export".len();
+    let rest = text[start..].trim_start().strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
+
+/// Aliases in the custom section of a binary module
+fn section_aliases(binary: &[u8]) -> Vec<(String, String)> {
+    let mut aliases = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::CustomSection(section)) = payload else {
+            continue;
+        };
+        if section.name() != SECTION_NAME {
+            continue;
+        }
+        let mut reader = BinaryReader::new(section.data(), section.data_offset());
+        let read = |reader: &mut BinaryReader| -> wasmparser::Result<(String, String)> {
+            Ok((
+                reader.read_string()?.to_string(),
+                reader.read_string()?.to_string(),
+            ))
+        };
+        let count = reader.read_var_u32().unwrap_or_default();
+        for _ in 0..count {
+            match read(&mut reader) {
+                Ok(alias) => aliases.push(alias),
+                Err(error) => {
+                    log::warn!(
+                        target: LOG_TARGET, phase = "aliases";
+                        "Malformed {} section: {}", SECTION_NAME, error
+                    );
+                    break;
+                },
+            }
+        }
+    }
+    aliases
+}
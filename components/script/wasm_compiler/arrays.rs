@@ -0,0 +1,192 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Element access for arrays of structs
+//!
+//! JavaScript cannot read the elements of a GC array. For every array type
+//! whose elements are references to a struct type, two functions are added
+//! and exported:
+//!
+//! ```text
+//! (func (export "__wasm_array_get_3") (param (ref null 3)) (param i32) (result (ref null 1)))
+//! (func (export "__wasm_array_len_3") (param (ref null 3)) (result i32))
+//! ```
+//!
+//! The loader tells which array type a reference has by the accessor that
+//! accepts it, and wraps the elements with the field names of their struct
+//! type, so that `players[0].name` reads the field like it would on a struct
+//! returned by an export.
+//!
+//! The functions are added like the other accessors, see
+//! [`super::accessors`].
+
+use std::collections::HashMap;
+
+use wasm_encoder::Instruction;
+use wasm_encoder::reencode;
+use wasmparser::{
+    CompositeInnerType, HeapType, KnownCustom, Name, Parser, Payload, RefType, StorageType, ValType,
+};
+
+use super::accessors::{self, Accessor};
+use super::names;
+
+/// Prefix of the added exports, which the loader does not install
+pub const EXPORT_PREFIX: &str = "__wasm_array_";
+
+/// An array type whose elements are references to a struct type
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructArray {
+    /// Type index of the array
+    pub array: u32,
+    /// Type index of the struct
+    pub element: u32,
+    /// Export reading an element
+    pub get: String,
+    /// Export reading the length
+    pub len: String,
+}
+
+/// Add and export accessors for the arrays of structs of a module, see the
+/// module documentation
+pub fn add_accessors(binary: &mut Vec<u8>) -> Result<Vec<StructArray>, reencode::Error> {
+    let mut types = 0;
+    let mut structs = Vec::new();
+    let mut arrays = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Payload::TypeSection(reader) = payload? else {
+            continue;
+        };
+        for group in reader {
+            for ty in group?.types() {
+                match &ty.composite_type.inner {
+                    CompositeInnerType::Struct(_) => structs.push(types),
+                    CompositeInnerType::Array(array) => {
+                        if let StorageType::Val(ValType::Ref(element)) = array.0.element_type {
+                            arrays.push((types, element));
+                        }
+                    },
+                    _ => {},
+                }
+                types += 1;
+            }
+        }
+    }
+
+    // Keep the arrays of references to struct types
+    let arrays: Vec<(u32, RefType)> = arrays
+        .into_iter()
+        .filter(|(_, element)| {
+            element_struct(element).is_some_and(|index| structs.contains(&index))
+        })
+        .collect();
+    if arrays.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let struct_arrays: Vec<StructArray> = arrays
+        .iter()
+        .map(|(array, element)| StructArray {
+            array: *array,
+            element: element_struct(element).unwrap_or_default(),
+            get: format!("{}get_{}", EXPORT_PREFIX, array),
+            len: format!("{}len_{}", EXPORT_PREFIX, array),
+        })
+        .collect();
+    let mut added = Vec::new();
+    for ((array, element), struct_array) in arrays.iter().zip(&struct_arrays) {
+        let array_type = wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+            nullable: true,
+            heap_type: wasm_encoder::HeapType::Concrete(*array),
+        });
+        added.push(Accessor {
+            export: struct_array.get.clone(),
+            params: vec![array_type, wasm_encoder::ValType::I32],
+            results: vec![ValType::Ref(*element).try_into()?],
+            body: vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::ArrayGet(*array),
+            ],
+        });
+        added.push(Accessor {
+            export: struct_array.len.clone(),
+            params: vec![array_type],
+            results: vec![wasm_encoder::ValType::I32],
+            body: vec![Instruction::LocalGet(0), Instruction::ArrayLen],
+        });
+    }
+    accessors::add(binary, &added)?;
+    Ok(struct_arrays)
+}
+
+/// The arrays as the loader reads them: the type index and accessors of each,
+/// and the names of its element type, see [`names::struct_info`]
+pub fn loader_json(arrays: &[StructArray], binary: &[u8]) -> String {
+    let structs = names::struct_info(binary);
+    serde_json::Value::Array(
+        arrays
+            .iter()
+            .map(|array| {
+                let element = structs.get(&array.element);
+                serde_json::json!({
+                    "array": array.array,
+                    "get": array.get,
+                    "len": array.len,
+                    "element": element,
+                })
+            })
+            .collect(),
+    )
+    .to_string()
+}
+
+/// Names of the types and their fields that have one in the name section
+pub fn type_names(binary: &[u8]) -> (HashMap<u32, String>, HashMap<u32, Vec<String>>) {
+    let mut types = HashMap::new();
+    let mut fields: HashMap<u32, Vec<String>> = HashMap::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::CustomSection(reader)) = payload else {
+            continue;
+        };
+        let KnownCustom::Name(reader) = reader.as_known() else {
+            continue;
+        };
+        for subsection in reader.flatten() {
+            match subsection {
+                Name::Type(map) => {
+                    for naming in map.into_iter().flatten() {
+                        types.insert(naming.index, naming.name.to_string());
+                    }
+                },
+                Name::Field(map) => {
+                    for indirect in map.into_iter().flatten() {
+                        let mut names = Vec::new();
+                        for naming in indirect.names.into_iter().flatten() {
+                            let position = naming.index as usize;
+                            if names.len() <= position {
+                                names.resize(position + 1, String::new());
+                            }
+                            names[position] = naming.name.to_string();
+                        }
+                        fields.insert(indirect.index, names);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    (types, fields)
+}
+
+/// Type index an element type refers to, if it is a concrete one
+fn element_struct(element: &RefType) -> Option<u32> {
+    match element.heap_type() {
+        HeapType::Concrete(index) => index.as_module_index(),
+        _ => None,
+    }
+}
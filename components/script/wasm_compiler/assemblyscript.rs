@@ -0,0 +1,202 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! AssemblyScript compatibility
+//!
+//! Modules compiled by AssemblyScript with `--exportRuntime` export the
+//! functions of its garbage collected runtime, [`RUNTIME_EXPORTS`], and keep
+//! strings as UTF-16 objects in memory 0 whose size precedes them. The loader
+//! uses them like AssemblyScript's own loader:
+//!
+//! - JavaScript strings passed to exports become pinned string objects,
+//!   unpinned once the call returns
+//! - exports get the helpers of AssemblyScript's loader, `__newString`,
+//!   `__getString`, `__newArray`, `__getArray`, `__getArrayView`, typed
+//!   variants like `__getInt32Array` and those for `ArrayBuffer`s; the array
+//!   helpers read the runtime type information of `__rtti_base`
+//! - `env.abort`, `env.trace` and `env.seed` are imported for modules the
+//!   page does not provide them for, with their messages decoded
+//!
+//! Exports return pointers, which a signature does not tell apart from
+//! numbers, so strings and arrays returned are read with the helpers.
+
+use wasmparser::{ExternalKind, Parser, Payload};
+
+/// Exports of the runtime: allocating, pinning and unpinning an object
+pub const RUNTIME_EXPORTS: [&str; 3] = ["__new", "__pin", "__unpin"];
+
+/// Whether `binary` was compiled by AssemblyScript with its runtime exported
+pub fn is_assemblyscript(binary: &[u8]) -> bool {
+    let mut functions = Vec::new();
+    let mut memory = false;
+    for payload in Parser::new(0).parse_all(binary) {
+        if let Ok(Payload::ExportSection(reader)) = payload {
+            for export in reader.into_iter().flatten() {
+                match export.kind {
+                    ExternalKind::Func => functions.push(export.name),
+                    ExternalKind::Memory => memory = true,
+                    _ => {},
+                }
+            }
+        }
+    }
+    memory && RUNTIME_EXPORTS.iter().all(|name| functions.contains(name))
+}
+
+/// JavaScript of the helpers of AssemblyScript's loader: the imports of its
+/// runtime, resolved before the module is instantiated, and the helpers
+/// themselves on top of its exports
+pub fn loader_js() -> (&'static str, &'static str) {
+    (
+        r#"
+        let assemblyScriptMemory = null;
+        const assemblyScriptString = function(ptr) {
+            if (!ptr) {
+                return null;
+            }
+            const buffer = assemblyScriptMemory.buffer;
+            const size = new Uint32Array(buffer)[(ptr - 4) >>> 2];
+            return new TextDecoder('utf-16le').decode(new Uint16Array(buffer, ptr, size >>> 1));
+        };
+        const assemblyScriptImports = {
+            env: {
+                abort: function(message, fileName, line, column) {
+                    throw new Error((assemblyScriptString(message) || 'abort') + ' in ' +
+                        assemblyScriptString(fileName) + '(' + line + ':' + column + ')');
+                },
+                trace: function(message, n, ...args) {
+                    console.log('trace: ' + assemblyScriptString(message) + (n ? ' ' : '') + args.slice(0, n).join(', '));
+                },
+                seed: () => Date.now()
+            }
+        };"#,
+        r#"(function() {
+                        const exports = result.instance.exports;
+                        assemblyScriptMemory = exports.memory;
+                        const memory = exports.memory;
+                        // Runtime type information flags and object layout
+                        const ARRAYBUFFERVIEW = 1 << 0;
+                        const ARRAY = 1 << 1;
+                        const STATICARRAY = 1 << 2;
+                        const VAL_ALIGN_OFFSET = 6;
+                        const VAL_SIGNED = 1 << 11;
+                        const VAL_FLOAT = 1 << 12;
+                        const ARRAYBUFFER_ID = 1;
+                        const STRING_ID = 2;
+                        const typeInfo = function(id) {
+                            if (!exports.__rtti_base) {
+                                throw new Error('AssemblyScript arrays need the runtime type information of --exportRuntime');
+                            }
+                            const U32 = new Uint32Array(memory.buffer);
+                            const base = exports.__rtti_base.value;
+                            if ((id >>> 0) >= U32[base >>> 2]) {
+                                throw new Error('invalid id: ' + id);
+                            }
+                            return U32[(base + 4 >>> 2) + (id >>> 0)];
+                        };
+                        const arrayInfo = function(ptr) {
+                            const info = typeInfo(new Uint32Array(memory.buffer)[(ptr - 8) >>> 2]);
+                            if (!(info & (ARRAYBUFFERVIEW | ARRAY | STATICARRAY))) {
+                                throw new Error('not an array: ' + ptr);
+                            }
+                            return info;
+                        };
+                        const valueAlign = info => 31 - Math.clz32((info >>> VAL_ALIGN_OFFSET) & 31);
+                        const valueView = function(align, signed, float) {
+                            const buffer = memory.buffer;
+                            if (float) {
+                                return align === 2 ? new Float32Array(buffer) : new Float64Array(buffer);
+                            }
+                            return new [
+                                [Uint8Array, Int8Array], [Uint16Array, Int16Array],
+                                [Uint32Array, Int32Array], [BigUint64Array, BigInt64Array]
+                            ][align][signed ? 1 : 0](buffer);
+                        };
+
+                        const loader = {};
+                        loader.__newString = function(str) {
+                            const ptr = exports.__new(str.length << 1, STRING_ID);
+                            const units = new Uint16Array(memory.buffer, ptr, str.length);
+                            for (let i = 0; i < str.length; i++) {
+                                units[i] = str.charCodeAt(i);
+                            }
+                            return ptr;
+                        };
+                        loader.__getString = assemblyScriptString;
+                        loader.__newArrayBuffer = function(buffer) {
+                            const bytes = new Uint8Array(buffer);
+                            const ptr = exports.__new(bytes.length, ARRAYBUFFER_ID);
+                            new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+                            return ptr;
+                        };
+                        loader.__getArrayBuffer = function(ptr) {
+                            const size = new Uint32Array(memory.buffer)[(ptr - 4) >>> 2];
+                            return memory.buffer.slice(ptr, ptr + size);
+                        };
+                        loader.__getArrayView = function(ptr) {
+                            const U32 = new Uint32Array(memory.buffer);
+                            const info = arrayInfo(ptr);
+                            const align = valueAlign(info);
+                            const data = info & STATICARRAY ? ptr : U32[(ptr + 4) >>> 2];
+                            const length = info & ARRAY ? U32[(ptr + 12) >>> 2] : U32[(data - 4) >>> 2] >>> align;
+                            const start = data >>> align;
+                            return valueView(align, info & VAL_SIGNED, info & VAL_FLOAT).subarray(start, start + length);
+                        };
+                        loader.__getArray = ptr => Array.from(loader.__getArrayView(ptr));
+                        loader.__newArray = function(id, values) {
+                            const info = typeInfo(id);
+                            if (!(info & (ARRAYBUFFERVIEW | ARRAY | STATICARRAY))) {
+                                throw new Error('not an array type: ' + id);
+                            }
+                            const align = valueAlign(info);
+                            const length = typeof values === 'number' ? values : values.length;
+                            const data = exports.__new(length << align, info & STATICARRAY ? id : ARRAYBUFFER_ID);
+                            let ptr = data;
+                            if (!(info & STATICARRAY)) {
+                                exports.__pin(data);
+                                ptr = exports.__new(info & ARRAY ? 16 : 12, id);
+                                exports.__unpin(data);
+                                const U32 = new Uint32Array(memory.buffer);
+                                U32[ptr >>> 2] = data;
+                                U32[(ptr + 4) >>> 2] = data;
+                                U32[(ptr + 8) >>> 2] = length << align;
+                                if (info & ARRAY) {
+                                    U32[(ptr + 12) >>> 2] = length;
+                                }
+                            }
+                            if (typeof values !== 'number') {
+                                valueView(align, info & VAL_SIGNED, info & VAL_FLOAT).set(values, data >>> align);
+                            }
+                            return ptr;
+                        };
+                        for (const View of [Int8Array, Uint8Array, Uint8ClampedArray, Int16Array, Uint16Array,
+                            Int32Array, Uint32Array, Float32Array, Float64Array, BigInt64Array, BigUint64Array]) {
+                            loader['__get' + View.name + 'View'] = function(ptr) {
+                                const view = loader.__getArrayView(ptr);
+                                return new View(view.buffer, view.byteOffset, Math.floor(view.byteLength / View.BYTES_PER_ELEMENT));
+                            };
+                            loader['__get' + View.name] = ptr => loader['__get' + View.name + 'View'](ptr).slice();
+                        }
+                        Object.assign(exportTarget, loader);
+
+                        // Strings passed to exports, pinned until the outermost call returns
+                        const pinned = [];
+                        return {
+                            pinString: function(str) {
+                                const ptr = loader.__newString(str);
+                                pinned.push(exports.__pin(ptr));
+                                return ptr;
+                            },
+                            unpinAll: function() {
+                                while (pinned.length > 0) {
+                                    exports.__unpin(pinned.pop());
+                                }
+                            }
+                        };
+                    })()"#,
+    )
+}
@@ -0,0 +1,48 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Individual pipeline stages, exposed for the criterion benchmarks in
+//! `components/script/benches.rs` (see also [`super::compile_bench`])
+
+use super::{CompileError, CompileOptions, ModuleMetadata};
+
+/// Lower the text extensions and parse the WAT, without any binary passes
+pub fn parse_wat(source: &str) -> Result<Vec<u8>, CompileError> {
+    let text = super::preprocess_wat(source, super::options::all_features(), true)?;
+    wat::parse_str(&text).map_err(|e| CompileError::ParseError(e.to_string()))
+}
+
+/// Struct types JSON from the binary's type and name sections
+pub fn field_names_json(binary: &[u8]) -> String {
+    super::names::field_names_json(binary)
+}
+
+/// Add the datacount section that `array.new_data` needs
+pub fn inject_datacount_section(binary: &mut Vec<u8>) {
+    super::inject_datacount_section(binary)
+}
+
+/// Field names, required proposals and imports, as cached with the binary
+pub fn module_metadata(source: &str, binary: &[u8]) -> ModuleMetadata {
+    ModuleMetadata::new(source, binary)
+}
+
+/// JavaScript loader for a compiled module
+pub fn generate_glue(
+    source: &str,
+    binary: Vec<u8>,
+    metadata: &ModuleMetadata,
+) -> Result<String, CompileError> {
+    super::generate_glue(
+        source.as_bytes(),
+        binary,
+        metadata,
+        "bench.wat",
+        None,
+        &CompileOptions::default(),
+    )
+}
@@ -0,0 +1,157 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reactive text
+//!
+//! `wasmBind` sets the text of elements to what a function returns, again
+//! whenever a module loads or a watched field changes, and elements with a
+//! `data-wasm-bind` attribute are bound to a call of an export. Bindings
+//! call exported functions, so the loader of a module exporting none leaves
+//! `wasmBind` to the modules that do.
+
+use wasmparser::{ExternalKind, Parser, Payload};
+
+/// Whether `binary` exports functions bindings can call
+pub fn exports_functions(binary: &[u8]) -> bool {
+    Parser::new(0)
+        .parse_all(binary)
+        .any(|payload| match payload {
+            Ok(Payload::ExportSection(reader)) => reader
+                .into_iter()
+                .flatten()
+                .any(|export| export.kind == ExternalKind::Func),
+            _ => false,
+        })
+}
+
+/// JavaScript installing `wasmBind` on `window`, unless a module did, for
+/// modules exporting functions, see [`exports_functions`]; `installs` tells
+/// whether the module may, see [`super::exports::installs_helper`]
+pub fn page_helpers_js(installs: impl Fn(&str) -> bool) -> &'static str {
+    if !installs("wasmBind") {
+        return "";
+    }
+    r#"
+
+                    // Reactive text: wasmBind('#score', () => get_score(box), [[box, 'score']])
+                    // sets the text of the elements the selector matches, or of the
+                    // element given, to what the function returns, then again on every
+                    // wasmloaded, each change of the watched fields and each
+                    // wasmBind.refresh(); it returns a function removing the binding.
+                    // Elements with data-wasm-bind="get_score(box)" are bound to the
+                    // call as modules load, watching the ref.field entries listed in
+                    // their data-wasm-watch. Markup can set these attributes, so they
+                    // are parsed, never evaluated: the call is of a function a module
+                    // installed, with numbers or the names of exports and of window
+                    // properties as arguments. Bindings belong to the page, not to a
+                    // module, so they outlive disposing the module that added them
+                    if (!window.wasmBind) {
+                        const bindings = new Set();
+                        const render = function(binding) {
+                            let text;
+                            try {
+                                const value = binding.compute();
+                                text = value === undefined || value === null ? '' : String(value);
+                            } catch (e) {
+                                // Not computable yet, e.g. before the module it calls loads
+                                return;
+                            }
+                            const elements = typeof binding.target === 'string'
+                                ? document.querySelectorAll(binding.target)
+                                : [binding.target];
+                            for (const element of elements) {
+                                if (element.textContent !== text) {
+                                    element.textContent = text;
+                                }
+                            }
+                        };
+                        const refresh = function() {
+                            for (const binding of Array.from(bindings)) {
+                                render(binding);
+                            }
+                        };
+                        window.wasmBind = function(target, compute, watch) {
+                            if ((typeof target !== 'string' && !(target instanceof Element)) ||
+                                typeof compute !== 'function') {
+                                throw new TypeError('wasmBind: expected a selector or an element and a function');
+                            }
+                            const binding = { target: target, compute: compute, unwatch: [] };
+                            for (const [ref, field] of watch || []) {
+                                binding.unwatch.push(window.wasmWatch(ref, field, () => render(binding)));
+                            }
+                            bindings.add(binding);
+                            render(binding);
+                            return function() {
+                                bindings.delete(binding);
+                                for (const unwatch of binding.unwatch.splice(0)) {
+                                    unwatch();
+                                }
+                            };
+                        };
+                        window.wasmBind.refresh = refresh;
+
+                        // Declarative bindings, made once the refs they watch exist
+                        const boundElements = new WeakSet();
+                        const boundExports = Object.create(null);
+                        const bindingName = /^[A-Za-z_$][\w$]*$/;
+                        const bindingValue = function(name) {
+                            if (/^-?\d+(\.\d+)?$/.test(name)) {
+                                return Number(name);
+                            }
+                            if (!bindingName.test(name)) {
+                                throw new SyntaxError('wasmBind: not a name or a number: ' + name);
+                            }
+                            if (name in boundExports) {
+                                return boundExports[name];
+                            }
+                            if (Object.hasOwn(window, name)) {
+                                return window[name];
+                            }
+                            throw new ReferenceError('wasmBind: ' + name + ' is not defined');
+                        };
+                        const bindingCall = function(expression) {
+                            const call = /^\s*([A-Za-z_$][\w$]*)\s*\(([^()]*)\)\s*$/.exec(expression);
+                            if (!call) {
+                                throw new SyntaxError('wasmBind: not a call of an export: ' + expression);
+                            }
+                            const args = call[2].split(',').map(arg => arg.trim()).filter(arg => arg !== '');
+                            return function() {
+                                if (typeof boundExports[call[1]] !== 'function') {
+                                    throw new ReferenceError('wasmBind: no module exports ' + call[1]);
+                                }
+                                return boundExports[call[1]](...args.map(bindingValue));
+                            };
+                        };
+                        const bindElements = function() {
+                            for (const element of document.querySelectorAll('[data-wasm-bind]')) {
+                                if (boundElements.has(element)) {
+                                    continue;
+                                }
+                                let compute, watch;
+                                try {
+                                    compute = bindingCall(element.getAttribute('data-wasm-bind'));
+                                    watch = (element.getAttribute('data-wasm-watch') || '')
+                                        .split(/[\s,]+/)
+                                        .filter(entry => entry.includes('.'))
+                                        .map(entry => [
+                                            bindingValue(entry.slice(0, entry.lastIndexOf('.'))),
+                                            entry.slice(entry.lastIndexOf('.') + 1)
+                                        ]);
+                                } catch (e) {
+                                    continue;
+                                }
+                                boundElements.add(element);
+                                window.wasmBind(element, compute, watch);
+                            }
+                        };
+                        window.addEventListener('wasmloaded', function(event) {
+                            Object.assign(boundExports, event.detail && event.detail.exports);
+                            bindElements();
+                            refresh();
+                        });
+                    }"#
+}
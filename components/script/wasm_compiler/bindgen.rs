@@ -0,0 +1,136 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Modules built by wasm-bindgen
+//!
+//! wasm-bindgen generates the JavaScript its modules import, and the wrappers
+//! of their exports, next to the module; without it the imports cannot be
+//! resolved. Modules importing `__wbindgen_*` or `__wbg_*` functions are
+//! loaded through that JavaScript when `data-wasm-bindgen` gives its URL:
+//!
+//! - with `--target web`, the module of the bindings, whose `initSync`
+//!   instantiates the module
+//! - with `--target bundler`, the `_bg.js` module holding the imports, given
+//!   the instance with `__wbg_set_wasm`
+//!
+//! and the exports of the bindings are installed rather than those of the
+//! module. Without it the loader reports what the module needs instead of
+//! instantiating it.
+
+use super::LOG_TARGET;
+use super::embed;
+use super::imports;
+
+/// Module of the imports of modules that wasm-bindgen did not process yet
+pub const PLACEHOLDER_MODULE: &str = "__wbindgen_placeholder__";
+
+/// Prefixes of the names of the functions the bindings provide
+const IMPORT_PREFIXES: [&str; 2] = ["__wbindgen_", "__wbg_"];
+
+/// The module a wasm-bindgen module imports its bindings from, e.g.
+/// `./app_bg.js`, or `None` if it is not one
+pub fn bindings_module(binary: &[u8]) -> Option<String> {
+    imports::declared_imports(binary)
+        .into_iter()
+        .find(|(module, name, _)| {
+            module == PLACEHOLDER_MODULE
+                || IMPORT_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .map(|(module, _, _)| module)
+}
+
+/// What a wasm-bindgen module without `data-wasm-bindgen` is missing
+pub fn missing_bindings(module: &str) -> String {
+    if module == PLACEHOLDER_MODULE {
+        return format!(
+            "module was built for wasm-bindgen but not processed by it: its imports from \
+             {} are placeholders. Run wasm-bindgen on it and load the generated JavaScript \
+             with data-wasm-bindgen",
+            module
+        );
+    }
+    let shim = module.rsplit('/').next().unwrap_or(module);
+    format!(
+        "module was built by wasm-bindgen and imports its bindings from {:?}, which the \
+         generic loader cannot provide. Add data-wasm-bindgen with the URL of the generated \
+         JavaScript, e.g. data-wasm-bindgen=\"{}\" (--target bundler) or the module of the \
+         bindings (--target web)",
+        module, shim
+    )
+}
+
+/// JavaScript loading a module through the bindings wasm-bindgen generated
+/// for it, whose URL is `shim` and which the module imports as `module`,
+/// and installing their wrappers of the exports on `export_target`
+pub fn loader_js(module: &str, shim: &str, export_target: &str) -> String {
+    format!(
+        r#"
+
+        // wasm-bindgen module (data-wasm-bindgen): instantiated by its bindings
+        // and installed with their wrappers of the exports
+        const bindingsModule = {module};
+        const bindingsUrl = new URL({shim}, document.baseURI).href;
+        import(bindingsUrl)
+            .then(function(bindings) {{
+                if (typeof bindings.initSync === 'function') {{
+                    bindings.initSync({{ module: wasmBytes }});
+                }} else if (typeof bindings.__wbg_set_wasm === 'function') {{
+                    importObject[bindingsModule] = bindings;
+                    const instance = new WebAssembly.Instance(new WebAssembly.Module(wasmBytes), importObject);
+                    bindings.__wbg_set_wasm(instance.exports);
+                    if (typeof instance.exports.__wbindgen_start === 'function') {{
+                        instance.exports.__wbindgen_start();
+                    }}
+                }} else {{
+                    throw new Error(bindingsUrl + ' exports neither initSync nor __wbg_set_wasm');
+                }}
+                const exportTarget = {export_target};
+                const installedExports = {{}};
+                for (const name in bindings) {{
+                    if (name !== 'default' && name !== 'initSync' && !name.startsWith('__wbg')) {{
+                        exportTarget[name] = installedExports[name] = bindings[name];
+                    }}
+                }}
+                wasmEntry.dispose = function() {{
+                    return disposeWasm(exportTarget, installedExports, null);
+                }};
+                watchLifecycle();
+                console.log('WASM module loaded successfully');
+                window.dispatchEvent(new CustomEvent('wasmloaded', {{
+                    detail: {{ filename: wasmFilename, exports: installedExports }}
+                }}));
+            }})
+            .catch(function(e) {{
+                console.error('WASM: wasm-bindgen module failed to load through ' + bindingsUrl + ':', e);
+                dispatchWasmError('wasm-bindgen module failed to load through ' + bindingsUrl + ': ' + e);
+            }});
+        return;"#,
+        module = embed::script_json(&module),
+        shim = embed::script_json(&shim),
+    )
+}
+
+/// JavaScript reporting that the module of `filename` has no bindings for
+/// the imports of `module`
+pub fn missing_bindings_js(module: &str, filename: &str) -> String {
+    let message = missing_bindings(module);
+    log::warn!(
+        target: LOG_TARGET, phase = "bindgen", filename;
+        "{} in {}", message, filename
+    );
+    format!(
+        r#"
+
+        // wasm-bindgen module without its bindings: the imports cannot resolve
+        console.error('WASM: ' + {message});
+        dispatchWasmError({message});
+        return;"#,
+        message = embed::script_json(&message),
+    )
+}
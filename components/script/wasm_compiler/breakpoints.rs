@@ -0,0 +1,100 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Breakpoint hooks
+//!
+//! With `data-breakpoints`, every function the module defines calls
+//! [`IMPORT_NAME`] with its index on entry. The loader routes the calls to the
+//! handler registered with `window.__wasmDebugger.setHandler(handler)`, which
+//! runs synchronously before the function body, so it can inspect state or
+//! pause in a JavaScript debugger. Setting `window.__wasmDebugger.breakpoints`
+//! to a set of function names limits the calls to those functions.
+
+use std::collections::BTreeMap;
+
+use wasm_encoder::reencode;
+
+use super::LOG_TARGET;
+use super::embed;
+use super::instrument::{self, Calls, Functions};
+
+/// Import module of the breakpoint calls, provided by the loader
+pub const IMPORT_MODULE: &str = "__wasm_debug";
+
+/// Import called on function entry
+pub const IMPORT_NAME: &str = "__debug_break";
+
+/// Add the breakpoint calls to `binary` if `enabled`
+/// Returns the names of the functions by index
+pub fn instrument(
+    binary: &mut Vec<u8>,
+    enabled: bool,
+) -> Result<Option<BTreeMap<u32, String>>, reencode::Error> {
+    if !enabled {
+        return Ok(None);
+    }
+    let names = instrument::function_names(binary)?;
+    let instrumented = instrument::add_calls(
+        binary,
+        &Calls {
+            module: IMPORT_MODULE,
+            entry: IMPORT_NAME,
+            exit: None,
+            functions: Functions::All,
+            arguments: false,
+        },
+    )?;
+
+    log::info!(
+        target: LOG_TARGET, phase = "breakpoints", functions = instrumented.functions.len();
+        "Added breakpoint hooks to {} functions", instrumented.functions.len()
+    );
+    Ok(Some(
+        instrumented
+            .functions
+            .into_iter()
+            .map(|index| (index, instrument::display_name(&names, index)))
+            .collect(),
+    ))
+}
+
+/// JavaScript routing the calls of the functions named by index in `names`
+/// to the handler of `window.__wasmDebugger`
+pub fn loader_js(names: &BTreeMap<u32, String>) -> String {
+    format!(
+        r#"
+
+        // Breakpoint hooks (data-breakpoints), see window.__wasmDebugger
+        window.__wasmDebugger = window.__wasmDebugger || {{
+            handler: null,
+            // Function names to break in; empty breaks in every function
+            breakpoints: new Set(),
+            setHandler: function(handler) {{
+                this.handler = handler;
+            }}
+        }};
+        const wasmBreakNames = {};
+        importObject['{module}'] = {{
+            '{name}': function(index) {{
+                const debug = window.__wasmDebugger;
+                const name = wasmBreakNames[index];
+                if (typeof debug.handler !== 'function' ||
+                    (debug.breakpoints.size > 0 && !debug.breakpoints.has(name))) {{
+                    return;
+                }}
+                try {{
+                    debug.handler({{ filename: wasmFilename, index: index, name: name }});
+                }} catch (e) {{
+                    console.error('WASM: Breakpoint handler error:', e);
+                }}
+            }}
+        }};"#,
+        embed::script_json(names),
+        module = IMPORT_MODULE,
+        name = IMPORT_NAME,
+    )
+}
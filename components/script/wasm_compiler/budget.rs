@@ -0,0 +1,520 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memory budget of a page
+//!
+//! The `dom_wat_scripts_memory_budget_mb` pref caps the WebAssembly memory
+//! the modules of a document allocate together, so that one page cannot
+//! exhaust the memory of the embedder:
+//!
+//! - the memories a module defines get a maximum of at most the budget, so
+//!   `memory.grow` fails past it, as it does past any maximum
+//! - `memory.grow` on them asks the loader first through [`IMPORT_MODULE`],
+//!   and fails, returning -1, if the growth does not fit in what the other
+//!   modules of the page leave of the budget
+//! - a module is not instantiated if the memory it declares is more than the
+//!   budget leaves beside the modules loaded before, nor are further
+//!   instances of it; the page gets a quota error instead
+//!
+//! An instance counts as declared with the growth `memory.grow` was granted,
+//! or as large as its exported memories are if the page grew them further.
+//! Memories the page passes in are its own and not counted.
+
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{
+    BlockType, CodeSection, EntityType, Function, ImportSection, Instruction, MemorySection,
+    Module, SectionId, TypeSection, ValType,
+};
+use wasmparser::{
+    CompositeInnerType, FunctionBody, ImportSectionReader, MemorySectionReader, MemoryType,
+    Operator, Parser, Payload, TypeRef, TypeSectionReader,
+};
+
+/// Log2 of the size of a page of memory, unless it declares another
+const PAGE_SIZE_LOG2: u32 = 16;
+
+/// Module of the imports `memory.grow` calls, see [`guard_growth`]
+pub const IMPORT_MODULE: &str = "__wasm_budget";
+
+/// The budget of the `dom_wat_scripts_memory_budget_mb` pref in bytes;
+/// `None` for no budget, `0` or less
+pub fn from_pref(megabytes: i64) -> Option<u64> {
+    u64::try_from(megabytes)
+        .ok()
+        .filter(|megabytes| *megabytes > 0)
+        .map(|megabytes| megabytes.saturating_mul(1 << 20))
+}
+
+/// JavaScript checking memory against a budget of `limit` bytes, for the
+/// modules held under the name `holder` evaluates to in
+/// `window.__wasmMemoryUse`:
+///
+/// - `memoryQuotaError(needed)` is why they cannot hold `needed` bytes beside
+///   what the others hold now, or `null` if they can
+/// - `memoryGrowth(pageBytes, held, grant)` are the imports of
+///   [`guard_growth`] for a module with the page sizes `pageBytes`, given
+///   what its holder holds and telling it the bytes granted or given back
+pub fn loader_js(limit: u64, holder: &str) -> String {
+    format!(
+        r#"
+        const memoryBudget = {limit};
+        const memoryQuotaError = function(needed) {{
+            const held = Object.keys(window.__wasmMemoryUse || {{}})
+                .filter(name => name !== {holder})
+                .reduce((total, name) => total + window.__wasmMemoryUse[name](), 0);
+            const left = Math.max(0, memoryBudget - held);
+            return needed > left
+                ? 'memory quota exceeded: module needs ' + needed + ' bytes of memory, the page has ' + left +
+                    ' of its budget of ' + memoryBudget + ' bytes left'
+                : null;
+        }};
+        const memoryGrowth = function(pageBytes, held, grant) {{
+            return {{
+                grow: function(memory, pages) {{
+                    const bytes = (pages >>> 0) * pageBytes[memory];
+                    if (memoryQuotaError(held() + bytes)) {{
+                        return 0;
+                    }}
+                    grant(bytes);
+                    return 1;
+                }},
+                release: function(memory, pages) {{
+                    grant(-(pages >>> 0) * pageBytes[memory]);
+                }}
+            }};
+        }};"#
+    )
+}
+
+/// Bytes of the memories a module defines, at their initial size
+pub fn initial_bytes(binary: &[u8]) -> u64 {
+    defined_memories(binary)
+        .iter()
+        .map(|memory| {
+            memory
+                .initial
+                .saturating_mul(1 << memory.page_size_log2.unwrap_or(PAGE_SIZE_LOG2))
+        })
+        .fold(0, u64::saturating_add)
+}
+
+/// Give the memories a module defines a maximum of at most `budget` bytes,
+/// or their initial size if that is more; whether any changed
+pub fn cap_memories(binary: &mut Vec<u8>, budget: u64) -> Result<bool, reencode::Error> {
+    if defined_memories(binary)
+        .into_iter()
+        .all(|memory| capped(memory, budget) == memory.maximum)
+    {
+        return Ok(false);
+    }
+    let mut module = Module::new();
+    Capper { budget }.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+    Ok(true)
+}
+
+/// Make `memory.grow` on the memories a module defines ask the loader first:
+/// `grow(memory, pages)` returns whether the memory may grow by that many
+/// pages, and `release(memory, pages)` gives them back if it could not.
+/// Pages past `u32::MAX` pass as `-1`. Whether the module grows any
+pub fn guard_growth(binary: &mut Vec<u8>) -> Result<bool, reencode::Error> {
+    let mut layout = Layout::default();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for group in reader {
+                    for ty in group?.types() {
+                        layout.params.push(match &ty.composite_type.inner {
+                            CompositeInnerType::Func(func) => func.params().len() as u32,
+                            _ => 0,
+                        });
+                    }
+                }
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    match import?.ty {
+                        TypeRef::Func(_) => layout.imported_functions += 1,
+                        TypeRef::Memory(memory) => layout.memories.push((memory, false)),
+                        _ => {},
+                    }
+                }
+            },
+            Payload::FunctionSection(reader) => {
+                for ty in reader {
+                    layout.function_types.push(ty?);
+                }
+            },
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    layout.memories.push((memory?, true));
+                }
+            },
+            Payload::CodeSectionEntry(body) => {
+                let mut grows = (false, false);
+                let mut reader = body.get_operators_reader()?;
+                while !reader.eof() {
+                    if let Operator::MemoryGrow { mem } = reader.read()? {
+                        match layout.memories.get(mem as usize) {
+                            Some((memory, true)) if memory.memory64 => grows.1 = true,
+                            Some((_, true)) => grows.0 = true,
+                            _ => {},
+                        }
+                    }
+                }
+                layout.grows.push(grows);
+            },
+            _ => {},
+        }
+    }
+    if !layout.grows.iter().any(|grows| grows.0 || grows.1) {
+        return Ok(false);
+    }
+    let mut guard = Guard {
+        first_type: layout.params.len() as u32,
+        layout,
+        next_body: 0,
+        types_added: false,
+        imports_added: false,
+    };
+    let mut module = Module::new();
+    guard.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+    Ok(true)
+}
+
+/// Bytes of a page of each memory of a module by index, as the loader
+/// counts the pages `memory.grow` asks for
+pub fn page_bytes(binary: &[u8]) -> Vec<u64> {
+    Parser::new(0)
+        .parse_all(binary)
+        .flat_map(|payload| match payload {
+            Ok(Payload::ImportSection(reader)) => reader
+                .into_iter()
+                .flatten()
+                .filter_map(|import| match import.ty {
+                    TypeRef::Memory(memory) => Some(memory),
+                    _ => None,
+                })
+                .collect(),
+            Ok(Payload::MemorySection(reader)) => reader.into_iter().flatten().collect(),
+            _ => Vec::new(),
+        })
+        .map(|memory| 1 << memory.page_size_log2.unwrap_or(PAGE_SIZE_LOG2))
+        .collect()
+}
+
+/// What [`guard_growth`] needs to know of a module
+#[derive(Default)]
+struct Layout {
+    /// Number of parameters of each type, 0 for those not of functions
+    params: Vec<u32>,
+    imported_functions: u32,
+    function_types: Vec<u32>,
+    /// Memories by index, and whether the module defines them
+    memories: Vec<(MemoryType, bool)>,
+    /// Whether each function body grows a defined 32-bit and 64-bit memory
+    grows: Vec<(bool, bool)>,
+}
+
+struct Guard {
+    layout: Layout,
+    /// Type index of the imports' types, `grow` then `release`
+    first_type: u32,
+    next_body: usize,
+    types_added: bool,
+    imports_added: bool,
+}
+
+impl Guard {
+    fn add_types(&mut self, types: &mut TypeSection) {
+        types
+            .ty()
+            .function([ValType::I32, ValType::I32], [ValType::I32]);
+        types.ty().function([ValType::I32, ValType::I32], []);
+        self.types_added = true;
+    }
+
+    fn add_imports(&mut self, imports: &mut ImportSection) {
+        imports.import(IMPORT_MODULE, "grow", EntityType::Function(self.first_type));
+        imports.import(
+            IMPORT_MODULE,
+            "release",
+            EntityType::Function(self.first_type + 1),
+        );
+        self.imports_added = true;
+    }
+
+    /// `memory.grow` of `memory`, with the pages to grow by and the result
+    /// in the locals from `temporary`
+    fn grow(&self, function: &mut Function, memory: u32, memory64: bool, temporary: u32) {
+        let (pages, grown) = (temporary, temporary + 1);
+        let (value_type, failed) = if memory64 {
+            (ValType::I64, Instruction::I64Const(-1))
+        } else {
+            (ValType::I32, Instruction::I32Const(-1))
+        };
+        let grow = self.layout.imported_functions;
+        let release = grow + 1;
+        let pages_argument = |function: &mut Function| {
+            function.instruction(&Instruction::I32Const(memory as i32));
+            function.instruction(&Instruction::LocalGet(pages));
+            if memory64 {
+                function.instruction(&Instruction::I64Const(u32::MAX as i64));
+                function.instruction(&Instruction::I64GtU);
+                function.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+                function.instruction(&Instruction::I32Const(-1));
+                function.instruction(&Instruction::Else);
+                function.instruction(&Instruction::LocalGet(pages));
+                function.instruction(&Instruction::I32WrapI64);
+                function.instruction(&Instruction::End);
+            }
+        };
+        function.instruction(&Instruction::LocalSet(pages));
+        pages_argument(function);
+        function.instruction(&Instruction::Call(grow));
+        function.instruction(&Instruction::If(BlockType::Result(value_type)));
+        function.instruction(&Instruction::LocalGet(pages));
+        function.instruction(&Instruction::MemoryGrow(memory));
+        function.instruction(&Instruction::LocalTee(grown));
+        function.instruction(&failed);
+        function.instruction(if memory64 {
+            &Instruction::I64Eq
+        } else {
+            &Instruction::I32Eq
+        });
+        function.instruction(&Instruction::If(BlockType::Empty));
+        pages_argument(function);
+        function.instruction(&Instruction::Call(release));
+        function.instruction(&Instruction::End);
+        function.instruction(&Instruction::LocalGet(grown));
+        function.instruction(&Instruction::Else);
+        function.instruction(&failed);
+        function.instruction(&Instruction::End);
+    }
+}
+
+impl Reencode for Guard {
+    type Error = Infallible;
+
+    fn function_index(&mut self, func: u32) -> u32 {
+        if func < self.layout.imported_functions {
+            func
+        } else {
+            func + 2
+        }
+    }
+
+    fn parse_type_section(
+        &mut self,
+        types: &mut TypeSection,
+        section: TypeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_type_section(self, types, section)?;
+        self.add_types(types);
+        Ok(())
+    }
+
+    fn parse_import_section(
+        &mut self,
+        imports: &mut ImportSection,
+        section: ImportSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_import_section(self, imports, section)?;
+        self.add_imports(imports);
+        Ok(())
+    }
+
+    // Modules without imports get an import section in its place
+    fn intersperse_section_hook(
+        &mut self,
+        module: &mut Module,
+        _after: Option<SectionId>,
+        before: Option<SectionId>,
+    ) -> Result<(), reencode::Error> {
+        if !self.types_added && before != Some(SectionId::Type) {
+            let mut types = TypeSection::new();
+            self.add_types(&mut types);
+            module.section(&types);
+        }
+        if !self.imports_added && !matches!(before, Some(SectionId::Type | SectionId::Import)) {
+            let mut imports = ImportSection::new();
+            self.add_imports(&mut imports);
+            module.section(&imports);
+        }
+        Ok(())
+    }
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut CodeSection,
+        body: FunctionBody<'_>,
+    ) -> Result<(), reencode::Error> {
+        let position = self.next_body;
+        self.next_body += 1;
+        let (grows32, grows64) = self.layout.grows[position];
+        if !grows32 && !grows64 {
+            return reencode::utils::parse_function_body(self, code, body);
+        }
+
+        // Two locals of each index type grown: the pages, then the result
+        let ty = self.layout.function_types[position];
+        let mut locals = Vec::new();
+        let mut next_local = self.layout.params.get(ty as usize).copied().unwrap_or(0);
+        for pair in body.get_locals_reader()? {
+            let (count, ty) = pair?;
+            locals.push((count, self.val_type(ty)?));
+            next_local += count;
+        }
+        let temporary32 = next_local;
+        if grows32 {
+            locals.push((2, ValType::I32));
+            next_local += 2;
+        }
+        let temporary64 = next_local;
+        if grows64 {
+            locals.push((2, ValType::I64));
+        }
+
+        let mut function = Function::new(locals);
+        let mut reader = body.get_operators_reader()?;
+        while !reader.eof() {
+            let operator = reader.read()?;
+            if let Operator::MemoryGrow { mem } = operator
+                && let Some((memory, true)) = self.layout.memories.get(mem as usize)
+            {
+                let memory64 = memory.memory64;
+                let temporary = if memory64 { temporary64 } else { temporary32 };
+                self.grow(&mut function, mem, memory64, temporary);
+                continue;
+            }
+            function.instruction(&self.instruction(operator)?);
+        }
+        code.function(&function);
+        Ok(())
+    }
+}
+
+fn defined_memories(binary: &[u8]) -> Vec<MemoryType> {
+    Parser::new(0)
+        .parse_all(binary)
+        .filter_map(|payload| match payload {
+            Ok(Payload::MemorySection(reader)) => Some(reader.into_iter().flatten()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// The maximum of `memory` under `budget`, in pages
+fn capped(memory: MemoryType, budget: u64) -> Option<u64> {
+    let page_size_log2 = memory.page_size_log2.unwrap_or(PAGE_SIZE_LOG2);
+    // The most pages an index type can address, which is also the most a
+    // maximum may be
+    let addressable = if memory.memory64 {
+        u64::MAX >> page_size_log2
+    } else {
+        (1 << 32) >> page_size_log2
+    };
+    let cap = (budget >> page_size_log2).max(memory.initial);
+    match memory.maximum {
+        Some(maximum) => Some(maximum.min(cap)),
+        None if cap < addressable => Some(cap),
+        None => None,
+    }
+}
+
+struct Capper {
+    budget: u64,
+}
+
+impl Reencode for Capper {
+    type Error = Infallible;
+
+    fn parse_memory_section(
+        &mut self,
+        memories: &mut MemorySection,
+        section: MemorySectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for memory in section {
+            let memory = memory?;
+            let mut encoded = reencode::utils::memory_type(self, memory);
+            encoded.maximum = capped(memory, self.budget);
+            memories.memory(encoded);
+        }
+        Ok(())
+    }
+}
+
+/// JavaScript holding a module of `binary` to the budget of `limit` bytes:
+/// the check that it fits and the imports of [`guard_growth`], made before it
+/// is instantiated, what its instances hold once it is, and giving that
+/// back if it fails to
+pub fn module_js(binary: &[u8], limit: u64) -> (String, String, &'static str) {
+    let initial = initial_bytes(binary);
+    let page_bytes = serde_json::to_string(&page_bytes(binary)).unwrap_or_default();
+    let quota = loader_js(limit, "wasmFilename");
+    (
+        format!(
+            r#"
+
+        // Memory budget of the page (dom_wat_scripts_memory_budget_mb): the other modules
+        // count with the memory they hold now, each instance of this one as declared{quota}
+        if (memoryQuotaError({initial})) {{
+            console.error('WASM: ' + memoryQuotaError({initial}));
+            dispatchWasmError(memoryQuotaError({initial}));
+            return;
+        }}
+        // memory.grow asks here first, and fails if the growth does not fit;
+        // what it was granted counts for the module until the page leaves
+        const memoryPageBytes = {page_bytes};
+        let memoryGrown = 0;
+        let memoryHeld = () => {initial} + memoryGrown;
+        importObject['{module}'] = memoryGrowth(memoryPageBytes, () => memoryHeld(), function(bytes) {{
+            memoryGrown += bytes;
+        }});
+        // Reserved while it is instantiated, for the scripts that follow
+        window.__wasmMemoryUse = window.__wasmMemoryUse || {{}};
+        window.__wasmMemoryUse[wasmFilename] = () => memoryHeld();"#,
+            module = IMPORT_MODULE,
+        ),
+        format!(
+            r#"
+
+                // Memory held against the page's budget: each instance as declared
+                // with what memory.grow was granted, or the exported memories as
+                // large as they are if the page grew them further
+                const memoryHolders = [result.instance];
+                const exportedMemory = function(instance) {{
+                    return Object.values(instance.exports)
+                        .filter(value => value instanceof WebAssembly.Memory)
+                        .reduce((total, memory) => total + memory.buffer.byteLength, 0);
+                }};
+                memoryHeld = () => Math.max(memoryHolders.length * {initial} + memoryGrown,
+                    memoryHolders.reduce((total, instance) => total + exportedMemory(instance), 0));
+                window.__wasmMemoryUse = window.__wasmMemoryUse || {{}};
+                window.__wasmMemoryUse[wasmFilename] = memoryHeld;
+                const instantiateUnbudgeted = window.__wasmModules[wasmFilename].instantiate;
+                window.__wasmModules[wasmFilename].instantiate = function(imports) {{
+                    const quotaError = memoryQuotaError(memoryHeld() + {initial});
+                    if (quotaError) {{
+                        return Promise.reject(new Error('instantiate: ' + quotaError));
+                    }}
+                    return instantiateUnbudgeted(imports).then(function(instance) {{
+                        memoryHolders.push(instance);
+                        return instance;
+                    }});
+                }};"#,
+        ),
+        r#"
+                if (window.__wasmMemoryUse) {
+                    delete window.__wasmMemoryUse[wasmFilename];
+                }"#,
+    )
+}
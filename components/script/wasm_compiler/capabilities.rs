@@ -0,0 +1,198 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Capability detection
+//!
+//! The proposals a module needs are found at compile time by validating it
+//! with each proposal disabled in turn. The loader probes the JS engine with
+//! tiny modules that each use a single proposal, so a missing capability is
+//! reported as e.g. "module requires wasm GC which is disabled" (and in
+//! `window.__wasmCapabilities`) instead of a generic instantiation failure.
+//! Proposals disabled by preferences are reported as unsupported even if the
+//! engine has them.
+
+use wasmparser::{Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+use super::options::FEATURES;
+
+/// Module header shared by the probes
+const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+/// A probe's sections after the header, one probe per `data-features` name,
+/// with the label used in error messages
+pub const PROBES: [(&str, &str, &[u8]); 9] = [
+    // (type (struct))
+    ("gc", "GC", &[0x01, 0x03, 0x01, 0x5F, 0x00]),
+    // (memory 1 1 shared)
+    ("threads", "threads", &[0x05, 0x04, 0x01, 0x03, 0x01, 0x01]),
+    // (tag (param))
+    (
+        "exceptions",
+        "exception handling",
+        &[
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x0D, 0x03, 0x01, 0x00, 0x00,
+        ],
+    ),
+    // (func return_call 0)
+    (
+        "tail-call",
+        "tail calls",
+        &[
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x0A, 0x06, 0x01, 0x04,
+            0x00, 0x12, 0x00, 0x0B,
+        ],
+    ),
+    // (func v128.const 0 drop)
+    (
+        "simd",
+        "SIMD",
+        &[
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x0A, 0x17, 0x01, 0x15,
+            0x00, 0xFD, 0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x1A, 0x0B,
+        ],
+    ),
+    // (func v128.const 0 v128.const 0 i8x16.relaxed_swizzle drop)
+    (
+        "relaxed-simd",
+        "relaxed SIMD",
+        &[
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x0A, 0x2C, 0x01, 0x2A,
+            0x00, 0xFD, 0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFD, 0x0C, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFD, 0x80, 0x02, 0x1A, 0x0B,
+        ],
+    ),
+    // (memory i64 1)
+    ("memory64", "memory64", &[0x05, 0x03, 0x01, 0x04, 0x01]),
+    // (memory 1) (memory 1)
+    (
+        "multi-memory",
+        "multiple memories",
+        &[0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x01],
+    ),
+    // (global i32 (i32.add (i32.const 1) (i32.const 2)))
+    (
+        "extended-const",
+        "extended constant expressions",
+        &[
+            0x06, 0x09, 0x01, 0x7F, 0x00, 0x41, 0x01, 0x41, 0x02, 0x6A, 0x0B,
+        ],
+    ),
+];
+
+/// Complete probe module for a feature
+pub fn probe_module(sections: &[u8]) -> Vec<u8> {
+    [&HEADER[..], sections].concat()
+}
+
+/// Names of the proposals beyond WebAssembly 2.0 that a module uses
+/// Modules that do not validate at all report nothing; instantiation will
+/// produce the actual error
+pub fn required_features(binary: &[u8]) -> Vec<&'static str> {
+    let validates = |features: WasmFeatures| {
+        Validator::new_with_features(features)
+            .validate_all(binary)
+            .is_ok()
+    };
+    if !validates(WasmFeatures::WASM3) {
+        return Vec::new();
+    }
+
+    FEATURES
+        .iter()
+        .filter(|(_, flags)| !validates(WasmFeatures::WASM3.difference(*flags)))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// Whether the module defines or imports a shared memory, which browsers
+/// only allow in cross-origin isolated documents
+pub fn uses_shared_memory(binary: &[u8]) -> bool {
+    Parser::new(0)
+        .parse_all(binary)
+        .flatten()
+        .any(|payload| match payload {
+            Payload::ImportSection(reader) => reader
+                .into_iter()
+                .flatten()
+                .any(|import| matches!(import.ty, TypeRef::Memory(memory) if memory.shared)),
+            Payload::MemorySection(reader) => {
+                reader.into_iter().flatten().any(|memory| memory.shared)
+            },
+            _ => false,
+        })
+}
+
+/// JSON object of feature name to `[label, probe bytes]`, for the loader
+pub fn probes_json() -> String {
+    let probes: serde_json::Map<String, serde_json::Value> = PROBES
+        .iter()
+        .map(|(name, label, sections)| {
+            (
+                name.to_string(),
+                serde_json::json!([label, probe_module(sections)]),
+            )
+        })
+        .collect();
+    serde_json::Value::Object(probes).to_string()
+}
+
+/// JSON array of the feature names enabled in `features`, for the loader
+pub fn enabled_json(features: WasmFeatures) -> String {
+    let names: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, flags)| features.contains(*flags))
+        .map(|(name, _)| *name)
+        .collect();
+    serde_json::to_string(&names).unwrap_or_default()
+}
+
+/// JavaScript reporting that the origin has no permission for the shared
+/// memory or threads a module uses
+pub fn threads_denied_js() -> &'static str {
+    r#"
+
+        // Shared memory and threads need the permission of the origin
+        const threadsDenied = 'module uses shared memory or threads, which ' + location.origin +
+            ' has no permission for';
+        console.error('WASM: ' + threadsDenied);
+        dispatchWasmError(threadsDenied, { kind: 'permission', permission: 'threads', origin: location.origin });
+        return;"#
+}
+
+/// JavaScript reporting the headers a document lacks to be cross-origin
+/// isolated, as the shared memory of a module needs
+pub fn isolation_check_js() -> &'static str {
+    r#"
+
+        // Shared memory needs a cross-origin isolated document
+        if (window.crossOriginIsolated === false || typeof SharedArrayBuffer === 'undefined') {
+            const isolationHeaders = [
+                ['Cross-Origin-Opener-Policy', ['same-origin']],
+                ['Cross-Origin-Embedder-Policy', ['require-corp', 'credentialless']]
+            ];
+            const reportIsolation = function(headers) {
+                const missing = isolationHeaders.filter(function(header) {
+                    const value = headers && headers.get(header[0]);
+                    return !value || !header[1].includes(value.split(';')[0].trim().toLowerCase());
+                }).map(function(header) {
+                    return header[0] + ': ' + header[1][0];
+                });
+                const message = 'module uses shared memory, which requires a cross-origin isolated document: ' +
+                    (missing.length > 0
+                        ? 'serve it with ' + missing.join(' and ')
+                        : 'its headers are set, so an embedding document or a subresource is not isolated');
+                console.error('WASM: ' + message);
+                dispatchWasmError(message);
+            };
+            fetch(location.href, { method: 'HEAD' }).then(function(response) {
+                reportIsolation(response.headers);
+            }, function() {
+                reportIsolation(null);
+            });
+            return;
+        }"#
+}
@@ -0,0 +1,1024 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Components and their WIT bindings
+//!
+//! Modules built with wit-bindgen embed the WIT world they implement in
+//! `component-type` custom sections, and export its functions, and import
+//! those it needs, lowered with the canonical ABI. The loader lifts them
+//! again, so the page calls them with JavaScript values:
+//!
+//! - the exports of the world go on `window[world]`, those of its interfaces
+//!   on `window[world][interface]`, with names in lower camel case
+//! - the imports are the page's functions of the same names, on `window`
+//!   or on `window[interface]`
+//! - records are objects with lower camel case fields, tuples arrays, flags
+//!   objects of booleans, enums their case names, variants and results
+//!   `{ tag, val }` objects, options their value or `undefined`, and lists
+//!   of numbers typed arrays
+//! - a function returning a `result` returns its `ok` value and throws its
+//!   `err` value as the `payload` of a `ComponentError`, and an import
+//!   returning one does the same
+//!
+//! Strings are UTF-8, as wit-bindgen builds modules by default. Functions
+//! using resources, futures or streams are left out.
+//!
+//! A component (as made by `wasm-tools component new`) is loaded as its main
+//! module, the first, with its world embedded like wit-bindgen does; modules
+//! it adapts from WASI preview 1 get the loader's own, see [`super::wasi`].
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use wasm_encoder::{CustomSection, Encode, Section};
+use wasmparser::{ExternalKind, Parser, Payload};
+use wit_parser::decoding::DecodedWasm;
+use wit_parser::{
+    Function, FunctionKind, Resolve, Results, Type, TypeDefKind, WorldItem, WorldKey,
+};
+
+use super::LOG_TARGET;
+use super::embed;
+use super::imports;
+
+/// Prefix of the names of the custom sections the world is embedded in
+const SECTION_PREFIX: &str = "component-type";
+
+/// Module of the imports of functions of the world itself
+const ROOT_MODULE: &str = "$root";
+
+/// Name of the world sections are merged into, and of those of components
+const ANONYMOUS_WORLD: &str = "root";
+
+/// Whether `binary` is a component rather than a core module
+pub fn is_component(binary: &[u8]) -> bool {
+    binary.len() >= 8 && binary[0..4] == *b"\0asm" && binary[6..8] == [1, 0]
+}
+
+/// The main module of a component, with the component's world embedded
+pub fn core_module(component: &[u8]) -> Result<Vec<u8>, String> {
+    let (resolve, world) = match wit_parser::decoding::decode(component) {
+        Ok(DecodedWasm::Component(resolve, world)) => (resolve, world),
+        Ok(DecodedWasm::WitPackage(..)) => {
+            return Err("binary holds WIT packages, not a component".to_string());
+        },
+        Err(e) => return Err(format!("invalid component: {:#}", e)),
+    };
+
+    // Modules nested in components are skipped along with them
+    let mut depth = 0;
+    let mut main = None;
+    for payload in Parser::new(0).parse_all(component) {
+        match payload.map_err(|e| e.to_string())? {
+            Payload::Version { .. } => depth += 1,
+            Payload::End(_) => depth -= 1,
+            Payload::ModuleSection {
+                unchecked_range, ..
+            } if depth == 1 => {
+                main = Some(unchecked_range);
+                break;
+            },
+            _ => {},
+        }
+    }
+    let main = main.ok_or("component has no core module")?;
+
+    let world_section =
+        wit_component::metadata::encode(&resolve, world, wit_component::StringEncoding::UTF8, None)
+            .map_err(|e| format!("cannot embed the world of the component: {:#}", e))?;
+    let section = CustomSection {
+        name: format!("{}:{}", SECTION_PREFIX, resolve.worlds[world].name).into(),
+        data: world_section.into(),
+    };
+    let mut module = component[main].to_vec();
+    module.push(section.id());
+    section.encode(&mut module);
+    Ok(module)
+}
+
+/// The bindings of the world a module implements, see the module
+/// documentation
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Bindings {
+    /// The world's name in lower camel case, where its exports go on
+    /// `window`; `None` for components, whose worlds are anonymous, which go
+    /// by the name of the script instead
+    pub world: Option<String>,
+    pub exports: Vec<Binding>,
+    pub imports: Vec<Binding>,
+}
+
+/// A function of the world the module exports or imports
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Binding {
+    /// The interface the function is in, in lower camel case, or `None` for
+    /// those of the world
+    pub interface: Option<String>,
+    /// The function's name in lower camel case
+    pub name: String,
+    /// The module of an import
+    pub module: Option<String>,
+    /// The core function's name
+    pub core: String,
+    /// Parameters by name, with their types, see [`type_json`]
+    pub params: Vec<(String, Value)>,
+    pub result: Option<Value>,
+}
+
+/// The bindings of the world embedded in `binary`, or `None` if it embeds
+/// none
+pub fn bindings(binary: &[u8]) -> Option<Bindings> {
+    let mut exports = Vec::new();
+    let mut embeds_world = false;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload {
+            Ok(Payload::ExportSection(reader)) => exports.extend(
+                reader
+                    .into_iter()
+                    .flatten()
+                    .filter(|export| export.kind == ExternalKind::Func)
+                    .map(|export| export.name.to_string()),
+            ),
+            Ok(Payload::CustomSection(section)) => {
+                embeds_world |= section.name().starts_with(SECTION_PREFIX)
+            },
+            _ => {},
+        }
+    }
+    if !embeds_world {
+        return None;
+    }
+    let bindgen = match wit_component::metadata::decode(binary) {
+        Ok((_, bindgen)) => bindgen,
+        Err(e) => {
+            log::warn!(
+                target: LOG_TARGET, phase = "component";
+                "Ignoring the embedded WIT world: {:#}", e
+            );
+            return None;
+        },
+    };
+    let resolve = &bindgen.resolve;
+    let world = &resolve.worlds[bindgen.world];
+    let imports = imports::declared_imports(binary);
+
+    // The sections are merged into an anonymous world, but their own are
+    // kept alongside it
+    let name = resolve
+        .worlds
+        .iter()
+        .map(|(_, world)| world.name.as_str())
+        .find(|name| *name != ANONYMOUS_WORLD);
+    let mut bindings = Bindings {
+        world: name.map(js_name),
+        exports: Vec::new(),
+        imports: Vec::new(),
+    };
+    for (key, item) in &world.exports {
+        for (interface, function) in functions(resolve, key, item) {
+            let core = match &interface {
+                Some(_) => format!("{}#{}", resolve.name_world_key(key), function.name),
+                None => function.name.clone(),
+            };
+            if exports.contains(&core) {
+                bindings
+                    .exports
+                    .extend(binding(resolve, interface, None, core, function));
+            }
+        }
+    }
+    for (key, item) in &world.imports {
+        for (interface, function) in functions(resolve, key, item) {
+            let module = match &interface {
+                Some(_) => resolve.name_world_key(key),
+                None => ROOT_MODULE.to_string(),
+            };
+            if imports
+                .iter()
+                .any(|(m, name, _)| *m == module && *name == function.name)
+            {
+                let core = function.name.clone();
+                bindings
+                    .imports
+                    .extend(binding(resolve, interface, Some(module), core, function));
+            }
+        }
+    }
+    Some(bindings)
+}
+
+/// The bindings as JSON for the loader, for modules embedding a world
+pub fn loader_json(binary: &[u8]) -> Option<String> {
+    bindings(binary).and_then(|bindings| serde_json::to_string(&bindings).ok())
+}
+
+/// The plain functions of a world item, with the name of their interface
+fn functions<'a>(
+    resolve: &'a Resolve,
+    key: &WorldKey,
+    item: &'a WorldItem,
+) -> Vec<(Option<String>, &'a Function)> {
+    match item {
+        WorldItem::Function(function) => vec![(None, function)],
+        WorldItem::Interface { id, .. } => {
+            let interface = &resolve.interfaces[*id];
+            let name = match key {
+                WorldKey::Name(name) => name.clone(),
+                WorldKey::Interface(_) => interface.name.clone().unwrap_or_default(),
+            };
+            interface
+                .functions
+                .values()
+                .map(|function| (Some(js_name(&name)), function))
+                .collect()
+        },
+        WorldItem::Type(_) => Vec::new(),
+    }
+    .into_iter()
+    .filter(|(_, function)| matches!(function.kind, FunctionKind::Freestanding))
+    .collect()
+}
+
+/// The binding of a function, or `None` if it has types the loader cannot
+/// lift
+fn binding(
+    resolve: &Resolve,
+    interface: Option<String>,
+    module: Option<String>,
+    core: String,
+    function: &Function,
+) -> Option<Binding> {
+    let params = function
+        .params
+        .iter()
+        .map(|(name, ty)| Some((js_name(name), type_json(resolve, ty)?)))
+        .collect::<Option<Vec<_>>>();
+    let result = match &function.results {
+        Results::Anon(ty) => type_json(resolve, ty).map(Some),
+        Results::Named(named) => match named.as_slice() {
+            [] => Some(None),
+            [(_, ty)] => type_json(resolve, ty).map(Some),
+            // Several results are returned as an object
+            named => named
+                .iter()
+                .map(|(name, ty)| Some(json!([js_name(name), type_json(resolve, ty)?])))
+                .collect::<Option<Vec<_>>>()
+                .map(|fields| Some(json!({ "record": fields }))),
+        },
+    };
+    let (Some(params), Some(result)) = (params, result) else {
+        log::warn!(
+            target: LOG_TARGET, phase = "component", function:% = core;
+            "Leaving out {}, its types need resources, futures or streams", core
+        );
+        return None;
+    };
+    Some(Binding {
+        interface,
+        name: js_name(&function.name),
+        module,
+        core,
+        params,
+        result,
+    })
+}
+
+/// The type for the loader: the name of a primitive type, or an object
+/// whose key is the kind of type, e.g. `{"list": "u8"}`, `{"record":
+/// [["x", "f32"], ["y", "f32"]]}` or `{"result": ["string", null]}`; `None`
+/// for resources, futures and streams
+pub fn type_json(resolve: &Resolve, ty: &Type) -> Option<Value> {
+    let name = match ty {
+        Type::Bool => "bool",
+        Type::U8 => "u8",
+        Type::U16 => "u16",
+        Type::U32 => "u32",
+        Type::U64 => "u64",
+        Type::S8 => "s8",
+        Type::S16 => "s16",
+        Type::S32 => "s32",
+        Type::S64 => "s64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+        Type::Char => "char",
+        Type::String => "string",
+        Type::Id(id) => {
+            let json = |ty: &Type| type_json(resolve, ty);
+            let optional = |ty: &Option<Type>| ty.as_ref().map_or(Some(Value::Null), json);
+            return Some(match &resolve.types[*id].kind {
+                TypeDefKind::Type(ty) => return json(ty),
+                TypeDefKind::Record(record) => json!({ "record": record
+                    .fields
+                    .iter()
+                    .map(|field| Some(json!([js_name(&field.name), json(&field.ty)?])))
+                    .collect::<Option<Vec<_>>>()? }),
+                TypeDefKind::Tuple(tuple) => json!({ "tuple": tuple
+                    .types
+                    .iter()
+                    .map(json)
+                    .collect::<Option<Vec<_>>>()? }),
+                TypeDefKind::Flags(flags) => json!({ "flags": flags
+                    .flags
+                    .iter()
+                    .map(|flag| js_name(&flag.name))
+                    .collect::<Vec<_>>() }),
+                TypeDefKind::Enum(enum_) => json!({ "enum": enum_
+                    .cases
+                    .iter()
+                    .map(|case| case.name.clone())
+                    .collect::<Vec<_>>() }),
+                TypeDefKind::Variant(variant) => json!({ "variant": variant
+                    .cases
+                    .iter()
+                    .map(|case| Some(json!([case.name, optional(&case.ty)?])))
+                    .collect::<Option<Vec<_>>>()? }),
+                TypeDefKind::Option(ty) => json!({ "option": json(ty)? }),
+                TypeDefKind::Result(result) => {
+                    json!({ "result": [optional(&result.ok)?, optional(&result.err)?] })
+                },
+                TypeDefKind::List(ty) => json!({ "list": json(ty)? }),
+                // Handles need tables of resources, and futures and streams
+                // the async ABI
+                _ => return None,
+            });
+        },
+    };
+    Some(Value::from(name))
+}
+
+/// A WIT name in lower camel case, e.g. `get-HTTP-status` to `getHttpStatus`
+pub fn js_name(name: &str) -> String {
+    let mut words = name.split('-');
+    let mut js = words.next().unwrap_or_default().to_ascii_lowercase();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            js.push(first.to_ascii_uppercase());
+            js.push_str(&chars.as_str().to_ascii_lowercase());
+        }
+    }
+    js
+}
+
+/// JavaScript of the functions of the WIT world `bindings` describes, as
+/// [`loader_json`] gives them: the page's functions it imports, resolved
+/// before the module is instantiated, and its exports, installed once it is
+/// and first initialized if `initialize`
+pub fn loader_js(bindings: &str, initialize: bool) -> (String, String) {
+    (
+        format!(
+            r#"
+
+        // Component bindings: the functions of the WIT world the module implements
+        const componentWorld = {bindings};"#,
+            bindings = embed::script_safe(bindings),
+        ) + r#"
+        const component = { exports: null };
+        class ComponentError extends Error {
+            constructor(payload) {
+                super(typeof payload === 'string' ? payload
+                    : payload !== null && typeof payload === 'object' && 'tag' in payload ? String(payload.tag)
+                    : String(payload));
+                this.name = 'ComponentError';
+                this.payload = payload;
+            }
+        }
+        const componentAbi = (function() {
+            const MAX_FLAT_PARAMS = 16;
+            const MAX_FLAT_RESULTS = 1;
+            const encoder = new TextEncoder();
+            const decoder = new TextDecoder('utf-8', { fatal: true });
+            const scratch = new DataView(new ArrayBuffer(8));
+            // Size and core type of the primitive types
+            const primitives = {
+                bool: [1, 'i32'], u8: [1, 'i32'], s8: [1, 'i32'], u16: [2, 'i32'], s16: [2, 'i32'],
+                u32: [4, 'i32'], s32: [4, 'i32'], char: [4, 'i32'], u64: [8, 'i64'], s64: [8, 'i64'],
+                f32: [4, 'f32'], f64: [8, 'f64']
+            };
+            const ranges = {
+                u8: [0, 0xff], s8: [-0x80, 0x7f], u16: [0, 0xffff], s16: [-0x8000, 0x7fff],
+                u32: [0, 0xffffffff], s32: [-0x80000000, 0x7fffffff]
+            };
+            // Lists of numbers are typed arrays
+            const arrays = {
+                u8: Uint8Array, s8: Int8Array, u16: Uint16Array, s16: Int16Array, u32: Uint32Array,
+                s32: Int32Array, u64: BigUint64Array, s64: BigInt64Array, f32: Float32Array, f64: Float64Array
+            };
+            const kind = type => typeof type === 'string' ? type : Object.keys(type)[0];
+            const alignTo = (offset, alignment) => Math.ceil(offset / alignment) * alignment;
+            const fields = type => kind(type) === 'record' ? type.record.map(field => field[1]) : type.tuple;
+            // The cases of enums, variants, options and results, as names and
+            // payload types
+            const cases = function(type) {
+                switch (kind(type)) {
+                    case 'enum':
+                        return type.enum.map(name => [name, null]);
+                    case 'variant':
+                        return type.variant;
+                    case 'option':
+                        return [['none', null], ['some', type.option]];
+                    default:
+                        return [['ok', type.result[0]], ['err', type.result[1]]];
+                }
+            };
+
+            // Size and alignment in memory, and the discriminant size and payload
+            // offset of cases
+            const layouts = new Map();
+            const layout = function(type) {
+                if (typeof type === 'string') {
+                    const size = type === 'string' ? 8 : primitives[type][0];
+                    return { size: size, align: Math.min(size, type === 'string' ? 4 : 8) };
+                }
+                if (layouts.has(type)) {
+                    return layouts.get(type);
+                }
+                let found;
+                switch (kind(type)) {
+                    case 'list':
+                        found = { size: 8, align: 4 };
+                        break;
+                    case 'record':
+                    case 'tuple': {
+                        let size = 0;
+                        let align = 1;
+                        for (const field of fields(type)) {
+                            const inner = layout(field);
+                            size = alignTo(size, inner.align) + inner.size;
+                            align = Math.max(align, inner.align);
+                        }
+                        found = { size: alignTo(size, align), align: align };
+                        break;
+                    }
+                    case 'flags': {
+                        const count = type.flags.length;
+                        const size = count === 0 ? 0 : count <= 8 ? 1 : count <= 16 ? 2 : 4 * Math.ceil(count / 32);
+                        found = { size: size, align: Math.max(1, Math.min(size, 4)) };
+                        break;
+                    }
+                    default: {
+                        const all = cases(type);
+                        const tag = all.length <= 0x100 ? 1 : all.length <= 0x10000 ? 2 : 4;
+                        let payloadSize = 0;
+                        let payloadAlign = 1;
+                        for (const [, payload] of all) {
+                            if (payload !== null) {
+                                payloadSize = Math.max(payloadSize, layout(payload).size);
+                                payloadAlign = Math.max(payloadAlign, layout(payload).align);
+                            }
+                        }
+                        const align = Math.max(tag, payloadAlign);
+                        const offset = alignTo(tag, payloadAlign);
+                        found = { size: alignTo(offset + payloadSize, align), align: align, tag: tag, offset: offset };
+                    }
+                }
+                layouts.set(type, found);
+                return found;
+            };
+
+            // Core types of a value passed as parameters or results, those of
+            // the payloads of cases joined
+            const flats = new Map();
+            const join = (a, b) => a === b ? a : (a === 'i32' && b === 'f32') || (a === 'f32' && b === 'i32') ? 'i32' : 'i64';
+            const flat = function(type) {
+                if (typeof type === 'string') {
+                    return type === 'string' ? ['i32', 'i32'] : [primitives[type][1]];
+                }
+                if (flats.has(type)) {
+                    return flats.get(type);
+                }
+                let found;
+                switch (kind(type)) {
+                    case 'list':
+                        found = ['i32', 'i32'];
+                        break;
+                    case 'record':
+                    case 'tuple':
+                        found = fields(type).flatMap(flat);
+                        break;
+                    case 'flags':
+                        found = new Array(Math.ceil(type.flags.length / 32)).fill('i32');
+                        break;
+                    default: {
+                        const payload = [];
+                        for (const [, type_] of cases(type)) {
+                            if (type_ !== null) {
+                                flat(type_).forEach(function(core, i) {
+                                    payload[i] = i < payload.length ? join(payload[i], core) : core;
+                                });
+                            }
+                        }
+                        found = ['i32'].concat(payload);
+                    }
+                }
+                flats.set(type, found);
+                return found;
+            };
+            // A core value of a payload as the joined type, and back
+            const widen = function(value, from, to) {
+                if (from === to) {
+                    return value;
+                }
+                if (from === 'f32') {
+                    scratch.setFloat32(0, value, true);
+                    value = scratch.getInt32(0, true);
+                    from = 'i32';
+                    if (to === 'i32') {
+                        return value;
+                    }
+                }
+                if (from === 'i32') {
+                    return BigInt(value >>> 0);
+                }
+                scratch.setFloat64(0, value, true);
+                return scratch.getBigInt64(0, true);
+            };
+            const narrow = function(value, from, to) {
+                if (from === to) {
+                    return value;
+                }
+                if (to === 'f64') {
+                    scratch.setBigInt64(0, value, true);
+                    return scratch.getFloat64(0, true);
+                }
+                const bits = from === 'i64' ? Number(BigInt.asIntN(32, value)) : value;
+                if (to === 'i32') {
+                    return bits;
+                }
+                scratch.setInt32(0, bits, true);
+                return scratch.getFloat32(0, true);
+            };
+
+            // The module's memory, allocated in with its cabi_realloc
+            let viewBuffer = null;
+            let view = null;
+            const memory = function() {
+                const exported = component.exports && component.exports.memory;
+                if (!(exported instanceof WebAssembly.Memory)) {
+                    throw new TypeError('module exports no memory');
+                }
+                if (exported.buffer !== viewBuffer) {
+                    viewBuffer = exported.buffer;
+                    view = new DataView(viewBuffer);
+                }
+                return view;
+            };
+            const bytes = function(ptr, length) {
+                const buffer = memory().buffer;
+                if (ptr + length > buffer.byteLength) {
+                    throw new RangeError('out of bounds of the memory');
+                }
+                return new Uint8Array(buffer, ptr, length);
+            };
+            const allocate = function(align, size) {
+                const realloc = component.exports.cabi_realloc;
+                if (typeof realloc !== 'function') {
+                    throw new TypeError('module exports no cabi_realloc');
+                }
+                return realloc(0, 0, align, size) >>> 0;
+            };
+
+            // Values checked before they are lowered
+            const integer = function(type, value) {
+                if (type === 'u64' || type === 's64') {
+                    const big = typeof value === 'bigint' ? value : Number.isInteger(value) ? BigInt(value) : null;
+                    if (big === null) {
+                        throw new TypeError('not an integer: ' + value);
+                    }
+                    if ((type === 'u64' ? BigInt.asUintN(64, big) : BigInt.asIntN(64, big)) !== big) {
+                        throw new RangeError(value + ' is out of the range of ' + type);
+                    }
+                    return big;
+                }
+                if (typeof value !== 'number' || !Number.isInteger(value)) {
+                    throw new TypeError('not an integer: ' + value);
+                }
+                if (value < ranges[type][0] || value > ranges[type][1]) {
+                    throw new RangeError(value + ' is out of the range of ' + type);
+                }
+                return value;
+            };
+            const number = function(value) {
+                if (typeof value !== 'number') {
+                    throw new TypeError('not a number: ' + value);
+                }
+                return value;
+            };
+            const codePoint = function(value) {
+                const code = typeof value === 'string' ? value.codePointAt(0) : undefined;
+                if (code === undefined || String.fromCodePoint(code) !== value || (code >= 0xd800 && code < 0xe000)) {
+                    throw new TypeError('not a char: ' + value);
+                }
+                return code;
+            };
+            const char = function(code) {
+                if (code > 0x10ffff || (code >= 0xd800 && code < 0xe000)) {
+                    throw new RangeError('invalid char ' + code);
+                }
+                return String.fromCodePoint(code);
+            };
+            const object = function(type, value) {
+                if (value === null || typeof value !== 'object') {
+                    throw new TypeError('not a ' + kind(type) + ': ' + value);
+                }
+                return value;
+            };
+            const fieldValues = function(type, value) {
+                object(type, value);
+                return kind(type) === 'tuple'
+                    ? type.tuple.map((_, i) => value[i])
+                    : type.record.map(field => value[field[0]]);
+            };
+            const record = function(type, values) {
+                return kind(type) === 'tuple'
+                    ? values
+                    : Object.fromEntries(type.record.map((field, i) => [field[0], values[i]]));
+            };
+            const flagWords = function(type, value) {
+                object(type, value);
+                const words = new Array(Math.ceil(type.flags.length / 32)).fill(0);
+                type.flags.forEach(function(name, i) {
+                    if (value[name]) {
+                        words[i >> 5] |= 1 << (i & 31);
+                    }
+                });
+                return words;
+            };
+            const flags = function(type, words) {
+                return Object.fromEntries(type.flags.map((name, i) => [name, ((words[i >> 5] >>> (i & 31)) & 1) === 1]));
+            };
+            // The case of a value, as its index and payload, and the value of one;
+            // options of options are { tag, val } like variants
+            const caseOf = function(type, value) {
+                const all = cases(type);
+                let index = -1;
+                let payload;
+                if (kind(type) === 'enum') {
+                    index = type.enum.indexOf(value);
+                } else if (kind(type) === 'option' && kind(type.option) !== 'option') {
+                    index = value === undefined || value === null ? 0 : 1;
+                    payload = value;
+                } else if (value !== null && typeof value === 'object') {
+                    index = all.findIndex(case_ => case_[0] === value.tag);
+                    payload = value.val;
+                }
+                if (index < 0) {
+                    throw new TypeError('not one of ' + all.map(case_ => case_[0]).join(', ') + ': ' + value);
+                }
+                return [index, payload];
+            };
+            const caseValue = function(type, name, payload) {
+                if (kind(type) === 'enum') {
+                    return name;
+                }
+                if (kind(type) === 'option' && kind(type.option) !== 'option') {
+                    return payload;
+                }
+                return { tag: name, val: payload };
+            };
+
+            // Strings and lists, copied into memory the module allocates
+            const lowerString = function(value) {
+                if (typeof value !== 'string') {
+                    throw new TypeError('not a string: ' + value);
+                }
+                const encoded = encoder.encode(value);
+                const ptr = allocate(1, encoded.length);
+                bytes(ptr, encoded.length).set(encoded);
+                return [ptr, encoded.length];
+            };
+            const liftString = (ptr, length) => decoder.decode(bytes(ptr, length));
+            const lowerList = function(element, value) {
+                if (value === null || typeof value !== 'object' || !(Symbol.iterator in value)) {
+                    throw new TypeError('not a list: ' + value);
+                }
+                const { size, align } = layout(element);
+                if (arrays[element] && value instanceof arrays[element]) {
+                    const ptr = allocate(align, value.byteLength);
+                    bytes(ptr, value.byteLength).set(new Uint8Array(value.buffer, value.byteOffset, value.byteLength));
+                    return [ptr, value.length];
+                }
+                const items = Array.from(value);
+                const ptr = allocate(align, size * items.length);
+                items.forEach((item, i) => store(element, ptr + i * size, item));
+                return [ptr, items.length];
+            };
+            const liftList = function(element, ptr, length) {
+                const { size } = layout(element);
+                if (typeof element === 'string' && arrays[element]) {
+                    return new arrays[element](bytes(ptr, size * length).slice().buffer);
+                }
+                return Array.from({ length: length }, (_, i) => load(element, ptr + i * size));
+            };
+
+            // Values in memory
+            const load = function(type, ptr) {
+                const view = memory();
+                switch (kind(type)) {
+                    case 'bool': return view.getUint8(ptr) !== 0;
+                    case 'u8': return view.getUint8(ptr);
+                    case 's8': return view.getInt8(ptr);
+                    case 'u16': return view.getUint16(ptr, true);
+                    case 's16': return view.getInt16(ptr, true);
+                    case 'u32': return view.getUint32(ptr, true);
+                    case 's32': return view.getInt32(ptr, true);
+                    case 'u64': return view.getBigUint64(ptr, true);
+                    case 's64': return view.getBigInt64(ptr, true);
+                    case 'f32': return view.getFloat32(ptr, true);
+                    case 'f64': return view.getFloat64(ptr, true);
+                    case 'char': return char(view.getUint32(ptr, true));
+                    case 'string': return liftString(view.getUint32(ptr, true), view.getUint32(ptr + 4, true));
+                    case 'list': return liftList(type.list, view.getUint32(ptr, true), view.getUint32(ptr + 4, true));
+                    case 'record':
+                    case 'tuple': {
+                        let offset = 0;
+                        return record(type, fields(type).map(function(field) {
+                            const { size, align } = layout(field);
+                            offset = alignTo(offset, align) + size;
+                            return load(field, ptr + offset - size);
+                        }));
+                    }
+                    case 'flags': {
+                        const { size } = layout(type);
+                        const words = size === 1 ? [view.getUint8(ptr)]
+                            : size === 2 ? [view.getUint16(ptr, true)]
+                            : Array.from({ length: size / 4 }, (_, i) => view.getUint32(ptr + 4 * i, true));
+                        return flags(type, words);
+                    }
+                    default: {
+                        const { tag, offset } = layout(type);
+                        const index = tag === 1 ? view.getUint8(ptr) : tag === 2 ? view.getUint16(ptr, true) : view.getUint32(ptr, true);
+                        const all = cases(type);
+                        if (index >= all.length) {
+                            throw new RangeError('invalid case ' + index);
+                        }
+                        const [name, payload] = all[index];
+                        return caseValue(type, name, payload === null ? undefined : load(payload, ptr + offset));
+                    }
+                }
+            };
+            const store = function(type, ptr, value) {
+                switch (kind(type)) {
+                    case 'bool': return memory().setUint8(ptr, value ? 1 : 0);
+                    case 'u8':
+                    case 's8': return memory().setUint8(ptr, integer(type, value));
+                    case 'u16':
+                    case 's16': return memory().setUint16(ptr, integer(type, value), true);
+                    case 'u32':
+                    case 's32': return memory().setUint32(ptr, integer(type, value), true);
+                    case 'u64':
+                    case 's64': return memory().setBigUint64(ptr, integer(type, value), true);
+                    case 'f32': return memory().setFloat32(ptr, number(value), true);
+                    case 'f64': return memory().setFloat64(ptr, number(value), true);
+                    case 'char': return memory().setUint32(ptr, codePoint(value), true);
+                    case 'string':
+                    case 'list': {
+                        const [address, length] = type === 'string' ? lowerString(value) : lowerList(type.list, value);
+                        memory().setUint32(ptr, address, true);
+                        return memory().setUint32(ptr + 4, length, true);
+                    }
+                    case 'record':
+                    case 'tuple': {
+                        const values = fieldValues(type, value);
+                        let offset = 0;
+                        return fields(type).forEach(function(field, i) {
+                            const { size, align } = layout(field);
+                            offset = alignTo(offset, align);
+                            store(field, ptr + offset, values[i]);
+                            offset += size;
+                        });
+                    }
+                    case 'flags': {
+                        const { size } = layout(type);
+                        const words = flagWords(type, value);
+                        if (size === 1) {
+                            return memory().setUint8(ptr, words[0]);
+                        }
+                        if (size === 2) {
+                            return memory().setUint16(ptr, words[0], true);
+                        }
+                        return words.forEach((word, i) => memory().setUint32(ptr + 4 * i, word, true));
+                    }
+                    default: {
+                        const { tag, offset } = layout(type);
+                        const [index, payload] = caseOf(type, value);
+                        if (tag === 1) {
+                            memory().setUint8(ptr, index);
+                        } else if (tag === 2) {
+                            memory().setUint16(ptr, index, true);
+                        } else {
+                            memory().setUint32(ptr, index, true);
+                        }
+                        const payloadType = cases(type)[index][1];
+                        if (payloadType !== null) {
+                            store(payloadType, ptr + offset, payload);
+                        }
+                    }
+                }
+            };
+
+            // Values as core values, and back from an iterator over them
+            const lowerFlat = function(type, value, out) {
+                switch (kind(type)) {
+                    case 'bool': return out.push(value ? 1 : 0);
+                    case 'u8':
+                    case 's8':
+                    case 'u16':
+                    case 's16':
+                    case 'u32':
+                    case 's32': return out.push(integer(type, value));
+                    case 'u64':
+                    case 's64': return out.push(BigInt.asIntN(64, integer(type, value)));
+                    case 'f32':
+                    case 'f64': return out.push(number(value));
+                    case 'char': return out.push(codePoint(value));
+                    case 'string': return out.push(...lowerString(value));
+                    case 'list': return out.push(...lowerList(type.list, value));
+                    case 'record':
+                    case 'tuple': {
+                        const values = fieldValues(type, value);
+                        return fields(type).forEach((field, i) => lowerFlat(field, values[i], out));
+                    }
+                    case 'flags': return out.push(...flagWords(type, value));
+                    default: {
+                        const [index, payload] = caseOf(type, value);
+                        const payloadType = cases(type)[index][1];
+                        const values = [];
+                        if (payloadType !== null) {
+                            lowerFlat(payloadType, payload, values);
+                        }
+                        const own = payloadType === null ? [] : flat(payloadType);
+                        out.push(index);
+                        flat(type).slice(1).forEach(function(core, i) {
+                            out.push(i < values.length ? widen(values[i], own[i], core) : core === 'i64' ? 0n : 0);
+                        });
+                    }
+                }
+            };
+            const liftFlat = function(type, values) {
+                const next = () => values.next().value;
+                switch (kind(type)) {
+                    case 'bool': return next() !== 0;
+                    case 'u8': return next() & 0xff;
+                    case 's8': return (next() << 24) >> 24;
+                    case 'u16': return next() & 0xffff;
+                    case 's16': return (next() << 16) >> 16;
+                    case 'u32': return next() >>> 0;
+                    case 's32': return next() | 0;
+                    case 'u64': return BigInt.asUintN(64, next());
+                    case 's64': return BigInt.asIntN(64, next());
+                    case 'f32':
+                    case 'f64': return next();
+                    case 'char': return char(next() >>> 0);
+                    case 'string':
+                    case 'list': {
+                        const ptr = next() >>> 0;
+                        const length = next() >>> 0;
+                        return type === 'string' ? liftString(ptr, length) : liftList(type.list, ptr, length);
+                    }
+                    case 'record':
+                    case 'tuple': return record(type, fields(type).map(field => liftFlat(field, values)));
+                    case 'flags': return flags(type, flat(type).map(next));
+                    default: {
+                        const index = next() >>> 0;
+                        const joined = flat(type).slice(1);
+                        const raw = joined.map(next);
+                        const all = cases(type);
+                        if (index >= all.length) {
+                            throw new RangeError('invalid case ' + index);
+                        }
+                        const [name, payloadType] = all[index];
+                        if (payloadType === null) {
+                            return caseValue(type, name, undefined);
+                        }
+                        const own = flat(payloadType).map((core, i) => narrow(raw[i], joined[i], core));
+                        return caseValue(type, name, liftFlat(payloadType, own[Symbol.iterator]()));
+                    }
+                }
+            };
+
+            // Results are returned as their ok value, and errors thrown
+            const unwrap = function(type, value) {
+                if (type === null || kind(type) !== 'result') {
+                    return value;
+                }
+                if (value.tag === 'err') {
+                    throw new ComponentError(value.val);
+                }
+                return value.val;
+            };
+            const wrap = function(type, call) {
+                if (type === null || kind(type) !== 'result') {
+                    return call();
+                }
+                try {
+                    return { tag: 'ok', val: call() };
+                } catch (e) {
+                    if (!(e instanceof ComponentError)) {
+                        throw e;
+                    }
+                    return { tag: 'err', val: e.payload };
+                }
+            };
+
+            return {
+                // An export, called with JavaScript values; parameters that do
+                // not fit in core parameters are passed in memory, as are results
+                lift: function(binding) {
+                    const core = component.exports[binding.core];
+                    const postReturn = component.exports['cabi_post_' + binding.core];
+                    const params = { tuple: binding.params.map(param => param[1]) };
+                    return function(...args) {
+                        const flatArgs = [];
+                        if (flat(params).length > MAX_FLAT_PARAMS) {
+                            const { size, align } = layout(params);
+                            const ptr = allocate(align, size);
+                            store(params, ptr, args);
+                            flatArgs.push(ptr);
+                        } else {
+                            lowerFlat(params, args, flatArgs);
+                        }
+                        const ret = core(...flatArgs);
+                        try {
+                            if (binding.result === null) {
+                                return undefined;
+                            }
+                            return unwrap(binding.result, flat(binding.result).length > MAX_FLAT_RESULTS
+                                ? load(binding.result, ret >>> 0)
+                                : liftFlat(binding.result, [ret][Symbol.iterator]()));
+                        } finally {
+                            if (typeof postReturn === 'function') {
+                                postReturn(ret);
+                            }
+                        }
+                    };
+                },
+                // A function of the page, called by the module with core values
+                lower: function(binding, callee) {
+                    const params = { tuple: binding.params.map(param => param[1]) };
+                    return function(...flatArgs) {
+                        const args = flat(params).length > MAX_FLAT_PARAMS
+                            ? load(params, flatArgs[0] >>> 0)
+                            : liftFlat(params, flatArgs[Symbol.iterator]());
+                        const value = wrap(binding.result, () => callee(...args));
+                        if (binding.result === null) {
+                            return undefined;
+                        }
+                        if (flat(binding.result).length > MAX_FLAT_RESULTS) {
+                            store(binding.result, flatArgs[flatArgs.length - 1] >>> 0, value);
+                            return undefined;
+                        }
+                        const out = [];
+                        lowerFlat(binding.result, value, out);
+                        return out[0];
+                    };
+                }
+            };
+        })();
+
+        // The page's functions of the world's imports, on window or on the
+        // object of their interface; those of the world itself and of the
+        // interfaces the page implements are not looked up elsewhere
+        const componentImports = {};
+        for (const binding of componentWorld.imports) {
+            const scope = binding.interface === null ? window : window[binding.interface];
+            const callee = scope !== null && scope !== undefined ? scope[binding.name] : undefined;
+            if (binding.interface === null || typeof callee === 'function') {
+                componentImports[binding.module] = componentImports[binding.module] || {};
+            }
+            if (typeof callee === 'function') {
+                componentImports[binding.module][binding.core] = componentAbi.lower(binding, callee.bind(scope));
+            }
+        }"#,
+        format!(
+            r#"
+
+                    // Component bindings: the world's exports with their WIT types
+                    component.exports = result.instance.exports;{initialize}
+                    const componentApi = {{}};
+                    for (const binding of componentWorld.exports) {{
+                        const scope = binding.interface === null ? componentApi
+                            : (componentApi[binding.interface] = componentApi[binding.interface] || {{}});
+                        scope[binding.name] = componentAbi.lift(binding);
+                    }}
+                    // Worlds of components are anonymous, named after the script
+                    const componentName = componentWorld.world || wasmFilename.replace(/^.*\//, '')
+                        .replace(/\.[^.]*$/, '').replace(/[^A-Za-z0-9_$]+(.)?/g, (_, c) => c ? c.toUpperCase() : '');
+                    window[componentName] = installedExports[componentName] = componentApi;
+                    console.log('WASM: Installed the exports of world ' + componentName);"#,
+            // Reactors built for WASI are initialized first, like the
+            // component would be; TinyGo's runtime does it itself
+            initialize = if initialize {
+                r#"
+                    if (typeof component.exports._initialize === 'function') {
+                        component.exports._initialize();
+                    }"#
+            } else {
+                ""
+            },
+        ),
+    )
+}
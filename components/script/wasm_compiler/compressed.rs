@@ -0,0 +1,64 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pre-compressed WAT sources
+//!
+//! A `.gz` or `.br` source served as is is decompressed before it is
+//! compiled. One served with a `Content-Encoding` was decoded by the fetch
+//! already: it starts like a module, WAT text or a patch, and is left alone.
+//!
+//! The `dom_wat_scripts_max_source_mb` pref caps what a source decompresses
+//! to, so that a small archive cannot exhaust the memory of the content
+//! process.
+
+use std::io::Read;
+
+use super::patch;
+
+/// Size of the buffer of the brotli decoder
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// The cap of the `dom_wat_scripts_max_source_mb` pref in bytes; `None` for
+/// no cap, `0` or less
+pub fn limit_from_pref(megabytes: i64) -> Option<u64> {
+    u64::try_from(megabytes)
+        .ok()
+        .filter(|megabytes| *megabytes > 0)
+        .map(|megabytes| megabytes.saturating_mul(1 << 20))
+}
+
+/// The source `body` of a script at `path`, decompressed if the path ends in
+/// `.gz` or `.br` and the body is not decoded already; an error if it does
+/// not decompress, or to more than `limit` bytes
+pub fn decompress(path: &str, body: Vec<u8>, limit: Option<u64>) -> Result<Vec<u8>, String> {
+    let decoded = body.starts_with(b"\0asm")
+        || patch::is_patch(&body)
+        || body
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .is_none_or(|byte| *byte == b'(' || *byte == b';');
+    let reader: Box<dyn Read + '_> = if path.ends_with(".gz") && !decoded {
+        Box::new(flate2::read::GzDecoder::new(&*body))
+    } else if path.ends_with(".br") && !decoded {
+        Box::new(brotli::Decompressor::new(&*body, BROTLI_BUFFER_SIZE))
+    } else {
+        return Ok(body);
+    };
+    // One byte past the cap tells a source over it from one just at it
+    let mut source = Vec::new();
+    reader
+        .take(limit.map_or(u64::MAX, |limit| limit.saturating_add(1)))
+        .read_to_end(&mut source)
+        .map_err(|error| error.to_string())?;
+    match limit {
+        Some(limit) if source.len() as u64 > limit => Err(format!(
+            "it decompresses to more than the {} bytes of dom_wat_scripts_max_source_mb",
+            limit
+        )),
+        _ => Ok(source),
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Coverage instrumentation
+//!
+//! With `data-coverage`, every function the module defines counts its calls
+//! in a mutable `i32` global, and with `data-coverage="blocks"` every `block`,
+//! `loop`, `if`, `else` and `try_table` body counts its executions too. The
+//! counters are exported as [`COUNTER_EXPORT_PREFIX`]`<n>`, and the loader
+//! reports them per function name from `window.__wasmCoverage()`.
+//!
+//! Counters only add globals and exports, so function indices and everything
+//! referring to them are unchanged.
+
+use std::convert::Infallible;
+
+use serde::Serialize;
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{
+    CodeSection, ConstExpr, ExportKind, ExportSection, GlobalSection, GlobalType, Instruction,
+    Module, SectionId, ValType,
+};
+use wasmparser::{
+    ExportSectionReader, FunctionBody, GlobalSectionReader, Operator, Parser, Payload, TypeRef,
+};
+
+use super::LOG_TARGET;
+use super::embed;
+use super::instrument;
+
+/// Export name prefix of the counters
+pub const COUNTER_EXPORT_PREFIX: &str = "__wasm_coverage_";
+
+/// What `data-coverage` counts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Coverage {
+    /// No instrumentation
+    #[default]
+    Off,
+    /// Calls of each function
+    Functions,
+    /// Calls of each function and executions of each block body
+    Blocks,
+}
+
+impl Coverage {
+    /// Parse a `data-coverage` attribute value: `functions` (or no value),
+    /// `blocks`, or `0`/`false` to disable
+    pub fn parse(value: &str) -> Option<Coverage> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "functions" => Some(Coverage::Functions),
+            "blocks" => Some(Coverage::Blocks),
+            "0" | "false" => Some(Coverage::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Counters of a function defined by the module
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FunctionCounters {
+    /// Function index
+    pub index: u32,
+    /// See [`instrument::display_name`]
+    pub name: String,
+    /// Counter of the calls; the block counters follow it
+    pub counter: u32,
+    /// Number of block counters
+    pub blocks: u32,
+}
+
+/// Counters of an instrumented module, as reported to the loader
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CoverageLayout {
+    pub blocks: bool,
+    pub functions: Vec<FunctionCounters>,
+}
+
+/// Add the counters to `binary`
+/// Returns `None` if `coverage` is [`Coverage::Off`]
+pub fn instrument(
+    binary: &mut Vec<u8>,
+    coverage: Coverage,
+) -> Result<Option<CoverageLayout>, reencode::Error> {
+    if coverage == Coverage::Off {
+        return Ok(None);
+    }
+    let count_blocks = coverage == Coverage::Blocks;
+
+    // The global and block counts are needed before the global section is
+    // written
+    let names = instrument::function_names(binary)?;
+    let mut imported_functions = 0;
+    let mut globals = 0;
+    let mut block_counts = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    match import?.ty {
+                        TypeRef::Func(_) => imported_functions += 1,
+                        TypeRef::Global(_) => globals += 1,
+                        _ => {},
+                    }
+                }
+            },
+            Payload::GlobalSection(reader) => globals += reader.count(),
+            Payload::CodeSectionEntry(body) => {
+                let mut blocks = 0;
+                if count_blocks {
+                    let mut reader = body.get_operators_reader()?;
+                    while !reader.eof() {
+                        if opens_block(&reader.read()?) {
+                            blocks += 1;
+                        }
+                    }
+                }
+                block_counts.push(blocks);
+            },
+            _ => {},
+        }
+    }
+
+    let mut counter = 0;
+    let functions = block_counts
+        .into_iter()
+        .enumerate()
+        .map(|(position, blocks)| {
+            let index = imported_functions + position as u32;
+            let counters = FunctionCounters {
+                index,
+                name: instrument::display_name(&names, index),
+                counter,
+                blocks,
+            };
+            counter += 1 + blocks;
+            counters
+        })
+        .collect();
+    let layout = CoverageLayout {
+        blocks: count_blocks,
+        functions,
+    };
+
+    let mut instrumenter = Instrumenter {
+        layout: &layout,
+        first_global: globals,
+        counters: counter,
+        next_body: 0,
+        globals_added: false,
+        exports_added: false,
+    };
+    let mut module = Module::new();
+    instrumenter.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+
+    log::info!(
+        target: LOG_TARGET,
+        phase = "coverage", counters = counter, functions = layout.functions.len();
+        "Added {} coverage counters to {} functions", counter, layout.functions.len()
+    );
+    Ok(Some(layout))
+}
+
+/// Instructions whose body gets a counter with [`Coverage::Blocks`]
+fn opens_block(operator: &Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::TryTable { .. }
+    )
+}
+
+struct Instrumenter<'a> {
+    layout: &'a CoverageLayout,
+    /// Global index of the first counter, after the module's own globals
+    first_global: u32,
+    counters: u32,
+    /// Position of the next function body in the code section
+    next_body: usize,
+    globals_added: bool,
+    exports_added: bool,
+}
+
+impl Instrumenter<'_> {
+    fn add_globals(&mut self, globals: &mut GlobalSection) {
+        for _ in 0..self.counters {
+            globals.global(
+                GlobalType {
+                    val_type: ValType::I32,
+                    mutable: true,
+                    shared: false,
+                },
+                &ConstExpr::i32_const(0),
+            );
+        }
+        self.globals_added = true;
+    }
+
+    fn add_exports(&mut self, exports: &mut ExportSection) {
+        for counter in 0..self.counters {
+            exports.export(
+                &format!("{}{}", COUNTER_EXPORT_PREFIX, counter),
+                ExportKind::Global,
+                self.first_global + counter,
+            );
+        }
+        self.exports_added = true;
+    }
+
+    /// Increment a counter; the sequence leaves the stack as it was, so it
+    /// can go anywhere in a function body
+    fn increment(&self, function: &mut wasm_encoder::Function, counter: u32) {
+        let global = self.first_global + counter;
+        function.instruction(&Instruction::GlobalGet(global));
+        function.instruction(&Instruction::I32Const(1));
+        function.instruction(&Instruction::I32Add);
+        function.instruction(&Instruction::GlobalSet(global));
+    }
+}
+
+impl Reencode for Instrumenter<'_> {
+    type Error = Infallible;
+
+    fn parse_global_section(
+        &mut self,
+        globals: &mut GlobalSection,
+        section: GlobalSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_global_section(self, globals, section)?;
+        self.add_globals(globals);
+        Ok(())
+    }
+
+    fn parse_export_section(
+        &mut self,
+        exports: &mut ExportSection,
+        section: ExportSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_export_section(self, exports, section)?;
+        self.add_exports(exports);
+        Ok(())
+    }
+
+    // Modules without globals or exports get new sections in their place
+    fn intersperse_section_hook(
+        &mut self,
+        module: &mut Module,
+        _after: Option<SectionId>,
+        before: Option<SectionId>,
+    ) -> Result<(), reencode::Error> {
+        use SectionId::*;
+        if !self.globals_added
+            && matches!(
+                before,
+                None | Some(Export | Start | Element | DataCount | Code | Data)
+            )
+        {
+            let mut globals = GlobalSection::new();
+            self.add_globals(&mut globals);
+            module.section(&globals);
+        }
+        if !self.exports_added
+            && matches!(
+                before,
+                None | Some(Start | Element | DataCount | Code | Data)
+            )
+        {
+            let mut exports = ExportSection::new();
+            self.add_exports(&mut exports);
+            module.section(&exports);
+        }
+        Ok(())
+    }
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut CodeSection,
+        body: FunctionBody<'_>,
+    ) -> Result<(), reencode::Error> {
+        let mut counter = self.layout.functions[self.next_body].counter;
+        self.next_body += 1;
+
+        let mut function = self.new_function_with_parsed_locals(&body)?;
+        self.increment(&mut function, counter);
+        let mut reader = body.get_operators_reader()?;
+        while !reader.eof() {
+            let operator = reader.read()?;
+            let count = self.layout.blocks && opens_block(&operator);
+            function.instruction(&self.instruction(operator)?);
+            if count {
+                counter += 1;
+                self.increment(&mut function, counter);
+            }
+        }
+        code.function(&function);
+        Ok(())
+    }
+}
+
+/// JavaScript recording the counters of `layout` once the module is
+/// instantiated, which `window.__wasmCoverage()` reports
+pub fn loader_js(layout: &CoverageLayout) -> String {
+    format!(
+        r#"
+
+                // Coverage counters (data-coverage), reported by window.__wasmCoverage()
+                window.__wasmCoverageModules = window.__wasmCoverageModules || {{}};
+                window.__wasmCoverageModules[wasmFilename] = {{
+                    layout: {},
+                    exports: result.instance.exports
+                }};
+                window.__wasmCoverage = window.__wasmCoverage || function() {{
+                    const report = {{}};
+                    for (const filename in window.__wasmCoverageModules) {{
+                        const module = window.__wasmCoverageModules[filename];
+                        const hits = function(counter) {{
+                            return module.exports['{prefix}' + counter].value >>> 0;
+                        }};
+                        const functions = module.layout.functions.map(function(func) {{
+                            const entry = {{ index: func.index, name: func.name, hits: hits(func.counter) }};
+                            if (module.layout.blocks) {{
+                                entry.blocks = Array.from({{ length: func.blocks }}, function(_, i) {{
+                                    return hits(func.counter + 1 + i);
+                                }});
+                            }}
+                            return entry;
+                        }});
+                        const covered = functions.filter(function(func) {{
+                            return func.hits > 0;
+                        }});
+                        report[filename] = {{
+                            functions: functions,
+                            executed: covered.map(function(func) {{
+                                return func.name;
+                            }}),
+                            covered: covered.length,
+                            total: functions.length
+                        }};
+                        if (module.layout.blocks) {{
+                            const blocks = [].concat(...functions.map(function(func) {{
+                                return func.blocks;
+                            }}));
+                            report[filename].blocksCovered = blocks.filter(function(count) {{
+                                return count > 0;
+                            }}).length;
+                            report[filename].blocksTotal = blocks.length;
+                        }}
+                    }}
+                    return report;
+                }};"#,
+        embed::script_json(layout),
+        prefix = COUNTER_EXPORT_PREFIX,
+    )
+}
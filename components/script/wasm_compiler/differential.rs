@@ -0,0 +1,119 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Differential validation of the binary passes
+//!
+//! The passes rewriting a binary module (datacount injection, field names,
+//! instrumentation, memory caps...) splice sections by hand. In debug builds
+//! each one runs between two validations: a pass turning a valid module
+//! invalid fails the compilation with its name, the validation error and the
+//! bytes it changed, rather than the engine rejecting the module later
+//! without saying which pass is to blame. Release builds run the passes
+//! unchecked.
+
+use std::ops::Range;
+
+use wasmparser::{Parser, Validator, WasmFeatures};
+
+use super::{CompileError, timeline};
+
+/// Run the pass `name` over `binary`, checking in debug builds that it
+/// keeps a valid module valid; `filename` names the script in the error
+pub fn run<T>(
+    filename: &str,
+    name: &str,
+    binary: &mut Vec<u8>,
+    features: WasmFeatures,
+    pass: impl FnOnce(&mut Vec<u8>) -> Result<T, CompileError>,
+) -> Result<T, CompileError> {
+    if !cfg!(debug_assertions) || !is_valid(binary, features) {
+        return timeline::phase(name, || pass(binary));
+    }
+    let before = binary.clone();
+    let result = timeline::phase(name, || pass(binary))?;
+    check(name, &before, binary, features).map_err(|report| {
+        CompileError::InstrumentationError(format!("in {}: {}", filename, report))
+    })?;
+    Ok(result)
+}
+
+/// Whether `after`, what the pass `name` made of the valid `before`, is
+/// valid; if not, a report of the error and the changed bytes
+pub fn check(
+    name: &str,
+    before: &[u8],
+    after: &[u8],
+    features: WasmFeatures,
+) -> Result<(), String> {
+    let Err(error) = Validator::new_with_features(features).validate_all(after) else {
+        return Ok(());
+    };
+    let (removed, added) = changed(before, after);
+    Err(format!(
+        "the {} pass made a valid module invalid: {} (at byte {}{}); bytes {}..{} of its input \
+         became bytes {}..{}{}",
+        name,
+        error.message(),
+        error.offset(),
+        section_of(after, error.offset()),
+        removed.start,
+        removed.end,
+        added.start,
+        added.end,
+        section_of(after, added.start),
+    ))
+}
+
+fn is_valid(binary: &[u8], features: WasmFeatures) -> bool {
+    Validator::new_with_features(features)
+        .validate_all(binary)
+        .is_ok()
+}
+
+/// The bytes of `before` that differ from `after`, and those replacing them:
+/// all but the common prefix and suffix
+fn changed(before: &[u8], after: &[u8]) -> (Range<usize>, Range<usize>) {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix..before.len() - suffix, prefix..after.len() - suffix)
+}
+
+/// `, in the … section` for the section of `binary` holding `offset`, if any
+fn section_of(binary: &[u8], offset: usize) -> String {
+    Parser::new(0)
+        .parse_all(binary)
+        .map_while(Result::ok)
+        .filter_map(|payload| payload.as_section())
+        .find(|(_, range)| range.contains(&offset))
+        .map(|(id, _)| format!(", in the {} section", section_name(id)))
+        .unwrap_or_default()
+}
+
+pub fn section_name(id: u8) -> &'static str {
+    match id {
+        0 => "custom",
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "datacount",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Embedding module bytes in the JavaScript glue
+//!
+//! The bytes are packed into a string literal with one latin1 character per
+//! byte, which the loader turns back into a `Uint8Array` with `charCodeAt`.
+//! Printable bytes take a single character, so the literal is close to the
+//! module size instead of the ~6x of a `0xAB, ` array literal.
+//!
+//! Some JavaScript parsers struggle with multi-megabyte literals, so modules
+//! larger than [`CHUNK_SIZE`] are split over several literals that the loader
+//! decodes one after the other.
+
+/// Module bytes per string literal
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// JavaScript string literal holding `bytes` as latin1 characters
+pub fn latin1_literal(bytes: &[u8]) -> String {
+    let mut literal = String::with_capacity(bytes.len() + bytes.len() / 4 + 2);
+    literal.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            // Names in the module must not end the surrounding script
+            b'<' => literal.push_str("\\x3c"),
+            // Control characters (including line terminators) are escaped;
+            // 0xA0..=0xFF are emitted as the characters U+00A0..U+00FF
+            0x00..=0x1F | 0x7F..=0x9F => literal.push_str(&format!("\\x{:02x}", byte)),
+            _ => literal.push(char::from(byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// JavaScript array of latin1 string literals, each holding up to
+/// `chunk_size` bytes; an empty module gives a single empty literal
+pub fn chunked_literals(bytes: &[u8], chunk_size: usize) -> String {
+    if bytes.is_empty() {
+        return String::from("[\"\"]");
+    }
+    let chunks: Vec<String> = bytes.chunks(chunk_size).map(latin1_literal).collect();
+    format!("[{}]", chunks.join(",\n"))
+}
+
+/// Make JSON text safe to splice into the glue: it is kept a valid JavaScript
+/// expression, and `<` (as in `</script>` or `<!--`) and the U+2028/U+2029
+/// line separators are escaped, which is only possible inside strings
+pub fn script_safe(json: &str) -> String {
+    json.replace('<', "\\u003c")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+/// Serialize a value for the glue, see [`script_safe`]
+pub fn script_json(value: &impl serde::Serialize) -> String {
+    script_safe(&serde_json::to_string(value).unwrap_or_else(|_| "null".to_string()))
+}
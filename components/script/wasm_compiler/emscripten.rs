@@ -0,0 +1,327 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Emscripten compatibility
+//!
+//! Modules built by Emscripten expect its JavaScript runtime: `Module.ccall`
+//! and `Module.cwrap` to call C functions with strings and arrays, views of
+//! the heap, and a few imports for resizing the heap and writing to stdout.
+//! Modules that look like Emscripten's, exporting its stack functions or
+//! `_main` or importing `emscripten_*` functions, get a minimal version of it
+//! from the loader instead, which runs the constructors and `main` like
+//! Emscripten's unless `Module.noInitialRun` is set.
+//!
+//! The exports the runtime uses differ between Emscripten versions, so the
+//! loader is told which ones the module has:
+//!
+//! ```text
+//! {"stackSave": "emscripten_stack_get_current", "stackRestore": "_emscripten_stack_restore",
+//!  "stackAlloc": "_emscripten_stack_alloc", "malloc": "malloc", "free": "free",
+//!  "initialize": "__wasm_call_ctors", "main": "main"}
+//! ```
+//!
+//! The imports are used for those the page does not provide; the memory is
+//! the exported one, or the one the page provides as `env.memory`.
+
+use serde::Serialize;
+use wasmparser::{ExternalKind, Parser, Payload};
+
+use super::embed;
+use super::imports;
+
+/// Exports for each part of the runtime, in order of preference
+const STACK_SAVE: [&str; 2] = ["stackSave", "emscripten_stack_get_current"];
+const STACK_RESTORE: [&str; 2] = ["stackRestore", "_emscripten_stack_restore"];
+const STACK_ALLOC: [&str; 2] = ["stackAlloc", "_emscripten_stack_alloc"];
+const MALLOC: [&str; 2] = ["malloc", "_malloc"];
+const FREE: [&str; 2] = ["free", "_free"];
+const INITIALIZE: [&str; 2] = ["__wasm_call_ctors", "_initialize"];
+const MAIN: [&str; 3] = ["main", "__main_argc_argv", "_main"];
+
+/// Prefix of the functions Emscripten's runtime provides to modules
+const IMPORT_PREFIX: &str = "emscripten_";
+
+/// The exports of a module the runtime uses, see the module documentation
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Runtime {
+    pub stack_save: Option<String>,
+    pub stack_restore: Option<String>,
+    pub stack_alloc: Option<String>,
+    pub malloc: Option<String>,
+    pub free: Option<String>,
+    pub initialize: Option<String>,
+    pub main: Option<String>,
+}
+
+/// The runtime a module built by Emscripten expects, or `None` if it does
+/// not look like one
+pub fn detect(binary: &[u8]) -> Option<Runtime> {
+    let mut functions = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        if let Ok(Payload::ExportSection(reader)) = payload {
+            functions.extend(
+                reader
+                    .into_iter()
+                    .flatten()
+                    .filter(|export| export.kind == ExternalKind::Func)
+                    .map(|export| export.name.to_string()),
+            );
+        }
+    }
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find(|name| functions.iter().any(|function| function == *name))
+            .map(|name| name.to_string())
+    };
+    let runtime = Runtime {
+        stack_save: find(&STACK_SAVE),
+        stack_restore: find(&STACK_RESTORE),
+        stack_alloc: find(&STACK_ALLOC),
+        malloc: find(&MALLOC),
+        free: find(&FREE),
+        initialize: find(&INITIALIZE),
+        main: find(&MAIN),
+    };
+
+    let imports_runtime = imports::declared_imports(binary)
+        .iter()
+        .any(|(module, name, _)| module == "env" && name.starts_with(IMPORT_PREFIX));
+    let exports_runtime = runtime.stack_save.is_some()
+        || runtime.stack_alloc.is_some()
+        || runtime.main.as_deref() == Some("_main");
+    (imports_runtime || exports_runtime).then_some(runtime)
+}
+
+/// The runtime as JSON for the loader, for modules built by Emscripten
+pub fn loader_json(binary: &[u8]) -> Option<String> {
+    detect(binary).and_then(|runtime| serde_json::to_string(&runtime).ok())
+}
+
+/// JavaScript of the minimal runtime of a module, given as JSON by
+/// [`loader_json`]: its imports, resolved before the module is instantiated,
+/// and the runtime itself, which runs the constructors and `main` once it is
+pub fn loader_js(runtime: &str) -> (String, String) {
+    (
+        r#"
+        let emscriptenMemory = null;
+        class EmscriptenExit extends Error {
+            constructor(status) {
+                super('exit(' + status + ')');
+                this.status = status;
+            }
+        }
+        // Output of fd_write by file descriptor, logged line by line
+        const emscriptenOutput = {
+            1: { decoder: new TextDecoder('utf-8'), line: '', log: console.log },
+            2: { decoder: new TextDecoder('utf-8'), line: '', log: console.error }
+        };
+        const emscriptenFlush = function() {
+            for (const fd in emscriptenOutput) {
+                const output = emscriptenOutput[fd];
+                if (output.line) {
+                    output.log(output.line);
+                    output.line = '';
+                }
+            }
+        };
+        const emscriptenImports = {
+            env: {
+                emscripten_resize_heap: function(requested) {
+                    const missing = (requested >>> 0) - emscriptenMemory.buffer.byteLength;
+                    try {
+                        if (missing > 0) {
+                            emscriptenMemory.grow(Math.ceil(missing / 65536));
+                        }
+                        return 1;
+                    } catch (e) {
+                        return 0;
+                    }
+                },
+                emscripten_memcpy_js: function(dest, src, num) {
+                    new Uint8Array(emscriptenMemory.buffer).copyWithin(dest, src, src + num);
+                },
+                emscripten_memcpy_big: function(dest, src, num) {
+                    new Uint8Array(emscriptenMemory.buffer).copyWithin(dest, src, src + num);
+                },
+                emscripten_date_now: () => Date.now(),
+                emscripten_get_now: () => performance.now(),
+                emscripten_notify_memory_growth: function() {},
+                abort: function() {
+                    throw new WebAssembly.RuntimeError('abort');
+                },
+                _abort_js: function() {
+                    throw new WebAssembly.RuntimeError('abort');
+                }
+            },
+            wasi_snapshot_preview1: {
+                fd_write: function(fd, iov, iovcnt, pnum) {
+                    const view = new DataView(emscriptenMemory.buffer);
+                    const output = emscriptenOutput[fd];
+                    let written = 0;
+                    for (let i = 0; i < iovcnt; i++) {
+                        const ptr = view.getUint32(iov + i * 8, true);
+                        const len = view.getUint32(iov + i * 8 + 4, true);
+                        if (output) {
+                            output.line += output.decoder.decode(
+                                new Uint8Array(emscriptenMemory.buffer, ptr, len), { stream: true });
+                        }
+                        written += len;
+                    }
+                    if (output) {
+                        const lines = output.line.split('\n');
+                        output.line = lines.pop();
+                        lines.forEach(line => output.log(line));
+                    }
+                    view.setUint32(pnum, written, true);
+                    return 0;
+                },
+                fd_close: () => 0,
+                fd_seek: () => 70, // ESPIPE
+                proc_exit: function(status) {
+                    emscriptenFlush();
+                    throw new EmscriptenExit(status);
+                }
+            }
+        };"#
+            .to_string(),
+        format!(
+            r#"
+
+                    // Emscripten runtime: Module.ccall and cwrap, heap views and
+                    // string helpers, then the constructors and main
+                    const emscriptenRuntime = {runtime};
+                    const emscriptenExports = result.instance.exports;
+                    emscriptenMemory = emscriptenExports.memory || (importObject.env && importObject.env.memory) || null;
+                    const Module = exportTarget.Module = exportTarget.Module || {{}};
+                    const runtimeExport = function(role) {{
+                        return emscriptenRuntime[role] ? emscriptenExports[emscriptenRuntime[role]] : undefined;
+                    }};
+                    for (const wasmName in emscriptenExports) {{
+                        if (typeof emscriptenExports[wasmName] === 'function') {{
+                            Module['_' + wasmName] = emscriptenExports[wasmName];
+                        }}
+                    }}
+                    for (const [heap, View] of [['HEAP8', Int8Array], ['HEAPU8', Uint8Array],
+                        ['HEAP16', Int16Array], ['HEAPU16', Uint16Array], ['HEAP32', Int32Array],
+                        ['HEAPU32', Uint32Array], ['HEAPF32', Float32Array], ['HEAPF64', Float64Array]]) {{
+                        Object.defineProperty(Module, heap, {{
+                            configurable: true,
+                            get: () => new View(emscriptenMemory.buffer)
+                        }});
+                    }}
+                    Module.UTF8ToString = function(ptr, maxBytesToRead) {{
+                        if (!ptr) {{
+                            return '';
+                        }}
+                        const heap = new Uint8Array(emscriptenMemory.buffer);
+                        const limit = maxBytesToRead === undefined ? heap.length : Math.min(heap.length, ptr + maxBytesToRead);
+                        let end = ptr;
+                        while (end < limit && heap[end] !== 0) {{
+                            end++;
+                        }}
+                        return new TextDecoder('utf-8').decode(heap.subarray(ptr, end));
+                    }};
+                    Module.lengthBytesUTF8 = str => new TextEncoder().encode(str).length;
+                    Module.stringToUTF8 = function(str, outPtr, maxBytesToWrite) {{
+                        if (!(maxBytesToWrite > 0)) {{
+                            return 0;
+                        }}
+                        const out = new Uint8Array(emscriptenMemory.buffer, outPtr, maxBytesToWrite);
+                        const written = new TextEncoder().encodeInto(str, out.subarray(0, maxBytesToWrite - 1)).written;
+                        out[written] = 0;
+                        return written;
+                    }};
+
+                    // Strings and arrays passed to ccall are copied onto the stack, or
+                    // into memory from malloc freed after the call
+                    Module.ccall = function(ident, returnType, argTypes, args, opts) {{
+                        const func = Module['_' + ident];
+                        if (typeof func !== 'function') {{
+                            throw new Error('Cannot call unknown function ' + ident + ', make sure it is exported');
+                        }}
+                        const stackSave = runtimeExport('stackSave');
+                        const stackRestore = runtimeExport('stackRestore');
+                        const stackAlloc = runtimeExport('stackAlloc');
+                        const malloc = runtimeExport('malloc');
+                        const free = runtimeExport('free');
+                        const stack = stackSave && stackRestore ? stackSave() : null;
+                        const allocated = [];
+                        const copy = function(bytes, terminated) {{
+                            const size = bytes.length + (terminated ? 1 : 0);
+                            let ptr;
+                            if (stack !== null && stackAlloc) {{
+                                ptr = stackAlloc(size);
+                            }} else if (malloc) {{
+                                ptr = malloc(size);
+                                allocated.push(ptr);
+                            }} else {{
+                                throw new Error('ccall: passing ' + ident + ' a string or array needs stackAlloc or malloc');
+                            }}
+                            const heap = new Uint8Array(emscriptenMemory.buffer, ptr, size);
+                            heap.set(bytes);
+                            if (terminated) {{
+                                heap[bytes.length] = 0;
+                            }}
+                            return ptr;
+                        }};
+                        try {{
+                            const cArgs = (args || []).map(function(arg, i) {{
+                                const type = argTypes && argTypes[i];
+                                if (type === 'string') {{
+                                    return arg === null || arg === undefined ? 0 : copy(new TextEncoder().encode(String(arg)), true);
+                                }}
+                                if (type === 'array') {{
+                                    return copy(arg, false);
+                                }}
+                                return type === 'boolean' ? (arg ? 1 : 0) : arg;
+                            }});
+                            const ret = func.apply(null, cArgs);
+                            const converted = returnType === 'string' ? Module.UTF8ToString(ret)
+                                : (returnType === 'boolean' ? Boolean(ret) : ret);
+                            return opts && opts.async ? Promise.resolve(converted) : converted;
+                        }} finally {{
+                            if (stack !== null) {{
+                                stackRestore(stack);
+                            }}
+                            allocated.forEach(ptr => free && free(ptr));
+                        }}
+                    }};
+                    // Functions of numbers only are the export itself, like Emscripten's
+                    Module.cwrap = function(ident, returnType, argTypes, opts) {{
+                        const numeric = !argTypes || argTypes.every(type => type === 'number' || type === 'boolean');
+                        if (numeric && returnType !== 'string' && returnType !== 'boolean' && !opts) {{
+                            return Module['_' + ident];
+                        }}
+                        return function(...args) {{
+                            return Module.ccall(ident, returnType, argTypes, args, opts);
+                        }};
+                    }};
+
+                    try {{
+                        if (runtimeExport('initialize')) {{
+                            runtimeExport('initialize')();
+                        }}
+                        Module.calledRun = true;
+                        if (typeof Module.onRuntimeInitialized === 'function') {{
+                            Module.onRuntimeInitialized();
+                        }}
+                        if (runtimeExport('main') && !Module.noInitialRun) {{
+                            runtimeExport('main')(0, 0);
+                        }}
+                    }} catch (e) {{
+                        if (!(e instanceof EmscriptenExit)) {{
+                            throw e;
+                        }}
+                    }} finally {{
+                        emscriptenFlush();
+                    }}"#,
+            runtime = embed::script_safe(runtime),
+        ),
+    )
+}
@@ -0,0 +1,105 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Export filtering
+//!
+//! Besides the functions a page calls, modules export the helpers of their
+//! toolchain and those the pipeline adds, which clutter `window` and may
+//! collide with the page's own names. With `data-exports="render_*,init"`
+//! only the exports matching one of the patterns are installed on `window`
+//! or the `data-namespace` object; `*` matches any run of characters. A
+//! pattern matches the name of an export in the module or the name it is
+//! installed under, see [`super::aliases`]. The other exports stay reachable
+//! as `exports` of the module's entry in `window.__wasmModules`.
+//!
+//! The patterns select the helpers the loader installs on `window` too, see
+//! [`PAGE_HELPERS`], so that a module installs only those matching one.
+//! The registries the loader keeps of every module, such as
+//! `window.__wasmModules`, are not filtered.
+
+use std::collections::BTreeMap;
+
+use super::LOG_TARGET;
+use super::aliases;
+
+/// The helpers the loader installs on `window` besides the exports, for the
+/// strings, structs and bindings of a module, see [`super::strings`],
+/// [`super::structs`] and [`super::bind`]
+pub const PAGE_HELPERS: [&str; 11] = [
+    "WasmStringToJs",
+    "WasmStringFromJs",
+    "_wasmExports",
+    "WasmGcStructDisplay",
+    "WasmGcStructGet",
+    "WasmGcStructSet",
+    "wasmWatch",
+    "wasmSetFields",
+    "WasmListGetters",
+    "__wasmFieldNames",
+    "wasmBind",
+];
+
+/// Names of the exports of `binary` to install, as a JSON array, or `null`
+/// to install all of them
+/// `aliases_json` gives the names exports are installed under, see
+/// [`aliases::styled_aliases_json`].
+pub fn installed_json(patterns: Option<&[String]>, aliases_json: &str, binary: &[u8]) -> String {
+    let Some(patterns) = patterns else {
+        return "null".to_string();
+    };
+    let aliases: BTreeMap<String, String> = serde_json::from_str(aliases_json).unwrap_or_default();
+    let exports = aliases::export_names(binary);
+    let selected = |pattern: &str, name: &String| {
+        matches(pattern, name)
+            || aliases
+                .get(name)
+                .is_some_and(|alias| matches(pattern, alias))
+    };
+
+    for pattern in patterns {
+        if !exports.iter().any(|name| selected(pattern, name))
+            && !PAGE_HELPERS.iter().any(|helper| matches(pattern, helper))
+        {
+            log::warn!(
+                target: LOG_TARGET, phase = "exports", pattern:% = pattern;
+                "No export matches data-exports pattern {:?}", pattern
+            );
+        }
+    }
+
+    let installed: Vec<&String> = exports
+        .iter()
+        .filter(|name| patterns.iter().any(|pattern| selected(pattern, name)))
+        .collect();
+    serde_json::to_string(&installed).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Whether a module installs the page helper `name`, one of
+/// [`PAGE_HELPERS`]: with `data-exports`, only if it matches a pattern
+pub fn installs_helper(patterns: Option<&[String]>, name: &str) -> bool {
+    debug_assert!(PAGE_HELPERS.contains(&name));
+    patterns.is_none_or(|patterns| patterns.iter().any(|pattern| matches(pattern, name)))
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let mut parts: Vec<&str> = pattern.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    if parts.is_empty() {
+        return name == last;
+    }
+    let Some(mut rest) = name.strip_prefix(parts[0]) else {
+        return false;
+    };
+    for part in &parts[1..] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
@@ -0,0 +1,69 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fallback for the optional binary passes
+//!
+//! Some passes over the parsed module are not needed for it to be valid, like
+//! injecting a datacount section. When one of them changed the module, the
+//! module as parsed is kept: if the engine rejects the result, the loader
+//! retries once with it and warns about the skipped passes, so pages degrade
+//! instead of failing.
+//!
+//! Modules that do not validate without a pass get no fallback, the pass was
+//! needed after all.
+
+use wasmparser::{Validator, WasmFeatures};
+
+use super::{CompileError, differential};
+
+/// A pass rewriting the binary in place
+type Pass = fn(&mut Vec<u8>);
+
+/// Passes the module is valid without, with the name used in warnings
+const OPTIONAL_PASSES: [(&str, Pass); 1] = [("datacount section", super::inject_datacount_section)];
+
+/// The module before the optional passes
+#[derive(Clone, Debug, MallocSizeOf, PartialEq)]
+pub struct Fallback {
+    pub binary: Vec<u8>,
+    /// Names of the passes that changed the module
+    pub skipped: Vec<String>,
+}
+
+/// Run the optional passes over `binary`, checked by [`differential`]
+/// Returns the fallback if a pass changed the module and it validates
+/// without them
+pub fn apply_optional_passes(
+    binary: &mut Vec<u8>,
+    features: WasmFeatures,
+    filename: &str,
+) -> Result<Option<Fallback>, CompileError> {
+    let parsed = binary.clone();
+    let mut skipped = Vec::new();
+    for (name, pass) in OPTIONAL_PASSES {
+        let before = binary.clone();
+        differential::run(filename, name, binary, features, |binary| {
+            pass(binary);
+            Ok(())
+        })?;
+        if *binary != before {
+            skipped.push(name.to_string());
+        }
+    }
+
+    if skipped.is_empty()
+        || Validator::new_with_features(features)
+            .validate_all(&parsed)
+            .is_err()
+    {
+        return Ok(None);
+    }
+    Ok(Some(Fallback {
+        binary: parsed,
+        skipped,
+    }))
+}
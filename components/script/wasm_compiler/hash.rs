@@ -0,0 +1,60 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Content hashes keying the compilation cache
+//!
+//! The cache is content-addressed, so a collision would load the module of
+//! another source. Keys are SHA-256 digests rather than those of
+//! `DefaultHasher`, which are 64 bits and may change with any Rust release:
+//! a digest names the same source in every build, as a cache kept on disk
+//! needs. Each entry records the [`Algorithm`] of its key, so that entries
+//! of a cache written under another one are told apart rather than matched.
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+/// Algorithm of a [`ContentHash`]
+#[derive(Clone, Copy, Debug, MallocSizeOf, PartialEq)]
+pub enum Algorithm {
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha-256",
+        }
+    }
+}
+
+/// Content hash of a source, the key of its module in the cache
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    /// Algorithm of the hashes computed by this build
+    pub const ALGORITHM: Algorithm = Algorithm::Sha256;
+
+    pub fn of(bytes: &[u8]) -> ContentHash {
+        ContentHash(Sha256::digest(bytes).into())
+    }
+
+    /// The hash of this one followed by `variant`, for a source compiled
+    /// another way
+    pub fn with(&self, variant: &[u8]) -> ContentHash {
+        ContentHash::of(&[&self.0[..], variant].concat())
+    }
+}
+
+impl fmt::Display for ContentHash {
+    /// `sha-256:` and the digest in hex
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", ContentHash::ALGORITHM.as_str())?;
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
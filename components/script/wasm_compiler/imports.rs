@@ -0,0 +1,92 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Import resolution
+//!
+//! The loader resolves only the imports a module declares, instead of copying
+//! every function on `window` into the import object for each instantiation.
+//! An import `(import "m" "f" ...)` is looked up as `window.m.f` when
+//! `window.m` is an object, and as the global `window.f` otherwise, so `env`
+//! imports keep resolving to globals.
+//!
+//! A module with an import the page does not provide fails to instantiate.
+//! With `data-stub-imports`, imported functions not found are stubs instead,
+//! which throw an error naming the import when called, so a module wired up
+//! in part loads and what is missing shows where it is needed. Tables,
+//! memories, globals and tags cannot be stubbed.
+//!
+//! `data-import-map='{"env.random": "myRng", "env.log": "console.debug"}'`
+//! binds imports, named by module and field, to expressions of the page
+//! instead. They are evaluated in its global scope as the module is
+//! instantiated, and take precedence over both the lookup on `window` and the
+//! runtime imports the loader provides.
+
+use std::collections::BTreeMap;
+
+use wasmparser::{Parser, Payload, TypeRef};
+
+use super::LOG_TARGET;
+
+/// A declared import: module name, field name and kind
+pub type Import = (String, String, &'static str);
+
+/// Imports declared by a module, in declaration order
+pub fn declared_imports(binary: &[u8]) -> Vec<Import> {
+    let mut imports = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::ImportSection(reader)) = payload else {
+            continue;
+        };
+        for import in reader.into_iter().flatten() {
+            let kind = match import.ty {
+                TypeRef::Func(_) => "function",
+                TypeRef::Table(_) => "table",
+                TypeRef::Memory(_) => "memory",
+                TypeRef::Global(_) => "global",
+                TypeRef::Tag(_) => "tag",
+            };
+            imports.push((import.module.to_string(), import.name.to_string(), kind));
+        }
+    }
+    imports
+}
+
+/// Parse a `data-import-map` value: a JSON object of non-empty expressions
+/// by `module.name`
+pub fn parse_import_map(value: &str) -> Option<Vec<(String, String)>> {
+    let map: BTreeMap<String, String> = serde_json::from_str(value).ok()?;
+    map.iter()
+        .all(|(import, expression)| import.contains('.') && !expression.trim().is_empty())
+        .then(|| map.into_iter().collect())
+}
+
+/// `import_map` as a JSON object for the loader; entries naming no import of
+/// `binary` are reported
+pub fn import_map_json(import_map: &[(String, String)], binary: &[u8]) -> String {
+    let imports = declared_imports(binary);
+    for (import, _) in import_map {
+        if !imports
+            .iter()
+            .any(|(module, name, _)| *import == format!("{}.{}", module, name))
+        {
+            log::warn!(
+                target: LOG_TARGET, phase = "imports", import:% = import;
+                "data-import-map binds {}, which the module does not import", import
+            );
+        }
+    }
+    let map: BTreeMap<&String, &String> = import_map
+        .iter()
+        .map(|(import, expression)| (import, expression))
+        .collect();
+    serde_json::to_string(&map).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Declared imports as a JSON array of `[module, name, kind]`, for the loader
+pub fn imports_json(binary: &[u8]) -> String {
+    serde_json::to_string(&declared_imports(binary)).unwrap_or_else(|_| "[]".to_string())
+}
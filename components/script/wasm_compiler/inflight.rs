@@ -0,0 +1,61 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compilations in progress, shared between script threads
+//!
+//! The compilation cache belongs to the content process, so the tabs of an
+//! origin share it, but tabs opened together all miss it for a source none of
+//! them has compiled yet. A script thread about to compile a source first
+//! claims its cache key here; the others loading that source wait for the
+//! claim to be released and then find the module in the cache.
+
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::hash::ContentHash;
+
+struct InFlight {
+    keys: Mutex<HashSet<ContentHash>>,
+    released: Condvar,
+}
+
+fn get_in_flight() -> &'static InFlight {
+    static IN_FLIGHT: OnceLock<InFlight> = OnceLock::new();
+    IN_FLIGHT.get_or_init(|| InFlight {
+        keys: Mutex::new(HashSet::new()),
+        released: Condvar::new(),
+    })
+}
+
+/// The claim of a thread on compiling the source with a cache key, released
+/// when dropped
+pub(super) struct Claim(ContentHash);
+
+impl Claim {
+    /// Claim `cache_key`, waiting until no other thread is compiling it
+    /// The caller should look the key up in the cache again, since the thread
+    /// it waited for will usually have put the module there.
+    pub(super) fn new(cache_key: ContentHash) -> Claim {
+        let in_flight = get_in_flight();
+        let mut keys = in_flight.keys.lock();
+        while keys.contains(&cache_key) {
+            in_flight.released.wait(&mut keys);
+        }
+        keys.insert(cache_key);
+        Claim(cache_key)
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let in_flight = get_in_flight();
+        in_flight.keys.lock().remove(&self.0);
+        in_flight.released.notify_all();
+    }
+}
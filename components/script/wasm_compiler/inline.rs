@@ -0,0 +1,347 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Inlining of small functions
+//!
+//! Getters and setters of struct fields are a call around one or two
+//! instructions, which costs more than the access in a hot loop. With
+//! `data-inline`, calls to functions of at most [`MAX_INSTRUCTIONS`]
+//! instructions without control flow, calls or locals of their own are
+//! replaced by their body: the arguments go to locals added to the caller,
+//! which the body reads instead of its parameters.
+//!
+//! Only functions the module alone calls are inlined; those that are
+//! exported, in a table, referenced by `ref.func` or the start function stay
+//! as they are. Once all their calls are inlined, the functions are removed,
+//! see [`super::shake`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{CodeSection, Function, Instruction, Module};
+use wasmparser::{
+    BinaryReaderError, CompositeInnerType, ConstExpr, ElementItems, ExternalKind, FunctionBody,
+    Operator, Parser, Payload, TableInit, TypeRef, ValType,
+};
+
+use super::shake;
+
+/// Most instructions of a function inlined, without the final `end`
+pub const MAX_INSTRUCTIONS: usize = 8;
+
+/// What [`inline`] did
+#[derive(Debug, Default, PartialEq)]
+pub struct Inlined {
+    /// Calls replaced by the body of the function
+    pub calls: u32,
+    /// Functions removed since all their calls were
+    pub functions: u32,
+}
+
+/// Inline the calls to small functions of `binary` that only it calls
+pub fn inline(binary: &mut Vec<u8>) -> Result<Inlined, reencode::Error> {
+    let functions = Functions::new(binary)?;
+    if functions.inlined.is_empty() {
+        return Ok(Inlined::default());
+    }
+    let mut inliner = Inliner {
+        functions: &functions,
+        next_body: 0,
+        calls: 0,
+    };
+    let mut module = Module::new();
+    inliner.parse_core_module(&mut module, Parser::new(0), binary)?;
+    let calls = inliner.calls;
+    let inlined: BTreeSet<u32> = functions.inlined.keys().copied().collect();
+    *binary = module.finish();
+    let removed = shake::remove_functions(binary, &inlined)?;
+    Ok(Inlined {
+        calls,
+        functions: removed.functions,
+    })
+}
+
+/// A function to inline
+struct Small<'a> {
+    params: Vec<ValType>,
+    /// Without the final `end`
+    body: Vec<Operator<'a>>,
+}
+
+/// The functions of a module and those to inline
+#[derive(Default)]
+struct Functions<'a> {
+    imported: u32,
+    /// Bodies read so far
+    bodies: u32,
+    /// Parameters of each type that is a function type
+    params: Vec<Option<Vec<ValType>>>,
+    /// Type of each function
+    types: Vec<u32>,
+    inlined: HashMap<u32, Small<'a>>,
+}
+
+impl<'a> Functions<'a> {
+    fn new(binary: &'a [u8]) -> Result<Functions<'a>, BinaryReaderError> {
+        let mut functions = Functions::default();
+        // Functions referenced other than by calls
+        let mut pinned = HashSet::new();
+        for payload in Parser::new(0).parse_all(binary) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for rec_group in reader {
+                        for sub_type in rec_group?.into_types() {
+                            functions.params.push(match sub_type.composite_type.inner {
+                                CompositeInnerType::Func(ty) => Some(ty.params().to_vec()),
+                                _ => None,
+                            });
+                        }
+                    }
+                },
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        if let TypeRef::Func(ty) = import?.ty {
+                            functions.imported += 1;
+                            functions.types.push(ty);
+                        }
+                    }
+                },
+                Payload::FunctionSection(reader) => {
+                    for ty in reader {
+                        functions.types.push(ty?);
+                    }
+                },
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if export.kind == ExternalKind::Func {
+                            pinned.insert(export.index);
+                        }
+                    }
+                },
+                Payload::StartSection { func, .. } => {
+                    pinned.insert(func);
+                },
+                Payload::TableSection(reader) => {
+                    for table in reader {
+                        if let TableInit::Expr(expr) = table?.init {
+                            ref_funcs(&expr, &mut pinned)?;
+                        }
+                    }
+                },
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        ref_funcs(&global?.init_expr, &mut pinned)?;
+                    }
+                },
+                Payload::ElementSection(reader) => {
+                    for element in reader {
+                        match element?.items {
+                            ElementItems::Functions(reader) => {
+                                for function in reader {
+                                    pinned.insert(function?);
+                                }
+                            },
+                            ElementItems::Expressions(_, reader) => {
+                                for expr in reader {
+                                    ref_funcs(&expr?, &mut pinned)?;
+                                }
+                            },
+                        }
+                    }
+                },
+                Payload::CodeSectionEntry(body) => {
+                    let index = functions.imported + functions.bodies;
+                    functions.bodies += 1;
+                    let mut operators = Vec::new();
+                    let mut small = body.get_locals_reader()?.get_count() == 0;
+                    let mut reader = body.get_operators_reader()?;
+                    while !reader.eof() {
+                        let operator = reader.read()?;
+                        if let Operator::RefFunc { function_index } = operator {
+                            pinned.insert(function_index);
+                        }
+                        if small {
+                            operators.push(operator);
+                            small = operators.len() <= MAX_INSTRUCTIONS + 1;
+                        }
+                    }
+                    if !small {
+                        continue;
+                    }
+                    let Some(Operator::End) = operators.pop() else {
+                        continue;
+                    };
+                    let Some(Some(params)) = functions
+                        .types
+                        .get(index as usize)
+                        .and_then(|ty| functions.params.get(*ty as usize))
+                    else {
+                        continue;
+                    };
+                    if operators.iter().all(straight) {
+                        let params = params.clone();
+                        functions.inlined.insert(
+                            index,
+                            Small {
+                                params,
+                                body: operators,
+                            },
+                        );
+                    }
+                },
+                _ => {},
+            }
+        }
+        functions
+            .inlined
+            .retain(|function, _| !pinned.contains(function));
+        Ok(functions)
+    }
+
+    fn params(&self, function: u32) -> usize {
+        self.types
+            .get(function as usize)
+            .and_then(|ty| self.params.get(*ty as usize))
+            .and_then(|params| params.as_ref())
+            .map_or(0, Vec::len)
+    }
+}
+
+/// Whether `operator` leaves the function where it follows it, without
+/// branches, calls or the end of a block
+fn straight(operator: &Operator) -> bool {
+    !matches!(
+        operator,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::BrOnNull { .. }
+            | Operator::BrOnNonNull { .. }
+            | Operator::BrOnCast { .. }
+            | Operator::BrOnCastFail { .. }
+            | Operator::Return
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::CallRef { .. }
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::ReturnCallRef { .. }
+            | Operator::Try { .. }
+            | Operator::TryTable { .. }
+            | Operator::Catch { .. }
+            | Operator::CatchAll
+            | Operator::Delegate { .. }
+            | Operator::Throw { .. }
+            | Operator::ThrowRef
+            | Operator::Rethrow { .. }
+    )
+}
+
+/// Add the functions `ref.func` refers to in `expr` to `functions`
+fn ref_funcs(expr: &ConstExpr, functions: &mut HashSet<u32>) -> Result<(), BinaryReaderError> {
+    let mut reader = expr.get_operators_reader();
+    while !reader.eof() {
+        if let Operator::RefFunc { function_index } = reader.read()? {
+            functions.insert(function_index);
+        }
+    }
+    Ok(())
+}
+
+struct Inliner<'a, 'b> {
+    functions: &'b Functions<'a>,
+    next_body: u32,
+    calls: u32,
+}
+
+impl Reencode for Inliner<'_, '_> {
+    type Error = Infallible;
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut CodeSection,
+        body: FunctionBody<'_>,
+    ) -> Result<(), reencode::Error> {
+        let functions = self.functions;
+        let index = functions.imported + self.next_body;
+        self.next_body += 1;
+
+        // The first local of the arguments of each function inlined
+        let mut locals = Vec::new();
+        let mut next_local = functions.params(index) as u32;
+        for pair in body.get_locals_reader()? {
+            let (count, ty) = pair?;
+            locals.push((count, self.val_type(ty)?));
+            next_local += count;
+        }
+        let mut arguments = BTreeMap::new();
+        let mut reader = body.get_operators_reader()?;
+        while !reader.eof() {
+            if let Operator::Call { function_index } = reader.read()?
+                && let Some(small) = functions.inlined.get(&function_index)
+            {
+                arguments.entry(function_index).or_insert_with(|| {
+                    next_local += small.params.len() as u32;
+                    next_local - small.params.len() as u32
+                });
+            }
+        }
+        if arguments.is_empty() {
+            return reencode::utils::parse_function_body(self, code, body);
+        }
+        for function in arguments.keys() {
+            for param in &functions.inlined[function].params {
+                locals.push((1, self.val_type(*param)?));
+            }
+        }
+
+        let mut function = Function::new(locals);
+        let mut reader = body.get_operators_reader()?;
+        while !reader.eof() {
+            let operator = reader.read()?;
+            let Operator::Call { function_index } = operator else {
+                function.instruction(&self.instruction(operator)?);
+                continue;
+            };
+            let (Some(small), Some(first)) = (
+                functions.inlined.get(&function_index),
+                arguments.get(&function_index),
+            ) else {
+                function.instruction(&self.instruction(operator)?);
+                continue;
+            };
+            for param in (0..small.params.len() as u32).rev() {
+                function.instruction(&Instruction::LocalSet(first + param));
+            }
+            for operator in small.body.iter().cloned() {
+                let operator = match operator {
+                    Operator::LocalGet { local_index } => Operator::LocalGet {
+                        local_index: first + local_index,
+                    },
+                    Operator::LocalSet { local_index } => Operator::LocalSet {
+                        local_index: first + local_index,
+                    },
+                    Operator::LocalTee { local_index } => Operator::LocalTee {
+                        local_index: first + local_index,
+                    },
+                    operator => operator,
+                };
+                function.instruction(&self.instruction(operator)?);
+            }
+            self.calls += 1;
+        }
+        code.function(&function);
+        Ok(())
+    }
+}
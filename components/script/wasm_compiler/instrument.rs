@@ -0,0 +1,433 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Calls into the loader at function entry and exit
+//!
+//! The debugging modes add function imports that the loader provides, and
+//! call them at the start of the functions they instrument with the function
+//! index (and optionally its arguments). The imports go after the module's
+//! own, so defined functions move up and every reference to them (calls,
+//! `ref.func`, element segments, exports, the start function and the name
+//! section) is renumbered.
+//!
+//! For exit calls the body is wrapped in a block with the function's results,
+//! so falling off the end and branches to the function's label both reach the
+//! call after the block; `return` and tail calls call it before leaving.
+//! Exceptions and traps unwind without an exit call.
+//!
+//! Indices passed to the imports and reported by [`function_names`] are those
+//! of the module before instrumentation.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{
+    CodeSection, EntityType, ImportSection, Instruction, Module, SectionId, TypeSection,
+};
+use wasmparser::{
+    AbstractHeapType, BlockType, CompositeInnerType, ExternalKind, FunctionBody, HeapType,
+    ImportSectionReader, KnownCustom, Name, Operator, Parser, Payload, TypeRef, TypeSectionReader,
+    ValType,
+};
+
+/// Names of the functions that have one, from the name section or else the
+/// first export
+pub fn function_names(binary: &[u8]) -> Result<HashMap<u32, String>, reencode::Error> {
+    let mut names = HashMap::new();
+    let mut export_names = HashMap::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        export_names
+                            .entry(export.index)
+                            .or_insert_with(|| export.name.to_string());
+                    }
+                }
+            },
+            Payload::CustomSection(reader) => {
+                if let KnownCustom::Name(reader) = reader.as_known() {
+                    for subsection in reader {
+                        if let Name::Function(map) = subsection? {
+                            for naming in map {
+                                let naming = naming?;
+                                names.insert(naming.index, naming.name.to_string());
+                            }
+                        }
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    for (index, name) in export_names {
+        names.entry(index).or_insert(name);
+    }
+    Ok(names)
+}
+
+/// Name of a function for reports, see [`function_names`]
+pub fn display_name(names: &HashMap<u32, String>, index: u32) -> String {
+    names
+        .get(&index)
+        .cloned()
+        .unwrap_or_else(|| format!("func[{}]", index))
+}
+
+/// Which functions get calls
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Functions {
+    Exported,
+    All,
+}
+
+/// Calls to add, see [`add_calls`]
+pub struct Calls {
+    /// Import module of the added functions
+    pub module: &'static str,
+    /// Import called on entry; signatures after the first get a numeric suffix
+    pub entry: &'static str,
+    /// Import called on exit, with the function index
+    pub exit: Option<&'static str>,
+    pub functions: Functions,
+    /// Pass the function's arguments after its index on entry
+    /// Functions taking values JavaScript cannot receive (`v128`, `exnref`)
+    /// only pass their index
+    pub arguments: bool,
+}
+
+/// Result of [`add_calls`]
+pub struct Instrumented {
+    /// Names of the added imports, all in [`Calls::module`]
+    pub imports: Vec<String>,
+    /// Indices of the instrumented functions
+    pub functions: Vec<u32>,
+}
+
+/// Call imports at the start (and end) of the selected functions
+/// Each call passes an `i32` function index, then on entry the arguments if
+/// requested; there is one entry import per distinct signature
+pub fn add_calls(binary: &mut Vec<u8>, calls: &Calls) -> Result<Instrumented, reencode::Error> {
+    let mut types: Vec<Option<(Vec<ValType>, Vec<ValType>)>> = Vec::new();
+    let mut imported_functions = 0;
+    let mut function_types = Vec::new();
+    let mut exported = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for group in reader {
+                    for ty in group?.types() {
+                        types.push(match &ty.composite_type.inner {
+                            CompositeInnerType::Func(func) => {
+                                Some((func.params().to_vec(), func.results().to_vec()))
+                            },
+                            _ => None,
+                        });
+                    }
+                }
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            },
+            Payload::FunctionSection(reader) => {
+                for ty in reader {
+                    function_types.push(ty?);
+                }
+            },
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        exported.push(export.index);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // One entry import per signature, `(param i32 <arguments>)`
+    let mut signatures: Vec<Vec<ValType>> = Vec::new();
+    let mut selected = Vec::new();
+    for (position, ty) in function_types.iter().enumerate() {
+        let index = imported_functions + position as u32;
+        if calls.functions == Functions::Exported && !exported.contains(&index) {
+            continue;
+        }
+        let (params, results) = types
+            .get(*ty as usize)
+            .cloned()
+            .flatten()
+            .unwrap_or_default();
+        let arguments = if calls.arguments && params.iter().all(is_js_compatible) {
+            params
+        } else {
+            Vec::new()
+        };
+        let signature = match signatures.iter().position(|s| *s == arguments) {
+            Some(signature) => signature,
+            None => {
+                signatures.push(arguments.clone());
+                signatures.len() - 1
+            },
+        };
+        selected.push((position, index, signature, arguments.len() as u32, results));
+    }
+
+    // Import `n` has the added type `n`; block types for multiple results
+    // follow the imports' types
+    let mut imports: Vec<String> = (0..signatures.len())
+        .map(|signature| match signature {
+            0 => calls.entry.to_string(),
+            _ => format!("{}{}", calls.entry, signature),
+        })
+        .collect();
+    let mut added_types: Vec<(Vec<ValType>, Vec<ValType>)> = signatures
+        .into_iter()
+        .map(|arguments| {
+            (
+                [ValType::I32].into_iter().chain(arguments).collect(),
+                vec![],
+            )
+        })
+        .collect();
+    let exit = calls.exit.map(|name| {
+        imports.push(name.to_string());
+        added_types.push((vec![ValType::I32], vec![]));
+        imported_functions + imports.len() as u32 - 1
+    });
+
+    let first_type = types.len() as u32;
+    let mut bodies: Vec<Option<FunctionCalls>> = function_types.iter().map(|_| None).collect();
+    for (position, index, signature, arguments, results) in selected {
+        let exit = exit.map(|import| {
+            let block_type = match results.as_slice() {
+                [] => BlockType::Empty,
+                [result] => BlockType::Type(*result),
+                _ => {
+                    let ty = (Vec::new(), results);
+                    let position = match added_types.iter().position(|added| *added == ty) {
+                        Some(position) => position,
+                        None => {
+                            added_types.push(ty);
+                            added_types.len() - 1
+                        },
+                    };
+                    BlockType::FuncType(first_type + position as u32)
+                },
+            };
+            (import, block_type)
+        });
+        bodies[position] = Some(FunctionCalls {
+            index,
+            entry: imported_functions + signature as u32,
+            arguments,
+            exit,
+        });
+    }
+
+    let functions = bodies.iter().flatten().map(|calls| calls.index).collect();
+    let mut instrumenter = Instrumenter {
+        module: calls.module,
+        imports: &imports,
+        added_types: &added_types,
+        first_type,
+        imported_functions,
+        bodies: &bodies,
+        next_body: 0,
+        types_added: false,
+        imports_added: false,
+    };
+    let mut module = Module::new();
+    instrumenter.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+
+    Ok(Instrumented { imports, functions })
+}
+
+/// Whether JavaScript can receive values of a type
+fn is_js_compatible(ty: &ValType) -> bool {
+    match ty {
+        ValType::V128 => false,
+        ValType::Ref(ty) => !matches!(
+            ty.heap_type(),
+            HeapType::Abstract {
+                ty: AbstractHeapType::Exn | AbstractHeapType::NoExn,
+                ..
+            }
+        ),
+        _ => true,
+    }
+}
+
+/// The calls added to a function
+struct FunctionCalls {
+    /// Function index before instrumentation
+    index: u32,
+    /// Import to call on entry
+    entry: u32,
+    arguments: u32,
+    /// Import to call on exit, and the type of the block wrapping the body
+    exit: Option<(u32, BlockType)>,
+}
+
+struct Instrumenter<'a> {
+    module: &'static str,
+    imports: &'a [String],
+    /// Types of the imports, then block types, as `(params, results)`
+    added_types: &'a [(Vec<ValType>, Vec<ValType>)],
+    /// Type index of the first added type, after the module's types
+    first_type: u32,
+    imported_functions: u32,
+    /// Calls of each function body, if it is instrumented
+    bodies: &'a [Option<FunctionCalls>],
+    next_body: usize,
+    types_added: bool,
+    imports_added: bool,
+}
+
+impl Instrumenter<'_> {
+    fn add_types(&mut self, types: &mut TypeSection) -> Result<(), reencode::Error> {
+        for (params, results) in self.added_types {
+            let params = params
+                .iter()
+                .map(|ty| self.val_type(*ty))
+                .collect::<Result<Vec<_>, _>>()?;
+            let results = results
+                .iter()
+                .map(|ty| self.val_type(*ty))
+                .collect::<Result<Vec<_>, _>>()?;
+            types.ty().function(params, results);
+        }
+        self.types_added = true;
+        Ok(())
+    }
+
+    fn add_imports(&mut self, imports: &mut ImportSection) {
+        for (position, name) in self.imports.iter().enumerate() {
+            imports.import(
+                self.module,
+                name,
+                EntityType::Function(self.first_type + position as u32),
+            );
+        }
+        self.imports_added = true;
+    }
+}
+
+impl Reencode for Instrumenter<'_> {
+    type Error = Infallible;
+
+    fn function_index(&mut self, func: u32) -> u32 {
+        if func < self.imported_functions {
+            func
+        } else {
+            func + self.imports.len() as u32
+        }
+    }
+
+    fn parse_type_section(
+        &mut self,
+        types: &mut TypeSection,
+        section: TypeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_type_section(self, types, section)?;
+        self.add_types(types)
+    }
+
+    fn parse_import_section(
+        &mut self,
+        imports: &mut ImportSection,
+        section: ImportSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_import_section(self, imports, section)?;
+        self.add_imports(imports);
+        Ok(())
+    }
+
+    // Modules without types or imports get new sections in their place
+    fn intersperse_section_hook(
+        &mut self,
+        module: &mut Module,
+        _after: Option<SectionId>,
+        before: Option<SectionId>,
+    ) -> Result<(), reencode::Error> {
+        if !self.types_added && before != Some(SectionId::Type) {
+            let mut types = TypeSection::new();
+            self.add_types(&mut types)?;
+            module.section(&types);
+        }
+        if !self.imports_added && !matches!(before, Some(SectionId::Type | SectionId::Import)) {
+            let mut imports = ImportSection::new();
+            self.add_imports(&mut imports);
+            module.section(&imports);
+        }
+        Ok(())
+    }
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut CodeSection,
+        body: FunctionBody<'_>,
+    ) -> Result<(), reencode::Error> {
+        let bodies = self.bodies;
+        let calls = bodies[self.next_body].as_ref();
+        self.next_body += 1;
+
+        let mut function = self.new_function_with_parsed_locals(&body)?;
+        let mut reader = body.get_operators_reader()?;
+        let Some(calls) = calls else {
+            while !reader.eof() {
+                function.instruction(&self.instruction(reader.read()?)?);
+            }
+            code.function(&function);
+            return Ok(());
+        };
+
+        function.instruction(&Instruction::I32Const(calls.index as i32));
+        for argument in 0..calls.arguments {
+            function.instruction(&Instruction::LocalGet(argument));
+        }
+        function.instruction(&Instruction::Call(calls.entry));
+        let exit = |function: &mut wasm_encoder::Function, import| {
+            function.instruction(&Instruction::I32Const(calls.index as i32));
+            function.instruction(&Instruction::Call(import));
+        };
+        if let Some((_, block_type)) = calls.exit {
+            function.instruction(&Instruction::Block(self.block_type(block_type)?));
+        }
+        while !reader.eof() {
+            let operator = reader.read()?;
+            if let Some((import, _)) = calls.exit
+                && matches!(
+                    operator,
+                    Operator::Return
+                        | Operator::ReturnCall { .. }
+                        | Operator::ReturnCallIndirect { .. }
+                        | Operator::ReturnCallRef { .. }
+                )
+            {
+                exit(&mut function, import);
+            }
+            function.instruction(&self.instruction(operator)?);
+        }
+        // The body's `end` closed the block
+        if let Some((import, _)) = calls.exit {
+            exit(&mut function, import);
+            function.instruction(&Instruction::End);
+        }
+        code.function(&function);
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Introspection of the pipeline for tests
+//!
+//! `window.internals.wasm`, exposed with the `dom_testbinding_enabled` pref,
+//! reads the cache through these, and empties it with
+//! [`super::clear_cache`], so that tests can assert on what the compiler did
+//! rather than scrape its console output. Modules are named by
+//! their [`ContentHash`] as displayed, `sha-256:` and the digest in hex.
+
+use super::hash::ContentHash;
+use super::{get_cache, memory, telemetry};
+
+/// Counters of the cache
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    /// Modules in the cache
+    pub entries: usize,
+    /// Bytes of their binaries, see [`memory::entry_bytes`]
+    pub bytes: usize,
+    /// Compilations that missed the cache since the process started
+    pub compiled: u64,
+    /// Compilations served from the cache since the process started
+    pub cache_hits: u64,
+}
+
+/// A module in the cache
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedModule {
+    pub hash: String,
+    /// Script the module was first compiled for
+    pub filename: String,
+    /// Bytes of its binary
+    pub size: usize,
+}
+
+pub fn cache_stats() -> CacheStats {
+    let cache = get_cache().read();
+    let counters = telemetry::snapshot();
+    CacheStats {
+        entries: cache.len(),
+        bytes: cache.values().map(memory::entry_bytes).sum(),
+        compiled: counters.compiled,
+        cache_hits: counters.cache_hits,
+    }
+}
+
+/// The modules in the cache, by hash
+pub fn modules() -> Vec<CachedModule> {
+    let cache = get_cache().read();
+    let mut keys: Vec<&ContentHash> = cache.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .map(|hash| CachedModule {
+            hash: hash.to_string(),
+            filename: cache[hash].filename.clone(),
+            size: cache[hash].binary.len(),
+        })
+        .collect()
+}
+
+/// The binary of the module `hash` as it is loaded, after the rewriting
+/// passes
+pub fn binary(hash: &str) -> Option<Vec<u8>> {
+    get_cache()
+        .read()
+        .iter()
+        .find(|(key, _)| key.to_string() == hash)
+        .map(|(_, entry)| entry.binary.clone())
+}
+
+/// The size report of the module `hash` as JSON, see [`super::sizes`]
+pub fn size_report(hash: &str) -> Option<String> {
+    get_cache()
+        .read()
+        .iter()
+        .find(|(key, _)| key.to_string() == hash)
+        .map(|(_, entry)| entry.metadata.size_report_json.clone())
+}
@@ -0,0 +1,345 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! String interpolation in WAT string literals
+//!
+//! A string literal used as an instruction operand may embed `{$name}`
+//! placeholders referring to a local, parameter or global of the enclosing
+//! function. The literal is lowered to a `$string` built by concatenating
+//! the static pieces (read from a passive data segment) with the formatted
+//! values:
+//!
+//! ```text
+//! (func (export "label") (param $score i32) (result (ref $string))
+//!   "score: {$score}")
+//! ```
+//!
+//! becomes
+//!
+//! ```text
+//! (string.concat
+//!   (array.new_data $string $__interp_0 (i32.const 0) (i32.const 7))
+//!   (i32.to_string (local.get $score)))
+//! ```
+//!
+//! The `string.concat` and `to_string` pseudo-instructions are then lowered
+//! by the intrinsics pass. A `{` not followed by `$` is kept as is; `\u{7b}`
+//! escapes a literal `{$`.
+
+use std::collections::HashMap;
+
+use super::CompileError;
+use super::wat_text::{Sexpr, paren_delta, parse_sexpr};
+
+/// Check whether a WAT source may contain interpolated string literals
+pub fn uses_interpolation(source: &str) -> bool {
+    source.contains("{$")
+}
+
+/// Value types that can be interpolated
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    I32,
+    F32,
+    F64,
+    String,
+    Unsupported,
+}
+
+impl Kind {
+    fn of(ty: &Sexpr) -> Kind {
+        match ty {
+            Sexpr::Atom(atom) => match atom.as_str() {
+                "i32" => Kind::I32,
+                "f32" => Kind::F32,
+                "f64" => Kind::F64,
+                _ => Kind::Unsupported,
+            },
+            Sexpr::List(_) if ty.head() == Some("mut") => {
+                ty.items().get(1).map_or(Kind::Unsupported, Kind::of)
+            },
+            Sexpr::List(items) if ty.head() == Some("ref") => {
+                if items.iter().any(|item| item.atom() == Some("$string")) {
+                    Kind::String
+                } else {
+                    Kind::Unsupported
+                }
+            },
+            Sexpr::List(_) => Kind::Unsupported,
+        }
+    }
+}
+
+/// A variable visible from a function body
+#[derive(Clone, Copy)]
+struct Var {
+    kind: Kind,
+    global: bool,
+}
+
+/// Lower interpolated string literals in function bodies
+pub fn lower_interpolation(source: &str) -> Result<String, CompileError> {
+    let globals = collect_globals(source);
+    let mut result = String::with_capacity(source.len());
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut func: Option<Vec<&str>> = None;
+
+    for line in source.lines() {
+        match func.as_mut() {
+            Some(lines) => lines.push(line),
+            None if depth == 1 && line.trim_start().starts_with("(func") => {
+                func = Some(vec![line]);
+            },
+            None => {
+                result.push_str(line);
+                result.push('\n');
+            },
+        }
+
+        depth += paren_delta(line);
+
+        // The function ended once we are back at module level
+        if depth <= 1
+            && let Some(lines) = func.take()
+        {
+            lower_function(&lines, &globals, &mut segments, &mut result)?;
+        }
+    }
+    if let Some(lines) = func {
+        lower_function(&lines, &globals, &mut segments, &mut result)?;
+    }
+
+    // Data segments may appear anywhere in the module, so they go before its
+    // closing parenthesis
+    let Some(end) = result.rfind(')').filter(|_| !segments.is_empty()) else {
+        return Ok(result);
+    };
+    let mut data = String::from("\n  ;; Interpolated string pieces (generated)\n");
+    for (index, text) in segments.iter().enumerate() {
+        data.push_str(&format!("  (data $__interp_{} \"{}\")\n", index, text));
+    }
+    result.insert_str(end, &data);
+    Ok(result)
+}
+
+/// Types of the module's named globals
+fn collect_globals(source: &str) -> HashMap<String, Var> {
+    let mut globals = HashMap::new();
+    let mut depth = 0;
+    for line in source.lines() {
+        if depth == 1
+            && line.trim_start().starts_with("(global")
+            && let Some(global) = parse_sexpr(line)
+            && let Some((name, kind)) = declaration(&global)
+        {
+            globals.insert(name, Var { kind, global: true });
+        }
+        depth += paren_delta(line);
+    }
+    globals
+}
+
+/// Name and kind of a `(param $x T)`, `(local $x T)` or `(global $x T ...)`
+fn declaration(decl: &Sexpr) -> Option<(String, Kind)> {
+    let items = decl.items();
+    let name = items.get(1)?.atom()?.strip_prefix('$')?.to_string();
+    let ty = items[2..]
+        .iter()
+        .find(|item| !matches!(item.head(), Some("export") | Some("import")))?;
+    Some((name, Kind::of(ty)))
+}
+
+fn lower_function(
+    lines: &[&str],
+    globals: &HashMap<String, Var>,
+    segments: &mut Vec<String>,
+    result: &mut String,
+) -> Result<(), CompileError> {
+    let text = lines.join("\n");
+    let func = parse_sexpr(&text);
+    let func_name = func
+        .as_ref()
+        .and_then(|func| func.items().get(1)?.atom())
+        .filter(|name| name.starts_with('$'))
+        .unwrap_or("(anonymous)")
+        .to_string();
+
+    let mut scope = globals.clone();
+    for item in func.as_ref().map_or(&[][..], Sexpr::items) {
+        if matches!(item.head(), Some("param") | Some("local"))
+            && let Some((name, kind)) = declaration(item)
+        {
+            scope.insert(
+                name,
+                Var {
+                    kind,
+                    global: false,
+                },
+            );
+        }
+    }
+
+    for line in lines {
+        let line = lower_line(line, &scope, segments).map_err(|msg| {
+            CompileError::InterpolationError(format!("in function {}: {}", func_name, msg))
+        })?;
+        result.push_str(&line);
+        result.push('\n');
+    }
+    Ok(())
+}
+
+/// Rewrite the interpolated literals of one line
+fn lower_line(
+    line: &str,
+    scope: &HashMap<String, Var>,
+    segments: &mut Vec<String>,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    // Export and import names are never interpolated
+    let mut in_name = false;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with(";;") {
+            result.push_str(rest);
+            break;
+        }
+        if c == '"' {
+            let bytes = rest.as_bytes();
+            let mut end = 1;
+            while end < bytes.len() && bytes[end] != b'"' {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(rest.len());
+            let literal = &rest[..end];
+            let content = literal.get(1..literal.len() - 1).unwrap_or_default();
+            let is_name = in_name
+                || ["(export", "(import"]
+                    .iter()
+                    .any(|keyword| result.trim_end().ends_with(keyword));
+            if !is_name && content.contains("{$") {
+                result.push_str(&lower_literal(content, scope, segments)?);
+            } else {
+                result.push_str(literal);
+            }
+            in_name = is_name;
+            rest = &rest[end..];
+            continue;
+        }
+        if !c.is_whitespace() {
+            in_name = false;
+        }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    Ok(result)
+}
+
+/// Build the concatenation expression for one literal's content
+fn lower_literal(
+    content: &str,
+    scope: &HashMap<String, Var>,
+    segments: &mut Vec<String>,
+) -> Result<String, String> {
+    let segment = segments.len();
+    let mut data = String::new();
+    let mut offset = 0;
+    let mut parts = Vec::new();
+    let mut rest = content;
+
+    while !rest.is_empty() {
+        let (text, placeholder) = match rest.find("{$") {
+            Some(start) => (&rest[..start], Some(start)),
+            None => (rest, None),
+        };
+        if !text.is_empty() {
+            let len = decoded_len(text);
+            data.push_str(text);
+            parts.push(format!(
+                "(array.new_data $string $__interp_{} (i32.const {}) (i32.const {}))",
+                segment, offset, len
+            ));
+            offset += len;
+        }
+        let Some(start) = placeholder else {
+            break;
+        };
+        let after = &rest[start + 2..];
+        let close = after
+            .find('}')
+            .ok_or_else(|| format!("unterminated placeholder in \"{}\"", content))?;
+        parts.push(format_value(&after[..close], scope)?);
+        rest = &after[close + 1..];
+    }
+
+    if !data.is_empty() {
+        segments.push(data);
+    }
+    let mut parts = parts.into_iter();
+    let first = parts.next().unwrap_or_default();
+    Ok(parts.fold(first, |acc, part| {
+        format!("(string.concat {} {})", acc, part)
+    }))
+}
+
+/// Expression producing a `$string` for the value of `$name`
+fn format_value(name: &str, scope: &HashMap<String, Var>) -> Result<String, String> {
+    let var = scope
+        .get(name)
+        .ok_or_else(|| format!("unknown variable `${}` in placeholder", name))?;
+    let get = format!(
+        "({} ${})",
+        if var.global {
+            "global.get"
+        } else {
+            "local.get"
+        },
+        name
+    );
+    Ok(match var.kind {
+        Kind::I32 => format!("(i32.to_string {})", get),
+        Kind::F32 => format!("(f64.to_string (f64.promote_f32 {}))", get),
+        Kind::F64 => format!("(f64.to_string {})", get),
+        Kind::String => get,
+        Kind::Unsupported => {
+            return Err(format!("cannot interpolate `${}`: unsupported type", name));
+        },
+    })
+}
+
+/// Number of bytes a WAT string literal content decodes to
+pub fn decoded_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            len += c.len_utf8();
+            continue;
+        }
+        len += match chars.next() {
+            Some('u') => {
+                // \u{hex}
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map_or(0, char::len_utf8)
+            },
+            Some(c) if c.is_ascii_hexdigit() => {
+                // \hh, a single raw byte
+                chars.next();
+                1
+            },
+            Some(_) => 1,
+            None => 0,
+        };
+    }
+    len
+}
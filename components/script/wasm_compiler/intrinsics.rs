@@ -0,0 +1,201 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Number formatting and string intrinsics
+//!
+//! `i32.to_string`, `f64.to_string` and `string.concat` are not real WAT
+//! instructions. They are lowered to calls of generated helpers that build
+//! `$string` arrays (`(array (mut i8))`, UTF-8), so modules can format
+//! numbers for logging or UI without calling back into JavaScript for every
+//! conversion:
+//!
+//! ```text
+//! (func (export "label") (param $score i32) (result (ref $string))
+//!   (i32.to_string (local.get $score)))
+//! ```
+
+/// Pseudo-instructions and the helper each one is lowered to
+const INTRINSICS: [(&str, &str); 3] = [
+    ("i32.to_string", "call $__i32_to_string"),
+    ("f64.to_string", "call $__f64_to_string"),
+    ("string.concat", "call $__string_concat"),
+];
+
+/// String type shared with the string-type sugar
+const STRING_TYPE: &str = "(type $string (array (mut i8)))";
+
+/// Generated helpers; they only rely on GC array instructions, so no data
+/// segments (and no datacount section) are needed
+const HELPERS: &str = r#"
+  ;; Number formatting intrinsics (generated)
+  (func $__string_char (param $c i32) (result (ref $string))
+    (array.new $string (local.get $c) (i32.const 1)))
+
+  (func $__string_slice (param $s (ref null $string)) (param $start i32) (param $end i32) (result (ref $string))
+    (local $out (ref $string))
+    (local.set $out (array.new $string (i32.const 0) (i32.sub (local.get $end) (local.get $start))))
+    (array.copy $string $string (local.get $out) (i32.const 0)
+      (local.get $s) (local.get $start) (i32.sub (local.get $end) (local.get $start)))
+    (local.get $out))
+
+  (func $__string_concat (param $a (ref null $string)) (param $b (ref null $string)) (result (ref $string))
+    (local $out (ref $string))
+    (local.set $out (array.new $string (i32.const 0)
+      (i32.add (array.len (local.get $a)) (array.len (local.get $b)))))
+    (array.copy $string $string (local.get $out) (i32.const 0)
+      (local.get $a) (i32.const 0) (array.len (local.get $a)))
+    (array.copy $string $string (local.get $out) (array.len (local.get $a))
+      (local.get $b) (i32.const 0) (array.len (local.get $b)))
+    (local.get $out))
+
+  (func $__u64_to_string (param $n i64) (result (ref $string))
+    (local $buf (ref $string))
+    (local $pos i32)
+    (local.set $buf (array.new $string (i32.const 0) (i32.const 20)))
+    (local.set $pos (i32.const 20))
+    (loop $digits
+      (local.set $pos (i32.sub (local.get $pos) (i32.const 1)))
+      (array.set $string (local.get $buf) (local.get $pos)
+        (i32.add (i32.const 48) (i32.wrap_i64 (i64.rem_u (local.get $n) (i64.const 10)))))
+      (local.set $n (i64.div_u (local.get $n) (i64.const 10)))
+      (br_if $digits (i64.ne (local.get $n) (i64.const 0))))
+    (call $__string_slice (local.get $buf) (local.get $pos) (i32.const 20)))
+
+  (func $__i32_to_string (param $value i32) (result (ref $string))
+    (if (result (ref $string)) (i32.lt_s (local.get $value) (i32.const 0))
+      (then
+        (call $__string_concat
+          (call $__string_char (i32.const 45))
+          (call $__u64_to_string (i64.sub (i64.const 0) (i64.extend_i32_s (local.get $value))))))
+      (else
+        (call $__u64_to_string (i64.extend_i32_u (local.get $value))))))
+
+  ;; Up to six fractional digits, trailing zeros dropped; exponent form from 1e19
+  (func $__f64_to_string (param $value f64) (result (ref $string))
+    (local $int f64)
+    (local $frac i64)
+    (local $width i32)
+    (local $digits (ref $string))
+    (local $result (ref $string))
+    (if (f64.ne (local.get $value) (local.get $value))
+      (then
+        (return (array.new_fixed $string 3 (i32.const 78) (i32.const 97) (i32.const 78)))))
+    (if (f64.lt (local.get $value) (f64.const 0))
+      (then
+        (return (call $__string_concat
+          (call $__string_char (i32.const 45))
+          (call $__f64_to_string (f64.neg (local.get $value)))))))
+    (if (f64.eq (local.get $value) (f64.const inf))
+      (then
+        (return (array.new_fixed $string 8
+          (i32.const 73) (i32.const 110) (i32.const 102) (i32.const 105)
+          (i32.const 110) (i32.const 105) (i32.const 116) (i32.const 121)))))
+    (if (f64.ge (local.get $value) (f64.const 1e19))
+      (then
+        (loop $scale
+          (local.set $value (f64.div (local.get $value) (f64.const 10)))
+          (local.set $width (i32.add (local.get $width) (i32.const 1)))
+          (br_if $scale (f64.ge (local.get $value) (f64.const 10))))
+        (return (call $__string_concat
+          (call $__f64_to_string (local.get $value))
+          (call $__string_concat
+            (array.new_fixed $string 2 (i32.const 101) (i32.const 43))
+            (call $__i32_to_string (local.get $width)))))))
+    (local.set $int (f64.floor (local.get $value)))
+    (local.set $frac (i64.trunc_f64_u (f64.nearest
+      (f64.mul (f64.sub (local.get $value) (local.get $int)) (f64.const 1e6)))))
+    (if (i64.ge_u (local.get $frac) (i64.const 1000000))
+      (then
+        (local.set $int (f64.add (local.get $int) (f64.const 1)))
+        (local.set $frac (i64.sub (local.get $frac) (i64.const 1000000)))))
+    (local.set $result (call $__u64_to_string (i64.trunc_f64_u (local.get $int))))
+    (if (i64.eqz (local.get $frac))
+      (then
+        (return (local.get $result))))
+    (local.set $width (i32.const 6))
+    (block $trimmed
+      (loop $trim
+        (br_if $trimmed (i64.ne (i64.rem_u (local.get $frac) (i64.const 10)) (i64.const 0)))
+        (local.set $frac (i64.div_u (local.get $frac) (i64.const 10)))
+        (local.set $width (i32.sub (local.get $width) (i32.const 1)))
+        (br $trim)))
+    (local.set $digits (call $__u64_to_string (local.get $frac)))
+    (local.set $result (call $__string_concat (local.get $result) (call $__string_char (i32.const 46))))
+    (local.set $result (call $__string_concat (local.get $result)
+      (array.new $string (i32.const 48) (i32.sub (local.get $width) (array.len (local.get $digits))))))
+    (call $__string_concat (local.get $result) (local.get $digits)))
+"#;
+
+/// Check whether a WAT source uses any intrinsic
+pub fn uses_intrinsics(source: &str) -> bool {
+    INTRINSICS.iter().any(|(name, _)| source.contains(name))
+}
+
+/// Replace intrinsic pseudo-instructions with helper calls and append the
+/// helpers (and the `$string` type, if missing) to the module
+pub fn lower_intrinsics(source: &str) -> String {
+    if !uses_intrinsics(source) {
+        return source.to_string();
+    }
+
+    let mut result = source.to_string();
+    for (name, call) in INTRINSICS {
+        result = replace_instruction(&result, name, call);
+    }
+
+    // Module fields may appear in any order in WAT, so the helpers can simply
+    // go before the module's closing parenthesis
+    let Some(end) = result.rfind(')') else {
+        return result;
+    };
+    let mut helpers = String::new();
+    if !result.contains("(type $string") {
+        helpers.push_str("\n  ");
+        helpers.push_str(STRING_TYPE);
+        helpers.push('\n');
+    }
+    helpers.push_str(HELPERS);
+    result.insert_str(end, &helpers);
+    result
+}
+
+/// Replace whole-word occurrences of an instruction, skipping strings and comments
+fn replace_instruction(source: &str, name: &str, replacement: &str) -> String {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || "_.$".contains(c);
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        if rest.starts_with('"') {
+            // String literal, including escaped quotes
+            let mut end = 1;
+            let bytes = rest.as_bytes();
+            while end < bytes.len() && bytes[end] != b'"' {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(rest.len());
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if rest.starts_with(";;") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if rest.starts_with(name)
+            && !result.ends_with(is_word_char)
+            && !rest[name.len()..].starts_with(is_word_char)
+        {
+            result.push_str(replacement);
+            rest = &rest[name.len()..];
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    result
+}
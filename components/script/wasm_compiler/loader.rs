@@ -0,0 +1,139 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Modules loaded at runtime
+//!
+//! A module loads others as it runs, as a program would with `dlopen`,
+//! through the functions the loader provides as [`MODULE`] unless the page
+//! does:
+//!
+//! ```text
+//! (import "loader" "load" (func $load (param $url i32) (param $len i32) (result i32)))
+//! (import "loader" "status" (func $status (param $handle i32) (result i32)))
+//! (import "loader" "export" (func $export (param $handle i32) (param $name i32) (param $len i32) (result funcref)))
+//! ```
+//!
+//! - `load` returns a handle at once, from 1, and loads the module at the URL,
+//!   relative to the page, as a WAT script of the page: it is fetched,
+//!   compiled and cached like any other. Loading a URL again returns the same
+//!   handle, and a module the page loaded already is not loaded twice.
+//! - `status` is 1 once the module is instantiated, 0 while it loads and -1
+//!   if it failed to, or the handle is unknown.
+//! - `export` is the function the loaded module exports under the name, or
+//!   null while it loads or if it exports none. The module puts it in a
+//!   table to `call_indirect` it with the signature it expects, which the
+//!   engine checks.
+//!
+//! Strings are UTF-8 in the memory the module exports, read once it is
+//! instantiated; modules holding JavaScript strings as `externref`s pass
+//! them instead of the pointer and length.
+
+use super::LOG_TARGET;
+use super::imports;
+use super::strings;
+
+/// Module of the imports of the runtime loader
+pub const MODULE: &str = "loader";
+
+/// The functions the loader provides
+const FUNCTIONS: [&str; 3] = ["load", "status", "export"];
+
+/// Whether `binary` imports functions of the runtime loader, warning about
+/// those it does not provide
+pub fn imports_loader(binary: &[u8]) -> bool {
+    let mut uses_loader = false;
+    for (module, name, _) in imports::declared_imports(binary) {
+        if module != MODULE {
+            continue;
+        }
+        uses_loader = true;
+        if !FUNCTIONS.contains(&name.as_str()) {
+            log::warn!(
+                target: LOG_TARGET, phase = "loader", name:% = name;
+                "The loader provides {}, not {:?}, which the page must", FUNCTIONS.join(", "), name
+            );
+        }
+    }
+    uses_loader
+}
+
+/// JavaScript of the functions of [`MODULE`], for a module importing them
+pub fn loader_js() -> String {
+    format!(
+        r#"
+        // Handles of the modules this one loaded at runtime, from 1
+        const loaderHandles = [null];
+        const loaderString = function(value, length) {{
+            if (typeof value === 'string') {{
+                return value;
+            }}
+            const instance = window.__wasmModules[wasmFilename].instance;
+            const exports = instance ? instance.exports : {{}};
+            const memory = exports.memory || exports['{string_memory}'] ||
+                Object.values(exports).find(value => value instanceof WebAssembly.Memory);
+            if (!memory) {{
+                throw new Error('WASM: ' + wasmFilename + ' passed the loader a string without exporting its memory');
+            }}
+            // Copied, as shared memories cannot be decoded in place
+            return new TextDecoder('utf-8').decode(new Uint8Array(memory.buffer, value >>> 0, length >>> 0).slice());
+        }};
+        const loaderImports = {{
+            '{module}': {{
+                load: function(url, length) {{
+                    const href = new URL(loaderString(url, length), document.baseURI).href;
+                    const known = loaderHandles.findIndex(handle => handle && handle.url === href);
+                    if (known > 0) {{
+                        return known;
+                    }}
+                    const handle = {{ url: href, status: 0, instance: null }};
+                    loaderHandles.push(handle);
+                    const loaded = window.__wasmModules && window.__wasmModules[href];
+                    if (loaded && loaded.instance) {{
+                        handle.status = 1;
+                        handle.instance = loaded.instance;
+                        return loaderHandles.length - 1;
+                    }}
+                    const settle = function(event) {{
+                        if (event.detail && event.detail.filename !== href) {{
+                            return;
+                        }}
+                        window.removeEventListener('wasmloaded', settle);
+                        window.removeEventListener('wasmerror', settle);
+                        const entry = event.type === 'wasmloaded' && window.__wasmModules[href];
+                        handle.instance = entry ? entry.instance : null;
+                        handle.status = handle.instance ? 1 : -1;
+                        if (!handle.instance) {{
+                            console.error('WASM: ' + wasmFilename + ' failed to load ' + href);
+                        }}
+                    }};
+                    window.addEventListener('wasmloaded', settle);
+                    window.addEventListener('wasmerror', settle);
+                    // Loaded as a WAT script of the page, compiled and cached like one
+                    const script = document.createElement('script');
+                    script.type = 'text/wat';
+                    script.src = href;
+                    script.addEventListener('error', settle);
+                    (document.head || document.documentElement).appendChild(script);
+                    return loaderHandles.length - 1;
+                }},
+                status: function(handle) {{
+                    return loaderHandles[handle] ? loaderHandles[handle].status : -1;
+                }},
+                export: function(handle, name, length) {{
+                    const loaded = loaderHandles[handle];
+                    if (!loaded || !loaded.instance) {{
+                        return null;
+                    }}
+                    const value = loaded.instance.exports[loaderString(name, length)];
+                    return typeof value === 'function' ? value : null;
+                }}
+            }}
+        }};"#,
+        string_memory = strings::MEMORY_EXPORT,
+        module = MODULE,
+    )
+}
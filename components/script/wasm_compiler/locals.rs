@@ -0,0 +1,133 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Function and local names
+//!
+//! The name section names functions and, in its local names subsection, their
+//! parameters and locals. Errors at an offset in a function body are reported
+//! with them, e.g. "in $update, local $velocity" for an instruction accessing
+//! a local, and the loader installs them as `window.__wasmLocals[filename]`
+//! for devtools, by function index:
+//!
+//! ```text
+//! {"3": {"name": "update", "locals": {"0": "dt", "1": "velocity"}}}
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasmparser::{KnownCustom, Name, Operator, Parser, Payload, TypeRef};
+
+/// Names of a function and its locals, parameters first
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct FunctionNames {
+    pub name: Option<String>,
+    pub locals: BTreeMap<u32, String>,
+}
+
+/// Names of the functions that have a name or named locals, by index
+pub fn local_names(binary: &[u8]) -> BTreeMap<u32, FunctionNames> {
+    let mut functions: BTreeMap<u32, FunctionNames> = BTreeMap::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::CustomSection(reader)) = payload else {
+            continue;
+        };
+        let KnownCustom::Name(reader) = reader.as_known() else {
+            continue;
+        };
+        for subsection in reader.flatten() {
+            match subsection {
+                Name::Function(map) => {
+                    for naming in map.into_iter().flatten() {
+                        functions.entry(naming.index).or_default().name =
+                            Some(naming.name.to_string());
+                    }
+                },
+                Name::Local(map) => {
+                    for indirect in map.into_iter().flatten() {
+                        let locals = &mut functions.entry(indirect.index).or_default().locals;
+                        for naming in indirect.names.into_iter().flatten() {
+                            locals.insert(naming.index, naming.name.to_string());
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    functions
+}
+
+/// [`local_names`] as JSON, for the loader
+pub fn locals_json(binary: &[u8]) -> String {
+    serde_json::to_string(&local_names(binary)).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Where `offset` is, e.g. "in $update, local $velocity", if it is in the
+/// body of a function
+/// The local is that of the instruction at `offset`, if it accesses one.
+pub fn describe_offset(binary: &[u8], offset: usize) -> Option<String> {
+    let mut function = None;
+    let mut imported_functions = 0;
+    let mut bodies = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload {
+            Ok(Payload::ImportSection(reader)) => {
+                imported_functions += reader
+                    .into_iter()
+                    .flatten()
+                    .filter(|import| matches!(import.ty, TypeRef::Func(_)))
+                    .count() as u32;
+            },
+            Ok(Payload::CodeSectionEntry(body)) => {
+                let index = imported_functions + bodies;
+                bodies += 1;
+                if body.range().contains(&offset) {
+                    function = Some((index, accessed_local(&body, offset)));
+                    break;
+                }
+            },
+            Ok(_) => {},
+            Err(_) => break,
+        }
+    }
+
+    let (index, local) = function?;
+    let names = local_names(binary);
+    let names = names.get(&index);
+    let mut description = match names.and_then(|names| names.name.as_ref()) {
+        Some(name) => format!("in ${}", name),
+        None => format!("in func[{}]", index),
+    };
+    if let Some(local) = local {
+        match names.and_then(|names| names.locals.get(&local)) {
+            Some(name) => description.push_str(&format!(", local ${}", name)),
+            None => description.push_str(&format!(", local {}", local)),
+        }
+    }
+    Some(description)
+}
+
+/// Index of the local the instruction at `offset` accesses, if any
+fn accessed_local(body: &wasmparser::FunctionBody, offset: usize) -> Option<u32> {
+    let mut reader = body.get_operators_reader().ok()?;
+    while !reader.eof() {
+        let (operator, at) = reader.read_with_offset().ok()?;
+        if at > offset {
+            return None;
+        }
+        if at == offset {
+            return match operator {
+                Operator::LocalGet { local_index }
+                | Operator::LocalSet { local_index }
+                | Operator::LocalTee { local_index } => Some(local_index),
+                _ => None,
+            };
+        }
+    }
+    None
+}
@@ -0,0 +1,178 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memory reporting and limits for the compilation cache
+//!
+//! The cache is shared by all script threads of a process, so it has a single
+//! reporter per process instead of being part of each script thread's report.
+//! about:memory shows it under `wasm-cache`, broken down by the origin and URL
+//! of the script each module was first compiled for.
+//!
+//! It holds at most [`CACHE_ENTRIES`] modules and [`CACHE_BYTES`] of them,
+//! evicting the least recently used ones, and sheds them when the engine
+//! collects garbage under memory pressure, see [`shed`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Once;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ipc_channel::ipc;
+use ipc_channel::router::ROUTER;
+use malloc_size_of::{MallocShallowSizeOf, MallocSizeOf, MallocSizeOfOps};
+use profile_traits::mem::{
+    ProcessReports, ProfilerChan, ProfilerMsg, Report, ReportKind, Reporter, ReporterRequest,
+    perform_memory_report,
+};
+use profile_traits::path;
+use servo_url::ServoUrl;
+
+use super::hash::ContentHash;
+use super::{CacheEntry, LOG_TARGET, get_cache};
+
+/// Modules the cache holds at most
+pub const CACHE_ENTRIES: usize = 100;
+
+/// Bytes of compiled modules the cache holds at most
+pub const CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// How much of the cache memory pressure sheds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pressure {
+    /// The least recently used modules, half of the bytes held
+    Moderate,
+    /// Every module, the process being about to run out of memory
+    Critical,
+}
+
+/// A stamp ordering the uses of cached modules
+pub(super) fn next_use() -> usize {
+    static USES: AtomicUsize = AtomicUsize::new(0);
+    USES.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Bytes a cached module holds: its binary and that of its fallback
+pub(super) fn entry_bytes(entry: &CacheEntry) -> usize {
+    entry.binary.len()
+        + entry
+            .metadata
+            .fallback
+            .as_ref()
+            .map_or(0, |fallback| fallback.binary.len())
+}
+
+/// Evict the least recently used modules until at most `entries` are left,
+/// holding at most `bytes`
+/// Returns the bytes released.
+pub(super) fn evict(
+    cache: &mut HashMap<ContentHash, CacheEntry>,
+    entries: usize,
+    bytes: usize,
+) -> usize {
+    let mut held: usize = cache.values().map(entry_bytes).sum();
+    let before = held;
+    let mut by_use: Vec<(usize, ContentHash)> = cache
+        .iter()
+        .map(|(key, entry)| (entry.last_used.load(Ordering::Relaxed), *key))
+        .collect();
+    by_use.sort_unstable();
+    for (_, key) in by_use {
+        if cache.len() <= entries && held <= bytes {
+            break;
+        }
+        if let Some(entry) = cache.remove(&key) {
+            held -= entry_bytes(&entry);
+        }
+    }
+    before - held
+}
+
+/// Shed cached modules under memory pressure; a cache in use by another
+/// thread is left alone rather than waited for
+/// Returns the bytes released.
+pub fn shed(pressure: Pressure) -> usize {
+    let Some(mut cache) = get_cache().try_write() else {
+        return 0;
+    };
+    let bytes = match pressure {
+        Pressure::Moderate => cache.values().map(entry_bytes).sum::<usize>() / 2,
+        Pressure::Critical => 0,
+    };
+    let released = evict(&mut cache, CACHE_ENTRIES, bytes);
+    if released > 0 {
+        log::info!(
+            target: LOG_TARGET, phase = "eviction", bytes = released, pressure:? = pressure;
+            "Shed {} bytes of compiled modules under {:?} memory pressure", released, pressure
+        );
+    }
+    released
+}
+
+/// Register the cache with the memory profiler; later calls do nothing
+pub fn register_reporter(chan: &ProfilerChan) {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        let (sender, receiver) = ipc::channel().expect("failed to create ipc channel");
+        ROUTER.add_typed_route(
+            receiver,
+            Box::new(|message| {
+                if let Ok(request) = message {
+                    collect_reports(request);
+                }
+            }),
+        );
+        chan.send(ProfilerMsg::RegisterReporter(
+            format!("wasm-cache-{}", std::process::id()),
+            Reporter(sender),
+        ));
+    });
+}
+
+fn collect_reports(request: ReporterRequest) {
+    let mut reports = vec![];
+    perform_memory_report(|ops| reports = cache_reports(ops));
+    request.reports_channel.send(ProcessReports::new(reports));
+}
+
+/// Measure the cache: the table itself, then each script's modules
+pub fn cache_reports(ops: &mut MallocSizeOfOps) -> Vec<Report> {
+    let cache = get_cache().read();
+    let mut reports = vec![Report {
+        path: path!["wasm-cache", "table"],
+        kind: ReportKind::ExplicitJemallocHeapSize,
+        size: cache.shallow_size_of(ops),
+    }];
+
+    // Inline scripts are filed under their document's URL, so several modules
+    // may share a path
+    let mut per_script: BTreeMap<(String, &str), usize> = BTreeMap::new();
+    for entry in cache.values() {
+        *per_script
+            .entry((origin(&entry.filename), entry.filename.as_str()))
+            .or_default() += entry.size_of(ops);
+    }
+    reports.extend(
+        per_script
+            .into_iter()
+            .map(|((origin, filename), size)| Report {
+                path: path![
+                    "wasm-cache",
+                    format!("origin({})", origin),
+                    format!("script({})", filename)
+                ],
+                kind: ReportKind::ExplicitJemallocHeapSize,
+                size,
+            }),
+    );
+    reports
+}
+
+/// Origin of a script URL; anything else is reported as unknown
+fn origin(filename: &str) -> String {
+    ServoUrl::parse(filename)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
@@ -0,0 +1,6408 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! WebAssembly Text (WAT) to binary compilation
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use base::cross_process_instant::CrossProcessInstant;
+use parking_lot::RwLock;
+use serde_json;
+use wasmparser::WasmFeatures;
+
+mod accessors;
+mod aliases;
+mod arrays;
+mod assemblyscript;
+pub mod bench;
+mod bind;
+mod bindgen;
+mod breakpoints;
+pub mod budget;
+mod capabilities;
+mod component;
+pub mod compressed;
+mod coverage;
+mod differential;
+mod embed;
+mod emscripten;
+mod exports;
+mod fallback;
+mod hash;
+mod imports;
+mod inflight;
+mod inline;
+mod instrument;
+pub mod internals;
+mod interpolation;
+mod intrinsics;
+mod loader;
+mod locals;
+pub mod memory;
+mod names;
+mod options;
+pub mod patch;
+mod profile;
+pub mod registry;
+mod repl;
+mod results;
+mod shake;
+pub mod shared;
+mod sizes;
+pub mod sniff;
+pub mod speculative;
+mod start;
+mod stdlib;
+mod strings;
+mod structs;
+mod sugar;
+pub mod syntax;
+mod tables;
+pub mod telemetry;
+pub mod timeline;
+mod tinygo;
+mod trace;
+mod wasi;
+mod wasi_http;
+mod wast;
+mod wat_text;
+
+pub use self::wast::run_wast;
+use hash::ContentHash;
+pub use options::{CompileOptions, PageDefaults, parse_feature_list};
+pub use repl::{compile_file, compile_fragment};
+pub use start::StartPolicy;
+
+/// Target of the compiler's log records, which carry key-values for
+/// embedders to filter and parse: `phase`, the step of the pipeline (the
+/// binary passes are named as in [`differential`] reports), and where the
+/// step knows them `filename`, `hash` (the cache key), `duration_ms` and
+/// sizes in `bytes`
+pub const LOG_TARGET: &str = "wasm_compiler";
+
+/// Error type for WASM compilation
+#[derive(Debug)]
+pub enum CompileError {
+    ParseError(String),
+    SugarError(String),
+    InterpolationError(String),
+    ValidationError(String),
+    InstrumentationError(String),
+    PatchError(String),
+    LinkError(String),
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::ParseError(msg) => write!(f, "WAT parse error: {}", msg),
+            CompileError::SugarError(msg) => write!(f, "WAT sugar error: {}", msg),
+            CompileError::InterpolationError(msg) => {
+                write!(f, "WAT string interpolation error: {}", msg)
+            },
+            CompileError::ValidationError(msg) => write!(f, "WASM validation error: {}", msg),
+            CompileError::InstrumentationError(msg) => {
+                write!(f, "WASM instrumentation error: {}", msg)
+            },
+            CompileError::PatchError(msg) => write!(f, "WASM patch error: {}", msg),
+            CompileError::LinkError(msg) => write!(f, "WAT stdlib link error: {}", msg),
+        }
+    }
+}
+
+impl CompileError {
+    /// Short names of the error kinds, used for telemetry, by
+    /// [`CompileError::index`]
+    pub const KINDS: [&'static str; 7] = [
+        "parse",
+        "sugar",
+        "interpolation",
+        "validation",
+        "instrumentation",
+        "patch",
+        "link",
+    ];
+
+    /// Index of the error kind in [`CompileError::KINDS`]
+    pub fn index(&self) -> usize {
+        match self {
+            CompileError::ParseError(_) => 0,
+            CompileError::SugarError(_) => 1,
+            CompileError::InterpolationError(_) => 2,
+            CompileError::ValidationError(_) => 3,
+            CompileError::InstrumentationError(_) => 4,
+            CompileError::PatchError(_) => 5,
+            CompileError::LinkError(_) => 6,
+        }
+    }
+
+    /// Short name of the error kind, used for telemetry
+    pub fn kind(&self) -> &'static str {
+        Self::KINDS[self.index()]
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// What the loader needs to know about a module, derived once per compilation
+#[derive(Clone, Debug, MallocSizeOf, PartialEq)]
+pub struct ModuleMetadata {
+    /// Struct types by type index, as JSON, see [`names::field_names_json`]
+    field_names_json: String,
+    /// Proposals the module needs, as a JSON array
+    required_features_json: String,
+    /// Declared imports, as a JSON array
+    imports_json: String,
+    /// Aliases by export name, as JSON, see [`aliases`]
+    export_aliases_json: String,
+    /// Function and local names, as JSON, see [`locals`]
+    locals_json: String,
+    /// Tables and element segments, as JSON, see [`tables`]
+    tables_json: String,
+    /// Where the bytes of the module go, as JSON, see [`sizes`]
+    size_report_json: String,
+    /// Whether a memory is shared, see [`capabilities::uses_shared_memory`]
+    shared_memory: bool,
+    /// Runtime of modules built by Emscripten, as JSON, see [`emscripten`]
+    emscripten_json: Option<String>,
+    /// Whether the module exports the runtime of AssemblyScript, see
+    /// [`assemblyscript`]
+    assemblyscript: bool,
+    /// Module of the bindings of a wasm-bindgen module, see [`bindgen`]
+    bindings_module: Option<String>,
+    /// Toolchain of a module built by Go, see [`tinygo`]
+    go_toolchain: Option<tinygo::Toolchain>,
+    /// Modules of wasi-http and wasi-io the loader provides, see [`wasi_http`]
+    wasi_http_modules: Vec<String>,
+    /// Whether the module imports WASI preview 1, see [`wasi`]
+    wasi: bool,
+    /// Bindings of the WIT world the module implements, as JSON, see
+    /// [`component`]
+    component_json: Option<String>,
+    /// String encoding the module was built for, see
+    /// [`strings::detect_encoding`]
+    string_encoding: options::StringEncoding,
+    /// The module without the optional passes, see [`fallback`]
+    fallback: Option<fallback::Fallback>,
+}
+
+impl ModuleMetadata {
+    fn new(source: &str, wasm_binary: &[u8]) -> ModuleMetadata {
+        ModuleMetadata {
+            field_names_json: names::field_names_json(wasm_binary),
+            required_features_json: serde_json::to_string(&capabilities::required_features(
+                wasm_binary,
+            ))
+            .unwrap_or_default(),
+            imports_json: imports::imports_json(wasm_binary),
+            export_aliases_json: aliases::aliases_json(source, wasm_binary),
+            locals_json: locals::locals_json(wasm_binary),
+            tables_json: tables::tables_json(wasm_binary),
+            size_report_json: sizes::size_report_json(wasm_binary),
+            shared_memory: capabilities::uses_shared_memory(wasm_binary),
+            emscripten_json: emscripten::loader_json(wasm_binary),
+            assemblyscript: assemblyscript::is_assemblyscript(wasm_binary),
+            bindings_module: bindgen::bindings_module(wasm_binary),
+            go_toolchain: tinygo::toolchain(wasm_binary),
+            wasi_http_modules: wasi_http::shimmed_modules(wasm_binary),
+            wasi: wasi::imports_wasi(wasm_binary),
+            component_json: component::loader_json(wasm_binary),
+            string_encoding: strings::detect_encoding(wasm_binary),
+            fallback: None,
+        }
+    }
+}
+
+/// A compiled module in the cache
+#[derive(MallocSizeOf)]
+struct CacheEntry {
+    binary: Vec<u8>,
+    /// Cached so that cache hits skip re-parsing the binary
+    metadata: ModuleMetadata,
+    /// Script the module was first compiled for, used in memory reports
+    filename: String,
+    /// When the module was last compiled or found, see [`memory::next_use`]
+    last_used: AtomicUsize,
+    /// Algorithm of the key, see [`hash`]
+    hash_algorithm: hash::Algorithm,
+}
+
+/// Simple in-memory cache for compiled WASM
+/// Maps hash(source_code) -> compiled binary
+fn get_cache() -> &'static RwLock<HashMap<ContentHash, CacheEntry>> {
+    static CACHE: OnceLock<RwLock<HashMap<ContentHash, CacheEntry>>> = OnceLock::new();
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Cache keys of sources by their HTTP validator, see
+/// [`CompileOptions::validator`]
+fn get_validators() -> &'static RwLock<HashMap<String, ContentHash>> {
+    static VALIDATORS: OnceLock<RwLock<HashMap<String, ContentHash>>> = OnceLock::new();
+    VALIDATORS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Compile WAT source code to WASM binary, then encode as base64 data URL
+///
+/// # Arguments
+/// * `source` - The WAT (WebAssembly Text) source code
+/// * `filename` - The name of the file (for error reporting)
+/// * `callback` - Optional JavaScript code to run after WASM loads (wrapped in wasmloaded event)
+/// * `options` - Per-script options, usually read from the script element's `data-*` attributes
+///
+/// # Returns
+/// JavaScript code that loads the WASM module and exports its functions
+pub fn compile_wat_to_js(
+    source: &str,
+    filename: &str,
+    callback: Option<&str>,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    compile_bytes_to_js(source.as_bytes(), filename, callback, options)
+}
+
+/// [`compile_wat_to_js`] for a source given as bytes: UTF-8 WAT text, or a
+/// binary module, which need not be valid UTF-8
+pub fn compile_bytes_to_js(
+    source: &[u8],
+    filename: &str,
+    callback: Option<&str>,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    log::info!(
+        target: LOG_TARGET, phase = "compile", filename, bytes = source.len();
+        "Compiling {} ({} bytes)", filename, source.len()
+    );
+
+    let source = &payload(source, filename, options)?;
+    // A spec test runs its modules rather than loading one, see [`wast`]
+    if let Some(text) = std::str::from_utf8(source)
+        .ok()
+        .filter(|text| wast::is_wast(text))
+    {
+        return wast::compile_wast_to_js(text, filename, options);
+    }
+    let (wasm_binary, metadata) = compile_cached(source, filename, options)?;
+    let registered = options
+        .register
+        .as_ref()
+        .map(|name| (name, wasm_binary.clone()));
+    let glue = timeline::phase("glue", || {
+        generate_glue(source, wasm_binary, &metadata, filename, callback, options)
+    })?;
+    if let Some((name, binary)) = registered {
+        registry::register(name, &binary, &metadata);
+    }
+    Ok(glue)
+}
+
+/// The source a script body holds, see [`sniff`]
+fn payload<'a>(
+    source: &'a [u8],
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<Cow<'a, [u8]>, CompileError> {
+    sniff::payload(source, options.base64)
+        .map_err(|e| CompileError::ParseError(format!("in {}: {}", filename, e)))
+}
+
+/// The binary module compiled from `source` and its metadata, from the cache
+/// if it holds them
+fn compile_cached(
+    source: &[u8],
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<(Vec<u8>, ModuleMetadata), CompileError> {
+    let start = CrossProcessInstant::now();
+    // A source revalidated by HTTP is not hashed again
+    let validated = options
+        .validator
+        .as_ref()
+        .and_then(|validator| get_validators().read().get(validator).copied());
+    let cache_key = validated.unwrap_or_else(|| ContentHash::of(source));
+    if let (Some(validator), None) = (&options.validator, validated) {
+        let mut validators = get_validators().write();
+        if validators.len() > 100 {
+            validators.clear();
+        }
+        validators.insert(validator.clone(), cache_key);
+    }
+    // The same source compiles to another module without the standard library
+    let cache_key = if options.no_stdlib {
+        cache_key.with(b"no-stdlib")
+    } else {
+        cache_key
+    };
+    // and to other ones shaken down to the exports to keep or with small
+    // functions inlined
+    let cache_key = match &options.keep {
+        Some(keep) => cache_key.with(format!("keep:{}", keep.join(",")).as_bytes()),
+        None => cache_key,
+    };
+    let cache_key = if options.inline {
+        cache_key.with(b"inline")
+    } else {
+        cache_key
+    };
+    // Check cache first - must drop read lock before attempting write
+    let lookup = || {
+        let cache = get_cache().read();
+        cache.get(&cache_key).map(|entry| {
+            entry.last_used.store(memory::next_use(), Ordering::Relaxed);
+            (entry.binary.clone(), entry.metadata.clone())
+        })
+    };
+    let mut cached = lookup();
+    // Wait for another script thread compiling the same source, then
+    // look again; held until the module is in the cache
+    let _claim = cached.is_none().then(|| {
+        let claim = inflight::Claim::new(cache_key);
+        cached = lookup();
+        claim
+    });
+
+    if let Some(cached) = cached {
+        let end = CrossProcessInstant::now();
+        telemetry::record_cache_hit(start, end);
+        timeline::record("cache hit", start, end);
+        log::info!(
+            target: LOG_TARGET,
+            phase = "cache", filename, hash:% = cache_key, bytes = cached.0.len(),
+            duration_ms = telemetry::millis(start, end);
+            "Cache hit for {} ({})", filename, cache_key
+        );
+        Ok(cached)
+    } else {
+        // Compile WAT to WASM binary
+        let (binary, fallback) = compile_module(
+            source,
+            filename,
+            options.effective_features(),
+            !options.no_stdlib,
+            options.keep.as_deref(),
+            options.inline,
+        )
+        .inspect_err(telemetry::record_failure)?;
+        let end = CrossProcessInstant::now();
+        telemetry::record_compile(start, end);
+        log::info!(
+            target: LOG_TARGET,
+            phase = "compile", filename, hash:% = cache_key, bytes = binary.len(),
+            duration_ms = telemetry::millis(start, end);
+            "Compiled {} to {} bytes of WASM", filename, binary.len()
+        );
+        let metadata = ModuleMetadata {
+            fallback,
+            ..ModuleMetadata::new(&String::from_utf8_lossy(source), &binary)
+        };
+
+        // Store in cache (read lock is already dropped at this point)
+        {
+            let mut cache = get_cache().write();
+            let entry = CacheEntry {
+                binary: binary.clone(),
+                metadata: metadata.clone(),
+                filename: filename.to_string(),
+                last_used: AtomicUsize::new(memory::next_use()),
+                hash_algorithm: ContentHash::ALGORITHM,
+            };
+            // Make room for it: WASM modules can be large
+            memory::evict(
+                &mut cache,
+                memory::CACHE_ENTRIES - 1,
+                memory::CACHE_BYTES.saturating_sub(memory::entry_bytes(&entry)),
+            );
+            cache.insert(cache_key, entry);
+        }
+
+        Ok((binary, metadata))
+    }
+}
+
+/// Compile WAT source to a WASM binary, skipping the cache and the
+/// JavaScript glue; used to benchmark the compiler itself
+pub fn compile_bench(source: &str) -> Result<Vec<u8>, CompileError> {
+    compile_wat_internal(source, "bench.wat", options::all_features())
+}
+
+/// Print a binary module as WAT text, to compare what the passes over a
+/// module made of it with what was expected
+pub fn print_module(wasm: &[u8]) -> Result<String, CompileError> {
+    wasmprinter::print_bytes(wasm).map_err(|e| CompileError::ValidationError(e.to_string()))
+}
+
+/// Generate the JavaScript that loads a compiled module
+/// Options are applied here rather than before caching, they are a per-script choice
+fn generate_glue(
+    source: &[u8],
+    mut wasm_binary: Vec<u8>,
+    metadata: &ModuleMetadata,
+    filename: &str,
+    callback: Option<&str>,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    // Validate against the proposals the script may use rather than whatever
+    // the wat crate accepts
+    let features = options.effective_features();
+    let validation_start = CrossProcessInstant::now();
+    wasmparser::Validator::new_with_features(features)
+        .validate_all(&wasm_binary)
+        .map_err(|e| {
+            let limit = if options.features.is_some() {
+                "data-features"
+            } else {
+                "enabled wasm features"
+            };
+            // Say which function and local the error is in, by name if the
+            // name section has them
+            let location = locals::describe_offset(&wasm_binary, e.offset())
+                .map(|location| format!(", {}", location))
+                .unwrap_or_default();
+            CompileError::ValidationError(format!("in {}{}: {} ({})", filename, location, e, limit))
+        })
+        .inspect_err(telemetry::record_failure)?;
+    timeline::record("validation", validation_start, CrossProcessInstant::now());
+
+    // data-coverage: counters are added before the name section may be
+    // stripped, which names the functions in the report
+    let coverage = differential::run(filename, "coverage", &mut wasm_binary, features, |binary| {
+        coverage::instrument(binary, options.coverage)
+            .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+    })
+    .inspect_err(telemetry::record_failure)?
+    .map(|layout| coverage::loader_js(&layout))
+    .unwrap_or_default();
+
+    // data-trace: entry calls into the loader, which logs them
+    let trace = differential::run(filename, "trace", &mut wasm_binary, features, |binary| {
+        trace::instrument(binary, options.trace)
+            .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+    })
+    .inspect_err(telemetry::record_failure)?
+    .map(|layout| trace::loader_js(&layout))
+    .unwrap_or_default();
+
+    // data-breakpoints: entry calls routed to the page's handler
+    let breakpoints = differential::run(
+        filename,
+        "breakpoints",
+        &mut wasm_binary,
+        features,
+        |binary| {
+            breakpoints::instrument(binary, options.breakpoints)
+                .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+        },
+    )
+    .inspect_err(telemetry::record_failure)?
+    .map(|names| breakpoints::loader_js(&names))
+    .unwrap_or_default();
+
+    // data-profile: entry and exit calls timed by the loader
+    let profile = differential::run(filename, "profile", &mut wasm_binary, features, |binary| {
+        profile::instrument(binary, options.profile)
+            .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+    })
+    .inspect_err(telemetry::record_failure)?
+    .map(|names| profile::loader_js(&names))
+    .unwrap_or_default();
+
+    // Arrays of structs get accessors for their elements, which the loader
+    // wraps with the names of the struct type; they are read before the name
+    // section may be stripped
+    let struct_arrays = arrays::add_accessors(&mut wasm_binary)
+        .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+        .inspect_err(telemetry::record_failure)?;
+    let struct_arrays_json = arrays::loader_json(&struct_arrays, &wasm_binary);
+
+    // Structs get accessors for their fields, by type and field index
+    structs::add_accessors(&mut wasm_binary)
+        .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+        .inspect_err(telemetry::record_failure)?;
+    let struct_types_json = structs::loader_json(&wasm_binary);
+
+    // Strings get accessors for their code units, which the loader decodes
+    // the strings exports return with
+    let string_types = strings::add_accessors(&mut wasm_binary)
+        .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+        .inspect_err(telemetry::record_failure)?;
+    let string_types_json = strings::loader_json(&string_types);
+    // Each module gets the converters of its own encoding, so modules of
+    // different toolchains can share a page
+    let string_encoding = options.strings.unwrap_or(metadata.string_encoding);
+    if matches!(
+        string_encoding,
+        options::StringEncoding::Linear | options::StringEncoding::Canonical
+    ) {
+        strings::export_memory(&mut wasm_binary)
+            .map_err(|e| CompileError::InstrumentationError(format!("in {}: {}", filename, e)))
+            .inspect_err(telemetry::record_failure)?;
+    }
+    let export_results_json = results::loader_json(&wasm_binary, &struct_arrays, &string_types);
+    let string_params_json = results::string_params_json(&wasm_binary, &string_types);
+    let struct_strings_json = structs::string_fields_json(&wasm_binary, &string_types);
+
+    // The page-level helpers of strings, structs and bindings are installed
+    // by the modules that have them, unless data-exports leaves them out
+    let installs_helper = |name: &str| exports::installs_helper(options.exports.as_deref(), name);
+    let string_helpers =
+        if strings::converts(&string_types, string_encoding) || options.strings.is_some() {
+            strings::page_helpers_js(installs_helper)
+        } else {
+            String::new()
+        };
+    let struct_helpers = if structs::declares_structs(&wasm_binary) {
+        structs::page_helpers_js(installs_helper)
+    } else {
+        String::new()
+    };
+    let bind_helpers = if bind::exports_functions(&wasm_binary) {
+        bind::page_helpers_js(installs_helper)
+    } else {
+        ""
+    };
+
+    // Modules importing the JavaScript string builtins get them from the
+    // engine, or else imports doing the same, see [`strings`]
+    let uses_js_strings = imports::declared_imports(&wasm_binary)
+        .iter()
+        .any(|(module, _, _)| module == strings::JS_STRING_MODULE);
+    let (builtin_imports, compile_options) = if uses_js_strings {
+        (strings::builtins_js(), "{ builtins: ['js-string'] }")
+    } else {
+        ("{}".to_string(), "{}")
+    };
+
+    if options.optimize && !options.debug {
+        differential::run(
+            filename,
+            "custom section stripping",
+            &mut wasm_binary,
+            features,
+            |binary| {
+                strip_custom_sections(binary);
+                Ok(())
+            },
+        )?;
+    }
+    let deferred = differential::run(
+        filename,
+        "start policy",
+        &mut wasm_binary,
+        features,
+        |binary| Ok(start::apply_start_policy(binary, options.start)),
+    )?;
+    let deferred_start = if deferred {
+        format!(
+            r#"
+                // Deferred start function (data-start="defer")
+                try {{
+                    result.instance.exports.{}();
+                }} catch (e) {{
+                    console.error('WASM start function error:', e);
+                }}"#,
+            start::DEFERRED_START_EXPORT
+        )
+    } else {
+        String::new()
+    };
+
+    // The engine may reject what an optional pass produced: retry once without
+    // them, unless instrumentation refers to the instrumented module
+    let fallback = match &metadata.fallback {
+        Some(fallback) if !options.instruments() => {
+            let mut binary = fallback.binary.clone();
+            if options.optimize && !options.debug {
+                strip_custom_sections(&mut binary);
+            }
+            start::apply_start_policy(&mut binary, options.start);
+            if let Some(limit) = options.memory_budget {
+                budget::cap_memories(&mut binary, limit)
+                    .and_then(|_| budget::guard_growth(&mut binary))
+                    .map_err(|e| {
+                        CompileError::InstrumentationError(format!("in {}: {}", filename, e))
+                    })?;
+            }
+            format!(
+                r#"
+            .catch(function(e) {{
+                if (!(e instanceof WebAssembly.CompileError)) {{
+                    throw e;
+                }}
+                const skippedPasses = {};
+                const message = 'engine rejected the module (' + e.message + '), retrying without the ' +
+                    skippedPasses.join(', ');
+                console.warn('WASM: ' + message);
+                window.dispatchEvent(new CustomEvent('wasmwarning', {{
+                    detail: {{ filename: wasmFilename, message: message, skipped: skippedPasses }}
+                }}));
+                const fallbackBytes = decodeWasmBytes({});
+                window.__wasmModules[wasmFilename].bytes = fallbackBytes;
+                return WebAssembly.instantiate(fallbackBytes, importObject, compileOptions);
+            }})"#,
+                embed::script_json(&fallback.skipped),
+                embed::chunked_literals(&binary, embed::CHUNK_SIZE)
+            )
+        },
+        _ => String::new(),
+    };
+
+    // Modules built by Emscripten get a minimal version of its runtime, see
+    // [`emscripten`]
+    let (emscripten_imports, emscripten) = match &metadata.emscripten_json {
+        Some(runtime) => emscripten::loader_js(runtime),
+        None => (String::new(), String::new()),
+    };
+
+    // Modules exporting the runtime of AssemblyScript get the helpers of its
+    // loader, see [`assemblyscript`]
+    let (assemblyscript_imports, assemblyscript) = if metadata.assemblyscript {
+        assemblyscript::loader_js()
+    } else {
+        ("", "null")
+    };
+
+    // Exports go on window, or on a namespace object with data-namespace
+    let export_target = match &options.namespace {
+        Some(namespace) => {
+            let key = embed::script_json(namespace);
+            format!("(window[{0}] = window[{0}] || {{}})", key)
+        },
+        None => "window".to_string(),
+    };
+
+    // Modules built by wasm-bindgen are loaded through the JavaScript it
+    // generated, see [`bindgen`]
+    let wasm_bindgen = match (&metadata.bindings_module, &options.wasm_bindgen) {
+        (Some(module), Some(shim)) => bindgen::loader_js(module, shim, &export_target),
+        (Some(module), None) => bindgen::missing_bindings_js(module, filename),
+        (None, _) => String::new(),
+    };
+
+    // Modules sending requests with wasi-http get its interfaces on top of
+    // fetch, see [`wasi_http`]
+    let (wasi_http_imports, wasi_http) = if metadata.wasi_http_modules.is_empty() {
+        (String::new(), "")
+    } else {
+        wasi_http::loader_js(&metadata.wasi_http_modules)
+    };
+
+    // Modules importing WASI preview 1 get its clocks, randomness and files,
+    // see [`wasi`]
+    let (wasi_imports, wasi_start) = if metadata.wasi {
+        wasi::loader_js(filename, options)
+    } else {
+        (String::new(), "")
+    };
+
+    // Modules implementing a WIT world get its functions with JavaScript
+    // values, lifted and lowered with the canonical ABI, see [`component`]
+    let (component_imports, component_exports) = match &metadata.component_json {
+        Some(bindings) => component::loader_js(bindings, metadata.go_toolchain.is_none()),
+        None => (String::new(), String::new()),
+    };
+    // The page's functions of the world are looked up in its bindings only
+    let builtin_imports = if metadata.component_json.is_some() {
+        format!("Object.assign({}, componentImports)", builtin_imports)
+    } else {
+        builtin_imports
+    };
+
+    // Files stored in the origin private file system are loaded first
+    let instantiate = if metadata.wasi && !options.wasi_preopens.is_empty() {
+        "wasiFilesystem.loaded.then(() => WebAssembly.instantiate(wasmBytes, importObject, compileOptions))"
+    } else {
+        "WebAssembly.instantiate(wasmBytes, importObject, compileOptions)"
+    };
+
+    // Modules importing the runtime loader get its functions, see [`loader`]
+    let uses_loader = loader::imports_loader(&wasm_binary);
+    let loader_imports = if uses_loader {
+        loader::loader_js()
+    } else {
+        String::new()
+    };
+
+    // Runtime imports for those the page does not provide, looked up in turn:
+    // the toolchain's, then those of the system interfaces, then the loader's
+    let fallback_imports = [
+        (metadata.assemblyscript, "assemblyScriptImports"),
+        (metadata.emscripten_json.is_some(), "emscriptenImports"),
+        (
+            metadata.go_toolchain == Some(tinygo::Toolchain::TinyGo),
+            "goImports",
+        ),
+        (!metadata.wasi_http_modules.is_empty(), "wasiHttpImports"),
+        (metadata.wasi, "wasiImports"),
+        (uses_loader, "loaderImports"),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, imports)| *imports)
+    .collect::<Vec<_>>();
+    // Those and the page's functions of a world act on the instance they were
+    // resolved for, so other instances of the module need their own
+    let instance_imports = fallback_imports
+        .iter()
+        .copied()
+        .chain(
+            metadata
+                .component_json
+                .is_some()
+                .then_some("componentImports"),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+    let fallback_imports = fallback_imports.join(", ");
+
+    // Modules built by TinyGo get the imports of its wasm_exec.js and are
+    // started like it starts them; those of Go are reported, see [`tinygo`]
+    let (go_imports, go_start) = if metadata.go_toolchain == Some(tinygo::Toolchain::TinyGo) {
+        tinygo::loader_js()
+    } else {
+        ("", "")
+    };
+    let toolchain_loader = if metadata.go_toolchain == Some(tinygo::Toolchain::Go) {
+        tinygo::unsupported_js(filename)
+    } else {
+        wasm_bindgen
+    };
+
+    // data-debug: show the lowered source and the module's interface
+    let (debug_source, debug_exports) = if options.debug {
+        let lowered = if source.starts_with(b"\0asm") {
+            String::from("(binary module)")
+        } else {
+            preprocess_wat(
+                &String::from_utf8_lossy(source),
+                options.effective_features(),
+                !options.no_stdlib,
+            )?
+            .into_owned()
+        };
+        (
+            format!(
+                "\n        console.debug('WASM: Lowered source of ' + {} + ':\\n' + {});",
+                embed::script_json(&filename),
+                embed::script_json(&lowered)
+            ),
+            "\n                console.debug('WASM: Exports', WebAssembly.Module.exports(result.module));".to_string(),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+
+    // data-hot-state: take over the state of the module previously loaded
+    // under the same filename, as data-lifecycle="reload" does too
+    let hot_state = if options.hot_state || options.lifecycle == options::Lifecycle::Reload {
+        format!(
+            r#"
+
+                // State-preserving hot reload (data-hot-state): mutable globals and the
+                // fields of GC structs held in globals are copied over, by name, from
+                // the module last loaded under this filename
+                window.__wasmHotState = window.__wasmHotState || {{}};
+                wasmLifecycle.kept.push('__wasmHotState');
+                const hotPrevious = window.__wasmHotState[wasmFilename];
+                window.__wasmHotState[wasmFilename] = {{
+                    exports: result.instance.exports,
+                    fieldNames: {}
+                }};
+                if (hotPrevious) {{
+                    const hotFields = new Set();
+                    for (const type in hotPrevious.fieldNames) {{
+                        for (const field of hotPrevious.fieldNames[type].fields) {{
+                            hotFields.add(field);
+                        }}
+                    }}
+                    let restored = 0;
+                    for (const name in hotPrevious.exports) {{
+                        const oldGlobal = hotPrevious.exports[name];
+                        const newGlobal = result.instance.exports[name];
+                        if (!(oldGlobal instanceof WebAssembly.Global) || !(newGlobal instanceof WebAssembly.Global)) {{
+                            continue;
+                        }}
+                        const oldValue = oldGlobal.value;
+                        const newValue = newGlobal.value;
+                        if (oldValue && typeof oldValue === 'object') {{
+                            // Struct fields go through the get_/set_ accessors of both modules
+                            if (!newValue || typeof newValue !== 'object') {{
+                                continue;
+                            }}
+                            for (const field of hotFields) {{
+                                const getter = hotPrevious.exports['get_' + field];
+                                const setter = result.instance.exports['set_' + field];
+                                if (typeof getter !== 'function' || typeof setter !== 'function') {{
+                                    continue;
+                                }}
+                                try {{
+                                    setter(newValue, getter(oldValue));
+                                    restored++;
+                                }} catch (e) {{
+                                    // Not a field of this struct, or its type changed
+                                }}
+                            }}
+                        }} else {{
+                            try {{
+                                newGlobal.value = oldValue;
+                                restored++;
+                            }} catch (e) {{
+                                // Immutable, or its type changed: the new value is kept
+                            }}
+                        }}
+                    }}
+                    console.log('WASM: Hot reload of ' + wasmFilename + ' restored ' + restored + ' values');
+                }}"#,
+            embed::script_safe(&metadata.field_names_json)
+        )
+    } else {
+        String::new()
+    };
+
+    // data-size-report: where the bytes of the module go, for devtools
+    let size_report = if options.size_report {
+        sizes::loader_js(&metadata.size_report_json)
+    } else {
+        String::new()
+    };
+
+    // Shared memory, which threads are spawned with, needs the permission of
+    // the origin; the page is told which one it lacks
+    let threads_permission = if metadata.shared_memory && options.threads_denied {
+        capabilities::threads_denied_js()
+    } else {
+        ""
+    };
+
+    // Shared memory: engines hide it from documents that are not cross-origin
+    // isolated, which would otherwise surface as a missing threads capability
+    // or an instantiation failure. The document's headers tell which of the
+    // isolation headers are missing
+    let isolation_check = if metadata.shared_memory {
+        capabilities::isolation_check_js()
+    } else {
+        ""
+    };
+
+    // Memory budget of the page: the module's own memories cannot grow past
+    // what the other modules leave of it, and it is not instantiated if what
+    // it declares does not fit beside the memory of the modules loaded before
+    let (memory_budget, memory_held, memory_release) = if let Some(limit) = options.memory_budget {
+        if differential::run(
+            filename,
+            "memory budget",
+            &mut wasm_binary,
+            features,
+            |binary| {
+                budget::cap_memories(binary, limit).map_err(|e| {
+                    CompileError::InstrumentationError(format!("in {}: {}", filename, e))
+                })
+            },
+        )? {
+            log::info!(
+                target: LOG_TARGET, phase = "memory budget", filename, bytes = limit;
+                "Capped the memories of {} at the page's budget", filename
+            );
+        }
+        differential::run(
+            filename,
+            "memory growth",
+            &mut wasm_binary,
+            features,
+            |binary| {
+                budget::guard_growth(binary).map_err(|e| {
+                    CompileError::InstrumentationError(format!("in {}: {}", filename, e))
+                })
+            },
+        )?;
+        budget::module_js(&wasm_binary, limit)
+    } else {
+        (String::new(), String::new(), "")
+    };
+
+    // Memories and tables shared with the other modules of the page, see
+    // [`shared`]; they are created once the module passed the checks above,
+    // so their imports are left to then
+    let (shared_imports, deferred_imports) = match shared::imported(&wasm_binary) {
+        Some(imported) => shared::loader_js(
+            &imported,
+            &options.shared_memories,
+            &options.shared_tables,
+            options.memory_budget.is_some(),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    // Embed the bytes directly (no base64 encoding needed!), packed one byte
+    // per latin1 character and split into chunks for large modules
+    let byte_chunks = embed::chunked_literals(&wasm_binary, embed::CHUNK_SIZE);
+
+    // Names the exports are installed under (data-name-style) and those
+    // installed at all (data-exports)
+    let export_aliases_json = aliases::styled_aliases_json(
+        &metadata.export_aliases_json,
+        &wasm_binary,
+        options.name_style,
+    );
+    let export_filter_json = exports::installed_json(
+        options.exports.as_deref(),
+        &export_aliases_json,
+        &wasm_binary,
+    );
+
+    // Generate JavaScript that uses direct byte array
+    // This avoids base64/atob issues and works perfectly in Servo
+    let mut js_code = format!(
+        r#"
+(function wasmLoad() {{
+    try {{
+        console.log('WASM: Starting module load');
+        // Declared first, the import resolution records by filename too
+        const wasmFilename = {filename_json};
+
+        // WASM module bytes, one latin1 character per byte
+        const wasmByteChunks = {};
+        const decodeWasmBytes = function(chunks) {{
+            const bytes = new Uint8Array(chunks.reduce(function(length, chunk) {{
+                return length + chunk.length;
+            }}, 0));
+            let offset = 0;
+            for (const chunk of chunks) {{
+                for (let i = 0; i < chunk.length; i++) {{
+                    bytes[offset + i] = chunk.charCodeAt(i);
+                }}
+                offset += chunk.length;
+            }}
+            return bytes;
+        }};
+        const wasmBytes = decodeWasmBytes(wasmByteChunks);
+
+        console.log('WASM: Instantiating module (' + wasmBytes.length + ' bytes)...');{debug_source}
+
+        // Resolve the declared imports: module.name if window[module] is an
+        // object, otherwise the global name (so "env" imports find globals);
+        // modules of builtins the loader provides are looked up there only,
+        // and the runtime imports it provides are used for those not found{emscripten_imports}{assemblyscript_imports}{go_imports}{wasi_http_imports}{wasi_imports}{component_imports}{loader_imports}
+        const wasmImports = {imports_json};
+        const builtinImports = {builtin_imports};
+        // Modules of builtins the loader provides once the module passed its checks
+        const deferredImports = [{deferred_imports}];
+        const fallbackImports = [{fallback_imports}];
+        const instanceImports = [{instance_imports}];
+        const runtimeImports = [];
+        const compileOptions = {compile_options};
+        const importObject = {{}};
+        const unresolvedImports = [];
+        // data-stub-imports: functions the page does not provide are stubs
+        // that throw once called, so the module loads without them
+        const stubImports = {stub_imports};
+        const stubbedImports = [];
+        // data-import-map: imports bound to expressions of the page, which
+        // take precedence over window and the runtime imports
+        const importMap = {import_map_json};
+        const mappedImport = function(key) {{
+            try {{
+                return new Function('return (' + importMap[key] + ');')();
+            }} catch (e) {{
+                console.error('WASM: data-import-map expression for ' + key + ' failed:', e);
+                return undefined;
+            }}
+        }};
+        for (const [module, name, kind] of wasmImports) {{
+            if (deferredImports.includes(module)) {{
+                continue;
+            }}
+            const builtin = Object.hasOwn(builtinImports, module);
+            const scope = builtin ? builtinImports[module] : window[module];
+            const mapped = Object.hasOwn(importMap, module + '.' + name);
+            let value = mapped ? mappedImport(module + '.' + name) : undefined;
+            if (!mapped && scope !== null && (typeof scope === 'object' || typeof scope === 'function')) {{
+                value = scope[name];
+            }}
+            if (value === undefined && !builtin && !mapped) {{
+                value = window[name];
+            }}
+            for (const fallback of fallbackImports) {{
+                if (value === undefined && !mapped && Object.hasOwn(fallback, module)) {{
+                    value = fallback[module][name];
+                }}
+            }}
+            if (value === undefined && stubImports && kind === 'function') {{
+                value = function() {{
+                    throw new Error('WASM: ' + wasmFilename + ' called its import ' + module + '.' + name +
+                        ', which the page does not provide; define ' +
+                        (builtin ? 'it in the builtins' : 'window.' + module + '.' + name + ' or window.' + name));
+                }};
+                stubbedImports.push(module + '.' + name);
+            }}
+            if (value === undefined) {{
+                unresolvedImports.push(module + '.' + name + ' (' + kind + ')');
+                continue;
+            }}
+            if (instanceImports.some(scope => Object.hasOwn(scope, module) && scope[module][name] === value)) {{
+                runtimeImports.push(module + '.' + name);
+            }}
+            (importObject[module] = importObject[module] || {{}})[name] = value;
+        }}
+
+        console.log('WASM: Resolved', wasmImports.length - unresolvedImports.length, 'of', wasmImports.length, 'imports');
+        if (unresolvedImports.length > 0) {{
+            console.warn('WASM: Unresolved imports:', unresolvedImports.join(', '));
+        }}
+        if (stubbedImports.length > 0) {{
+            console.warn('WASM: Imports stubbed to throw once called:', stubbedImports.join(', '));
+        }}
+
+        // Probe the proposals the engine supports and compare with what the module needs
+        // Errors the page can act on say so in details, e.g. the permission missing
+        const dispatchWasmError = function(message, details) {{
+            window.dispatchEvent(new CustomEvent('wasmerror', {{
+                detail: Object.assign({{ filename: wasmFilename, message: message }}, details)
+            }}));
+        }};
+
+        // Function and local names by function index, for devtools
+        window.__wasmLocals = window.__wasmLocals || {{}};
+        window.__wasmLocals[wasmFilename] = {locals_json};
+
+        // Tables, element segments and the slots of functions placed in tables
+        const wasmTables = {tables_json};
+        window.__wasmTables = window.__wasmTables || {{}};
+        window.__wasmTables[wasmFilename] = wasmTables;{size_report}
+
+        // The bytes as instantiated, after the injection passes, for download
+        window.__wasmModules = window.__wasmModules || {{}};
+        window.__wasmModules[wasmFilename] = {{ bytes: wasmBytes }};{threads_permission}{isolation_check}
+
+        // Teardown (dispose): the exports installed on target are removed, those the
+        // page replaced since kept, as are the loader's records of the module, so
+        // its instance, memories and tables can be collected; a module disposed
+        // already, or loaded again under the same filename since, is left alone
+        const wasmEntry = window.__wasmModules[wasmFilename];
+        const disposeWasm = function(target, installed, exports) {{
+            if (window.__wasmModules[wasmFilename] !== wasmEntry) {{
+                return false;
+            }}
+            for (const [target, type, listener] of wasmLifecycle.listeners.splice(0)) {{
+                target.removeEventListener(type, listener);
+            }}
+            for (const name in installed) {{
+                if (target[name] === installed[name]) {{
+                    delete target[name];
+                }}
+            }}
+            if (exports && window._wasmExports === exports) {{
+                delete window._wasmExports;
+            }}
+            // The records are kept by filename in the window.__wasm* objects
+            const registries = Object.keys(window)
+                .filter(key => key.startsWith('__wasm') && !(wasmLifecycle.reloading && wasmLifecycle.kept.includes(key)))
+                .map(key => window[key])
+                .concat([(window.__wasmCapabilities || {{}}).modules]);
+            for (const registry of registries) {{
+                if (registry && typeof registry === 'object' && Object.hasOwn(registry, wasmFilename)) {{
+                    delete registry[wasmFilename];
+                }}
+            }}
+            console.log('WASM: Disposed ' + wasmFilename);
+            window.dispatchEvent(new CustomEvent('wasmunloaded', {{
+                detail: {{ filename: wasmFilename }}
+            }}));
+            return true;
+        }};
+
+        // Page lifecycle (data-lifecycle): the loader's timers wait while the page is
+        // hidden, frozen or in the back/forward cache, unless the policy is keep;
+        // dispose tears the module down as the page is frozen or left, and reload
+        // loads it again, with the state the kept registries hold, once it is back
+        const wasmLifecycle = {{ policy: '{lifecycle}', suspended: false, pending: [], listeners: [], kept: [], reloading: false }};
+        const suspendable = function(callback) {{
+            return function(...args) {{
+                if (wasmLifecycle.suspended) {{
+                    wasmLifecycle.pending.push(() => callback.apply(this, args));
+                    return;
+                }}
+                return callback.apply(this, args);
+            }};
+        }};
+        const watchLifecycle = function() {{
+            if (wasmLifecycle.policy === 'keep') {{
+                return;
+            }}
+            const setSuspended = function(suspended) {{
+                if (wasmLifecycle.suspended === suspended) {{
+                    return;
+                }}
+                wasmLifecycle.suspended = suspended;
+                window.dispatchEvent(new CustomEvent(suspended ? 'wasmsuspended' : 'wasmresumed', {{
+                    detail: {{ filename: wasmFilename }}
+                }}));
+                while (!wasmLifecycle.suspended && wasmLifecycle.pending.length > 0) {{
+                    wasmLifecycle.pending.shift()();
+                }}
+            }};
+            const onLifecycle = function(event) {{
+                const left = event.type === 'freeze' || event.type === 'pagehide';
+                if (left && wasmLifecycle.policy !== 'suspend') {{
+                    // Only a page that may be shown again is reloaded
+                    const reload = wasmLifecycle.policy === 'reload' && (event.type === 'freeze' || event.persisted);
+                    wasmLifecycle.reloading = reload;
+                    if (wasmEntry.dispose() && reload) {{
+                        let reloaded = false;
+                        const onShown = function() {{
+                            if (!reloaded) {{
+                                reloaded = true;
+                                console.log('WASM: Reloading ' + wasmFilename);
+                                wasmLoad();
+                            }}
+                        }};
+                        document.addEventListener('resume', onShown, {{ once: true }});
+                        window.addEventListener('pageshow', onShown, {{ once: true }});
+                    }}
+                    return;
+                }}
+                const hidden = left || (event.type === 'visibilitychange' && document.visibilityState === 'hidden');
+                setSuspended(hidden);
+            }};
+            for (const [target, type] of [[document, 'freeze'], [document, 'resume'], [document, 'visibilitychange'],
+                [window, 'pagehide'], [window, 'pageshow']]) {{
+                target.addEventListener(type, onLifecycle);
+                wasmLifecycle.listeners.push([target, type, onLifecycle]);
+            }}
+        }};
+        const wasmProbes = {probes_json};
+        const requiredFeatures = {required_features};
+        const enabledFeatures = {enabled_features};
+        window.__wasmCapabilities = window.__wasmCapabilities || {{ supported: {{}}, modules: {{}} }};
+        for (const feature in wasmProbes) {{
+            window.__wasmCapabilities.supported[feature] = enabledFeatures.includes(feature) &&
+                WebAssembly.validate(new Uint8Array(wasmProbes[feature][1]));
+        }}
+        const missingFeatures = requiredFeatures.filter(function(feature) {{
+            return !window.__wasmCapabilities.supported[feature];
+        }});
+        window.__wasmCapabilities.modules[wasmFilename] = {{ required: requiredFeatures, missing: missingFeatures }};
+        if (missingFeatures.length > 0) {{
+            const labels = missingFeatures.map(function(feature) {{
+                return wasmProbes[feature][0];
+            }});
+            const message = 'module requires wasm ' + labels.join(', ') +
+                (labels.length > 1 ? ' which are disabled' : ' which is disabled');
+            console.error('WASM: ' + message);
+            dispatchWasmError(message);
+            return;
+        }}{memory_budget}{shared_imports}{trace}{breakpoints}{profile}{toolchain_loader}
+
+        // The instantiation shows in the devtools performance view as a User
+        // Timing measure, next to the timeline markers of the compilation
+        const instantiateMark = 'wasm instantiate start ' + wasmFilename;
+        try {{
+            performance.mark(instantiateMark);
+        }} catch (e) {{}}
+
+        // Instantiate directly from byte array with imports
+        {instantiate}{fallback}
+            .then(function(result) {{
+                console.log('WASM: Module instantiated successfully');
+                try {{
+                    performance.measure('wasm instantiate ' + wasmFilename, instantiateMark);
+                }} catch (e) {{}}
+
+                // Further instances of the compiled module, with the imports of this one
+                // but those given as an object of modules like the import object; the
+                // runtime imports of the loader act on this instance and must be given
+                window.__wasmModules[wasmFilename].module = result.module;
+                // and the instance, whose exports modules loading this one at runtime call
+                window.__wasmModules[wasmFilename].instance = result.instance;
+
+                // Exported tables by index, to drive dynamic dispatch from JS
+                const liveTables = {{}};
+                const tableExports = (result.instance && result.instance.exports) || {{}};
+                for (const table of wasmTables.tables) {{
+                    const live = table.exports.map(name => tableExports[name])
+                        .find(value => value instanceof WebAssembly.Table);
+                    if (!live) {{
+                        continue;
+                    }}
+                    liveTables[table.index] = {{
+                        index: table.index,
+                        exports: table.exports,
+                        get size() {{
+                            return live.length;
+                        }},
+                        get: function(slot) {{
+                            return live.get(slot);
+                        }},
+                        call: function(slot, ...args) {{
+                            const target = live.get(slot);
+                            if (typeof target !== 'function') {{
+                                throw new TypeError('WASM: slot ' + slot + ' of table ' + table.index + ' holds no function');
+                            }}
+                            return target(...args);
+                        }}
+                    }};
+                }}
+                window.__wasmModules[wasmFilename].tables = liveTables;
+                window.__wasmModules[wasmFilename].instantiate = function(imports) {{
+                    imports = imports || {{}};
+                    const ownImports = {{}};
+                    const missing = [];
+                    for (const [module, name] of wasmImports) {{
+                        let value = Object.hasOwn(imports, module) && imports[module] ? imports[module][name] : undefined;
+                        if (value === undefined && runtimeImports.includes(module + '.' + name)) {{
+                            missing.push(module + '.' + name);
+                            continue;
+                        }}
+                        if (value === undefined && Object.hasOwn(importObject, module)) {{
+                            value = importObject[module][name];
+                        }}
+                        if (value !== undefined) {{
+                            (ownImports[module] = ownImports[module] || {{}})[name] = value;
+                        }}
+                    }}
+                    if (missing.length > 0) {{
+                        return Promise.reject(new Error('instantiate: the loader provides ' + missing.join(', ') +
+                            ' for the first instance only, pass them in imports'));
+                    }}
+                    // as are the imports the loader's passes added to the module
+                    for (const module in importObject) {{
+                        if (!wasmImports.some(([declared]) => declared === module)) {{
+                            ownImports[module] = importObject[module];
+                        }}
+                    }}
+                    return WebAssembly.instantiate(result.module, ownImports);
+                }};{memory_held}{debug_exports}{coverage}{hot_state}
+
+                // Export all WASM functions to window; installedExtras holds the raw
+                // globals and memory views installed next to them
+                const installedExports = {{}};
+                const installedExtras = {{}};
+                if (result.instance && result.instance.exports) {{
+                    // String representation of this module: utf8, utf16, linear,
+                    // canonical or js-string, chosen with data-strings or detected from the module
+                    const stringEncoding = '{string_encoding}';
+
+                    // String types, read through the accessors added to the module
+                    const stringTypes = {string_types_json};
+                    const stringTypeOfs = new WeakMap();
+
+                    // Helper to find the string type of a GC object, if it is one: its
+                    // len accessor takes the object, and throws a TypeError for others
+                    const stringTypeOf = function(obj) {{
+                        if (stringTypeOfs.has(obj)) {{
+                            return stringTypeOfs.get(obj);
+                        }}
+                        const found = stringTypes.find(function(stringType) {{
+                            try {{
+                                result.instance.exports[stringType.len](obj);
+                                return true;
+                            }} catch (e) {{
+                                return false;
+                            }}
+                        }}) || null;
+                        stringTypeOfs.set(obj, found);
+                        return found;
+                    }};
+
+                    // Longest string read through the module's own string_len and
+                    // string_get_byte without a maxLength, chosen with data-display
+                    const stringLimit = {display_string};
+                    const utf8Decoder = new TextDecoder('utf-8');
+                    const utf16Decoder = new TextDecoder('utf-16le');
+
+                    // Scratch memory strings are copied through, if the module has no
+                    // memory of its own
+                    const stringMemory = result.instance.exports['{string_memory}'];
+
+                    // Helper to view the first count code units of the scratch memory,
+                    // grown to hold them
+                    const scratchUnits = function(Units, count) {{
+                        const missing = count * Units.BYTES_PER_ELEMENT - stringMemory.buffer.byteLength;
+                        if (missing > 0) {{
+                            stringMemory.grow(Math.ceil(missing / 65536));
+                        }}
+                        return new Units(stringMemory.buffer, 0, count);
+                    }};
+
+                    // Helper to decode the first count code units of a string, copied into
+                    // a single typed array: with one call through the scratch memory if its
+                    // string type can, else one by one with get; ASCII needs no TextDecoder
+                    const decodeUnits = function(wasmStr, get, count, utf16, stringType) {{
+                        const Units = utf16 ? Uint16Array : Uint8Array;
+                        let units;
+                        if (stringType && stringType.toMemory && stringMemory) {{
+                            units = scratchUnits(Units, count);
+                            result.instance.exports[stringType.toMemory](wasmStr, 0, count);
+                        }} else {{
+                            units = new Units(count);
+                            for (let i = 0; i < count; i++) {{
+                                units[i] = get(wasmStr, i);
+                            }}
+                        }}
+                        let high = 0;
+                        for (let i = 0; i < count; i++) {{
+                            high |= units[i];
+                        }}
+                        if (high < 0x80) {{
+                            let text = '';
+                            for (let i = 0; i < count; i += 8192) {{
+                                text += String.fromCharCode.apply(null, units.subarray(i, i + 8192));
+                            }}
+                            return text;
+                        }}
+                        return (utf16 ? utf16Decoder : utf8Decoder).decode(units);
+                    }};
+
+                    // Helper to convert WASM string array (array i8, UTF-8) to JS string
+                    // Only the first maxLength elements are read if it is given
+                    const wasmStringToJs = function(wasmStr, maxLength) {{
+                        if (stringEncoding === 'js-string') {{
+                            // Held as a JavaScript string already
+                            if (typeof wasmStr !== 'string') {{
+                                return null;
+                            }}
+                            return maxLength === undefined || wasmStr.length <= maxLength
+                                ? wasmStr
+                                : wasmStr.slice(0, maxLength) + '…';
+                        }}
+
+                        if (stringEncoding === 'linear') {{
+                            // Pointer to NUL-terminated UTF-8 in the exported memory
+                            const memory = linearMemory;
+                            if (typeof wasmStr !== 'number' || !(memory instanceof WebAssembly.Memory)) {{
+                                return null;
+                            }}
+                            const bytes = new Uint8Array(memory.buffer);
+                            const limit = maxLength === undefined ? bytes.length : Math.min(bytes.length, wasmStr + maxLength);
+                            let end = wasmStr;
+                            while (end < limit && bytes[end] !== 0) {{
+                                end++;
+                            }}
+                            const text = new TextDecoder('utf-8').decode(bytes.subarray(wasmStr, end));
+                            return end < bytes.length && bytes[end] !== 0 ? text + '…' : text;
+                        }}
+
+                        if (stringEncoding === 'canonical') {{
+                            // Pointer to the pointer and length of UTF-8 in the exported memory
+                            if (typeof wasmStr !== 'number' || !(linearMemory instanceof WebAssembly.Memory)) {{
+                                return null;
+                            }}
+                            const [ptr, len] = canonicalPair(wasmStr);
+                            if (ptr + len > linearMemory.buffer.byteLength) {{
+                                return null;
+                            }}
+                            const shown = maxLength === undefined ? len : Math.min(len, maxLength);
+                            // A code point cut by maxLength is left out rather than replaced
+                            const text = new TextDecoder('utf-8').decode(new Uint8Array(linearMemory.buffer, ptr, shown), {{ stream: shown < len }});
+                            return shown < len ? text + '…' : text;
+                        }}
+
+                        if (!wasmStr || typeof wasmStr !== 'object') {{
+                            return null;
+                        }}
+
+                        // Read through the accessors of its string type, or else the
+                        // string_len and string_get_byte exports of the module
+                        try {{
+                            const stringType = stringTypeOf(wasmStr);
+                            const exports = result.instance.exports;
+                            const len = stringType ? result.instance.exports[stringType.len] : exports.string_len;
+                            const get = stringType ? result.instance.exports[stringType.get] : exports.string_get_byte;
+                            if (typeof len !== 'function' || typeof get !== 'function') {{
+                                return null;
+                            }}
+
+                            // Reads are bounded by the length: array.len for string types,
+                            // which are read in full, and the module's string_len, trusted
+                            // up to stringLimit
+                            const length = len(wasmStr);
+                            if (!Number.isInteger(length) || length < 0) {{
+                                return null;
+                            }}
+                            if (!stringType && maxLength === undefined && length > stringLimit) return null; // Safety limit
+                            const shown = maxLength === undefined ? length : Math.min(length, maxLength);
+                            const cut = shown < length ? '…' : '';
+                            const utf16 = stringType ? stringType.unit === 16 : stringEncoding === 'utf16';
+                            return decodeUnits(wasmStr, get, shown, utf16, stringType) + cut;
+                        }} catch (e) {{
+                            return null;
+                        }}
+                    }};
+
+                    // Helper to convert JS string to WASM string array (array i8, UTF-8),
+                    // of the string type with the given index if there is one
+                    const jsStringToWasm = function(jsStr, type) {{
+                        if (typeof jsStr !== 'string' || stringEncoding === 'js-string') {{
+                            return jsStr; // Not a string, or held as one, return as-is
+                        }}
+
+                        // Strings of a mutable string type are created through its accessors
+                        const stringType = type === undefined ? null : stringTypes.find(s => s.array === type);
+                        if (stringType && stringType.new) {{
+                            const units = stringType.unit === 16
+                                ? Array.from({{ length: jsStr.length }}, (_, i) => jsStr.charCodeAt(i))
+                                : new TextEncoder().encode(jsStr);
+                            const wasmStr = result.instance.exports[stringType.new](units.length);
+                            if (stringType.fromMemory && stringMemory) {{
+                                scratchUnits(stringType.unit === 16 ? Uint16Array : Uint8Array, units.length).set(units);
+                                result.instance.exports[stringType.fromMemory](wasmStr, 0, units.length);
+                                return wasmStr;
+                            }}
+                            const set = result.instance.exports[stringType.set];
+                            for (let i = 0; i < units.length; i++) {{
+                                set(wasmStr, i, units[i]);
+                            }}
+                            return wasmStr;
+                        }}
+
+                        // Encode JS string to UTF-8 bytes
+                        const encoder = new TextEncoder();
+                        const bytes = encoder.encode(jsStr);
+
+                        if (stringEncoding === 'linear') {{
+                            // Copy into the memory using the module's allocator; the string is
+                            // kept, so it is not freed
+                            const alloc = linearAlloc;
+                            if (linearMemory instanceof WebAssembly.Memory && typeof alloc === 'function') {{
+                                const ptr = alloc(bytes.length + 1);
+                                const view = new Uint8Array(linearMemory.buffer, ptr, bytes.length + 1);
+                                view.set(bytes);
+                                view[bytes.length] = 0;
+                                return ptr;
+                            }}
+                            console.warn('jsStringToWasm: Linear strings need a memory and alloc or malloc');
+                            return jsStr;
+                        }}
+
+                        if (stringEncoding === 'canonical') {{
+                            // Allocated with cabi_realloc, as a pointer to its pointer and
+                            // length; the module owns both
+                            if (linearMemory instanceof WebAssembly.Memory && typeof canonicalRealloc === 'function') {{
+                                const [ptr, len] = canonicalString(jsStr);
+                                const pair = canonicalRealloc(0, 0, 4, 8);
+                                new DataView(linearMemory.buffer).setUint32(pair, ptr, true);
+                                new DataView(linearMemory.buffer).setUint32(pair + 4, len, true);
+                                return pair;
+                            }}
+                            console.warn('jsStringToWasm: Canonical strings need a memory and cabi_realloc');
+                            return jsStr;
+                        }}
+
+                        // UTF-16 strings store code units instead of bytes
+                        const units = stringEncoding === 'utf16'
+                            ? Array.from({{ length: jsStr.length }}, (_, i) => jsStr.charCodeAt(i))
+                            : bytes;
+
+                        // Create WASM string array using newString and string_set_byte
+                        const exports = result.instance.exports;
+                        if (exports.newString && exports.string_set_byte) {{
+                            try {{
+                                const wasmStr = exports.newString(units.length);
+                                for (let i = 0; i < units.length; i++) {{
+                                    exports.string_set_byte(wasmStr, i, units[i]);
+                                }}
+                                return wasmStr;
+                            }} catch (e) {{
+                                console.warn('jsStringToWasm: Failed to create WASM string:', e);
+                            }}
+                        }}
+
+                        // No constructor found - return bytes as fallback
+                        console.warn('jsStringToWasm: No WASM string constructor found');
+                        return bytes;
+                    }};
+
+                    // How much of a struct toString shows, chosen with data-display
+                    const displayDepth = {display_depth};
+                    const displayLength = {display_length};
+
+                    // Arrays of structs, read through the accessors added to the module
+                    const structArrays = {struct_arrays_json};
+                    const structArrayTypes = new WeakMap();
+
+                    // Helper to find the array of structs type of a GC object, if it is
+                    // one: its get accessor takes the object, and traps past the end
+                    // rather than throwing a TypeError for the wrong type
+                    const structArrayType = function(obj) {{
+                        if (structArrayTypes.has(obj)) {{
+                            return structArrayTypes.get(obj);
+                        }}
+                        let found = null;
+                        for (const arrayType of structArrays) {{
+                            const get = result.instance.exports[arrayType.get];
+                            if (typeof get !== 'function') {{
+                                continue;
+                            }}
+                            try {{
+                                get(obj, 0);
+                                found = arrayType;
+                            }} catch (e) {{
+                                if (e instanceof WebAssembly.RuntimeError) {{
+                                    found = arrayType;
+                                }}
+                            }}
+                            if (found) {{
+                                break;
+                            }}
+                        }}
+                        structArrayTypes.set(obj, found);
+                        return found;
+                    }};
+
+                    // Struct types, read and written through the field accessors added
+                    // to the module
+                    const structTypes = {struct_types_json};
+                    // String types of the fields of struct types, by type index
+                    const structStrings = {struct_strings_json};
+                    const structTypeOfs = new WeakMap();
+
+                    // Helper to find the struct type of a GC object, if it is a struct:
+                    // the last type whose getters accept it, so subtypes, which come
+                    // after their supertypes, before these
+                    const structTypeOf = function(obj) {{
+                        if (structTypeOfs.has(obj)) {{
+                            return structTypeOfs.get(obj);
+                        }}
+                        let found = null;
+                        for (let i = structTypes.length - 1; i >= 0 && !found; i--) {{
+                            const getter = structTypes[i].get.find(name => name !== null);
+                            const get = result.instance.exports[getter];
+                            if (typeof get !== 'function') {{
+                                continue;
+                            }}
+                            try {{
+                                get(obj);
+                                found = structTypes[i];
+                            }} catch (e) {{
+                                // A TypeError: the struct is of another type
+                            }}
+                        }}
+                        structTypeOfs.set(obj, found);
+                        return found;
+                    }};
+
+                    // Helper to find the position of a field of a struct type, by
+                    // position or name
+                    const structFieldPosition = function(structType, prop) {{
+                        return /^\d+$/.test(prop) ? Number(prop) : structType.fields.indexOf(prop);
+                    }};
+
+                    // Helper to read a field of a struct through its getter; undefined
+                    // if its type has none
+                    const readStructField = function(obj, position) {{
+                        const structType = structTypeOf(obj);
+                        const getter = structType && structType.get[position];
+                        return getter ? result.instance.exports[getter](obj) : undefined;
+                    }};
+
+                    // Helper to decode a string of the string type with the given index;
+                    // null strings stay null
+                    const decodeString = function(value, type) {{
+                        const stringType = stringTypes.find(s => s.array === type);
+                        if (!value || !stringType) {{
+                            return null;
+                        }}
+                        const exports = result.instance.exports;
+                        return decodeUnits(value, exports[stringType.get], exports[stringType.len](value), stringType.unit === 16, stringType);
+                    }};
+
+                    // Declared result types of the exports returning a struct, an array
+                    // of structs or a string
+                    const exportResults = {export_results_json};
+
+                    // Helper to find the type info to wrap a value of an export with;
+                    // arrays of structs are recorded as such, so they need no probing
+                    const resultTypeInfo = function(name, value) {{
+                        const info = Object.hasOwn(exportResults, name) ? exportResults[name] : null;
+                        if (!info || !value || typeof value !== 'object') {{
+                            return undefined;
+                        }}
+                        if (info.array !== undefined) {{
+                            if (!structArrayTypes.has(value)) {{
+                                structArrayTypes.set(value, structArrays.find(a => a.array === info.array) || null);
+                            }}
+                            return undefined;
+                        }}
+                        return info;
+                    }};
+
+                    // String parameters of the exports taking any, by position
+                    const exportStringParams = {string_params_json};
+
+                    // Linear-memory strings passed to exports: allocated with the module's
+                    // alloc or malloc, and freed with its free after the outermost call,
+                    // or else bump allocated on pages the memory is grown by, reused by
+                    // the next call
+                    const linearMemory = result.instance.exports.memory instanceof WebAssembly.Memory
+                        ? result.instance.exports.memory
+                        : result.instance.exports['{string_memory}'];
+                    const linearAlloc = result.instance.exports.alloc || result.instance.exports.malloc;
+                    const linearFree = result.instance.exports.free;
+                    const linearBump = {{ start: 0, top: 0, limit: 0 }};
+                    const linearAllocated = [];
+                    let linearCalls = 0;
+
+                    // Helper to allocate a linear-memory string argument of size bytes
+                    const allocLinear = function(size) {{
+                        if (typeof linearAlloc === 'function') {{
+                            const ptr = linearAlloc(size);
+                            if (typeof linearFree === 'function') {{
+                                linearAllocated.push(ptr);
+                            }}
+                            return ptr;
+                        }}
+                        if (linearBump.top + size > linearBump.limit) {{
+                            const pages = Math.ceil(size / 65536);
+                            linearBump.start = linearBump.top = linearMemory.grow(pages) * 65536;
+                            linearBump.limit = linearBump.start + pages * 65536;
+                        }}
+                        const ptr = linearBump.top;
+                        linearBump.top += size;
+                        return ptr;
+                    }};
+
+                    // Helper to write a JS string to linear memory as a pointer and a
+                    // length; the bytes are NUL-terminated too
+                    const linearString = function(jsStr) {{
+                        const bytes = new TextEncoder().encode(jsStr);
+                        const ptr = allocLinear(bytes.length + 1);
+                        const view = new Uint8Array(linearMemory.buffer, ptr, bytes.length + 1);
+                        view.set(bytes);
+                        view[bytes.length] = 0;
+                        return [ptr, bytes.length];
+                    }};
+
+                    // Canonical ABI strings passed to exports: allocated with the module's
+                    // cabi_realloc and owned by the callee, which frees them
+                    const canonicalRealloc = result.instance.exports.cabi_realloc;
+
+                    // Helper to write a JS string to linear memory as the canonical ABI
+                    // lowers it, a pointer and a length in bytes
+                    const canonicalString = function(jsStr) {{
+                        const bytes = new TextEncoder().encode(jsStr);
+                        const ptr = canonicalRealloc(0, 0, 1, bytes.length);
+                        new Uint8Array(linearMemory.buffer, ptr, bytes.length).set(bytes);
+                        return [ptr, bytes.length];
+                    }};
+
+                    // Helper to read the pointer and length a canonical ABI string
+                    // result points to
+                    const canonicalPair = function(retptr) {{
+                        const view = new DataView(linearMemory.buffer);
+                        return [view.getUint32(retptr, true), view.getUint32(retptr + 4, true)];
+                    }};
+
+                    // Helper to free the linear-memory string arguments, and unpin the
+                    // AssemblyScript ones, once the outermost export call returns
+                    const releaseLinear = function() {{
+                        if (linearCalls > 0) {{
+                            return;
+                        }}
+                        if (assemblyScript !== null) {{
+                            assemblyScript.unpinAll();
+                        }}
+                        while (linearAllocated.length > 0) {{
+                            linearFree(linearAllocated.pop());
+                        }}
+                        linearBump.top = linearBump.start;
+                    }};
+
+                    // Helper to convert the arguments of an export for WebAssembly: JS
+                    // strings passed as string parameters are encoded, and with linear
+                    // or canonical ABI strings all JS strings become a pointer and a
+                    // length, and with the AssemblyScript runtime a pinned string
+                    const encodeArgs = function(name, args) {{
+                        if (assemblyScript !== null) {{
+                            return args.map(arg => typeof arg === 'string' ? assemblyScript.pinString(arg) : arg);
+                        }}
+                        if (stringEncoding === 'linear' && linearMemory instanceof WebAssembly.Memory) {{
+                            return args.flatMap(arg => typeof arg === 'string' ? linearString(arg) : [arg]);
+                        }}
+                        if (stringEncoding === 'canonical' && linearMemory instanceof WebAssembly.Memory &&
+                            typeof canonicalRealloc === 'function') {{
+                            return args.flatMap(arg => typeof arg === 'string' ? canonicalString(arg) : [arg]);
+                        }}
+                        const strings = Object.hasOwn(exportStringParams, name) ? exportStringParams[name] : null;
+                        if (!strings) {{
+                            return args;
+                        }}
+                        return args.map((arg, i) => (strings[i] === null || strings[i] === undefined)
+                            ? arg
+                            : jsStringToWasm(arg, strings[i]));
+                    }};
+
+                    // Helper to convert a value of an export for JavaScript: strings are
+                    // decoded by their declared type, or for canonical ABI strings by the
+                    // cabi_post_ function freeing them, GC objects wrapped
+                    const wrapResult = function(name, value) {{
+                        const info = Object.hasOwn(exportResults, name) ? exportResults[name] : null;
+                        if (info && info.string !== undefined) {{
+                            return decodeString(value, info.string);
+                        }}
+                        const postReturn = result.instance.exports['cabi_post_' + name];
+                        if (stringEncoding === 'canonical' && typeof value === 'number' && typeof postReturn === 'function') {{
+                            const [ptr, len] = canonicalPair(value);
+                            const text = new TextDecoder('utf-8').decode(new Uint8Array(linearMemory.buffer, ptr, len));
+                            postReturn(value);
+                            return text;
+                        }}
+                        return wrapGcObject(value, resultTypeInfo(name, value));
+                    }};
+
+                    // Names of the struct types and their fields, the first as the
+                    // default for structs of unknown type
+                    const structFieldNames = {field_names_json};
+
+                    // Field access through the get_ and set_ exports of the module, for
+                    // fields without accessors, notifying the watchers of the field;
+                    // defined by the struct helpers below for modules with struct types
+                    let structFieldGet = null;
+                    let structFieldSet = null;
+
+                    // Helper to format a GC struct for toString, down to displayDepth
+                    // levels of nesting and cut to displayLength characters
+                    const formatGcStruct = function(obj, depth, typeInfo) {{
+                        if (depth >= displayDepth) {{
+                            return '…';
+                        }}
+
+                        // Try to get field values for display
+                        let fields = [];
+                        typeInfo = typeInfo || structTypeOf(obj) || structFieldNames.default;
+                        const typeName = (typeInfo && typeInfo.typeName) ? typeInfo.typeName : 'WasmGcStruct';
+                        const fieldNames = (typeInfo && typeInfo.fields) ? typeInfo.fields : null;
+                        const formatField = function(val) {{
+                            if (!val || typeof val !== 'object') {{
+                                return val;
+                            }}
+                            // Convert nested string arrays
+                            if (val[0] !== undefined && typeof val[0] === 'number') {{
+                                return '"' + (wasmStringToJs(val, displayLength) || '') + '"';
+                            }}
+                            return formatGcStruct(val, depth + 1);
+                        }};
+
+                        try {{
+                            if (fieldNames) {{
+                                // Use field names if available
+                                for (let i = 0; i < fieldNames.length; i++) {{
+                                    const read = readStructField(obj, i);
+                                    const val = read !== undefined ? read : obj[i];
+                                    if (val !== undefined) {{
+                                        fields.push(fieldNames[i] + '=' + formatField(val));
+                                    }}
+                                }}
+                            }} else {{
+                                // Fallback to numeric indices
+                                if (obj[0] !== undefined) {{
+                                    fields.push('0=' + formatField(obj[0]));
+                                }}
+                            }}
+                        }} catch (e) {{
+                            // Ignore errors
+                        }}
+
+                        const text = typeName + '{{' + fields.join(', ') + '}}';
+                        return text.length > displayLength ? text.slice(0, displayLength) + '…' : text;
+                    }};
+
+                    // Helper to wrap GC objects with toString support; typeInfo gives
+                    // the name and fields of a struct of known type
+                    const wrapGcObject = function(obj, typeInfo) {{
+                        if (!obj || typeof obj !== 'object') {{
+                            return obj;
+                        }}
+
+                        // Check if already wrapped
+                        if (obj.__wasmGcWrapped) {{
+                            return obj;
+                        }}
+
+                        // Check if this is a string array (has numeric indices that are UTF-8 bytes)
+                        const isStringArray = function() {{
+                            try {{
+                                // Check first few elements - if they're all valid bytes (0-255), it's likely a string
+                                const first = obj[0];
+                                if (first !== undefined && typeof first === 'number' && first >= 0 && first <= 255) {{
+                                    return true;
+                                }}
+                            }} catch (e) {{}}
+                            return false;
+                        }};
+
+                        // Get type info (name and fields) for this struct
+                        const getTypeInfo = function() {{
+                            if (typeInfo) {{
+                                return typeInfo;
+                            }}
+                            const structType = structTypeOf(obj);
+                            if (structType) {{
+                                return structType;
+                            }}
+                            return structFieldNames.default || null;
+                        }};
+
+                        // Create proxy with toString and Symbol.toPrimitive handlers
+                        return new Proxy(obj, {{
+                            get(target, prop) {{
+                                // Handle toString
+                                if (prop === 'toString') {{
+                                    return function() {{
+                                        // Check if this is a string array
+                                        if (isStringArray()) {{
+                                            const jsStr = wasmStringToJs(target);
+                                            return jsStr !== null ? jsStr : '[WasmString]';
+                                        }}
+                                        return formatGcStruct(target, 0, getTypeInfo());
+                                    }};
+                                }} else if (prop === Symbol.toPrimitive) {{
+                                    // Handle Symbol.toPrimitive for string conversion
+                                    return function(hint) {{
+                                        if (hint === 'string' || hint === 'default') {{
+                                            // Check if this is a string array
+                                            if (isStringArray()) {{
+                                                const jsStr = wasmStringToJs(target);
+                                                return jsStr !== null ? jsStr : '[WasmString]';
+                                            }}
+                                            return formatGcStruct(target, 0, getTypeInfo());
+                                        }}
+                                        // For number hint, return NaN to avoid conversion errors
+                                        return NaN;
+                                    }};
+                                }} else if (prop === Symbol.toStringTag) {{
+                                    const typeInfo = getTypeInfo();
+                                    return (typeInfo && typeInfo.typeName) ? typeInfo.typeName : 'WasmGcStruct';
+                                }} else if (prop === '__wasmGcWrapped') {{
+                                    return true;
+                                }} else if (prop === '__wasmGcTarget') {{
+                                    return target;
+                                }}
+
+                                // Elements of arrays of structs, wrapped with the names of
+                                // their struct type
+                                const arrayType = typeof prop === 'string' ? structArrayType(target) : null;
+                                if (arrayType) {{
+                                    if (prop === 'length') {{
+                                        return result.instance.exports[arrayType.len](target);
+                                    }}
+                                    if (/^\d+$/.test(prop)) {{
+                                        try {{
+                                            return wrapGcObject(
+                                                result.instance.exports[arrayType.get](target, Number(prop)),
+                                                arrayType.element
+                                            );
+                                        }} catch (e) {{
+                                            // Past the end
+                                            return undefined;
+                                        }}
+                                    }}
+                                }}
+
+                                // Fields of structs, through their accessors
+                                const structType = typeof prop === 'string' && !arrayType ? structTypeOf(target) : null;
+                                const getter = structType && structType.get[structFieldPosition(structType, prop)];
+                                if (getter) {{
+                                    const value = result.instance.exports[getter](target);
+                                    // Try to convert to JS string if it's a WASM string array
+                                    if (value && typeof value === 'object') {{
+                                        const jsStr = wasmStringToJs(value);
+                                        if (jsStr !== null) {{
+                                            return jsStr;
+                                        }}
+                                    }}
+                                    return wrapGcObject(value);
+                                }}
+
+                                // Map numeric index to field name, or use string field name directly
+                                let fieldName = prop;
+                                const typeInfo = getTypeInfo();
+                                const fieldNames = (typeInfo && typeInfo.fields) ? typeInfo.fields : null;
+
+                                // Convert numeric index to field name
+                                const propNum = typeof prop === 'number' ? prop : parseInt(prop, 10);
+                                if (!isNaN(propNum) && fieldNames && propNum >= 0 && propNum < fieldNames.length) {{
+                                    fieldName = fieldNames[propNum];
+                                }}
+
+                                // Try to get value using WASM getter function
+                                if (structFieldGet !== null) {{
+                                    const value = structFieldGet(target, fieldName);
+                                    if (value !== undefined) {{
+                                        return value;
+                                    }}
+                                }}
+
+                                // Fallback: direct property access
+                                const value = target[prop];
+                                // Auto-convert string arrays to JS strings
+                                if (value && typeof value === 'object' && value[0] !== undefined && typeof value[0] === 'number' && value[0] >= 0 && value[0] <= 255) {{
+                                    return wasmStringToJs(value) || value;
+                                }}
+                                return value;
+                            }},
+                            set(target, prop, value) {{
+                                // Convert JS string to WASM string array if needed
+                                let wasmValue = value;
+                                if (typeof value === 'string' && typeof jsStringToWasm !== 'undefined') {{
+                                    wasmValue = jsStringToWasm(value);
+                                }}
+
+                                // Fields of structs, through their accessors
+                                const structType = typeof prop === 'string' ? structTypeOf(target) : null;
+                                const setter = structType && structType.set[structFieldPosition(structType, prop)];
+                                if (setter) {{
+                                    result.instance.exports[setter](target, wasmValue);
+                                    return true;
+                                }}
+
+                                // Convert numeric index or string number to field name
+                                let fieldName = prop;
+                                const propNum = typeof prop === 'number' ? prop : parseInt(prop, 10);
+                                if (!isNaN(propNum)) {{
+                                    const typeInfo = getTypeInfo();
+                                    const fieldNames = (typeInfo && typeInfo.fields) ? typeInfo.fields : null;
+                                    if (fieldNames && propNum >= 0 && propNum < fieldNames.length) {{
+                                        fieldName = fieldNames[propNum];
+                                    }}
+                                }}
+
+                                // Try to set using WASM setter function
+                                if (structFieldSet !== null) {{
+                                    structFieldSet(target, fieldName, wasmValue);
+                                }} else {{
+                                    target[prop] = wasmValue;
+                                }}
+                                return true;
+
+                                // TODO: Enforce field mutability
+                                // Currently allows modification of immutable WASM fields from JS.
+                                // To fix: parse type section to track which fields are mutable,
+                                // and throw TypeError when attempting to modify immutable fields.
+                                // For now: "It's a feature, not a bug!" 😄
+                            }}
+                        }});
+                    }};
+
+                    // Exports go on window, or on the data-namespace object
+                    const exportTarget = {export_target};
+
+                    // AssemblyScript runtime, with the helpers of its loader on the exports
+                    const assemblyScript = {assemblyscript};{string_helpers}
+
+                    // Typed views of the exported memories, as <name>_views. Growing a
+                    // memory detaches its old buffer, so the views are replaced and a
+                    // 'memorygrow' event is dispatched whenever a memory has grown:
+                    // checked after each call of an export and each memory.grow() from
+                    // JavaScript
+                    const memoryViews = [];
+                    const refreshMemoryViews = function(state) {{
+                        const buffer = state.memory.buffer;
+                        state.buffer = buffer;
+                        state.byteLength = buffer.byteLength;
+                        Object.assign(state.views, {{
+                            buffer: buffer,
+                            data: new DataView(buffer),
+                            i8: new Int8Array(buffer),
+                            u8: new Uint8Array(buffer),
+                            i16: new Int16Array(buffer),
+                            u16: new Uint16Array(buffer),
+                            i32: new Int32Array(buffer),
+                            u32: new Uint32Array(buffer),
+                            i64: new BigInt64Array(buffer),
+                            u64: new BigUint64Array(buffer),
+                            f32: new Float32Array(buffer),
+                            f64: new Float64Array(buffer)
+                        }});
+                    }};
+                    const checkMemoryGrowth = function() {{
+                        for (const state of memoryViews) {{
+                            const buffer = state.memory.buffer;
+                            if (buffer === state.buffer && buffer.byteLength === state.byteLength) {{
+                                continue;
+                            }}
+                            // Growing by zero pages may also replace the buffer
+                            const oldBytes = state.byteLength;
+                            refreshMemoryViews(state);
+                            if (state.byteLength === oldBytes) {{
+                                continue;
+                            }}
+                            const event = new CustomEvent('memorygrow', {{
+                                detail: {{
+                                    filename: wasmFilename,
+                                    memory: state.name,
+                                    oldBytes: oldBytes,
+                                    newBytes: state.byteLength
+                                }}
+                            }});
+                            window.dispatchEvent(event);
+                            if (typeof window.onmemorygrow === 'function') {{
+                                window.onmemorygrow(event);
+                            }}
+                        }}
+                    }};
+                    const trackMemory = function(name, memory) {{
+                        const state = {{ name: name, memory: memory, views: {{}} }};
+                        refreshMemoryViews(state);
+                        memoryViews.push(state);
+                        const grow = memory.grow;
+                        memory.grow = function(delta) {{
+                            try {{
+                                return grow.call(memory, delta);
+                            }} finally {{
+                                checkMemoryGrowth();
+                            }}
+                        }};
+                        return state.views;
+                    }};
+
+                    // Names to install exports under instead of their own, recorded
+                    // for tooling with the wrappers by the names of the module
+                    const exportAliases = {export_aliases_json};
+                    wasmEntry.nameStyle = '{name_style}';
+                    wasmEntry.exportNames = exportAliases;
+                    wasmEntry.exports = {{}};
+
+                    // Names of the exports to install (data-exports), null for all; the
+                    // others are only reachable through wasmEntry.exports
+                    const exportFilter = {export_filter_json};
+
+                    for (const wasmName in result.instance.exports) {{
+                        const exported = result.instance.exports[wasmName];
+
+                        // Loader internals: deferred start function, coverage counters and
+                        // array, struct and string accessors
+                        if (wasmName === '{deferred_start_export}' || wasmName.startsWith('{coverage_prefix}') ||
+                            wasmName.startsWith('{array_prefix}') || wasmName.startsWith('{struct_prefix}') ||
+                            wasmName.startsWith('{string_prefix}')) {{
+                            continue;
+                        }}
+                        const name = Object.hasOwn(exportAliases, wasmName) ? exportAliases[wasmName] : wasmName;
+                        const target = exportFilter === null || exportFilter.includes(wasmName) ? exportTarget : {{}};
+
+                        if (typeof exported === 'function') {{
+                            // Wrap function to encode strings passed to it, and decode strings
+                            // and wrap GC objects it returns
+                            target[name] = function(...args) {{
+                                linearCalls++;
+                                try {{
+                                    return wrapResult(wasmName, exported.apply(this, encodeArgs(wasmName, args)));
+                                }} finally {{
+                                    checkMemoryGrowth();
+                                    linearCalls--;
+                                    releaseLinear();
+                                }}
+                            }};
+                            console.log('WASM: Exported function ' + name);
+                        }} else if (exported instanceof WebAssembly.Global) {{
+                            // For globals containing GC objects, wrap the value and expose directly
+                            const globalValue = exported.value;
+                            if (globalValue && typeof globalValue === 'object') {{
+                                // This is a GC object (struct, array, etc.) - wrap and export the value directly
+                                target[name] = wrapResult(wasmName, globalValue);
+                                // Also store the raw Global for advanced use (mutable globals)
+                                target[name + '_global'] = exported;
+                                if (target === exportTarget) {{
+                                    installedExtras[name + '_global'] = exported;
+                                }}
+                                console.log('WASM: Exported GC global ' + name + ' = WasmGcStruct');
+                            }} else {{
+                                // Simple global (i32, f64, etc.) - export the Global object with .value property
+                                target[name] = exported;
+                                console.log('WASM: Exported global ' + name + ' = ' + exported.value);
+                            }}
+                        }} else if (exported instanceof WebAssembly.Memory) {{
+                            target[name] = exported;
+                            target[name + '_views'] = trackMemory(name, exported);
+                            if (target === exportTarget) {{
+                                installedExtras[name + '_views'] = target[name + '_views'];
+                            }}
+                            console.log('WASM: Exported memory ' + name + ' (' + exported.buffer.byteLength + ' bytes)');
+                        }} else {{
+                            // Export other types (Table, Tag, etc.)
+                            target[name] = exported;
+                            console.log('WASM: Exported ' + name);
+                        }}
+                        wasmEntry.exports[wasmName] = target[name];
+                        if (target === exportTarget) {{
+                            installedExports[name] = target[name];
+                        }}
+                    }}
+                    wasmEntry.dispose = function() {{
+                        return disposeWasm(exportTarget, Object.assign({{}}, installedExtras, installedExports),
+                            result.instance.exports);
+                    }};
+                    watchLifecycle();{struct_helpers}{bind_helpers}{emscripten}{go_start}{wasi_http}{wasi_start}{component_exports}
+                }}
+
+                console.log('WASM module loaded successfully');
+                // Dispatch custom event so pages can listen for WASM completion
+                window.dispatchEvent(new CustomEvent('wasmloaded', {{
+                    detail: {{ filename: wasmFilename, exports: installedExports }}
+                }}));{deferred_start}
+            }})
+            .catch(function(e) {{
+                console.error('WASM instantiation error:', e);
+                dispatchWasmError('instantiation failed: ' + e);{memory_release}
+            }});
+
+    }} catch (e) {{
+        console.error('WASM error:', e);
+    }}
+}})();
+"#,
+        byte_chunks,
+        deferred_start_export = start::DEFERRED_START_EXPORT,
+        coverage_prefix = coverage::COUNTER_EXPORT_PREFIX,
+        array_prefix = arrays::EXPORT_PREFIX,
+        struct_prefix = structs::EXPORT_PREFIX,
+        string_prefix = strings::EXPORT_PREFIX,
+        string_memory = strings::MEMORY_EXPORT,
+        struct_types_json = embed::script_safe(&struct_types_json),
+        struct_strings_json = embed::script_safe(&struct_strings_json),
+        struct_arrays_json = embed::script_safe(&struct_arrays_json),
+        string_types_json = embed::script_safe(&string_types_json),
+        export_results_json = embed::script_safe(&export_results_json),
+        string_params_json = embed::script_safe(&string_params_json),
+        string_encoding = string_encoding.as_str(),
+        builtin_imports = builtin_imports,
+        toolchain_loader = toolchain_loader,
+        go_imports = go_imports,
+        go_start = go_start,
+        wasi_http_imports = wasi_http_imports,
+        wasi_http = wasi_http,
+        wasi_imports = wasi_imports,
+        wasi_start = wasi_start,
+        component_imports = component_imports,
+        loader_imports = loader_imports,
+        component_exports = component_exports,
+        assemblyscript_imports = assemblyscript_imports,
+        assemblyscript = assemblyscript,
+        fallback_imports = fallback_imports,
+        instance_imports = instance_imports,
+        lifecycle = options.lifecycle.as_str(),
+        instantiate = instantiate,
+        compile_options = compile_options,
+        stub_imports = options.stub_imports,
+        shared_imports = shared_imports,
+        deferred_imports = deferred_imports,
+        import_map_json =
+            embed::script_safe(&imports::import_map_json(&options.import_map, &wasm_binary)),
+        display_depth = options.display.depth,
+        display_length = options.display.length,
+        display_string = options.display.string,
+        filename_json = embed::script_json(&filename),
+        probes_json = capabilities::probes_json(),
+        enabled_features = capabilities::enabled_json(
+            options
+                .enabled_features
+                .unwrap_or_else(options::all_features)
+        ),
+        required_features = embed::script_safe(&metadata.required_features_json),
+        field_names_json = embed::script_safe(&metadata.field_names_json),
+        imports_json = embed::script_safe(&metadata.imports_json),
+        export_aliases_json = embed::script_safe(&export_aliases_json),
+        export_filter_json = embed::script_safe(&export_filter_json),
+        name_style = options.name_style.as_str(),
+        locals_json = embed::script_safe(&metadata.locals_json),
+        tables_json = embed::script_safe(&metadata.tables_json),
+        size_report = size_report,
+    );
+
+    // Append optional callback code wrapped in wasmloaded event listener
+    if let Some(callback_code) = callback {
+        if !callback_code.trim().is_empty() {
+            // The code is passed as a string so that it cannot break out of
+            // the listener, whatever it contains
+            js_code.push_str("\n// Auto-generated callback from inline script content\n");
+            js_code.push_str(&format!(
+                r#"try {{
+    window.addEventListener('wasmloaded', new Function({}));
+}} catch (e) {{
+    console.error('WASM: Invalid callback script:', e);
+}}
+"#,
+                embed::script_json(&callback_code)
+            ));
+        }
+    }
+
+    Ok(js_code)
+}
+
+/// Transform WAT source to replace 'string' type with GC array representation
+/// Strings are represented as (array i8) for UTF-8 encoding
+fn transform_string_types(source: &str) -> String {
+    // Check if $string type is already defined
+    let has_string_type = source.contains("(type $string");
+
+    let mut result = String::new();
+    let mut in_module = false;
+    let mut string_type_added = false;
+    let mut data_sections = Vec::new();
+    let mut string_counter = 0;
+    let mut open_lists = Vec::new();
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+
+        // Detect module start to inject string type definition
+        if trimmed.starts_with("(module") {
+            result.push_str(line);
+            result.push('\n');
+            in_module = true;
+            continue;
+        }
+
+        // Add string type definition right after module start, before any other content
+        // Skip if already defined in source
+        if in_module
+            && !string_type_added
+            && !has_string_type
+            && !trimmed.is_empty()
+            && !trimmed.starts_with(";")
+        {
+            // Insert string type before any module content
+            result.push_str("  ;; String type: array of i8 (UTF-8)\n");
+            result.push_str("  (type $string (array (mut i8)))\n\n");
+            string_type_added = true;
+        }
+
+        // First, replace 'string' type references with '(ref null $string)'
+        let type_transformed = replace_string_type(line, &mut open_lists);
+
+        // Then, transform string literals in struct.new
+        let transformed = if trimmed.contains("struct.new") && trimmed.contains("\"") {
+            let (line_result, data_section) =
+                transform_string_literal_to_data(&type_transformed, &mut string_counter);
+            if let Some(data) = data_section {
+                data_sections.push(data);
+            }
+            line_result
+        } else {
+            type_transformed
+        };
+
+        result.push_str(&transformed);
+        result.push('\n');
+    }
+
+    // Add all data sections before closing the module
+    if !data_sections.is_empty() {
+        result.push('\n');
+        result.push_str("  ;; String data sections\n");
+        for data in data_sections {
+            result.push_str(&format!("  {}\n", data));
+        }
+    }
+
+    result
+}
+
+/// Lists in which `string` stands for a value type
+const STRING_TYPE_LISTS: &[&str] = &["param", "result", "local", "global", "field", "mut"];
+
+/// Replace the `string` type with `(ref null $string)` wherever a line uses it
+/// as a value type: `(param $s string)`, `(local $a i32 string)`,
+/// `(global $g (mut string) ...)` and so on.
+/// `open_lists` holds the keywords of the lists still open before the line,
+/// and is left with those open after it, so headers may span lines.
+fn replace_string_type(line: &str, open_lists: &mut Vec<String>) -> String {
+    let is_delimiter = |c: char| c.is_whitespace() || c == '(' || c == ')' || c == '"';
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            ';' if rest.starts_with(";;") => rest.len(),
+            '(' if rest.starts_with("(;") => rest.find(";)").map_or(rest.len(), |end| end + 2),
+            '(' => {
+                let keyword = rest[1..].split(is_delimiter).next().unwrap_or_default();
+                open_lists.push(keyword.to_string());
+                1
+            },
+            ')' => {
+                open_lists.pop();
+                1
+            },
+            '"' => {
+                // Up to the closing quote, skipping escaped characters
+                let mut escaped = false;
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let closing = c == '"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        closing
+                    })
+                    .map_or(rest.len(), |(end, _)| end + 1)
+            },
+            c if c.is_whitespace() => c.len_utf8(),
+            _ => {
+                let len = rest.find(is_delimiter).unwrap_or(rest.len());
+                let in_type_list = open_lists
+                    .last()
+                    .is_some_and(|keyword| STRING_TYPE_LISTS.contains(&keyword.as_str()));
+                if &rest[..len] == "string" && in_type_list {
+                    result.push_str("(ref null $string)");
+                    rest = &rest[len..];
+                    continue;
+                }
+                len
+            },
+        };
+        result.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    result
+}
+
+/// Transform a line containing struct.new with string literal using data section
+/// Returns (transformed_line, optional_data_section)
+fn transform_string_literal_to_data(line: &str, counter: &mut usize) -> (String, Option<String>) {
+    // Find struct.new position first
+    if let Some(struct_new_pos) = line.find("struct.new") {
+        // Only look for string literals AFTER struct.new
+        let after_struct_new = &line[struct_new_pos..];
+
+        if let Some(start_quote) = after_struct_new.find('"') {
+            let absolute_start_quote = struct_new_pos + start_quote;
+
+            if let Some(end_quote) = after_struct_new[start_quote + 1..].find('"') {
+                let literal_start = absolute_start_quote + 1;
+                let literal_end = absolute_start_quote + 1 + end_quote;
+                let string_content = &line[literal_start..literal_end];
+
+                // Create data section identifier
+                let data_id = format!("$str_{}", counter);
+                *counter += 1;
+
+                // Create data section
+                let data_section = format!(r#"(data {} "{}")"#, data_id, string_content);
+
+                // Use array.new_data to reference the data section
+                let string_len = string_content.len();
+                let array_init = format!(
+                    "(array.new_data $string {} (i32.const 0) (i32.const {}))",
+                    data_id, string_len
+                );
+
+                // Replace the string literal with array.new_data
+                let before = &line[..absolute_start_quote];
+                let after = &line[literal_end + 1..];
+                let transformed_line = format!("{}{}{}", before, array_init, after);
+
+                return (transformed_line, Some(data_section));
+            }
+        }
+    }
+
+    (line.to_string(), None)
+}
+
+/// Internal compilation function using wat crate
+/// Extensions that need a proposal outside `features` are rejected
+fn compile_wat_internal(
+    source: &str,
+    filename: &str,
+    features: WasmFeatures,
+) -> Result<Vec<u8>, CompileError> {
+    compile_module(source.as_bytes(), filename, features, true, None, false)
+        .map(|(binary, _)| binary)
+}
+
+/// [`compile_wat_internal`], also returning the module without the optional
+/// passes if the loader can fall back to it; `stdlib` links the imports of
+/// the WAT standard library, `keep` lists the exports to shake the module
+/// down to, see [`shake`], and `inline` inlines small functions, see
+/// [`inline`]
+fn compile_module(
+    source_bytes: &[u8],
+    filename: &str,
+    features: WasmFeatures,
+    stdlib: bool,
+    keep: Option<&[String]>,
+    inline: bool,
+) -> Result<(Vec<u8>, Option<fallback::Fallback>), CompileError> {
+    // Check if input is already binary WASM (starts with magic number \0asm)
+    let is_binary = source_bytes.len() >= 4 && &source_bytes[0..4] == b"\0asm";
+    let parse_start = CrossProcessInstant::now();
+    let mut wasm_binary = if is_binary && component::is_component(source_bytes) {
+        log::info!(
+            target: LOG_TARGET, phase = "parse", filename, bytes = source_bytes.len();
+            "Input is a component, loading its main module"
+        );
+        component::core_module(source_bytes)
+            .map_err(|e| CompileError::ParseError(format!("in {}: {}", filename, e)))?
+    } else if is_binary {
+        log::info!(
+            target: LOG_TARGET, phase = "parse", filename, bytes = source_bytes.len();
+            "Input is already binary WASM, using directly"
+        );
+        // Already compiled, use the bytes
+        source_bytes.to_vec()
+    } else {
+        // Parse as WAT text format (plain WAT stays untouched, extensions are opt-in)
+        let source = std::str::from_utf8(source_bytes)
+            .map_err(|e| CompileError::ParseError(format!("in {}: {}", filename, e)))?;
+        let text = preprocess_wat(source, features, stdlib)?;
+        wat::parse_str(&text)
+            .map_err(|e| CompileError::ParseError(format!("in {}: {}", filename, e)))?
+    };
+    timeline::record("parse", parse_start, CrossProcessInstant::now());
+
+    if let Some(keep) = keep {
+        let shaken = differential::run(
+            filename,
+            "tree shaking",
+            &mut wasm_binary,
+            features,
+            |binary| {
+                shake::shake(binary, keep).map_err(|e| {
+                    CompileError::InstrumentationError(format!("in {}: {}", filename, e))
+                })
+            },
+        )?;
+        for name in &shaken.missing {
+            log::warn!(
+                target: LOG_TARGET, phase = "tree shaking", filename, export:% = name;
+                "data-keep lists {:?}, which {} does not export", name, filename
+            );
+        }
+        log::info!(
+            target: LOG_TARGET,
+            phase = "tree shaking", filename, functions = shaken.functions, data = shaken.data,
+            types = shaken.types;
+            "Removed {} functions, {} data segments and {} types",
+            shaken.functions, shaken.data, shaken.types
+        );
+    }
+    if inline {
+        let inlined =
+            differential::run(filename, "inlining", &mut wasm_binary, features, |binary| {
+                inline::inline(binary).map_err(|e| {
+                    CompileError::InstrumentationError(format!("in {}: {}", filename, e))
+                })
+            })?;
+        log::info!(
+            target: LOG_TARGET,
+            phase = "inlining", filename, calls = inlined.calls, functions = inlined.functions;
+            "Inlined {} calls, removing {} functions", inlined.calls, inlined.functions
+        );
+    }
+
+    // Inject datacount section if missing (required for array.new_data instruction)
+    // wasm-tools 1.243.0 doesn't generate this section automatically, but SpiderMonkey requires it
+    let fallback = fallback::apply_optional_passes(&mut wasm_binary, features, filename)?;
+
+    Ok((wasm_binary, fallback))
+}
+
+/// Lower the text-level extensions (expression sugar, string interpolation,
+/// intrinsics) to standard WAT
+/// Sources not using any extension are returned unchanged
+/// All extensions produce `$string` GC arrays, so they need the gc proposal
+/// The imports of the standard library are linked if `stdlib` is set
+fn preprocess_wat(
+    source: &str,
+    features: WasmFeatures,
+    stdlib: bool,
+) -> Result<Cow<'_, str>, CompileError> {
+    let require_gc = |extension: &str| {
+        if features.contains(WasmFeatures::GC) {
+            Ok(())
+        } else {
+            Err(CompileError::ValidationError(format!(
+                "{} needs wasm GC, which is disabled",
+                extension
+            )))
+        }
+    };
+    let mut text = Cow::Borrowed(source);
+    let sugar = sugar::is_enabled(&text);
+
+    if sugar {
+        // Opt-in expression sugar
+        require_gc("(@sugar)")?;
+        text = Cow::Owned(sugar::desugar(&text)?);
+    }
+
+    // Interpolated literals must be lowered before the string-type sugar turns
+    // literals into data segments
+    if interpolation::uses_interpolation(&text) {
+        let lowered = interpolation::lower_interpolation(&text)?;
+        if lowered != *text {
+            require_gc("string interpolation")?;
+        }
+        text = Cow::Owned(lowered);
+    }
+
+    if sugar {
+        // The expression sugar is layered on top of the string-type sugar
+        text = Cow::Owned(transform_string_types(&text));
+    }
+
+    if intrinsics::uses_intrinsics(&text) {
+        let lowered = intrinsics::lower_intrinsics(&text);
+        if lowered != *text {
+            require_gc("string intrinsics")?;
+        }
+        text = Cow::Owned(lowered);
+    }
+
+    // Imports of the standard library are linked once the sugar, which reads
+    // their signatures, is lowered
+    if stdlib && stdlib::uses_stdlib(&text) {
+        let linked = stdlib::link(&text).map_err(CompileError::LinkError)?;
+        if linked.needs_gc {
+            require_gc("the WAT standard library")?;
+        }
+        text = Cow::Owned(linked.text);
+    }
+
+    Ok(text)
+}
+
+/// Remove custom sections (section 0) except the name section, which the
+/// loader reads for struct field names
+fn strip_custom_sections(binary: &mut Vec<u8>) {
+    if binary.len() < 8 || &binary[0..4] != b"\0asm" {
+        return;
+    }
+
+    let mut i = 8;
+    while i < binary.len() {
+        let (size, size_len) = read_leb128_u32(&binary[i + 1..]);
+        let payload = i + 1 + size_len;
+        let end = payload + size as usize;
+        if end > binary.len() {
+            return;
+        }
+
+        if binary[i] == 0 {
+            let (name_len, name_len_size) = read_leb128_u32(&binary[payload..end]);
+            let name_start = payload + name_len_size;
+            let name = binary
+                .get(name_start..name_start + name_len as usize)
+                .unwrap_or_default();
+            if name != b"name" {
+                log::info!(
+                    target: LOG_TARGET, phase = "custom section stripping", bytes = end - i;
+                    "Stripping custom section {:?}", String::from_utf8_lossy(name)
+                );
+                binary.drain(i..end);
+                continue;
+            }
+        }
+        i = end;
+    }
+}
+
+/// Inject the datacount section (section 12) if missing
+/// The datacount section is required for bulk memory operations including array.new_data
+/// wasm-tools 1.243.0 doesn't generate this section, so we inject it manually.
+/// It counts the segments of the data section, active and passive alike, and
+/// goes where the spec orders it: before the code section, or the data
+/// section if the module has no code or lists its data first. A data section
+/// without segments needs none.
+fn inject_datacount_section(binary: &mut Vec<u8>) {
+    if binary.len() < 8 || &binary[0..4] != b"\0asm" {
+        return;
+    }
+
+    let (mut active, mut passive) = (0u32, 0u32);
+    let mut datacount_offset = None;
+    // Sections start where the previous one ends, whatever the encoding of
+    // their sizes
+    let mut section_start = 8;
+    for payload in wasmparser::Parser::new(0).parse_all(binary) {
+        let Ok(payload) = payload else {
+            // The engine reports malformed modules
+            return;
+        };
+        match &payload {
+            wasmparser::Payload::DataCountSection { .. } => {
+                log::info!(
+                    target: LOG_TARGET, phase = "datacount section";
+                    "Datacount section already present"
+                );
+                return;
+            },
+            wasmparser::Payload::DataSection(reader) => {
+                for data in reader.clone() {
+                    match data.map(|data| data.kind) {
+                        Ok(wasmparser::DataKind::Active { .. }) => active += 1,
+                        Ok(wasmparser::DataKind::Passive) => passive += 1,
+                        Err(_) => return,
+                    }
+                }
+            },
+            _ => {},
+        }
+        if let Some((id, range)) = payload.as_section() {
+            if id == 10 || id == 11 {
+                datacount_offset.get_or_insert(section_start);
+            }
+            section_start = range.end;
+        }
+    }
+
+    let count = active + passive;
+    let Some(offset) = datacount_offset.filter(|_| count > 0) else {
+        return;
+    };
+    log::info!(
+        target: LOG_TARGET, phase = "datacount section", count, active, passive;
+        "Injecting datacount section (count={}: {} active, {} passive) at offset {}",
+        count,
+        active,
+        passive,
+        offset
+    );
+    let mut payload = Vec::new();
+    write_leb128_u32(count, &mut payload);
+    let mut section = vec![12];
+    write_leb128_u32(payload.len() as u32, &mut section);
+    section.extend(payload);
+    binary.splice(offset..offset, section);
+}
+
+/// Read LEB128 unsigned 32-bit integer
+fn read_leb128_u32(data: &[u8]) -> (u32, usize) {
+    let mut result = 0u32;
+    let mut shift = 0;
+    let mut pos = 0;
+
+    loop {
+        if pos >= data.len() {
+            break;
+        }
+
+        let byte = data[pos];
+        pos += 1;
+
+        result |= ((byte & 0x7F) as u32) << shift;
+        shift += 7;
+
+        if (byte & 0x80) == 0 {
+            break;
+        }
+
+        if shift >= 32 {
+            break;
+        }
+    }
+
+    (result, pos)
+}
+
+/// Write LEB128 unsigned 32-bit integer
+fn write_leb128_u32(mut value: u32, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Clear the compilation cache (useful for testing or memory management)
+pub fn clear_cache() {
+    get_cache().write().clear();
+    get_validators().write().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_transformation() {
+        let source = r#"(module
+  (type $Box (struct (field $val (mut string))))
+  (global $box (export "box") (ref $Box) (struct.new $Box "hello"))
+)"#;
+
+        let transformed = transform_string_types(source);
+        println!("Transformed WAT:\n{}", transformed);
+
+        // Check that string type was added
+        assert!(transformed.contains("(type $string (array (mut i8)))"));
+
+        // Check that string references were replaced
+        assert!(transformed.contains("(ref null $string)"));
+
+        // Check that string literal was transformed
+        assert!(transformed.contains("array.new_fixed $string"));
+    }
+
+    #[test]
+    fn test_simple_wasm() {
+        let source = r#"
+            (module
+              (func $add (param $a i32) (param $b i32) (result i32)
+                local.get $a
+                local.get $b
+                i32.add)
+              (export "add" (func $add)))
+        "#;
+
+        let result = compile_wat_to_js(source, "test.wat", None, &CompileOptions::default());
+        assert!(result.is_ok());
+
+        let js = result.unwrap();
+        assert!(js.contains("WebAssembly"));
+        assert!(js.contains("data:application/wasm;base64,"));
+    }
+
+    #[test]
+    fn test_caching() {
+        clear_cache();
+
+        let source = "(module)";
+
+        // First compilation
+        let result1 = compile_wat_to_js(source, "test.wat", None, &CompileOptions::default());
+        assert!(result1.is_ok());
+
+        // Second compilation (should hit cache)
+        let result2 = compile_wat_to_js(source, "test.wat", None, &CompileOptions::default());
+        assert!(result2.is_ok());
+
+        assert_eq!(result1.unwrap(), result2.unwrap());
+    }
+
+    #[test]
+    fn test_sugar_arithmetic_and_control_flow() {
+        let source = r#"(module (@sugar)
+  (func $clamp (export "clamp") (param $x f64) (param $limit f64) (result f64)
+    let $y = $x * 2 + 1
+    if $y > $limit { return $limit } else { $y = $y - 0.5 }
+    $y
+  )
+  (func $sum (export "sum") (param $n i32) (result i32)
+    let $total = 0
+    while $n > 0 {
+      $total += $n
+      $n -= 1
+    }
+    $total
+  )
+)"#;
+
+        let desugared = sugar::desugar(source).unwrap();
+        assert!(desugared.contains("(local $y f64)"));
+        assert!(desugared.contains(
+            "(local.set $y (f64.add (f64.mul (local.get $x) (f64.const 2)) (f64.const 1)))"
+        ));
+        assert!(desugared.contains("(if (f64.gt (local.get $y) (local.get $limit)) (then"));
+        assert!(desugared.contains("(local $total i32)"));
+        assert!(
+            desugared.contains("(local.set $total (i32.add (local.get $total) (local.get $n)))")
+        );
+        assert!(desugared.contains("(local.set $n (i32.sub (local.get $n) (i32.const 1)))"));
+        assert!(compile_wat_internal(source, "sugar.wat", options::all_features()).is_ok());
+    }
+
+    #[test]
+    fn test_sugar_errors() {
+        let source = "(module (@sugar)\n  (func $f (result i32)\n    $missing + 1\n  )\n)";
+        let result = compile_wat_internal(source, "sugar.wat", options::all_features());
+        assert!(matches!(result, Err(CompileError::SugarError(ref msg)) if msg.contains("line 3")));
+    }
+
+    #[test]
+    fn test_number_formatting_intrinsics() {
+        let source = r#"(module
+  (func (export "label") (param $score i32) (result (ref $string))
+    (i32.to_string (local.get $score)))
+  (func (export "ratio") (param $value f64) (result (ref $string))
+    local.get $value
+    f64.to_string)
+  ;; i32.to_string in a comment stays as written
+)"#;
+
+        let lowered = intrinsics::lower_intrinsics(source);
+        assert!(lowered.contains("(call $__i32_to_string (local.get $score))"));
+        assert!(lowered.contains("call $__f64_to_string"));
+        assert!(lowered.contains(";; i32.to_string in a comment"));
+        assert!(lowered.contains("(type $string (array (mut i8)))"));
+
+        let binary = compile_wat_internal(source, "format.wat", options::all_features()).unwrap();
+        assert!(wasmparser::validate(&binary).is_ok());
+    }
+
+    #[test]
+    fn test_start_policy() {
+        let source = r#"(module
+  (global $counter (mut i32) (i32.const 0))
+  (func $init
+    (global.set $counter (i32.const 1)))
+  (func (export "counter") (result i32)
+    global.get $counter)
+  (start $init))"#;
+        let binary = compile_wat_internal(source, "start.wat", options::all_features()).unwrap();
+
+        let has_start = |binary: &[u8]| {
+            wasmparser::Parser::new(0)
+                .parse_all(binary)
+                .any(|payload| matches!(payload, Ok(wasmparser::Payload::StartSection { .. })))
+        };
+        let exports = |binary: &[u8]| {
+            wasmparser::Parser::new(0)
+                .parse_all(binary)
+                .filter_map(|payload| match payload {
+                    Ok(wasmparser::Payload::ExportSection(reader)) => Some(reader),
+                    _ => None,
+                })
+                .flat_map(|reader| {
+                    reader
+                        .into_iter()
+                        .map(|export| export.unwrap().name.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut run = binary.clone();
+        assert!(!start::apply_start_policy(&mut run, StartPolicy::Run));
+        assert_eq!(run, binary);
+
+        let mut skipped = binary.clone();
+        assert!(!start::apply_start_policy(&mut skipped, StartPolicy::Skip));
+        assert!(wasmparser::validate(&skipped).is_ok());
+        assert!(!has_start(&skipped));
+        assert_eq!(exports(&skipped), ["counter"]);
+
+        let mut deferred = binary.clone();
+        assert!(start::apply_start_policy(&mut deferred, StartPolicy::Defer));
+        assert!(wasmparser::validate(&deferred).is_ok());
+        assert!(!has_start(&deferred));
+        assert_eq!(
+            exports(&deferred),
+            ["counter", start::DEFERRED_START_EXPORT]
+        );
+
+        let options = CompileOptions {
+            start: StartPolicy::Defer,
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(source, "start.wat", None, &options).unwrap();
+        assert!(js.contains("result.instance.exports.__wasm_start()"));
+        assert_eq!(StartPolicy::parse(" Defer "), Some(StartPolicy::Defer));
+        assert_eq!(StartPolicy::parse("later"), None);
+    }
+
+    #[test]
+    fn test_compile_options_from_attributes() {
+        let attributes = HashMap::from([
+            ("data-opt", ""),
+            ("data-features", "gc, tail-call"),
+            ("data-namespace", "game"),
+            ("data-strings", "UTF16"),
+            ("data-debug", "false"),
+            ("data-start", "skip"),
+        ]);
+        let options =
+            CompileOptions::from_attributes(|name| attributes.get(name).map(|v| v.to_string()));
+        assert!(options.optimize);
+        assert!(!options.debug);
+        assert_eq!(options.namespace.as_deref(), Some("game"));
+        assert_eq!(options.strings, Some(options::StringEncoding::Utf16));
+        assert_eq!(options.start, StartPolicy::Skip);
+        let features = options.features.unwrap();
+        assert!(features.gc() && features.tail_call() && !features.threads());
+
+        // Invalid values fall back to the defaults
+        let options = CompileOptions::from_attributes(|name| match name {
+            "data-namespace" => Some("not a name".to_string()),
+            "data-strings" => Some("ebcdic".to_string()),
+            _ => None,
+        });
+        assert_eq!(options, CompileOptions::default());
+
+        let js = compile_wat_to_js(
+            r#"(module (func (export "answer") (result i32) i32.const 42))"#,
+            "options.wat",
+            None,
+            &CompileOptions {
+                namespace: Some("game".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(js.contains(r#"const exportTarget = (window["game"] = window["game"] || {});"#));
+    }
+
+    #[test]
+    fn test_feature_validation() {
+        let source = r#"(module
+  (type $point (struct (field $x i32)))
+  (func (export "make") (result (ref $point))
+    (struct.new $point (i32.const 1))))"#;
+        let with = |features: &str| {
+            CompileOptions::from_attributes(|name| {
+                (name == "data-features").then(|| features.to_string())
+            })
+        };
+
+        assert!(compile_wat_to_js(source, "gc.wat", None, &with("gc")).is_ok());
+        let result = compile_wat_to_js(source, "gc.wat", None, &with("threads"));
+        assert!(matches!(result, Err(CompileError::ValidationError(_))));
+    }
+
+    #[test]
+    fn test_capability_detection() {
+        for (name, _, sections) in capabilities::PROBES {
+            let (_, flags) = options::FEATURES
+                .iter()
+                .find(|(feature, _)| *feature == name)
+                .unwrap();
+            let probe = capabilities::probe_module(sections);
+            let validate =
+                |features| wasmparser::Validator::new_with_features(features).validate_all(&probe);
+            assert!(
+                validate(wasmparser::WasmFeatures::WASM3).is_ok(),
+                "{} probe is invalid",
+                name
+            );
+            assert!(
+                validate(wasmparser::WasmFeatures::WASM3.difference(*flags)).is_err(),
+                "{} probe does not need {}",
+                name,
+                name
+            );
+        }
+
+        let gc = r#"(module (type $point (struct (field i32))))"#;
+        let gc = compile_wat_internal(gc, "gc.wat", options::all_features()).unwrap();
+        assert_eq!(capabilities::required_features(&gc), ["gc"]);
+        let plain = r#"(module (func (export "f") (result i32) i32.const 1))"#;
+        let plain = compile_wat_internal(plain, "plain.wat", options::all_features()).unwrap();
+        assert!(capabilities::required_features(&plain).is_empty());
+    }
+
+    #[test]
+    fn test_telemetry_counters() {
+        // Counters are process-wide and other tests compile concurrently
+        let before = telemetry::snapshot();
+        let source = r#"(module (func (export "telemetry") (result i32) i32.const 2647))"#;
+        compile_wat_to_js(source, "telemetry.wat", None, &CompileOptions::default()).unwrap();
+        compile_wat_to_js(source, "telemetry.wat", None, &CompileOptions::default()).unwrap();
+        assert!(
+            compile_wat_to_js(
+                "(module (func",
+                "broken.wat",
+                None,
+                &CompileOptions::default()
+            )
+            .is_err()
+        );
+        let after = telemetry::snapshot();
+
+        assert!(after.compiled > before.compiled);
+        assert!(after.cache_hits > before.cache_hits);
+        assert!(after.failures[0].1 > before.failures[0].1);
+        assert_eq!(after.failures[0].0, "parse");
+        assert_eq!(
+            after.compile_times.len(),
+            telemetry::COMPILE_TIME_BUCKETS_MS.len() + 1
+        );
+        assert!(after.compile_times.iter().sum::<u64>() >= after.compiled);
+        assert!(after.cache_hit_ratio() > 0.0 && after.cache_hit_ratio() < 1.0);
+
+        // Every kind of error is counted under its own name
+        let errors = [
+            CompileError::ParseError(String::new()),
+            CompileError::SugarError(String::new()),
+            CompileError::InterpolationError(String::new()),
+            CompileError::ValidationError(String::new()),
+            CompileError::InstrumentationError(String::new()),
+            CompileError::PatchError(String::new()),
+            CompileError::LinkError(String::new()),
+        ];
+        assert_eq!(errors.len(), CompileError::KINDS.len());
+        let before = telemetry::snapshot();
+        for error in &errors {
+            telemetry::record_failure(error);
+        }
+        let after = telemetry::snapshot();
+        for (index, error) in errors.iter().enumerate() {
+            assert_eq!(error.index(), index);
+            assert_eq!(after.failures[index].0, error.kind());
+            assert!(after.failures[index].1 > before.failures[index].1);
+        }
+    }
+
+    #[test]
+    fn test_cache_memory_report() {
+        let source = r#"(module (func (export "memory_report") (result i32) i32.const 2648))"#;
+        let filename = "https://example.com/memory-report.wat";
+        compile_wat_to_js(source, filename, None, &CompileOptions::default()).unwrap();
+
+        let mut reports = vec![];
+        profile_traits::mem::perform_memory_report(|ops| reports = memory::cache_reports(ops));
+        assert_eq!(reports[0].path, ["wasm-cache", "table"]);
+        let script = reports
+            .iter()
+            .find(|report| {
+                report
+                    .path
+                    .iter()
+                    .any(|name| name.contains("memory-report.wat"))
+            })
+            .expect("no report for the cached module");
+        assert_eq!(
+            script.path,
+            [
+                "wasm-cache",
+                "origin(https://example.com)",
+                "script(https://example.com/memory-report.wat)"
+            ]
+        );
+        assert!(script.size > 0);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let entry = |size: usize, last_used: usize| CacheEntry {
+            binary: vec![0; size],
+            metadata: ModuleMetadata::new("", &[]),
+            filename: "evicted.wat".to_string(),
+            last_used: AtomicUsize::new(last_used),
+            hash_algorithm: ContentHash::ALGORITHM,
+        };
+        let key = |n: u8| ContentHash::of(&[n]);
+        let mut cache = HashMap::from([
+            (key(1), entry(100, 3)),
+            (key(2), entry(200, 1)),
+            (key(3), entry(300, 2)),
+        ]);
+        // The least recently used go first
+        assert_eq!(memory::evict(&mut cache, 2, usize::MAX), 200);
+        assert!(!cache.contains_key(&key(2)));
+        assert_eq!(memory::evict(&mut cache, 2, 150), 300);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [&key(1)]);
+        assert_eq!(memory::evict(&mut cache, 2, 150), 0);
+        assert_eq!(memory::evict(&mut cache, 0, 0), 100);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cached_metadata() {
+        let source = r#"(module
+  (type $cached_point (struct (field $x (mut i32)) (field $y (mut i32))))
+  (func (export "cached_metadata") (result i32) i32.const 2650))"#;
+        let miss =
+            compile_wat_to_js(source, "metadata.wat", None, &CompileOptions::default()).unwrap();
+        let hit =
+            compile_wat_to_js(source, "metadata.wat", None, &CompileOptions::default()).unwrap();
+        assert_eq!(miss, hit);
+
+        let cache = get_cache().read();
+        let entry = cache
+            .get(&ContentHash::of(source.as_bytes()))
+            .expect("module should be cached");
+        assert_eq!(entry.metadata, ModuleMetadata::new(source, &entry.binary));
+        assert!(entry.metadata.field_names_json.contains("cached_point"));
+        assert!(hit.contains(&entry.metadata.field_names_json));
+    }
+
+    #[test]
+    fn test_declared_imports() {
+        let source = r#"(module
+  (import "env" "alert" (func $alert (param i32)))
+  (import "console" "log" (func $log (param i32)))
+  (import "env" "memory" (memory 1))
+  (func (export "main") i32.const 2651 call $alert))"#;
+        let binary = compile_bench(source).unwrap();
+        assert_eq!(
+            imports::declared_imports(&binary),
+            [
+                ("env".to_string(), "alert".to_string(), "function"),
+                ("console".to_string(), "log".to_string(), "function"),
+                ("env".to_string(), "memory".to_string(), "memory"),
+            ]
+        );
+
+        let js =
+            compile_wat_to_js(source, "imports.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#"const wasmImports = [["env","alert","function"],["console","log","function"]"#
+        ));
+        assert!(!js.contains("for (const key in window)"));
+    }
+
+    #[test]
+    fn test_latin1_embedding() {
+        assert_eq!(
+            embed::latin1_literal(&[0x00, b'A', b'"', b'\\', 0x0A, 0x7F, 0x9F, 0xA0, 0xFF]),
+            "\"\\x00A\\\"\\\\\\x0a\\x7f\\x9f\u{a0}\u{ff}\""
+        );
+
+        let source = r#"(module (func (export "embedded") (result i32) i32.const 2652))"#;
+        let js = compile_wat_to_js(source, "embed.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const wasmByteChunks = [\"\\x00asm\\x01\\x00\\x00\\x00"));
+
+        assert_eq!(
+            embed::chunked_literals(b"abcde", 2),
+            "[\"ab\",\n\"cd\",\n\"e\"]"
+        );
+        assert_eq!(
+            embed::chunked_literals(b"abcde", embed::CHUNK_SIZE),
+            "[\"abcde\"]"
+        );
+        assert_eq!(embed::chunked_literals(b"", 2), "[\"\"]");
+    }
+
+    #[test]
+    fn test_glue_escaping() {
+        let source = r#"(module
+  (type $</script><script>alert// (struct (field $a\b (mut i32)) (field $c (mut i32))))
+  (func (export "hostile") (result i32) i32.const 2654))"#;
+        let filename = "x</script><!--\u{2028}.wat";
+        let callback = "console.log('\\'); }); alert('escaped'); (function() {";
+        let js = compile_wat_to_js(source, filename, Some(callback), &CompileOptions::default())
+            .unwrap();
+
+        assert!(!js.contains("</script>"));
+        assert!(!js.contains("<!--"));
+        assert!(!js.contains('\u{2028}'));
+        assert!(js.contains(r#"const wasmFilename = "x\u003c/script>\u003c!--\u2028.wat";"#));
+        assert!(js.contains(&format!("new Function({})", embed::script_json(&callback))));
+
+        let field_names = js
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("const structFieldNames = "))
+            .and_then(|json| json.strip_suffix(';'))
+            .expect("field names should be in the glue");
+        let field_names: serde_json::Value = serde_json::from_str(field_names).unwrap();
+        assert_eq!(
+            field_names["default"]["typeName"],
+            "</script><script>alert//"
+        );
+        assert_eq!(field_names["default"]["fields"][0], "a\\b");
+    }
+
+    #[test]
+    fn test_enabled_features() {
+        let without_gc = CompileOptions {
+            enabled_features: Some(parse_feature_list("threads, tail-call")),
+            ..CompileOptions::default()
+        };
+        let gc = r#"(module (type $enabled_point (struct (field i32))))"#;
+        let result = compile_wat_to_js(gc, "enabled.wat", None, &without_gc);
+        assert!(
+            matches!(result, Err(CompileError::ValidationError(ref e)) if e.contains("enabled wasm features"))
+        );
+
+        // data-features cannot enable what preferences disable
+        let listed = CompileOptions {
+            features: Some(parse_feature_list("gc")),
+            ..without_gc.clone()
+        };
+        assert!(!listed.effective_features().contains(WasmFeatures::GC));
+        assert!(compile_wat_to_js(gc, "enabled.wat", None, &listed).is_err());
+
+        let sugar = "(module (@sugar)\n  (func (export \"f\") (result i32) 1 + 2))";
+        let result = compile_wat_to_js(sugar, "enabled-sugar.wat", None, &without_gc);
+        assert!(
+            matches!(result, Err(CompileError::ValidationError(ref e)) if e.contains("needs wasm GC"))
+        );
+
+        let plain = r#"(module (func (export "enabled_plain") (result i32) i32.const 2655))"#;
+        let js = compile_wat_to_js(plain, "enabled-plain.wat", None, &without_gc).unwrap();
+        let enabled = js
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("const enabledFeatures = "))
+            .expect("enabled features should be in the glue");
+        assert!(enabled.contains("\"threads\"") && enabled.contains("\"tail-call\""));
+        assert!(!enabled.contains("\"gc\""));
+    }
+
+    #[test]
+    fn test_syntax_classification() {
+        use syntax::TokenKind::*;
+
+        let source = concat!(
+            "(module (@sugar) ;; note\n",
+            "  (func $f (result f64) (; a (; nested ;) ;) ",
+            "f64.const -1.5e3 \"\\\"x\" nan:0x1 $\"q d\"))",
+        );
+        let tokens: Vec<_> = syntax::classify(source)
+            .into_iter()
+            .map(|span| (span.kind, &source[span.start..span.end]))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (Punctuation, "("),
+                (Keyword, "module"),
+                (Punctuation, "("),
+                (Keyword, "@sugar"),
+                (Punctuation, ")"),
+                (Comment, ";; note"),
+                (Punctuation, "("),
+                (Keyword, "func"),
+                (Identifier, "$f"),
+                (Punctuation, "("),
+                (Keyword, "result"),
+                (Keyword, "f64"),
+                (Punctuation, ")"),
+                (Comment, "(; a (; nested ;) ;)"),
+                (Keyword, "f64.const"),
+                (Literal, "-1.5e3"),
+                (Literal, "\"\\\"x\""),
+                (Literal, "nan:0x1"),
+                (Identifier, "$\"q d\""),
+                (Punctuation, ")"),
+                (Punctuation, ")"),
+            ]
+        );
+
+        // Unterminated strings and comments end the span, not the classifier
+        let spans = syntax::classify("(data \"open\n(; open");
+        assert_eq!((spans[2].kind, spans[2].end), (Literal, 11));
+        assert_eq!(spans[3].kind, Comment);
+        assert_eq!(spans.len(), 4);
+    }
+
+    #[test]
+    fn test_coverage_instrumentation() {
+        use coverage::{Coverage, FunctionCounters};
+
+        let source = r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (func $abs (export "abs") (param $x i32) (result i32)
+    (if (result i32) (i32.lt_s (local.get $x) (i32.const 0))
+      (then (i32.sub (i32.const 0) (local.get $x)))
+      (else (local.get $x))))
+  (func (export "count") (param $n i32)
+    (loop $l
+      (call $log (local.get $n))
+      (br_if $l (local.tee $n (i32.sub (local.get $n) (i32.const 1))))))
+  (func (nop)))"#;
+        let binary = compile_wat_internal(source, "coverage.wat", options::all_features()).unwrap();
+        let exports = |binary: &[u8]| {
+            wasmparser::Parser::new(0)
+                .parse_all(binary)
+                .filter_map(|payload| match payload {
+                    Ok(wasmparser::Payload::ExportSection(reader)) => Some(reader.count()),
+                    _ => None,
+                })
+                .sum::<u32>()
+        };
+
+        let mut off = binary.clone();
+        assert_eq!(coverage::instrument(&mut off, Coverage::Off).unwrap(), None);
+        assert_eq!(off, binary);
+
+        let mut functions = binary.clone();
+        let layout = coverage::instrument(&mut functions, Coverage::Functions)
+            .unwrap()
+            .unwrap();
+        assert!(wasmparser::validate(&functions).is_ok());
+        assert!(!layout.blocks);
+        let names: Vec<_> = layout
+            .functions
+            .iter()
+            .map(|f| (f.index, f.name.as_str()))
+            .collect();
+        assert_eq!(names, [(1, "abs"), (2, "count"), (3, "func[3]")]);
+        assert_eq!(exports(&functions), exports(&binary) + 3);
+
+        let mut blocks = binary.clone();
+        let layout = coverage::instrument(&mut blocks, Coverage::Blocks)
+            .unwrap()
+            .unwrap();
+        assert!(wasmparser::validate(&blocks).is_ok());
+        assert_eq!(
+            layout.functions[1],
+            FunctionCounters {
+                index: 2,
+                name: "count".to_string(),
+                counter: 3,
+                blocks: 1,
+            }
+        );
+        assert_eq!(exports(&blocks), exports(&binary) + 6);
+
+        // Modules without globals or exports get new sections
+        let mut bare =
+            compile_wat_internal("(module (func))", "bare.wat", options::all_features()).unwrap();
+        coverage::instrument(&mut bare, Coverage::Blocks).unwrap();
+        assert!(wasmparser::validate(&bare).is_ok());
+        assert_eq!(exports(&bare), 1);
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-coverage").then(|| "Blocks".to_string())
+        });
+        assert_eq!(options.coverage, Coverage::Blocks);
+        let js = compile_wat_to_js(source, "coverage.wat", None, &options).unwrap();
+        assert!(js.contains("window.__wasmCoverage = window.__wasmCoverage ||"));
+        assert!(js.contains(r#""name":"count","counter":3,"blocks":1"#));
+        assert_eq!(Coverage::parse(""), Some(Coverage::Functions));
+        assert_eq!(Coverage::parse("lines"), None);
+    }
+
+    #[test]
+    fn test_call_tracing() {
+        use trace::Trace;
+
+        let source = r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (table 1 funcref)
+  (elem (i32.const 0) $double)
+  (func $double (param $x i32) (result i32) (i32.mul (local.get $x) (i32.const 2)))
+  (func $main (export "main") (param $k i32) (param $v v128) (result i32)
+    (call $log (local.get $k))
+    (call $double (local.get $k)))
+  (func $init)
+  (start $init))"#;
+        let binary = compile_wat_internal(source, "trace.wat", options::all_features()).unwrap();
+
+        let mut off = binary.clone();
+        assert_eq!(trace::instrument(&mut off, Trace::Off).unwrap(), None);
+        assert_eq!(off, binary);
+
+        // v128 arguments cannot be passed to JavaScript
+        let mut exports = binary.clone();
+        let layout = trace::instrument(&mut exports, Trace::Exports)
+            .unwrap()
+            .unwrap();
+        assert!(wasmparser::validate(&exports).is_ok());
+        assert_eq!(layout.imports, ["call"]);
+        assert_eq!(
+            layout.names,
+            std::collections::BTreeMap::from([(2, "main".to_string())])
+        );
+        assert_eq!(
+            imports::declared_imports(&exports),
+            [
+                ("env".to_string(), "log".to_string(), "function"),
+                (
+                    trace::IMPORT_MODULE.to_string(),
+                    "call".to_string(),
+                    "function"
+                ),
+            ]
+        );
+
+        let mut all = binary.clone();
+        let layout = trace::instrument(&mut all, Trace::All).unwrap().unwrap();
+        assert!(wasmparser::validate(&all).is_ok());
+        assert_eq!(layout.imports, ["call", "call1"]);
+        let names: Vec<_> = layout.names.values().map(String::as_str).collect();
+        assert_eq!(names, ["double", "main", "init"]);
+        // Renumbered references keep their names
+        assert_eq!(instrument::function_names(&all).unwrap()[&5], "init");
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-trace").then(|| "all".to_string())
+        });
+        assert_eq!(options.trace, Trace::All);
+        let js = compile_wat_to_js(source, "trace.wat", None, &options).unwrap();
+        assert!(js.contains("importObject['__wasm_trace'][name] = traceCall;"));
+        assert!(js.contains(r#""names":{"1":"double","2":"main","3":"init"}"#));
+        assert_eq!(Trace::parse(" Exports"), Some(Trace::Exports));
+        assert_eq!(Trace::parse("some"), None);
+    }
+
+    #[test]
+    fn test_breakpoint_hooks() {
+        let source = r#"(module
+  (func $helper (param f64) (result f64) (local.get 0))
+  (func (export "run") (result f64) (call $helper (f64.const 1))))"#;
+        let binary = compile_wat_internal(source, "break.wat", options::all_features()).unwrap();
+
+        let mut off = binary.clone();
+        assert_eq!(breakpoints::instrument(&mut off, false).unwrap(), None);
+        assert_eq!(off, binary);
+
+        let mut hooked = binary.clone();
+        let names = breakpoints::instrument(&mut hooked, true).unwrap().unwrap();
+        assert!(wasmparser::validate(&hooked).is_ok());
+        let names: Vec<_> = names.values().map(String::as_str).collect();
+        assert_eq!(names, ["helper", "run"]);
+        assert_eq!(
+            imports::declared_imports(&hooked),
+            [(
+                breakpoints::IMPORT_MODULE.to_string(),
+                breakpoints::IMPORT_NAME.to_string(),
+                "function"
+            )]
+        );
+
+        // Passes stack: tracing adds an import per signature after this one
+        trace::instrument(&mut hooked, trace::Trace::All).unwrap();
+        assert!(wasmparser::validate(&hooked).is_ok());
+        assert_eq!(imports::declared_imports(&hooked).len(), 3);
+
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-breakpoints").then(String::new));
+        assert!(options.breakpoints);
+        let js = compile_wat_to_js(source, "break.wat", None, &options).unwrap();
+        assert!(js.contains("window.__wasmDebugger = window.__wasmDebugger ||"));
+        assert!(js.contains(r#"const wasmBreakNames = {"0":"helper","1":"run"};"#));
+    }
+
+    #[test]
+    fn test_self_profiling() {
+        let source = r#"(module
+  (func $one (export "one") (result i32) (i32.const 1))
+  (func $pair (param i32) (result i32 i32)
+    (if (local.get 0) (then (return (i32.const 3) (i32.const 4))))
+    (i32.const 1) (i32.const 2)))"#;
+        let binary = compile_wat_internal(source, "profile.wat", options::all_features()).unwrap();
+
+        let mut off = binary.clone();
+        assert_eq!(profile::instrument(&mut off, false).unwrap(), None);
+        assert_eq!(off, binary);
+
+        let mut profiled = binary.clone();
+        let names = profile::instrument(&mut profiled, true).unwrap().unwrap();
+        assert!(wasmparser::validate(&profiled).is_ok());
+        let names: Vec<_> = names.values().map(String::as_str).collect();
+        assert_eq!(names, ["one", "pair"]);
+        let imports: Vec<_> = imports::declared_imports(&profiled)
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect();
+        assert_eq!(imports, ["enter", "leave"]);
+
+        // The body is wrapped in a block followed by the exit call
+        let bodies: Vec<_> = wasmparser::Parser::new(0)
+            .parse_all(&profiled)
+            .filter_map(|payload| match payload {
+                Ok(wasmparser::Payload::CodeSectionEntry(body)) => Some(body),
+                _ => None,
+            })
+            .collect();
+        let operators = |body: &wasmparser::FunctionBody| -> Vec<String> {
+            body.get_operators_reader()
+                .unwrap()
+                .into_iter()
+                .map(|operator| format!("{:?}", operator.unwrap()))
+                .collect()
+        };
+        assert_eq!(
+            operators(&bodies[0]),
+            [
+                "I32Const { value: 0 }",
+                "Call { function_index: 0 }",
+                "Block { blockty: Type(I32) }",
+                "I32Const { value: 1 }",
+                "End",
+                "I32Const { value: 0 }",
+                "Call { function_index: 1 }",
+                "End",
+            ]
+        );
+        // `return` calls the exit import first
+        let pair = operators(&bodies[1]);
+        let exit = pair
+            .iter()
+            .position(|operator| operator == "Return")
+            .unwrap();
+        assert_eq!(pair[exit - 1], "Call { function_index: 1 }");
+        assert!(pair[2].starts_with("Block { blockty: FuncType("));
+
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-profile").then(String::new));
+        assert!(options.profile);
+        let js = compile_wat_to_js(source, "profile.wat", None, &options).unwrap();
+        assert!(js.contains("window.__wasmProfile = window.__wasmProfile ||"));
+        assert!(js.contains(r#"names: {"0":"one","1":"pair"}, totals: {} };"#));
+    }
+
+    #[test]
+    fn test_memory_growth_views() {
+        let source = r#"(module
+  (memory (export "memory") 1)
+  (func (export "grow") (result i32) (memory.grow (i32.const 1))))"#;
+        let js = compile_wat_to_js(source, "memory.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("target[name + '_views'] = trackMemory(name, exported);"));
+        assert!(js.contains("new CustomEvent('memorygrow'"));
+        // Export wrappers check for growth, also when the call throws
+        assert!(
+            js.contains("} finally {\n                                    checkMemoryGrowth();")
+        );
+    }
+
+    #[test]
+    fn test_field_watchpoints() {
+        let source = r#"(module
+  (type $box (struct (field $hp (mut i32))))
+  (func (export "make") (result (ref $box)) (struct.new $box (i32.const 10))))"#;
+        let js = compile_wat_to_js(source, "watch.wat", None, &CompileOptions::default()).unwrap();
+        assert!(
+            js.contains("window.wasmWatch = window.wasmWatch || function(ref, field, callback) {")
+        );
+        // The proxy's set trap goes through the notifying setter
+        assert!(js.contains("structFieldSet(target, fieldName, wasmValue);"));
+        assert!(js.contains("callback(newValue, oldValue, String(fieldIndex));"));
+    }
+
+    #[test]
+    fn test_hot_state() {
+        let source = r#"(module (global (export "score") (mut i32) (i32.const 0)))"#;
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-hot-state").then(String::new));
+        assert!(options.hot_state);
+        let js = compile_wat_to_js(source, "hot.wat", None, &options).unwrap();
+        assert!(js.contains("const hotPrevious = window.__wasmHotState[wasmFilename];"));
+        assert!(js.contains("newGlobal.value = oldValue;"));
+
+        let js = compile_wat_to_js(source, "hot.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("__wasmHotState"));
+    }
+
+    #[test]
+    fn test_repl_fragment() {
+        let fragment = r#"(import "env" "bump" (func $bump))
+(func (export "bump_twice") (call $bump) (call $bump))"#;
+        assert!(repl::fragment_module(fragment).starts_with("(module\n(import"));
+        assert_eq!(repl::fragment_module("(module)"), "(module)");
+
+        let js =
+            compile_fragment(fragment, "page.html#wat-eval-1", &CompileOptions::default()).unwrap();
+        assert!(js.starts_with("new Promise(function(resolve, reject) {"));
+        assert!(js.contains(r#"const filename = "page.html#wat-eval-1";"#));
+        assert!(js.contains("resolve(event.detail.exports);"));
+        // The loader reports its filename and installed exports
+        assert!(js.contains("detail: { filename: wasmFilename, exports: installedExports }"));
+
+        assert!(
+            compile_fragment(
+                "(func (call $nope))",
+                "page.html#wat-eval-2",
+                &CompileOptions::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_shared_memory_isolation() {
+        let shared = r#"(module (memory (export "memory") 1 1 shared))"#;
+        let js = compile_wat_to_js(shared, "shared.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("if (window.crossOriginIsolated === false || typeof SharedArrayBuffer === 'undefined') {"));
+        assert!(
+            js.contains("['Cross-Origin-Embedder-Policy', ['require-corp', 'credentialless']]")
+        );
+
+        let imported =
+            wat::parse_str(r#"(module (import "env" "memory" (memory 1 1 shared)))"#).unwrap();
+        assert!(capabilities::uses_shared_memory(&imported));
+
+        let unshared = r#"(module (memory (export "memory") 1 1))"#;
+        let js =
+            compile_wat_to_js(unshared, "unshared.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("crossOriginIsolated"));
+    }
+
+    #[test]
+    fn test_optional_pass_fallback() {
+        let optional = r#"(module (memory 1) (data (i32.const 0) "hi") (func))"#;
+        let (binary, fallback) = compile_module(
+            optional.as_bytes(),
+            "optional.wat",
+            options::all_features(),
+            true,
+            None,
+            false,
+        )
+        .unwrap();
+        let fallback = fallback.expect("the datacount section is not needed");
+        assert_eq!(fallback.skipped, ["datacount section"]);
+        assert!(binary.len() > fallback.binary.len());
+
+        let js =
+            compile_wat_to_js(optional, "optional.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("if (!(e instanceof WebAssembly.CompileError)) {"));
+        assert!(js.contains(r#"const skippedPasses = ["datacount section"];"#));
+        assert!(js.contains("new CustomEvent('wasmwarning'"));
+
+        // Instrumentation refers to the instrumented module
+        let options = CompileOptions {
+            profile: true,
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(optional, "optional.wat", None, &options).unwrap();
+        assert!(!js.contains("skippedPasses"));
+
+        // memory.init needs the datacount section
+        let needed = r#"(module (memory 1) (data $d "hi")
+  (func (memory.init $d (i32.const 0) (i32.const 0) (i32.const 2))))"#;
+        let (_, fallback) = compile_module(
+            needed.as_bytes(),
+            "needed.wat",
+            options::all_features(),
+            true,
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(fallback.is_none());
+    }
+
+    #[test]
+    fn test_compile_file() {
+        // A binary module need not be valid UTF-8
+        let binary =
+            wat::parse_str(r#"(module (memory 1) (data (i32.const 0) "\ff\fe"))"#).unwrap();
+        assert!(std::str::from_utf8(&binary).is_err());
+        let js = compile_file(&binary, "dropped.wasm", &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"const filename = "dropped.wasm";"#));
+
+        let text = compile_file(
+            b"(module (func (export \"f\")))",
+            "dropped.wat",
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(text.contains(r#"const filename = "dropped.wat";"#));
+
+        // Text that is not UTF-8 is reported under the file's name
+        let err =
+            compile_file(b"(module \xff)", "broken.wat", &CompileOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("broken.wat"));
+    }
+
+    #[test]
+    fn test_compiled_bytes() {
+        let js = compile_wat_to_js(
+            "(module (memory 1) (data (i32.const 0) \"hi\") (func))",
+            "bytes.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(js.contains("window.__wasmModules[wasmFilename] = { bytes: wasmBytes };"));
+        // A retry without the optional passes replaces them
+        assert!(js.contains("window.__wasmModules[wasmFilename].bytes = fallbackBytes;"));
+    }
+
+    #[test]
+    fn test_instantiate_factory() {
+        let js = compile_wat_to_js(
+            r#"(module (import "env" "log" (func (param i32))) (func (export "f")))"#,
+            "game.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(
+            js.contains("window.__wasmModules[wasmFilename].instantiate = function(imports) {")
+        );
+        assert!(js.contains("return WebAssembly.instantiate(result.module, ownImports);"));
+        assert!(js.contains("const instanceImports = [];"));
+
+        // Runtime imports of the loader are not shared with other instances
+        let wasi = r#"(module (import "wasi_snapshot_preview1" "proc_exit" (func (param i32))) (memory (export "memory") 1))"#;
+        let js = compile_wat_to_js(wasi, "wasi.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const instanceImports = [wasiImports];"));
+    }
+
+    #[test]
+    fn test_dispose() {
+        let js = compile_wat_to_js(r#"(module (memory (export "memory") 1) (table (export "table") 1 funcref) (func (export "f")))"#, "app.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const disposeWasm = function(target, installed, exports) {"));
+        assert!(js.contains("window.dispatchEvent(new CustomEvent('wasmunloaded', {"));
+        assert!(js.contains("installedExtras[name + '_views'] = target[name + '_views'];"));
+        assert!(js.contains(
+            "return disposeWasm(exportTarget, Object.assign({}, installedExtras, installedExports),"
+        ));
+    }
+
+    #[test]
+    fn test_page_lifecycle() {
+        let source = r#"(module (global (export "score") (mut i32) (i32.const 0)))"#;
+        let js = compile_wat_to_js(source, "life.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const wasmLifecycle = { policy: 'suspend',"));
+        assert!(!js.contains("__wasmHotState"));
+
+        // Reloading takes over the state like data-hot-state
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-lifecycle").then(|| "Reload".to_string())
+        });
+        assert_eq!(options.lifecycle, options::Lifecycle::Reload);
+        let js = compile_wat_to_js(source, "life.wat", None, &options).unwrap();
+        assert!(js.contains("const wasmLifecycle = { policy: 'reload',"));
+        assert!(js.contains("wasmLifecycle.kept.push('__wasmHotState');"));
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-lifecycle").then(|| "pause".to_string())
+        });
+        assert_eq!(options.lifecycle, options::Lifecycle::Suspend);
+    }
+
+    #[test]
+    fn test_print_module() {
+        let binary = compile_wat_internal(
+            r#"(module (memory 1) (data (i32.const 0) "hi") (func (export "f")))"#,
+            "print.wat",
+            options::all_features(),
+        )
+        .unwrap();
+        let text = print_module(&binary).unwrap();
+        assert!(text.contains(r#"(export "f" (func"#));
+        assert!(text.contains(r#"(data (;0;) (i32.const 0) "hi")"#));
+        // The datacount section injected by the compiler has no text form
+        assert!(binary.len() > wat::parse_str(&text).unwrap().len());
+
+        assert!(print_module(b"not wasm").is_err());
+    }
+
+    #[test]
+    fn test_compressed_source() {
+        use std::io::Write;
+
+        let source = b"(module (func (export \"f\") (result i32) i32.const 2673))".to_vec();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&source).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut brotli = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        brotli.write_all(&source).unwrap();
+        let brotli = brotli.into_inner();
+
+        let limit = compressed::limit_from_pref(1);
+        assert_eq!(limit, Some(1 << 20));
+        assert_eq!(compressed::limit_from_pref(0), None);
+        assert_eq!(
+            compressed::decompress("/app.wat.gz", gzip.clone(), limit).unwrap(),
+            source
+        );
+        assert_eq!(
+            compressed::decompress("/app.wat.br", brotli.clone(), limit).unwrap(),
+            source
+        );
+        // Decoded by the fetch already, or not compressed
+        assert_eq!(
+            compressed::decompress("/app.wat.gz", source.clone(), limit).unwrap(),
+            source
+        );
+        assert_eq!(
+            compressed::decompress("/app.wat", gzip.clone(), limit).unwrap(),
+            gzip
+        );
+        assert!(
+            compressed::decompress("/app.wat.gz", b"\x1f\x8b garbage".to_vec(), limit).is_err()
+        );
+
+        // A source over the cap is an error, one at it is not
+        let cap = Some(source.len() as u64);
+        assert!(compressed::decompress("/app.wat.gz", gzip.clone(), cap).is_ok());
+        let under = Some(source.len() as u64 - 1);
+        assert!(
+            compressed::decompress("/app.wat.gz", gzip, under)
+                .unwrap_err()
+                .contains("dom_wat_scripts_max_source_mb")
+        );
+        assert!(compressed::decompress("/app.wat.br", brotli, under).is_err());
+        let mut bomb = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        bomb.write_all(&vec![b' '; 4 << 20]).unwrap();
+        let bomb = bomb.finish().unwrap();
+        assert!(bomb.len() < 64 << 10);
+        assert!(compressed::decompress("/bomb.wat.gz", bomb, limit).is_err());
+    }
+
+    #[test]
+    fn test_patch() {
+        use sha2::{Digest, Sha256};
+
+        let url = "https://example.com/patched.wat";
+        let old = b"(module (func (export \"f\") (result i32) i32.const 1))".to_vec();
+        assert_eq!(patch::resolve(url, old.clone()).unwrap(), old);
+        let old_hash: [u8; 32] = Sha256::digest(&old).into();
+        assert_eq!(patch::base_hash(url).unwrap().len(), 64);
+
+        // Keep the head, turn the 1 into a 2, insert a second function
+        let new = b"(module (func (export \"f\") (result i32) i32.const 2) (func))".to_vec();
+        let digit = old.iter().position(|byte| *byte == b'1').unwrap();
+        let mut body = b"\0wpt\x01".to_vec();
+        body.extend_from_slice(&old_hash);
+        body.extend_from_slice(&<[u8; 32]>::from(Sha256::digest(&new)));
+        body.extend_from_slice(&[0x00, 0, digit as u8]);
+        body.extend_from_slice(&[0x01, digit as u8, 1, 1]);
+        body.extend_from_slice(&[0x00, digit as u8 + 1, 1]);
+        body.extend_from_slice(&[0x02, 7]);
+        body.extend_from_slice(b" (func)");
+        body.extend_from_slice(&[0x00, digit as u8 + 2, 1]);
+        assert!(patch::is_patch(&body));
+        assert_eq!(patch::resolve(url, body.clone()).unwrap(), new);
+
+        // The base is now the patched module
+        let err = patch::resolve(url, body.clone()).unwrap_err();
+        assert!(err.to_string().contains("another version of the module"));
+        assert!(patch::apply_patch(&old, old_hash, &body[..body.len() - 1]).is_err());
+        assert!(patch::resolve("https://example.com/unknown.wat", body).is_err());
+    }
+
+    #[test]
+    fn test_validator_cache_key() {
+        let options = CompileOptions {
+            validator: Some(r#"https://example.com/validated.wat "v1""#.to_string()),
+            ..Default::default()
+        };
+        let first = compile_wat_to_js(
+            r#"(module (func (export "validated_one")))"#,
+            "validated.wat",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(first.contains("validated_one"));
+        // Same validator, so the source is taken to be the same
+        let again = compile_wat_to_js(
+            r#"(module (func (export "validated_two")))"#,
+            "validated.wat",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn test_page_defaults() {
+        let defaults = PageDefaults::parse("opt=2; Strings=utf16;namespace = app; debug");
+        assert_eq!(defaults.get("data-strings").as_deref(), Some("utf16"));
+        assert_eq!(defaults.get("data-debug").as_deref(), Some(""));
+        assert_eq!(defaults.get("data-trace"), None);
+
+        // Attributes of the script override the page defaults
+        let options = CompileOptions::from_attributes(|name| match name {
+            "data-namespace" => Some("mine".to_string()),
+            "data-debug" => Some("false".to_string()),
+            _ => defaults.get(name),
+        });
+        assert!(options.optimize);
+        assert_eq!(options.strings, Some(options::StringEncoding::Utf16));
+        assert_eq!(options.namespace.as_deref(), Some("mine"));
+        assert!(!options.debug);
+    }
+
+    #[test]
+    fn test_registry() {
+        let name = registry::RegisteredName {
+            origin: "https://example.com".to_string(),
+            name: "physics@1.2".to_string(),
+        };
+        let options = CompileOptions {
+            register: Some(name.clone()),
+            ..Default::default()
+        };
+        compile_wat_to_js(
+            r#"(module (func (export "step")))"#,
+            "physics.wat",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(registry::is_registered(&name));
+
+        let loaded =
+            registry::compile_registered(&name, "later.wat", None, &CompileOptions::default());
+        assert!(loaded.unwrap().unwrap().contains("step"));
+
+        // Names are scoped to the origin
+        let other = registry::RegisteredName {
+            origin: "https://example.org".to_string(),
+            ..name
+        };
+        assert!(!registry::is_registered(&other));
+        assert!(
+            registry::compile_registered(&other, "later.wat", None, &CompileOptions::default())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_inflight_compilation() {
+        let source = r#"(module (func (export "shared_stdlib")))"#;
+        // Another thread is compiling the source
+        let claim = inflight::Claim::new(ContentHash::of(source.as_bytes()));
+        let waiting = std::thread::spawn(move || {
+            compile_wat_to_js(source, "stdlib.wat", None, &CompileOptions::default())
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+        drop(claim);
+        assert!(waiting.join().unwrap().unwrap().contains("shared_stdlib"));
+    }
+
+    #[test]
+    fn test_speculative_compilation() {
+        let source = r#"(module (func (export "below_the_fold")))"#;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        speculative::compile(
+            source.into(),
+            "lazy.wat".to_string(),
+            CompileOptions::default(),
+            Default::default(),
+            move |cancelled| sender.send(cancelled).unwrap(),
+        );
+        assert!(!receiver.recv().unwrap());
+        assert!(
+            get_cache()
+                .read()
+                .contains_key(&ContentHash::of(source.as_bytes()))
+        );
+
+        // Hold the thread up so the next compilation is cancelled before it starts
+        let (resume, blocked) = std::sync::mpsc::channel::<()>();
+        speculative::compile(
+            vec![],
+            "blocker.wat".to_string(),
+            CompileOptions::default(),
+            Default::default(),
+            move |_| blocked.recv().unwrap(),
+        );
+        let removed = r#"(module (func (export "removed_first")))"#;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let speculation = speculative::compile(
+            removed.into(),
+            "removed.wat".to_string(),
+            CompileOptions::default(),
+            Default::default(),
+            move |cancelled| sender.send(cancelled).unwrap(),
+        );
+        speculation.cancel();
+        resume.send(()).unwrap();
+        assert!(receiver.recv().unwrap());
+        assert!(
+            !get_cache()
+                .read()
+                .contains_key(&ContentHash::of(removed.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn test_speculative_priority() {
+        use speculative::Priority;
+
+        assert_eq!(Priority::parse(" HIGH"), Some(Priority::High));
+        assert_eq!(Priority::parse("urgent"), None);
+
+        let (resume, blocked) = std::sync::mpsc::channel::<()>();
+        speculative::compile(
+            vec![],
+            "blocker.wat".to_string(),
+            CompileOptions::default(),
+            Priority::High,
+            move |_| blocked.recv().unwrap(),
+        );
+        // Queued behind the blocker, so they run in the order of the queue
+        let (sender, order) = std::sync::mpsc::channel();
+        for (name, priority) in [
+            ("auxiliary", Priority::Low),
+            ("widget", Priority::Auto),
+            ("hero", Priority::High),
+        ] {
+            let sender = sender.clone();
+            let source = format!(r#"(module (func (export "{}_priority")))"#, name);
+            speculative::compile(
+                source.into_bytes(),
+                format!("{}.wat", name),
+                CompileOptions::default(),
+                priority,
+                move |_| sender.send(name).unwrap(),
+            );
+        }
+        resume.send(()).unwrap();
+        let order: Vec<_> = order.iter().take(3).collect();
+        assert_eq!(order, ["hero", "widget", "auxiliary"]);
+    }
+
+    #[test]
+    fn test_display_limits() {
+        use options::DisplayLimits;
+
+        assert_eq!(
+            DisplayLimits::parse("depth=1, Length=50"),
+            Some(DisplayLimits {
+                depth: 1,
+                length: 50,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            DisplayLimits::parse("length=80").map(|limits| limits.depth),
+            Some(DisplayLimits::default().depth)
+        );
+        assert_eq!(
+            DisplayLimits::parse("string=50000").map(|limits| limits.string),
+            Some(50_000)
+        );
+        assert_eq!(DisplayLimits::parse("depth=0"), None);
+        assert_eq!(DisplayLimits::parse("width=3"), None);
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-display").then(|| "depth=2 length=64 string=500".to_string())
+        });
+        let js = compile_wat_to_js(
+            r#"(module (func (export "displayed")))"#,
+            "display.wat",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(js.contains("const displayDepth = 2;"));
+        assert!(js.contains("const displayLength = 64;"));
+        assert!(js.contains("const stringLimit = 500;"));
+    }
+
+    #[test]
+    fn test_struct_array_accessors() {
+        let source = r#"(module
+  (type $player (struct (field $name (mut i32)) (field $score (mut i32))))
+  (type $players (array (mut (ref null $player))))
+  (type $bytes (array (mut i8)))
+  (func (export "team") (result (ref $players))
+    (array.new_default $players (i32.const 2)))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        let struct_arrays = arrays::add_accessors(&mut binary).unwrap();
+        // Only the array of structs gets accessors
+        assert_eq!(struct_arrays.len(), 1);
+        assert_eq!((struct_arrays[0].array, struct_arrays[0].element), (1, 0));
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+        let exports = print_module(&binary).unwrap();
+        assert!(exports.contains(r#"(export "__wasm_array_get_1""#));
+        assert!(exports.contains(r#"(export "__wasm_array_len_1""#));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&arrays::loader_json(&struct_arrays, &binary)).unwrap();
+        assert_eq!(json[0]["element"]["typeName"], "player");
+        assert_eq!(
+            json[0]["element"]["fields"],
+            serde_json::json!(["name", "score"])
+        );
+
+        // The loader does not install the accessors on window
+        let js = compile_wat_to_js(source, "team.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#""get":"__wasm_array_get_1""#));
+
+        // Modules without functions get the sections in their place
+        let mut binary = wat::parse_str(
+            "(module (type $p (struct (field i32))) (type $ps (array (ref null $p))) (global (mut i32) (i32.const 0)))",
+        )
+        .unwrap();
+        assert_eq!(arrays::add_accessors(&mut binary).unwrap().len(), 1);
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_export_result_types() {
+        let source = r#"(module
+  (type $player (struct (field $name (mut i32)) (field $score (mut i32))))
+  (type $players (array (mut (ref null $player))))
+  (global $best (export "best") (ref null $player) (ref.null $player))
+  (func (export "spawn") (result (ref $player))
+    (struct.new $player (i32.const 1) (i32.const 2)))
+  (func (export "team") (result (ref $players))
+    (array.new_default $players (i32.const 2)))
+  (func (export "count") (result i32)
+    (i32.const 2))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        let struct_arrays = arrays::add_accessors(&mut binary).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&results::loader_json(&binary, &struct_arrays, &[])).unwrap();
+        assert_eq!(json["spawn"]["typeName"], "player");
+        assert_eq!(
+            json["spawn"]["fields"],
+            serde_json::json!(["name", "score"])
+        );
+        assert_eq!(json["best"]["typeName"], "player");
+        assert_eq!(json["team"], serde_json::json!({ "array": 1 }));
+        // Neither exports of other types nor the accessors are recorded
+        assert!(json.get("count").is_none());
+        assert_eq!(json.as_object().unwrap().len(), 3);
+
+        let js =
+            compile_wat_to_js(source, "results.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#""team":{"array":1}"#));
+        assert!(js.contains(r#""array":1,"element""#));
+    }
+
+    #[test]
+    fn test_string_results() {
+        let source = r#"(module
+  (type $string (array (mut i8)))
+  (type $text16 (array i16))
+  (type $bytes (array (mut i8)))
+  (global $title (export "title") (ref null $string) (ref.null $string))
+  (func (export "greet") (result (ref $string))
+    (array.new_fixed $string 2 (i32.const 104) (i32.const 105)))
+  (func (export "raw") (result (ref $bytes))
+    (array.new_default $bytes (i32.const 2)))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        // Only arrays named string are strings; byte buffers stay objects
+        assert_eq!(string_types.len(), 1);
+        assert_eq!(string_types[0].array, 0);
+        assert_eq!(string_types[0].unit, 8);
+        assert!(wasmparser::validate(&binary).is_ok());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&results::loader_json(&binary, &[], &string_types)).unwrap();
+        assert_eq!(json["greet"], serde_json::json!({ "string": 0 }));
+        assert_eq!(json["title"], serde_json::json!({ "string": 0 }));
+        assert!(json.get("raw").is_none());
+        assert_eq!(json.as_object().unwrap().len(), 2);
+
+        let js =
+            compile_wat_to_js(source, "strings.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#""get":"__wasm_string_get_0","len":"__wasm_string_len_0","new":"__wasm_string_new_0""#
+        ));
+        assert!(js.contains(r#""greet":{"string":0}"#));
+    }
+
+    #[test]
+    fn test_string_memory_copies() {
+        let mut binary = wat::parse_str("(module (type $string (array (mut i16))))").unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        assert_eq!(string_types[0].unit, 16);
+        assert_eq!(
+            string_types[0].to_memory.as_deref(),
+            Some("__wasm_string_to_memory_0")
+        );
+        assert_eq!(
+            string_types[0].from_memory.as_deref(),
+            Some("__wasm_string_from_memory_0")
+        );
+        assert!(wasmparser::validate(&binary).is_ok());
+        let exports: Vec<String> = wasmparser::Parser::new(0)
+            .parse_all(&binary)
+            .filter_map(|payload| match payload {
+                Ok(wasmparser::Payload::ExportSection(reader)) => Some(reader),
+                _ => None,
+            })
+            .flat_map(|reader| {
+                reader
+                    .into_iter()
+                    .flatten()
+                    .map(|export| export.name.to_string())
+            })
+            .collect();
+        assert!(exports.contains(&strings::MEMORY_EXPORT.to_string()));
+
+        // Modules with a memory of their own copy a code unit at a time
+        let mut binary =
+            wat::parse_str("(module (memory 1) (type $string (array (mut i8))))").unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        assert!(string_types[0].to_memory.is_none() && string_types[0].from_memory.is_none());
+        assert!(wasmparser::validate(&binary).is_ok());
+
+        // Immutable strings can only be copied out
+        let mut binary = wat::parse_str("(module (type $string (array i8)))").unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        assert!(string_types[0].to_memory.is_some() && string_types[0].from_memory.is_none());
+        assert!(wasmparser::validate(&binary).is_ok());
+    }
+
+    #[test]
+    fn test_linear_strings() {
+        let source = r#"(module
+  (memory 1)
+  (func (export "length") (param $ptr i32) (param $len i32) (result i32)
+    (local.get $len))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        assert!(strings::export_memory(&mut binary).unwrap());
+        assert!(wasmparser::validate(&binary).is_ok());
+        // Exported already
+        assert!(!strings::export_memory(&mut binary).unwrap());
+        let mut exported = wat::parse_str(r#"(module (memory (export "memory") 1))"#).unwrap();
+        assert!(!strings::export_memory(&mut exported).unwrap());
+        let mut memoryless = wat::parse_str("(module)").unwrap();
+        assert!(!strings::export_memory(&mut memoryless).unwrap());
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-strings").then(|| "linear".to_string())
+        });
+        let js = compile_wat_to_js(source, "linear.wat", None, &options).unwrap();
+        assert!(js.contains("const stringEncoding = 'linear';"));
+        assert!(
+            js.contains("args.flatMap(arg => typeof arg === 'string' ? linearString(arg) : [arg])")
+        );
+    }
+
+    #[test]
+    fn test_canonical_strings() {
+        let source = r#"(module
+  (memory (export "memory") 1)
+  (global $top (mut i32) (i32.const 1024))
+  (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+    (global.get $top)
+    (global.set $top (i32.add (global.get $top) (local.get 3))))
+  (func (export "echo") (param $ptr i32) (param $len i32) (result i32)
+    (i32.store (i32.const 16) (local.get $ptr))
+    (i32.store (i32.const 20) (local.get $len))
+    (i32.const 16))
+  (func (export "cabi_post_echo") (param i32))
+  (func (export "malloc") (param i32) (result i32) (i32.const 0))
+)"#;
+        // cabi_realloc is preferred to the allocators of linear strings
+        assert_eq!(
+            strings::detect_encoding(&wat::parse_str(source).unwrap()),
+            options::StringEncoding::Canonical
+        );
+        assert_eq!(
+            options::StringEncoding::parse("cabi"),
+            Some(options::StringEncoding::Canonical)
+        );
+        let js =
+            compile_wat_to_js(source, "canonical.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const stringEncoding = 'canonical';"));
+        assert!(js.contains(
+            "args.flatMap(arg => typeof arg === 'string' ? canonicalString(arg) : [arg])"
+        ));
+        assert!(js.contains("const postReturn = result.instance.exports['cabi_post_' + name];"));
+    }
+
+    #[test]
+    fn test_string_encoding_per_module() {
+        let detect = |source: &str| strings::detect_encoding(&wat::parse_str(source).unwrap());
+        let utf16 = r#"(module
+  (type $string (array (mut i16)))
+  (func (export "name") (result (ref null $string)) (ref.null $string))
+)"#;
+        assert_eq!(detect(utf16), options::StringEncoding::Utf16);
+        assert_eq!(
+            detect(
+                r#"(module (type $s (array (mut i16))) (func (export "newString") (param i32) (result (ref $s)) (array.new_default $s (local.get 0))))"#
+            ),
+            options::StringEncoding::Utf16
+        );
+        let linear = r#"(module (memory (export "memory") 1) (func (export "malloc") (param i32) (result i32) (i32.const 16)))"#;
+        assert_eq!(detect(linear), options::StringEncoding::Linear);
+        let js_string = r#"(module
+  (import "wasm:js-string" "length" (func $length (param externref) (result i32)))
+  (func (export "size") (param externref) (result i32) (call $length (local.get 0)))
+)"#;
+        assert_eq!(detect(js_string), options::StringEncoding::JsString);
+        assert_eq!(detect("(module (memory 1))"), options::StringEncoding::Utf8);
+
+        // Modules of a page each get the converters of their own encoding,
+        // unless data-strings chooses one
+        let js = compile_wat_to_js(utf16, "utf16.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const stringEncoding = 'utf16';"));
+        assert!(js.contains("const compileOptions = {};"));
+        assert!(js.contains("window.__wasmStrings[wasmFilename] = {"));
+        assert!(!js.contains("window._wasmExports.newString"));
+        let options = CompileOptions {
+            strings: Some(options::StringEncoding::Utf8),
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(utf16, "utf8.wat", None, &options).unwrap();
+        assert!(js.contains("const stringEncoding = 'utf8';"));
+        let js = compile_wat_to_js(linear, "linear.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const stringEncoding = 'linear';"));
+        let js = compile_wat_to_js(js_string, "js-string.wat", None, &CompileOptions::default())
+            .unwrap();
+        assert!(js.contains("const stringEncoding = 'js-string';"));
+        assert!(js.contains("const compileOptions = { builtins: ['js-string'] };"));
+        assert!(js.contains("'wasm:js-string': (function() {"));
+    }
+
+    #[test]
+    fn test_emscripten_runtime() {
+        let source = r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $sp (mut i32) (i32.const 65536))
+  (func (export "emscripten_stack_get_current") (result i32) (global.get $sp))
+  (func (export "_emscripten_stack_restore") (param i32) (global.set $sp (local.get 0)))
+  (func (export "_emscripten_stack_alloc") (param i32) (result i32)
+    (global.set $sp (i32.sub (global.get $sp) (local.get 0)))
+    (global.get $sp))
+  (func (export "__wasm_call_ctors"))
+  (func (export "main") (param i32 i32) (result i32) (i32.const 0))
+)"#;
+        let binary = wat::parse_str(source).unwrap();
+        assert_eq!(
+            emscripten::detect(&binary),
+            Some(emscripten::Runtime {
+                stack_save: Some("emscripten_stack_get_current".to_string()),
+                stack_restore: Some("_emscripten_stack_restore".to_string()),
+                stack_alloc: Some("_emscripten_stack_alloc".to_string()),
+                initialize: Some("__wasm_call_ctors".to_string()),
+                main: Some("main".to_string()),
+                ..Default::default()
+            })
+        );
+        let imported = wat::parse_str(
+            r#"(module (import "env" "emscripten_resize_heap" (func (param i32) (result i32))))"#,
+        )
+        .unwrap();
+        assert!(emscripten::detect(&imported).is_some());
+        // A main of its own does not make a module Emscripten's
+        let plain =
+            wat::parse_str(r#"(module (memory (export "memory") 1) (func (export "main")))"#)
+                .unwrap();
+        assert_eq!(emscripten::detect(&plain), None);
+
+        let js =
+            compile_wat_to_js(source, "emscripten.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const fallbackImports = [emscriptenImports, wasiImports];"));
+        assert!(
+            js.contains(
+                r#"const emscriptenRuntime = {"stackSave":"emscripten_stack_get_current","#
+            )
+        );
+        assert!(js.contains("Module.ccall = function(ident, returnType, argTypes, args, opts) {"));
+        assert!(js.contains("Module.cwrap = function(ident, returnType, argTypes, opts) {"));
+        let js = compile_wat_to_js(
+            r#"(module (func (export "main")))"#,
+            "plain.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(js.contains("const fallbackImports = [];"));
+        assert!(!js.contains("Module.ccall"));
+    }
+
+    #[test]
+    fn test_assemblyscript_runtime() {
+        let source = r#"(module
+  (import "env" "abort" (func (param i32 i32 i32 i32)))
+  (memory (export "memory") 1)
+  (global (export "__rtti_base") i32 (i32.const 2000))
+  (func (export "__new") (param i32 i32) (result i32) (i32.const 16))
+  (func (export "__pin") (param i32) (result i32) (local.get 0))
+  (func (export "__unpin") (param i32))
+  (func (export "length") (param i32) (result i32) (i32.const 0))
+)"#;
+        assert!(assemblyscript::is_assemblyscript(
+            &wat::parse_str(source).unwrap()
+        ));
+        // The runtime is pinned through its exports, a memory alone is not enough
+        let unpinned = r#"(module (memory (export "memory") 1) (func (export "__new") (param i32 i32) (result i32) (i32.const 16)))"#;
+        assert!(!assemblyscript::is_assemblyscript(
+            &wat::parse_str(unpinned).unwrap()
+        ));
+
+        let js = compile_wat_to_js(
+            source,
+            "assemblyscript.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(js.contains("const fallbackImports = [assemblyScriptImports];"));
+        assert!(js.contains("loader.__newString = function(str) {"));
+        assert!(js.contains(
+            "args.map(arg => typeof arg === 'string' ? assemblyScript.pinString(arg) : arg)"
+        ));
+        let js =
+            compile_wat_to_js(unpinned, "plain.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const assemblyScript = null;"));
+    }
+
+    #[test]
+    fn test_wasm_bindgen_modules() {
+        let source = |module: &str| {
+            format!(
+                r#"(module
+  (import "{}" "__wbg_log_1" (func (param i32)))
+  (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+)"#,
+                module
+            )
+        };
+        let bundler = wat::parse_str(source("./app_bg.js")).unwrap();
+        assert_eq!(
+            bindgen::bindings_module(&bundler).as_deref(),
+            Some("./app_bg.js")
+        );
+        let placeholder = wat::parse_str(r#"(module (import "__wbindgen_placeholder__" "__wbindgen_describe" (func (param i32))))"#).unwrap();
+        assert_eq!(
+            bindgen::bindings_module(&placeholder).as_deref(),
+            Some(bindgen::PLACEHOLDER_MODULE)
+        );
+        assert_eq!(
+            bindgen::bindings_module(
+                &wat::parse_str(r#"(module (import "env" "log" (func)))"#).unwrap()
+            ),
+            None
+        );
+
+        // Without its bindings the loader says what is missing
+        let js = compile_wat_to_js(
+            &source("./app_bg.js"),
+            "app.wasm",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(js.contains(r#"imports its bindings from \"./app_bg.js\""#));
+        assert!(js.contains(r#"data-wasm-bindgen=\"app_bg.js\""#));
+        assert!(
+            bindgen::missing_bindings(bindgen::PLACEHOLDER_MODULE).contains("not processed by it")
+        );
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-wasm-bindgen").then(|| " pkg/app_bg.js ".to_string())
+        });
+        assert_eq!(options.wasm_bindgen.as_deref(), Some("pkg/app_bg.js"));
+        let js = compile_wat_to_js(&source("./app_bg.js"), "app.wasm", None, &options).unwrap();
+        assert!(
+            js.contains(r#"const bindingsUrl = new URL("pkg/app_bg.js", document.baseURI).href;"#)
+        );
+        assert!(js.contains("bindings.__wbg_set_wasm(instance.exports);"));
+        // Other modules ignore it
+        let js = compile_wat_to_js(
+            r#"(module (func (export "f")))"#,
+            "plain.wat",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(!js.contains("bindingsUrl"));
+    }
+
+    #[test]
+    fn test_go_modules() {
+        let tinygo = r#"(module
+  (import "gojs" "syscall/js.valueGet" (func (param i64 i32 i32) (result i64)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "resume"))
+  (func (export "_start"))
+)"#;
+        let go = r#"(module
+  (import "gojs" "runtime.wasmExit" (func (param i32)))
+  (import "gojs" "syscall/js.valueGet" (func (param i32)))
+)"#;
+        assert_eq!(
+            tinygo::toolchain(&wat::parse_str(tinygo).unwrap()),
+            Some(tinygo::Toolchain::TinyGo)
+        );
+        assert_eq!(
+            tinygo::toolchain(&wat::parse_str(go).unwrap()),
+            Some(tinygo::Toolchain::Go)
+        );
+        let legacy =
+            wat::parse_str(r#"(module (import "env" "syscall/js.valueGet" (func)))"#).unwrap();
+        assert_eq!(tinygo::toolchain(&legacy), Some(tinygo::Toolchain::TinyGo));
+        assert_eq!(
+            tinygo::toolchain(&wat::parse_str(r#"(module (import "env" "log" (func)))"#).unwrap()),
+            None
+        );
+
+        // TinyGo modules get the imports of wasm_exec.js and are started
+        let js =
+            compile_wat_to_js(tinygo, "tinygo.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const fallbackImports = [goImports, wasiImports];"));
+        assert!(js.contains("'syscall/js.valueGet': (ref, ptr, len) =>"));
+        assert!(js.contains("go.start(result.instance);"));
+
+        // Go's are reported instead
+        let js = compile_wat_to_js(go, "go.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("module was built by Go (GOOS=js)"));
+        assert!(!js.contains("go.start(result.instance);"));
+    }
+
+    #[test]
+    fn test_wasi_http_modules() {
+        let source = r#"(module
+  (import "wasi:http/outgoing-handler@0.2.0" "handle" (func (param i32 i32 i32 i32)))
+  (import "wasi:http/types@0.2.0" "[constructor]fields" (func (result i32)))
+  (import "wasi:io/poll@0.2.0" "[method]pollable.block" (func (param i32)))
+  (import "wasi:cli/environment@0.2.0" "get-environment" (func (param i32)))
+  (memory (export "memory") 1)
+)"#;
+        assert_eq!(
+            wasi_http::shimmed_modules(&wat::parse_str(source).unwrap()),
+            [
+                "wasi:http/outgoing-handler@0.2.0",
+                "wasi:http/types@0.2.0",
+                "wasi:io/poll@0.2.0"
+            ]
+        );
+        // wasi-io alone, or other versions, are not sending requests with it
+        let streams = wat::parse_str(r#"(module (import "wasi:io/streams@0.2.0" "[resource-drop]input-stream" (func (param i32))))"#).unwrap();
+        assert!(wasi_http::shimmed_modules(&streams).is_empty());
+        let preview3 = wat::parse_str(r#"(module (import "wasi:http/types@0.3.0" "[constructor]fields" (func (result i32))))"#).unwrap();
+        assert!(wasi_http::shimmed_modules(&preview3).is_empty());
+
+        let js = compile_wat_to_js(source, "http.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const fallbackImports = [wasiHttpImports];"));
+        assert!(js.contains(r#"for (const module of ["wasi:http/outgoing-handler@0.2.0","wasi:http/types@0.2.0","wasi:io/poll@0.2.0"])"#));
+        assert!(js.contains("wasiHttp.exports = result.instance.exports;"));
+        let js = compile_wat_to_js(
+            r#"(module (func (export "f")))"#,
+            "plain.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(!js.contains("wasiHttpImports"));
+    }
+
+    #[test]
+    fn test_wasi_clocks_and_random() {
+        let source = r#"(module
+  (import "wasi_snapshot_preview1" "clock_time_get" (func (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+)"#;
+        assert!(wasi::imports_wasi(&wat::parse_str(source).unwrap()));
+        assert!(!wasi::imports_wasi(
+            &wat::parse_str(r#"(module (import "env" "clock_time_get" (func)))"#).unwrap()
+        ));
+
+        let js = compile_wat_to_js(source, "wasi.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const fallbackImports = [wasiImports];"));
+        assert!(js.contains("crypto.getRandomValues(new Uint8Array(wasiMemory.buffer"));
+        assert!(js.contains("wasiMemory = result.instance.exports.memory"));
+        let js = compile_wat_to_js(
+            r#"(module (func (export "f")))"#,
+            "plain.wat",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert!(!js.contains("wasiPreview1"));
+    }
+
+    #[test]
+    fn test_wasi_filesystem() {
+        use wasi::{Preopen, Storage};
+        let preopens = Preopen::parse_list(
+            "/data, /tmp=memory /cache/./app/ /data=memory relative /x=disk /a/../b",
+        );
+        assert_eq!(
+            preopens,
+            [
+                Preopen {
+                    path: "/data".to_string(),
+                    storage: Storage::Opfs
+                },
+                Preopen {
+                    path: "/tmp".to_string(),
+                    storage: Storage::Memory
+                },
+                Preopen {
+                    path: "/cache/app".to_string(),
+                    storage: Storage::Opfs
+                },
+            ]
+        );
+        assert_eq!(Preopen::parse_list("/")[0].path, "/");
+
+        let source = r#"(module
+  (import "wasi_snapshot_preview1" "path_open" (func (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+)"#;
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-wasi-preopens").then(|| "/data /tmp=memory".to_string())
+        });
+        let js = compile_wat_to_js(source, "fs.wasm", None, &options).unwrap();
+        assert!(js.contains(r#"const wasiPreopens = [{"path":"/data","storage":"opfs"},{"path":"/tmp","storage":"memory"}];"#));
+        assert!(js.contains("wasiFilesystem.loaded.then(() => WebAssembly.instantiate(wasmBytes, importObject, compileOptions))"));
+        assert!(js.contains(
+            "window.__wasmModules[wasmFilename].filesystem = { flush: wasiFilesystem.flush };"
+        ));
+        // Without preopens only stdio is open, and nothing is waited for
+        let js = compile_wat_to_js(source, "fs.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const wasiPreopens = [];"));
+        assert!(js.contains(
+            "\n        WebAssembly.instantiate(wasmBytes, importObject, compileOptions)"
+        ));
+    }
+
+    #[test]
+    fn test_wasi_args_and_env() {
+        assert_eq!(
+            wasi::parse_args(r#"["--verbose", "input.txt"]"#),
+            Some(vec!["--verbose".to_string(), "input.txt".to_string()])
+        );
+        assert_eq!(wasi::parse_args(r#"["a", 1]"#), None);
+        assert_eq!(wasi::parse_args(r#"{"a": "b"}"#), None);
+        assert_eq!(
+            wasi::parse_env(r#"{"LANG": "C", "HOME": "/data"}"#),
+            Some(vec![
+                ("HOME".to_string(), "/data".to_string()),
+                ("LANG".to_string(), "C".to_string())
+            ])
+        );
+        assert_eq!(wasi::parse_env(r#"{"A=B": "C"}"#), None);
+        assert_eq!(wasi::parse_env(r#"{"DEBUG": true}"#), None);
+        assert_eq!(
+            wasi::argv("pkg/tool.wasm", &["-v".to_string()]),
+            ["tool.wasm", "-v"]
+        );
+
+        let options = CompileOptions::from_attributes(|name| match name {
+            "data-wasi-args" => Some(r#"["--verbose", "input.txt"]"#.to_string()),
+            "data-wasi-env" => Some(r#"{"LANG": "C"}"#.to_string()),
+            _ => None,
+        });
+        let source = r#"(module
+  (import "wasi_snapshot_preview1" "args_get" (func (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+)"#;
+        let js = compile_wat_to_js(source, "tool.wasm", None, &options).unwrap();
+        assert!(js.contains(r#"const wasiArgs = ["tool.wasm","--verbose","input.txt"];"#));
+        assert!(js.contains(r#"const wasiEnvironment = ["LANG=C"];"#));
+        let invalid = CompileOptions::from_attributes(|name| {
+            (name == "data-wasi-args").then(|| "--verbose".to_string())
+        });
+        assert!(invalid.wasi_args.is_empty());
+    }
+
+    #[test]
+    fn test_wasi_output() {
+        assert_eq!(wasi::Output::parse("console"), Some(wasi::Output::Console));
+        assert_eq!(
+            wasi::Output::parse("#terminal"),
+            Some(wasi::Output::Element("#terminal".to_string()))
+        );
+        assert_eq!(
+            wasi::Output::parse("callback:terminal.write"),
+            Some(wasi::Output::Callback("terminal.write".to_string()))
+        );
+        assert_eq!(wasi::Output::parse("callback:not a function"), None);
+        assert_eq!(wasi::Output::parse(" "), None);
+
+        let options = CompileOptions::from_attributes(|name| match name {
+            "data-stdout" => Some("#terminal".to_string()),
+            "data-stderr" => Some("callback:onError".to_string()),
+            _ => None,
+        });
+        let source = r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+)"#;
+        let js = compile_wat_to_js(source, "tool.wasm", None, &options).unwrap();
+        assert!(js.contains(r##"const wasiStdout = {"element":"#terminal"};"##));
+        assert!(js.contains(r#"const wasiStderr = {"callback":"onError"};"#));
+        let js = compile_wat_to_js(source, "tool.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"const wasiStdout = "console";"#));
+        let invalid = CompileOptions::from_attributes(|name| {
+            (name == "data-stdout").then(|| "callback:".to_string())
+        });
+        assert_eq!(invalid.wasi_stdout, wasi::Output::Console);
+    }
+
+    #[test]
+    fn test_component_bindings() {
+        use wasm_encoder::{Encode, Section};
+
+        let mut resolve = wit_parser::Resolve::default();
+        let package = resolve
+            .push_str(
+                "calc.wit",
+                r#"package demo:calc;
+interface ops {
+    record pair { left: s32, right-side: s32 }
+    sum: func(p: pair) -> s32;
+}
+world calculator {
+    import log-line: func(line: string);
+    export ops;
+    export negate: func(x: s32) -> s32;
+}"#,
+            )
+            .unwrap();
+        let world = resolve.select_world(package, Some("calculator")).unwrap();
+        let world_section = wit_component::metadata::encode(
+            &resolve,
+            world,
+            wit_component::StringEncoding::UTF8,
+            None,
+        )
+        .unwrap();
+        let mut module = wat::parse_str(
+            r#"(module
+  (import "$root" "log-line" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "negate") (param i32) (result i32) (i32.sub (i32.const 0) (local.get 0)))
+  (func (export "demo:calc/ops#sum") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+  (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32) (i32.const 0))
+)"#,
+        )
+        .unwrap();
+        let section = wasm_encoder::CustomSection {
+            name: "component-type:calculator".into(),
+            data: world_section.into(),
+        };
+        module.push(section.id());
+        section.encode(&mut module);
+
+        let bindings = component::bindings(&module).unwrap();
+        assert_eq!(bindings.world.as_deref(), Some("calculator"));
+        let exports: Vec<_> = bindings
+            .exports
+            .iter()
+            .map(|binding| {
+                (
+                    binding.interface.as_deref(),
+                    binding.name.as_str(),
+                    binding.core.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            exports,
+            [
+                (None, "negate", "negate"),
+                (Some("ops"), "sum", "demo:calc/ops#sum")
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&bindings.exports[1].params).unwrap(),
+            serde_json::json!([["p", { "record": [["left", "s32"], ["rightSide", "s32"]] }]])
+        );
+        assert_eq!(bindings.imports[0].name, "logLine");
+        assert_eq!(bindings.imports[0].module.as_deref(), Some("$root"));
+        assert!(component::bindings(&wat::parse_str("(module)").unwrap()).is_none());
+
+        let js =
+            compile_bytes_to_js(&module, "calc.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"const componentWorld = {"world":"calculator","exports":[{"interface":null,"name":"negate""#));
+        assert!(js.contains("const builtinImports = Object.assign({}, componentImports);"));
+        assert!(js.contains("scope[binding.name] = componentAbi.lift(binding);"));
+
+        // Components are loaded as their main module, with their world
+        let component = wit_component::ComponentEncoder::default()
+            .module(&module)
+            .unwrap()
+            .encode()
+            .unwrap();
+        assert!(component::is_component(&component) && !component::is_component(&module));
+        let js =
+            compile_bytes_to_js(&component, "calc.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#"const componentWorld = {"world":null,"exports":[{"interface":null,"name":"negate""#
+        ));
+        assert_eq!(component::js_name("get-HTTP-status"), "getHttpStatus");
+    }
+
+    #[test]
+    fn test_string_params() {
+        let source = r#"(module
+  (type $string (array (mut i8)))
+  (func (export "greet") (param $name (ref null $string)) (param $times i32) (result i32)
+    (local.get $times))
+  (func (export "count") (param i32) (result i32)
+    (local.get 0))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        assert!(wasmparser::validate(&binary).is_ok());
+        assert_eq!(string_types[0].new.as_deref(), Some("__wasm_string_new_0"));
+        assert_eq!(string_types[0].set.as_deref(), Some("__wasm_string_set_0"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&results::string_params_json(&binary, &string_types)).unwrap();
+        assert_eq!(json, serde_json::json!({ "greet": [0, null] }));
+
+        // Immutable strings cannot be built from JavaScript
+        let mut immutable = wat::parse_str("(module (type $string (array i8)))").unwrap();
+        let string_types = strings::add_accessors(&mut immutable).unwrap();
+        assert!(string_types[0].new.is_none() && string_types[0].set.is_none());
+
+        let js = compile_wat_to_js(source, "params.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"const exportStringParams = {"greet":[0,null]};"#));
+    }
+
+    #[test]
+    fn test_export_aliases() {
+        let source = r#"(module
+  (func (export "_ZN3app6render17h5f1c") (;@export-as renderFrame;) (result i32)
+    (i32.const 1))
+  (func $tick (result i32)
+    (i32.const 2))
+  (export "tick_v2" (func $tick)) (;@export-as tick;)
+  (func (export "plain") (result i32)
+    (i32.const 3))
+)"#;
+        let binary = wat::parse_str(source).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&aliases::aliases_json(source, &binary)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "_ZN3app6render17h5f1c": "renderFrame", "tick_v2": "tick" })
+        );
+
+        // Binary modules give theirs in a custom section
+        let mut data = Vec::new();
+        wasm_encoder::Encode::encode(&1u32, &mut data);
+        wasm_encoder::Encode::encode("plain", &mut data);
+        wasm_encoder::Encode::encode("simple", &mut data);
+        let mut module = wasm_encoder::Module::new();
+        module.section(&wasm_encoder::CustomSection {
+            name: aliases::SECTION_NAME.into(),
+            data: data.into(),
+        });
+        let binary = module.finish();
+        assert_eq!(aliases::aliases_json("", &binary), r#"{"plain":"simple"}"#);
+
+        let js =
+            compile_wat_to_js(source, "aliases.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#"const exportAliases = {"_ZN3app6render17h5f1c":"renderFrame","tick_v2":"tick"};"#
+        ));
+    }
+
+    #[test]
+    fn test_local_names() {
+        let source = r#"(module
+  (func $update (export "update") (param $dt f64) (local $velocity f64)
+    (local.set $velocity (local.get $dt)))
+)"#;
+        let binary = wat::parse_str(source).unwrap();
+        let names = locals::local_names(&binary);
+        assert_eq!(names[&0].name.as_deref(), Some("update"));
+        assert_eq!(names[&0].locals[&1], "velocity");
+        assert_eq!(
+            locals::locals_json(&binary),
+            r#"{"0":{"name":"update","locals":{"0":"dt","1":"velocity"}}}"#
+        );
+
+        // Errors name the function and local they are in
+        let invalid = r#"(module
+  (func $update (param $dt f64) (local $velocity i32)
+    (local.set $velocity (local.get $dt)))
+)"#;
+        let error = compile_wat_to_js(invalid, "update.wat", None, &CompileOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("in update.wat, in $update, local $velocity: type mismatch"),
+            "{}",
+            error
+        );
+
+        let js = compile_wat_to_js(source, "update.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"window.__wasmLocals[wasmFilename] = {"0":{"name":"update","locals":{"0":"dt","1":"velocity"}}};"#));
+    }
+
+    #[test]
+    fn test_field_names_written() {
+        let source = r#"(module
+  (type $player (struct (field i32) (field $score i32)))
+  (type $point (struct (field $x f64) (field $y f64)))
+  (tag $boom)
+)"#;
+        // The text format writes the names it has into the name section
+        let binary = compile_wat_internal(source, "fields.wat", options::all_features()).unwrap();
+        let (types, written) = arrays::type_names(&binary);
+        assert_eq!(types[&0], "player");
+        assert_eq!(written[&0], ["", "score"]);
+        assert_eq!(written[&1], ["x", "y"]);
+        let info = names::struct_info(&binary);
+        assert_eq!(info[&0].fields, ["field0", "score"]);
+        assert_eq!(info[&1].fields, ["x", "y"]);
+    }
+
+    #[test]
+    fn test_synthesized_struct_names() {
+        let source = r#"(module
+  (type (struct (field i32) (field f64)))
+  (type $pair (struct (field $left i32) (field i32)))
+  (type (array (mut (ref null 1))))
+  (func (export "make") (result (ref 0))
+    (struct.new 0 (i32.const 1) (f64.const 2)))
+)"#;
+        let binary = wat::parse_str(source).unwrap();
+        let names = names::struct_info(&binary);
+        assert_eq!(names[&0].type_name, "type0");
+        assert_eq!(names[&0].fields, ["field0", "field1"]);
+        // The name section overrides them
+        assert_eq!(names[&1].type_name, "pair");
+        assert_eq!(names[&1].fields, ["left", "field1"]);
+
+        let anonymous = "(module (type (struct (field i32) (field f64))))";
+        let metadata = ModuleMetadata::new(anonymous, &wat::parse_str(anonymous).unwrap());
+        let field_names: serde_json::Value =
+            serde_json::from_str(&metadata.field_names_json).unwrap();
+        assert_eq!(field_names["default"]["typeName"], "type0");
+        assert_eq!(
+            field_names["default"]["fields"],
+            serde_json::json!(["field0", "field1"])
+        );
+
+        // The loader wraps results and array elements with the same names
+        let mut binary = binary;
+        let struct_arrays = arrays::add_accessors(&mut binary).unwrap();
+        let results: serde_json::Value =
+            serde_json::from_str(&results::loader_json(&binary, &struct_arrays, &[])).unwrap();
+        assert_eq!(results["make"]["typeName"], "type0");
+        let arrays: serde_json::Value =
+            serde_json::from_str(&arrays::loader_json(&struct_arrays, &binary)).unwrap();
+        assert_eq!(
+            arrays[0]["element"]["fields"],
+            serde_json::json!(["left", "field1"])
+        );
+    }
+
+    #[test]
+    fn test_struct_field_accessors() {
+        // Generated code refers to types and fields by index
+        let source = r#"(module
+  (type (struct (field i32) (field (mut f64)) (field (mut i8)) (field v128)))
+  (type (struct))
+  (func (export "make") (result (ref 0))
+    (struct.new 0 (i32.const 1) (f64.const 2) (i32.const 3) (v128.const i64x2 0 0)))
+  (func (export "second") (param (ref 0)) (result f64)
+    (struct.get 0 1 (local.get 0)))
+)"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        // A getter for each field JavaScript can hold, a setter for each mutable one
+        assert_eq!(structs::add_accessors(&mut binary).unwrap(), 5);
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+        let printed = print_module(&binary).unwrap();
+        assert!(printed.contains(r#"(export "__wasm_struct_get_0_0""#));
+        assert!(printed.contains(r#"(export "__wasm_struct_set_0_2""#));
+        assert!(!printed.contains(r#"(export "__wasm_struct_set_0_0""#));
+        assert!(!printed.contains(r#"(export "__wasm_struct_get_0_3""#));
+
+        let json: serde_json::Value = serde_json::from_str(&structs::loader_json(&binary)).unwrap();
+        // Types without accessors are left out
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["type"], 0);
+        assert_eq!(json[0]["typeName"], "type0");
+        assert_eq!(
+            json[0]["fields"],
+            serde_json::json!(["field0", "field1", "field2", "field3"])
+        );
+        assert_eq!(
+            json[0]["get"],
+            serde_json::json!([
+                "__wasm_struct_get_0_0",
+                "__wasm_struct_get_0_1",
+                "__wasm_struct_get_0_2",
+                null
+            ])
+        );
+        assert_eq!(
+            json[0]["set"],
+            serde_json::json!([null, "__wasm_struct_set_0_1", "__wasm_struct_set_0_2", null])
+        );
+
+        // Modules without functions get the sections in their place
+        let mut binary = wat::parse_str("(module (type (struct (field (mut i32)))))").unwrap();
+        assert_eq!(structs::add_accessors(&mut binary).unwrap(), 2);
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+
+        // The loader does not install the accessors on window
+        let js =
+            compile_wat_to_js(source, "structs.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("wasmName.startsWith('__wasm_struct_')"));
+        assert!(js.contains(r#""get":["__wasm_struct_get_0_0""#));
+    }
+
+    #[test]
+    fn test_type_section_metadata() {
+        let source = r#"(module
+  (type $name (array (mut i8)))
+  (type $point (struct (field $x (mut f64)) (field $y f64)))
+  (type $player (struct (field $name (ref null $name)) (field $hp (mut i16)) (field $tag anyref)))
+)"#;
+        // Binary modules get the same metadata as their WAT source
+        let binary = wat::parse_str(source).unwrap();
+        let from_wat = ModuleMetadata::new(source, &binary);
+        assert_eq!(
+            from_wat.field_names_json,
+            ModuleMetadata::new("", &binary).field_names_json
+        );
+
+        let field_names: serde_json::Value =
+            serde_json::from_str(&from_wat.field_names_json).unwrap();
+        assert_eq!(field_names["default"], field_names["1"]);
+        assert_eq!(field_names["1"]["typeName"], "point");
+        assert_eq!(
+            field_names["1"]["mutable"],
+            serde_json::json!([true, false])
+        );
+        assert_eq!(
+            field_names["2"]["fields"],
+            serde_json::json!(["name", "hp", "tag"])
+        );
+        assert_eq!(
+            field_names["2"]["fieldTypes"],
+            serde_json::json!(["(ref null 0)", "i16", "(ref null any)"])
+        );
+        // Arrays are not structs
+        assert!(field_names.get("0").is_none());
+
+        let js =
+            compile_bytes_to_js(&binary, "point.wasm", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#""typeName":"point""#));
+    }
+
+    #[test]
+    fn test_string_type_positions() {
+        let source = r#"(module (@sugar)
+  (global $title (mut string) (ref.null $string))
+  (global $empty string (ref.null $string))
+  (func $pick (export "pick") (param $a string) (param $flag i32) (param string i32)
+    (result string)
+    (local $s string) (local i32 string)
+    ;; (param string) in a comment stays
+    (local.set $s (local.get $a))
+    (local.get $s)
+  )
+  (func (export "name") (result i32) (call $string_len (global.get $title)))
+  (func $string_len (param string) (result i32) i32.const 0)
+)"#;
+
+        let transformed = transform_string_types(source);
+        assert!(transformed.contains("(global $title (mut (ref null $string))"));
+        assert!(transformed.contains("(global $empty (ref null $string) (ref.null $string))"));
+        assert!(transformed.contains("(param $a (ref null $string))"));
+        assert!(transformed.contains("(param (ref null $string) i32)"));
+        assert!(transformed.contains("(result (ref null $string))"));
+        assert!(
+            transformed.contains("(local $s (ref null $string)) (local i32 (ref null $string))")
+        );
+        assert!(transformed.contains(";; (param string) in a comment stays"));
+        assert!(transformed.contains("(call $string_len (global.get $title))"));
+        assert!(compile_wat_internal(source, "strings.wat", options::all_features()).is_ok());
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = r#"(module
+  (global $level (mut f64) (f64.const 1.5))
+  (func (export "label") (param $score i32) (param $name (ref null $string)) (result (ref $string))
+    "{$name} scored {$score} at level {$level}\n")
+  (func (export "plain") (result i32)
+    i32.const 0)
+)"#;
+
+        let lowered = preprocess_wat(source, options::all_features(), true).unwrap();
+        assert!(lowered.contains(r#"(data $__interp_0 " scored  at level \n")"#));
+        assert!(lowered.contains("(call $__i32_to_string (local.get $score))"));
+        assert!(lowered.contains("(call $__f64_to_string (global.get $level))"));
+
+        let binary =
+            compile_wat_internal(source, "interpolation.wat", options::all_features()).unwrap();
+        assert!(wasmparser::validate(&binary).is_ok());
+
+        let unknown = r#"(module
+  (func (export "f") (result (ref $string))
+    "value: {$missing}"))"#;
+        assert!(matches!(
+            compile_wat_internal(unknown, "unknown.wat", options::all_features()),
+            Err(CompileError::InterpolationError(ref msg)) if msg.contains("$missing")
+        ));
+    }
+
+    #[test]
+    fn test_invalid_wat() {
+        let source = "(module (invalid syntax))";
+
+        let result = compile_wat_to_js(source, "test.wat", None, &CompileOptions::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_stdlib() {
+        let source = r#"(module
+  (import "std/string" "concat" (func $concat (param (ref null $std/string) (ref null $std/string)) (result (ref $std/string))))
+  (import "std/math" "clamp" (func $clamp (param i32 i32 i32) (result i32)))
+  (func $push (import "std/list" "push") (param (ref null $std/list) i32))
+  (import "std/list" "new" (func $new_list (result (ref $std/list))))
+  (import "std/list" "length" (func $length (param (ref null $std/list)) (result i32)))
+  (func (export "twice") (param $s (ref null $std/string)) (result (ref $std/string))
+    (call $concat (local.get $s) (local.get $s)))
+  (func (export "count") (param $n i32) (result i32)
+    (local $list (ref $std/list))
+    (local.set $list (call $new_list))
+    (call $push (local.get $list) (call $clamp (local.get $n) (i32.const 0) (i32.const 10)))
+    (call $length (local.get $list)))
+  ;; $concat in a comment stays as written
+)"#;
+        let linked = stdlib::link(source).unwrap();
+        assert!(linked.needs_gc);
+        assert!(
+            linked
+                .text
+                .contains("(call $std/string.concat (local.get $s) (local.get $s))")
+        );
+        assert!(linked.text.contains(";; $concat in a comment"));
+        assert!(!linked.text.contains("\"std/list\" \"push\")"));
+        assert_eq!(linked.text.matches("(type $std/i32s ").count(), 1);
+        // Only the libraries used are linked
+        assert!(!linked.text.contains("$std/map.new"));
+
+        let binary = compile_wat_internal(source, "stdlib.wat", options::all_features()).unwrap();
+        assert!(wasmparser::validate(&binary).is_ok());
+        assert!(
+            !imports::declared_imports(&binary)
+                .iter()
+                .any(|(module, _, _)| module.starts_with("std/"))
+        );
+
+        let unknown = r#"(module (import "std/string" "reverse" (func $reverse)))"#;
+        assert!(matches!(
+            compile_wat_internal(unknown, "unknown.wat", options::all_features()),
+            Err(CompileError::LinkError(ref msg)) if msg.contains("reverse")
+        ));
+        let anonymous = r#"(module (import "std/math" "abs" (func (param i32) (result i32))))"#;
+        assert!(matches!(
+            compile_wat_internal(anonymous, "anonymous.wat", options::all_features()),
+            Err(CompileError::LinkError(ref msg)) if msg.contains("needs an id")
+        ));
+    }
+
+    #[test]
+    fn test_stdlib_versions() {
+        let pinned = r#"(module
+  (import "std@1/math" "max" (func $max (param i32 i32) (result i32)))
+  (func (export "larger") (param i32 i32) (result i32)
+    (call $max (local.get 0) (local.get 1))))"#;
+        let linked = stdlib::link(pinned).unwrap();
+        assert!(
+            linked
+                .text
+                .contains("(call $std/math.max (local.get 0) (local.get 1))")
+        );
+        assert!(!linked.needs_gc);
+
+        let future = pinned.replace("std@1", &format!("std@{}", stdlib::VERSION + 1));
+        assert!(
+            stdlib::link(&future)
+                .unwrap_err()
+                .contains("needs a newer compiler")
+        );
+        assert!(stdlib::link(&pinned.replace("std@1", "std@one")).is_err());
+
+        // data-no-stdlib leaves the import to the page
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-no-stdlib").then(String::new));
+        assert!(options.no_stdlib);
+        let js = compile_wat_to_js(pinned, "pinned.wat", None, &options).unwrap();
+        let (binary, _) = compile_cached(pinned.as_bytes(), "pinned.wat", &options).unwrap();
+        assert!(
+            imports::declared_imports(&binary)
+                .iter()
+                .any(|(module, name, _)| module == "std@1/math" && name == "max")
+        );
+        assert!(js.contains("std@1/math"));
+        let (linked, _) =
+            compile_cached(pinned.as_bytes(), "pinned.wat", &CompileOptions::default()).unwrap();
+        assert!(imports::declared_imports(&linked).is_empty());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        // SHA-256, the same in every build
+        assert_eq!(
+            ContentHash::of(b"").to_string(),
+            "sha-256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(
+            ContentHash::of(b"a"),
+            ContentHash::of(b"a").with(b"no-stdlib")
+        );
+
+        let source = "(module (func (export \"hashed\") (result i32) i32.const 2714))";
+        compile_wat_to_js(source, "hashed.wat", None, &CompileOptions::default()).unwrap();
+        let cache = get_cache().read();
+        let entry = cache.get(&ContentHash::of(source.as_bytes())).unwrap();
+        assert_eq!(entry.hash_algorithm.as_str(), "sha-256");
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let source = r#"(module
+  (memory (export "memory") 2)
+  (memory $scratch 1 1000)
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0))))"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        assert_eq!(budget::initial_bytes(&binary), 3 * 65536);
+        assert!(budget::cap_memories(&mut binary, 4 * 65536).unwrap());
+        assert!(wasmparser::validate(&binary).is_ok());
+        let maxima: Vec<Option<u64>> = wasmparser::Parser::new(0)
+            .parse_all(&binary)
+            .filter_map(|payload| match payload {
+                Ok(wasmparser::Payload::MemorySection(reader)) => Some(
+                    reader
+                        .into_iter()
+                        .map(|memory| memory.unwrap().maximum)
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(maxima, [Some(4), Some(4)]);
+        // Capped already
+        assert!(!budget::cap_memories(&mut binary, 4 * 65536).unwrap());
+        // memory.grow asks the loader first
+        assert!(budget::guard_growth(&mut binary).unwrap());
+        assert!(wasmparser::validate(&binary).is_ok());
+        assert!(
+            imports::declared_imports(&binary)
+                .iter()
+                .any(|(module, name, _)| module == budget::IMPORT_MODULE && name == "grow")
+        );
+        assert_eq!(budget::page_bytes(&binary), [65536, 65536]);
+        let mut constant =
+            wat::parse_str("(module (memory 1) (func (drop (memory.size))))").unwrap();
+        assert!(!budget::guard_growth(&mut constant).unwrap());
+        assert_eq!(budget::from_pref(2), Some(2 << 20));
+        assert_eq!(budget::from_pref(0), None);
+
+        let options = CompileOptions {
+            memory_budget: Some(4 * 65536),
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(source, "budget.wat", None, &options).unwrap();
+        assert!(js.contains("const memoryBudget = 262144;"));
+        assert!(js.contains("if (memoryQuotaError(196608)) {"));
+        assert!(js.contains("window.__wasmMemoryUse[wasmFilename] = memoryHeld;"));
+        assert!(js.contains("const memoryPageBytes = [65536,65536];"));
+        assert!(js.contains("importObject['__wasm_budget'] = memoryGrowth(memoryPageBytes, "));
+        let unlimited =
+            compile_wat_to_js(source, "budget.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!unlimited.contains("memoryBudget"));
+    }
+
+    #[test]
+    fn test_threads_permission() {
+        let shared = r#"(module (memory (export "memory") 1 1 shared))"#;
+        let denied = CompileOptions {
+            threads_denied: true,
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(shared, "threads.wat", None, &denied).unwrap();
+        assert!(js.contains(
+            "dispatchWasmError(threadsDenied, { kind: 'permission', permission: 'threads', origin: location.origin });"
+        ));
+        assert!(js.contains(
+            "detail: Object.assign({ filename: wasmFilename, message: message }, details)"
+        ));
+        // Checked before cross-origin isolation, which would not help
+        assert!(js.find("threadsDenied").unwrap() < js.find("crossOriginIsolated").unwrap());
+
+        let permitted =
+            compile_wat_to_js(shared, "threads.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!permitted.contains("threadsDenied"));
+        let unshared = r#"(module (memory (export "memory") 1 1))"#;
+        let js = compile_wat_to_js(unshared, "unshared.wat", None, &denied).unwrap();
+        assert!(!js.contains("threadsDenied"));
+    }
+
+    #[test]
+    fn test_payload_sniffing() {
+        let text = r#"(module (func (export "one") (result i32) i32.const 1))"#;
+        let binary = wat::parse_str(text).unwrap();
+        assert_eq!(
+            &*sniff::payload(text.as_bytes(), false).unwrap(),
+            text.as_bytes()
+        );
+        assert_eq!(&*sniff::payload(&binary, false).unwrap(), &binary[..]);
+
+        // Base64, prefixed or bare, line-wrapped or not
+        let armored = sniff::binary_as_text(&binary).unwrap();
+        assert!(armored.starts_with("data:application/wasm;base64,AGFzbQEAAAA"));
+        assert_eq!(
+            &*sniff::payload(armored.as_bytes(), false).unwrap(),
+            &binary[..]
+        );
+        let bare = armored.trim_start_matches("data:application/wasm;base64,");
+        let wrapped = format!("\n  base64,{}\n  {}\n", &bare[..20], &bare[20..]);
+        assert_eq!(
+            &*sniff::payload(wrapped.as_bytes(), false).unwrap(),
+            &binary[..]
+        );
+        assert_eq!(
+            &*sniff::payload(bare.trim_end_matches('=').as_bytes(), false).unwrap(),
+            &binary[..]
+        );
+        assert!(sniff::binary_as_text(text.as_bytes()).is_none());
+        let not_wasm = sniff::payload(b"base64,aGVsbG8=", false).unwrap_err();
+        assert!(
+            not_wasm.contains("not a WebAssembly module"),
+            "{}",
+            not_wasm
+        );
+        assert!(
+            sniff::payload(b"AGFzbQ!!", false)
+                .unwrap_err()
+                .contains("malformed")
+        );
+
+        // Binary bodies mangled by the HTML parser or a charset
+        let parsed = String::from_utf8_lossy(&binary).replace('\0', "\u{fffd}");
+        let error = compile_wat_to_js(&parsed, "inline.wasm", None, &CompileOptions::default())
+            .unwrap_err();
+        assert!(matches!(error, CompileError::ParseError(_)));
+        assert!(
+            error
+                .to_string()
+                .contains("bytes were replaced when the page was parsed"),
+            "{}",
+            error
+        );
+        assert!(
+            sniff::payload(&binary[1..], false)
+                .unwrap_err()
+                .contains("\"asm\\u{1}\"")
+        );
+        let mut decoded = binary.clone();
+        decoded.truncate(12);
+        decoded.extend_from_slice("\u{fffd}\u{fffd}".as_bytes());
+        assert!(
+            sniff::payload(&decoded, false)
+                .unwrap_err()
+                .contains("replaced by U+FFFD")
+        );
+        let garbage = "\u{fffd}\u{1}\u{fffd}PNG";
+        assert!(
+            sniff::payload(garbage.as_bytes(), false)
+                .unwrap_err()
+                .contains("binary data rather than WAT text")
+        );
+    }
+
+    #[test]
+    fn test_base64_encoding() {
+        let text = r#"(module (func (export "two") (result i32) i32.const 2))"#;
+        let binary = wat::parse_str(text).unwrap();
+        let encoded = sniff::binary_as_text(&binary).unwrap();
+        let encoded = encoded.trim_start_matches("data:application/wasm;base64,");
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-encoding").then(|| "Base64".to_string())
+        });
+        assert!(options.base64);
+        assert!(
+            !CompileOptions::from_attributes(
+                |name| (name == "data-encoding").then(|| "hex".to_string())
+            )
+            .base64
+        );
+
+        // Decoded before anything is sniffed, however the body starts
+        let inline = format!("\n    {}\n  ", encoded);
+        let js = compile_wat_to_js(&inline, "inline.wasm", None, &options).unwrap();
+        assert!(js.contains("const wasmByteChunks = [\"\\x00asm\\x01\\x00\\x00\\x00"));
+        assert_eq!(
+            &*sniff::payload(encoded.as_bytes(), true).unwrap(),
+            &binary[..]
+        );
+        assert_eq!(
+            &*sniff::payload(format!("base64,{}", encoded).as_bytes(), true).unwrap(),
+            &binary[..]
+        );
+        let error = compile_wat_to_js(text, "text.wat", None, &options).unwrap_err();
+        assert!(
+            error.to_string().contains("base64 body is malformed"),
+            "{}",
+            error
+        );
+        let error = sniff::payload(b"aGVsbG8=", true).unwrap_err();
+        assert!(error.contains("not a WebAssembly module"), "{}", error);
+    }
+
+    #[test]
+    fn test_wast_scripts() {
+        use super::wast::is_wast;
+        let module = r#"(module (func (export "add") (param i32 i32) (result i32)
+            local.get 0 local.get 1 i32.add))"#;
+        assert!(!is_wast(module));
+        assert!(!is_wast(
+            "(func (export \"f\"))\n(global i32 (i32.const 0))"
+        ));
+        assert!(!is_wast(&format!(
+            "{}\n;; (assert_return (invoke \"add\"))\n(; (invoke \"add\") ;)",
+            module
+        )));
+        assert!(is_wast(&format!(
+            "{}\n(invoke \"add\" (i32.const 1) (i32.const 2))",
+            module
+        )));
+        assert!(is_wast(&format!("{}\n{}", module, module)));
+
+        let script = format!(
+            "{}\n(assert_return (invoke \"add\" (i32.const 1) (i32.const 2)) (i32.const 3))\n\
+             (assert_trap (invoke \"add\" (i64.const 1)) \"type mismatch\")\n\
+             (assert_malformed (module quote \"(func\") \"unexpected end\")\n\
+             (assert_return (invoke \"v\") (v128.const i32x4 0 0 0 0))",
+            module
+        );
+        let js = compile_wat_to_js(&script, "add.wast", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const wastFile = \"add.wast\";"));
+        assert!(js.contains("check(3, 2, 'assert_return', function() { return expectValues(invoke(null, \"add\", [1, 2]), [expect.value(\"i32.const 3\", 3)]); });"));
+        assert!(js.contains("check(4, 2, 'assert_trap', function() { return expectThrow(function() { return invoke(null, \"add\", [1n]); }, isTrap, 'a trap', \"type mismatch\"); });"));
+        // Rejected by the text format, so it passes without the engine
+        assert!(js.contains("check(5, 2, 'assert_malformed', function() { return null; });"));
+        assert!(js.contains("return skip(\"a v128 value cannot be run from JavaScript\");"));
+        assert!(js.contains("window.__wastResults[results.file] = results;"));
+        assert!(!js.contains("wasmByteChunks"));
+
+        let error = compile_wat_to_js(
+            "(assert_return (invoke",
+            "broken.wast",
+            None,
+            &CompileOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, CompileError::ParseError(_)), "{}", error);
+
+        // The modules are checked as the scripts of the page are
+        let script = r#"(module (memory 1 1 shared))
+(module (type (struct (field i32))))
+(module (memory 1))"#;
+        let options = CompileOptions {
+            enabled_features: Some(parse_feature_list("threads")),
+            memory_budget: Some(65536),
+            threads_denied: true,
+            ..CompileOptions::default()
+        };
+        let js = compile_wat_to_js(script, "gated.wast", None, &options).unwrap();
+        assert!(js.contains("check(1, 2, 'module', function() { current = null; return \"module uses shared memory or threads, which the origin has no permission for\"; });"));
+        assert!(js.contains("check(2, 2, 'module', function() { current = null; return \""));
+        assert!(js.contains(", { initial: 65536, pages: [65536] }, null), null); });"));
+        assert!(js.contains("const memoryBudget = 65536;"));
+        assert!(js.contains("window.__wasmMemoryUse[wastFile] = () => memoryHeld;"));
+    }
+
+    #[test]
+    fn test_run_wast() {
+        let script = r#"(module (func (export "one") (result i32) i32.const 1))
+(assert_return (invoke "one") (i32.const 1))"#;
+        let js = run_wast(script, "about:blank#wat-wast-1", &CompileOptions::default()).unwrap();
+        assert!(js.starts_with("Promise.resolve().then(function() { return (function wastRun() {"));
+        assert!(js.contains("const wastFile = \"about:blank#wat-wast-1\";"));
+        assert!(js.contains("check(2, 2, 'assert_return',"));
+        // Reported by the page, not the runner
+        assert!(!js.contains("__wastResults"));
+        assert!(!js.contains("console."));
+        assert!(run_wast("(assert_return", "broken", &CompileOptions::default()).is_err());
+
+        // Checked against the options of the page like the scripts
+        let options = CompileOptions {
+            threads_denied: true,
+            ..CompileOptions::default()
+        };
+        let js = run_wast(
+            "(module (memory 1 1 shared))\n(module)",
+            "about:blank#wat-wast-2",
+            &options,
+        )
+        .unwrap();
+        assert!(js.contains(
+            "module uses shared memory or threads, which the origin has no permission for"
+        ));
+    }
+
+    #[test]
+    fn test_internals() {
+        // The cache is shared with tests compiling concurrently, so it is not
+        // cleared here
+        let source = r#"(module (func (export "introspected") (result i32) i32.const 2721))"#;
+        compile_wat_to_js(source, "internals.wat", None, &CompileOptions::default()).unwrap();
+        let hash = ContentHash::of(source.as_bytes()).to_string();
+        assert!(hash.starts_with("sha-256:"));
+
+        let modules = internals::modules();
+        let module = modules
+            .iter()
+            .find(|module| module.hash == hash)
+            .expect("module should be listed");
+        assert_eq!(module.filename, "internals.wat");
+        let binary = internals::binary(&hash).unwrap();
+        assert_eq!(module.size, binary.len());
+        assert!(binary.starts_with(b"\0asm"));
+        assert!(internals::binary("sha-256:00").is_none());
+
+        let stats = internals::cache_stats();
+        assert!(stats.entries >= 1);
+        assert!(stats.bytes >= binary.len());
+        assert!(stats.compiled >= 1);
+    }
+
+    #[test]
+    fn test_differential_validation() {
+        let features = WasmFeatures::default();
+        let before = wat::parse_str("(module (func (result i32) i32.const 1))").unwrap();
+        let after = wat::parse_str("(module (func (result i32) i64.const 1))").unwrap();
+        assert_eq!(
+            differential::check("noop", &before, &before, features),
+            Ok(())
+        );
+        let report = differential::check("retyping", &before, &after, features).unwrap_err();
+        assert!(report.starts_with("the retyping pass made a valid module invalid"));
+        assert!(report.contains("in the code section"));
+
+        // A pass breaking the module fails the compilation in debug builds
+        let mut binary = before.clone();
+        let result = differential::run("test.wat", "truncating", &mut binary, features, |binary| {
+            binary.truncate(binary.len() - 1);
+            Ok(())
+        });
+        if cfg!(debug_assertions) {
+            let Err(CompileError::InstrumentationError(message)) = result else {
+                panic!("the truncating pass was not caught");
+            };
+            assert!(message.contains("the truncating pass made a valid module invalid"));
+        }
+    }
+
+    #[test]
+    fn test_datacount_section() {
+        // Section ids in order, and the datacount if any
+        fn layout(binary: &[u8]) -> (Vec<u8>, Option<u32>) {
+            let mut count = None;
+            let mut ids = Vec::new();
+            for payload in wasmparser::Parser::new(0).parse_all(binary) {
+                let payload = payload.unwrap();
+                if let wasmparser::Payload::DataCountSection { count: n, .. } = payload {
+                    count = Some(n);
+                }
+                ids.extend(payload.as_section().map(|(id, _)| id));
+            }
+            (ids, count)
+        }
+        let inject = |source: &str| {
+            let mut binary = wat::parse_str(source).unwrap();
+            inject_datacount_section(&mut binary);
+            wasmparser::Validator::new().validate_all(&binary).unwrap();
+            layout(&binary)
+        };
+
+        // Active and passive segments alike, before the code
+        let (ids, count) =
+            inject(r#"(module (memory 1) (data (i32.const 0) "a") (data "b") (func))"#);
+        assert_eq!(count, Some(2));
+        assert_eq!(ids, [1, 3, 5, 12, 10, 11]);
+
+        // Before the data without code
+        let (ids, count) = inject(r#"(module (memory 1) (data "a"))"#);
+        assert_eq!(count, Some(1));
+        assert_eq!(ids, [5, 12, 11]);
+
+        // Code past the first kilobytes
+        let body = "i32.const 0 drop ".repeat(5000);
+        let (ids, count) = inject(&format!(
+            r#"(module (memory 1) (func {}) (data "a"))"#,
+            body
+        ));
+        assert_eq!(count, Some(1));
+        assert_eq!(ids, [1, 3, 5, 12, 10, 11]);
+
+        // A data section without segments needs none
+        let mut empty = wat::parse_str("(module (memory 1))").unwrap();
+        empty.extend([11, 1, 0]);
+        let before = empty.clone();
+        inject_datacount_section(&mut empty);
+        assert_eq!(empty, before);
+
+        // Nor does a module that has one
+        let mut present =
+            wat::parse_str(r#"(module (memory 1) (data $d "hi") (func (data.drop $d)))"#).unwrap();
+        assert_eq!(layout(&present).1, Some(1));
+        let before = present.clone();
+        inject_datacount_section(&mut present);
+        assert_eq!(present, before);
+    }
+
+    #[test]
+    fn test_tables() {
+        let source = r#"(module
+  (import "env" "shared" (table 1 externref))
+  (type $op (func (param i32) (result i32)))
+  (table $ops (export "ops") 4 8 funcref)
+  (func $double (type $op) (i32.mul (local.get 0) (i32.const 2)))
+  (func $negate (type $op) (i32.sub (i32.const 0) (local.get 0)))
+  (elem (table $ops) (i32.const 1) func $double $negate)
+  (elem $spare funcref (ref.func $negate) (ref.null func))
+  (func (export "apply") (param i32 i32) (result i32)
+    (call_indirect $ops (type $op) (local.get 1) (local.get 0))))"#;
+        let binary = wat::parse_str(source).unwrap();
+        let found = tables::tables(&binary);
+        assert_eq!(found.tables.len(), 2);
+        assert_eq!(found.tables[0].import.as_deref(), Some("env.shared"));
+        assert_eq!(found.tables[0].element, "externref");
+        assert_eq!(found.tables[1].element, "funcref");
+        assert_eq!(
+            (found.tables[1].initial, found.tables[1].maximum),
+            (4, Some(8))
+        );
+        assert_eq!(found.tables[1].exports, ["ops"]);
+
+        assert_eq!(found.elements.len(), 2);
+        assert_eq!(found.elements[0].mode, "active");
+        assert_eq!(
+            (found.elements[0].table, found.elements[0].offset),
+            (Some(1), Some(1))
+        );
+        assert_eq!(found.elements[0].functions, [Some(0), Some(1)]);
+        assert_eq!(found.elements[1].mode, "passive");
+        assert_eq!(found.elements[1].functions, [Some(1), None]);
+        // Only active segments place functions
+        assert_eq!(found.resident[&0], [(1, 1)]);
+        assert_eq!(found.resident[&1], [(1, 2)]);
+
+        let js = compile_wat_to_js(source, "tables.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#""resident":{"0":[[1,1]],"1":[[1,2]]}"#));
+        assert!(js.contains("window.__wasmTables[wasmFilename] = wasmTables;"));
+        assert!(js.contains("window.__wasmModules[wasmFilename].tables = liveTables;"));
+    }
+
+    #[test]
+    fn test_log_target() {
+        use std::sync::Mutex;
+
+        /// Key-values of the records of the compiler
+        struct Capture(Mutex<Vec<HashMap<String, String>>>);
+        impl log::Log for Capture {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == LOG_TARGET
+            }
+            fn log(&self, record: &log::Record) {
+                struct Visitor<'a>(&'a mut HashMap<String, String>);
+                impl<'kvs> log::kv::VisitSource<'kvs> for Visitor<'_> {
+                    fn visit_pair(
+                        &mut self,
+                        key: log::kv::Key<'kvs>,
+                        value: log::kv::Value<'kvs>,
+                    ) -> Result<(), log::kv::Error> {
+                        self.0.insert(key.to_string(), value.to_string());
+                        Ok(())
+                    }
+                }
+                if self.enabled(record.metadata()) {
+                    let mut pairs = HashMap::new();
+                    record.key_values().visit(&mut Visitor(&mut pairs)).unwrap();
+                    self.0.lock().unwrap().push(pairs);
+                }
+            }
+            fn flush(&self) {}
+        }
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        if log::set_logger(&CAPTURE).is_err() {
+            // Another logger was installed first
+            return;
+        }
+        log::set_max_level(log::LevelFilter::Info);
+
+        let source = "(module (func (export \"logged\")))";
+        compile_wat_to_js(source, "logged.wat", None, &CompileOptions::default()).unwrap();
+        let records = CAPTURE.0.lock().unwrap();
+        let compiled = records
+            .iter()
+            .find(|pairs| {
+                pairs.get("filename").map(String::as_str) == Some("logged.wat")
+                    && pairs.get("phase").map(String::as_str) == Some("compile")
+                    && pairs.contains_key("hash")
+            })
+            .expect("no record of the compilation");
+        assert!(compiled["hash"].starts_with("sha-256:"));
+        assert!(compiled["bytes"].parse::<usize>().unwrap() > 0);
+        assert!(compiled.contains_key("duration_ms"));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let source = r#"(module (func (export "timed") (result i32) i32.const 7))"#;
+        let options = CompileOptions {
+            coverage: coverage::Coverage::Functions,
+            ..Default::default()
+        };
+        let phases = |spans: Vec<timeline::Span>| -> Vec<String> {
+            assert!(spans.iter().all(|span| span.start <= span.end));
+            spans.into_iter().map(|span| span.phase).collect()
+        };
+
+        let (js, spans) =
+            timeline::capture(|| compile_wat_to_js(source, "timed.wat", None, &options));
+        assert!(
+            js.unwrap().contains(
+                "performance.measure('wasm instantiate ' + wasmFilename, instantiateMark);"
+            )
+        );
+        let phases_compiled = phases(spans);
+        for phase in [
+            "parse",
+            "datacount section",
+            "validation",
+            "coverage",
+            "glue",
+        ] {
+            assert!(
+                phases_compiled.iter().any(|name| name == phase),
+                "no {} in {:?}",
+                phase,
+                phases_compiled
+            );
+        }
+
+        let (_, spans) =
+            timeline::capture(|| compile_wat_to_js(source, "timed.wat", None, &options));
+        let phases_cached = phases(spans);
+        assert!(phases_cached.iter().any(|name| name == "cache hit"));
+        assert!(!phases_cached.iter().any(|name| name == "parse"));
+
+        // Nothing is recorded outside a capture
+        compile_wat_to_js(source, "timed.wat", None, &options).unwrap();
+        let (_, spans) = timeline::capture(|| ());
+        assert!(spans.is_empty());
+    }
+
+    #[test]
+    fn test_size_report() {
+        let source = r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (memory 1)
+  (data (i32.const 0) "sized")
+  (data "passive bytes")
+  (func $small)
+  (func $large (export "large") (param i32) (result i32)
+    (i32.add (i32.mul (local.get 0) (i32.const 3)) (i32.const 1000000))))"#;
+        let binary = compile_wat_internal(source, "sized.wat", options::all_features()).unwrap();
+        let report = sizes::size_report(&binary);
+        assert_eq!(report.total, binary.len());
+        assert_eq!(
+            report
+                .sections
+                .iter()
+                .map(|section| section.bytes)
+                .sum::<usize>(),
+            binary.len() - 8
+        );
+        assert!(report.sections.iter().any(|section| section.name == "type"));
+        assert_eq!(report.types, 3);
+        assert_eq!(
+            report.data,
+            sizes::Data {
+                segments: 2,
+                active: 1,
+                passive: 1,
+                bytes: 18
+            }
+        );
+        assert!(report.names > 0);
+        // Largest first, indexed after the imported function
+        assert_eq!(report.functions.len(), 2);
+        assert_eq!(
+            (
+                report.functions[0].index,
+                report.functions[0].name.as_deref()
+            ),
+            (2, Some("large"))
+        );
+        assert!(report.functions[0].bytes > report.functions[1].bytes);
+
+        let js = compile_wat_to_js(source, "sized.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("__wasmSizes"));
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-size-report").then(String::new));
+        let js = compile_wat_to_js(source, "sized.wat", None, &options).unwrap();
+        assert!(js.contains("window.__wasmSizes[wasmFilename] = wasmSizes;"));
+        assert!(js.contains(r#""data":{"segments":2,"active":1,"passive":1,"bytes":18}"#));
+
+        let hash = ContentHash::of(source.as_bytes()).to_string();
+        let json = internals::size_report(&hash).unwrap();
+        assert!(json.contains(r#""name":"large""#));
+        assert!(internals::size_report("sha-256:00").is_none());
+    }
+
+    #[test]
+    fn test_tree_shaking() {
+        let source = r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (type $unary (func (param f64) (result f64)))
+  (memory 1)
+  (table 1 funcref)
+  (elem (i32.const 0) $slot)
+  (data $greeting "hello")
+  (data $farewell "bye")
+  (data (i32.const 16) "active")
+  (func $init (export "init")
+    (call $used))
+  (func $tick (export "tick") (param i32)
+    (call $log (local.get 0)))
+  (func $used
+    (memory.init $greeting (i32.const 0) (i32.const 0) (i32.const 5))
+    (data.drop $greeting))
+  (func $helper (export "helper") (type $unary)
+    (data.drop $farewell)
+    (call $orphan)
+    (local.get 0))
+  (func $orphan)
+  (func $slot))"#;
+        let mut binary =
+            compile_wat_internal(source, "shaken.wat", options::all_features()).unwrap();
+        let keep = [
+            "init".to_string(),
+            "tick".to_string(),
+            "missing".to_string(),
+        ];
+        let shaken = shake::shake(&mut binary, &keep).unwrap();
+        assert_eq!(
+            shaken,
+            shake::Shaken {
+                functions: 2,
+                data: 1,
+                types: 1,
+                missing: vec!["missing".to_string()]
+            }
+        );
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+        // The functions after the removed ones move down, names and all
+        let names = instrument::function_names(&binary).unwrap();
+        let mut names: Vec<_> = names.into_iter().collect();
+        names.sort();
+        let names: Vec<_> = names
+            .iter()
+            .map(|(index, name)| (*index, name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (0, "log"),
+                (1, "init"),
+                (2, "tick"),
+                (3, "used"),
+                (4, "slot")
+            ]
+        );
+        let tables = tables::tables(&binary);
+        assert_eq!(tables.elements[0].functions, [Some(4)]);
+        let report = sizes::size_report(&binary);
+        assert_eq!(report.types, 2);
+        assert_eq!(report.data.segments, 2);
+
+        // Shaking keeps a module whose exports are all kept as it is
+        let unshaken = binary.clone();
+        let shaken = shake::shake(&mut binary, &["init".to_string(), "tick".to_string()]).unwrap();
+        assert_eq!(shaken, shake::Shaken::default());
+        assert_eq!(binary, unshaken);
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-keep").then(|| "init, tick".to_string())
+        });
+        assert_eq!(
+            options.keep,
+            Some(vec!["init".to_string(), "tick".to_string()])
+        );
+        let js = compile_wat_to_js(source, "shaken.wat", None, &options).unwrap();
+        assert!(!js.contains(r#""helper""#));
+        let js = compile_wat_to_js(source, "shaken.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#""helper""#));
+        let empty = CompileOptions::from_attributes(|name| (name == "data-keep").then(String::new));
+        assert_eq!(empty.keep, None);
+    }
+
+    #[test]
+    fn test_inlining() {
+        let source = r#"(module
+  (type $Point (struct (field $x (mut i32)) (field $y (mut i32))))
+  (func $get_x (param (ref $Point)) (result i32)
+    (struct.get $Point $x (local.get 0)))
+  (func $set_x (param (ref $Point)) (param i32)
+    (struct.set $Point $x (local.get 0) (local.get 1)))
+  (func $get_y (export "get_y") (param (ref $Point)) (result i32)
+    (struct.get $Point $y (local.get 0)))
+  (func $sign (param i32) (result i32)
+    (if (result i32) (local.get 0) (then (i32.const 1)) (else (i32.const 0))))
+  (func $sum (export "sum") (param $p (ref $Point)) (param $n i32) (result i32)
+    (local $total i32)
+    (loop $again
+      (call $set_x (local.get $p) (i32.add (call $get_x (local.get $p)) (i32.const 1)))
+      (local.set $total (i32.add (local.get $total) (call $get_y (local.get $p))))
+      (br_if $again (i32.lt_s (call $get_x (local.get $p)) (local.get $n))))
+    (call $sign (local.get $total))))"#;
+        let mut binary =
+            compile_wat_internal(source, "inlined.wat", options::all_features()).unwrap();
+        let inlined = inline::inline(&mut binary).unwrap();
+        // The exported getter and the function with control flow stay
+        assert_eq!(
+            inlined,
+            inline::Inlined {
+                calls: 3,
+                functions: 2
+            }
+        );
+        wasmparser::Validator::new_with_features(options::all_features())
+            .validate_all(&binary)
+            .unwrap();
+        let names = instrument::function_names(&binary).unwrap();
+        let mut names: Vec<_> = names.into_values().collect();
+        names.sort();
+        assert_eq!(names, ["get_y", "sign", "sum"]);
+
+        // Nothing is left to inline
+        let inlined_once = binary.clone();
+        assert_eq!(
+            inline::inline(&mut binary).unwrap(),
+            inline::Inlined::default()
+        );
+        assert_eq!(binary, inlined_once);
+
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-inline").then(String::new));
+        assert!(options.inline);
+        compile_wat_to_js(source, "inlined.wat", None, &options).unwrap();
+    }
+
+    #[test]
+    fn test_sugar_constant_folding() {
+        let source = r#"(module (@sugar)
+  (global $WIDTH i32 (i32.const 320))
+  (global $count (mut i32) (i32.const 0))
+  (func $f (export "f") (param $x i32) (result i32)
+    let $bytes = $WIDTH * 4 + len("px")
+    let $scaled = $x * (2 + 3)
+    let $next = $count + 1
+    let $half: f32 = 1.5 * 2
+    let $trap = 7 / 0
+    $bytes + $scaled + $next + $trap
+  )
+)"#;
+
+        let desugared = sugar::desugar(source).unwrap();
+        assert!(desugared.contains("(local.set $bytes (i32.const 1282))"));
+        assert!(desugared.contains("(local.set $scaled (i32.mul (local.get $x) (i32.const 5)))"));
+        // Mutable globals are read at run time
+        assert!(desugared.contains("(i32.add (global.get $count) (i32.const 1))"));
+        assert!(desugared.contains("(local.set $half (f32.const 3.0))"));
+        // Traps stay in the module
+        assert!(desugared.contains("(i32.div_s (i32.const 7) (i32.const 0))"));
+        assert!(compile_wat_internal(source, "folded.wat", options::all_features()).is_ok());
+    }
+
+    #[test]
+    fn test_name_style() {
+        let source = r#"(module
+  (memory (export "memory") 1)
+  (func (export "draw_frame"))
+  (func (export "__heap_base"))
+  (func (export "get_x") (result i32) (i32.const 1))
+  (func (export "getX") (result i32) (i32.const 2))
+  (func (export "tick_v2") (;@export-as tick;))
+)"#;
+        assert_eq!(aliases::camel_case("draw_frame"), "drawFrame");
+        assert_eq!(aliases::camel_case("load-image_2d"), "loadImage2d");
+        assert_eq!(aliases::camel_case("__heap_base"), "__heapBase");
+        assert_eq!(aliases::camel_case("type_"), "type_");
+
+        let binary = wat::parse_str(source).unwrap();
+        let aliases_json = aliases::aliases_json(source, &binary);
+        assert_eq!(
+            aliases::styled_aliases_json(&aliases_json, &binary, aliases::NameStyle::Wasm),
+            aliases_json
+        );
+        let styled: serde_json::Value = serde_json::from_str(&aliases::styled_aliases_json(
+            &aliases_json,
+            &binary,
+            aliases::NameStyle::Camel,
+        ))
+        .unwrap();
+        // get_x keeps its name, getX is taken
+        assert_eq!(
+            styled,
+            serde_json::json!({ "draw_frame": "drawFrame", "__heap_base": "__heapBase", "tick_v2": "tick" })
+        );
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-name-style").then(|| "camel".to_string())
+        });
+        assert_eq!(options.name_style, aliases::NameStyle::Camel);
+        let js = compile_wat_to_js(source, "styled.wat", None, &options).unwrap();
+        assert!(js.contains(r#""draw_frame":"drawFrame""#));
+        assert!(js.contains("wasmEntry.nameStyle = 'camel';"));
+        assert!(js.contains("wasmEntry.exports[wasmName] = target[name];"));
+    }
+
+    #[test]
+    fn test_export_filter() {
+        assert!(exports::matches("render_*", "render_frame"));
+        assert!(exports::matches("*_frame", "render_frame"));
+        assert!(exports::matches("r*d*r_*", "render_frame"));
+        assert!(!exports::matches("render_*", "prerender_frame"));
+        assert!(exports::matches("init", "init"));
+        assert!(!exports::matches("init", "init2"));
+
+        let source = r#"(module
+  (func (export "render_frame"))
+  (func (export "render_text"))
+  (func (export "init"))
+  (func (export "string_len") (result i32) (i32.const 0))
+  (func (export "update_all"))
+)"#;
+        let binary = wat::parse_str(source).unwrap();
+        let patterns = vec![
+            "render_*".to_string(),
+            "init".to_string(),
+            "updateAll".to_string(),
+        ];
+        assert_eq!(exports::installed_json(None, "{}", &binary), "null");
+        assert_eq!(
+            exports::installed_json(Some(&patterns), "{}", &binary),
+            r#"["render_frame","render_text","init"]"#
+        );
+        // Patterns also match the names exports are installed under
+        assert_eq!(
+            exports::installed_json(Some(&patterns), r#"{"update_all":"updateAll"}"#, &binary),
+            r#"["render_frame","render_text","init","update_all"]"#
+        );
+
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-exports").then(|| "render_*, init".to_string())
+        });
+        assert_eq!(
+            options.exports,
+            Some(vec!["render_*".to_string(), "init".to_string()])
+        );
+        let js = compile_wat_to_js(source, "filtered.wat", None, &options).unwrap();
+        assert!(js.contains(r#"const exportFilter = ["render_frame","render_text","init"];"#));
+        // Extras are disposed of only where the export was installed
+        assert!(js.contains(
+            "if (target === exportTarget) {\n                                installedExtras[name + '_views'] = target[name + '_views'];"
+        ));
+        let js =
+            compile_wat_to_js(source, "filtered.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const exportFilter = null;"));
+
+        let empty =
+            CompileOptions::from_attributes(|name| (name == "data-exports").then(String::new));
+        assert_eq!(empty.exports, None);
+    }
+
+    #[test]
+    fn test_export_filter_helpers() {
+        // The patterns select the page helpers as well
+        let filter = |patterns: &str| {
+            let patterns = patterns.to_string();
+            CompileOptions::from_attributes(move |name| {
+                (name == "data-exports").then(|| patterns.clone())
+            })
+        };
+        let source = r#"(module
+  (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+  (global (export "g") i32 (i32.const 1)))"#;
+        let js = compile_wat_to_js(source, "plain.wat", None, &filter("a*,g")).unwrap();
+        assert!(!js.contains("window.wasmBind"));
+        let js = compile_wat_to_js(source, "plain.wat", None, &filter("a*,wasmBind")).unwrap();
+        assert!(js.contains("window.wasmBind = function(target, compute, watch) {"));
+
+        let source = r#"(module
+  (type $string (array (mut i8)))
+  (type $box (struct (field $hp (mut i32))))
+  (func (export "make") (result (ref $box)) (struct.new $box (i32.const 10))))"#;
+        let js = compile_wat_to_js(source, "box.wat", None, &filter("make,wasmWatch")).unwrap();
+        assert!(
+            js.contains("window.wasmWatch = window.wasmWatch || function(ref, field, callback) {")
+        );
+        // The loader of the module still reads and sets the fields
+        assert!(js.contains("structFieldSet = function(structObj, fieldIndex, value) {"));
+        for helper in [
+            "window.__wasmStrings",
+            "window.WasmStringToJs",
+            "window.WasmStringFromJs",
+            "window._wasmExports = ",
+            "window.WasmGcStructDisplay",
+            "window.WasmGcStructGet",
+            "window.WasmGcStructSet",
+            "window.wasmSetFields",
+            "window.__wasmStructSetters",
+            "window.WasmListGetters",
+            "window.__wasmFieldNames",
+            "window.wasmBind",
+        ] {
+            assert!(!js.contains(helper), "{} installed", helper);
+        }
+        let js = compile_wat_to_js(source, "box.wat", None, &filter("Wasm*")).unwrap();
+        assert!(js.contains("window.__wasmStrings[wasmFilename] = {"));
+        assert!(js.contains("window.WasmGcStructGet = structFieldGet;"));
+        assert!(!js.contains("window.wasmWatch = "));
+    }
+
+    #[test]
+    fn test_import_stubs() {
+        let source = r#"(module
+  (import "env" "missing" (func $missing (param i32)))
+  (import "env" "table" (table 1 funcref))
+  (func (export "f") (call $missing (i32.const 1))))"#;
+        let js = compile_wat_to_js(source, "stubs.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const stubImports = false;"));
+
+        let options =
+            CompileOptions::from_attributes(|name| (name == "data-stub-imports").then(String::new));
+        assert!(options.stub_imports);
+        let js = compile_wat_to_js(source, "stubs.wat", None, &options).unwrap();
+        assert!(js.contains("const stubImports = true;"));
+        // Only functions can be stubbed
+        assert!(js.contains("if (value === undefined && stubImports && kind === 'function') {"));
+    }
+
+    #[test]
+    fn test_import_map() {
+        assert_eq!(
+            imports::parse_import_map(r#"{"env.random": "myRng", "env.log": "console.debug"}"#),
+            Some(vec![
+                ("env.log".to_string(), "console.debug".to_string()),
+                ("env.random".to_string(), "myRng".to_string()),
+            ])
+        );
+        assert_eq!(imports::parse_import_map(r#"{"random": "myRng"}"#), None);
+        assert_eq!(imports::parse_import_map(r#"{"env.random": " "}"#), None);
+        assert_eq!(imports::parse_import_map("[]"), None);
+
+        let source = r#"(module
+  (import "env" "random" (func (result f64)))
+  (import "env" "log" (func (param i32))))"#;
+        let options = CompileOptions::from_attributes(|name| {
+            (name == "data-import-map").then(|| r#"{"env.log": "console.debug"}"#.to_string())
+        });
+        assert_eq!(
+            options.import_map,
+            vec![("env.log".to_string(), "console.debug".to_string())]
+        );
+        let js = compile_wat_to_js(source, "mapped.wat", None, &options).unwrap();
+        assert!(js.contains(r#"const importMap = {"env.log":"console.debug"};"#));
+        let js = compile_wat_to_js(source, "mapped.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const importMap = {};"));
+    }
+
+    #[test]
+    fn test_shared_memory() {
+        let heap = shared::SharedMemory::parse("heap; initial=16; maximum=256; shared").unwrap();
+        assert_eq!(
+            heap,
+            shared::SharedMemory {
+                name: "heap".to_string(),
+                initial: 16,
+                maximum: Some(256),
+                shared: true,
+            }
+        );
+        assert_eq!(shared::SharedMemory::parse("scratch").unwrap().initial, 1);
+        // Shared memories need a maximum
+        assert_eq!(shared::SharedMemory::parse("heap; shared"), None);
+        assert_eq!(
+            shared::SharedMemory::parse("heap; initial=4; maximum=2"),
+            None
+        );
+        assert_eq!(shared::SharedMemory::parse("heap; pages=2"), None);
+        assert_eq!(
+            shared::declared_json(&[heap], &[]).0,
+            r#"{"heap":{"initial":16,"maximum":256,"shared":true}}"#
+        );
+
+        let source = r#"(module
+  (import "shared" "heap" (memory 2 16 shared))
+  (func (export "peek") (result i32) (i32.load (i32.const 0))))"#;
+        let binary = wat::parse_str(source).unwrap();
+        assert_eq!(
+            shared::imported(&binary).unwrap().to_json().0,
+            r#"{"heap":{"initial":2,"maximum":16,"shared":true}}"#
+        );
+        assert_eq!(shared::imported(&wat::parse_str("(module)").unwrap()), None);
+
+        let js = compile_wat_to_js(source, "shared.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#"const importedMemories = {"heap":{"initial":2,"maximum":16,"shared":true}};"#
+        ));
+        assert!(js.contains("importObject['shared'] = sharedScope;"));
+        assert!(js.contains("const deferredImports = ['shared'];"));
+        // Created once the module passed its checks, against the page's budget
+        let options = CompileOptions {
+            memory_budget: Some(4 * 65536),
+            ..CompileOptions::default()
+        };
+        let js = compile_wat_to_js(source, "shared.wat", None, &options).unwrap();
+        assert!(
+            js.find("missingFeatures.length > 0").unwrap()
+                < js.find("window.wasmShared = ").unwrap()
+        );
+        assert!(
+            js.find("const memoryPageBytes").unwrap() < js.find("window.wasmShared = ").unwrap()
+        );
+        assert!(js.contains("if (memoryQuotaError(memoryHeld() + sharedBytes)) {"));
+        assert!(js.contains("window.__wasmMemoryUse['wasmShared'] = function() {"));
+        let js = compile_wat_to_js("(module)", "unshared.wat", None, &CompileOptions::default())
+            .unwrap();
+        assert!(!js.contains("window.wasmShared"));
+    }
+
+    #[test]
+    fn test_shared_table() {
+        let table = shared::SharedTable::parse("functions; initial=4; maximum=1024").unwrap();
+        assert_eq!(
+            table,
+            shared::SharedTable {
+                name: "functions".to_string(),
+                element: "anyfunc",
+                initial: 4,
+                maximum: Some(1024),
+                slots: None,
+            }
+        );
+        assert_eq!(
+            shared::SharedTable::parse("handles; element=externref")
+                .unwrap()
+                .element,
+            "externref"
+        );
+        assert_eq!(shared::SharedTable::parse("functions; element=i32"), None);
+        assert_eq!(
+            shared::SharedTable::parse("functions; initial=8; maximum=4"),
+            None
+        );
+        assert_eq!(
+            shared::declared_json(&[], &[table]).1,
+            r#"{"functions":{"element":"anyfunc","initial":4,"maximum":1024}}"#
+        );
+
+        // Two segments at the base, the second three slots after it
+        let source = r#"(module
+  (import "shared" "functions" (table 0 funcref))
+  (import "shared" "functions.base" (global $base i32))
+  (func $draw)
+  (func $update)
+  (elem (table 0) (offset (global.get $base)) func $draw $update)
+  (elem (table 0) (offset (i32.add (global.get $base) (i32.const 3))) func $draw))"#;
+        let binary = wat::parse_str(source).unwrap();
+        assert_eq!(
+            shared::imported(&binary).unwrap().to_json().1,
+            r#"{"functions":{"element":"anyfunc","initial":0,"slots":4}}"#
+        );
+        let js = compile_wat_to_js(source, "plugin.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(
+            r#"const importedTables = {"functions":{"element":"anyfunc","initial":0,"slots":4}};"#
+        ));
+        assert!(js.contains("window.__wasmTableBases[wasmFilename] = tableBases;"));
+        // Reserved once per filename, a module loaded again is given the same
+        assert!(js.contains(
+            "if (!held || held.table !== window.wasmShared[name] || held.slots < slots) {"
+        ));
+
+        // Without a base the table is only shared
+        let binary =
+            wat::parse_str(r#"(module (import "shared" "functions" (table 1 funcref)))"#).unwrap();
+        assert_eq!(
+            shared::imported(&binary).unwrap().to_json().1,
+            r#"{"functions":{"element":"anyfunc","initial":1}}"#
+        );
+    }
+
+    #[test]
+    fn test_runtime_loader() {
+        let source = r#"(module
+  (import "loader" "load" (func $load (param i32 i32) (result i32)))
+  (import "loader" "export" (func $export (param i32 i32 i32) (result funcref)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "plugin.wat")
+  (func (export "start") (result i32) (call $load (i32.const 0) (i32.const 10))))"#;
+        let binary = wat::parse_str(source).unwrap();
+        assert!(loader::imports_loader(&binary));
+        assert!(!loader::imports_loader(
+            &wat::parse_str("(module)").unwrap()
+        ));
+
+        let js = compile_wat_to_js(source, "host.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("const loaderImports = {"));
+        assert!(js.contains("const fallbackImports = [loaderImports];"));
+        assert!(js.contains("script.type = 'text/wat';"));
+        assert!(js.contains("window.__wasmModules[wasmFilename].instance = result.instance;"));
+        let js =
+            compile_wat_to_js("(module)", "plain.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("loaderImports"));
+    }
+
+    #[test]
+    fn test_set_fields() {
+        let source = r#"(module
+  (type $string (array (mut i8)))
+  (type $player (struct (field $score (mut i32)) (field $name (mut (ref null $string))) (field $id i32)))
+  (func (export "make") (result (ref $player))
+    (struct.new $player (i32.const 0) (ref.null $string) (i32.const 7))))"#;
+        let mut binary = wat::parse_str(source).unwrap();
+        let string_types = strings::add_accessors(&mut binary).unwrap();
+        // Only the name holds a string
+        assert_eq!(
+            structs::string_fields_json(&binary, &string_types),
+            r#"{"1":[null,0,null]}"#
+        );
+        assert_eq!(structs::string_fields_json(&binary, &[]), "{}");
+
+        let js = compile_wat_to_js(source, "player.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains(r#"const structStrings = {"1":[null,0,null]};"#));
+        assert!(js.contains("window.__wasmStructSetters[wasmFilename] = setStructFields;"));
+        assert!(
+            js.contains("window.wasmSetFields = window.wasmSetFields || function(ref, values) {")
+        );
+    }
+
+    #[test]
+    fn test_bind() {
+        let source = r#"(module (func (export "get_score") (result i32) (i32.const 0)))"#;
+        let js = compile_wat_to_js(source, "score.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("window.wasmBind = function(target, compute, watch) {"));
+        assert!(js.contains("window.wasmBind.refresh = refresh;"));
+        assert!(
+            js.contains("for (const element of document.querySelectorAll('[data-wasm-bind]')) {")
+        );
+        // Defined by the first module only, and not disposed with it
+        assert!(js.contains("if (!window.wasmBind) {"));
+        // The attributes are parsed as a call of an export, not evaluated
+        assert!(!js.contains("new Function('return (' + expression"));
+        assert!(js.contains("return boundExports[call[1]](...args.map(bindingValue));"));
+    }
+
+    #[test]
+    fn test_page_helpers() {
+        // Modules without strings, structs or functions install none of
+        // their helpers on window
+        let js =
+            compile_wat_to_js("(module)", "empty.wat", None, &CompileOptions::default()).unwrap();
+        assert!(!js.contains("window.wasmBind"));
+        let source = r#"(module (func (export "f") (result i32) (i32.const 1)))"#;
+        let js = compile_wat_to_js(source, "plain.wat", None, &CompileOptions::default()).unwrap();
+        assert!(js.contains("window.wasmBind = function(target, compute, watch) {"));
+        for helper in [
+            "window.__wasmStrings",
+            "window.WasmStringToJs",
+            "window.WasmStringFromJs",
+            "window._wasmExports = ",
+            "window.WasmGcStructGet",
+            "window.WasmGcStructSet",
+            "window.wasmWatch = ",
+            "window.wasmSetFields",
+            "window.__wasmStructSetters",
+            "window.__wasmFieldNames",
+        ] {
+            assert!(!js.contains(helper), "{} installed", helper);
+        }
+
+        // Choosing an encoding installs the string converters
+        let options = CompileOptions {
+            strings: Some(options::StringEncoding::Utf8),
+            ..Default::default()
+        };
+        let js = compile_wat_to_js(source, "utf8.wat", None, &options).unwrap();
+        assert!(js.contains("window.WasmStringToJs = window.WasmStringToJs || function("));
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Struct type and field names
+//!
+//! The loader's metadata about struct types comes from the type section of
+//! the binary, the field count, types and mutability, and their names from the
+//! name section, so it is the same for binary modules, handwritten WAT and
+//! generated WAT. The text format writes the names of the fields it names
+//! into that section, so compiled WAT describes itself to devtools, external
+//! inspectors and anything reading the cache without the source. Types and
+//! fields without a name in the name section get `type3` and `field0` style
+//! names from their index, so the loader can wrap structs of anonymous types
+//! like any other.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasmparser::{CompositeInnerType, HeapType, Parser, Payload, StorageType, ValType};
+
+use super::arrays;
+
+/// A struct type as the loader reads it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructInfo {
+    pub type_name: String,
+    pub fields: Vec<String>,
+    /// Field types in WAT syntax, e.g. `i8` or `(ref null 2)`
+    pub field_types: Vec<String>,
+    pub mutable: Vec<bool>,
+}
+
+/// Field type in WAT syntax
+fn storage_type_text(ty: StorageType) -> String {
+    let value = match ty {
+        StorageType::I8 => return "i8".to_string(),
+        StorageType::I16 => return "i16".to_string(),
+        StorageType::Val(value) => value,
+    };
+    let ValType::Ref(ty) = value else {
+        return value.to_string();
+    };
+    let heap_type = match ty.heap_type() {
+        HeapType::Concrete(index) => index
+            .as_module_index()
+            .map_or_else(|| "?".to_string(), |index| index.to_string()),
+        HeapType::Abstract { shared, ty } => {
+            let name = format!("{:?}", ty).to_lowercase();
+            if shared {
+                format!("(shared {})", name)
+            } else {
+                name
+            }
+        },
+    };
+    if ty.is_nullable() {
+        format!("(ref null {})", heap_type)
+    } else {
+        format!("(ref {})", heap_type)
+    }
+}
+
+/// The struct types of `binary` by type index, named from the name section
+/// or else synthesized, see the module documentation
+pub fn struct_info(binary: &[u8]) -> BTreeMap<u32, StructInfo> {
+    let mut structs = BTreeMap::new();
+    let mut index = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::TypeSection(reader)) = payload else {
+            continue;
+        };
+        for group in reader.into_iter().flatten() {
+            for ty in group.types() {
+                if let CompositeInnerType::Struct(ty) = &ty.composite_type.inner {
+                    structs.insert(index, ty.fields.to_vec());
+                }
+                index += 1;
+            }
+        }
+    }
+
+    let (type_names, field_names) = arrays::type_names(binary);
+    structs
+        .into_iter()
+        .map(|(index, field_types)| {
+            let named = field_names.get(&index);
+            let fields = (0..field_types.len())
+                .map(|position| {
+                    named
+                        .and_then(|names| names.get(position))
+                        .filter(|name| !name.is_empty())
+                        .cloned()
+                        .unwrap_or_else(|| format!("field{}", position))
+                })
+                .collect();
+            let type_name = type_names
+                .get(&index)
+                .cloned()
+                .unwrap_or_else(|| format!("type{}", index));
+            let info = StructInfo {
+                type_name,
+                fields,
+                field_types: field_types
+                    .iter()
+                    .map(|field| storage_type_text(field.element_type))
+                    .collect(),
+                mutable: field_types.iter().map(|field| field.mutable).collect(),
+            };
+            (index, info)
+        })
+        .collect()
+}
+
+/// The struct types as JSON for the loader's `window.__wasmFieldNames`: by
+/// type index, and the first as `default`, for structs of unknown type
+pub fn field_names_json(binary: &[u8]) -> String {
+    let structs = struct_info(binary);
+    let mut json = serde_json::Map::new();
+    if let Some(first) = structs.values().next() {
+        json.insert("default".to_string(), serde_json::json!(first));
+    }
+    for (index, info) in &structs {
+        json.insert(index.to_string(), serde_json::json!(info));
+    }
+    serde_json::Value::Object(json).to_string()
+}
@@ -0,0 +1,618 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Per-script compile options
+//!
+//! WASM script elements configure compilation through `data-*` attributes:
+//!
+//! | Attribute            | Values                          | Effect                                              |
+//! |----------------------|---------------------------------|-----------------------------------------------------|
+//! | `data-opt`           | present, `0`/`false` to disable | strip custom sections the loader does not use       |
+//! | `data-features`      | `gc,threads,...`                | proposals the module may use, validated up front    |
+//! | `data-namespace`     | JS identifier                   | install exports on `window[namespace]`              |
+//! | `data-strings`       | see [`StringEncoding`]          | how `$string` values are exchanged with JavaScript  |
+//! | `data-display`       | `depth=3, length=200, string=N` | how much of a struct or string is shown             |
+//! | `data-debug`         | present, `0`/`false` to disable | log the lowered WAT and the module's exports        |
+//! | `data-start`         | `run`, `defer`, `skip`          | start function policy, see [`StartPolicy`]          |
+//! | `data-coverage`      | `functions`, `blocks`           | count calls and blocks, see [`super::coverage`]     |
+//! | `data-trace`         | `exports`, `all`                | log calls and arguments, see [`super::trace`]       |
+//! | `data-breakpoints`   | present, `0`/`false` to disable | break on function entry, see [`super::breakpoints`] |
+//! | `data-profile`       | present, `0`/`false` to disable | time functions, see [`super::profile`]              |
+//! | `data-hot-state`     | present, `0`/`false` to disable | keep globals and struct fields across reloads       |
+//! | `data-register`      | name, e.g. `physics@1.2`        | reuse across the origin, see [`super::registry`]    |
+//! | `data-lazy`          | present                         | compile ahead, see [`super::speculative`]           |
+//! | `data-priority`      | `high`, `auto`, `low`           | order of compiling ahead, overrides `fetchpriority` |
+//! | `data-wasm-bindgen`  | URL of the generated JavaScript | load through wasm-bindgen, see [`super::bindgen`]   |
+//! | `data-wasi-preopens` | `/data, /tmp=memory`            | directories of WASI modules, see [`super::wasi`]    |
+//! | `data-wasi-args`     | `["--verbose", "input.txt"]`    | arguments of WASI modules after the program name    |
+//! | `data-wasi-env`      | `{"LANG": "C"}`                 | environment variables of WASI modules               |
+//! | `data-stdout`        | `#terminal`, `callback:name`    | where WASI modules write, see [`super::wasi`]       |
+//! | `data-stderr`        | like `data-stdout`              | where WASI modules write errors                     |
+//! | `data-lifecycle`     | see [`Lifecycle`]               | what becomes of the module as the page is hidden    |
+//! | `data-no-stdlib`     | present, `0`/`false` to disable | skip linking `std/`, see [`super::stdlib`]          |
+//! | `data-encoding`      | `base64`                        | body is a module in base64, see [`super::sniff`]    |
+//! | `data-size-report`   | present, `0`/`false` to disable | log where the bytes go, see [`super::sizes`]        |
+//! | `data-keep`          | `init,tick`                     | shake off other exports, see [`super::shake`]       |
+//! | `data-inline`        | present, `0`/`false` to disable | inline small functions, see [`super::inline`]       |
+//! | `data-name-style`    | `camel`, `wasm`                 | names of the exports, see [`super::aliases`]        |
+//! | `data-exports`       | `render_*,init`                 | install matching exports, see [`super::exports`]    |
+//! | `data-stub-imports`  | present, `0`/`false` to disable | stub missing functions, see [`super::imports`]      |
+//! | `data-import-map`    | `{"env.log": "console.debug"}`  | bind imports to expressions, see [`super::imports`] |
+//!
+//! A `<meta name="wat-compiler" content="opt; strings=utf16; namespace=app">`
+//! gives defaults for all WAT scripts of the page, see [`PageDefaults`]; the
+//! attributes of a script override them.
+//!
+//! Independently of the script, the `dom_wat_scripts_features` pref limits the
+//! proposals any module may use; see [`CompileOptions::enabled_features`]. The
+//! `dom_wat_scripts_memory_budget_mb` pref limits their memory, see
+//! [`CompileOptions::memory_budget`], and the `dom_wat_scripts_threads_*`
+//! prefs the origins that may use shared memory and threads, see
+//! [`CompileOptions::threads_denied`].
+//!
+//! Memories and tables shared between the modules of the page are declared
+//! in `<meta name="wasm-memory">` and `<meta name="wasm-table">`, see
+//! [`super::shared`].
+
+use servo_config::pref;
+use wasmparser::WasmFeatures;
+
+use super::aliases::NameStyle;
+use super::budget;
+use super::coverage::Coverage;
+use super::imports;
+use super::registry::RegisteredName;
+use super::shared::{SharedMemory, SharedTable};
+use super::trace::Trace;
+use super::wasi::{self, Output, Preopen};
+use super::{LOG_TARGET, StartPolicy};
+
+/// Proposals that can be listed in `data-features`
+pub const FEATURES: [(&str, WasmFeatures); 9] = [
+    (
+        "gc",
+        WasmFeatures::GC.union(WasmFeatures::FUNCTION_REFERENCES),
+    ),
+    ("threads", WasmFeatures::THREADS),
+    ("exceptions", WasmFeatures::EXCEPTIONS),
+    ("tail-call", WasmFeatures::TAIL_CALL),
+    ("simd", WasmFeatures::SIMD),
+    ("relaxed-simd", WasmFeatures::RELAXED_SIMD),
+    ("memory64", WasmFeatures::MEMORY64),
+    ("multi-memory", WasmFeatures::MULTI_MEMORY),
+    ("extended-const", WasmFeatures::EXTENDED_CONST),
+];
+
+/// Every proposal the pipeline supports: WebAssembly 3.0 and all of [`FEATURES`]
+pub fn all_features() -> WasmFeatures {
+    FEATURES
+        .iter()
+        .fold(WasmFeatures::WASM3, |features, (_, flags)| {
+            features | *flags
+        })
+}
+
+/// Parse a comma or space separated list of [`FEATURES`] names, on top of
+/// WebAssembly 2.0
+pub fn parse_feature_list(value: &str) -> WasmFeatures {
+    let mut features = WasmFeatures::WASM2;
+    for name in value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+    {
+        let name = name.to_ascii_lowercase();
+        match FEATURES.iter().find(|(feature, _)| *feature == name) {
+            Some((_, flags)) => features |= *flags,
+            None => log::warn!(
+                target: LOG_TARGET, phase = "options", feature:% = name;
+                "Unknown feature {:?}", name
+            ),
+        }
+    }
+    features
+}
+
+/// Representation of `$string` values on the JavaScript side
+/// Modules on a page may each use their own, detected from the module unless
+/// `data-strings` gives it, see [`super::strings::detect_encoding`].
+#[derive(Clone, Copy, Debug, Default, MallocSizeOf, PartialEq)]
+pub enum StringEncoding {
+    /// GC array of UTF-8 bytes, the representation of the string-type sugar
+    #[default]
+    Utf8,
+    /// GC array of UTF-16 code units
+    Utf16,
+    /// NUL-terminated UTF-8 in memory 0; strings passed to exports are a
+    /// pointer and a length, see [`super::strings`]
+    Linear,
+    /// UTF-8 in memory 0 as the canonical ABI lays it out: strings passed to
+    /// exports are a pointer and a length allocated with `cabi_realloc`, and
+    /// those returned a pointer to both
+    Canonical,
+    /// JavaScript strings held as `externref`, through the `wasm:js-string`
+    /// builtins
+    JsString,
+}
+
+impl StringEncoding {
+    pub fn parse(value: &str) -> Option<StringEncoding> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Some(StringEncoding::Utf8),
+            "utf16" | "utf-16" => Some(StringEncoding::Utf16),
+            "linear" => Some(StringEncoding::Linear),
+            "canonical" | "cabi" => Some(StringEncoding::Canonical),
+            "js-string" | "builtins" => Some(StringEncoding::JsString),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StringEncoding::Utf8 => "utf8",
+            StringEncoding::Utf16 => "utf16",
+            StringEncoding::Linear => "linear",
+            StringEncoding::Canonical => "canonical",
+            StringEncoding::JsString => "js-string",
+        }
+    }
+}
+
+/// What becomes of a module as its page is hidden, frozen, put in the
+/// back/forward cache or unloaded, given by `data-lifecycle`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Lifecycle {
+    /// Nothing: the timers of the loader keep running in the background
+    Keep,
+    /// The timers of the loader, such as those of the TinyGo scheduler, wait
+    /// while the page is hidden or frozen, and run once it is shown again
+    #[default]
+    Suspend,
+    /// Also dispose the module once the page is frozen or left
+    Dispose,
+    /// Also instantiate the module again, with the state of its globals as
+    /// `data-hot-state` copies it, once a page frozen or in the back/forward
+    /// cache is shown again
+    Reload,
+}
+
+impl Lifecycle {
+    pub fn parse(value: &str) -> Option<Lifecycle> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "keep" => Some(Lifecycle::Keep),
+            "suspend" => Some(Lifecycle::Suspend),
+            "dispose" => Some(Lifecycle::Dispose),
+            "reload" => Some(Lifecycle::Reload),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lifecycle::Keep => "keep",
+            Lifecycle::Suspend => "suspend",
+            Lifecycle::Dispose => "dispose",
+            Lifecycle::Reload => "reload",
+        }
+    }
+}
+
+/// How much of a GC struct its `toString` shows, so that logging a large
+/// graph of them stays cheap
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayLimits {
+    /// Levels of nested structs shown; deeper ones are shown as `…`
+    pub depth: u32,
+    /// Characters of a struct and string elements of a nested string shown,
+    /// the rest being cut to `…`
+    pub length: u32,
+    /// Code units of the longest string converted to a JavaScript string
+    /// through the `string_len` and `string_get_byte` exports of a module;
+    /// longer ones are left as GC objects. Strings of the types of
+    /// [`super::strings`] are read in full.
+    pub string: u32,
+}
+
+impl Default for DisplayLimits {
+    fn default() -> DisplayLimits {
+        DisplayLimits {
+            depth: 3,
+            length: 200,
+            string: 10_000,
+        }
+    }
+}
+
+impl DisplayLimits {
+    /// Parse `depth=N`, `length=N` and `string=N` separated by commas or
+    /// spaces; the limits not given keep their default
+    pub fn parse(value: &str) -> Option<DisplayLimits> {
+        let mut limits = DisplayLimits::default();
+        for pair in value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|pair| !pair.is_empty())
+        {
+            let (name, number) = pair.split_once('=')?;
+            let number = number.parse().ok().filter(|number| *number > 0)?;
+            match name.to_ascii_lowercase().as_str() {
+                "depth" => limits.depth = number,
+                "length" => limits.length = number,
+                "string" => limits.string = number,
+                _ => return None,
+            }
+        }
+        Some(limits)
+    }
+}
+
+/// Options for [`super::compile_wat_to_js`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompileOptions {
+    pub optimize: bool,
+    /// Proposals on top of WebAssembly 2.0; `None` skips up-front validation
+    pub features: Option<WasmFeatures>,
+    pub namespace: Option<String>,
+    /// `None` uses the encoding detected from the module, see
+    /// [`super::strings::detect_encoding`]
+    pub strings: Option<StringEncoding>,
+    pub display: DisplayLimits,
+    pub debug: bool,
+    pub start: StartPolicy,
+    /// Proposals enabled by preferences; `None` enables [`all_features`]
+    pub enabled_features: Option<WasmFeatures>,
+    /// Bytes of memory the modules of the page may allocate together, from
+    /// preferences; `None` for no limit, see [`super::budget`]
+    pub memory_budget: Option<u64>,
+    /// The origin has no permission for shared memory and threads, from
+    /// preferences: modules using them are not instantiated
+    pub threads_denied: bool,
+    /// Name of the script in diagnostics and events, set by the element for
+    /// URLs that make a poor one; `None` uses the script's URL
+    pub name: Option<String>,
+    /// URL and HTTP validator (a strong ETag or Last-Modified) of a fetched
+    /// source; a source fetched again with the same one is not hashed to find
+    /// its module in the cache
+    pub validator: Option<String>,
+    pub coverage: Coverage,
+    pub trace: Trace,
+    pub breakpoints: bool,
+    pub profile: bool,
+    /// Copy the state of the module last loaded under the same filename
+    pub hot_state: bool,
+    /// Register the module under this name, set by the element from
+    /// `data-register` since it is scoped to the origin
+    pub register: Option<RegisteredName>,
+    /// URL of the JavaScript wasm-bindgen generated for the module, see
+    /// [`super::bindgen`]
+    pub wasm_bindgen: Option<String>,
+    /// Directories WASI modules open files in, see [`super::wasi`]
+    pub wasi_preopens: Vec<Preopen>,
+    /// Arguments of WASI modules after the program name
+    pub wasi_args: Vec<String>,
+    /// Environment variables of WASI modules
+    pub wasi_env: Vec<(String, String)>,
+    /// Where WASI modules write stdout
+    pub wasi_stdout: Output,
+    /// Where WASI modules write stderr
+    pub wasi_stderr: Output,
+    pub lifecycle: Lifecycle,
+    /// Leave the imports of the WAT standard library to the page
+    pub no_stdlib: bool,
+    /// The body is a binary module in base64, from `data-encoding`; it is
+    /// decoded without sniffing, see [`super::sniff`]
+    pub base64: bool,
+    /// Install and log the size report of the module, see [`super::sizes`]
+    pub size_report: bool,
+    /// Exports to keep, from `data-keep`; the other function exports and
+    /// what only they reach are removed, see [`super::shake`]
+    pub keep: Option<Vec<String>>,
+    /// Inline the small functions only the module calls, see
+    /// [`super::inline`]
+    pub inline: bool,
+    /// Names the exports are installed under, see [`super::aliases`]
+    pub name_style: NameStyle,
+    /// Patterns of the exports and page helpers to install, from
+    /// `data-exports`; `None` installs all of them, see [`super::exports`]
+    pub exports: Option<Vec<String>>,
+    /// Stand in for the imported functions the page does not provide with
+    /// functions that throw, see [`super::imports`]
+    pub stub_imports: bool,
+    /// Expressions of the page imports are bound to, by `module.name`, from
+    /// `data-import-map`
+    pub import_map: Vec<(String, String)>,
+    /// Memories the page declares in `<meta name="wasm-memory">`, set by the
+    /// element, see [`super::shared`]
+    pub shared_memories: Vec<SharedMemory>,
+    /// Tables the page declares in `<meta name="wasm-table">`, set by the
+    /// element, see [`super::shared`]
+    pub shared_tables: Vec<SharedTable>,
+}
+
+/// Defaults for the WAT scripts of a page, from the content of its
+/// `<meta name="wat-compiler">`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PageDefaults(Vec<(String, String)>);
+
+impl PageDefaults {
+    /// Parse `name=value` pairs separated by `;`, named like the attributes
+    /// without `data-`; a name without a value enables a boolean option
+    pub fn parse(content: &str) -> PageDefaults {
+        PageDefaults(
+            content
+                .split(';')
+                .filter_map(|pair| {
+                    let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    let name = name.trim().to_ascii_lowercase();
+                    (!name.is_empty()).then(|| (format!("data-{}", name), value.trim().to_string()))
+                })
+                .collect(),
+        )
+    }
+
+    /// The default for the attribute `name`; the last one given wins
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+impl CompileOptions {
+    /// The options the prefs set for every module of a page, see
+    /// [`CompileOptions::with_prefs`]
+    pub fn from_prefs(threads_permitted: bool) -> CompileOptions {
+        CompileOptions::default().with_prefs(threads_permitted)
+    }
+
+    /// These options with those the prefs set rather than the script: the
+    /// proposals enabled, the memory budget, and whether shared memory and
+    /// threads are denied, as `threads_permitted` tells for the origin
+    pub fn with_prefs(self, threads_permitted: bool) -> CompileOptions {
+        CompileOptions {
+            enabled_features: Some(parse_feature_list(&pref!(dom_wat_scripts_features))),
+            memory_budget: budget::from_pref(pref!(dom_wat_scripts_memory_budget_mb)),
+            threads_denied: !threads_permitted,
+            ..self
+        }
+    }
+
+    /// Read the options from `data-*` attributes; `attribute` returns the value
+    /// of an attribute if it is present
+    /// Invalid values are reported and fall back to the defaults
+    pub fn from_attributes(attribute: impl Fn(&str) -> Option<String>) -> CompileOptions {
+        let mut options = CompileOptions {
+            optimize: attribute("data-opt").is_some_and(|value| is_enabled(&value)),
+            debug: attribute("data-debug").is_some_and(|value| is_enabled(&value)),
+            breakpoints: attribute("data-breakpoints").is_some_and(|value| is_enabled(&value)),
+            profile: attribute("data-profile").is_some_and(|value| is_enabled(&value)),
+            hot_state: attribute("data-hot-state").is_some_and(|value| is_enabled(&value)),
+            no_stdlib: attribute("data-no-stdlib").is_some_and(|value| is_enabled(&value)),
+            size_report: attribute("data-size-report").is_some_and(|value| is_enabled(&value)),
+            inline: attribute("data-inline").is_some_and(|value| is_enabled(&value)),
+            stub_imports: attribute("data-stub-imports").is_some_and(|value| is_enabled(&value)),
+            ..Default::default()
+        };
+
+        if let Some(value) = attribute("data-encoding") {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "base64" => options.base64 = true,
+                _ => log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-encoding", value:% = value;
+                    "Unknown data-encoding value {:?}, sniffing the body", value
+                ),
+            }
+        }
+        if let Some(value) = attribute("data-keep") {
+            let keep: Vec<String> = value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            if keep.is_empty() {
+                log::warn!(
+                    target: LOG_TARGET, phase = "options", attribute = "data-keep";
+                    "Ignoring empty data-keep, keeping all exports"
+                );
+            } else {
+                options.keep = Some(keep);
+            }
+        }
+        if let Some(value) = attribute("data-exports") {
+            let patterns: Vec<String> = value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::to_string)
+                .collect();
+            if patterns.is_empty() {
+                log::warn!(
+                    target: LOG_TARGET, phase = "options", attribute = "data-exports";
+                    "Ignoring empty data-exports, installing all exports"
+                );
+            } else {
+                options.exports = Some(patterns);
+            }
+        }
+        if let Some(value) = attribute("data-features") {
+            options.features = Some(parse_feature_list(&value));
+        }
+        if let Some(value) = attribute("data-namespace") {
+            let value = value.trim();
+            if is_identifier(value) {
+                options.namespace = Some(value.to_string());
+            } else {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-namespace", value:% = value;
+                    "Ignoring invalid data-namespace {:?}", value
+                );
+            }
+        }
+        if let Some(value) = attribute("data-wasm-bindgen") {
+            let value = value.trim();
+            if value.is_empty() {
+                log::warn!(
+                    target: LOG_TARGET, phase = "options", attribute = "data-wasm-bindgen";
+                    "Ignoring empty data-wasm-bindgen"
+                );
+            } else {
+                options.wasm_bindgen = Some(value.to_string());
+            }
+        }
+        if let Some(value) = attribute("data-wasi-preopens") {
+            options.wasi_preopens = Preopen::parse_list(&value);
+        }
+        if let Some(value) = attribute("data-wasi-args") {
+            options.wasi_args = wasi::parse_args(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-wasi-args", value:% = value;
+                    "Ignoring data-wasi-args {:?}, not a JSON array of strings", value
+                );
+                Vec::new()
+            });
+        }
+        if let Some(value) = attribute("data-wasi-env") {
+            options.wasi_env = wasi::parse_env(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-wasi-env", value:% = value;
+                    "Ignoring data-wasi-env {:?}, not a JSON object of strings", value
+                );
+                Vec::new()
+            });
+        }
+        if let Some(value) = attribute("data-import-map") {
+            options.import_map = imports::parse_import_map(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-import-map", value:% = value;
+                    "Ignoring data-import-map {:?}, not a JSON object of expressions by module.name",
+                    value
+                );
+                Vec::new()
+            });
+        }
+        for (name, output) in [
+            ("data-stdout", &mut options.wasi_stdout),
+            ("data-stderr", &mut options.wasi_stderr),
+        ] {
+            if let Some(value) = attribute(name) {
+                *output = Output::parse(&value).unwrap_or_else(|| {
+                    log::warn!(
+                        target: LOG_TARGET, phase = "options", attribute:% = name, value:% = value;
+                        "Invalid {} value {:?}, writing to the console", name, value
+                    );
+                    Output::Console
+                });
+            }
+        }
+        if let Some(value) = attribute("data-strings") {
+            options.strings = StringEncoding::parse(&value);
+            if options.strings.is_none() {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-strings", value:% = value;
+                    "Unknown data-strings value {:?}, detecting it from the module", value
+                );
+            }
+        }
+        if let Some(value) = attribute("data-display") {
+            options.display = DisplayLimits::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-display", value:% = value;
+                    "Invalid data-display value {:?}, using defaults", value
+                );
+                DisplayLimits::default()
+            });
+        }
+        if let Some(value) = attribute("data-start") {
+            options.start = StartPolicy::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-start", value:% = value;
+                    "Unknown data-start value {:?}, running start function", value
+                );
+                StartPolicy::default()
+            });
+        }
+        if let Some(value) = attribute("data-coverage") {
+            options.coverage = Coverage::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-coverage", value:% = value;
+                    "Unknown data-coverage value {:?}, counting functions", value
+                );
+                Coverage::Functions
+            });
+        }
+        if let Some(value) = attribute("data-lifecycle") {
+            options.lifecycle = Lifecycle::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-lifecycle", value:% = value;
+                    "Unknown data-lifecycle value {:?}, suspending in the background", value
+                );
+                Lifecycle::default()
+            });
+        }
+        if let Some(value) = attribute("data-name-style") {
+            options.name_style = NameStyle::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-name-style", value:% = value;
+                    "Unknown data-name-style value {:?}, keeping the names of the module", value
+                );
+                NameStyle::default()
+            });
+        }
+        if let Some(value) = attribute("data-trace") {
+            options.trace = Trace::parse(&value).unwrap_or_else(|| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-trace", value:% = value;
+                    "Unknown data-trace value {:?}, tracing exports", value
+                );
+                Trace::Exports
+            });
+        }
+
+        options
+    }
+
+    /// Whether an instrumentation pass rewrites the module
+    pub fn instruments(&self) -> bool {
+        self.coverage != Coverage::Off
+            || self.trace != Trace::Off
+            || self.breakpoints
+            || self.profile
+    }
+
+    /// Proposals a module may use: those listed in `data-features`, if any,
+    /// that are also enabled
+    /// Validation, the text extensions and the capability report all use this
+    pub fn effective_features(&self) -> WasmFeatures {
+        let enabled = self.enabled_features.unwrap_or_else(all_features);
+        self.features
+            .map_or(enabled, |features| features.intersection(enabled))
+    }
+}
+
+/// Boolean attributes are enabled unless explicitly set to `0` or `false`
+fn is_enabled(value: &str) -> bool {
+    !matches!(value.trim().to_ascii_lowercase().as_str(), "0" | "false")
+}
+
+pub(super) fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
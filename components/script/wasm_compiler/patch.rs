@@ -0,0 +1,216 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Patch updates of fetched modules
+//!
+//! The source of the module last fetched from a URL is kept as a base. The
+//! next fetch of that URL names it in the [`BASE_HEADER`] request header, as
+//! the hex SHA-256 of the source, and the server may answer with a patch
+//! against it instead of the whole module. The new source is rebuilt here,
+//! checked against the hash the patch gives for it, and compiled as usual.
+//! Servers answering with patches should send `Vary: Wasm-Patch-Base`, so
+//! HTTP caches keep them apart from the module.
+//!
+//! Patches are bsdiff-style:
+//! * the magic `\0wpt` and the version byte 1
+//! * the SHA-256 of the base, then that of the result
+//! * operations up to the end, with offsets and lengths as unsigned LEB128:
+//!   * `0x00 offset length`: copy bytes of the base
+//!   * `0x01 offset length bytes`: bytes of the base plus `bytes`, bytewise
+//!     modulo 256
+//!   * `0x02 length bytes`: insert `bytes`
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use parking_lot::RwLock;
+use sha2::{Digest, Sha256};
+
+use super::CompileError;
+
+/// Request header naming the base a patch may be made against
+pub const BASE_HEADER: &str = "wasm-patch-base";
+
+const MAGIC: &[u8] = b"\0wpt";
+const VERSION: u8 = 1;
+const COPY: u8 = 0x00;
+const ADD: u8 = 0x01;
+const INSERT: u8 = 0x02;
+
+/// Number of URLs whose source is kept
+const MAX_BASES: usize = 100;
+
+struct Base {
+    hash: [u8; 32],
+    source: Vec<u8>,
+}
+
+/// Maps URL -> source last fetched from it
+fn get_bases() -> &'static RwLock<HashMap<String, Base>> {
+    static BASES: OnceLock<RwLock<HashMap<String, Base>>> = OnceLock::new();
+    BASES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether a response body is a patch rather than a module
+pub fn is_patch(body: &[u8]) -> bool {
+    body.starts_with(MAGIC)
+}
+
+/// Value of [`BASE_HEADER`] for a fetch of `url`, if a source is kept for it
+pub fn base_hash(url: &str) -> Option<String> {
+    get_bases().read().get(url).map(|base| hex(&base.hash))
+}
+
+/// The source of the module fetched from `url`: `body`, or the result of
+/// applying it to the base of `url` if it is a patch
+/// The source is kept as the base of the next fetch
+pub fn resolve(url: &str, body: Vec<u8>) -> Result<Vec<u8>, CompileError> {
+    let source = if is_patch(&body) {
+        let bases = get_bases().read();
+        let base = bases.get(url).ok_or_else(|| {
+            CompileError::PatchError(format!("no module kept for {} to apply a patch to", url))
+        })?;
+        apply_patch(&base.source, base.hash, &body)?
+    } else {
+        body
+    };
+
+    let mut bases = get_bases().write();
+    if bases.get(url).is_some_and(|base| base.source == source) {
+        return Ok(source);
+    }
+    if bases.len() >= MAX_BASES && !bases.contains_key(url) {
+        bases.clear();
+    }
+    bases.insert(
+        url.to_string(),
+        Base {
+            hash: sha256(&source),
+            source: source.clone(),
+        },
+    );
+    Ok(source)
+}
+
+/// Apply `patch` to `base`, whose SHA-256 is `base_hash`
+pub fn apply_patch(
+    base: &[u8],
+    base_hash: [u8; 32],
+    patch: &[u8],
+) -> Result<Vec<u8>, CompileError> {
+    let mut reader = Reader {
+        data: patch,
+        pos: 0,
+    };
+    if reader.bytes(MAGIC.len())? != MAGIC || reader.byte()? != VERSION {
+        return Err(CompileError::PatchError(
+            "not a version 1 patch".to_string(),
+        ));
+    }
+    if reader.bytes(32)? != base_hash {
+        return Err(CompileError::PatchError(
+            "patch is against another version of the module".to_string(),
+        ));
+    }
+    let target_hash = reader.bytes(32)?;
+
+    let mut target = Vec::new();
+    while !reader.is_empty() {
+        match reader.byte()? {
+            COPY => {
+                let range = reader.base_range(base)?;
+                target.extend_from_slice(&base[range]);
+            },
+            ADD => {
+                let range = reader.base_range(base)?;
+                let delta = reader.bytes(range.len())?;
+                target.extend(
+                    base[range]
+                        .iter()
+                        .zip(delta)
+                        .map(|(byte, delta)| byte.wrapping_add(*delta)),
+                );
+            },
+            INSERT => {
+                let length = reader.leb128()?;
+                target.extend_from_slice(reader.bytes(length)?);
+            },
+            op => {
+                return Err(CompileError::PatchError(format!(
+                    "unknown patch operation {:#04x}",
+                    op
+                )));
+            },
+        }
+    }
+
+    if sha256(&target) != target_hash {
+        return Err(CompileError::PatchError(
+            "patched module does not match its hash".to_string(),
+        ));
+    }
+    Ok(target)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], CompileError> {
+        let end = self
+            .pos
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| CompileError::PatchError("truncated patch".to_string()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, CompileError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn leb128(&mut self) -> Result<usize, CompileError> {
+        let mut value = 0usize;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(CompileError::PatchError(
+            "invalid LEB128 in patch".to_string(),
+        ))
+    }
+
+    /// An offset and length into `base`
+    fn base_range(&mut self, base: &[u8]) -> Result<std::ops::Range<usize>, CompileError> {
+        let offset = self.leb128()?;
+        let length = self.leb128()?;
+        offset
+            .checked_add(length)
+            .filter(|end| *end <= base.len())
+            .map(|end| offset..end)
+            .ok_or_else(|| CompileError::PatchError("patch reads past its base".to_string()))
+    }
+}
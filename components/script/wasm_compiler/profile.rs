@@ -0,0 +1,121 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Self-profiling
+//!
+//! With `data-profile`, every function the module defines calls imports in
+//! [`IMPORT_MODULE`] on entry and exit. The loader times the calls with
+//! `performance.now()` and `window.__wasmProfile()` reports, per function
+//! name, the number of calls, the total time including callees and the self
+//! time spent in the function's own code, in milliseconds.
+//!
+//! Frames unwound by an exception are closed at the next exit of a function
+//! further up the stack.
+
+use std::collections::BTreeMap;
+
+use wasm_encoder::reencode;
+
+use super::LOG_TARGET;
+use super::embed;
+use super::instrument::{self, Calls, Functions};
+
+/// Import module of the timing calls, provided by the loader
+pub const IMPORT_MODULE: &str = "__wasm_profile";
+
+/// Add the timing calls to `binary` if `enabled`
+/// Returns the names of the functions by index
+pub fn instrument(
+    binary: &mut Vec<u8>,
+    enabled: bool,
+) -> Result<Option<BTreeMap<u32, String>>, reencode::Error> {
+    if !enabled {
+        return Ok(None);
+    }
+    let names = instrument::function_names(binary)?;
+    let instrumented = instrument::add_calls(
+        binary,
+        &Calls {
+            module: IMPORT_MODULE,
+            entry: "enter",
+            exit: Some("leave"),
+            functions: Functions::All,
+            arguments: false,
+        },
+    )?;
+
+    log::info!(
+        target: LOG_TARGET, phase = "profile", functions = instrumented.functions.len();
+        "Profiling {} functions", instrumented.functions.len()
+    );
+    Ok(Some(
+        instrumented
+            .functions
+            .into_iter()
+            .map(|index| (index, instrument::display_name(&names, index)))
+            .collect(),
+    ))
+}
+
+/// JavaScript timing the calls of the functions named by index in `names`,
+/// which `window.__wasmProfile()` reports
+pub fn loader_js(names: &BTreeMap<u32, String>) -> String {
+    format!(
+        r#"
+
+        // Self-profiling (data-profile), reported by window.__wasmProfile()
+        const wasmProfile = {{ names: {}, totals: {{}} }};
+        const profileStack = [];
+        importObject['{module}'] = {{
+            enter: function(index) {{
+                profileStack.push({{ index: index, start: performance.now(), callees: 0 }});
+            }},
+            leave: function(index) {{
+                const now = performance.now();
+                // Frames unwound by exceptions are closed here as well
+                while (profileStack.length > 0) {{
+                    const frame = profileStack.pop();
+                    const elapsed = now - frame.start;
+                    const totals = wasmProfile.totals[frame.index] ||
+                        (wasmProfile.totals[frame.index] = {{ calls: 0, total: 0, self: 0 }});
+                    totals.calls++;
+                    totals.total += elapsed;
+                    totals.self += elapsed - frame.callees;
+                    if (profileStack.length > 0) {{
+                        profileStack[profileStack.length - 1].callees += elapsed;
+                    }}
+                    if (frame.index === index) {{
+                        break;
+                    }}
+                }}
+            }}
+        }};
+        window.__wasmProfileModules = window.__wasmProfileModules || {{}};
+        window.__wasmProfileModules[wasmFilename] = wasmProfile;
+        window.__wasmProfile = window.__wasmProfile || function() {{
+            const report = {{}};
+            for (const filename in window.__wasmProfileModules) {{
+                const module = window.__wasmProfileModules[filename];
+                report[filename] = Object.keys(module.totals).map(function(index) {{
+                    const totals = module.totals[index];
+                    return {{
+                        index: Number(index),
+                        name: module.names[index],
+                        calls: totals.calls,
+                        total: totals.total,
+                        self: totals.self
+                    }};
+                }}).sort(function(a, b) {{
+                    return b.self - a.self;
+                }});
+            }}
+            return report;
+        }};"#,
+        embed::script_json(names),
+        module = IMPORT_MODULE,
+    )
+}
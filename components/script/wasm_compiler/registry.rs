@@ -0,0 +1,91 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Named modules, registered per origin
+//!
+//! A WAT script with `data-register="physics@1.2"` registers its compiled
+//! module under that name for the origin of its document. Later scripts of
+//! the origin with the same `data-register`, on the same page or a later one,
+//! load the registered module instead of fetching and compiling their source;
+//! inline ones may then be empty.
+//!
+//! Like the compilation cache, the registry lasts as long as the process.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use parking_lot::RwLock;
+
+use super::{CompileError, CompileOptions, LOG_TARGET, ModuleMetadata};
+
+/// Number of modules kept
+const MAX_MODULES: usize = 100;
+
+/// A `data-register` name, scoped to an origin
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RegisteredName {
+    /// ASCII serialization of a tuple origin
+    pub origin: String,
+    pub name: String,
+}
+
+struct Registered {
+    binary: Vec<u8>,
+    metadata: ModuleMetadata,
+}
+
+fn get_registry() -> &'static RwLock<HashMap<RegisteredName, Registered>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<RegisteredName, Registered>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Register a compiled module under `name`, replacing any module there
+pub(super) fn register(name: &RegisteredName, binary: &[u8], metadata: &ModuleMetadata) {
+    let mut registry = get_registry().write();
+    if registry.len() >= MAX_MODULES && !registry.contains_key(name) {
+        log::warn!(
+            target: LOG_TARGET, phase = "registry", name:% = name.name;
+            "Not registering {:?}, {} modules are registered already", name.name, MAX_MODULES
+        );
+        return;
+    }
+    registry.insert(
+        name.clone(),
+        Registered {
+            binary: binary.to_vec(),
+            metadata: metadata.clone(),
+        },
+    );
+}
+
+/// Whether a module is registered under `name`
+pub fn is_registered(name: &RegisteredName) -> bool {
+    get_registry().read().contains_key(name)
+}
+
+/// The loader of the module registered under `name`, like
+/// [`super::compile_wat_to_js`] would generate for its source, or `None` if
+/// no module is registered under that name
+pub fn compile_registered(
+    name: &RegisteredName,
+    filename: &str,
+    callback: Option<&str>,
+    options: &CompileOptions,
+) -> Option<Result<String, CompileError>> {
+    let (binary, metadata) = {
+        let registry = get_registry().read();
+        let registered = registry.get(name)?;
+        (registered.binary.clone(), registered.metadata.clone())
+    };
+    log::info!(
+        target: LOG_TARGET, phase = "registry", name:% = name.name, filename;
+        "Loading registered module {:?} for {}", name.name, filename
+    );
+    Some(super::generate_glue(
+        b"", binary, &metadata, filename, callback, options,
+    ))
+}
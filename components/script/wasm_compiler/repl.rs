@@ -0,0 +1,82 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Incremental evaluation of WAT fragments
+//!
+//! `navigator.watCompiler.eval(fragment)` compiles each fragment as a module
+//! of its own; module fields without a surrounding `(module ...)` are wrapped
+//! in one. Its imports resolve like those of any WAT script, so
+//! `(import "env" "f" ...)` finds the export `f` of a module already on the
+//! page, and its exports are installed next to (and over) theirs.
+//!
+//! `navigator.watCompiler.compileFile(file)` does the same for a complete
+//! module from a `File` or `Blob`, in the text or the binary format.
+//!
+//! The loader is wrapped in a promise that settles with the `wasmloaded` or
+//! `wasmerror` event of the module's filename.
+
+use std::borrow::Cow;
+
+use super::{CompileError, CompileOptions, embed};
+
+/// The fragment as a module, wrapping bare module fields
+pub fn fragment_module(fragment: &str) -> Cow<'_, str> {
+    let trimmed = fragment.trim_start();
+    if trimmed.starts_with("(module") || trimmed.as_bytes().starts_with(b"\0asm") {
+        Cow::Borrowed(fragment)
+    } else {
+        Cow::Owned(format!("(module\n{}\n)", fragment))
+    }
+}
+
+/// JavaScript evaluating to a promise for the exports of the fragment
+/// `filename` must differ between fragments, it identifies their events
+pub fn compile_fragment(
+    fragment: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let loader = super::compile_wat_to_js(&fragment_module(fragment), filename, None, options)?;
+    Ok(settle_on_load(filename, &loader))
+}
+
+/// JavaScript evaluating to a promise for the exports of a module file,
+/// WAT text or a binary module; `filename` names it in diagnostics
+pub fn compile_file(
+    bytes: &[u8],
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let loader = super::compile_bytes_to_js(bytes, filename, None, options)?;
+    Ok(settle_on_load(filename, &loader))
+}
+
+/// Wrap `loader` in a promise settled by its events
+fn settle_on_load(filename: &str, loader: &str) -> String {
+    format!(
+        r#"new Promise(function(resolve, reject) {{
+    const filename = {};
+    const settle = function(event) {{
+        if (!event.detail || event.detail.filename !== filename) {{
+            return;
+        }}
+        window.removeEventListener('wasmloaded', settle);
+        window.removeEventListener('wasmerror', settle);
+        if (event.type === 'wasmloaded') {{
+            resolve(event.detail.exports);
+        }} else {{
+            reject(new Error(event.detail.message));
+        }}
+    }};
+    window.addEventListener('wasmloaded', settle);
+    window.addEventListener('wasmerror', settle);
+{}
+}})"#,
+        embed::script_json(&filename),
+        loader
+    )
+}
@@ -0,0 +1,192 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Declared types of exports
+//!
+//! The loader wraps the GC references exports return with the names of a
+//! struct type. Without knowing the type of a reference it has to guess one
+//! for the whole module; the declared result type of each exported function,
+//! and the type of each exported global, tell it which one applies:
+//!
+//! ```text
+//! {"spawn": {"typeName": "player", "fields": ["name", "score"]}, "team": {"array": 3}, "title": {"string": 0}}
+//! ```
+//!
+//! Structs are given by their names, see [`super::names`], and arrays of
+//! structs and strings by type index, see [`super::arrays`] and
+//! [`super::strings`]. Exports of other types and the accessors the loader
+//! adds are left out.
+//!
+//! Parameters are recorded for strings only, which the loader encodes, by
+//! position and null for other parameters:
+//!
+//! ```text
+//! {"greet": [0, null]}
+//! ```
+
+use serde_json::{Map, Value, json};
+use wasmparser::{CompositeInnerType, ExternalKind, HeapType, Parser, Payload, TypeRef, ValType};
+
+use super::arrays::{self, StructArray};
+use super::strings::{self, StringType};
+use super::{names, structs};
+
+/// What a type index refers to, as far as the loader is concerned
+enum Type {
+    /// A function type, with its parameters and results
+    Func(Vec<ValType>, Vec<ValType>),
+    Other,
+}
+
+/// The exports of a module and the types they are declared with
+#[derive(Default)]
+struct Signatures {
+    types: Vec<Type>,
+    /// Type index of each function, and value type of each global
+    functions: Vec<u32>,
+    globals: Vec<ValType>,
+    /// Name, kind and index of each export, but the accessors the loader adds
+    exports: Vec<(String, ExternalKind, u32)>,
+}
+
+impl Signatures {
+    fn read(binary: &[u8]) -> Option<Signatures> {
+        let mut signatures = Signatures::default();
+        for payload in Parser::new(0).parse_all(binary) {
+            match payload.ok()? {
+                Payload::TypeSection(reader) => {
+                    for group in reader.into_iter().flatten() {
+                        for ty in group.types() {
+                            signatures.types.push(match &ty.composite_type.inner {
+                                CompositeInnerType::Func(func) => {
+                                    Type::Func(func.params().to_vec(), func.results().to_vec())
+                                },
+                                _ => Type::Other,
+                            });
+                        }
+                    }
+                },
+                Payload::ImportSection(reader) => {
+                    for import in reader.into_iter().flatten() {
+                        match import.ty {
+                            TypeRef::Func(ty) => signatures.functions.push(ty),
+                            TypeRef::Global(ty) => signatures.globals.push(ty.content_type),
+                            _ => {},
+                        }
+                    }
+                },
+                Payload::FunctionSection(reader) => {
+                    signatures.functions.extend(reader.into_iter().flatten())
+                },
+                Payload::GlobalSection(reader) => signatures.globals.extend(
+                    reader
+                        .into_iter()
+                        .flatten()
+                        .map(|global| global.ty.content_type),
+                ),
+                Payload::ExportSection(reader) => {
+                    signatures.exports.extend(
+                        reader
+                            .into_iter()
+                            .flatten()
+                            .filter(|export| {
+                                !export.name.starts_with(arrays::EXPORT_PREFIX)
+                                    && !export.name.starts_with(structs::EXPORT_PREFIX)
+                                    && !export.name.starts_with(strings::EXPORT_PREFIX)
+                            })
+                            .map(|export| (export.name.to_string(), export.kind, export.index)),
+                    );
+                },
+                _ => {},
+            }
+        }
+        Some(signatures)
+    }
+
+    /// Parameters and results of the function with index `index`
+    fn function(&self, index: u32) -> Option<(&[ValType], &[ValType])> {
+        let ty = self.functions.get(index as usize)?;
+        match self.types.get(*ty as usize)? {
+            Type::Func(params, results) => Some((params, results)),
+            Type::Other => None,
+        }
+    }
+}
+
+/// Index of the type a value type refers to, if it is a concrete reference
+fn concrete_type(ty: ValType) -> Option<u32> {
+    let ValType::Ref(ty) = ty else {
+        return None;
+    };
+    match ty.heap_type() {
+        HeapType::Concrete(index) => index.as_module_index(),
+        HeapType::Abstract { .. } => None,
+    }
+}
+
+/// Result types of the exports of `binary`, as JSON; see the module
+/// documentation
+pub fn loader_json(
+    binary: &[u8],
+    struct_arrays: &[StructArray],
+    string_types: &[StringType],
+) -> String {
+    let Some(signatures) = Signatures::read(binary) else {
+        return "{}".to_string();
+    };
+    let structs = names::struct_info(binary);
+    let mut results = Map::new();
+    for (name, kind, index) in &signatures.exports {
+        let result = match kind {
+            ExternalKind::Func => match signatures.function(*index) {
+                Some((_, [result])) => Some(*result),
+                _ => None,
+            },
+            ExternalKind::Global => signatures.globals.get(*index as usize).copied(),
+            _ => None,
+        };
+        let Some(ty) = result.and_then(concrete_type) else {
+            continue;
+        };
+        let info = match structs.get(&ty) {
+            Some(names) => json!(names),
+            None if struct_arrays.iter().any(|array| array.array == ty) => json!({ "array": ty }),
+            None if string_types.iter().any(|string| string.array == ty) => json!({ "string": ty }),
+            _ => continue,
+        };
+        results.insert(name.clone(), info);
+    }
+    Value::Object(results).to_string()
+}
+
+/// String parameters of the exported functions of `binary` that take any, as
+/// JSON; see the module documentation
+pub fn string_params_json(binary: &[u8], string_types: &[StringType]) -> String {
+    let Some(signatures) = Signatures::read(binary) else {
+        return "{}".to_string();
+    };
+    let mut params = Map::new();
+    for (name, kind, index) in &signatures.exports {
+        let Some((types, _)) = signatures
+            .function(*index)
+            .filter(|_| *kind == ExternalKind::Func)
+        else {
+            continue;
+        };
+        let strings: Vec<Option<u32>> = types
+            .iter()
+            .map(|ty| {
+                concrete_type(*ty)
+                    .filter(|ty| string_types.iter().any(|string| string.array == *ty))
+            })
+            .collect();
+        if strings.iter().any(Option::is_some) {
+            params.insert(name.clone(), json!(strings));
+        }
+    }
+    Value::Object(params).to_string()
+}
@@ -0,0 +1,499 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tree shaking
+//!
+//! Generated WAT often carries large sets of helpers of which a page calls a
+//! few. With `data-keep="init,tick"` the function exports not listed are
+//! dropped, and so are the functions none of the rest reaches: the kept
+//! exports, the start function and the functions of element segments, global
+//! and table initializers are the roots, and calls and `ref.func` reach
+//! further. Passive data segments and type rec groups only removed functions
+//! used go with them; active segments, which write memory on instantiation,
+//! stay.
+//!
+//! Functions, data segments and types move down to fill the gaps, and every
+//! reference to them, the name section included, is renumbered. The module is
+//! shaken as it is compiled, so the cache and the module metadata describe
+//! what the page loads.
+
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{CodeSection, DataSection, ExportSection, FunctionSection, Module, TypeSection};
+use wasmparser::{
+    BinaryReaderError, ConstExpr, DataKind, DataSectionReader, ElementItems, Export, ExternalKind,
+    FunctionBody, FunctionSectionReader, Name, Operator, Parser, Payload, TableInit, TypeRef,
+    TypeSectionReader,
+};
+
+/// What [`shake`] removed
+#[derive(Debug, Default, PartialEq)]
+pub struct Shaken {
+    pub functions: u32,
+    pub data: u32,
+    pub types: u32,
+    /// Names to keep that the module does not export
+    pub missing: Vec<String>,
+}
+
+/// Drop the function exports of `binary` not named in `keep`, and what only
+/// they reach
+pub fn shake(binary: &mut Vec<u8>, keep: &[String]) -> Result<Shaken, reencode::Error> {
+    let scan = Scan::new(binary)?;
+    let shaken = Shaken {
+        missing: keep
+            .iter()
+            .filter(|name| !scan.exports.iter().any(|(export, _)| export == *name))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut reachable = vec![false; scan.imported_functions as usize + scan.bodies.len()];
+    let mut stack: Vec<u32> = scan
+        .exports
+        .iter()
+        .filter(|(name, _)| keep.contains(name))
+        .filter_map(|(_, function)| *function)
+        .chain(scan.roots.iter().copied())
+        .chain(0..scan.imported_functions)
+        .collect();
+    while let Some(function) = stack.pop() {
+        let Some(seen) = reachable.get_mut(function as usize) else {
+            continue;
+        };
+        if *seen {
+            continue;
+        }
+        *seen = true;
+        if let Some(body) = function
+            .checked_sub(scan.imported_functions)
+            .and_then(|index| scan.bodies.get(index as usize))
+        {
+            stack.extend(&body.functions);
+        }
+    }
+    remove(binary, &scan, Some(keep), &reachable, shaken)
+}
+
+/// Remove the defined `functions` of `binary`, which nothing else may
+/// reference, and what only they used
+pub fn remove_functions(
+    binary: &mut Vec<u8>,
+    functions: &BTreeSet<u32>,
+) -> Result<Shaken, reencode::Error> {
+    let scan = Scan::new(binary)?;
+    let kept: Vec<bool> = (0..scan.imported_functions + scan.bodies.len() as u32)
+        .map(|function| function < scan.imported_functions || !functions.contains(&function))
+        .collect();
+    remove(binary, &scan, None, &kept, Shaken::default())
+}
+
+/// Remove the functions not `kept`, the function exports not in `keep` if it
+/// is given, and the passive data segments and types left unused
+fn remove(
+    binary: &mut Vec<u8>,
+    scan: &Scan,
+    keep: Option<&[String]>,
+    kept: &[bool],
+    mut shaken: Shaken,
+) -> Result<Shaken, reencode::Error> {
+    let dropped_exports = keep.is_some_and(|keep| {
+        scan.exports
+            .iter()
+            .any(|(name, function)| function.is_some() && !keep.contains(name))
+    });
+    if kept.iter().all(|kept| *kept) && !dropped_exports {
+        return Ok(shaken);
+    }
+
+    let mut used_data = vec![false; scan.passive.len()];
+    for (index, body) in scan.bodies.iter().enumerate() {
+        if kept[scan.imported_functions as usize + index] {
+            for data in &body.data {
+                if let Some(used) = used_data.get_mut(*data as usize) {
+                    *used = true;
+                }
+            }
+        }
+    }
+    let kept_data = scan
+        .passive
+        .iter()
+        .zip(&used_data)
+        .map(|(passive, used)| !passive || *used);
+
+    let mut shaker = Shaker {
+        keep,
+        imported_functions: scan.imported_functions,
+        functions: renumber(kept.iter().copied()),
+        data: renumber(kept_data),
+        types: None,
+        next_type: 0,
+        next_body: 0,
+        group: None,
+        referenced: BTreeSet::new(),
+        edges: Vec::new(),
+    };
+    shaken.functions = count_removed(&shaker.functions);
+    shaken.data = count_removed(&shaker.data);
+
+    // The first pass finds the types still in use, the second one drops the
+    // others if there are any
+    let mut module = Module::new();
+    shaker.parse_core_module(&mut module, Parser::new(0), binary)?;
+    let mut kept_groups = vec![false; scan.groups.iter().max().map_or(0, |group| group + 1)];
+    let mut stack: Vec<u32> = shaker.referenced.iter().copied().collect();
+    while let Some(ty) = stack.pop() {
+        let Some(&group) = scan.groups.get(ty as usize) else {
+            continue;
+        };
+        if !kept_groups[group] {
+            kept_groups[group] = true;
+            stack.extend(
+                shaker
+                    .edges
+                    .iter()
+                    .filter(|(from, _)| *from == group)
+                    .map(|(_, ty)| *ty),
+            );
+        }
+    }
+    let types = renumber(scan.groups.iter().map(|group| kept_groups[*group]));
+    shaken.types = count_removed(&types);
+    if shaken.types > 0 {
+        shaker.types = Some(types);
+        shaker.next_type = 0;
+        shaker.next_body = 0;
+        module = Module::new();
+        shaker.parse_core_module(&mut module, Parser::new(0), binary)?;
+    }
+    *binary = module.finish();
+    Ok(shaken)
+}
+
+/// What a function body references
+#[derive(Default)]
+struct Body {
+    functions: Vec<u32>,
+    data: Vec<u32>,
+}
+
+/// The references between the parts of a module
+#[derive(Default)]
+struct Scan {
+    imported_functions: u32,
+    /// Name of each export, and the function it exports
+    exports: Vec<(String, Option<u32>)>,
+    /// Functions referenced outside function bodies
+    roots: Vec<u32>,
+    bodies: Vec<Body>,
+    /// Whether each data segment is passive
+    passive: Vec<bool>,
+    /// Rec group of each type
+    groups: Vec<usize>,
+}
+
+impl Scan {
+    fn new(binary: &[u8]) -> Result<Scan, BinaryReaderError> {
+        let mut scan = Scan::default();
+        for payload in Parser::new(0).parse_all(binary) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for (group, rec_group) in reader.into_iter().enumerate() {
+                        let types = rec_group?.types().len();
+                        scan.groups.extend(std::iter::repeat_n(group, types));
+                    }
+                },
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        if matches!(import?.ty, TypeRef::Func(_)) {
+                            scan.imported_functions += 1;
+                        }
+                    }
+                },
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        let function = (export.kind == ExternalKind::Func).then_some(export.index);
+                        scan.exports.push((export.name.to_string(), function));
+                    }
+                },
+                Payload::StartSection { func, .. } => scan.roots.push(func),
+                Payload::TableSection(reader) => {
+                    for table in reader {
+                        if let TableInit::Expr(expr) = table?.init {
+                            ref_funcs(&expr, &mut scan.roots)?;
+                        }
+                    }
+                },
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        ref_funcs(&global?.init_expr, &mut scan.roots)?;
+                    }
+                },
+                Payload::ElementSection(reader) => {
+                    for element in reader {
+                        match element?.items {
+                            ElementItems::Functions(reader) => {
+                                for function in reader {
+                                    scan.roots.push(function?);
+                                }
+                            },
+                            ElementItems::Expressions(_, reader) => {
+                                for expr in reader {
+                                    ref_funcs(&expr?, &mut scan.roots)?;
+                                }
+                            },
+                        }
+                    }
+                },
+                Payload::CodeSectionEntry(body) => {
+                    let mut references = Body::default();
+                    let mut reader = body.get_operators_reader()?;
+                    while !reader.eof() {
+                        match reader.read()? {
+                            Operator::Call { function_index }
+                            | Operator::ReturnCall { function_index }
+                            | Operator::RefFunc { function_index } => {
+                                references.functions.push(function_index)
+                            },
+                            Operator::MemoryInit { data_index, .. }
+                            | Operator::DataDrop { data_index }
+                            | Operator::ArrayNewData {
+                                array_data_index: data_index,
+                                ..
+                            }
+                            | Operator::ArrayInitData {
+                                array_data_index: data_index,
+                                ..
+                            } => references.data.push(data_index),
+                            _ => {},
+                        }
+                    }
+                    scan.bodies.push(references);
+                },
+                Payload::DataSection(reader) => {
+                    for data in reader {
+                        scan.passive.push(matches!(data?.kind, DataKind::Passive));
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(scan)
+    }
+}
+
+/// Add the functions `ref.func` refers to in `expr` to `functions`
+fn ref_funcs(expr: &ConstExpr, functions: &mut Vec<u32>) -> Result<(), BinaryReaderError> {
+    let mut reader = expr.get_operators_reader();
+    while !reader.eof() {
+        if let Operator::RefFunc { function_index } = reader.read()? {
+            functions.push(function_index);
+        }
+    }
+    Ok(())
+}
+
+/// The new index of each item, `None` for those not kept
+fn renumber(kept: impl IntoIterator<Item = bool>) -> Vec<Option<u32>> {
+    let mut next = 0;
+    kept.into_iter()
+        .map(|kept| {
+            kept.then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect()
+}
+
+fn count_removed(indices: &[Option<u32>]) -> u32 {
+    indices.iter().filter(|index| index.is_none()).count() as u32
+}
+
+/// The new index of `index`, which is kept unless it is out of range
+fn renumbered(indices: &[Option<u32>], index: u32) -> Option<u32> {
+    indices.get(index as usize).copied().unwrap_or(Some(index))
+}
+
+struct Shaker<'a> {
+    /// Function exports to keep; `None` keeps them all
+    keep: Option<&'a [String]>,
+    imported_functions: u32,
+    functions: Vec<Option<u32>>,
+    data: Vec<Option<u32>>,
+    /// `None` in the pass finding the types in use
+    types: Option<Vec<Option<u32>>>,
+    next_type: u32,
+    next_body: u32,
+    /// Rec group being encoded
+    group: Option<usize>,
+    /// Types referenced outside the type section
+    referenced: BTreeSet<u32>,
+    /// Types referenced by each rec group
+    edges: Vec<(usize, u32)>,
+}
+
+impl Shaker<'_> {
+    fn type_kept(&self, ty: u32) -> Option<u32> {
+        match &self.types {
+            Some(types) => renumbered(types, ty),
+            None => Some(ty),
+        }
+    }
+}
+
+/// A name map with the names of the items kept, renumbered
+fn name_map(
+    map: wasmparser::NameMap<'_>,
+    index: impl Fn(u32) -> Option<u32>,
+) -> Result<wasm_encoder::NameMap, reencode::Error> {
+    let mut names = wasm_encoder::NameMap::new();
+    for naming in map {
+        let naming = naming?;
+        if let Some(index) = index(naming.index) {
+            names.append(index, naming.name);
+        }
+    }
+    Ok(names)
+}
+
+/// [`name_map`] of the outer indices of an indirect name map
+fn indirect_name_map(
+    map: wasmparser::IndirectNameMap<'_>,
+    index: impl Fn(u32) -> Option<u32>,
+) -> Result<wasm_encoder::IndirectNameMap, reencode::Error> {
+    let mut names = wasm_encoder::IndirectNameMap::new();
+    for naming in map {
+        let naming = naming?;
+        if let Some(index) = index(naming.index) {
+            names.append(index, &name_map(naming.names, Some)?);
+        }
+    }
+    Ok(names)
+}
+
+impl Reencode for Shaker<'_> {
+    type Error = Infallible;
+
+    fn function_index(&mut self, func: u32) -> u32 {
+        renumbered(&self.functions, func).unwrap_or(func)
+    }
+
+    fn data_index(&mut self, data: u32) -> u32 {
+        renumbered(&self.data, data).unwrap_or(data)
+    }
+
+    fn data_count(&mut self, count: u32) -> u32 {
+        count - count_removed(&self.data)
+    }
+
+    fn type_index(&mut self, ty: u32) -> u32 {
+        match self.group {
+            Some(group) => self.edges.push((group, ty)),
+            None => {
+                self.referenced.insert(ty);
+            },
+        }
+        self.type_kept(ty).unwrap_or(ty)
+    }
+
+    fn parse_type_section(
+        &mut self,
+        types: &mut TypeSection,
+        section: TypeSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for (group, rec_group) in section.into_iter().enumerate() {
+            let rec_group = rec_group?;
+            let first = self.next_type;
+            self.next_type += rec_group.types().len() as u32;
+            if first < self.next_type && self.type_kept(first).is_none() {
+                continue;
+            }
+            self.group = Some(group);
+            reencode::utils::parse_recursive_type_group(self, types.ty(), rec_group)?;
+        }
+        self.group = None;
+        Ok(())
+    }
+
+    fn parse_function_section(
+        &mut self,
+        functions: &mut FunctionSection,
+        section: FunctionSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for (index, ty) in (self.imported_functions..).zip(section) {
+            let ty = ty?;
+            if renumbered(&self.functions, index).is_some() {
+                functions.function(self.type_index(ty));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut CodeSection,
+        body: FunctionBody<'_>,
+    ) -> Result<(), reencode::Error> {
+        let index = self.imported_functions + self.next_body;
+        self.next_body += 1;
+        if renumbered(&self.functions, index).is_none() {
+            return Ok(());
+        }
+        reencode::utils::parse_function_body(self, code, body)
+    }
+
+    fn parse_export(&mut self, exports: &mut ExportSection, export: Export<'_>) {
+        if export.kind == ExternalKind::Func
+            && self
+                .keep
+                .is_some_and(|keep| !keep.iter().any(|name| name == export.name))
+        {
+            return;
+        }
+        reencode::utils::parse_export(self, exports, export)
+    }
+
+    fn parse_data_section(
+        &mut self,
+        data: &mut DataSection,
+        section: DataSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        for (index, datum) in (0..).zip(section) {
+            let datum = datum?;
+            if renumbered(&self.data, index).is_some() {
+                self.parse_data(data, datum)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_custom_name_subsection(
+        &mut self,
+        names: &mut wasm_encoder::NameSection,
+        section: Name<'_>,
+    ) -> Result<(), reencode::Error> {
+        let function = |index| renumbered(&self.functions, index);
+        let data = |index| renumbered(&self.data, index);
+        let ty = |index| self.type_kept(index);
+        match section {
+            Name::Function(map) => names.functions(&name_map(map, function)?),
+            Name::Local(map) => names.locals(&indirect_name_map(map, function)?),
+            Name::Label(map) => names.labels(&indirect_name_map(map, function)?),
+            Name::Data(map) => names.data(&name_map(map, data)?),
+            Name::Type(map) => names.types(&name_map(map, ty)?),
+            Name::Field(map) => names.fields(&indirect_name_map(map, ty)?),
+            section => return reencode::utils::parse_custom_name_subsection(self, names, section),
+        }
+        Ok(())
+    }
+}
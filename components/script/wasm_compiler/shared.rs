@@ -0,0 +1,433 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memories and tables shared by the modules of a page
+//!
+//! Cooperating modules exchange data through a memory they all import from
+//! the [`MODULE`] module, rather than copying it through JavaScript:
+//!
+//! ```text
+//! (import "shared" "heap" (memory 16 256 shared))
+//! ```
+//!
+//! The imports of [`MODULE`] are looked up in `window.wasmShared` only. The
+//! page may put memories there itself before its WAT scripts load, or declare
+//! them with `<meta name="wasm-memory" content="heap; initial=16;
+//! maximum=256; shared">`, see [`SharedMemory::parse`]; a memory neither
+//! provides is created as the first module importing it declares it, once
+//! that module passed the checks of the loader. Under the memory budget of
+//! the page the memories the loader creates count against it, held by none
+//! of the modules, and a module is not loaded if those it would create do
+//! not fit. Modules importing a memory with limits it does not meet fail to
+//! link.
+//!
+//! Tables are shared the same way, declared with `<meta name="wasm-table"
+//! content="functions; initial=0; maximum=1024">`, see
+//! [`SharedTable::parse`], so that modules can call the functions of others
+//! by index. A module places its functions in slots the loader reserves for
+//! it: importing the global `<table>.base`, it is given the first of them,
+//! and the active element segments of the table at that offset, plus a
+//! constant, tell how many it needs.
+//!
+//! ```text
+//! (import "shared" "functions" (table 0 funcref))
+//! (import "shared" "functions.base" (global $base i32))
+//! (elem (table 0) (offset (global.get $base)) func $draw $update)
+//! ```
+//!
+//! The slots are reserved once per filename, when the module passed the
+//! checks of the loader: loaded again, it is given the same slots as long as
+//! they are enough. The loader records them as
+//! `window.__wasmTableBases[filename]`, so that the page can tell other
+//! modules where the functions are.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasmparser::{ElementItems, ElementKind, Operator, Parser, Payload, RefType, TypeRef};
+
+use super::LOG_TARGET;
+use super::embed;
+
+/// Import module of the shared memories
+pub const MODULE: &str = "shared";
+
+/// A memory of the page, as given to `new WebAssembly.Memory()`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SharedMemory {
+    #[serde(skip)]
+    pub name: String,
+    /// Size in pages
+    pub initial: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<u64>,
+    pub shared: bool,
+}
+
+impl SharedMemory {
+    /// Parse the content of a `<meta name="wasm-memory">`: the name, then
+    /// `initial=N`, `maximum=N` in pages and `shared`, separated by `;`
+    /// A shared memory needs a maximum.
+    pub fn parse(content: &str) -> Option<SharedMemory> {
+        let (name, settings) = declaration(content)?;
+        let mut memory = SharedMemory {
+            name,
+            initial: 1,
+            maximum: None,
+            shared: false,
+        };
+        for (key, value) in settings {
+            match (key, value) {
+                ("initial", Some(pages)) => memory.initial = pages.parse().ok()?,
+                ("maximum", Some(pages)) => memory.maximum = Some(pages.parse().ok()?),
+                ("shared", None) => memory.shared = true,
+                _ => return None,
+            }
+        }
+        if (memory.shared && memory.maximum.is_none())
+            || memory
+                .maximum
+                .is_some_and(|maximum| maximum < memory.initial)
+        {
+            return None;
+        }
+        Some(memory)
+    }
+}
+
+/// A table of the page, as given to `new WebAssembly.Table()`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SharedTable {
+    #[serde(skip)]
+    pub name: String,
+    /// `anyfunc` or `externref`, as the JavaScript API names them
+    pub element: &'static str,
+    pub initial: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<u64>,
+    /// Slots reserved for a module importing `<name>.base`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slots: Option<u64>,
+}
+
+impl SharedTable {
+    /// Parse the content of a `<meta name="wasm-table">`: the name, then
+    /// `initial=N`, `maximum=N` and `element=funcref` or `externref`,
+    /// separated by `;`
+    pub fn parse(content: &str) -> Option<SharedTable> {
+        let (name, settings) = declaration(content)?;
+        let mut table = SharedTable {
+            name,
+            element: "anyfunc",
+            initial: 0,
+            maximum: None,
+            slots: None,
+        };
+        for (key, value) in settings {
+            match (key, value) {
+                ("initial", Some(slots)) => table.initial = slots.parse().ok()?,
+                ("maximum", Some(slots)) => table.maximum = Some(slots.parse().ok()?),
+                ("element", Some(element)) => table.element = element_name(element)?,
+                _ => return None,
+            }
+        }
+        if table.maximum.is_some_and(|maximum| maximum < table.initial) {
+            return None;
+        }
+        Some(table)
+    }
+}
+
+/// Settings of a declaration, `key=value` or a bare `key`
+type Settings<'a> = Vec<(&'a str, Option<&'a str>)>;
+
+/// Name and settings of a declaration separated by `;`
+fn declaration(content: &str) -> Option<(String, Settings<'_>)> {
+    let mut parts = content.split(';').map(str::trim);
+    let name = parts
+        .next()
+        .filter(|name| !name.is_empty() && !name.contains('='))?;
+    let settings = parts
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (part, None),
+        })
+        .collect();
+    Some((name.to_string(), settings))
+}
+
+/// Element type of a table as `new WebAssembly.Table()` takes it
+fn element_name(element: &str) -> Option<&'static str> {
+    match element {
+        "funcref" | "anyfunc" => Some("anyfunc"),
+        "externref" => Some("externref"),
+        _ => None,
+    }
+}
+
+/// Memories and tables the page declares, as JSON objects of descriptors
+/// by name
+pub fn declared_json(memories: &[SharedMemory], tables: &[SharedTable]) -> (String, String) {
+    let memories: BTreeMap<&str, &SharedMemory> = memories
+        .iter()
+        .map(|memory| (memory.name.as_str(), memory))
+        .collect();
+    let tables: BTreeMap<&str, &SharedTable> = tables
+        .iter()
+        .map(|table| (table.name.as_str(), table))
+        .collect();
+    (
+        serde_json::to_string(&memories).unwrap_or_else(|_| "{}".to_string()),
+        serde_json::to_string(&tables).unwrap_or_else(|_| "{}".to_string()),
+    )
+}
+
+/// What a module imports from [`MODULE`]
+#[derive(Debug, Default, PartialEq)]
+pub struct Imported {
+    pub memories: BTreeMap<String, SharedMemory>,
+    /// Tables as the module declares them, with the slots it needs
+    pub tables: BTreeMap<String, SharedTable>,
+}
+
+impl Imported {
+    /// Memories and tables as JSON objects of descriptors by name
+    pub fn to_json(&self) -> (String, String) {
+        (
+            serde_json::to_string(&self.memories).unwrap_or_default(),
+            serde_json::to_string(&self.tables).unwrap_or_default(),
+        )
+    }
+}
+
+/// The memories and tables `binary` imports from [`MODULE`], or `None` if it
+/// imports none
+pub fn imported(binary: &[u8]) -> Option<Imported> {
+    let mut imported = Imported::default();
+    // Shared tables by table index, and the tables of `.base` globals by
+    // global index
+    let mut tables = BTreeMap::new();
+    let mut bases = BTreeMap::new();
+    let mut globals = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload {
+            Ok(Payload::ImportSection(reader)) => {
+                let mut table_index = 0;
+                let mut global_index = 0;
+                for import in reader.into_iter().flatten() {
+                    let shared = import.module == MODULE;
+                    match import.ty {
+                        TypeRef::Memory(memory) if shared => {
+                            imported.memories.insert(
+                                import.name.to_string(),
+                                SharedMemory {
+                                    name: import.name.to_string(),
+                                    initial: memory.initial,
+                                    maximum: memory.maximum,
+                                    shared: memory.shared,
+                                },
+                            );
+                        },
+                        TypeRef::Table(table) => {
+                            let element = match table.element_type {
+                                RefType::FUNCREF => Some("anyfunc"),
+                                RefType::EXTERNREF => Some("externref"),
+                                _ => None,
+                            };
+                            match element {
+                                Some(element) if shared => {
+                                    tables.insert(table_index, import.name.to_string());
+                                    imported.tables.insert(
+                                        import.name.to_string(),
+                                        SharedTable {
+                                            name: import.name.to_string(),
+                                            element,
+                                            initial: table.initial,
+                                            maximum: table.maximum,
+                                            slots: None,
+                                        },
+                                    );
+                                },
+                                None if shared => unresolved(import.name),
+                                _ => {},
+                            }
+                            table_index += 1;
+                        },
+                        TypeRef::Global(_) => {
+                            if shared {
+                                globals.push((global_index, import.name));
+                            }
+                            global_index += 1;
+                        },
+                        _ if shared => unresolved(import.name),
+                        _ => {},
+                    }
+                }
+                for (index, name) in globals.drain(..) {
+                    match name.strip_suffix(".base") {
+                        Some(table) if imported.tables.contains_key(table) => {
+                            let table = imported.tables.get_mut(table).expect("checked above");
+                            table.slots = Some(0);
+                            bases.insert(index, table.name.clone());
+                        },
+                        _ => unresolved(name),
+                    }
+                }
+            },
+            Ok(Payload::ElementSection(reader)) => {
+                for element in reader.into_iter().flatten() {
+                    let ElementKind::Active {
+                        table_index,
+                        offset_expr,
+                    } = element.kind
+                    else {
+                        continue;
+                    };
+                    let Some(name) = tables.get(&table_index.unwrap_or(0)) else {
+                        continue;
+                    };
+                    let Some((global, offset)) = base_offset(&offset_expr) else {
+                        continue;
+                    };
+                    if bases.get(&global) != Some(name) {
+                        continue;
+                    }
+                    let count = match element.items {
+                        ElementItems::Functions(reader) => reader.count(),
+                        ElementItems::Expressions(_, reader) => reader.count(),
+                    };
+                    let table = imported.tables.get_mut(name).expect("tables are imported");
+                    let end = offset + count as u64;
+                    table.slots = Some(table.slots.unwrap_or(0).max(end));
+                }
+            },
+            _ => {},
+        }
+    }
+    (!imported.memories.is_empty() || !imported.tables.is_empty()).then_some(imported)
+}
+
+/// Global and constant of an offset `(global.get $base)` or
+/// `(i32.add (global.get $base) (i32.const N))`
+fn base_offset(expr: &wasmparser::ConstExpr) -> Option<(u32, u64)> {
+    let mut reader = expr.get_operators_reader();
+    let Operator::GlobalGet { global_index } = reader.read().ok()? else {
+        return None;
+    };
+    match reader.read().ok()? {
+        Operator::End => Some((global_index, 0)),
+        Operator::I32Const { value } if value >= 0 => match reader.read().ok()? {
+            Operator::I32Add => Some((global_index, value as u64)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn unresolved(name: &str) {
+    log::warn!(
+        target: LOG_TARGET, phase = "shared", name;
+        "Only memories, tables and their .base globals are shared between modules, {:?} will not resolve",
+        name
+    );
+}
+
+/// JavaScript creating the memories and tables the module imports as
+/// `imported`, as `memories` and `tables` the page declares them or else as
+/// the module does, and counting those it creates against the budget of the
+/// page if `budgeted`: the imports, left to once the module passed its
+/// checks, and the quoted name of their module
+pub fn loader_js(
+    imported: &Imported,
+    memories: &[SharedMemory],
+    tables: &[SharedTable],
+    budgeted: bool,
+) -> (String, String) {
+    let (declared_memories, declared_tables) = declared_json(memories, tables);
+    let (imported_memories, imported_tables) = imported.to_json();
+    // The memories the loader creates count against the budget of the
+    // page, held by none of the modules sharing them
+    let shared_budget = if budgeted {
+        r#"
+        const sharedBytes = createdMemories.reduce(function(total, name) {
+            return total + (declaredMemories[name] || importedMemories[name]).initial * 65536;
+        }, 0);
+        if (memoryQuotaError(memoryHeld() + sharedBytes)) {
+            console.error('WASM: ' + memoryQuotaError(memoryHeld() + sharedBytes));
+            dispatchWasmError(memoryQuotaError(memoryHeld() + sharedBytes));
+            delete window.__wasmMemoryUse[wasmFilename];
+            return;
+        }
+        window.__wasmSharedMemories = window.__wasmSharedMemories || [];
+        window.__wasmMemoryUse['wasmShared'] = function() {
+            return window.__wasmSharedMemories.reduce((total, memory) => total + memory.buffer.byteLength, 0);
+        };"#
+    } else {
+        ""
+    };
+    let shared_created = if budgeted {
+        r#"
+            window.__wasmSharedMemories.push(window.wasmShared[name]);"#
+    } else {
+        ""
+    };
+    (
+        format!(
+            r#"
+
+        // Memories and tables shared by the modules of the page, created as
+        // the page declares them or else as the first module importing them
+        // does
+        window.wasmShared = window.wasmShared || {{}};
+        const declaredMemories = {};
+        const importedMemories = {};
+        const createdMemories = Object.keys(importedMemories).filter(function(name) {{
+            return !Object.hasOwn(window.wasmShared, name);
+        }});{shared_budget}
+        for (const name of createdMemories) {{
+            window.wasmShared[name] = new WebAssembly.Memory(declaredMemories[name] || importedMemories[name]);{shared_created}
+        }}
+        const declaredTables = {};
+        const importedTables = {};
+        // A module importing the `.base` of a table places its functions in
+        // slots reserved for it at the end of the table, the first of which
+        // is the value of the global; they are reserved once per filename,
+        // by table so that disposing the module keeps them, and a module
+        // loaded again is given the same as long as they are enough
+        window.__wasmTableSlots = window.__wasmTableSlots || {{}};
+        const sharedScope = Object.create(window.wasmShared);
+        const tableBases = {{}};
+        for (const name in importedTables) {{
+            if (!Object.hasOwn(window.wasmShared, name)) {{
+                window.wasmShared[name] = new WebAssembly.Table(declaredTables[name] || importedTables[name]);
+            }}
+            const slots = importedTables[name].slots;
+            if (slots !== undefined) {{
+                const reserved = window.__wasmTableSlots[name] = window.__wasmTableSlots[name] || {{}};
+                const held = reserved[wasmFilename];
+                if (!held || held.table !== window.wasmShared[name] || held.slots < slots) {{
+                    reserved[wasmFilename] = {{ table: window.wasmShared[name], base: window.wasmShared[name].grow(slots), slots: slots }};
+                }}
+                tableBases[name] = reserved[wasmFilename].base;
+                sharedScope[name + '.base'] = new WebAssembly.Global({{ value: 'i32' }}, tableBases[name]);
+            }}
+        }}
+        if (Object.keys(tableBases).length > 0) {{
+            window.__wasmTableBases = window.__wasmTableBases || {{}};
+            window.__wasmTableBases[wasmFilename] = tableBases;
+        }}
+        importObject['{}'] = sharedScope;"#,
+            embed::script_safe(&declared_memories),
+            embed::script_safe(&imported_memories),
+            embed::script_safe(&declared_tables),
+            embed::script_safe(&imported_tables),
+            MODULE
+        ),
+        format!("'{}'", MODULE),
+    )
+}
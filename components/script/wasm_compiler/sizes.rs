@@ -0,0 +1,157 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Code size report
+//!
+//! Where the bytes of a module go: each section with its header, the body of
+//! each function, the data segments and the name section, which only tools
+//! read. It is derived with the module metadata, as the module was compiled
+//! before any instrumentation, and read through
+//! `window.internals.wasm.sizeReport(hash)`; with `data-size-report` the
+//! loader also installs it as `window.__wasmSizes[filename]` and logs the
+//! largest functions:
+//!
+//! ```text
+//! {"total": 1520, "sections": [{"name": "type", "bytes": 24}, ...],
+//!  "functions": [{"index": 3, "name": "update", "bytes": 812}, ...],
+//!  "types": 5, "data": {"segments": 2, "active": 1, "passive": 1, "bytes": 96},
+//!  "names": 140}
+//! ```
+
+use serde::Serialize;
+use wasmparser::{DataKind, Parser, Payload, TypeRef};
+
+use super::differential::section_name;
+use super::embed;
+use super::locals;
+
+/// Bytes of a section, header included
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Section {
+    /// Name of a custom section, or of the section kind
+    pub name: String,
+    pub bytes: usize,
+}
+
+/// Bytes of the body of a function, locals included
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Function {
+    /// Index counting the imported functions first
+    pub index: u32,
+    /// From the name section
+    pub name: Option<String>,
+    pub bytes: usize,
+}
+
+/// Data segments and the bytes they hold
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Data {
+    pub segments: u32,
+    pub active: u32,
+    pub passive: u32,
+    pub bytes: usize,
+}
+
+/// Where the bytes of a module go
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SizeReport {
+    pub total: usize,
+    /// In the order of the module
+    pub sections: Vec<Section>,
+    /// Largest first
+    pub functions: Vec<Function>,
+    /// Entries of the type section
+    pub types: u32,
+    pub data: Data,
+    /// Bytes of the name section
+    pub names: usize,
+}
+
+/// The size report of `binary`, as far as it parses
+pub fn size_report(binary: &[u8]) -> SizeReport {
+    let names = locals::local_names(binary);
+    let mut report = SizeReport {
+        total: binary.len(),
+        ..Default::default()
+    };
+    let mut imported_functions = 0;
+    // Sections start where the previous one ends
+    let mut section_start = 8;
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(payload) = payload else {
+            break;
+        };
+        match &payload {
+            Payload::TypeSection(reader) => report.types = reader.count(),
+            Payload::ImportSection(reader) => {
+                imported_functions += reader
+                    .clone()
+                    .into_iter()
+                    .flatten()
+                    .filter(|import| matches!(import.ty, TypeRef::Func(_)))
+                    .count() as u32;
+            },
+            Payload::CodeSectionEntry(body) => {
+                let index = imported_functions + report.functions.len() as u32;
+                report.functions.push(Function {
+                    index,
+                    name: names.get(&index).and_then(|names| names.name.clone()),
+                    bytes: body.range().len(),
+                });
+            },
+            Payload::DataSection(reader) => {
+                for data in reader.clone().into_iter().flatten() {
+                    report.data.segments += 1;
+                    report.data.bytes += data.data.len();
+                    match data.kind {
+                        DataKind::Active { .. } => report.data.active += 1,
+                        DataKind::Passive => report.data.passive += 1,
+                    }
+                }
+            },
+            _ => {},
+        }
+        if let Some((id, range)) = payload.as_section() {
+            let name = match &payload {
+                Payload::CustomSection(reader) => reader.name().to_string(),
+                _ => section_name(id).to_string(),
+            };
+            let bytes = range.end - section_start;
+            if name == "name" {
+                report.names = bytes;
+            }
+            report.sections.push(Section { name, bytes });
+            section_start = range.end;
+        }
+    }
+    report
+        .functions
+        .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.index.cmp(&b.index)));
+    report
+}
+
+/// [`size_report`] as JSON, for the loader
+pub fn size_report_json(binary: &[u8]) -> String {
+    serde_json::to_string(&size_report(binary)).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// JavaScript installing the report of [`size_report_json`] and logging it
+pub fn loader_js(report_json: &str) -> String {
+    format!(
+        r#"
+
+        // Code size report (data-size-report), see window.internals.wasm.sizeReport
+        const wasmSizes = {};
+        window.__wasmSizes = window.__wasmSizes || {{}};
+        window.__wasmSizes[wasmFilename] = wasmSizes;
+        console.log('WASM: ' + wasmFilename + ' is ' + wasmSizes.total + ' bytes: ' +
+            wasmSizes.sections.map(section => section.name + ' ' + section.bytes).join(', '));
+        console.log('WASM: largest functions of ' + wasmFilename + ': ' + wasmSizes.functions.slice(0, 10)
+            .map(f => (f.name ? '$' + f.name : 'func ' + f.index) + ' ' + f.bytes).join(', '));"#,
+        embed::script_safe(report_json)
+    )
+}
@@ -0,0 +1,142 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Sniffing the payload of a script body
+//!
+//! The body of a WAT script is one of:
+//!
+//! - WAT text
+//! - a binary module, starting with the `\0asm` magic
+//! - a binary module in base64, after a `base64,` or
+//!   `data:application/wasm;base64,` prefix, or bare if it decodes to the
+//!   magic; whitespace between the characters is ignored. A body in
+//!   `data-encoding="base64"` is decoded without looking at it first.
+//!
+//! Binary bytes do not survive HTML parsing, which replaces a NUL with
+//! U+FFFD, nor decoding as text in the charset of a page, which replaces
+//! the bytes invalid in it. A body that looks like such a remnant gets an
+//! error saying so rather than the WAT parse error of its first byte.
+
+use std::borrow::Cow;
+
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use wasmparser::Parser;
+
+/// The magic a binary module or component starts with
+const MAGIC: &[u8] = b"\0asm";
+
+/// Prefixes marking a body as base64
+const BASE64_PREFIXES: [&str; 2] = ["data:application/wasm;base64,", "base64,"];
+
+/// `\0asm` in base64, as any module in base64 starts
+const BASE64_MAGIC: &str = "AGFzbQ";
+
+/// Characters read for signs of binary data
+const SNIFF_CHARS: usize = 64;
+
+const ADVICE: &str = "load it with src from a .wasm file, or put it in the script in base64";
+
+/// Standard base64, padded or not
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The source to compile for the body `source`: the body itself, or the
+/// module it holds in base64, as it always does if `base64` is set
+pub fn payload(source: &[u8], base64: bool) -> Result<Cow<'_, [u8]>, String> {
+    if !base64 && source.starts_with(MAGIC) {
+        return binary(source);
+    }
+    let text = String::from_utf8_lossy(source);
+    let body = text.trim_start_matches(['\u{feff}', ' ', '\t', '\n', '\r', '\x0c']);
+    let prefixed = BASE64_PREFIXES
+        .iter()
+        .find_map(|prefix| body.strip_prefix(prefix));
+    if let Some(encoded) = prefixed.or(base64.then_some(body)) {
+        let decoded = decode(encoded)?;
+        if !decoded.starts_with(MAGIC) {
+            return Err("the base64 body is not a WebAssembly module".to_owned());
+        }
+        return Ok(Cow::Owned(decoded));
+    }
+    // No WAT text starts like a module in base64
+    if body.starts_with(BASE64_MAGIC) {
+        return decode(body).map(Cow::Owned);
+    }
+    if let Some(header) = mangled_magic(body) {
+        return Err(format!(
+            "the body is a binary WebAssembly module whose bytes were replaced \
+             when the page was parsed (it starts with {:?} instead of \"\\0asm\"); {}",
+            header, ADVICE
+        ));
+    }
+    if looks_binary(body) {
+        return Err(format!(
+            "the body looks like binary data rather than WAT text; {}",
+            ADVICE
+        ));
+    }
+    Ok(Cow::Borrowed(source))
+}
+
+/// A binary module as the text of a script, in base64 after the prefix
+/// marking it, as its bytes do not survive decoding as text; `None` for a
+/// body that is not one
+pub fn binary_as_text(body: &[u8]) -> Option<String> {
+    body.starts_with(MAGIC)
+        .then(|| format!("{}{}", BASE64_PREFIXES[0], BASE64.encode(body)))
+}
+
+/// A module with the magic, unless what follows it was corrupted
+fn binary(source: &[u8]) -> Result<Cow<'_, [u8]>, String> {
+    // Bytes replaced when decoding as text become U+FFFD in UTF-8
+    const REPLACEMENT: &[u8] = "\u{fffd}".as_bytes();
+    let malformed = Parser::new(0)
+        .parse_all(source)
+        .any(|payload| payload.is_err());
+    if malformed
+        && source
+            .windows(REPLACEMENT.len())
+            .any(|window| window == REPLACEMENT)
+    {
+        return Err(format!(
+            "the body is a binary WebAssembly module some of whose bytes were \
+             replaced by U+FFFD, as decoding it as text does; {}",
+            ADVICE
+        ));
+    }
+    Ok(Cow::Borrowed(source))
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, String> {
+    let compact: String = encoded
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    BASE64
+        .decode(compact)
+        .map_err(|error| format!("the base64 body is malformed: {}", error))
+}
+
+/// The start of `body` if it is the magic with its NUL replaced or dropped
+fn mangled_magic(body: &str) -> Option<&'static str> {
+    ["\u{fffd}asm", "asm\u{1}"]
+        .into_iter()
+        .find(|header| body.starts_with(header))
+}
+
+/// Whether the first characters of `body` hold control characters or
+/// U+FFFD, which WAT text, starting with `(` or a comment, has no use for
+fn looks_binary(body: &str) -> bool {
+    !body.starts_with(['(', ';'])
+        && body.chars().take(SNIFF_CHARS).any(|c| {
+            c == '\u{fffd}' || (c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+        })
+}
@@ -0,0 +1,180 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compilation of `data-lazy` and low priority scripts in the background
+//!
+//! Modules that are not needed right away are compiled one at a time on a
+//! thread of their own, so they do not hold up the script thread nor compete
+//! with the modules it compiles for the scripts it runs. Once compiled, the
+//! module is in the cache and loading it takes no more than generating its
+//! loader.
+//!
+//! The queue is ordered by the [`Priority`] of the scripts, from their
+//! `data-priority` or `fetchpriority` attribute, then by when they were
+//! queued; a module driving the visible part of a page can so be compiled
+//! before the auxiliary modules queued ahead of it.
+
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::{CompileOptions, LOG_TARGET};
+
+/// How soon a script needs its module, like the `fetchpriority` attribute
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    Low,
+    #[default]
+    Auto,
+    High,
+}
+
+impl Priority {
+    /// Parse a `fetchpriority` keyword, ASCII case-insensitively
+    pub fn parse(value: &str) -> Option<Priority> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "auto" => Some(Priority::Auto),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+}
+
+/// Called once the module is in the cache, or compiling it failed or was
+/// cancelled; the argument tells whether it was cancelled
+type Done = Box<dyn FnOnce(bool) + Send>;
+
+struct Job {
+    priority: Priority,
+    /// Number of the job in the order of queuing
+    sequence: u64,
+    source: Vec<u8>,
+    filename: String,
+    options: CompileOptions,
+    cancelled: Arc<AtomicBool>,
+    done: Done,
+}
+
+/// Jobs of higher priority first, then those queued first
+impl Ord for Job {
+    fn cmp(&self, other: &Job) -> CmpOrdering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Job) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Job) -> bool {
+        self.sequence == other.sequence
+    }
+}
+
+impl Eq for Job {}
+
+#[derive(Default)]
+struct Jobs {
+    heap: BinaryHeap<Job>,
+    /// Number of jobs queued so far
+    count: u64,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Mutex<Jobs>,
+    queued: Condvar,
+}
+
+/// A queued compilation
+pub struct Speculation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Speculation {
+    /// Skip the compilation if it has not started yet, e.g. because the script
+    /// was removed from its document
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn get_queue() -> &'static Queue {
+    static QUEUE: OnceLock<Queue> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        thread::Builder::new()
+            .name("WatCompiler".to_owned())
+            .spawn(run)
+            .expect("Failed to spawn the WAT compiler thread");
+        Queue::default()
+    })
+}
+
+fn run() {
+    let queue = get_queue();
+    loop {
+        let job = {
+            let mut jobs = queue.jobs.lock();
+            loop {
+                if let Some(job) = jobs.heap.pop() {
+                    break job;
+                }
+                queue.queued.wait(&mut jobs);
+            }
+        };
+        let cancelled = job.cancelled.load(Ordering::Relaxed);
+        if !cancelled {
+            // Errors are reported when the script loads its module
+            let compiled = super::payload(&job.source, &job.filename, &job.options)
+                .and_then(|source| super::compile_cached(&source, &job.filename, &job.options));
+            if let Err(error) = compiled {
+                log::debug!(
+                    target: LOG_TARGET,
+                    phase = "speculative", filename:% = job.filename, error:% = error;
+                    "Compiling {} ahead failed: {}", job.filename, error
+                );
+            }
+        }
+        (job.done)(cancelled);
+    }
+}
+
+/// Queue the compilation of `source` into the cache, then call `done`
+pub fn compile(
+    source: Vec<u8>,
+    filename: String,
+    options: CompileOptions,
+    priority: Priority,
+    done: impl FnOnce(bool) + Send + 'static,
+) -> Speculation {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let queue = get_queue();
+    let mut jobs = queue.jobs.lock();
+    jobs.count += 1;
+    let sequence = jobs.count;
+    jobs.heap.push(Job {
+        priority,
+        sequence,
+        source,
+        filename,
+        options,
+        cancelled: cancelled.clone(),
+        done: Box::new(done),
+    });
+    queue.queued.notify_one();
+    Speculation { cancelled }
+}
@@ -0,0 +1,120 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Start-function execution policy
+//!
+//! A module's `(start ...)` function runs during instantiation, before its
+//! exports are wired onto `window` and before `wasmloaded` fires. Scripts can
+//! choose, via `data-start`, to defer it until the module is fully loaded or
+//! to skip it entirely. Both are done by removing the start section from the
+//! binary; a deferred start function is exported under
+//! [`DEFERRED_START_EXPORT`] so the loader can call it explicitly.
+
+use super::{LOG_TARGET, read_leb128_u32, write_leb128_u32};
+
+/// Export name of a deferred start function
+pub const DEFERRED_START_EXPORT: &str = "__wasm_start";
+
+/// What to do with a module's start function
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StartPolicy {
+    /// Run during instantiation, as the WebAssembly spec does
+    #[default]
+    Run,
+    /// Run after the exports are installed and `wasmloaded` was dispatched
+    Defer,
+    /// Never run
+    Skip,
+}
+
+impl StartPolicy {
+    /// Parse a `data-start` attribute value (`run`, `defer` or `skip`)
+    pub fn parse(value: &str) -> Option<StartPolicy> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "run" => Some(StartPolicy::Run),
+            "defer" => Some(StartPolicy::Defer),
+            "skip" => Some(StartPolicy::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Apply the policy to a compiled module
+/// Returns true if the start function was exported as [`DEFERRED_START_EXPORT`]
+pub fn apply_start_policy(binary: &mut Vec<u8>, policy: StartPolicy) -> bool {
+    if policy == StartPolicy::Run || binary.len() < 8 || &binary[0..4] != b"\0asm" {
+        return false;
+    }
+
+    // Locate the export (7) and start (8) sections
+    let mut exports = None;
+    let mut start = None;
+    let mut i = 8;
+    while i < binary.len() {
+        let section_id = binary[i];
+        let (size, size_len) = read_leb128_u32(&binary[i + 1..]);
+        let payload = i + 1 + size_len;
+        let end = payload + size as usize;
+        if end > binary.len() {
+            return false;
+        }
+        match section_id {
+            7 => exports = Some((i, payload, end)),
+            8 => start = Some((i, payload, end)),
+            _ => {},
+        }
+        i = end;
+    }
+
+    let Some((start_offset, start_payload, start_end)) = start else {
+        return false;
+    };
+    let (func_index, _) = read_leb128_u32(&binary[start_payload..start_end]);
+    binary.drain(start_offset..start_end);
+
+    if policy == StartPolicy::Skip {
+        log::info!(
+            target: LOG_TARGET, phase = "start policy", function = func_index;
+            "Skipping start function {}", func_index
+        );
+        return false;
+    }
+
+    // Export entry: name, kind 0 (func), function index
+    let mut entry = Vec::new();
+    write_leb128_u32(DEFERRED_START_EXPORT.len() as u32, &mut entry);
+    entry.extend_from_slice(DEFERRED_START_EXPORT.as_bytes());
+    entry.push(0x00);
+    write_leb128_u32(func_index, &mut entry);
+
+    // The export section precedes the start section, so removing the start
+    // section did not move it; without one, a new section takes the start
+    // section's place
+    let (offset, end, mut payload) = match exports {
+        Some((offset, payload, end)) => {
+            let (count, count_len) = read_leb128_u32(&binary[payload..end]);
+            let mut new_payload = Vec::new();
+            write_leb128_u32(count + 1, &mut new_payload);
+            new_payload.extend_from_slice(&binary[payload + count_len..end]);
+            (offset, end, new_payload)
+        },
+        // Count of one
+        None => (start_offset, start_offset, vec![1]),
+    };
+    payload.extend(entry);
+
+    let mut section = vec![7];
+    write_leb128_u32(payload.len() as u32, &mut section);
+    section.extend(payload);
+    binary.splice(offset..end, section);
+
+    log::info!(
+        target: LOG_TARGET, phase = "start policy", function = func_index;
+        "Deferring start function {} (exported as {})", func_index, DEFERRED_START_EXPORT
+    );
+    true
+}
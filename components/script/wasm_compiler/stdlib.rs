@@ -0,0 +1,709 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Standard library of WAT modules
+//!
+//! Modules import its functions from the `std/` namespace like any other
+//! function:
+//!
+//! ```text
+//! (import "std/string" "concat" (func $concat (param (ref null $string) (ref null $string)) (result (ref $string))))
+//! (import "std/math" "clamp" (func $clamp (param i32 i32 i32) (result i32)))
+//! ```
+//!
+//! The imports are linked at compile time rather than instantiation: each is
+//! dropped, the library's functions added to the module from the WAT the
+//! compiler embeds, and the import's id renamed to the function it names.
+//! Imports of the library therefore need an id, and modules importing it
+//! should refer to the functions declared after the imports by id too.
+//!
+//! - `std/string`: `length`, `concat`, `equals`, `slice`, `index_of`,
+//!   `starts_with`, `to_upper`, `to_lower` and `parse_i32` on UTF-8
+//!   strings, by byte
+//! - `std/math`: `abs`, `min`, `max`, `clamp`, `gcd` and `pow` on `i32`s,
+//!   `clamp_f64` and `lerp` on `f64`s
+//! - `std/list`: growable lists of `i32`s, `(ref $std/list)`: `new`,
+//!   `length`, `push`, `pop`, `get` and `set`
+//! - `std/map`: hash maps from `i32` to `i32`, `(ref $std/map)`: `new`,
+//!   `size`, `has`, `get` with a default and `set`
+//!
+//! The types the libraries add are named `$std/...`. Being structurally the
+//! same, `$std/string` is the `$string` of the string-type sugar.
+//!
+//! The library is versioned as a whole, see [`VERSION`]. Modules importing
+//! from `std/` get the latest revision of each library; those that pin a
+//! version, importing from e.g. `std@1/string`, get the library as that
+//! version had it, however the compiler evolves. A library changes by adding
+//! a revision under a new version to [`LIBRARIES`], never by editing one.
+//! Scripts with `data-no-stdlib` are not linked: their `std/` imports are
+//! left to the page to provide, like any other.
+
+use std::collections::BTreeSet;
+
+use super::wat_text::{Sexpr, parse_sexpr};
+
+/// Name of the library in import modules, followed by `/` or `@<version>/`
+const NAMESPACE: &str = "std";
+
+/// Current version of the library
+pub const VERSION: u32 = 1;
+
+/// Types of the libraries, in an order in which each only refers to those
+/// before it
+const TYPES: [(&str, &str); 5] = [
+    ("$std/string", "(type $std/string (array (mut i8)))"),
+    ("$std/bytes", "(type $std/bytes (array (mut i8)))"),
+    ("$std/i32s", "(type $std/i32s (array (mut i32)))"),
+    (
+        "$std/list",
+        "(type $std/list (struct (field $items (mut (ref $std/i32s))) (field $length (mut i32))))",
+    ),
+    (
+        "$std/map",
+        "(type $std/map (struct (field $keys (mut (ref $std/i32s))) (field $values (mut (ref $std/i32s))) \
+         (field $used (mut (ref $std/bytes))) (field $size (mut i32))))",
+    ),
+];
+
+/// A revision of a library: the functions modules may import, defined as
+/// `$std/<library>.<function>` in its code along with private helpers
+struct Library {
+    name: &'static str,
+    /// Version of the library that introduced the revision
+    version: u32,
+    functions: &'static [&'static str],
+    types: &'static [&'static str],
+    code: &'static str,
+}
+
+impl Library {
+    /// Whether it needs the GC proposal, for its types
+    pub fn needs_gc(&self) -> bool {
+        !self.types.is_empty()
+    }
+}
+
+const LIBRARIES: [Library; 4] = [
+    Library {
+        name: "string",
+        version: 1,
+        functions: &[
+            "length",
+            "concat",
+            "equals",
+            "slice",
+            "index_of",
+            "starts_with",
+            "to_upper",
+            "to_lower",
+            "parse_i32",
+        ],
+        types: &["$std/string"],
+        code: STRING,
+    },
+    Library {
+        name: "math",
+        version: 1,
+        functions: &[
+            "abs",
+            "min",
+            "max",
+            "clamp",
+            "gcd",
+            "pow",
+            "clamp_f64",
+            "lerp",
+        ],
+        types: &[],
+        code: MATH,
+    },
+    Library {
+        name: "list",
+        version: 1,
+        functions: &["new", "length", "push", "pop", "get", "set"],
+        types: &["$std/i32s", "$std/list"],
+        code: LIST,
+    },
+    Library {
+        name: "map",
+        version: 1,
+        functions: &["new", "size", "has", "get", "set"],
+        types: &["$std/bytes", "$std/i32s", "$std/map"],
+        code: MAP,
+    },
+];
+
+const STRING: &str = r#"
+  ;; std/string (linked)
+  (func $std/string.length (param $s (ref null $std/string)) (result i32)
+    (array.len (local.get $s)))
+
+  (func $std/string.concat (param $a (ref null $std/string)) (param $b (ref null $std/string)) (result (ref $std/string))
+    (local $out (ref $std/string))
+    (local.set $out (array.new_default $std/string
+      (i32.add (array.len (local.get $a)) (array.len (local.get $b)))))
+    (array.copy $std/string $std/string (local.get $out) (i32.const 0)
+      (local.get $a) (i32.const 0) (array.len (local.get $a)))
+    (array.copy $std/string $std/string (local.get $out) (array.len (local.get $a))
+      (local.get $b) (i32.const 0) (array.len (local.get $b)))
+    (local.get $out))
+
+  ;; Whether $s has $other at $at
+  (func $std/string/matches (param $s (ref null $std/string)) (param $at i32) (param $other (ref null $std/string)) (result i32)
+    (local $i i32)
+    (if (i32.gt_u (array.len (local.get $other)) (i32.sub (array.len (local.get $s)) (local.get $at)))
+      (then (return (i32.const 0))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (array.len (local.get $other))))
+        (if (i32.ne (array.get_u $std/string (local.get $s) (i32.add (local.get $at) (local.get $i)))
+                    (array.get_u $std/string (local.get $other) (local.get $i)))
+          (then (return (i32.const 0))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i32.const 1))
+
+  (func $std/string.equals (param $a (ref null $std/string)) (param $b (ref null $std/string)) (result i32)
+    (i32.and
+      (i32.eq (array.len (local.get $a)) (array.len (local.get $b)))
+      (call $std/string/matches (local.get $a) (i32.const 0) (local.get $b))))
+
+  (func $std/string.starts_with (param $s (ref null $std/string)) (param $prefix (ref null $std/string)) (result i32)
+    (call $std/string/matches (local.get $s) (i32.const 0) (local.get $prefix)))
+
+  ;; Bytes $start to $end, both clamped to the string
+  (func $std/string.slice (param $s (ref null $std/string)) (param $start i32) (param $end i32) (result (ref $std/string))
+    (local $out (ref $std/string))
+    (local.set $end (select (local.get $end) (array.len (local.get $s))
+      (i32.le_u (local.get $end) (array.len (local.get $s)))))
+    (local.set $start (select (local.get $start) (local.get $end)
+      (i32.le_u (local.get $start) (local.get $end))))
+    (local.set $out (array.new_default $std/string (i32.sub (local.get $end) (local.get $start))))
+    (array.copy $std/string $std/string (local.get $out) (i32.const 0)
+      (local.get $s) (local.get $start) (i32.sub (local.get $end) (local.get $start)))
+    (local.get $out))
+
+  ;; Byte offset of the first $needle in $s, or -1
+  (func $std/string.index_of (param $s (ref null $std/string)) (param $needle (ref null $std/string)) (result i32)
+    (local $at i32)
+    (block $missing
+      (loop $next
+        (br_if $missing (i32.gt_s (local.get $at)
+          (i32.sub (array.len (local.get $s)) (array.len (local.get $needle)))))
+        (if (call $std/string/matches (local.get $s) (local.get $at) (local.get $needle))
+          (then (return (local.get $at))))
+        (local.set $at (i32.add (local.get $at) (i32.const 1)))
+        (br $next)))
+    (i32.const -1))
+
+  ;; A copy with the ASCII letters from $first to $first + 25 shifted by $shift
+  (func $std/string/shift_case (param $s (ref null $std/string)) (param $first i32) (param $shift i32) (result (ref $std/string))
+    (local $out (ref $std/string))
+    (local $i i32)
+    (local $c i32)
+    (local.set $out (array.new_default $std/string (array.len (local.get $s))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (array.len (local.get $s))))
+        (local.set $c (array.get_u $std/string (local.get $s) (local.get $i)))
+        (if (i32.lt_u (i32.sub (local.get $c) (local.get $first)) (i32.const 26))
+          (then (local.set $c (i32.add (local.get $c) (local.get $shift)))))
+        (array.set $std/string (local.get $out) (local.get $i) (local.get $c))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (local.get $out))
+
+  (func $std/string.to_upper (param $s (ref null $std/string)) (result (ref $std/string))
+    (call $std/string/shift_case (local.get $s) (i32.const 97) (i32.const -32)))
+
+  (func $std/string.to_lower (param $s (ref null $std/string)) (result (ref $std/string))
+    (call $std/string/shift_case (local.get $s) (i32.const 65) (i32.const 32)))
+
+  ;; The decimal integer $s starts with, after an optional `-`; 0 without one
+  (func $std/string.parse_i32 (param $s (ref null $std/string)) (result i32)
+    (local $i i32)
+    (local $digit i32)
+    (local $value i32)
+    (local $negative i32)
+    (if (i32.and (i32.gt_u (array.len (local.get $s)) (i32.const 0))
+                 (i32.eq (array.get_u $std/string (local.get $s) (i32.const 0)) (i32.const 45)))
+      (then
+        (local.set $negative (i32.const 1))
+        (local.set $i (i32.const 1))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (array.len (local.get $s))))
+        (local.set $digit (i32.sub (array.get_u $std/string (local.get $s) (local.get $i)) (i32.const 48)))
+        (br_if $done (i32.ge_u (local.get $digit) (i32.const 10)))
+        (local.set $value (i32.add (i32.mul (local.get $value) (i32.const 10)) (local.get $digit)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (select (i32.sub (i32.const 0) (local.get $value)) (local.get $value) (local.get $negative)))
+"#;
+
+const MATH: &str = r#"
+  ;; std/math (linked)
+  (func $std/math.abs (param $x i32) (result i32)
+    (select (i32.sub (i32.const 0) (local.get $x)) (local.get $x) (i32.lt_s (local.get $x) (i32.const 0))))
+
+  (func $std/math.min (param $a i32) (param $b i32) (result i32)
+    (select (local.get $a) (local.get $b) (i32.lt_s (local.get $a) (local.get $b))))
+
+  (func $std/math.max (param $a i32) (param $b i32) (result i32)
+    (select (local.get $a) (local.get $b) (i32.gt_s (local.get $a) (local.get $b))))
+
+  (func $std/math.clamp (param $x i32) (param $low i32) (param $high i32) (result i32)
+    (call $std/math.min (call $std/math.max (local.get $x) (local.get $low)) (local.get $high)))
+
+  (func $std/math.gcd (param $a i32) (param $b i32) (result i32)
+    (local $rest i32)
+    (local.set $a (call $std/math.abs (local.get $a)))
+    (local.set $b (call $std/math.abs (local.get $b)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $b)))
+        (local.set $rest (i32.rem_u (local.get $a) (local.get $b)))
+        (local.set $a (local.get $b))
+        (local.set $b (local.get $rest))
+        (br $next)))
+    (local.get $a))
+
+  ;; $base to the power $exponent, wrapping; 0 for negative exponents
+  (func $std/math.pow (param $base i32) (param $exponent i32) (result i32)
+    (local $result i32)
+    (if (i32.lt_s (local.get $exponent) (i32.const 0))
+      (then (return (i32.const 0))))
+    (local.set $result (i32.const 1))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $exponent)))
+        (if (i32.and (local.get $exponent) (i32.const 1))
+          (then (local.set $result (i32.mul (local.get $result) (local.get $base)))))
+        (local.set $base (i32.mul (local.get $base) (local.get $base)))
+        (local.set $exponent (i32.shr_u (local.get $exponent) (i32.const 1)))
+        (br $next)))
+    (local.get $result))
+
+  (func $std/math.clamp_f64 (param $x f64) (param $low f64) (param $high f64) (result f64)
+    (f64.min (f64.max (local.get $x) (local.get $low)) (local.get $high)))
+
+  (func $std/math.lerp (param $a f64) (param $b f64) (param $t f64) (result f64)
+    (f64.add (local.get $a) (f64.mul (f64.sub (local.get $b) (local.get $a)) (local.get $t))))
+"#;
+
+const LIST: &str = r#"
+  ;; std/list (linked)
+  (func $std/list.new (result (ref $std/list))
+    (struct.new $std/list (array.new_default $std/i32s (i32.const 8)) (i32.const 0)))
+
+  (func $std/list.length (param $list (ref null $std/list)) (result i32)
+    (struct.get $std/list $length (local.get $list)))
+
+  (func $std/list.push (param $list (ref null $std/list)) (param $value i32)
+    (local $items (ref $std/i32s))
+    (local $length i32)
+    (local $grown (ref $std/i32s))
+    (local.set $items (struct.get $std/list $items (local.get $list)))
+    (local.set $length (struct.get $std/list $length (local.get $list)))
+    (if (i32.eq (local.get $length) (array.len (local.get $items)))
+      (then
+        (local.set $grown (array.new_default $std/i32s
+          (i32.add (i32.shl (local.get $length) (i32.const 1)) (i32.const 8))))
+        (array.copy $std/i32s $std/i32s (local.get $grown) (i32.const 0)
+          (local.get $items) (i32.const 0) (local.get $length))
+        (struct.set $std/list $items (local.get $list) (local.get $grown))
+        (local.set $items (local.get $grown))))
+    (array.set $std/i32s (local.get $items) (local.get $length) (local.get $value))
+    (struct.set $std/list $length (local.get $list) (i32.add (local.get $length) (i32.const 1))))
+
+  ;; Traps on an empty list
+  (func $std/list.pop (param $list (ref null $std/list)) (result i32)
+    (local $length i32)
+    (local.set $length (struct.get $std/list $length (local.get $list)))
+    (if (i32.eqz (local.get $length))
+      (then (unreachable)))
+    (local.set $length (i32.sub (local.get $length) (i32.const 1)))
+    (struct.set $std/list $length (local.get $list) (local.get $length))
+    (array.get $std/i32s (struct.get $std/list $items (local.get $list)) (local.get $length)))
+
+  ;; Traps past the end of the list
+  (func $std/list.get (param $list (ref null $std/list)) (param $index i32) (result i32)
+    (if (i32.ge_u (local.get $index) (struct.get $std/list $length (local.get $list)))
+      (then (unreachable)))
+    (array.get $std/i32s (struct.get $std/list $items (local.get $list)) (local.get $index)))
+
+  (func $std/list.set (param $list (ref null $std/list)) (param $index i32) (param $value i32)
+    (if (i32.ge_u (local.get $index) (struct.get $std/list $length (local.get $list)))
+      (then (unreachable)))
+    (array.set $std/i32s (struct.get $std/list $items (local.get $list)) (local.get $index) (local.get $value)))
+"#;
+
+const MAP: &str = r#"
+  ;; std/map (linked): open addressing with linear probing, grown past three
+  ;; quarters full
+  (func $std/map.new (result (ref $std/map))
+    (struct.new $std/map
+      (array.new_default $std/i32s (i32.const 16))
+      (array.new_default $std/i32s (i32.const 16))
+      (array.new_default $std/bytes (i32.const 16))
+      (i32.const 0)))
+
+  (func $std/map.size (param $map (ref null $std/map)) (result i32)
+    (struct.get $std/map $size (local.get $map)))
+
+  ;; Slot of $key, or the free slot it would take
+  (func $std/map/slot (param $map (ref null $std/map)) (param $key i32) (result i32)
+    (local $mask i32)
+    (local $hash i32)
+    (local.set $mask (i32.sub (array.len (struct.get $std/map $keys (local.get $map))) (i32.const 1)))
+    (local.set $hash (i32.mul (local.get $key) (i32.const 0x9e3779b1)))
+    (local.set $hash (i32.and (i32.xor (local.get $hash) (i32.shr_u (local.get $hash) (i32.const 16))) (local.get $mask)))
+    (block $found
+      (loop $next
+        (br_if $found (i32.eqz (array.get_u $std/bytes (struct.get $std/map $used (local.get $map)) (local.get $hash))))
+        (br_if $found (i32.eq (array.get $std/i32s (struct.get $std/map $keys (local.get $map)) (local.get $hash)) (local.get $key)))
+        (local.set $hash (i32.and (i32.add (local.get $hash) (i32.const 1)) (local.get $mask)))
+        (br $next)))
+    (local.get $hash))
+
+  (func $std/map.has (param $map (ref null $std/map)) (param $key i32) (result i32)
+    (array.get_u $std/bytes (struct.get $std/map $used (local.get $map))
+      (call $std/map/slot (local.get $map) (local.get $key))))
+
+  (func $std/map.get (param $map (ref null $std/map)) (param $key i32) (param $default i32) (result i32)
+    (local $slot i32)
+    (local.set $slot (call $std/map/slot (local.get $map) (local.get $key)))
+    (if (result i32) (array.get_u $std/bytes (struct.get $std/map $used (local.get $map)) (local.get $slot))
+      (then (array.get $std/i32s (struct.get $std/map $values (local.get $map)) (local.get $slot)))
+      (else (local.get $default))))
+
+  (func $std/map.set (param $map (ref null $std/map)) (param $key i32) (param $value i32)
+    (local $slot i32)
+    (local.set $slot (call $std/map/slot (local.get $map) (local.get $key)))
+    (if (i32.eqz (array.get_u $std/bytes (struct.get $std/map $used (local.get $map)) (local.get $slot)))
+      (then
+        (if (i32.ge_u (i32.mul (i32.add (struct.get $std/map $size (local.get $map)) (i32.const 1)) (i32.const 4))
+                      (i32.mul (array.len (struct.get $std/map $keys (local.get $map))) (i32.const 3)))
+          (then
+            (call $std/map/grow (local.get $map))
+            (local.set $slot (call $std/map/slot (local.get $map) (local.get $key)))))
+        (array.set $std/bytes (struct.get $std/map $used (local.get $map)) (local.get $slot) (i32.const 1))
+        (array.set $std/i32s (struct.get $std/map $keys (local.get $map)) (local.get $slot) (local.get $key))
+        (struct.set $std/map $size (local.get $map)
+          (i32.add (struct.get $std/map $size (local.get $map)) (i32.const 1)))))
+    (array.set $std/i32s (struct.get $std/map $values (local.get $map)) (local.get $slot) (local.get $value)))
+
+  ;; Double the slots, putting the entries in their new ones
+  (func $std/map/grow (param $map (ref null $std/map))
+    (local $keys (ref $std/i32s))
+    (local $values (ref $std/i32s))
+    (local $used (ref $std/bytes))
+    (local $i i32)
+    (local $slot i32)
+    (local.set $keys (struct.get $std/map $keys (local.get $map)))
+    (local.set $values (struct.get $std/map $values (local.get $map)))
+    (local.set $used (struct.get $std/map $used (local.get $map)))
+    (struct.set $std/map $keys (local.get $map)
+      (array.new_default $std/i32s (i32.shl (array.len (local.get $keys)) (i32.const 1))))
+    (struct.set $std/map $values (local.get $map)
+      (array.new_default $std/i32s (i32.shl (array.len (local.get $keys)) (i32.const 1))))
+    (struct.set $std/map $used (local.get $map)
+      (array.new_default $std/bytes (i32.shl (array.len (local.get $keys)) (i32.const 1))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (array.len (local.get $keys))))
+        (if (array.get_u $std/bytes (local.get $used) (local.get $i))
+          (then
+            (local.set $slot (call $std/map/slot (local.get $map) (array.get $std/i32s (local.get $keys) (local.get $i))))
+            (array.set $std/bytes (struct.get $std/map $used (local.get $map)) (local.get $slot) (i32.const 1))
+            (array.set $std/i32s (struct.get $std/map $keys (local.get $map)) (local.get $slot)
+              (array.get $std/i32s (local.get $keys) (local.get $i)))
+            (array.set $std/i32s (struct.get $std/map $values (local.get $map)) (local.get $slot)
+              (array.get $std/i32s (local.get $values) (local.get $i)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next))))
+"#;
+
+/// Whether a WAT source may import from the library
+pub fn uses_stdlib(source: &str) -> bool {
+    source.contains("\"std/") || source.contains("\"std@")
+}
+
+/// A source with its imports of the library linked
+#[derive(Debug)]
+pub struct Linked {
+    pub text: String,
+    /// Whether a library it uses needs the GC proposal
+    pub needs_gc: bool,
+}
+
+/// Link the imports of the library into a module, see the module
+/// documentation
+pub fn link(source: &str) -> Result<Linked, String> {
+    let Some((fields, end)) = module_fields(source) else {
+        return Ok(Linked {
+            text: source.to_string(),
+            needs_gc: false,
+        });
+    };
+
+    let mut text = String::with_capacity(source.len());
+    let mut renames = Vec::new();
+    let mut exports = String::new();
+    let mut used: BTreeSet<usize> = BTreeSet::new();
+    let mut copied = 0;
+    for (start, field_end) in fields {
+        let Some(import) =
+            parse_sexpr(&source[start..field_end]).and_then(|field| std_import(&field))
+        else {
+            continue;
+        };
+        let (id, module, name, inline_exports) = import?;
+        let index = resolve(&module)?;
+        let library = LIBRARIES[index].name;
+        if !LIBRARIES[index].functions.contains(&name.as_str()) {
+            return Err(format!("{} has no function {:?}", module, name));
+        }
+        if let Some(other) = used
+            .iter()
+            .find(|other| **other != index && LIBRARIES[**other].name == library)
+        {
+            return Err(format!(
+                "{} is imported as of both version {} and {}",
+                module, LIBRARIES[*other].version, LIBRARIES[index].version
+            ));
+        }
+        let Some(id) = id else {
+            return Err(format!("the import of {} {:?} needs an id", module, name));
+        };
+        let target = format!("$std/{}.{}", library, name);
+        for export in inline_exports {
+            exports.push_str(&format!("\n  (export {} (func {}))", export, target));
+        }
+        used.insert(index);
+        renames.push((id, target));
+        text.push_str(&source[copied..start]);
+        text.push_str(&format!(";; {} {:?} linked", module, name));
+        copied = field_end;
+    }
+    if used.is_empty() {
+        return Ok(Linked {
+            text: source.to_string(),
+            needs_gc: false,
+        });
+    }
+    text.push_str(&source[copied..end]);
+    for (id, target) in &renames {
+        text = rename_id(&text, id, target);
+    }
+
+    // Types and functions of the libraries go before the module's closing
+    // parenthesis, or at the end of a module without one
+    let libraries: Vec<&Library> = used.iter().map(|index| &LIBRARIES[*index]).collect();
+    for (name, definition) in TYPES {
+        if libraries
+            .iter()
+            .any(|library| library.types.contains(&name))
+            && !text.contains(&format!("(type {} ", name))
+        {
+            text.push_str("\n  ");
+            text.push_str(definition);
+        }
+    }
+    text.push_str(&exports);
+    for library in &libraries {
+        text.push_str(library.code);
+    }
+    text.push_str(&source[end..]);
+    Ok(Linked {
+        text,
+        needs_gc: libraries.iter().any(|library| library.needs_gc()),
+    })
+}
+
+/// An import of the library: the id of the function, the module and name it
+/// imports, and the names it is exported as; `None` for other fields
+#[allow(clippy::type_complexity)]
+fn std_import(
+    field: &Sexpr,
+) -> Option<Result<(Option<String>, String, String, Vec<String>), String>> {
+    let unquote = |item: Option<&Sexpr>| {
+        item.and_then(Sexpr::atom)
+            .and_then(|atom| atom.strip_prefix('"')?.strip_suffix('"'))
+            .map(str::to_string)
+    };
+    let id = |items: &[Sexpr]| {
+        items
+            .get(1)
+            .and_then(Sexpr::atom)
+            .filter(|atom| atom.starts_with('$'))
+            .map(str::to_string)
+    };
+    let (id, import, exports) = match field.head()? {
+        // (import "std/string" "concat" (func $concat ...))
+        "import" => {
+            let func = field
+                .items()
+                .get(3)
+                .filter(|item| item.head() == Some("func"));
+            (
+                func.and_then(|func| id(func.items())),
+                field.items(),
+                Vec::new(),
+            )
+        },
+        // (func $concat (export "concat") (import "std/string" "concat") ...)
+        "func" => {
+            let import = field
+                .items()
+                .iter()
+                .find(|item| item.head() == Some("import"))?;
+            let exports = field
+                .items()
+                .iter()
+                .filter(|item| item.head() == Some("export"))
+                .filter_map(|export| export.items().get(1).and_then(Sexpr::atom))
+                .map(str::to_string)
+                .collect();
+            (id(field.items()), import.items(), exports)
+        },
+        _ => return None,
+    };
+    let module = unquote(import.get(1))?;
+    if !module
+        .strip_prefix(NAMESPACE)
+        .is_some_and(|rest| rest.starts_with(['/', '@']))
+    {
+        return None;
+    }
+    let Some(name) = unquote(import.get(2)) else {
+        return Some(Err(format!("the import of {} has no name", module)));
+    };
+    Some(Ok((id, module, name, exports)))
+}
+
+/// The index in [`LIBRARIES`] of the revision an import module names:
+/// `std/<library>` for the latest, `std@<version>/<library>` for the one
+/// that version had
+fn resolve(module: &str) -> Result<usize, String> {
+    let rest = &module[NAMESPACE.len()..];
+    let (version, library) = match rest.strip_prefix('@') {
+        Some(pinned) => {
+            let (version, library) = pinned
+                .split_once('/')
+                .ok_or_else(|| format!("{} names no library", module))?;
+            let version = version
+                .parse::<u32>()
+                .ok()
+                .filter(|version| *version > 0)
+                .ok_or_else(|| format!("{} has an invalid version", module))?;
+            if version > VERSION {
+                return Err(format!(
+                    "{} needs a newer compiler, whose library is at version {}",
+                    module, VERSION
+                ));
+            }
+            (version, library)
+        },
+        None => (VERSION, &rest[1..]),
+    };
+    LIBRARIES
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| candidate.name == library && candidate.version <= version)
+        .max_by_key(|(_, candidate)| candidate.version)
+        .map(|(index, _)| index)
+        .ok_or_else(|| format!("there is no library {}", module))
+}
+
+/// Byte ranges of the fields of a module and where it closes: the lists
+/// inside `(module ...)`, or at the top level without one
+fn module_fields(source: &str) -> Option<(Vec<(usize, usize)>, usize)> {
+    let bytes = source.as_bytes();
+    let mut fields = Vec::new();
+    let mut field_depth = None;
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            },
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            },
+            b'(' if bytes.get(i + 1) == Some(&b';') => {
+                i = source[i..]
+                    .find(";)")
+                    .map_or(bytes.len(), |end| i + end + 1);
+            },
+            b'(' => {
+                let field_depth = *field_depth.get_or_insert_with(|| {
+                    if source[i + 1..].starts_with("module") {
+                        1
+                    } else {
+                        0
+                    }
+                });
+                if depth == field_depth {
+                    start = i;
+                }
+                depth += 1;
+            },
+            b')' => {
+                depth -= 1;
+                if Some(depth) == field_depth {
+                    fields.push((start, i + 1));
+                } else if depth == 0 && field_depth == Some(1) {
+                    return Some((fields, i));
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    field_depth.map(|_| (fields, source.len()))
+}
+
+/// Rename the id `from` to `to`, skipping strings and comments
+fn rename_id(source: &str, from: &str, to: &str) -> String {
+    let delimits = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || c == '(' || c == ')');
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        let skipped = if c == '"' {
+            let mut end = 1;
+            let bytes = rest.as_bytes();
+            while end < bytes.len() && bytes[end] != b'"' {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            (end + 1).min(rest.len())
+        } else if rest.starts_with(";;") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("(;") {
+            rest.find(";)").map_or(rest.len(), |end| end + 2)
+        } else if rest.starts_with(from)
+            && delimits(result.chars().last())
+            && delimits(rest[from.len()..].chars().next())
+        {
+            result.push_str(to);
+            rest = &rest[from.len()..];
+            continue;
+        } else {
+            c.len_utf8()
+        };
+        result.push_str(&rest[..skipped]);
+        rest = &rest[skipped..];
+    }
+    result
+}
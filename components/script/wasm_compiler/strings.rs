@@ -0,0 +1,656 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strings passed to and returned by exports
+//!
+//! The `$string` type of the string-type sugar is a GC array of UTF-8 bytes,
+//! which JavaScript can neither read nor create. Every array type named
+//! `string` with `i8` elements (UTF-8) or `i16` elements (UTF-16 code units)
+//! gets accessors, and mutable ones a constructor and a setter too:
+//!
+//! ```text
+//! (func (export "__wasm_string_get_0") (param (ref null 0)) (param i32) (result i32))
+//! (func (export "__wasm_string_len_0") (param (ref null 0)) (result i32))
+//! (func (export "__wasm_string_new_0") (param i32) (result (ref 0)))
+//! (func (export "__wasm_string_set_0") (param (ref null 0)) (param i32) (param i32))
+//! ```
+//!
+//! Modules without a memory of their own also get a scratch memory, exported
+//! as [`MEMORY_EXPORT`], and functions copying the first code units of a
+//! string to and from it, so the loader copies a string with a single call
+//! and typed array rather than a call per code unit:
+//!
+//! ```text
+//! (func (export "__wasm_string_to_memory_0") (param (ref null 0)) (param $ptr i32) (param $count i32))
+//! (func (export "__wasm_string_from_memory_0") (param (ref null 0)) (param $ptr i32) (param $count i32))
+//! ```
+//!
+//! Exports declared to return one, see [`super::results`], return JavaScript
+//! strings decoded through them rather than objects to stringify, and those
+//! declared to take one encode the JavaScript strings they are passed.
+//!
+//! With linear-memory strings, given by `data-strings="linear"` or detected,
+//! see [`detect_encoding`], strings live in memory 0 instead, as the modules
+//! of C toolchains keep them. JavaScript strings passed to an export
+//! become a pointer and a length, the bytes being NUL-terminated too, and
+//! are freed after the call. The memory is exported as [`MEMORY_EXPORT`] if
+//! the module does not export it. Pointers returned are `i32`s, which a
+//! signature does not tell apart from numbers, so they are left to the page.
+//!
+//! Modules following the canonical ABI of components, given by
+//! `data-strings="canonical"` or detected from their [`CANONICAL_REALLOC`]
+//! export, keep strings in memory 0 too, without the NUL. JavaScript strings
+//! passed to an export become a pointer and a length allocated with
+//! `cabi_realloc`, which the callee owns. An export returning one `i32` that
+//! has a `cabi_post_` function returns a pointer to the pointer and length
+//! of a string: it is decoded, then the function called to free it.
+//!
+//! Modules importing the `wasm:js-string` builtins hold JavaScript strings as
+//! `externref`s, which need no conversion. The loader asks the engine for the
+//! builtins and imports equivalent functions for engines without them, all but
+//! those working on arrays.
+
+use std::convert::Infallible;
+
+use wasm_encoder::reencode::{self, Reencode};
+use wasm_encoder::{
+    BlockType, ExportKind, ExportSection, Instruction, MemArg, MemorySection, MemoryType, Module,
+    SectionId,
+};
+use wasmparser::{
+    CompositeInnerType, ExportSectionReader, ExternalKind, HeapType, Parser, Payload, StorageType,
+    TypeRef, ValType,
+};
+
+use super::accessors::{self, Accessor};
+use super::arrays;
+use super::options::StringEncoding;
+
+/// Prefix of the added exports, which the loader does not install
+pub const EXPORT_PREFIX: &str = "__wasm_string_";
+
+/// Export of the memory strings are copied through: the scratch memory, or
+/// memory 0 for linear-memory strings
+pub const MEMORY_EXPORT: &str = "__wasm_string_memory";
+
+/// Name of the string types in the name section
+const TYPE_NAME: &str = "string";
+
+/// A string type
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringType {
+    /// Type index of the array
+    pub array: u32,
+    /// Bits of a code unit, 8 for UTF-8 and 16 for UTF-16
+    pub unit: u32,
+    /// Export reading a code unit
+    pub get: String,
+    /// Export reading the length in code units
+    pub len: String,
+    /// Exports creating a string of a length and writing a code unit, for
+    /// mutable string types
+    pub new: Option<String>,
+    pub set: Option<String>,
+    /// Exports copying code units to and from the scratch memory, for
+    /// modules without a memory; the latter for mutable string types only
+    pub to_memory: Option<String>,
+    pub from_memory: Option<String>,
+}
+
+/// Module name of the JavaScript string builtins
+pub const JS_STRING_MODULE: &str = "wasm:js-string";
+
+/// Exports of the module's own allocator, used for linear-memory strings
+pub const LINEAR_ALLOCATORS: [&str; 2] = ["alloc", "malloc"];
+
+/// Export of the allocator of the canonical ABI
+pub const CANONICAL_REALLOC: &str = "cabi_realloc";
+
+/// What a type index refers to, as far as detecting the encoding goes
+enum Type {
+    /// An array type, with the bits of its packed elements, if packed
+    Array(Option<u32>),
+    /// A function type, with the type its single result refers to
+    Func(Option<u32>),
+    Other,
+}
+
+/// The string encoding a module was built for, from what it declares:
+/// imports of [`JS_STRING_MODULE`] for JavaScript strings, else the code
+/// units of the array its `newString` export returns or of its string types,
+/// UTF-16 if any has them, and for modules without either a memory exported
+/// with [`CANONICAL_REALLOC`], or else one of [`LINEAR_ALLOCATORS`]
+pub fn detect_encoding(binary: &[u8]) -> StringEncoding {
+    let (type_names, _) = arrays::type_names(binary);
+    let mut types = Vec::new();
+    let mut functions = Vec::new();
+    let mut exports = Vec::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(payload) = payload else {
+            return StringEncoding::default();
+        };
+        match payload {
+            Payload::TypeSection(reader) => {
+                for group in reader.into_iter().flatten() {
+                    for ty in group.types() {
+                        types.push(match &ty.composite_type.inner {
+                            CompositeInnerType::Array(array) => {
+                                Type::Array(match array.0.element_type {
+                                    StorageType::I8 => Some(8),
+                                    StorageType::I16 => Some(16),
+                                    StorageType::Val(_) => None,
+                                })
+                            },
+                            CompositeInnerType::Func(func) => Type::Func(match func.results() {
+                                [ValType::Ref(ty)] => match ty.heap_type() {
+                                    HeapType::Concrete(index) => index.as_module_index(),
+                                    HeapType::Abstract { .. } => None,
+                                },
+                                _ => None,
+                            }),
+                            _ => Type::Other,
+                        });
+                    }
+                }
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader.into_iter().flatten() {
+                    if import.module == JS_STRING_MODULE {
+                        return StringEncoding::JsString;
+                    }
+                    if let TypeRef::Func(ty) = import.ty {
+                        functions.push(ty);
+                    }
+                }
+            },
+            Payload::FunctionSection(reader) => functions.extend(reader.into_iter().flatten()),
+            Payload::ExportSection(reader) => exports.extend(
+                reader
+                    .into_iter()
+                    .flatten()
+                    .map(|export| (export.name.to_string(), export.kind, export.index)),
+            ),
+            _ => {},
+        }
+    }
+
+    let unit = |index: u32| match types.get(index as usize) {
+        Some(Type::Array(unit)) => *unit,
+        _ => None,
+    };
+    let constructed = exports
+        .iter()
+        .find(|(name, kind, _)| name == "newString" && *kind == ExternalKind::Func)
+        .and_then(
+            |(_, _, index)| match types.get(*functions.get(*index as usize)? as usize)? {
+                Type::Func(result) => unit((*result)?),
+                _ => None,
+            },
+        );
+    let named = type_names
+        .iter()
+        .filter(|(_, name)| *name == TYPE_NAME)
+        .filter_map(|(index, _)| unit(*index))
+        .max();
+    match constructed.or(named) {
+        Some(16) => StringEncoding::Utf16,
+        Some(_) => StringEncoding::Utf8,
+        None => {
+            let exported = |wanted: ExternalKind, names: &[&str]| {
+                exports.iter().any(|(name, kind, _)| {
+                    *kind == wanted && (names.is_empty() || names.contains(&name.as_str()))
+                })
+            };
+            if !exported(ExternalKind::Memory, &[]) {
+                StringEncoding::default()
+            } else if exported(ExternalKind::Func, &[CANONICAL_REALLOC]) {
+                StringEncoding::Canonical
+            } else if exported(ExternalKind::Func, &LINEAR_ALLOCATORS) {
+                StringEncoding::Linear
+            } else {
+                StringEncoding::default()
+            }
+        },
+    }
+}
+
+/// Add and export accessors for the string types of a module, see the module
+/// documentation
+pub fn add_accessors(binary: &mut Vec<u8>) -> Result<Vec<StringType>, reencode::Error> {
+    let (type_names, _) = arrays::type_names(binary);
+    let mut types = Vec::new();
+    let mut has_memory = false;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::TypeSection(reader) => types.push(reader),
+            Payload::MemorySection(reader) => has_memory |= reader.count() > 0,
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    has_memory |= matches!(import?.ty, TypeRef::Memory(_));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let mut strings = Vec::new();
+    let mut index = 0;
+    for reader in types {
+        for group in reader {
+            for ty in group?.types() {
+                let unit = match &ty.composite_type.inner {
+                    CompositeInnerType::Array(array) => match array.0.element_type {
+                        StorageType::I8 => Some((8, array.0.mutable)),
+                        StorageType::I16 => Some((16, array.0.mutable)),
+                        StorageType::Val(_) => None,
+                    },
+                    _ => None,
+                };
+                let named = type_names.get(&index).is_some_and(|name| name == TYPE_NAME);
+                if let Some((unit, mutable)) = unit.filter(|_| named) {
+                    let export =
+                        |accessor: &str| format!("{}{}_{}", EXPORT_PREFIX, accessor, index);
+                    strings.push(StringType {
+                        array: index,
+                        unit,
+                        get: export("get"),
+                        len: export("len"),
+                        new: mutable.then(|| export("new")),
+                        set: mutable.then(|| export("set")),
+                        to_memory: (!has_memory).then(|| export("to_memory")),
+                        from_memory: (mutable && !has_memory).then(|| export("from_memory")),
+                    });
+                }
+                index += 1;
+            }
+        }
+    }
+
+    let mut added = Vec::new();
+    for string in &strings {
+        let array_type = wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+            nullable: true,
+            heap_type: wasm_encoder::HeapType::Concrete(string.array),
+        });
+        added.push(Accessor {
+            export: string.get.clone(),
+            params: vec![array_type, wasm_encoder::ValType::I32],
+            results: vec![wasm_encoder::ValType::I32],
+            body: vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::ArrayGetU(string.array),
+            ],
+        });
+        added.push(Accessor {
+            export: string.len.clone(),
+            params: vec![array_type],
+            results: vec![wasm_encoder::ValType::I32],
+            body: vec![Instruction::LocalGet(0), Instruction::ArrayLen],
+        });
+        if let (Some(new), Some(set)) = (&string.new, &string.set) {
+            added.push(Accessor {
+                export: new.clone(),
+                params: vec![wasm_encoder::ValType::I32],
+                results: vec![wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+                    nullable: false,
+                    heap_type: wasm_encoder::HeapType::Concrete(string.array),
+                })],
+                body: vec![
+                    Instruction::LocalGet(0),
+                    Instruction::ArrayNewDefault(string.array),
+                ],
+            });
+            added.push(Accessor {
+                export: set.clone(),
+                params: vec![
+                    array_type,
+                    wasm_encoder::ValType::I32,
+                    wasm_encoder::ValType::I32,
+                ],
+                results: vec![],
+                body: vec![
+                    Instruction::LocalGet(0),
+                    Instruction::LocalGet(1),
+                    Instruction::LocalGet(2),
+                    Instruction::ArraySet(string.array),
+                ],
+            });
+        }
+        let copy_params = vec![
+            array_type,
+            wasm_encoder::ValType::I32,
+            wasm_encoder::ValType::I32,
+        ];
+        if let Some(to_memory) = &string.to_memory {
+            let mut access = address(string.unit);
+            access.extend([
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(2),
+                Instruction::ArrayGetU(string.array),
+                match string.unit {
+                    16 => Instruction::I32Store16(scratch(1)),
+                    _ => Instruction::I32Store8(scratch(0)),
+                },
+            ]);
+            added.push(Accessor {
+                export: to_memory.clone(),
+                params: copy_params.clone(),
+                results: vec![],
+                body: copy_loop(access),
+            });
+        }
+        if let Some(from_memory) = &string.from_memory {
+            let mut access = vec![Instruction::LocalGet(0), Instruction::LocalGet(2)];
+            access.extend(address(string.unit));
+            access.extend([
+                match string.unit {
+                    16 => Instruction::I32Load16U(scratch(1)),
+                    _ => Instruction::I32Load8U(scratch(0)),
+                },
+                Instruction::ArraySet(string.array),
+            ]);
+            added.push(Accessor {
+                export: from_memory.clone(),
+                params: copy_params,
+                results: vec![],
+                body: copy_loop(access),
+            });
+        }
+    }
+    accessors::add(binary, &added)?;
+    if strings.iter().any(|string| string.to_memory.is_some()) {
+        let mut module = Module::new();
+        let mut scratch = StringMemory {
+            memory_added: false,
+            export_added: false,
+        };
+        scratch.parse_core_module(&mut module, Parser::new(0), binary)?;
+        *binary = module.finish();
+    }
+    Ok(strings)
+}
+
+/// Access to the scratch memory, aligned to code units of `2^align` bytes
+fn scratch(align: u32) -> MemArg {
+    MemArg {
+        offset: 0,
+        align,
+        memory_index: 0,
+    }
+}
+
+/// Address in the scratch memory of the code unit with index `$count`, the
+/// third parameter, of a copy starting at `$ptr`, the second
+fn address(unit: u32) -> Vec<Instruction<'static>> {
+    let mut address = vec![Instruction::LocalGet(1), Instruction::LocalGet(2)];
+    if unit == 16 {
+        address.extend([Instruction::I32Const(1), Instruction::I32Shl]);
+    }
+    address.push(Instruction::I32Add);
+    address
+}
+
+/// Body running `access` for each code unit of a copy, with `$count` counted
+/// down to its index
+fn copy_loop(access: Vec<Instruction<'static>>) -> Vec<Instruction<'static>> {
+    let mut body = vec![
+        Instruction::Block(BlockType::Empty),
+        Instruction::Loop(BlockType::Empty),
+        Instruction::LocalGet(2),
+        Instruction::I32Eqz,
+        Instruction::BrIf(1),
+        Instruction::LocalGet(2),
+        Instruction::I32Const(1),
+        Instruction::I32Sub,
+        Instruction::LocalSet(2),
+    ];
+    body.extend(access);
+    body.extend([Instruction::Br(0), Instruction::End, Instruction::End]);
+    body
+}
+
+/// Export memory 0 of a module for linear-memory strings, unless it is
+/// exported already
+/// Returns whether it was.
+pub fn export_memory(binary: &mut Vec<u8>) -> Result<bool, reencode::Error> {
+    let mut has_memory = false;
+    let mut exported = false;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload? {
+            Payload::MemorySection(reader) => has_memory |= reader.count() > 0,
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    has_memory |= matches!(import?.ty, TypeRef::Memory(_));
+                }
+            },
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    exported |= export.kind == ExternalKind::Memory && export.index == 0;
+                }
+            },
+            _ => {},
+        }
+    }
+    if !has_memory || exported {
+        return Ok(false);
+    }
+
+    let mut module = Module::new();
+    let mut memory = StringMemory {
+        memory_added: true,
+        export_added: false,
+    };
+    memory.parse_core_module(&mut module, Parser::new(0), binary)?;
+    *binary = module.finish();
+    Ok(true)
+}
+
+/// Adds the scratch memory to a module without a memory, unless
+/// `memory_added`, and exports memory 0
+struct StringMemory {
+    memory_added: bool,
+    export_added: bool,
+}
+
+impl StringMemory {
+    fn add_memory(&mut self, module: &mut Module) {
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        module.section(&memories);
+        self.memory_added = true;
+    }
+
+    fn add_export(&mut self, exports: &mut ExportSection) {
+        exports.export(MEMORY_EXPORT, ExportKind::Memory, 0);
+        self.export_added = true;
+    }
+}
+
+impl Reencode for StringMemory {
+    type Error = Infallible;
+
+    fn parse_export_section(
+        &mut self,
+        exports: &mut ExportSection,
+        section: ExportSectionReader<'_>,
+    ) -> Result<(), reencode::Error> {
+        reencode::utils::parse_export_section(self, exports, section)?;
+        self.add_export(exports);
+        Ok(())
+    }
+
+    fn intersperse_section_hook(
+        &mut self,
+        module: &mut Module,
+        _after: Option<SectionId>,
+        before: Option<SectionId>,
+    ) -> Result<(), reencode::Error> {
+        let before = accessors::section_order(before);
+        if !self.memory_added && before > accessors::section_order(Some(SectionId::Memory)) {
+            self.add_memory(module);
+        }
+        if !self.export_added && before > accessors::section_order(Some(SectionId::Export)) {
+            let mut exports = ExportSection::new();
+            self.add_export(&mut exports);
+            module.section(&exports);
+        }
+        Ok(())
+    }
+}
+
+/// The string types as the loader reads them: the type index, code unit size
+/// and accessors of each, null for those it has none of
+pub fn loader_json(strings: &[StringType]) -> String {
+    serde_json::Value::Array(
+        strings
+            .iter()
+            .map(|string| {
+                serde_json::json!({
+                    "array": string.array,
+                    "unit": string.unit,
+                    "get": string.get,
+                    "len": string.len,
+                    "new": string.new,
+                    "set": string.set,
+                    "toMemory": string.to_memory,
+                    "fromMemory": string.from_memory,
+                })
+            })
+            .collect(),
+    )
+    .to_string()
+}
+
+/// JavaScript of the [`JS_STRING_MODULE`] builtins, imported by engines
+/// without them
+pub fn builtins_js() -> String {
+    format!(
+        r#"{{
+            '{module}': (function() {{
+                const string = function(value) {{
+                    if (typeof value !== 'string') {{
+                        throw new WebAssembly.RuntimeError('not a string');
+                    }}
+                    return value;
+                }};
+                const index = function(value, i) {{
+                    if ((i >>> 0) >= string(value).length) {{
+                        throw new WebAssembly.RuntimeError('string index out of bounds');
+                    }}
+                    return i >>> 0;
+                }};
+                return {{
+                    cast: string,
+                    test: value => typeof value === 'string' ? 1 : 0,
+                    fromCharCode: code => String.fromCharCode(code & 0xffff),
+                    fromCodePoint: code => String.fromCodePoint(code >>> 0),
+                    charCodeAt: (value, i) => value.charCodeAt(index(value, i)),
+                    codePointAt: (value, i) => value.codePointAt(index(value, i)),
+                    length: value => string(value).length,
+                    concat: (a, b) => string(a) + string(b),
+                    substring: function(value, start, end) {{
+                        start >>>= 0;
+                        end >>>= 0;
+                        return start > string(value).length || start > end ? '' : value.substring(start, end);
+                    }},
+                    equals: function(a, b) {{
+                        // Either may be null, which is equal to itself only
+                        for (const value of [a, b]) {{
+                            if (value !== null) {{
+                                string(value);
+                            }}
+                        }}
+                        return a === b ? 1 : 0;
+                    }},
+                    compare: (a, b) => string(a) < string(b) ? -1 : (a > b ? 1 : 0)
+                }};
+            }})()
+        }}"#,
+        module = JS_STRING_MODULE,
+    )
+}
+
+/// Whether the loader converts strings for a module: those of its string
+/// types, or of an encoding other than the default
+pub fn converts(string_types: &[StringType], encoding: StringEncoding) -> bool {
+    !string_types.is_empty() || encoding != StringEncoding::default()
+}
+
+/// JavaScript registering the string converters of a module and installing
+/// `WasmStringToJs` and `WasmStringFromJs` on `window`, for modules the
+/// loader converts strings for, see [`converts`]; `installs` tells the
+/// helpers the module may install, see [`super::exports::installs_helper`]
+pub fn page_helpers_js(installs: impl Fn(&str) -> bool) -> String {
+    let mut js = String::new();
+    if !installs("WasmStringToJs") && !installs("WasmStringFromJs") {
+        return js;
+    }
+    js.push_str(
+        r#"
+
+                    // String conversion helpers, for strings passed as plain values. Each
+                    // module converts the strings of its own encoding: strings go to the
+                    // most recently loaded module that can hold them, or the one named
+                    window.__wasmStrings = window.__wasmStrings || {};
+                    delete window.__wasmStrings[wasmFilename];
+                    window.__wasmStrings[wasmFilename] = {
+                        encoding: stringEncoding,
+                        toJs: wasmStringToJs,
+                        fromJs: jsStringToWasm,
+                        owns: function(value) {
+                            if (stringEncoding === 'js-string') {
+                                return typeof value === 'string';
+                            }
+                            if (stringEncoding === 'linear' || stringEncoding === 'canonical') {
+                                return typeof value === 'number' && linearMemory instanceof WebAssembly.Memory;
+                            }
+                            if (!value || typeof value !== 'object') {
+                                return false;
+                            }
+                            if (stringTypeOf(value)) {
+                                return true;
+                            }
+                            try {
+                                result.instance.exports.string_len(value);
+                                return true;
+                            } catch (e) {
+                                return false;
+                            }
+                        }
+                    };"#,
+    );
+    if installs("WasmStringToJs") {
+        js.push_str(
+            r#"
+                    window.WasmStringToJs = window.WasmStringToJs || function(value, maxLength, filename) {
+                        const modules = window.__wasmStrings;
+                        const names = filename === undefined ? Object.keys(modules).reverse() : [filename];
+                        const module = modules[names.find(name => modules[name] && modules[name].owns(value))];
+                        return module ? module.toJs(value, maxLength) : null;
+                    };"#,
+        );
+    }
+    if installs("WasmStringFromJs") {
+        js.push_str(
+            r#"
+                    window.WasmStringFromJs = window.WasmStringFromJs || function(jsStr, type, filename) {
+                        const modules = window.__wasmStrings;
+                        const module = modules[filename === undefined ? Object.keys(modules).pop() : filename];
+                        return module ? module.fromJs(jsStr, type) : jsStr;
+                    };"#,
+        );
+    }
+    js
+}
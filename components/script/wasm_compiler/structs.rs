@@ -0,0 +1,541 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Field access for structs
+//!
+//! JavaScript cannot read the fields of a GC struct. Every field of every
+//! struct type gets an exported getter, and every mutable field a setter:
+//!
+//! ```text
+//! (func (export "__wasm_struct_get_1_0") (param (ref null 1)) (result i32))
+//! (func (export "__wasm_struct_set_1_0") (param (ref null 1)) (param i32))
+//! ```
+//!
+//! Types and fields are taken by index from the type section, so modules
+//! referring to them by index (`struct.get 0 1`), as generated code often
+//! does, work like those naming them. The loader tells the type of a struct
+//! by the getters that accept it and reads its fields by position or by the
+//! names of [`super::names`]. Packed fields read zero-extended; fields
+//! JavaScript cannot hold (`v128`, `exnref`) get no accessors.
+
+use std::collections::HashSet;
+
+use wasm_encoder::Instruction;
+use wasm_encoder::reencode;
+use wasmparser::{
+    AbstractHeapType, CompositeInnerType, FieldType, HeapType, Parser, Payload, StorageType,
+    ValType,
+};
+
+use super::accessors::{self, Accessor};
+use super::names;
+use super::strings::StringType;
+
+/// Prefix of the added exports, which the loader does not install
+pub const EXPORT_PREFIX: &str = "__wasm_struct_";
+
+fn getter(ty: u32, field: usize) -> String {
+    format!("{}get_{}_{}", EXPORT_PREFIX, ty, field)
+}
+
+fn setter(ty: u32, field: usize) -> String {
+    format!("{}set_{}_{}", EXPORT_PREFIX, ty, field)
+}
+
+/// Whether JavaScript can hold values of a field
+fn accessible(field: &FieldType) -> bool {
+    match field.element_type {
+        StorageType::I8 | StorageType::I16 => true,
+        StorageType::Val(ValType::V128) => false,
+        StorageType::Val(ValType::Ref(ty)) => !matches!(
+            ty.heap_type(),
+            HeapType::Abstract {
+                ty: AbstractHeapType::Exn | AbstractHeapType::NoExn,
+                ..
+            }
+        ),
+        StorageType::Val(_) => true,
+    }
+}
+
+/// Add and export accessors for the fields of the struct types of a module,
+/// see the module documentation
+/// Returns the number of accessors added.
+pub fn add_accessors(binary: &mut Vec<u8>) -> Result<usize, reencode::Error> {
+    let mut added = Vec::new();
+    let mut index = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        let Payload::TypeSection(reader) = payload? else {
+            continue;
+        };
+        for group in reader {
+            for ty in group?.types() {
+                if let CompositeInnerType::Struct(struct_type) = &ty.composite_type.inner {
+                    for (position, field) in struct_type.fields.iter().enumerate() {
+                        if accessible(field) {
+                            add_field(&mut added, index, position, field)?;
+                        }
+                    }
+                }
+                index += 1;
+            }
+        }
+    }
+    accessors::add(binary, &added)?;
+    Ok(added.len())
+}
+
+fn add_field(
+    added: &mut Vec<Accessor>,
+    ty: u32,
+    position: usize,
+    field: &FieldType,
+) -> Result<(), reencode::Error> {
+    let struct_type = wasm_encoder::ValType::Ref(wasm_encoder::RefType {
+        nullable: true,
+        heap_type: wasm_encoder::HeapType::Concrete(ty),
+    });
+    let (value, get) = match field.element_type {
+        StorageType::Val(value) => (
+            value.try_into()?,
+            Instruction::StructGet {
+                struct_type_index: ty,
+                field_index: position as u32,
+            },
+        ),
+        StorageType::I8 | StorageType::I16 => (
+            wasm_encoder::ValType::I32,
+            Instruction::StructGetU {
+                struct_type_index: ty,
+                field_index: position as u32,
+            },
+        ),
+    };
+    added.push(Accessor {
+        export: getter(ty, position),
+        params: vec![struct_type],
+        results: vec![value],
+        body: vec![Instruction::LocalGet(0), get],
+    });
+    if field.mutable {
+        added.push(Accessor {
+            export: setter(ty, position),
+            params: vec![struct_type, value],
+            results: vec![],
+            body: vec![
+                Instruction::LocalGet(0),
+                Instruction::LocalGet(1),
+                Instruction::StructSet {
+                    struct_type_index: ty,
+                    field_index: position as u32,
+                },
+            ],
+        });
+    }
+    Ok(())
+}
+
+/// The struct types as the loader reads them: their names, see
+/// [`names::struct_info`], and the accessors of each field, null for those
+/// without one
+pub fn loader_json(binary: &[u8]) -> String {
+    let mut exports = HashSet::new();
+    for payload in Parser::new(0).parse_all(binary) {
+        if let Ok(Payload::ExportSection(reader)) = payload {
+            exports.extend(
+                reader
+                    .into_iter()
+                    .flatten()
+                    .filter(|export| export.name.starts_with(EXPORT_PREFIX))
+                    .map(|export| export.name.to_string()),
+            );
+        }
+    }
+    let present = |name: String| exports.contains(&name).then_some(name);
+
+    serde_json::Value::Array(
+        names::struct_info(binary)
+            .into_iter()
+            .filter_map(|(ty, names)| {
+                let get: Vec<Option<String>> = (0..names.fields.len())
+                    .map(|position| present(getter(ty, position)))
+                    .collect();
+                let set: Vec<Option<String>> = (0..names.fields.len())
+                    .map(|position| present(setter(ty, position)))
+                    .collect();
+                get.iter().any(Option::is_some).then(|| {
+                    serde_json::json!({
+                        "type": ty,
+                        "typeName": names.type_name,
+                        "fields": names.fields,
+                        "get": get,
+                        "set": set,
+                    })
+                })
+            })
+            .collect(),
+    )
+    .to_string()
+}
+
+/// The string types the fields of each struct type hold, by type index, for
+/// the loader to encode the strings it sets; types without string fields are
+/// left out
+pub fn string_fields_json(binary: &[u8], string_types: &[StringType]) -> String {
+    let mut fields = serde_json::Map::new();
+    let mut index = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        let Ok(Payload::TypeSection(reader)) = payload else {
+            continue;
+        };
+        for ty in reader
+            .into_iter()
+            .flatten()
+            .flat_map(|group| group.into_types())
+        {
+            if let CompositeInnerType::Struct(struct_type) = &ty.composite_type.inner {
+                let strings: Vec<Option<u32>> = struct_type
+                    .fields
+                    .iter()
+                    .map(|field| match field.element_type {
+                        StorageType::Val(ValType::Ref(ty)) => match ty.heap_type() {
+                            HeapType::Concrete(array) => array.as_module_index(),
+                            HeapType::Abstract { .. } => None,
+                        },
+                        _ => None,
+                    })
+                    .map(|array| {
+                        array.filter(|array| {
+                            string_types.iter().any(|string| string.array == *array)
+                        })
+                    })
+                    .collect();
+                if strings.iter().any(Option::is_some) {
+                    fields.insert(index.to_string(), serde_json::json!(strings));
+                }
+            }
+            index += 1;
+        }
+    }
+    serde_json::Value::Object(fields).to_string()
+}
+
+/// Whether `binary` declares struct types, whose fields the loader reads
+/// and sets
+pub fn declares_structs(binary: &[u8]) -> bool {
+    !names::struct_info(binary).is_empty()
+}
+
+/// JavaScript of the field access of the structs of a module, installing
+/// `WasmGcStructGet` and `WasmGcStructSet`, the `wasmWatch` watchpoints and
+/// the `wasmSetFields` batched updates on `window`, for modules that declare
+/// struct types, see [`declares_structs`]; `installs` tells the helpers the
+/// module may install, see [`super::exports::installs_helper`]
+pub fn page_helpers_js(installs: impl Fn(&str) -> bool) -> String {
+    let mut js = String::from(
+        r#"
+
+                    // Create GC struct field accessors
+                    // For WASM GC structs, we need getter functions that call struct.get
+                    // These are typically exported as 'get_field_X' functions by WASM
+                    structFieldGet = function(structObj, fieldIndex) {
+                        // Attempt to extract field value from GC struct
+                        // Look for exported getter functions following common patterns
+                        const getterName = 'get_' + fieldIndex;
+                        if (result.instance.exports[getterName]) {
+                            try {
+                                const value = result.instance.exports[getterName](structObj);
+                                // Try to convert to JS string if it's a WASM string array
+                                if (value && typeof value === 'object') {
+                                    const jsStr = wasmStringToJs(value);
+                                    if (jsStr !== null) {
+                                        return jsStr;
+                                    }
+                                }
+                                // Not a string array - wrap as GC object
+                                return wrapGcObject(value);
+                            } catch (e) {
+                                console.warn('WasmGcStructGet: Getter', getterName, 'failed:', e);
+                            }
+                        }
+
+                        // Fallback: try numeric field access patterns
+                        const fieldGetter = 'struct_get_' + fieldIndex;
+                        if (result.instance.exports[fieldGetter]) {
+                            try {
+                                const value = result.instance.exports[fieldGetter](structObj);
+                                // Try to convert to JS string if it's a WASM string array
+                                if (value && typeof value === 'object') {
+                                    const jsStr = wasmStringToJs(value);
+                                    if (jsStr !== null) {
+                                        return jsStr;
+                                    }
+                                }
+                                // Not a string array - wrap as GC object
+                                return wrapGcObject(value);
+                            } catch (e) {
+                                console.warn('WasmGcStructGet: Getter', fieldGetter, 'failed:', e);
+                            }
+                        }
+
+                        // Try property access as last resort (for externref wrapping)
+                        if (structObj && typeof structObj === 'object') {
+                            if (structObj[fieldIndex] !== undefined) {
+                                return structObj[fieldIndex];
+                            }
+                            const fieldName = 'field' + fieldIndex;
+                            if (structObj[fieldName] !== undefined) {
+                                return structObj[fieldName];
+                            }
+                        }
+
+                        console.warn('WasmGcStructGet: Unable to access field', fieldIndex, 'on', structObj);
+                        return undefined;
+                    };
+
+                    // Setter function for WASM GC struct fields
+                    const setGcStructField = function(structObj, fieldIndex, value) {
+                        // Look for exported setter functions following common patterns
+                        const setterName = 'set_' + fieldIndex;
+                        if (result.instance.exports[setterName]) {
+                            try {
+                                return result.instance.exports[setterName](structObj, value);
+                            } catch (e) {
+                                console.warn('WasmGcStructSet: Setter', setterName, 'failed:', e);
+                            }
+                        }
+
+                        // Fallback: try numeric field access patterns
+                        const fieldSetter = 'struct_set_' + fieldIndex;
+                        if (result.instance.exports[fieldSetter]) {
+                            try {
+                                return result.instance.exports[fieldSetter](structObj, value);
+                            } catch (e) {
+                                console.warn('WasmGcStructSet: Setter', fieldSetter, 'failed:', e);
+                            }
+                        }
+
+                        console.warn('WasmGcStructSet: Unable to set field', fieldIndex, 'on', structObj);
+                        return undefined;
+                    };
+
+                    // Sets notify the watchers of the field, registered by the wasmWatch
+                    // of whichever module installed it
+                    const unwrapGcObject = function(obj) {
+                        return obj && obj.__wasmGcWrapped ? obj.__wasmGcTarget : obj;
+                    };
+                    structFieldSet = function(structObj, fieldIndex, value) {
+                        const target = unwrapGcObject(structObj);
+                        const fields = target && typeof target === 'object' && window.__wasmWatchers
+                            ? window.__wasmWatchers.get(target)
+                            : undefined;
+                        const watchers = fields ? fields.get(String(fieldIndex)) : undefined;
+                        if (!watchers || watchers.size === 0) {
+                            return setGcStructField(target, fieldIndex, value);
+                        }
+                        const oldValue = structFieldGet(target, fieldIndex);
+                        const result = setGcStructField(target, fieldIndex, value);
+                        const newValue = structFieldGet(target, fieldIndex);
+                        if (newValue !== oldValue) {
+                            for (const callback of Array.from(watchers)) {
+                                try {
+                                    callback(newValue, oldValue, String(fieldIndex));
+                                } catch (e) {
+                                    console.error('wasmWatch: Callback error:', e);
+                                }
+                            }
+                        }
+                        return result;
+                    };
+
+                    console.log('WASM: GC struct accessors installed');"#,
+    );
+    if installs("_wasmExports") {
+        js.push_str(
+            r#"
+
+                    // Store all exports in _wasmExports for getter/setter functions
+                    window._wasmExports = result.instance.exports;"#,
+        );
+    }
+    if installs("WasmGcStructDisplay") {
+        js.push_str(
+            r#"
+
+                    // Helper function to display GC struct contents
+                    window.WasmGcStructDisplay = function(structObj, structName) {
+                        if (!structObj || typeof structObj !== 'object') {
+                            return String(structObj);
+                        }
+
+                        structName = structName || 'box';
+                        let fields = [];
+
+                        // Try common field names
+                        const commonFields = ['val', 'value', 'data', 'x', 'y', 'z', 'width', 'height'];
+                        for (const fieldName of commonFields) {
+                            try {
+                                const fieldValue = structFieldGet(structObj, fieldName);
+                                if (fieldValue !== undefined) {
+                                    fields.push(fieldName + '=' + fieldValue);
+                                }
+                            } catch (e) {
+                                // Field doesn't exist, skip
+                            }
+                        }
+
+                        if (fields.length > 0) {
+                            return structName + '{' + fields.join(', ') + '}';
+                        } else {
+                            return structName + '{}';
+                        }
+                    };"#,
+        );
+    }
+    if installs("WasmGcStructGet") {
+        js.push_str(
+            r#"
+
+                    window.WasmGcStructGet = structFieldGet;"#,
+        );
+    }
+    if installs("WasmGcStructSet") {
+        js.push_str(
+            r#"
+
+                    window.WasmGcStructSet = structFieldSet;"#,
+        );
+    }
+    if installs("wasmWatch") {
+        js.push_str(
+            r#"
+
+                    // Field watchpoints: wasmWatch(ref, 'hp', callback) calls
+                    // callback(newValue, oldValue, field) after each change made through
+                    // the wrapper or WasmGcStructSet (not by struct.set inside the
+                    // module), and returns a function removing the watch
+                    window.__wasmWatchers = window.__wasmWatchers || new WeakMap();
+                    window.wasmWatch = window.wasmWatch || function(ref, field, callback) {
+                        const target = unwrapGcObject(ref);
+                        if (!target || typeof target !== 'object' || typeof callback !== 'function') {
+                            throw new TypeError('wasmWatch: expected a GC reference, a field name and a callback');
+                        }
+                        let fields = window.__wasmWatchers.get(target);
+                        if (!fields) {
+                            fields = new Map();
+                            window.__wasmWatchers.set(target, fields);
+                        }
+                        const key = String(field);
+                        if (!fields.has(key)) {
+                            fields.set(key, new Set());
+                        }
+                        fields.get(key).add(callback);
+                        return function() {
+                            fields.get(key).delete(callback);
+                        };
+                    };"#,
+        );
+    }
+    if installs("wasmSetFields") {
+        js.push_str(
+            r#"
+
+                    // Batched field updates: wasmSetFields(ref, { x: 1, name: 'bob' })
+                    // resolves the struct type and its fields once, then calls the
+                    // setters in turn, strings encoded as the string type of their
+                    // field; none is set if a field is unknown or immutable. Watchers
+                    // are called as for single sets. Each module sets the structs of
+                    // its own types, the most recently loaded first
+                    const setStructFields = function(target, values) {
+                        const structType = structTypeOf(target);
+                        if (!structType) {
+                            return false;
+                        }
+                        const strings = structStrings[structType.type] || [];
+                        const updates = Object.keys(values).map(function(field) {
+                            const position = structFieldPosition(structType, field);
+                            const setter = position >= 0 ? structType.set[position] : null;
+                            if (!setter) {
+                                throw new TypeError('wasmSetFields: ' + structType.typeName +
+                                    ' has no mutable field ' + field);
+                            }
+                            return [field, position, result.instance.exports[setter]];
+                        });
+                        const fields = window.__wasmWatchers ? window.__wasmWatchers.get(target) : undefined;
+                        const wrapped = wrapGcObject(target, structType);
+                        for (const [field, position, setter] of updates) {
+                            const value = values[field];
+                            const wasmValue = typeof value === 'string'
+                                ? jsStringToWasm(value, strings[position] === null ? undefined : strings[position])
+                                : unwrapGcObject(value);
+                            const watchers = fields ? fields.get(field) : undefined;
+                            if (!watchers || watchers.size === 0) {
+                                setter(target, wasmValue);
+                                continue;
+                            }
+                            const oldValue = wrapped[field];
+                            setter(target, wasmValue);
+                            const newValue = wrapped[field];
+                            if (newValue === oldValue) {
+                                continue;
+                            }
+                            for (const callback of Array.from(watchers)) {
+                                try {
+                                    callback(newValue, oldValue, field);
+                                } catch (e) {
+                                    console.error('wasmWatch: Callback error:', e);
+                                }
+                            }
+                        }
+                        return true;
+                    };
+                    window.__wasmStructSetters = window.__wasmStructSetters || {};
+                    delete window.__wasmStructSetters[wasmFilename];
+                    window.__wasmStructSetters[wasmFilename] = setStructFields;
+                    window.wasmSetFields = window.wasmSetFields || function(ref, values) {
+                        const target = unwrapGcObject(ref);
+                        if (!target || typeof target !== 'object' || !values || typeof values !== 'object') {
+                            throw new TypeError('wasmSetFields: expected a GC reference and an object of fields');
+                        }
+                        const modules = window.__wasmStructSetters;
+                        for (const name of Object.keys(modules).reverse()) {
+                            if (modules[name](target, values)) {
+                                return ref;
+                            }
+                        }
+                        throw new TypeError('wasmSetFields: not a struct of a loaded module');
+                    };"#,
+        );
+    }
+    if installs("WasmListGetters") {
+        js.push_str(
+            r#"
+
+                    // Helper to list available getter functions
+                    window.WasmListGetters = function() {
+                        const getters = [];
+                        for (const name in result.instance.exports) {
+                            if (name.startsWith('get_') || name.startsWith('struct_get_')) {
+                                getters.push(name);
+                            }
+                        }
+                        return getters;
+                    };
+                    console.log('WASM: Available getters:', window.WasmListGetters());"#,
+        );
+    }
+    if installs("__wasmFieldNames") {
+        js.push_str(
+            r#"
+
+                    // Install field name mappings
+                    window.__wasmFieldNames = structFieldNames;
+                    console.log('WASM: Field names installed:', window.__wasmFieldNames);"#,
+        );
+    }
+    js
+}
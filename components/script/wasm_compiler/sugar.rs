@@ -0,0 +1,1374 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Optional infix expression sugar for WAT
+//!
+//! Modules opting in with a `(@sugar)` annotation may write function bodies
+//! with infix arithmetic, `let` bindings, assignments and braced `if`/`else`
+//! and `while` blocks. Sugar lines are lowered to folded WAT expressions
+//! before the module reaches the `wat` parser; all other lines pass through
+//! untouched, so sugar and plain WAT can be mixed freely:
+//!
+//! ```text
+//! (module (@sugar)
+//!   (func $area (export "area") (param $w f64) (param $h f64) (result f64)
+//!     let $a = $w * $h
+//!     if $a > 100 {
+//!       return 100
+//!     }
+//!     $a
+//!   )
+//! )
+//! ```
+//!
+//! Arithmetic whose operands are all known when the module is compiled is
+//! computed then: numeric literals, immutable globals initialized with a
+//! constant, and `len("...")`, the length in bytes of a string literal. With
+//! `(global $WIDTH i32 (i32.const 320))`, `$WIDTH * 4 + len("px")` lowers to
+//! `(i32.const 1282)`. Operations that would trap, like an integer division
+//! by zero, are left for the module to run.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::CompileError;
+use super::interpolation::decoded_len;
+use super::wat_text::{Sexpr, is_header_line, paren_delta, parse_sexpr};
+
+/// Annotation marking a module as using the expression sugar
+/// (unknown annotations are ignored by the `wat` parser, so it can stay in the output)
+const SUGAR_ANNOTATION: &str = "(@sugar)";
+
+/// Check whether a WAT source opted into the expression sugar
+pub fn is_enabled(source: &str) -> bool {
+    source.contains(SUGAR_ANNOTATION)
+}
+
+/// Lower all sugar lines of a module to standard WAT
+pub fn desugar(source: &str) -> Result<String, CompileError> {
+    let module = ModuleInfo::collect(source);
+    let mut result = String::with_capacity(source.len());
+    let mut depth = 0;
+    let mut func: Option<FuncBody> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        let error = |msg: String| CompileError::SugarError(format!("line {}: {}", index + 1, msg));
+
+        match func.as_mut() {
+            Some(body) if is_sugar_line(trimmed) => {
+                body.lower_line(line, &module).map_err(error)?
+            },
+            Some(body) => body.push_raw(line),
+            None if depth == 1 && trimmed.starts_with("(func") => {
+                let mut body = FuncBody::default();
+                body.push_raw(line);
+                func = Some(body);
+            },
+            None => {
+                result.push_str(line);
+                result.push('\n');
+            },
+        }
+
+        depth += paren_delta(line);
+
+        // The function ended once we are back at module level
+        if depth <= 1
+            && let Some(body) = func.take()
+        {
+            result.push_str(&body.finish().map_err(error)?);
+        }
+    }
+
+    if let Some(body) = func {
+        result.push_str(&body.finish().map_err(CompileError::SugarError)?);
+    }
+
+    Ok(result)
+}
+
+/// Value types the sugar knows how to do arithmetic on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Reference or vector types: can be passed around but not computed with
+    Other,
+    /// Result of calling a function without results
+    Void,
+}
+
+impl Ty {
+    fn parse(name: &str) -> Ty {
+        match name {
+            "i32" => Ty::I32,
+            "i64" => Ty::I64,
+            "f32" => Ty::F32,
+            "f64" => Ty::F64,
+            _ => Ty::Other,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Ty::I32 => "i32",
+            Ty::I64 => "i64",
+            Ty::F32 => "f32",
+            Ty::F64 => "f64",
+            Ty::Other => "reference",
+            Ty::Void => "nothing",
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Ty::F32 | Ty::F64)
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Ty::I32 | Ty::I64 | Ty::F32 | Ty::F64)
+    }
+}
+
+/// Value type named by a type expression (`i32`, `(ref $t)`, ...)
+fn value_type(sexpr: &Sexpr) -> Ty {
+    sexpr.atom().map_or(Ty::Other, Ty::parse)
+}
+
+/// Check whether a trimmed line inside a function body is written in sugar
+/// (plain WAT lines start with `(`, an instruction keyword or a comment)
+fn is_sugar_line(trimmed: &str) -> bool {
+    let keyword = |word: &str| {
+        trimmed
+            .strip_prefix(word)
+            .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    };
+    keyword("let")
+        || ((keyword("if") || keyword("while")) && trimmed.contains('{'))
+        || keyword("return")
+        || trimmed == "break"
+        || trimmed == "continue"
+        || trimmed.starts_with('}')
+        || trimmed.starts_with('$')
+        || trimmed.starts_with('!')
+        || trimmed.starts_with(|c: char| c.is_ascii_digit())
+        || (trimmed.starts_with('-')
+            && trimmed[1..].starts_with(|c: char| c == '$' || c.is_ascii_digit()))
+}
+
+/// Signature of a function callable from sugar expressions
+#[derive(Debug)]
+struct FuncSig {
+    params: Vec<Ty>,
+    result: Ty,
+}
+
+/// Module-wide declarations visible to every function body
+#[derive(Default)]
+struct ModuleInfo {
+    funcs: HashMap<String, FuncSig>,
+    globals: HashMap<String, Ty>,
+    /// Values of the immutable globals initialized with a constant
+    constants: HashMap<String, Value>,
+}
+
+impl ModuleInfo {
+    fn collect(source: &str) -> ModuleInfo {
+        let mut info = ModuleInfo::default();
+        let mut depth = 0;
+        let lines: Vec<&str> = source.lines().collect();
+
+        for (index, line) in lines.iter().enumerate() {
+            let trimmed = line.trim();
+            if depth == 1 {
+                if trimmed.starts_with("(func") || trimmed.starts_with("(import") {
+                    // Gather multi-line headers: (func $f\n (param ...)\n (result ...)
+                    let mut header = trimmed.to_string();
+                    for next in &lines[index + 1..] {
+                        if !is_header_line(next.trim()) {
+                            break;
+                        }
+                        header.push(' ');
+                        header.push_str(next.trim());
+                    }
+                    if let Some(func) = parse_sexpr(&header).as_ref().and_then(find_func)
+                        && let Some((name, sig)) = func_signature(func)
+                    {
+                        info.funcs.insert(name, sig);
+                    }
+                } else if trimmed.starts_with("(global")
+                    && let Some(global) = parse_sexpr(trimmed)
+                    && let Some((name, ty)) = global_type(&global)
+                {
+                    if let Some(value) = global_constant(&global, ty) {
+                        info.constants.insert(name.clone(), value);
+                    }
+                    info.globals.insert(name, ty);
+                }
+            }
+            depth += paren_delta(line);
+        }
+
+        info
+    }
+}
+
+/// Find a `(func ...)` list, either at the top or nested inside an `(import ...)`
+fn find_func(sexpr: &Sexpr) -> Option<&Sexpr> {
+    match sexpr.head() {
+        Some("func") => Some(sexpr),
+        Some("import") => sexpr
+            .items()
+            .iter()
+            .find(|item| item.head() == Some("func")),
+        _ => None,
+    }
+}
+
+fn func_signature(func: &Sexpr) -> Option<(String, FuncSig)> {
+    let name = func.items().get(1)?.atom()?.strip_prefix('$')?.to_string();
+    let mut sig = FuncSig {
+        params: Vec::new(),
+        result: Ty::Void,
+    };
+    for item in func.items() {
+        match item.head() {
+            Some("param") => sig.params.extend(declared_types(item)),
+            Some("result") => {
+                sig.result = item.items().get(1).map_or(Ty::Void, value_type);
+            },
+            _ => {},
+        }
+    }
+    Some((name, sig))
+}
+
+/// Types declared by a `(param ...)` or `(local ...)` list, named or not
+fn declared_types(decl: &Sexpr) -> Vec<Ty> {
+    let rest = &decl.items()[1..];
+    match rest.first().and_then(Sexpr::atom) {
+        Some(name) if name.starts_with('$') => {
+            vec![rest.get(1).map_or(Ty::Other, value_type)]
+        },
+        _ => rest.iter().map(value_type).collect(),
+    }
+}
+
+/// Name and value type of a `(global $g (mut f64) ...)` declaration
+fn global_type(global: &Sexpr) -> Option<(String, Ty)> {
+    let name = global
+        .items()
+        .get(1)?
+        .atom()?
+        .strip_prefix('$')?
+        .to_string();
+    let ty = global.items()[2..].iter().find_map(|item| match item {
+        Sexpr::Atom(atom) => Some(Ty::parse(atom)),
+        Sexpr::List(items) if item.head() == Some("mut") => {
+            Some(items.get(1).map_or(Ty::Other, value_type))
+        },
+        Sexpr::List(_) if matches!(item.head(), Some("export") | Some("import")) => None,
+        Sexpr::List(_) => Some(Ty::Other),
+    })?;
+    Some((name, ty))
+}
+
+/// Value of an immutable `(global $g i32 (i32.const 4))`
+fn global_constant(global: &Sexpr, ty: Ty) -> Option<Value> {
+    let items = global.items();
+    if items
+        .iter()
+        .any(|item| matches!(item.head(), Some("mut") | Some("import")))
+    {
+        return None;
+    }
+    let init = items.last()?;
+    let instr = format!("{}.const", ty.name());
+    if init.head() != Some(instr.as_str()) || init.items().len() != 2 {
+        return None;
+    }
+    Value::parse(init.items()[1].atom()?, ty)
+}
+
+/// Open braced block inside a function body
+enum Block {
+    /// `if`/`else` chain, closed by this many parentheses
+    If { closers: usize },
+    /// `while` loop with its label number
+    While { label: usize },
+}
+
+/// A function being rewritten, buffered until its end so that locals
+/// introduced by `let` can be declared ahead of the body
+#[derive(Default)]
+struct FuncBody {
+    lines: Vec<String>,
+    header: String,
+    header_done: bool,
+    locals_at: usize,
+    vars: HashMap<String, Ty>,
+    new_locals: Vec<(String, Ty)>,
+    result: Option<Ty>,
+    blocks: Vec<Block>,
+    labels: usize,
+}
+
+impl FuncBody {
+    fn push_raw(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if !self.header_done {
+            if self.lines.is_empty() || is_header_line(trimmed) {
+                self.header.push(' ');
+                self.header.push_str(trimmed);
+            } else {
+                self.end_header();
+            }
+        }
+        self.lines.push(line.to_string());
+    }
+
+    /// Record params and locals once the header is complete
+    fn end_header(&mut self) {
+        if self.header_done {
+            return;
+        }
+        self.header_done = true;
+        self.locals_at = self.lines.len();
+        let Some(func) = parse_sexpr(&self.header) else {
+            return;
+        };
+        for item in func.items() {
+            match item.head() {
+                Some("param") | Some("local") => {
+                    if let Some(name) = item.items().get(1).and_then(Sexpr::atom)
+                        && let Some(name) = name.strip_prefix('$')
+                    {
+                        let ty = item.items().get(2).map_or(Ty::Other, value_type);
+                        self.vars.insert(name.to_string(), ty);
+                    }
+                },
+                Some("result") => self.result = item.items().get(1).map(value_type),
+                _ => {},
+            }
+        }
+    }
+
+    fn lower_line(&mut self, line: &str, module: &ModuleInfo) -> Result<(), String> {
+        self.end_header();
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let mut text = line.trim();
+
+        // A trailing `)` closing the enclosing function stays plain WAT
+        let mut closing = String::new();
+        while paren_delta(text) < 0 && text.ends_with(')') {
+            text = text[..text.len() - 1].trim_end();
+            closing.push(')');
+        }
+
+        for statement in split_statements(text) {
+            // Plain WAT may appear inside braces, e.g. `if $x { call $f }`
+            let lowered = if is_sugar_line(&statement) {
+                self.lower_statement(&statement, module)?
+            } else {
+                statement
+            };
+            self.lines.push(format!("{}{}", indent, lowered));
+        }
+        if !closing.is_empty() {
+            self.lines.push(format!("{}{}", indent, closing));
+        }
+        Ok(())
+    }
+
+    fn lower_statement(&mut self, statement: &str, module: &ModuleInfo) -> Result<String, String> {
+        let scope = Scope {
+            vars: &self.vars,
+            module,
+        };
+
+        if statement == "}" {
+            return match self.blocks.pop() {
+                Some(Block::If { closers }) => Ok(")".repeat(closers)),
+                Some(Block::While { label }) => Ok(format!("(br $__loop_{})))", label)),
+                None => Err("unmatched `}`".to_string()),
+            };
+        }
+
+        if let Some(rest) = statement.strip_prefix('}') {
+            let rest = rest.trim();
+            let Some(Block::If { closers }) = self.blocks.last_mut() else {
+                return Err("`else` without matching `if`".to_string());
+            };
+            if rest == "else {" {
+                return Ok(") (else".to_string());
+            }
+            if let Some(cond) = rest
+                .strip_prefix("else if ")
+                .and_then(|r| r.strip_suffix('{'))
+            {
+                *closers += 2;
+                return Ok(format!(") (else (if {} (then", scope.condition(cond)?));
+            }
+            return Err(format!("unexpected `{}`", statement));
+        }
+
+        if let Some(cond) = statement
+            .strip_prefix("if ")
+            .and_then(|r| r.strip_suffix('{'))
+        {
+            let cond = scope.condition(cond)?;
+            self.blocks.push(Block::If { closers: 2 });
+            return Ok(format!("(if {} (then", cond));
+        }
+
+        if let Some(cond) = statement
+            .strip_prefix("while ")
+            .and_then(|r| r.strip_suffix('{'))
+        {
+            let cond = scope.condition(cond)?;
+            let label = self.labels;
+            self.labels += 1;
+            self.blocks.push(Block::While { label });
+            return Ok(format!(
+                "(block $__exit_{0} (loop $__loop_{0} (br_if $__exit_{0} (i32.eqz {1}))",
+                label, cond
+            ));
+        }
+
+        if statement == "break" || statement == "continue" {
+            let label = self
+                .blocks
+                .iter()
+                .rev()
+                .find_map(|block| match block {
+                    Block::While { label } => Some(*label),
+                    Block::If { .. } => None,
+                })
+                .ok_or_else(|| format!("`{}` outside of `while`", statement))?;
+            let target = if statement == "break" { "exit" } else { "loop" };
+            return Ok(format!("(br $__{}_{})", target, label));
+        }
+
+        if let Some(value) = statement.strip_prefix("return") {
+            let value = value.trim();
+            if value.is_empty() {
+                return Ok("(return)".to_string());
+            }
+            let (code, _) = scope.lower(&parse_expr(value)?, self.result)?;
+            return Ok(format!("(return {})", code));
+        }
+
+        if let Some(binding) = statement.strip_prefix("let ") {
+            let (target, value) = binding
+                .split_once('=')
+                .ok_or_else(|| "expected `let $name = value`".to_string())?;
+            let (name, declared) = match target.split_once(':') {
+                Some((name, ty)) => (name.trim(), Some(Ty::parse(ty.trim()))),
+                None => (target.trim(), None),
+            };
+            let name = name
+                .strip_prefix('$')
+                .ok_or_else(|| format!("`let` names must start with `$`, got `{}`", name))?;
+            let expr = parse_expr(value)?;
+            let (code, ty) = scope.lower(&expr, declared)?;
+            if !ty.is_numeric() && declared.is_none() {
+                return Err(format!("cannot infer a type for `${}`, annotate it", name));
+            }
+            let ty = declared.unwrap_or(ty);
+            match self.vars.get(name) {
+                Some(existing) if *existing != ty => {
+                    return Err(format!(
+                        "`${}` is already declared as {}, not {}",
+                        name,
+                        existing.name(),
+                        ty.name()
+                    ));
+                },
+                Some(_) => {},
+                None => {
+                    self.vars.insert(name.to_string(), ty);
+                    self.new_locals.push((name.to_string(), ty));
+                },
+            }
+            return Ok(format!("(local.set ${} {})", name, code));
+        }
+
+        if let Some((name, op, value)) = split_assignment(statement) {
+            let target = Expr::Var(name.to_string());
+            let value = parse_expr(value)?;
+            let value = match op {
+                Some(op) => Expr::Binary(op, Box::new(target), Box::new(value)),
+                None => value,
+            };
+            let (setter, ty) = match scope.vars.get(name) {
+                Some(ty) => ("local.set", *ty),
+                None => match module.globals.get(name) {
+                    Some(ty) => ("global.set", *ty),
+                    None => return Err(format!("unknown variable `${}`", name)),
+                },
+            };
+            let (code, _) = scope.lower(&value, Some(ty))?;
+            return Ok(format!("({} ${} {})", setter, name, code));
+        }
+
+        let (code, _) = scope.lower(&parse_expr(statement)?, None)?;
+        Ok(code)
+    }
+
+    fn finish(mut self) -> Result<String, String> {
+        if !self.blocks.is_empty() {
+            return Err("unclosed `{` at end of function".to_string());
+        }
+        self.end_header();
+
+        let indent = self
+            .lines
+            .get(self.locals_at)
+            .map(|line| line[..line.len() - line.trim_start().len()].to_string())
+            .unwrap_or_else(|| "    ".to_string());
+        let locals = self
+            .new_locals
+            .iter()
+            .map(|(name, ty)| format!("{}(local ${} {})", indent, name, ty.name()));
+        let at = self.locals_at.min(self.lines.len());
+        self.lines.splice(at..at, locals);
+
+        let mut result = self.lines.join("\n");
+        result.push('\n');
+        Ok(result)
+    }
+}
+
+/// Split a line at braces so that `if $x { return 1 } else { return 2 }`
+/// becomes one statement per block boundary
+fn split_statements(line: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            },
+            '{' if !in_string => {
+                current.push('{');
+                statements.push(current.trim().to_string());
+                current.clear();
+            },
+            '}' if !in_string => {
+                if !current.trim().is_empty() {
+                    statements.push(current.trim().to_string());
+                }
+                current = "}".to_string();
+                // `} else {` stays together, a lone `}` ends here
+                if !line[index + 1..].trim_start().starts_with("else") {
+                    statements.push(current.clone());
+                    current.clear();
+                }
+            },
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() {
+        statements.push(current.trim().to_string());
+    }
+    statements
+}
+
+/// Split `$x = value` or `$x += value`, leaving comparisons like `$x == 1` alone
+fn split_assignment(statement: &str) -> Option<(&str, Option<&'static str>, &str)> {
+    let after_sigil = statement.strip_prefix('$')?;
+    let name_end = after_sigil
+        .find(|c: char| !is_name_char(c))
+        .unwrap_or(after_sigil.len());
+    let (name, rest) = after_sigil.split_at(name_end);
+    let rest = rest.trim_start();
+    for op in ["+", "-", "*", "/", "%"] {
+        if let Some(value) = rest.strip_prefix(op).and_then(|r| r.strip_prefix('=')) {
+            return Some((name, Some(op), value));
+        }
+    }
+    let value = rest.strip_prefix('=')?;
+    if value.starts_with('=') {
+        return None;
+    }
+    Some((name, None, value))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Expression tree of the sugar syntax
+#[derive(Debug)]
+enum Expr {
+    /// Numeric literal, kept as written so it can become any numeric type
+    Number(String),
+    /// String literal, content still escaped, only valid as the argument of `len`
+    Str(String),
+    Var(String),
+    Call(String, Vec<Expr>),
+    /// Float intrinsic such as `sqrt(x)` or `min(a, b)`
+    Builtin(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn is_float_literal(&self) -> bool {
+        match self {
+            Expr::Number(text) => {
+                text.contains('.') || text.contains(['e', 'E']) && !text.starts_with("0x")
+            },
+            Expr::Neg(inner) => inner.is_float_literal(),
+            Expr::Binary(_, left, right) => left.is_float_literal() || right.is_float_literal(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(String),
+    Str(String),
+    Var(String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+const OPERATORS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "&",
+    "|", "^",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            1
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            1
+        } else if c == '$' {
+            let len = rest[1..]
+                .find(|c: char| !is_name_char(c))
+                .unwrap_or(rest.len() - 1)
+                + 1;
+            if len == 1 {
+                return Err("expected a name after `$`".to_string());
+            }
+            tokens.push(Token::Var(rest[1..len].to_string()));
+            len
+        } else if c == '"' {
+            let mut escaped = false;
+            let end = rest[1..]
+                .find(|c: char| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .ok_or_else(|| "unterminated string literal".to_string())?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            end + 2
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..len].to_string()));
+            len
+        } else if c.is_ascii_alphabetic() {
+            let len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("unexpected character `{}`", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.binary(0)?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {:?} in `{}`", token, text.trim())),
+    }
+}
+
+/// Precedence-climbing parser over expression tokens
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            let op = *op;
+            let Some(precedence) = binary_precedence(op).filter(|p| *p > min_precedence) else {
+                break;
+            };
+            self.pos += 1;
+            let right = self.binary(precedence)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op("-")) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            },
+            Some(Token::Op("!")) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            },
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(text)) => Ok(Expr::Number(text.clone())),
+            Some(Token::Str(text)) => Ok(Expr::Str(text.clone())),
+            Some(Token::Var(name)) => {
+                let name = name.clone();
+                if self.tokens.get(self.pos) == Some(&Token::Open) {
+                    self.pos += 1;
+                    Ok(Expr::Call(name, self.arguments()?))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            },
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                match name.as_str() {
+                    "true" => return Ok(Expr::Number("1".to_string())),
+                    "false" => return Ok(Expr::Number("0".to_string())),
+                    _ => {},
+                }
+                if self.next() != Some(&Token::Open) {
+                    return Err(format!(
+                        "unknown name `{}` (variables start with `$`)",
+                        name
+                    ));
+                }
+                Ok(Expr::Builtin(name, self.arguments()?))
+            },
+            Some(Token::Open) => {
+                let inner = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("expected `)`".to_string()),
+                }
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    /// Comma-separated arguments after an opening parenthesis
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if self.tokens.get(self.pos) == Some(&Token::Close) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.binary(0)?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::Close) => return Ok(args),
+                _ => return Err("expected `,` or `)` in argument list".to_string()),
+            }
+        }
+    }
+}
+
+/// Float intrinsics available as `name(args)` and their arity
+const BUILTINS: [(&str, usize); 8] = [
+    ("sqrt", 1),
+    ("abs", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("trunc", 1),
+    ("nearest", 1),
+    ("min", 2),
+    ("max", 2),
+];
+
+/// Variables visible while lowering one statement
+struct Scope<'a> {
+    vars: &'a HashMap<String, Ty>,
+    module: &'a ModuleInfo,
+}
+
+impl Scope<'_> {
+    fn var_type(&self, name: &str) -> Result<Ty, String> {
+        self.vars
+            .get(name)
+            .or_else(|| self.module.globals.get(name))
+            .copied()
+            .ok_or_else(|| format!("unknown variable `${}`", name))
+    }
+
+    /// Type an expression has on its own, `None` for untyped literals
+    fn natural_type(&self, expr: &Expr) -> Result<Option<Ty>, String> {
+        Ok(match expr {
+            Expr::Number(_) => None,
+            Expr::Str(_) => Some(Ty::Other),
+            Expr::Var(name) => Some(self.var_type(name)?),
+            Expr::Call(name, _) => self.module.funcs.get(name).map(|sig| sig.result),
+            Expr::Builtin(name, _) if name == "len" => Some(Ty::I32),
+            Expr::Builtin(_, args) => match args.first() {
+                Some(arg) => self.natural_type(arg)?,
+                None => None,
+            },
+            Expr::Neg(inner) => self.natural_type(inner)?,
+            Expr::Not(_) => Some(Ty::I32),
+            Expr::Binary(op, left, right) => {
+                if is_comparison(op) || is_logical(op) {
+                    Some(Ty::I32)
+                } else {
+                    match self.natural_type(left)? {
+                        Some(ty) => Some(ty),
+                        None => self.natural_type(right)?,
+                    }
+                }
+            },
+        })
+    }
+
+    /// Operand type of a binary or builtin expression
+    fn operand_type(&self, operands: &[&Expr], expected: Option<Ty>) -> Result<Ty, String> {
+        for operand in operands {
+            if let Some(ty) = self.natural_type(operand)? {
+                return Ok(ty);
+            }
+        }
+        if let Some(ty) = expected.filter(|ty| ty.is_numeric()) {
+            return Ok(ty);
+        }
+        Ok(
+            if operands.iter().any(|operand| operand.is_float_literal()) {
+                Ty::F64
+            } else {
+                Ty::I32
+            },
+        )
+    }
+
+    fn condition(&self, text: &str) -> Result<String, String> {
+        let (code, ty) = self.lower(&parse_expr(text)?, Some(Ty::I32))?;
+        if ty != Ty::I32 {
+            return Err(format!("condition must be i32, got {}", ty.name()));
+        }
+        Ok(code)
+    }
+
+    /// Lower an expression to folded WAT, returning the code and its type
+    /// Operations on constants become the constant they compute
+    fn lower(&self, expr: &Expr, expected: Option<Ty>) -> Result<(String, Ty), String> {
+        let (code, ty) = self.lower_operations(expr, expected)?;
+        let literal = match expr {
+            Expr::Neg(inner) => matches!(inner.as_ref(), Expr::Number(_)),
+            expr => matches!(expr, Expr::Number(_) | Expr::Var(_)),
+        };
+        if literal {
+            return Ok((code, ty));
+        }
+        let folded = self
+            .evaluate(expr, ty)
+            .filter(|value| value.ty() == ty)
+            .and_then(Value::code);
+        Ok((folded.unwrap_or(code), ty))
+    }
+
+    /// Lower an expression without computing its constant operations
+    fn lower_operations(&self, expr: &Expr, expected: Option<Ty>) -> Result<(String, Ty), String> {
+        match expr {
+            Expr::Number(text) => {
+                let ty =
+                    expected
+                        .filter(|ty| ty.is_numeric())
+                        .unwrap_or(if expr.is_float_literal() {
+                            Ty::F64
+                        } else {
+                            Ty::I32
+                        });
+                if expr.is_float_literal() && !ty.is_float() {
+                    return Err(format!("float literal `{}` used as {}", text, ty.name()));
+                }
+                Ok((format!("({}.const {})", ty.name(), text), ty))
+            },
+            Expr::Str(_) => Err("string literals can only be passed to `len`".to_string()),
+            Expr::Var(name) => {
+                let ty = self.var_type(name)?;
+                let getter = if self.vars.contains_key(name) {
+                    "local.get"
+                } else {
+                    "global.get"
+                };
+                Ok((format!("({} ${})", getter, name), ty))
+            },
+            Expr::Call(name, args) => {
+                let sig = self.module.funcs.get(name);
+                let mut code = format!("(call ${}", name);
+                for (index, arg) in args.iter().enumerate() {
+                    let param = sig.and_then(|sig| sig.params.get(index)).copied();
+                    code.push(' ');
+                    code.push_str(&self.lower(arg, param)?.0);
+                }
+                code.push(')');
+                Ok((code, sig.map_or(Ty::Other, |sig| sig.result)))
+            },
+            Expr::Builtin(name, args) if name == "len" => match args.as_slice() {
+                [Expr::Str(text)] => Ok((format!("(i32.const {})", decoded_len(text)), Ty::I32)),
+                _ => Err("`len` takes a string literal".to_string()),
+            },
+            Expr::Builtin(name, args) => {
+                let arity = BUILTINS
+                    .iter()
+                    .find(|(builtin, _)| builtin == name)
+                    .map(|(_, arity)| *arity)
+                    .ok_or_else(|| format!("unknown function `{}`", name))?;
+                if args.len() != arity {
+                    return Err(format!("`{}` takes {} argument(s)", name, arity));
+                }
+                let operands: Vec<&Expr> = args.iter().collect();
+                let ty = self.operand_type(&operands, expected.or(Some(Ty::F64)))?;
+                if !ty.is_float() {
+                    return Err(format!(
+                        "`{}` needs a float argument, got {}",
+                        name,
+                        ty.name()
+                    ));
+                }
+                let mut code = format!("({}.{}", ty.name(), name);
+                for arg in args {
+                    code.push(' ');
+                    code.push_str(&self.expect(arg, ty)?);
+                }
+                code.push(')');
+                Ok((code, ty))
+            },
+            Expr::Neg(inner) => {
+                if let Expr::Number(text) = inner.as_ref() {
+                    return self.lower(&Expr::Number(format!("-{}", text)), expected);
+                }
+                let ty = self.operand_type(&[inner.as_ref()], expected)?;
+                let value = self.expect(inner, ty)?;
+                if ty.is_float() {
+                    Ok((format!("({}.neg {})", ty.name(), value), ty))
+                } else {
+                    Ok((format!("({0}.sub ({0}.const 0) {1})", ty.name(), value), ty))
+                }
+            },
+            Expr::Not(inner) => Ok((
+                format!("(i32.eqz {})", self.expect(inner, Ty::I32)?),
+                Ty::I32,
+            )),
+            Expr::Binary(op, left, right) if is_logical(op) => {
+                let left = self.expect(left, Ty::I32)?;
+                let right = self.expect(right, Ty::I32)?;
+                // Short-circuit like JavaScript
+                let code = if *op == "&&" {
+                    format!(
+                        "(if (result i32) {} (then {}) (else (i32.const 0)))",
+                        left, right
+                    )
+                } else {
+                    format!(
+                        "(if (result i32) {} (then (i32.const 1)) (else {}))",
+                        left, right
+                    )
+                };
+                Ok((code, Ty::I32))
+            },
+            Expr::Binary(op, left, right) => {
+                let comparison = is_comparison(op);
+                let ty = self.operand_type(
+                    &[left.as_ref(), right.as_ref()],
+                    if comparison { None } else { expected },
+                )?;
+                let instr = binary_instr(op, ty)?;
+                let code = format!(
+                    "({}.{} {} {})",
+                    ty.name(),
+                    instr,
+                    self.expect(left, ty)?,
+                    self.expect(right, ty)?
+                );
+                Ok((code, if comparison { Ty::I32 } else { ty }))
+            },
+        }
+    }
+
+    /// Value of an expression of type `ty` whose operands are all literals or
+    /// constant globals, `None` if it has to be computed at run time
+    fn evaluate(&self, expr: &Expr, ty: Ty) -> Option<Value> {
+        match expr {
+            Expr::Number(text) => Value::parse(text, ty),
+            Expr::Str(_) | Expr::Call(..) => None,
+            Expr::Var(name) if self.vars.contains_key(name) => None,
+            Expr::Var(name) => self.module.constants.get(name).copied(),
+            Expr::Builtin(name, args) if name == "len" => match args.as_slice() {
+                [Expr::Str(text)] => Some(Value::I32(decoded_len(text) as i32)),
+                _ => None,
+            },
+            Expr::Builtin(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg, ty)?.float())
+                    .collect::<Option<Vec<f64>>>()?;
+                let value = fold_builtin(name, &args)?;
+                Some(match ty {
+                    Ty::F32 => Value::F32(value as f32),
+                    _ => Value::F64(value),
+                })
+            },
+            Expr::Neg(inner) => match inner.as_ref() {
+                Expr::Number(text) => Value::parse(&format!("-{}", text), ty),
+                inner => Some(self.evaluate(inner, ty)?.neg()),
+            },
+            Expr::Not(inner) => Some(Value::I32(!self.evaluate(inner, Ty::I32)?.is_true() as i32)),
+            Expr::Binary(op, left, right) if is_logical(op) => {
+                // Like the lowered code, skip the right side once the left decides
+                let left = self.evaluate(left, Ty::I32)?.is_true();
+                if left == (*op == "||") {
+                    return Some(Value::I32(left as i32));
+                }
+                Some(Value::I32(self.evaluate(right, Ty::I32)?.is_true() as i32))
+            },
+            Expr::Binary(op, left, right) => {
+                let operands = if is_comparison(op) {
+                    self.operand_type(&[left.as_ref(), right.as_ref()], None)
+                        .ok()?
+                } else {
+                    ty
+                };
+                fold_binary(
+                    op,
+                    self.evaluate(left, operands)?,
+                    self.evaluate(right, operands)?,
+                )
+            },
+        }
+    }
+
+    /// Lower an expression that must have the given type
+    fn expect(&self, expr: &Expr, ty: Ty) -> Result<String, String> {
+        let (code, actual) = self.lower(expr, Some(ty))?;
+        if actual != ty {
+            return Err(format!(
+                "type mismatch: expected {}, got {}",
+                ty.name(),
+                actual.name()
+            ));
+        }
+        Ok(code)
+    }
+}
+
+fn is_comparison(op: &str) -> bool {
+    matches!(op, "==" | "!=" | "<" | "<=" | ">" | ">=")
+}
+
+fn is_logical(op: &str) -> bool {
+    matches!(op, "&&" | "||")
+}
+
+/// WAT instruction name (without type prefix) for a binary operator
+fn binary_instr(op: &str, ty: Ty) -> Result<&'static str, String> {
+    if !ty.is_numeric() {
+        return Err(format!("cannot apply `{}` to {}", op, ty.name()));
+    }
+    let float = ty.is_float();
+    Ok(match (op, float) {
+        ("+", _) => "add",
+        ("-", _) => "sub",
+        ("*", _) => "mul",
+        ("/", true) => "div",
+        ("/", false) => "div_s",
+        ("%", false) => "rem_s",
+        ("&", false) => "and",
+        ("|", false) => "or",
+        ("^", false) => "xor",
+        ("<<", false) => "shl",
+        (">>", false) => "shr_s",
+        ("==", _) => "eq",
+        ("!=", _) => "ne",
+        ("<", true) => "lt",
+        ("<", false) => "lt_s",
+        ("<=", true) => "le",
+        ("<=", false) => "le_s",
+        (">", true) => "gt",
+        (">", false) => "gt_s",
+        (">=", true) => "ge",
+        (">=", false) => "ge_s",
+        _ => return Err(format!("`{}` is not defined for {}", op, ty.name())),
+    })
+}
+
+/// Value of a constant expression, computed while lowering
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    /// Read a literal the way the `wat` parser reads the operand of `ty.const`
+    /// Hexadecimal floats, `inf` and `nan` are not folded
+    fn parse(text: &str, ty: Ty) -> Option<Value> {
+        let text = text.replace('_', "");
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        match ty {
+            Ty::I32 | Ty::I64 => {
+                let magnitude = match digits.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+                    None => digits.parse::<u64>().ok()?,
+                };
+                let value = if negative {
+                    -i128::from(magnitude)
+                } else {
+                    i128::from(magnitude)
+                };
+                // Integer literals may be written signed or unsigned
+                let bits = if ty == Ty::I32 { 32 } else { 64 };
+                if value < -(1 << (bits - 1)) || value >= 1 << bits {
+                    return None;
+                }
+                Some(if ty == Ty::I32 {
+                    Value::I32(value as i32)
+                } else {
+                    Value::I64(value as i64)
+                })
+            },
+            Ty::F32 | Ty::F64 => {
+                if !digits.starts_with(|c: char| c.is_ascii_digit()) || digits.starts_with("0x") {
+                    return None;
+                }
+                let value = if ty == Ty::F32 {
+                    Value::F32(digits.parse().ok()?)
+                } else {
+                    Value::F64(digits.parse().ok()?)
+                };
+                Some(if negative { value.neg() } else { value })
+            },
+            Ty::Other | Ty::Void => None,
+        }
+    }
+
+    fn ty(self) -> Ty {
+        match self {
+            Value::I32(_) => Ty::I32,
+            Value::I64(_) => Ty::I64,
+            Value::F32(_) => Ty::F32,
+            Value::F64(_) => Ty::F64,
+        }
+    }
+
+    /// The `ty.const` instruction producing the value, `None` for NaN, whose
+    /// bits the computation does not pin down
+    fn code(self) -> Option<String> {
+        let float = |value: f64, text: String| {
+            if value.is_nan() {
+                None
+            } else if value.is_infinite() {
+                Some(if value < 0.0 { "-inf" } else { "inf" }.to_string())
+            } else {
+                Some(text)
+            }
+        };
+        let text = match self {
+            Value::I32(value) => value.to_string(),
+            Value::I64(value) => value.to_string(),
+            // Debug formatting is the shortest text reading back as the same value
+            Value::F32(value) => float(value.into(), format!("{:?}", value))?,
+            Value::F64(value) => float(value, format!("{:?}", value))?,
+        };
+        Some(format!("({}.const {})", self.ty().name(), text))
+    }
+
+    fn float(self) -> Option<f64> {
+        match self {
+            Value::F32(value) => Some(value.into()),
+            Value::F64(value) => Some(value),
+            Value::I32(_) | Value::I64(_) => None,
+        }
+    }
+
+    fn is_true(self) -> bool {
+        match self {
+            Value::I32(value) => value != 0,
+            Value::I64(value) => value != 0,
+            Value::F32(value) => value != 0.0,
+            Value::F64(value) => value != 0.0,
+        }
+    }
+
+    fn neg(self) -> Value {
+        match self {
+            Value::I32(value) => Value::I32(value.wrapping_neg()),
+            Value::I64(value) => Value::I64(value.wrapping_neg()),
+            Value::F32(value) => Value::F32(-value),
+            Value::F64(value) => Value::F64(-value),
+        }
+    }
+}
+
+/// Compute a binary operator on two values of the same type, as the
+/// instruction from [`binary_instr`] would
+fn fold_binary(op: &str, left: Value, right: Value) -> Option<Value> {
+    if is_comparison(op) {
+        let ordering = match (left, right) {
+            (Value::I32(a), Value::I32(b)) => a.partial_cmp(&b),
+            (Value::I64(a), Value::I64(b)) => a.partial_cmp(&b),
+            (Value::F32(a), Value::F32(b)) => a.partial_cmp(&b),
+            (Value::F64(a), Value::F64(b)) => a.partial_cmp(&b),
+            _ => return None,
+        };
+        // NaN is unordered: only `!=` holds
+        let holds = match op {
+            "==" => ordering == Some(Ordering::Equal),
+            "!=" => ordering != Some(Ordering::Equal),
+            "<" => ordering == Some(Ordering::Less),
+            "<=" => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            ">" => ordering == Some(Ordering::Greater),
+            _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        };
+        return Some(Value::I32(holds as i32));
+    }
+    Some(match (left, right) {
+        (Value::I32(a), Value::I32(b)) => Value::I32(fold_int(op, a.into(), b.into(), 32)? as i32),
+        (Value::I64(a), Value::I64(b)) => Value::I64(fold_int(op, a, b, 64)?),
+        // f64 has enough precision that rounding its result to f32 gives the
+        // f32 result of the four arithmetic operators
+        (Value::F32(a), Value::F32(b)) => Value::F32(fold_float(op, a.into(), b.into())? as f32),
+        (Value::F64(a), Value::F64(b)) => Value::F64(fold_float(op, a, b)?),
+        _ => return None,
+    })
+}
+
+/// Integer operators on sign-extended `bits`-wide values, `None` where the
+/// instruction traps
+fn fold_int(op: &str, a: i64, b: i64, bits: u32) -> Option<i64> {
+    // Shift counts are taken modulo the width
+    let shift = (b as u32) & (bits - 1);
+    Some(match op {
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" => {
+            let quotient = a.checked_div(b)?;
+            if bits == 32 && i32::try_from(quotient).is_err() {
+                return None;
+            }
+            quotient
+        },
+        "%" if b == 0 => return None,
+        "%" => a.wrapping_rem(b),
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        "<<" => a.wrapping_shl(shift),
+        ">>" => a.wrapping_shr(shift),
+        _ => return None,
+    })
+}
+
+fn fold_float(op: &str, a: f64, b: f64) -> Option<f64> {
+    Some(match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        _ => return None,
+    })
+}
+
+/// Float intrinsics of [`BUILTINS`], with the rounding and signed zeros of
+/// the instructions
+fn fold_builtin(name: &str, args: &[f64]) -> Option<f64> {
+    Some(match (name, args) {
+        ("sqrt", [x]) => x.sqrt(),
+        ("abs", [x]) => x.abs(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("trunc", [x]) => x.trunc(),
+        ("nearest", [x]) => x.round_ties_even(),
+        ("min" | "max", [a, b]) if a.is_nan() || b.is_nan() => f64::NAN,
+        ("min", [a, b]) if a == b => {
+            if a.is_sign_negative() {
+                *a
+            } else {
+                *b
+            }
+        },
+        ("max", [a, b]) if a == b => {
+            if a.is_sign_positive() {
+                *a
+            } else {
+                *b
+            }
+        },
+        ("min", [a, b]) => a.min(*b),
+        ("max", [a, b]) => a.max(*b),
+        _ => return None,
+    })
+}
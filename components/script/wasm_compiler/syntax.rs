@@ -0,0 +1,156 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Syntax classification of WAT sources
+//!
+//! [`classify`] splits a source into spans for syntax highlighting, e.g. of
+//! inline `text/wat` scripts in the devtools source viewer. It follows the
+//! lexical grammar of the text format, so it also works on sources that do
+//! not parse, and covers the extensions (annotations like `(@sugar)` and the
+//! sugar's operators are keywords). Whitespace is not reported.
+
+use serde::Serialize;
+
+/// Class of a span
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    /// Instructions, module fields, types, annotations and operators
+    Keyword,
+    /// `$name`
+    Identifier,
+    /// Numbers (including `inf` and `nan`) and strings
+    Literal,
+    /// `;; line` and `(; block ;)` comments
+    Comment,
+    /// Parentheses
+    Punctuation,
+}
+
+/// A classified span, in byte offsets into the source
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Span {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Classify the tokens of a WAT source, in source order
+pub fn classify(source: &str) -> Vec<Span> {
+    let bytes = source.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let kind = match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                i += 1;
+                continue;
+            },
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                TokenKind::Comment
+            },
+            b'(' if bytes.get(i + 1) == Some(&b';') => {
+                i = block_comment_end(bytes, i);
+                TokenKind::Comment
+            },
+            b'(' | b')' => {
+                i += 1;
+                TokenKind::Punctuation
+            },
+            b'"' => {
+                i = string_end(bytes, i);
+                TokenKind::Literal
+            },
+            b'$' => {
+                // `$name` or a quoted `$"name"`
+                i = if bytes.get(i + 1) == Some(&b'"') {
+                    string_end(bytes, i + 1)
+                } else {
+                    token_end(bytes, i + 1)
+                };
+                TokenKind::Identifier
+            },
+            _ => {
+                i = token_end(bytes, i + 1);
+                if is_number(&source[start..i]) {
+                    TokenKind::Literal
+                } else {
+                    TokenKind::Keyword
+                }
+            },
+        };
+        spans.push(Span {
+            kind,
+            start,
+            end: i,
+        });
+    }
+
+    spans
+}
+
+/// End of the token starting at `start`: tokens run until whitespace, a
+/// parenthesis, a string or a comment
+fn token_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' | b'(' | b')' | b'"' => break,
+            b';' if bytes.get(i + 1) == Some(&b';') => break,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// End of the string literal whose opening quote is at `start`; an
+/// unterminated string runs to the end of its line
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return i + 1,
+            b'\n' => return i,
+            b'\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// End of the (possibly nested) block comment starting at `start`
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"(;") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b";)") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Integers, floats (`1.5e3`, `0x1p-2`), `inf` and `nan` (`nan:0x200000`)
+fn is_number(token: &str) -> bool {
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    unsigned.starts_with(|c: char| c.is_ascii_digit())
+        || unsigned == "inf"
+        || unsigned == "nan"
+        || unsigned.starts_with("nan:0x")
+}
@@ -0,0 +1,187 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tables and element segments
+//!
+//! Modules dispatching through `call_indirect` keep their function pointers in
+//! tables filled by element segments. The loader installs what the module
+//! declares as `window.__wasmTables[filename]`, and the exported tables of the
+//! instance as `window.__wasmModules[filename].tables`, whose `call(slot, ...)`
+//! calls the function in a slot:
+//!
+//! ```text
+//! {"tables": [{"index": 0, "element": "funcref", "initial": 4, "maximum": null,
+//!              "import": null, "exports": ["table"]}],
+//!  "elements": [{"index": 0, "mode": "active", "table": 0, "offset": 1,
+//!                "functions": [2, 3]}],
+//!  "resident": {"2": [[0, 1]], "3": [[0, 2]]}}
+//! ```
+//!
+//! `resident` lists the slots of each function placed by an active segment at
+//! a constant offset. Function indices count the imported functions first,
+//! as the name section does, see [`super::locals`].
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasmparser::{
+    ConstExpr, ElementItems, ElementKind, ExternalKind, Operator, Parser, Payload, TypeRef,
+};
+
+/// A table, imported or defined
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Table {
+    pub index: u32,
+    /// Type of its elements, e.g. `funcref`
+    pub element: String,
+    pub initial: u64,
+    pub maximum: Option<u64>,
+    /// `module.name` of an imported table
+    pub import: Option<String>,
+    pub exports: Vec<String>,
+}
+
+/// An element segment
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Element {
+    pub index: u32,
+    /// `active`, `passive` or `declared`
+    pub mode: &'static str,
+    /// Table of an active segment
+    pub table: Option<u32>,
+    /// Offset of an active segment, unless it depends on a global
+    pub offset: Option<i64>,
+    /// Function of each item, `None` for null references and items that are
+    /// not functions
+    pub functions: Vec<Option<u32>>,
+}
+
+/// Tables and element segments of a module
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Tables {
+    pub tables: Vec<Table>,
+    pub elements: Vec<Element>,
+    /// `[table, slot]` of the functions placed in tables, by function index
+    pub resident: BTreeMap<u32, Vec<(u32, u64)>>,
+}
+
+/// The tables and element segments `binary` declares; those of a malformed
+/// section are left out
+pub fn tables(binary: &[u8]) -> Tables {
+    let mut tables = Tables::default();
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload {
+            Ok(Payload::ImportSection(reader)) => {
+                for import in reader.into_iter().flatten() {
+                    if let TypeRef::Table(ty) = import.ty {
+                        tables.tables.push(Table {
+                            index: tables.tables.len() as u32,
+                            element: ty.element_type.to_string(),
+                            initial: ty.initial,
+                            maximum: ty.maximum,
+                            import: Some(format!("{}.{}", import.module, import.name)),
+                            exports: Vec::new(),
+                        });
+                    }
+                }
+            },
+            Ok(Payload::TableSection(reader)) => {
+                for table in reader.into_iter().flatten() {
+                    tables.tables.push(Table {
+                        index: tables.tables.len() as u32,
+                        element: table.ty.element_type.to_string(),
+                        initial: table.ty.initial,
+                        maximum: table.ty.maximum,
+                        import: None,
+                        exports: Vec::new(),
+                    });
+                }
+            },
+            Ok(Payload::ExportSection(reader)) => {
+                for export in reader.into_iter().flatten() {
+                    if export.kind != ExternalKind::Table {
+                        continue;
+                    }
+                    if let Some(table) = tables.tables.get_mut(export.index as usize) {
+                        table.exports.push(export.name.to_string());
+                    }
+                }
+            },
+            Ok(Payload::ElementSection(reader)) => {
+                for element in reader.into_iter().flatten() {
+                    let (mode, table, offset) = match element.kind {
+                        ElementKind::Active {
+                            table_index,
+                            offset_expr,
+                        } => (
+                            "active",
+                            Some(table_index.unwrap_or(0)),
+                            constant(&offset_expr),
+                        ),
+                        ElementKind::Passive => ("passive", None, None),
+                        ElementKind::Declared => ("declared", None, None),
+                    };
+                    let functions = match element.items {
+                        ElementItems::Functions(reader) => {
+                            reader.into_iter().map(Result::ok).collect()
+                        },
+                        ElementItems::Expressions(_, reader) => reader
+                            .into_iter()
+                            .map(|expr| expr.ok().and_then(|expr| function(&expr)))
+                            .collect(),
+                    };
+                    tables.elements.push(Element {
+                        index: tables.elements.len() as u32,
+                        mode,
+                        table,
+                        offset,
+                        functions,
+                    });
+                }
+            },
+            _ => {},
+        }
+    }
+
+    for element in &tables.elements {
+        let (Some(table), Some(offset)) = (element.table, element.offset) else {
+            continue;
+        };
+        for (slot, function) in element.functions.iter().enumerate() {
+            if let Some(function) = function {
+                tables
+                    .resident
+                    .entry(*function)
+                    .or_default()
+                    .push((table, offset as u64 + slot as u64));
+            }
+        }
+    }
+    tables
+}
+
+/// [`tables`] as JSON, for the loader
+pub fn tables_json(binary: &[u8]) -> String {
+    serde_json::to_string(&tables(binary)).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// The value of a constant offset
+fn constant(expr: &ConstExpr) -> Option<i64> {
+    match expr.get_operators_reader().read().ok()? {
+        Operator::I32Const { value } => Some(value as u32 as i64),
+        Operator::I64Const { value } => Some(value),
+        _ => None,
+    }
+}
+
+/// The function a `ref.func` item refers to
+fn function(expr: &ConstExpr) -> Option<u32> {
+    match expr.get_operators_reader().read().ok()? {
+        Operator::RefFunc { function_index } => Some(function_index),
+        _ => None,
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Telemetry for the WAT pipeline
+//!
+//! Compilations and cache hits are reported to the time profiler under
+//! `ScriptWasmCompile` and `ScriptWasmCacheHit`, which gives their counts and
+//! time distribution in `--profile` output; the cache hit ratio follows from
+//! the two counts. Process-wide counters additionally track failures by
+//! error kind and a compile time histogram, see [`snapshot`].
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use base::cross_process_instant::CrossProcessInstant;
+use profile_traits::time::{ProfilerCategory, ProfilerChan, send_profile_data};
+
+use super::CompileError;
+
+/// Upper bounds (inclusive, in milliseconds) of the compile time histogram
+/// buckets; slower compilations go in a final overflow bucket
+pub const COMPILE_TIME_BUCKETS_MS: [u64; 10] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000];
+
+static PROFILER_CHAN: OnceLock<ProfilerChan> = OnceLock::new();
+
+static COMPILED: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+/// Failures by [`CompileError::index`]
+static FAILURES: [AtomicU64; CompileError::KINDS.len()] =
+    [const { AtomicU64::new(0) }; CompileError::KINDS.len()];
+static COMPILE_TIMES: [AtomicU64; COMPILE_TIME_BUCKETS_MS.len() + 1] =
+    [const { AtomicU64::new(0) }; COMPILE_TIME_BUCKETS_MS.len() + 1];
+
+/// Send timings to the time profiler of this process
+/// Only the first channel is kept; all script threads share the profiler
+pub fn set_profiler_chan(chan: ProfilerChan) {
+    let _ = PROFILER_CHAN.set(chan);
+}
+
+/// Record a compilation that missed the cache
+pub fn record_compile(start: CrossProcessInstant, end: CrossProcessInstant) {
+    COMPILED.fetch_add(1, Ordering::Relaxed);
+    let millis = millis(start, end);
+    let bucket = COMPILE_TIME_BUCKETS_MS
+        .iter()
+        .position(|bound| millis <= *bound)
+        .unwrap_or(COMPILE_TIME_BUCKETS_MS.len());
+    COMPILE_TIMES[bucket].fetch_add(1, Ordering::Relaxed);
+    send(ProfilerCategory::ScriptWasmCompile, start, end);
+}
+
+/// Milliseconds from `start` to `end`
+pub fn millis(start: CrossProcessInstant, end: CrossProcessInstant) -> u64 {
+    u64::try_from((end - start).whole_milliseconds()).unwrap_or(0)
+}
+
+/// Record a compilation served from the cache
+pub fn record_cache_hit(start: CrossProcessInstant, end: CrossProcessInstant) {
+    CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+    send(ProfilerCategory::ScriptWasmCacheHit, start, end);
+}
+
+/// Record a failed compilation
+pub fn record_failure(error: &CompileError) {
+    FAILURES[error.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+fn send(category: ProfilerCategory, start: CrossProcessInstant, end: CrossProcessInstant) {
+    if let Some(chan) = PROFILER_CHAN.get() {
+        send_profile_data(category, None, chan, start, end);
+    }
+}
+
+/// Counters since process start
+#[allow(dead_code)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub compiled: u64,
+    pub cache_hits: u64,
+    /// Failure count per error kind
+    pub failures: Vec<(&'static str, u64)>,
+    /// Compilations per [`COMPILE_TIME_BUCKETS_MS`] bucket, plus overflow
+    pub compile_times: Vec<u64>,
+}
+
+#[allow(dead_code)]
+impl Snapshot {
+    /// Share of successful compile calls served from the cache
+    pub fn cache_hit_ratio(&self) -> f64 {
+        let total = self.compiled + self.cache_hits;
+        if total == 0 {
+            return 0.0;
+        }
+        self.cache_hits as f64 / total as f64
+    }
+}
+
+/// Read the counters (for tests, or when inspecting a running process)
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        compiled: COMPILED.load(Ordering::Relaxed),
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+        failures: CompileError::KINDS
+            .iter()
+            .zip(&FAILURES)
+            .map(|(kind, count)| (*kind, count.load(Ordering::Relaxed)))
+            .collect(),
+        compile_times: COMPILE_TIMES
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect(),
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Timeline of a compilation, for the devtools performance view
+//!
+//! While [`capture`] runs, the phases of compiling a script are recorded as
+//! spans on its thread: the cache lookup, parsing, each binary pass (named as
+//! in [`super::differential`] reports), validation and the glue. The script
+//! element emits them as `Wasm` timeline markers, next to the evaluation of
+//! the glue, and the loader measures the instantiation with the User Timing
+//! API as `wasm instantiate <filename>`. Compilations outside a capture,
+//! like those [`super::speculative`] runs ahead on other threads, record
+//! nothing.
+
+use std::cell::RefCell;
+
+use base::cross_process_instant::CrossProcessInstant;
+
+thread_local! {
+    static SPANS: RefCell<Option<Vec<Span>>> = const { RefCell::new(None) };
+}
+
+/// A phase of the compilation
+#[derive(Clone, Debug, MallocSizeOf, PartialEq)]
+pub struct Span {
+    pub phase: String,
+    pub start: CrossProcessInstant,
+    pub end: CrossProcessInstant,
+}
+
+/// Run `f`, returning the spans of the phases it went through
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<Span>) {
+    let outer = SPANS.with(|spans| spans.replace(Some(Vec::new())));
+    let result = f();
+    let spans = SPANS.with(|spans| spans.replace(outer)).unwrap_or_default();
+    (result, spans)
+}
+
+/// Run `f` as the phase `phase`
+pub fn phase<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    let start = CrossProcessInstant::now();
+    let result = f();
+    record(phase, start, CrossProcessInstant::now());
+    result
+}
+
+/// Record the phase `phase` from `start` to `end`
+pub fn record(phase: &str, start: CrossProcessInstant, end: CrossProcessInstant) {
+    SPANS.with(|spans| {
+        if let Some(spans) = spans.borrow_mut().as_mut() {
+            spans.push(Span {
+                phase: phase.to_owned(),
+                start,
+                end,
+            });
+        }
+    });
+}
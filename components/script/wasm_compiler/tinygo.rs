@@ -0,0 +1,335 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Modules built by TinyGo
+//!
+//! Go code reaches JavaScript through `syscall/js`, whose functions a module
+//! imports from `gojs` (`go` or `env` for older toolchains), and expects
+//! the `wasm_exec.js` of its toolchain to provide them. TinyGo modules get the
+//! same functions from the loader, for those the page does not provide, with
+//! `fd_write` to the console and the timers of the scheduler, and are started
+//! like `Go.run` does: `_start`, or `_initialize` for libraries. Functions made
+//! with `js.FuncOf` call back into the module through its `resume` export.
+//!
+//! Modules built by Go itself (`GOOS=js`) pass arguments on their own stack
+//! rather than as parameters, which only Go's `wasm_exec.js` knows the layout
+//! of; the loader reports that instead of instantiating them.
+
+use super::LOG_TARGET;
+use super::embed;
+use super::imports;
+
+/// Modules of the `syscall/js` imports, the last two of older toolchains
+const IMPORT_MODULES: [&str; 3] = ["gojs", "go", "env"];
+
+/// Prefixes of the names of the functions of `wasm_exec.js`
+const IMPORT_PREFIXES: [&str; 2] = ["syscall/js.", "runtime."];
+
+/// Toolchain a Go module was built with
+#[derive(Clone, Copy, Debug, MallocSizeOf, PartialEq)]
+pub enum Toolchain {
+    TinyGo,
+    /// Go itself, with stack-based imports
+    Go,
+}
+
+/// The toolchain of a module importing the functions of `wasm_exec.js`, or
+/// `None` for other modules; only Go's runtime imports `runtime.wasmExit`
+pub fn toolchain(binary: &[u8]) -> Option<Toolchain> {
+    let go_imports: Vec<String> = imports::declared_imports(binary)
+        .into_iter()
+        .filter(|(module, name, _)| {
+            IMPORT_MODULES.contains(&module.as_str())
+                && IMPORT_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .map(|(_, name, _)| name)
+        .collect();
+    if go_imports.is_empty() {
+        None
+    } else if go_imports.iter().any(|name| name == "runtime.wasmExit") {
+        Some(Toolchain::Go)
+    } else {
+        Some(Toolchain::TinyGo)
+    }
+}
+
+/// Why a module built by Go is not instantiated
+pub const GO_UNSUPPORTED: &str = "module was built by Go (GOOS=js), whose imports pass their \
+     arguments on the stack of its runtime; load it with the wasm_exec.js of the Go release it \
+     was built with, or build it with TinyGo";
+
+/// JavaScript of the imports of `wasm_exec.js` for a module built by TinyGo,
+/// resolved before it is instantiated, and starting it once it is, like
+/// `Go.run` does
+pub fn loader_js() -> (&'static str, &'static str) {
+    (
+        r#"
+        const goEncoder = new TextEncoder();
+        const goDecoder = new TextDecoder('utf-8');
+        const goReinterpret = new DataView(new ArrayBuffer(8));
+        const goExit = {};
+        const goTimeOrigin = Date.now() - performance.now();
+        const goState = { exports: null, exited: false, exitCode: 0, values: [], refCounts: [], ids: new Map(), idPool: [] };
+        // The object Go sees as the runtime: js.FuncOf calls _makeFuncWrapper and
+        // reads the event being handled from _pendingEvent
+        const go = {
+            _pendingEvent: null,
+            _makeFuncWrapper: function(id) {
+                return function() {
+                    const event = { id: id, this: this, args: arguments };
+                    go._pendingEvent = event;
+                    if (goState.exited) {
+                        throw new Error('Go program has already exited');
+                    }
+                    try {
+                        goState.exports.resume();
+                    } catch (e) {
+                        if (e !== goExit) {
+                            throw e;
+                        }
+                    }
+                    return event.result;
+                };
+            },
+            start: function(instance) {
+                goState.exports = instance.exports;
+                goState.values = [NaN, 0, null, true, false, window, go];
+                try {
+                    if (typeof instance.exports._start === 'function') {
+                        instance.exports._start();
+                    } else if (typeof instance.exports._initialize === 'function') {
+                        instance.exports._initialize();
+                    }
+                } catch (e) {
+                    if (e !== goExit) {
+                        throw e;
+                    }
+                }
+            }
+        };
+        const goMemory = () => new DataView(goState.exports.memory.buffer);
+        const goBytes = (ptr, len) => new Uint8Array(goState.exports.memory.buffer, ptr, len);
+        const goString = (ptr, len) => goDecoder.decode(goBytes(ptr, len));
+
+        // Values are NaN-boxed: numbers as themselves, other values as an
+        // index into the values table with a type flag
+        const goUnbox = function(ref) {
+            goReinterpret.setBigInt64(0, ref, true);
+            const number = goReinterpret.getFloat64(0, true);
+            if (number === 0) {
+                return undefined;
+            }
+            if (!isNaN(number)) {
+                return number;
+            }
+            return goState.values[ref & 0xffffffffn];
+        };
+        const goBox = function(value) {
+            const nanHead = 0x7FF80000n;
+            if (typeof value === 'number') {
+                if (isNaN(value)) {
+                    return nanHead << 32n;
+                }
+                if (value === 0) {
+                    return (nanHead << 32n) | 1n;
+                }
+                goReinterpret.setFloat64(0, value, true);
+                return goReinterpret.getBigInt64(0, true);
+            }
+            switch (value) {
+                case undefined:
+                    return 0n;
+                case null:
+                    return (nanHead << 32n) | 2n;
+                case true:
+                    return (nanHead << 32n) | 3n;
+                case false:
+                    return (nanHead << 32n) | 4n;
+            }
+            let id = goState.ids.get(value);
+            if (id === undefined) {
+                id = goState.idPool.pop();
+                if (id === undefined) {
+                    id = BigInt(goState.values.length);
+                }
+                goState.values[id] = value;
+                goState.refCounts[id] = 0;
+                goState.ids.set(value, id);
+            }
+            goState.refCounts[id]++;
+            const typeFlag = { string: 2n, symbol: 3n, function: 4n }[typeof value] || 1n;
+            return id | ((nanHead | typeFlag) << 32n);
+        };
+        const goStore = (addr, value) => goMemory().setBigUint64(addr, goBox(value), true);
+        const goLoadValues = function(ptr, len) {
+            const values = new Array(len);
+            for (let i = 0; i < len; i++) {
+                values[i] = goUnbox(goMemory().getBigUint64(ptr + i * 8, true));
+            }
+            return values;
+        };
+        // Result of a call at retAddr, and whether it returned rather than threw
+        const goCall = function(retAddr, call) {
+            try {
+                goStore(retAddr, call());
+                goMemory().setUint8(retAddr + 8, 1);
+            } catch (e) {
+                goStore(retAddr, e);
+                goMemory().setUint8(retAddr + 8, 0);
+            }
+        };
+        const goOutput = { 1: [], 2: [] };
+        const goSyscalls = {
+            'runtime.ticks': () => goTimeOrigin + performance.now(),
+            'runtime.sleepTicks': function(timeout) {
+                setTimeout(suspendable(function() {
+                    try {
+                        goState.exports.go_scheduler();
+                    } catch (e) {
+                        if (e !== goExit) {
+                            throw e;
+                        }
+                    }
+                }), timeout);
+            },
+            'syscall/js.finalizeRef': function(ref) {
+                const id = ref & 0xffffffffn;
+                if (goState.refCounts[id] === undefined) {
+                    return;
+                }
+                if (--goState.refCounts[id] === 0) {
+                    goState.ids.delete(goState.values[id]);
+                    goState.values[id] = null;
+                    goState.idPool.push(id);
+                }
+            },
+            'syscall/js.stringVal': (ptr, len) => goBox(goString(ptr, len)),
+            'syscall/js.valueGet': (ref, ptr, len) => goBox(Reflect.get(goUnbox(ref), goString(ptr, len))),
+            'syscall/js.valueSet': function(ref, ptr, len, value) {
+                Reflect.set(goUnbox(ref), goString(ptr, len), goUnbox(value));
+            },
+            'syscall/js.valueDelete': function(ref, ptr, len) {
+                Reflect.deleteProperty(goUnbox(ref), goString(ptr, len));
+            },
+            'syscall/js.valueIndex': (ref, i) => goBox(Reflect.get(goUnbox(ref), i)),
+            'syscall/js.valueSetIndex': function(ref, i, value) {
+                Reflect.set(goUnbox(ref), i, goUnbox(value));
+            },
+            'syscall/js.valueCall': function(retAddr, ref, ptr, len, argsPtr, argsLen) {
+                goCall(retAddr, function() {
+                    const target = goUnbox(ref);
+                    return Reflect.apply(Reflect.get(target, goString(ptr, len)), target, goLoadValues(argsPtr, argsLen));
+                });
+            },
+            'syscall/js.valueInvoke': function(retAddr, ref, argsPtr, argsLen) {
+                goCall(retAddr, () => Reflect.apply(goUnbox(ref), undefined, goLoadValues(argsPtr, argsLen)));
+            },
+            'syscall/js.valueNew': function(retAddr, ref, argsPtr, argsLen) {
+                goCall(retAddr, () => Reflect.construct(goUnbox(ref), goLoadValues(argsPtr, argsLen)));
+            },
+            'syscall/js.valueLength': ref => goUnbox(ref).length,
+            'syscall/js.valuePrepareString': function(retAddr, ref) {
+                const bytes = goEncoder.encode(String(goUnbox(ref)));
+                goStore(retAddr, bytes);
+                goMemory().setInt32(retAddr + 8, bytes.length, true);
+            },
+            'syscall/js.valueLoadString': function(ref, ptr, len) {
+                goBytes(ptr, len).set(goUnbox(ref));
+            },
+            'syscall/js.valueInstanceOf': (ref, type) => goUnbox(ref) instanceof goUnbox(type),
+            'syscall/js.copyBytesToGo': function(retAddr, ptr, len, cap, source) {
+                const bytes = goUnbox(source);
+                if (!(bytes instanceof Uint8Array || bytes instanceof Uint8ClampedArray)) {
+                    goMemory().setUint8(retAddr + 4, 0);
+                    return;
+                }
+                const copied = bytes.subarray(0, len);
+                goBytes(ptr, len).set(copied);
+                goMemory().setUint32(retAddr, copied.length, true);
+                goMemory().setUint8(retAddr + 4, 1);
+            },
+            'syscall/js.copyBytesToJS': function(retAddr, target, ptr, len) {
+                const bytes = goUnbox(target);
+                if (!(bytes instanceof Uint8Array || bytes instanceof Uint8ClampedArray)) {
+                    goMemory().setUint8(retAddr + 4, 0);
+                    return;
+                }
+                const copied = goBytes(ptr, len).subarray(0, bytes.length);
+                bytes.set(copied);
+                goMemory().setUint32(retAddr, copied.length, true);
+                goMemory().setUint8(retAddr + 4, 1);
+            }
+        };
+        const goImports = {
+            gojs: goSyscalls,
+            env: goSyscalls,
+            wasi_snapshot_preview1: {
+                fd_write: function(fd, iovs, iovsLen, writtenPtr) {
+                    const memory = goMemory();
+                    const line = goOutput[fd];
+                    let written = 0;
+                    for (let i = 0; i < iovsLen; i++) {
+                        const ptr = memory.getUint32(iovs + i * 8, true);
+                        const len = memory.getUint32(iovs + i * 8 + 4, true);
+                        written += len;
+                        for (const byte of goBytes(ptr, len)) {
+                            if (!line || byte === 13) {
+                                continue;
+                            }
+                            if (byte === 10) {
+                                (fd === 2 ? console.error : console.log)(goDecoder.decode(new Uint8Array(line)));
+                                line.length = 0;
+                            } else {
+                                line.push(byte);
+                            }
+                        }
+                    }
+                    memory.setUint32(writtenPtr, written, true);
+                    return 0;
+                },
+                fd_close: () => 0,
+                fd_fdstat_get: () => 0,
+                fd_seek: () => 0,
+                proc_exit: function(code) {
+                    goState.exited = true;
+                    goState.exitCode = code;
+                    throw goExit;
+                },
+                random_get: function(ptr, len) {
+                    for (let offset = 0; offset < len; offset += 65536) {
+                        crypto.getRandomValues(goBytes(ptr + offset, Math.min(65536, len - offset)));
+                    }
+                    return 0;
+                }
+            }
+        };"#,
+        r#"
+
+                    // TinyGo program: main runs now, its callbacks later
+                    go.start(result.instance);"#,
+    )
+}
+
+/// JavaScript reporting that the module of `filename` was built by Go, see
+/// [`GO_UNSUPPORTED`]
+pub fn unsupported_js(filename: &str) -> String {
+    log::warn!(
+        target: LOG_TARGET, phase = "toolchain", filename;
+        "{} in {}", GO_UNSUPPORTED, filename
+    );
+    format!(
+        r#"
+
+        // Module built by Go, whose imports only its own wasm_exec.js provides
+        console.error('WASM: ' + {message});
+        dispatchWasmError({message});
+        return;"#,
+        message = embed::script_json(&GO_UNSUPPORTED),
+    )
+}
@@ -0,0 +1,119 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Call tracing
+//!
+//! With `data-trace`, exported functions (or with `data-trace="all"`, every
+//! function the module defines) call an import in [`IMPORT_MODULE`] on entry,
+//! and the loader logs each call with the function's name and arguments.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasm_encoder::reencode;
+
+use super::LOG_TARGET;
+use super::embed;
+use super::instrument::{self, Calls, Functions};
+
+/// Import module of the trace calls, provided by the loader
+pub const IMPORT_MODULE: &str = "__wasm_trace";
+
+/// Functions traced with `data-trace`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Trace {
+    #[default]
+    Off,
+    /// Exported functions, when called from JavaScript or from the module
+    Exports,
+    All,
+}
+
+impl Trace {
+    /// Parse a `data-trace` attribute value: `exports` (or no value), `all`,
+    /// or `0`/`false` to disable
+    pub fn parse(value: &str) -> Option<Trace> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "exports" => Some(Trace::Exports),
+            "all" => Some(Trace::All),
+            "0" | "false" => Some(Trace::Off),
+            _ => None,
+        }
+    }
+}
+
+/// What the loader needs to provide the trace imports and name the calls
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TraceLayout {
+    pub imports: Vec<String>,
+    /// Names of the traced functions by index
+    pub names: BTreeMap<u32, String>,
+}
+
+/// Add the trace calls to `binary`
+/// Returns `None` if `trace` is [`Trace::Off`]
+pub fn instrument(
+    binary: &mut Vec<u8>,
+    trace: Trace,
+) -> Result<Option<TraceLayout>, reencode::Error> {
+    let functions = match trace {
+        Trace::Off => return Ok(None),
+        Trace::Exports => Functions::Exported,
+        Trace::All => Functions::All,
+    };
+    let names = instrument::function_names(binary)?;
+    let instrumented = instrument::add_calls(
+        binary,
+        &Calls {
+            module: IMPORT_MODULE,
+            entry: "call",
+            exit: None,
+            functions,
+            arguments: true,
+        },
+    )?;
+
+    log::info!(
+        target: LOG_TARGET, phase = "trace", functions = instrumented.functions.len();
+        "Tracing {} functions", instrumented.functions.len()
+    );
+    Ok(Some(TraceLayout {
+        imports: instrumented.imports,
+        names: instrumented
+            .functions
+            .into_iter()
+            .map(|index| (index, instrument::display_name(&names, index)))
+            .collect(),
+    }))
+}
+
+/// JavaScript logging the calls of the functions of `layout`
+pub fn loader_js(layout: &TraceLayout) -> String {
+    format!(
+        r#"
+
+        // Call tracing (data-trace)
+        const wasmTrace = {};
+        const formatTraceValue = function(value) {{
+            try {{
+                return typeof value === 'bigint' ? value + 'n' : String(value);
+            }} catch (e) {{
+                // GC references cannot be converted
+                return '[' + typeof value + ']';
+            }}
+        }};
+        const traceCall = function(index, ...args) {{
+            console.log('WASM trace: ' + wasmTrace.names[index] + '(' + args.map(formatTraceValue).join(', ') + ')');
+        }};
+        importObject['{module}'] = {{}};
+        for (const name of wasmTrace.imports) {{
+            importObject['{module}'][name] = traceCall;
+        }}"#,
+        embed::script_json(layout),
+        module = IMPORT_MODULE,
+    )
+}
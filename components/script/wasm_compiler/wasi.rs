@@ -0,0 +1,1065 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! WASI preview 1
+//!
+//! Modules built for `wasm32-wasi` import their system interface from
+//! [`MODULE`]. The loader provides its functions for those the page, or the
+//! runtime of the module's toolchain, does not:
+//!
+//! - `clock_time_get` and `clock_res_get`: the realtime clock from the time
+//!   origin of the page and the monotonic and CPU time clocks from
+//!   `performance.now()`, in nanoseconds
+//! - `random_get` from `crypto.getRandomValues()`
+//! - `poll_oneoff` for clock subscriptions: it returns once the earliest
+//!   times out, waiting with `Atomics.wait` where the page may block, and by
+//!   spinning otherwise; other subscriptions are reported as unsupported
+//!   without waiting
+//! - the `fd_*` and `path_*` functions on the directories given by
+//!   `data-wasi-preopens`, see [`Preopen`], with stdout and stderr written
+//!   where `data-stdout` and `data-stderr` say, see [`Output`]
+//! - `args_get` and `environ_get`: the program name, the name of the script,
+//!   followed by `data-wasi-args`, a JSON array of strings, and the variables
+//!   of `data-wasi-env`, a JSON object of strings
+//! - `proc_exit`, which throws a `WasiExit` with the status out of the export
+//!   that exited
+//!
+//! Files are held in memory, the module being unable to wait for the origin
+//! private file system: directories stored there are loaded before the
+//! module is instantiated, and what it changes is written back once it
+//! returns to the event loop, or when the page awaits
+//! `window.__wasmModules[name].filesystem.flush()`. Without the origin
+//! private file system they are kept in memory. Symbolic links are not
+//! supported.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::LOG_TARGET;
+use super::embed;
+use super::imports;
+use super::options::CompileOptions;
+use super::options::is_identifier;
+
+/// Module of the imports of WASI preview 1
+pub const MODULE: &str = "wasi_snapshot_preview1";
+
+/// Whether `binary` imports functions of WASI preview 1
+pub fn imports_wasi(binary: &[u8]) -> bool {
+    imports::declared_imports(binary)
+        .iter()
+        .any(|(module, _, _)| module == MODULE)
+}
+
+/// Where a preopened directory keeps its files
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// The directory of the same path under `wasi/` in the origin private
+    /// file system, shared by the modules of the origin
+    Opfs,
+    /// Memory, for the lifetime of the instance
+    Memory,
+}
+
+/// A directory the module can open files in, given as its absolute path and
+/// storage: `/data` (stored in the origin private file system) or
+/// `/tmp=memory`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Preopen {
+    pub path: String,
+    pub storage: Storage,
+}
+
+impl Preopen {
+    /// Parse preopens separated by commas or spaces; invalid ones are
+    /// reported and skipped, as are paths given twice
+    pub fn parse_list(value: &str) -> Vec<Preopen> {
+        let mut preopens: Vec<Preopen> = Vec::new();
+        for entry in value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|entry| !entry.is_empty())
+        {
+            let (path, storage) = entry.split_once('=').unwrap_or((entry, "opfs"));
+            let storage = match storage.to_ascii_lowercase().as_str() {
+                "opfs" => Storage::Opfs,
+                "memory" => Storage::Memory,
+                _ => {
+                    log::warn!(
+                        target: LOG_TARGET,
+                        phase = "options", attribute = "data-wasi-preopens", preopen:% = entry;
+                        "Ignoring preopen {:?} with unknown storage", entry
+                    );
+                    continue;
+                },
+            };
+            let Some(path) = normalize(path) else {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-wasi-preopens", preopen:% = entry;
+                    "Ignoring preopen {:?}, not an absolute path", entry
+                );
+                continue;
+            };
+            if preopens.iter().any(|preopen| preopen.path == path) {
+                log::warn!(
+                    target: LOG_TARGET,
+                    phase = "options", attribute = "data-wasi-preopens", preopen:% = entry;
+                    "Ignoring preopen {:?}, {} is already preopened", entry, path
+                );
+                continue;
+            }
+            preopens.push(Preopen { path, storage });
+        }
+        preopens
+    }
+}
+
+/// An absolute path without empty, `.` or trailing components, or `None` if
+/// it is relative or has `..` components
+fn normalize(path: &str) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    let mut normalized = String::new();
+    for component in rest.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        if component == ".." {
+            return None;
+        }
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    Some(if normalized.is_empty() {
+        "/".to_string()
+    } else {
+        normalized
+    })
+}
+
+/// Parse `data-wasi-args`, a JSON array of strings
+pub fn parse_args(value: &str) -> Option<Vec<String>> {
+    let args: Vec<String> = serde_json::from_str(value).ok()?;
+    args.iter().all(|arg| !arg.contains('\0')).then_some(args)
+}
+
+/// Parse `data-wasi-env`, a JSON object of strings, sorted by name
+pub fn parse_env(value: &str) -> Option<Vec<(String, String)>> {
+    let env: BTreeMap<String, String> = serde_json::from_str(value).ok()?;
+    env.iter()
+        .all(|(name, value)| {
+            !name.is_empty() && !name.contains(['=', '\0']) && !value.contains('\0')
+        })
+        .then(|| env.into_iter().collect())
+}
+
+/// The arguments of a module: the name of its script, then `args`
+pub fn argv(filename: &str, args: &[String]) -> Vec<String> {
+    let program = filename.rsplit('/').next().unwrap_or(filename);
+    std::iter::once(program.to_string())
+        .chain(args.iter().cloned())
+        .collect()
+}
+
+/// The environment of a module as `NAME=value` strings
+pub fn environ(env: &[(String, String)]) -> Vec<String> {
+    env.iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect()
+}
+
+/// Where a module's stdout or stderr goes
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// The console, line by line: stdout logged, stderr as errors
+    #[default]
+    Console,
+    /// The text of the element a CSS selector matches, e.g. `#terminal`,
+    /// appended as written; the value of a `<textarea>`
+    Element(String),
+    /// A function of the page, e.g. `callback:terminal.write`, called with
+    /// the text as written and the descriptor
+    Callback(String),
+}
+
+impl Output {
+    /// Parse `console`, `callback:` and the path of a function on `window`,
+    /// or a CSS selector
+    pub fn parse(value: &str) -> Option<Output> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("console") {
+            return Some(Output::Console);
+        }
+        if let Some(path) = value.strip_prefix("callback:") {
+            let path = path.trim();
+            return path
+                .split('.')
+                .all(is_identifier)
+                .then(|| Output::Callback(path.to_string()));
+        }
+        (!value.is_empty()).then(|| Output::Element(value.to_string()))
+    }
+}
+
+/// JavaScript of WASI preview 1 for a module loaded from `filename`, with
+/// the arguments, environment, files and output of `options`: the imports,
+/// resolved before the module is instantiated, and what they need of its
+/// exports once it is
+pub fn loader_js(filename: &str, options: &CompileOptions) -> (String, &'static str) {
+    (
+        String::from(
+            r#"
+        let wasiMemory = null;
+        const wasiData = () => new DataView(wasiMemory.buffer);
+        const WASI_ESUCCESS = 0;
+        const WASI_EINVAL = 28;
+        const WASI_ENOTSUP = 58;
+        // Nanoseconds of a time in milliseconds, without losing those below one
+        const wasiNanos = ms => BigInt(Math.floor(ms)) * 1000000n + BigInt(Math.round((ms % 1) * 1e6));
+        // Realtime, monotonic, process and thread CPU time
+        const wasiClocks = [
+            () => wasiNanos(performance.timeOrigin + performance.now()),
+            () => wasiNanos(performance.now()),
+            () => wasiNanos(performance.now()),
+            () => wasiNanos(performance.now())
+        ];
+        // The page may not block where it could deadlock, so waiting spins there
+        const wasiSleep = function(ms) {
+            try {
+                Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, ms);
+                return;
+            } catch (e) {}
+            const end = performance.now() + ms;
+            while (performance.now() < end) {}
+        };
+        const wasiPreview1 = {
+            clock_time_get: function(id, precision, timePtr) {
+                if (!wasiClocks[id]) {
+                    return WASI_EINVAL;
+                }
+                wasiData().setBigUint64(timePtr, wasiClocks[id](), true);
+                return WASI_ESUCCESS;
+            },
+            clock_res_get: function(id, resolutionPtr) {
+                if (!wasiClocks[id]) {
+                    return WASI_EINVAL;
+                }
+                wasiData().setBigUint64(resolutionPtr, 1000n, true);
+                return WASI_ESUCCESS;
+            },
+            random_get: function(ptr, len) {
+                for (let offset = 0; offset < len; offset += 65536) {
+                    crypto.getRandomValues(new Uint8Array(wasiMemory.buffer, ptr + offset, Math.min(65536, len - offset)));
+                }
+                return WASI_ESUCCESS;
+            },
+            // Subscriptions of 48 bytes: userdata, tag, then for clocks the id,
+            // timeout, precision and flags; events of 32 bytes
+            poll_oneoff: function(inPtr, outPtr, count, eventsPtr) {
+                const view = wasiData();
+                const clocks = [];
+                const others = [];
+                for (let i = 0; i < count; i++) {
+                    const at = inPtr + i * 48;
+                    const subscription = { userdata: view.getBigUint64(at, true), tag: view.getUint8(at + 8) };
+                    if (subscription.tag !== 0) {
+                        others.push(subscription);
+                        continue;
+                    }
+                    const id = view.getUint32(at + 16, true);
+                    if (!wasiClocks[id]) {
+                        return WASI_EINVAL;
+                    }
+                    const timeout = view.getBigUint64(at + 24, true);
+                    const absolute = (view.getUint16(at + 40, true) & 1) !== 0;
+                    subscription.deadline = absolute ? timeout : wasiClocks[id]() + timeout;
+                    subscription.clock = wasiClocks[id];
+                    clocks.push(subscription);
+                }
+                const events = others.map(subscription => [subscription, WASI_ENOTSUP]);
+                if (events.length === 0 && clocks.length > 0) {
+                    const first = clocks.reduce((a, b) => (b.deadline - b.clock() < a.deadline - a.clock() ? b : a));
+                    const remaining = first.deadline - first.clock();
+                    if (remaining > 0n) {
+                        wasiSleep(Number(remaining) / 1e6);
+                    }
+                }
+                for (const subscription of clocks) {
+                    if (subscription.clock() >= subscription.deadline) {
+                        events.push([subscription, WASI_ESUCCESS]);
+                    }
+                }
+                events.forEach(function([subscription, error], i) {
+                    const at = outPtr + i * 32;
+                    view.setBigUint64(at, subscription.userdata, true);
+                    view.setUint16(at + 8, error, true);
+                    view.setUint8(at + 10, subscription.tag);
+                    view.setBigUint64(at + 16, 0n, true);
+                    view.setUint16(at + 24, 0, true);
+                });
+                view.setUint32(eventsPtr, events.length, true);
+                return WASI_ESUCCESS;
+            }
+        };"#,
+        ) + &format!(
+            r#"
+        const wasiPreopens = {preopens};
+        const wasiArgs = {args};
+        const wasiEnvironment = {environment};
+        const wasiStdout = {stdout};
+        const wasiStderr = {stderr};"#,
+            preopens = embed::script_safe(
+                &serde_json::to_string(&options.wasi_preopens).unwrap_or_default()
+            ),
+            args = embed::script_safe(
+                &serde_json::to_string(&argv(filename, &options.wasi_args)).unwrap_or_default()
+            ),
+            environment = embed::script_safe(
+                &serde_json::to_string(&environ(&options.wasi_env)).unwrap_or_default()
+            ),
+            stdout = embed::script_safe(
+                &serde_json::to_string(&options.wasi_stdout).unwrap_or_default()
+            ),
+            stderr = embed::script_safe(
+                &serde_json::to_string(&options.wasi_stderr).unwrap_or_default()
+            ),
+        ) + r#"
+        // Files of the directories preopened with data-wasi-preopens, held in
+        // memory; those stored in the origin private file system are loaded
+        // before instantiating and written back once the module changed them
+        const WASI_EBADF = 8;
+        const WASI_EEXIST = 20;
+        const WASI_EISDIR = 31;
+        const WASI_ENOENT = 44;
+        const WASI_ENOTDIR = 54;
+        const WASI_ENOTEMPTY = 55;
+        const WASI_EPERM = 63;
+        const WASI_ESPIPE = 70;
+        const WASI_ENOTCAPABLE = 76;
+        const WASI_CHARACTER_DEVICE = 2;
+        const WASI_DIRECTORY = 3;
+        const WASI_REGULAR_FILE = 4;
+        const WASI_RIGHTS = 0x1FFFFFFFn;
+        const wasiFsEncoder = new TextEncoder();
+        const wasiFsDecoder = new TextDecoder('utf-8');
+        const wasiHeap = () => new Uint8Array(wasiMemory.buffer);
+        let wasiNextInode = 1n;
+        const wasiNode = function(entries, data) {
+            const now = wasiClocks[0]();
+            return { ino: wasiNextInode++, entries: entries, data: data, size: data ? data.length : 0, dirty: !entries, atim: now, mtim: now };
+        };
+        const wasiNewFile = data => wasiNode(null, data || new Uint8Array(0));
+        const wasiNewDirectory = () => wasiNode(new Map(), null);
+        const wasiFiletype = node => node.entries ? WASI_DIRECTORY : WASI_REGULAR_FILE;
+        // Files and directories moved or linked are written again under their new name
+        const wasiTouch = function(node) {
+            if (node.entries) {
+                node.entries.forEach(wasiTouch);
+            } else {
+                node.dirty = true;
+            }
+        };
+
+        // Open files by descriptor: stdin, stdout and stderr, then the preopens
+        const wasiFds = new Map([[0, { stdio: 0 }], [1, { stdio: 1 }], [2, { stdio: 2 }]]);
+        const wasiOpen = function(entry) {
+            let fd = 3;
+            while (wasiFds.has(fd)) {
+                fd++;
+            }
+            wasiFds.set(fd, entry);
+            return fd;
+        };
+        // Where stdout and stderr go, from data-stdout and data-stderr: the
+        // console line by line, the text of an element, or a function of the
+        // page called with the text and the descriptor
+        const wasiSink = function(fd, target, log) {
+            const decoder = new TextDecoder('utf-8');
+            let line = '';
+            const fallBack = function(reason) {
+                console.warn('WASM: WASI: ' + reason + ', writing ' + (fd === 1 ? 'stdout' : 'stderr') + ' to the console');
+                target = 'console';
+            };
+            const write = function(text) {
+                if (text === '') {
+                    return;
+                }
+                if (target.element !== undefined) {
+                    const element = document.querySelector(target.element);
+                    if (!element) {
+                        fallBack('no element matches ' + target.element);
+                        return write(text);
+                    }
+                    if (element.tagName === 'TEXTAREA') {
+                        element.value += text;
+                    } else {
+                        element.append(text);
+                    }
+                    element.scrollTop = element.scrollHeight;
+                    return;
+                }
+                if (target.callback !== undefined) {
+                    const callback = target.callback.split('.').reduce(
+                        (scope, name) => (scope === null || scope === undefined ? undefined : scope[name]), window);
+                    if (typeof callback !== 'function') {
+                        fallBack(target.callback + ' is not a function');
+                        return write(text);
+                    }
+                    callback(text, fd);
+                    return;
+                }
+                const lines = (line + text).split('\n');
+                line = lines.pop();
+                lines.forEach(text => log(text));
+            };
+            return {
+                write: bytes => write(decoder.decode(bytes, { stream: true })),
+                // The rest of an incomplete line or character
+                flush: function() {
+                    write(decoder.decode());
+                    if (line) {
+                        log(line);
+                        line = '';
+                    }
+                }
+            };
+        };
+        const wasiOutput = { 1: wasiSink(1, wasiStdout, console.log), 2: wasiSink(2, wasiStderr, console.error) };
+
+        const wasiOpfsDirectory = function(path) {
+            return path.split('/').filter(Boolean).reduce(
+                (parent, name) => parent.then(directory => directory.getDirectoryHandle(name, { create: true })),
+                Promise.resolve()
+                    .then(() => navigator.storage.getDirectory())
+                    .then(root => root.getDirectoryHandle('wasi', { create: true })));
+        };
+        const wasiOpfsLoad = async function(handle, directory) {
+            for await (const [name, child] of handle.entries()) {
+                if (child.kind === 'directory') {
+                    const node = wasiNewDirectory();
+                    directory.entries.set(name, node);
+                    await wasiOpfsLoad(child, node);
+                } else {
+                    const file = await child.getFile();
+                    const node = wasiNewFile(new Uint8Array(await file.arrayBuffer()));
+                    node.dirty = false;
+                    node.mtim = BigInt(file.lastModified) * 1000000n;
+                    directory.entries.set(name, node);
+                }
+            }
+        };
+        // Make the stored directory match the one in memory, writing the files changed
+        const wasiOpfsStore = async function(handle, directory) {
+            const stored = [];
+            for await (const [name, child] of handle.entries()) {
+                stored.push([name, child.kind]);
+            }
+            for (const [name, kind] of stored) {
+                const node = directory.entries.get(name);
+                if (!node || (kind === 'directory') !== !!node.entries) {
+                    await handle.removeEntry(name, { recursive: true });
+                }
+            }
+            for (const [name, node] of directory.entries) {
+                if (node.entries) {
+                    await wasiOpfsStore(await handle.getDirectoryHandle(name, { create: true }), node);
+                } else if (node.dirty) {
+                    node.dirty = false;
+                    const data = node.data.slice(0, node.size);
+                    const writable = await (await handle.getFileHandle(name, { create: true })).createWritable();
+                    await writable.write(data);
+                    await writable.close();
+                }
+            }
+        };
+        const wasiFilesystem = {
+            preopens: [],
+            flushing: Promise.resolve(),
+            // Write what changed to the origin private file system
+            flush: function() {
+                wasiFilesystem.flushing = wasiFilesystem.flushing.then(function() {
+                    return Promise.all(wasiFilesystem.preopens.filter(preopen => preopen.dirty).map(function(preopen) {
+                        preopen.dirty = false;
+                        return wasiOpfsStore(preopen.handle, preopen.root);
+                    }));
+                }).catch(function(e) {
+                    console.error('WASM: WASI: writing to the origin private file system failed:', e);
+                });
+                return wasiFilesystem.flushing;
+            }
+        };
+        const wasiChanged = function(preopen) {
+            if (!preopen.handle || preopen.dirty) {
+                return;
+            }
+            preopen.dirty = true;
+            setTimeout(wasiFilesystem.flush, 0);
+        };
+        wasiFilesystem.loaded = Promise.all(wasiPreopens.map(function(preopen) {
+            const entry = { path: preopen.path, root: wasiNewDirectory(), handle: null, dirty: false };
+            if (preopen.storage !== 'opfs') {
+                return entry;
+            }
+            return wasiOpfsDirectory(preopen.path)
+                .then(function(handle) {
+                    entry.handle = handle;
+                    return wasiOpfsLoad(handle, entry.root);
+                })
+                .catch(function(e) {
+                    console.warn('WASM: WASI: keeping ' + preopen.path + ' in memory, the origin private file system is not available:', e);
+                    entry.handle = null;
+                    entry.root = wasiNewDirectory();
+                })
+                .then(() => entry);
+        })).then(function(entries) {
+            for (const entry of entries) {
+                wasiFilesystem.preopens.push(entry);
+                wasiOpen({ node: entry.root, preopen: entry, name: entry.path, position: 0, flags: 0 });
+            }
+        });
+
+        // The node a path names relative to a directory descriptor, with its
+        // parent, name and the directories above; paths cannot leave the preopen
+        const wasiResolve = function(fd, ptr, len) {
+            const directory = wasiFds.get(fd);
+            if (!directory || !directory.node) {
+                return { error: WASI_EBADF };
+            }
+            if (!directory.node.entries) {
+                return { error: WASI_ENOTDIR };
+            }
+            const path = wasiFsDecoder.decode(wasiHeap().subarray(ptr, ptr + len));
+            if (path === '') {
+                return { error: WASI_ENOENT };
+            }
+            if (path.startsWith('/')) {
+                return { error: WASI_ENOTCAPABLE };
+            }
+            const names = path.split('/').filter(name => name !== '' && name !== '.');
+            const ancestors = [directory.node];
+            let name = '.';
+            for (let i = 0; i < names.length; i++) {
+                const last = i === names.length - 1;
+                if (names[i] === '..') {
+                    if (ancestors.length === 1) {
+                        return { error: WASI_ENOTCAPABLE };
+                    }
+                    ancestors.pop();
+                    continue;
+                }
+                if (last) {
+                    name = names[i];
+                    break;
+                }
+                const next = ancestors[ancestors.length - 1].entries.get(names[i]);
+                if (!next) {
+                    return { error: WASI_ENOENT };
+                }
+                if (!next.entries) {
+                    return { error: WASI_ENOTDIR };
+                }
+                ancestors.push(next);
+            }
+            const parent = ancestors[ancestors.length - 1];
+            return {
+                preopen: directory.preopen,
+                parent: parent,
+                name: name,
+                node: name === '.' ? parent : parent.entries.get(name),
+                ancestors: ancestors,
+                directoryOnly: path.endsWith('/')
+            };
+        };
+        const wasiIovs = function(iovs, count) {
+            const view = wasiData();
+            const buffers = [];
+            for (let i = 0; i < count; i++) {
+                buffers.push([view.getUint32(iovs + i * 8, true), view.getUint32(iovs + i * 8 + 4, true)]);
+            }
+            return buffers;
+        };
+        const wasiResize = function(node, size) {
+            if (size > node.data.length) {
+                const data = new Uint8Array(Math.max(size, node.data.length * 2));
+                data.set(node.data.subarray(0, node.size));
+                node.data = data;
+            } else if (size < node.size) {
+                node.data.fill(0, size, node.size);
+            }
+            node.size = size;
+        };
+        const wasiReadFile = function(node, iovs, count, position) {
+            const heap = wasiHeap();
+            let read = 0;
+            for (const [ptr, len] of wasiIovs(iovs, count)) {
+                const start = Math.min(position + read, node.size);
+                const chunk = node.data.subarray(start, Math.min(start + len, node.size));
+                heap.set(chunk, ptr);
+                read += chunk.length;
+                if (chunk.length < len) {
+                    break;
+                }
+            }
+            node.atim = wasiClocks[0]();
+            return read;
+        };
+        const wasiWriteFile = function(entry, iovs, count, position) {
+            const node = entry.node;
+            const heap = wasiHeap();
+            let written = 0;
+            for (const [ptr, len] of wasiIovs(iovs, count)) {
+                if (position + written + len > node.size) {
+                    wasiResize(node, position + written + len);
+                }
+                node.data.set(heap.subarray(ptr, ptr + len), position + written);
+                written += len;
+            }
+            node.dirty = true;
+            node.mtim = wasiClocks[0]();
+            wasiChanged(entry.preopen);
+            return written;
+        };
+        const wasiWriteOutput = function(fd, iovs, count) {
+            const output = wasiOutput[fd];
+            const heap = wasiHeap();
+            let written = 0;
+            for (const [ptr, len] of wasiIovs(iovs, count)) {
+                output.write(heap.subarray(ptr, ptr + len));
+                written += len;
+            }
+            return written;
+        };
+        // A descriptor of an open file rather than a directory or stdio, or the errno
+        const wasiFileEntry = function(fd) {
+            const entry = wasiFds.get(fd);
+            return !entry ? WASI_EBADF : entry.stdio !== undefined ? WASI_ESPIPE : entry.node.entries ? WASI_EISDIR : entry;
+        };
+        // Filestat: device, inode, filetype, links, size and access, modification
+        // and status change times
+        const wasiStoreFilestat = function(ptr, node) {
+            const view = wasiData();
+            view.setBigUint64(ptr, 0n, true);
+            view.setBigUint64(ptr + 8, node ? node.ino : 0n, true);
+            view.setUint8(ptr + 16, node ? wasiFiletype(node) : WASI_CHARACTER_DEVICE);
+            view.setBigUint64(ptr + 24, 1n, true);
+            view.setBigUint64(ptr + 32, BigInt(node && !node.entries ? node.size : 0), true);
+            view.setBigUint64(ptr + 40, node ? node.atim : 0n, true);
+            view.setBigUint64(ptr + 48, node ? node.mtim : 0n, true);
+            view.setBigUint64(ptr + 56, node ? node.mtim : 0n, true);
+            return WASI_ESUCCESS;
+        };
+        const wasiSetTimes = function(node, atim, mtim, flags) {
+            const now = wasiClocks[0]();
+            if (flags & 2) {
+                node.atim = now;
+            } else if (flags & 1) {
+                node.atim = atim;
+            }
+            if (flags & 8) {
+                node.mtim = now;
+            } else if (flags & 4) {
+                node.mtim = mtim;
+            }
+            return WASI_ESUCCESS;
+        };
+        const wasiSeek = function(fd, position, pointer) {
+            const entry = wasiFileEntry(fd);
+            if (typeof entry === 'number') {
+                return entry;
+            }
+            if (position < 0) {
+                return WASI_EINVAL;
+            }
+            entry.position = position;
+            wasiData().setBigUint64(pointer, BigInt(position), true);
+            return WASI_ESUCCESS;
+        };
+
+        Object.assign(wasiPreview1, {
+            fd_write: function(fd, iovs, count, writtenPtr) {
+                const entry = wasiFds.get(fd);
+                let written;
+                if (entry && (entry.stdio === 1 || entry.stdio === 2)) {
+                    written = wasiWriteOutput(entry.stdio, iovs, count);
+                } else {
+                    const file = wasiFileEntry(fd);
+                    if (typeof file === 'number') {
+                        return file === WASI_ESPIPE ? WASI_EBADF : file;
+                    }
+                    if (file.flags & 1) {
+                        file.position = file.node.size;
+                    }
+                    written = wasiWriteFile(file, iovs, count, file.position);
+                    file.position += written;
+                }
+                wasiData().setUint32(writtenPtr, written, true);
+                return WASI_ESUCCESS;
+            },
+            fd_pwrite: function(fd, iovs, count, offset, writtenPtr) {
+                const file = wasiFileEntry(fd);
+                if (typeof file === 'number') {
+                    return file;
+                }
+                wasiData().setUint32(writtenPtr, wasiWriteFile(file, iovs, count, Number(offset)), true);
+                return WASI_ESUCCESS;
+            },
+            // stdin is empty
+            fd_read: function(fd, iovs, count, readPtr) {
+                const entry = wasiFds.get(fd);
+                let read = 0;
+                if (!entry || entry.stdio === 0) {
+                    if (!entry) {
+                        return WASI_EBADF;
+                    }
+                } else {
+                    const file = wasiFileEntry(fd);
+                    if (typeof file === 'number') {
+                        return file === WASI_ESPIPE ? WASI_EBADF : file;
+                    }
+                    read = wasiReadFile(file.node, iovs, count, file.position);
+                    file.position += read;
+                }
+                wasiData().setUint32(readPtr, read, true);
+                return WASI_ESUCCESS;
+            },
+            fd_pread: function(fd, iovs, count, offset, readPtr) {
+                const file = wasiFileEntry(fd);
+                if (typeof file === 'number') {
+                    return file;
+                }
+                wasiData().setUint32(readPtr, wasiReadFile(file.node, iovs, count, Number(offset)), true);
+                return WASI_ESUCCESS;
+            },
+            fd_seek: function(fd, offset, whence, newOffsetPtr) {
+                const file = wasiFileEntry(fd);
+                if (typeof file === 'number') {
+                    return file;
+                }
+                const base = [0, file.position, file.node.size][whence];
+                return base === undefined ? WASI_EINVAL : wasiSeek(fd, base + Number(offset), newOffsetPtr);
+            },
+            fd_tell: function(fd, offsetPtr) {
+                const file = wasiFileEntry(fd);
+                return typeof file === 'number' ? file : wasiSeek(fd, file.position, offsetPtr);
+            },
+            fd_close: function(fd) {
+                return wasiFds.delete(fd) ? WASI_ESUCCESS : WASI_EBADF;
+            },
+            fd_renumber: function(fd, to) {
+                if (!wasiFds.has(fd) || !wasiFds.has(to)) {
+                    return WASI_EBADF;
+                }
+                wasiFds.set(to, wasiFds.get(fd));
+                wasiFds.delete(fd);
+                return WASI_ESUCCESS;
+            },
+            // Changes are written back once the module returns, which it cannot wait for
+            fd_sync: fd => wasiFds.has(fd) ? WASI_ESUCCESS : WASI_EBADF,
+            fd_datasync: fd => wasiFds.has(fd) ? WASI_ESUCCESS : WASI_EBADF,
+            fd_advise: fd => wasiFds.has(fd) ? WASI_ESUCCESS : WASI_EBADF,
+            fd_allocate: function(fd, offset, len) {
+                const file = wasiFileEntry(fd);
+                if (typeof file === 'number') {
+                    return file;
+                }
+                const size = Number(offset + len);
+                if (size > file.node.size) {
+                    wasiResize(file.node, size);
+                    file.node.dirty = true;
+                    wasiChanged(file.preopen);
+                }
+                return WASI_ESUCCESS;
+            },
+            // Fdstat: filetype, flags and rights
+            fd_fdstat_get: function(fd, ptr) {
+                const entry = wasiFds.get(fd);
+                if (!entry) {
+                    return WASI_EBADF;
+                }
+                const view = wasiData();
+                view.setUint8(ptr, entry.node ? wasiFiletype(entry.node) : WASI_CHARACTER_DEVICE);
+                view.setUint16(ptr + 2, entry.flags || 0, true);
+                view.setBigUint64(ptr + 8, WASI_RIGHTS, true);
+                view.setBigUint64(ptr + 16, WASI_RIGHTS, true);
+                return WASI_ESUCCESS;
+            },
+            fd_fdstat_set_flags: function(fd, flags) {
+                const entry = wasiFds.get(fd);
+                if (!entry) {
+                    return WASI_EBADF;
+                }
+                entry.flags = flags;
+                return WASI_ESUCCESS;
+            },
+            fd_fdstat_set_rights: fd => wasiFds.has(fd) ? WASI_ESUCCESS : WASI_EBADF,
+            fd_filestat_get: function(fd, ptr) {
+                const entry = wasiFds.get(fd);
+                return entry ? wasiStoreFilestat(ptr, entry.node) : WASI_EBADF;
+            },
+            fd_filestat_set_size: function(fd, size) {
+                const file = wasiFileEntry(fd);
+                if (typeof file === 'number') {
+                    return file;
+                }
+                wasiResize(file.node, Number(size));
+                file.node.dirty = true;
+                file.node.mtim = wasiClocks[0]();
+                wasiChanged(file.preopen);
+                return WASI_ESUCCESS;
+            },
+            fd_filestat_set_times: function(fd, atim, mtim, flags) {
+                const entry = wasiFds.get(fd);
+                return entry && entry.node ? wasiSetTimes(entry.node, atim, mtim, flags) : WASI_EBADF;
+            },
+            // Prestat: the tag of directories and the length of their path
+            fd_prestat_get: function(fd, ptr) {
+                const entry = wasiFds.get(fd);
+                if (!entry || !entry.preopen || entry.node !== entry.preopen.root || entry.name === undefined) {
+                    return WASI_EBADF;
+                }
+                const view = wasiData();
+                view.setUint8(ptr, 0);
+                view.setUint32(ptr + 4, wasiFsEncoder.encode(entry.name).length, true);
+                return WASI_ESUCCESS;
+            },
+            fd_prestat_dir_name: function(fd, ptr, len) {
+                const entry = wasiFds.get(fd);
+                if (!entry || entry.name === undefined) {
+                    return WASI_EBADF;
+                }
+                wasiHeap().set(wasiFsEncoder.encode(entry.name).subarray(0, len), ptr);
+                return WASI_ESUCCESS;
+            },
+            // Dirents: next cookie, inode, name length and type, then the name;
+            // the last one is cut off when the buffer is full
+            fd_readdir: function(fd, buf, bufLen, cookie, usedPtr) {
+                const entry = wasiFds.get(fd);
+                if (!entry || !entry.node) {
+                    return WASI_EBADF;
+                }
+                if (!entry.node.entries) {
+                    return WASI_ENOTDIR;
+                }
+                const children = [['.', entry.node], ['..', entry.node]].concat(Array.from(entry.node.entries));
+                const heap = wasiHeap();
+                let used = 0;
+                for (let i = Number(cookie); i < children.length && used < bufLen; i++) {
+                    const [name, node] = children[i];
+                    const nameBytes = wasiFsEncoder.encode(name);
+                    const dirent = new Uint8Array(24 + nameBytes.length);
+                    const view = new DataView(dirent.buffer);
+                    view.setBigUint64(0, BigInt(i + 1), true);
+                    view.setBigUint64(8, node.ino, true);
+                    view.setUint32(16, nameBytes.length, true);
+                    view.setUint8(20, wasiFiletype(node));
+                    dirent.set(nameBytes, 24);
+                    const copied = Math.min(dirent.length, bufLen - used);
+                    heap.set(dirent.subarray(0, copied), buf + used);
+                    used += copied;
+                }
+                wasiData().setUint32(usedPtr, used, true);
+                return WASI_ESUCCESS;
+            },
+            // Open flags: create, directory, exclusive and truncate
+            path_open: function(fd, lookupFlags, ptr, len, openFlags, rightsBase, rightsInheriting, fdFlags, fdPtr) {
+                const path = wasiResolve(fd, ptr, len);
+                if (path.error) {
+                    return path.error;
+                }
+                let node = path.node;
+                if (node && (openFlags & 1) && (openFlags & 4)) {
+                    return WASI_EEXIST;
+                }
+                if (!node) {
+                    if (!(openFlags & 1)) {
+                        return WASI_ENOENT;
+                    }
+                    if ((openFlags & 2) || path.directoryOnly) {
+                        return WASI_EISDIR;
+                    }
+                    node = wasiNewFile();
+                    path.parent.entries.set(path.name, node);
+                    wasiChanged(path.preopen);
+                }
+                if (((openFlags & 2) || path.directoryOnly) && !node.entries) {
+                    return WASI_ENOTDIR;
+                }
+                if (openFlags & 8) {
+                    if (node.entries) {
+                        return WASI_EISDIR;
+                    }
+                    wasiResize(node, 0);
+                    node.dirty = true;
+                    node.mtim = wasiClocks[0]();
+                    wasiChanged(path.preopen);
+                }
+                const opened = wasiOpen({ node: node, preopen: path.preopen, position: 0, flags: fdFlags });
+                wasiData().setUint32(fdPtr, opened, true);
+                return WASI_ESUCCESS;
+            },
+            path_create_directory: function(fd, ptr, len) {
+                const path = wasiResolve(fd, ptr, len);
+                if (path.error) {
+                    return path.error;
+                }
+                if (path.node) {
+                    return WASI_EEXIST;
+                }
+                path.parent.entries.set(path.name, wasiNewDirectory());
+                wasiChanged(path.preopen);
+                return WASI_ESUCCESS;
+            },
+            path_filestat_get: function(fd, lookupFlags, ptr, len, buf) {
+                const path = wasiResolve(fd, ptr, len);
+                return path.error || (path.node ? wasiStoreFilestat(buf, path.node) : WASI_ENOENT);
+            },
+            path_filestat_set_times: function(fd, lookupFlags, ptr, len, atim, mtim, flags) {
+                const path = wasiResolve(fd, ptr, len);
+                return path.error || (path.node ? wasiSetTimes(path.node, atim, mtim, flags) : WASI_ENOENT);
+            },
+            path_remove_directory: function(fd, ptr, len) {
+                const path = wasiResolve(fd, ptr, len);
+                if (path.error) {
+                    return path.error;
+                }
+                if (!path.node) {
+                    return WASI_ENOENT;
+                }
+                if (!path.node.entries) {
+                    return WASI_ENOTDIR;
+                }
+                if (path.name === '.') {
+                    return WASI_EINVAL;
+                }
+                if (path.node.entries.size > 0) {
+                    return WASI_ENOTEMPTY;
+                }
+                path.parent.entries.delete(path.name);
+                wasiChanged(path.preopen);
+                return WASI_ESUCCESS;
+            },
+            path_unlink_file: function(fd, ptr, len) {
+                const path = wasiResolve(fd, ptr, len);
+                if (path.error) {
+                    return path.error;
+                }
+                if (!path.node) {
+                    return WASI_ENOENT;
+                }
+                if (path.node.entries) {
+                    return WASI_EISDIR;
+                }
+                path.parent.entries.delete(path.name);
+                wasiChanged(path.preopen);
+                return WASI_ESUCCESS;
+            },
+            path_rename: function(fd, oldPtr, oldLen, newFd, newPtr, newLen) {
+                const from = wasiResolve(fd, oldPtr, oldLen);
+                const to = wasiResolve(newFd, newPtr, newLen);
+                if (from.error || to.error) {
+                    return from.error || to.error;
+                }
+                if (!from.node) {
+                    return WASI_ENOENT;
+                }
+                if (from.name === '.' || to.name === '.' || to.ancestors.includes(from.node)) {
+                    return WASI_EINVAL;
+                }
+                if (to.node && to.node !== from.node) {
+                    if (from.node.entries && !to.node.entries) {
+                        return WASI_ENOTDIR;
+                    }
+                    if (!from.node.entries && to.node.entries) {
+                        return WASI_EISDIR;
+                    }
+                    if (to.node.entries && to.node.entries.size > 0) {
+                        return WASI_ENOTEMPTY;
+                    }
+                }
+                from.parent.entries.delete(from.name);
+                to.parent.entries.set(to.name, from.node);
+                wasiTouch(from.node);
+                wasiChanged(from.preopen);
+                wasiChanged(to.preopen);
+                return WASI_ESUCCESS;
+            },
+            // Hard links share the file in memory, and are stored as copies
+            path_link: function(fd, lookupFlags, oldPtr, oldLen, newFd, newPtr, newLen) {
+                const from = wasiResolve(fd, oldPtr, oldLen);
+                const to = wasiResolve(newFd, newPtr, newLen);
+                if (from.error || to.error) {
+                    return from.error || to.error;
+                }
+                if (!from.node) {
+                    return WASI_ENOENT;
+                }
+                if (from.node.entries) {
+                    return WASI_EPERM;
+                }
+                if (to.node) {
+                    return WASI_EEXIST;
+                }
+                to.parent.entries.set(to.name, from.node);
+                wasiTouch(from.node);
+                wasiChanged(to.preopen);
+                return WASI_ESUCCESS;
+            },
+            path_readlink: function(fd, ptr, len, buf, bufLen, usedPtr) {
+                const path = wasiResolve(fd, ptr, len);
+                return path.error || (path.node ? WASI_EINVAL : WASI_ENOENT);
+            },
+            path_symlink: () => WASI_ENOTSUP
+        });
+
+        // Arguments and environment from data-wasi-args and data-wasi-env, as
+        // NUL-terminated strings and pointers to them
+        const wasiStringSizes = function(strings, countPtr, sizePtr) {
+            const view = wasiData();
+            view.setUint32(countPtr, strings.length, true);
+            view.setUint32(sizePtr, strings.reduce((size, string) => size + wasiFsEncoder.encode(string).length + 1, 0), true);
+            return WASI_ESUCCESS;
+        };
+        const wasiStoreStrings = function(strings, pointersPtr, bufferPtr) {
+            const view = wasiData();
+            const heap = wasiHeap();
+            let at = bufferPtr;
+            strings.forEach(function(string, i) {
+                const bytes = wasiFsEncoder.encode(string);
+                view.setUint32(pointersPtr + i * 4, at, true);
+                heap.set(bytes, at);
+                heap[at + bytes.length] = 0;
+                at += bytes.length + 1;
+            });
+            return WASI_ESUCCESS;
+        };
+        // Thrown by proc_exit out of the export that exited, with its status
+        class WasiExit extends Error {
+            constructor(status) {
+                super('exit(' + status + ')');
+                this.status = status;
+            }
+        }
+        Object.assign(wasiPreview1, {
+            args_sizes_get: (countPtr, sizePtr) => wasiStringSizes(wasiArgs, countPtr, sizePtr),
+            args_get: (argvPtr, bufferPtr) => wasiStoreStrings(wasiArgs, argvPtr, bufferPtr),
+            environ_sizes_get: (countPtr, sizePtr) => wasiStringSizes(wasiEnvironment, countPtr, sizePtr),
+            environ_get: (environPtr, bufferPtr) => wasiStoreStrings(wasiEnvironment, environPtr, bufferPtr),
+            proc_exit: function(status) {
+                for (const output of Object.values(wasiOutput)) {
+                    output.flush();
+                }
+                throw new WasiExit(status);
+            },
+            sched_yield: () => WASI_ESUCCESS
+        });
+        const wasiImports = { wasi_snapshot_preview1: wasiPreview1 };"#,
+        r#"
+
+                    // WASI: its memory is the exported one, or that the page provides
+                    wasiMemory = result.instance.exports.memory || (importObject.env && importObject.env.memory) || null;
+                    window.__wasmModules[wasmFilename].filesystem = { flush: wasiFilesystem.flush };"#,
+    )
+}
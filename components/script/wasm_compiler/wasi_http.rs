@@ -0,0 +1,649 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Outgoing HTTP requests of wasi-http
+//!
+//! Modules importing `wasi:http/outgoing-handler` (as the core module of a
+//! component, or built for `wasm32-wasip2`) send requests with the functions
+//! of `wasi:http/types`, `wasi:io/streams` and `wasi:io/poll` 0.2, lowered
+//! with the canonical ABI. The loader provides them for those the page does
+//! not, on top of the page's `fetch`, so requests are subject to CORS like
+//! any other the page makes:
+//!
+//! - a request is sent once `handle` was called and its body finished, when
+//!   the module returns to the event loop; bodies are buffered both ways
+//! - blocking on the response before that sends it with a synchronous
+//!   `XMLHttpRequest` instead, since the module cannot wait for a promise;
+//!   blocking on a request already sent traps
+//! - requests without an authority are relative to the document, and the
+//!   scheme defaults to the page's
+//! - fetch errors, including CORS failures, are `internal-error` with the
+//!   message, timeouts of `request-options` are `connection-timeout` or
+//!   `HTTP-response-timeout`, and trailers are not sent
+//!
+//! Lists returned to the module are allocated with its `cabi_realloc`.
+
+use super::embed;
+use super::imports;
+
+/// Package of the interfaces that make a module use the shim
+const HTTP_PACKAGE: &str = "wasi:http/";
+
+/// Package of the streams and pollables the interfaces use
+const IO_PACKAGE: &str = "wasi:io/";
+
+/// Version the shim implements, the ABI of later 0.2 releases being the same
+const VERSION: &str = "0.2.";
+
+/// The versioned modules a module imports the shim from, e.g.
+/// `wasi:http/types@0.2.0`, or none if it does not send requests
+pub fn shimmed_modules(binary: &[u8]) -> Vec<String> {
+    let mut modules: Vec<String> = imports::declared_imports(binary)
+        .into_iter()
+        .map(|(module, _, _)| module)
+        .filter(|module| {
+            let (package, version) = module.split_once('@').unwrap_or((module, VERSION));
+            (package.starts_with(HTTP_PACKAGE) || package.starts_with(IO_PACKAGE))
+                && version.starts_with(VERSION)
+        })
+        .collect();
+    modules.sort();
+    modules.dedup();
+    if modules
+        .iter()
+        .any(|module| module.starts_with(HTTP_PACKAGE))
+    {
+        modules
+    } else {
+        Vec::new()
+    }
+}
+
+/// JavaScript of the interfaces of wasi-http, for a module importing them
+/// from the `modules` of [`shimmed_modules`]: the imports, resolved before the
+/// module is instantiated, and what they need of its exports once it is
+pub fn loader_js(modules: &[String]) -> (String, &'static str) {
+    (
+        String::from(
+            r#"
+        const wasiHttp = { exports: null, handles: new Map(), nextHandle: 1 };
+        const wasiEncoder = new TextEncoder();
+        const wasiDecoder = new TextDecoder('utf-8');
+        const wasiView = () => new DataView(wasiHttp.exports.memory.buffer);
+        const wasiBytes = (ptr, len) => new Uint8Array(wasiHttp.exports.memory.buffer, ptr, len);
+        const wasiString = (ptr, len) => wasiDecoder.decode(wasiBytes(ptr, len));
+        const wasiValidName = name => /^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/.test(name);
+        const WASI_METHODS = ['GET', 'HEAD', 'POST', 'PUT', 'DELETE', 'CONNECT', 'OPTIONS', 'TRACE', 'PATCH'];
+        const WASI_SCHEMES = ['http', 'https'];
+
+        // Resources by handle, owned by the module until dropped or passed back
+        const wasiOwn = function(resource) {
+            const handle = wasiHttp.nextHandle++;
+            wasiHttp.handles.set(handle, resource);
+            return handle;
+        };
+        const wasiGet = function(handle) {
+            const resource = wasiHttp.handles.get(handle);
+            if (resource === undefined) {
+                throw new Error('wasi-http: unknown handle ' + handle);
+            }
+            return resource;
+        };
+        const wasiTake = function(handle) {
+            const resource = wasiGet(handle);
+            wasiHttp.handles.delete(handle);
+            return resource;
+        };
+        const wasiDrop = function(handle) {
+            wasiHttp.handles.delete(handle);
+        };
+
+        // Lists returned to the module are allocated by it, which may grow its
+        // memory, so views are taken after allocating
+        const wasiStoreBytes = function(bytes) {
+            if (typeof wasiHttp.exports.cabi_realloc !== 'function') {
+                throw new Error('wasi-http: the module does not export cabi_realloc');
+            }
+            const ptr = wasiHttp.exports.cabi_realloc(0, 0, 1, bytes.length);
+            wasiBytes(ptr, bytes.length).set(bytes);
+            return ptr;
+        };
+        const wasiStoreList = function(addr, ptr, len) {
+            const view = wasiView();
+            view.setUint32(addr, ptr, true);
+            view.setUint32(addr + 4, len, true);
+        };
+        const wasiStoreString = function(addr, string) {
+            const bytes = wasiEncoder.encode(string);
+            wasiStoreList(addr, wasiStoreBytes(bytes), bytes.length);
+        };
+        const wasiStoreOption = function(addr, string) {
+            if (string === null) {
+                wasiView().setUint8(addr, 0);
+                return;
+            }
+            wasiStoreString(addr + 4, string);
+            wasiView().setUint8(addr, 1);
+        };
+        const wasiStoreVariant = function(addr, names, value) {
+            const tag = names.indexOf(value);
+            if (tag < 0) {
+                wasiStoreString(addr + 4, value);
+            }
+            wasiView().setUint8(addr, tag < 0 ? names.length : tag);
+        };
+        // An error-code, internal-error with its message if there is one
+        const wasiStoreError = function(addr, error) {
+            const [code, message] = error;
+            if (message !== undefined) {
+                wasiStoreOption(addr + 8, message);
+            }
+            wasiView().setUint8(addr, code);
+        };
+        const WASI_CONNECTION_TIMEOUT = 8;
+        const WASI_REQUEST_URI_INVALID = 19;
+        const WASI_RESPONSE_TIMEOUT = 33;
+        const WASI_INTERNAL_ERROR = 38;
+
+        // result<_, header-error>: invalid-syntax, forbidden or immutable
+        const wasiFieldsUpdate = function(handle, name, retptr, update) {
+            const fields = wasiGet(handle);
+            const error = fields.immutable ? 2 : wasiValidName(name) ? -1 : 0;
+            if (error < 0) {
+                update(fields, name.toLowerCase());
+            }
+            const view = wasiView();
+            view.setUint8(retptr, error < 0 ? 0 : 1);
+            view.setUint8(retptr + 1, Math.max(error, 0));
+        };
+        const wasiFieldValues = function(ptr, len) {
+            const view = wasiView();
+            const values = [];
+            for (let i = 0; i < len; i++) {
+                values.push(wasiBytes(view.getUint32(ptr + i * 8, true), view.getUint32(ptr + i * 8 + 4, true)).slice());
+            }
+            return values;
+        };
+        const wasiHeaders = function(fields) {
+            const headers = new Headers();
+            for (const [name, value] of fields.entries) {
+                try {
+                    headers.append(name, wasiDecoder.decode(value));
+                } catch (e) {
+                    console.warn('WASM: wasi-http: header ' + name + ' not sent:', e);
+                }
+            }
+            return headers;
+        };
+        const wasiReady = { ready: () => true, block: function() {} };
+
+        // A request is sent once handled with its body finished: with fetch once
+        // the module returns, or synchronously if it blocks on the response first
+        const wasiRequestBody = function(request) {
+            if (!request.body || request.method === 'GET' || request.method === 'HEAD') {
+                return undefined;
+            }
+            const length = request.body.chunks.reduce((total, chunk) => total + chunk.length, 0);
+            const bytes = new Uint8Array(length);
+            let offset = 0;
+            for (const chunk of request.body.chunks) {
+                bytes.set(chunk, offset);
+                offset += chunk.length;
+            }
+            return bytes;
+        };
+        const wasiSettle = function(exchange, response, error) {
+            clearTimeout(exchange.timer);
+            exchange.state = 'done';
+            exchange.error = error || null;
+            exchange.response = response && {
+                status: response.status,
+                headers: {
+                    entries: response.headers.map(([name, value]) => [name.toLowerCase(), wasiEncoder.encode(value)]),
+                    immutable: true
+                },
+                body: response.body,
+                consumed: false
+            };
+        };
+        const wasiTimeout = function(options) {
+            const nanos = options && ((options.connectTimeout || 0n) + (options.firstByteTimeout || 0n));
+            return nanos ? {
+                ms: Number(nanos / 1000000n),
+                code: options.firstByteTimeout ? WASI_RESPONSE_TIMEOUT : WASI_CONNECTION_TIMEOUT
+            } : null;
+        };
+        const wasiFetch = function(exchange) {
+            exchange.state = 'sending';
+            const request = exchange.request;
+            const timeout = wasiTimeout(exchange.options);
+            const init = { method: request.method, headers: wasiHeaders(request.headers), body: wasiRequestBody(request) };
+            if (timeout) {
+                const controller = new AbortController();
+                exchange.timer = setTimeout(() => controller.abort(), timeout.ms);
+                init.signal = controller.signal;
+            }
+            fetch(exchange.url, init)
+                .then(response => response.arrayBuffer().then(function(buffer) {
+                    wasiSettle(exchange, {
+                        status: response.status,
+                        headers: Array.from(response.headers),
+                        body: new Uint8Array(buffer)
+                    });
+                }))
+                .catch(function(e) {
+                    wasiSettle(exchange, null, timeout && e.name === 'AbortError'
+                        ? [timeout.code] : [WASI_INTERNAL_ERROR, String(e.message || e)]);
+                });
+        };
+        const wasiSendNow = function(exchange) {
+            exchange.state = 'sending';
+            const request = exchange.request;
+            const xhr = new XMLHttpRequest();
+            try {
+                xhr.open(request.method, exchange.url, false);
+                wasiHeaders(request.headers).forEach(function(value, name) {
+                    xhr.setRequestHeader(name, value);
+                });
+                // The bytes of the response as they are, one per character
+                xhr.overrideMimeType('text/plain; charset=x-user-defined');
+                xhr.send(wasiRequestBody(request) || null);
+            } catch (e) {
+                wasiSettle(exchange, null, [WASI_INTERNAL_ERROR, String(e.message || e)]);
+                return;
+            }
+            if (xhr.status === 0) {
+                wasiSettle(exchange, null, [WASI_INTERNAL_ERROR, 'network error']);
+                return;
+            }
+            const text = xhr.responseText;
+            const body = new Uint8Array(text.length);
+            for (let i = 0; i < text.length; i++) {
+                body[i] = text.charCodeAt(i) & 0xff;
+            }
+            const headers = xhr.getAllResponseHeaders().split(/\r?\n/).filter(line => line.includes(':')).map(function(line) {
+                const colon = line.indexOf(':');
+                return [line.slice(0, colon).trim(), line.slice(colon + 1).trim()];
+            });
+            wasiSettle(exchange, { status: xhr.status, headers: headers, body: body });
+        };
+        const wasiRequestReady = function(request) {
+            const exchange = request.exchange;
+            if (!exchange || exchange.state !== 'waiting' || (request.body && !request.body.finished)) {
+                return;
+            }
+            exchange.state = 'scheduled';
+            queueMicrotask(function() {
+                if (exchange.state === 'scheduled') {
+                    wasiFetch(exchange);
+                }
+            });
+        };
+        const wasiBlock = function(exchange) {
+            if (exchange.state === 'scheduled') {
+                wasiSendNow(exchange);
+            } else if (exchange.state === 'waiting') {
+                throw new Error('wasi-http: blocking on a response whose request body is not finished');
+            } else if (exchange.state === 'sending') {
+                throw new Error('wasi-http: blocking on a request fetch is sending; poll it with ready() instead');
+            }
+        };
+
+        const wasiTypes = {
+            '[constructor]fields': () => wasiOwn({ entries: [], immutable: false }),
+            '[static]fields.from-list': function(ptr, len, retptr) {
+                const view = wasiView();
+                const entries = [];
+                for (let i = 0; i < len; i++) {
+                    const at = ptr + i * 16;
+                    const name = wasiString(view.getUint32(at, true), view.getUint32(at + 4, true));
+                    if (!wasiValidName(name)) {
+                        view.setUint8(retptr, 1);
+                        view.setUint8(retptr + 4, 0);
+                        return;
+                    }
+                    entries.push([name.toLowerCase(), wasiBytes(view.getUint32(at + 8, true), view.getUint32(at + 12, true)).slice()]);
+                }
+                view.setUint8(retptr, 0);
+                view.setUint32(retptr + 4, wasiOwn({ entries: entries, immutable: false }), true);
+            },
+            '[method]fields.get': function(handle, ptr, len, retptr) {
+                const name = wasiString(ptr, len).toLowerCase();
+                const values = wasiGet(handle).entries.filter(entry => entry[0] === name);
+                const list = wasiStoreBytes(new Uint8Array(values.length * 8));
+                values.forEach(function([, value], i) {
+                    wasiStoreList(list + i * 8, wasiStoreBytes(value), value.length);
+                });
+                wasiStoreList(retptr, list, values.length);
+            },
+            '[method]fields.has': function(handle, ptr, len) {
+                const name = wasiString(ptr, len).toLowerCase();
+                return wasiGet(handle).entries.some(entry => entry[0] === name) ? 1 : 0;
+            },
+            '[method]fields.set': function(handle, namePtr, nameLen, ptr, len, retptr) {
+                const values = wasiFieldValues(ptr, len);
+                wasiFieldsUpdate(handle, wasiString(namePtr, nameLen), retptr, function(fields, name) {
+                    fields.entries = fields.entries.filter(entry => entry[0] !== name)
+                        .concat(values.map(value => [name, value]));
+                });
+            },
+            '[method]fields.delete': function(handle, ptr, len, retptr) {
+                wasiFieldsUpdate(handle, wasiString(ptr, len), retptr, function(fields, name) {
+                    fields.entries = fields.entries.filter(entry => entry[0] !== name);
+                });
+            },
+            '[method]fields.append': function(handle, namePtr, nameLen, ptr, len, retptr) {
+                const value = wasiBytes(ptr, len).slice();
+                wasiFieldsUpdate(handle, wasiString(namePtr, nameLen), retptr, function(fields, name) {
+                    fields.entries.push([name, value]);
+                });
+            },
+            '[method]fields.entries': function(handle, retptr) {
+                const entries = wasiGet(handle).entries;
+                const list = wasiStoreBytes(new Uint8Array(entries.length * 16));
+                entries.forEach(function([name, value], i) {
+                    wasiStoreString(list + i * 16, name);
+                    wasiStoreList(list + i * 16 + 8, wasiStoreBytes(value), value.length);
+                });
+                wasiStoreList(retptr, list, entries.length);
+            },
+            '[method]fields.clone': handle => wasiOwn({ entries: wasiGet(handle).entries.slice(), immutable: false }),
+            '[resource-drop]fields': wasiDrop,
+
+            '[constructor]outgoing-request': function(headers) {
+                const fields = wasiTake(headers);
+                fields.immutable = true;
+                return wasiOwn({ method: 'GET', scheme: null, authority: null, path: null, headers: fields, body: null, exchange: null });
+            },
+            '[method]outgoing-request.body': function(handle, retptr) {
+                const request = wasiGet(handle);
+                const taken = request.body !== null;
+                if (!taken) {
+                    request.body = { request: request, chunks: [], stream: false, finished: false };
+                    wasiView().setUint32(retptr + 4, wasiOwn(request.body), true);
+                }
+                wasiView().setUint8(retptr, taken ? 1 : 0);
+            },
+            '[method]outgoing-request.method': (handle, retptr) => wasiStoreVariant(retptr, WASI_METHODS, wasiGet(handle).method),
+            '[method]outgoing-request.set-method': function(handle, tag, ptr, len) {
+                const method = tag < WASI_METHODS.length ? WASI_METHODS[tag] : wasiString(ptr, len);
+                if (!wasiValidName(method)) {
+                    return 1;
+                }
+                wasiGet(handle).method = method;
+                return 0;
+            },
+            '[method]outgoing-request.scheme': function(handle, retptr) {
+                const scheme = wasiGet(handle).scheme;
+                if (scheme !== null) {
+                    wasiStoreVariant(retptr + 4, WASI_SCHEMES, scheme);
+                }
+                wasiView().setUint8(retptr, scheme === null ? 0 : 1);
+            },
+            '[method]outgoing-request.set-scheme': function(handle, some, tag, ptr, len) {
+                wasiGet(handle).scheme = !some ? null : tag < WASI_SCHEMES.length ? WASI_SCHEMES[tag] : wasiString(ptr, len);
+                return 0;
+            },
+            '[method]outgoing-request.authority': (handle, retptr) => wasiStoreOption(retptr, wasiGet(handle).authority),
+            '[method]outgoing-request.set-authority': function(handle, some, ptr, len) {
+                wasiGet(handle).authority = some ? wasiString(ptr, len) : null;
+                return 0;
+            },
+            '[method]outgoing-request.path-with-query': (handle, retptr) => wasiStoreOption(retptr, wasiGet(handle).path),
+            '[method]outgoing-request.set-path-with-query': function(handle, some, ptr, len) {
+                wasiGet(handle).path = some ? wasiString(ptr, len) : null;
+                return 0;
+            },
+            '[method]outgoing-request.headers': handle => wasiOwn(wasiGet(handle).headers),
+            '[resource-drop]outgoing-request': wasiDrop,
+
+            '[constructor]request-options': () => wasiOwn({ connectTimeout: null, firstByteTimeout: null, betweenBytesTimeout: null }),
+            '[resource-drop]request-options': wasiDrop,
+
+            '[method]outgoing-body.write': function(handle, retptr) {
+                const body = wasiGet(handle);
+                const taken = body.stream;
+                if (!taken) {
+                    body.stream = true;
+                    wasiView().setUint32(retptr + 4, wasiOwn({ body: body }), true);
+                }
+                wasiView().setUint8(retptr, taken ? 1 : 0);
+            },
+            // Trailers cannot be sent with fetch, and are dropped
+            '[static]outgoing-body.finish': function(handle, hasTrailers, trailers, retptr) {
+                const body = wasiTake(handle);
+                if (hasTrailers) {
+                    wasiDrop(trailers);
+                }
+                body.finished = true;
+                wasiRequestReady(body.request);
+                wasiView().setUint8(retptr, 0);
+            },
+            '[resource-drop]outgoing-body': function(handle) {
+                const body = wasiHttp.handles.get(handle);
+                wasiDrop(handle);
+                const exchange = body && body.request.exchange;
+                if (exchange && exchange.state === 'waiting') {
+                    wasiSettle(exchange, null, [WASI_INTERNAL_ERROR, 'request body dropped without finish']);
+                }
+            },
+
+            '[method]future-incoming-response.subscribe': function(handle) {
+                const exchange = wasiGet(handle).exchange;
+                return wasiOwn({ ready: () => exchange.state === 'done', block: () => wasiBlock(exchange) });
+            },
+            // option<result<result<incoming-response, error-code>>>
+            '[method]future-incoming-response.get': function(handle, retptr) {
+                const future = wasiGet(handle);
+                const exchange = future.exchange;
+                if (exchange.state !== 'done') {
+                    wasiView().setUint8(retptr, 0);
+                    return;
+                }
+                const taken = future.taken;
+                future.taken = true;
+                if (!taken && exchange.error) {
+                    wasiStoreError(retptr + 24, exchange.error);
+                } else if (!taken) {
+                    wasiView().setUint32(retptr + 24, wasiOwn(exchange.response), true);
+                }
+                const view = wasiView();
+                view.setUint8(retptr, 1);
+                view.setUint8(retptr + 8, taken ? 1 : 0);
+                view.setUint8(retptr + 16, exchange.error ? 1 : 0);
+            },
+            '[resource-drop]future-incoming-response': wasiDrop,
+
+            '[method]incoming-response.status': handle => wasiGet(handle).status,
+            '[method]incoming-response.headers': handle => wasiOwn(wasiGet(handle).headers),
+            '[method]incoming-response.consume': function(handle, retptr) {
+                const response = wasiGet(handle);
+                const consumed = response.consumed;
+                if (!consumed) {
+                    response.consumed = true;
+                    wasiView().setUint32(retptr + 4, wasiOwn({ bytes: response.body, stream: false }), true);
+                }
+                wasiView().setUint8(retptr, consumed ? 1 : 0);
+            },
+            '[resource-drop]incoming-response': wasiDrop,
+            '[method]incoming-body.stream': function(handle, retptr) {
+                const body = wasiGet(handle);
+                const taken = body.stream;
+                if (!taken) {
+                    body.stream = true;
+                    wasiView().setUint32(retptr + 4, wasiOwn({ bytes: body.bytes, offset: 0 }), true);
+                }
+                wasiView().setUint8(retptr, taken ? 1 : 0);
+            },
+            // Responses have no trailers once fetch returns them
+            '[static]incoming-body.finish': function(handle) {
+                wasiTake(handle);
+                return wasiOwn({ taken: false });
+            },
+            '[resource-drop]incoming-body': wasiDrop,
+            '[method]future-trailers.subscribe': () => wasiOwn(wasiReady),
+            // option<result<result<option<trailers>, error-code>>>
+            '[method]future-trailers.get': function(handle, retptr) {
+                const future = wasiGet(handle);
+                const view = wasiView();
+                view.setUint8(retptr, 1);
+                view.setUint8(retptr + 8, future.taken ? 1 : 0);
+                view.setUint8(retptr + 16, 0);
+                view.setUint8(retptr + 24, 0);
+                future.taken = true;
+            },
+            '[resource-drop]future-trailers': wasiDrop
+        };
+        for (const [name, key] of [['connect-timeout', 'connectTimeout'],
+            ['first-byte-timeout', 'firstByteTimeout'], ['between-bytes-timeout', 'betweenBytesTimeout']]) {
+            wasiTypes['[method]request-options.' + name] = function(handle, retptr) {
+                const duration = wasiGet(handle)[key];
+                const view = wasiView();
+                view.setUint8(retptr, duration === null ? 0 : 1);
+                if (duration !== null) {
+                    view.setBigUint64(retptr + 8, duration, true);
+                }
+            };
+            wasiTypes['[method]request-options.set-' + name] = function(handle, some, duration) {
+                wasiGet(handle)[key] = some ? duration : null;
+                return 0;
+            };
+        }
+
+        const wasiOutgoingHandler = {
+            // result<future-incoming-response, error-code>
+            handle: function(handle, hasOptions, options, retptr) {
+                const request = wasiTake(handle);
+                const exchange = { request: request, options: hasOptions ? wasiTake(options) : null, state: 'waiting', timer: null };
+                try {
+                    exchange.url = request.authority === null
+                        ? new URL(request.path || '/', document.baseURI).href
+                        : new URL((request.scheme || location.protocol.slice(0, -1)) + '://' + request.authority + (request.path || '/')).href;
+                } catch (e) {
+                    wasiView().setUint8(retptr + 8, WASI_REQUEST_URI_INVALID);
+                    wasiView().setUint8(retptr, 1);
+                    return;
+                }
+                request.exchange = exchange;
+                wasiRequestReady(request);
+                const view = wasiView();
+                view.setUint32(retptr + 8, wasiOwn({ exchange: exchange, taken: false }), true);
+                view.setUint8(retptr, 0);
+            }
+        };
+
+        // Bodies are buffered, so streams never wait; writes go to the request body
+        const wasiReadStream = function(handle, len, retptr) {
+            const stream = wasiGet(handle);
+            if (stream.offset >= stream.bytes.length && len > 0n) {
+                const view = wasiView();
+                view.setUint8(retptr, 1);
+                view.setUint8(retptr + 4, 1);
+                return;
+            }
+            const chunk = stream.bytes.subarray(stream.offset, stream.offset + Number(len));
+            stream.offset += chunk.length;
+            wasiStoreList(retptr + 4, wasiStoreBytes(chunk), chunk.length);
+            wasiView().setUint8(retptr, 0);
+        };
+        const wasiSkipStream = function(handle, len, retptr) {
+            const stream = wasiGet(handle);
+            const view = wasiView();
+            if (stream.offset >= stream.bytes.length && len > 0n) {
+                view.setUint8(retptr, 1);
+                view.setUint8(retptr + 8, 1);
+                return;
+            }
+            const skipped = Math.min(Number(len), stream.bytes.length - stream.offset);
+            stream.offset += skipped;
+            view.setUint8(retptr, 0);
+            view.setBigUint64(retptr + 8, BigInt(skipped), true);
+        };
+        const wasiWriteStream = function(handle, bytes, retptr) {
+            const body = wasiGet(handle).body;
+            const view = wasiView();
+            if (body.finished) {
+                view.setUint8(retptr, 1);
+                view.setUint8(retptr + 4, 1);
+                return;
+            }
+            body.chunks.push(bytes);
+            view.setUint8(retptr, 0);
+        };
+        const wasiFlushStream = function(handle, retptr) {
+            wasiView().setUint8(retptr, 0);
+        };
+        const wasiStreams = {
+            '[method]input-stream.read': wasiReadStream,
+            '[method]input-stream.blocking-read': wasiReadStream,
+            '[method]input-stream.skip': wasiSkipStream,
+            '[method]input-stream.blocking-skip': wasiSkipStream,
+            '[method]input-stream.subscribe': () => wasiOwn(wasiReady),
+            '[resource-drop]input-stream': wasiDrop,
+            '[method]output-stream.check-write': function(handle, retptr) {
+                const view = wasiView();
+                view.setUint8(retptr, 0);
+                view.setBigUint64(retptr + 8, 1048576n, true);
+            },
+            '[method]output-stream.write': (handle, ptr, len, retptr) => wasiWriteStream(handle, wasiBytes(ptr, len).slice(), retptr),
+            '[method]output-stream.blocking-write-and-flush': (handle, ptr, len, retptr) => wasiWriteStream(handle, wasiBytes(ptr, len).slice(), retptr),
+            '[method]output-stream.write-zeroes': (handle, len, retptr) => wasiWriteStream(handle, new Uint8Array(Number(len)), retptr),
+            '[method]output-stream.blocking-write-zeroes-and-flush': (handle, len, retptr) => wasiWriteStream(handle, new Uint8Array(Number(len)), retptr),
+            '[method]output-stream.flush': wasiFlushStream,
+            '[method]output-stream.blocking-flush': wasiFlushStream,
+            '[method]output-stream.subscribe': () => wasiOwn(wasiReady),
+            '[resource-drop]output-stream': wasiDrop
+        };
+        const wasiPoll = {
+            '[method]pollable.ready': handle => wasiGet(handle).ready() ? 1 : 0,
+            '[method]pollable.block': handle => wasiGet(handle).block(),
+            '[resource-drop]pollable': wasiDrop,
+            // Indices of the ready pollables, blocking on the first if none is
+            poll: function(ptr, len, retptr) {
+                const view = wasiView();
+                const pollables = [];
+                for (let i = 0; i < len; i++) {
+                    pollables.push(wasiGet(view.getUint32(ptr + i * 4, true)));
+                }
+                if (pollables.length > 0 && !pollables.some(pollable => pollable.ready())) {
+                    pollables[0].block();
+                }
+                const ready = [];
+                pollables.forEach(function(pollable, i) {
+                    if (pollable.ready()) {
+                        ready.push(i);
+                    }
+                });
+                const list = wasiStoreBytes(new Uint8Array(new Uint32Array(ready).buffer));
+                wasiStoreList(retptr, list, ready.length);
+            }
+        };
+        const wasiErrors = {
+            '[method]error.to-debug-string': (handle, retptr) => wasiStoreString(retptr, String(wasiGet(handle))),
+            '[resource-drop]error': wasiDrop
+        };
+        const wasiInterfaces = {
+            'wasi:http/types': wasiTypes,
+            'wasi:http/outgoing-handler': wasiOutgoingHandler,
+            'wasi:io/streams': wasiStreams,
+            'wasi:io/poll': wasiPoll,
+            'wasi:io/error': wasiErrors
+        };"#,
+        ) + &format!(
+            r#"
+        const wasiHttpImports = {{}};
+        for (const module of {modules}) {{
+            wasiHttpImports[module] = wasiInterfaces[module.split('@')[0]] || {{}};
+        }}"#,
+            modules = embed::script_safe(&serde_json::to_string(modules).unwrap_or_default()),
+        ),
+        r#"
+
+                    // wasi-http: lists are returned in the memory of this module
+                    wasiHttp.exports = result.instance.exports;"#,
+    )
+}
@@ -0,0 +1,780 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Spec-style `.wast` scripts
+//!
+//! A script whose body holds the directives of the spec test suite, such as
+//! `(assert_return (invoke "f" (i32.const 1)) (i32.const 2))`, or more than
+//! one module is run as a spec test rather than loaded as a module:
+//!
+//! - each module is compiled here and instantiated in order, the last one
+//!   being the module `invoke` and `get` use unless they name another
+//! - `register` makes the exports of a module importable under a name, next
+//!   to the `spectest` module of the suite
+//! - assertions run against the exports of the instances, by the engine of
+//!   the page; modules that do not encode pass `assert_invalid` and
+//!   `assert_malformed` here, the others are handed to the engine
+//!
+//! The modules are checked as a WAT script of the page would be: they may
+//! only use the proposals enabled, nor shared memory if the origin has no
+//! permission for threads, and their memory counts against the budget of the
+//! page. A module refused fails its directive.
+//!
+//! Each directive gets an outcome, `passed`, `failed` or `skipped` for what
+//! JavaScript cannot express (`v128` values, components, threads), with its
+//! line and column. The results of a script go to the console and to
+//! `window.__wastResults[filename]`:
+//!
+//! ```text
+//! { file, passed, failed, skipped,
+//!   directives: [{ line, column, kind, outcome, message }] }
+//! ```
+//!
+//! `navigator.watCompiler.runWast(text)` runs a script the same way and
+//! settles its promise with the results, see [`run_wast`].
+
+use wasmparser::Validator;
+use wast::core::{AbstractHeapType, HeapType, NanPattern, WastArgCore, WastRetCore};
+use wast::parser::{self, ParseBuffer};
+use wast::token::Id;
+use wast::{QuoteWat, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet, Wat};
+
+use super::{CompileError, CompileOptions, budget, capabilities, embed};
+
+/// Directives only a `.wast` script has
+const DIRECTIVES: [&str; 12] = [
+    "assert_return",
+    "assert_trap",
+    "assert_exhaustion",
+    "assert_invalid",
+    "assert_malformed",
+    "assert_unlinkable",
+    "assert_exception",
+    "assert_suspension",
+    "register",
+    "invoke",
+    "thread",
+    "wait",
+];
+
+/// Whether `source` is a `.wast` script: it has a directive of the spec
+/// tests at the top level, or more than one module
+pub fn is_wast(source: &str) -> bool {
+    let heads = top_level_heads(source);
+    heads.iter().any(|head| DIRECTIVES.contains(head))
+        || heads.iter().filter(|head| **head == "module").count() > 1
+}
+
+/// The keyword after each parenthesis at the top level of `source`,
+/// skipping comments and strings
+fn top_level_heads(source: &str) -> Vec<&str> {
+    let bytes = source.as_bytes();
+    let mut heads = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            },
+            b'(' if bytes.get(i + 1) == Some(&b';') => {
+                let mut nested = 0usize;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"(;") {
+                        nested += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b";)") {
+                        nested -= 1;
+                        i += 2;
+                        if nested == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            },
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            },
+            b'(' => {
+                if depth == 0 {
+                    let start = i + 1;
+                    let end = bytes[start..]
+                        .iter()
+                        .position(|byte| {
+                            byte.is_ascii_whitespace() || *byte == b'(' || *byte == b')'
+                        })
+                        .map_or(bytes.len(), |length| start + length);
+                    heads.push(&source[start..end]);
+                }
+                depth += 1;
+            },
+            b')' => depth = depth.saturating_sub(1),
+            _ => {},
+        }
+        i += 1;
+    }
+    heads
+}
+
+/// JavaScript evaluating to the results of running the `.wast` script
+/// `source`, see the module documentation
+pub fn compile_wast(
+    source: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let parse_error = |mut error: wast::Error| {
+        error.set_text(source);
+        CompileError::ParseError(format!("in {}: {}", filename, error))
+    };
+    let buffer = ParseBuffer::new(source).map_err(parse_error)?;
+    let wast = parser::parse::<Wast>(&buffer).map_err(parse_error)?;
+
+    let mut steps = String::new();
+    for directive in wast.directives {
+        let (line, column) = directive.span().linecol_in(source);
+        let kind = directive_kind(&directive);
+        let step = match step(directive, options) {
+            Ok(body) => body,
+            Err(Unsupported(reason)) => format!("return skip({});", string(&reason)),
+        };
+        steps.push_str(&format!(
+            "\n    check({}, {}, '{}', function() {{ {} }});",
+            line + 1,
+            column + 1,
+            kind,
+            step
+        ));
+    }
+    let memory_budget = options
+        .memory_budget
+        .map(|limit| format!("{}{}", budget::loader_js(limit, "wastFile"), MEMORY_BUDGET))
+        .unwrap_or_default();
+    Ok(format!(
+        "(function wastRun() {{\n    const wastFile = {};{}{}{}\n    return results;\n}})()",
+        string(filename),
+        RUNTIME,
+        memory_budget,
+        steps
+    ))
+}
+
+/// Script running the `.wast` script `source` and reporting its results
+pub fn compile_wast_to_js(
+    source: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let run = compile_wast(source, filename, options)?;
+    Ok(format!(
+        r#"
+(function wastScript() {{
+    const results = {run};
+    for (const directive of results.directives) {{
+        if (directive.outcome === 'failed') {{
+            console.error('WAST: ' + results.file + ':' + directive.line + ':' + directive.column + ': ' +
+                directive.kind + ' failed: ' + directive.message);
+        }}
+    }}
+    console.log('WAST: ' + results.file + ': ' + results.passed + ' passed, ' + results.failed +
+        ' failed, ' + results.skipped + ' skipped');
+    window.__wastResults = window.__wastResults || {{}};
+    window.__wastResults[results.file] = results;
+}})();
+"#
+    ))
+}
+
+/// JavaScript evaluating to a promise for the results of the `.wast` script
+/// `source`, whose modules are checked against `options` like those of the
+/// page; nothing is reported, so that the page can report them its own way
+pub fn run_wast(
+    source: &str,
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let run = compile_wast(source, filename, options)?;
+    Ok(format!(
+        "Promise.resolve().then(function() {{ return {}; }})",
+        run
+    ))
+}
+
+/// Helpers of the steps, in the scope of `wastFile`
+const RUNTIME: &str = r#"
+    const results = { file: wastFile, passed: 0, failed: 0, skipped: 0, directives: [] };
+    const spectest = {
+        print: function() {}, print_i32: function() {}, print_i64: function() {},
+        print_f32: function() {}, print_f64: function() {}, print_i32_f32: function() {},
+        print_f64_f64: function() {},
+        global_i32: 666, global_i64: 666n, global_f32: 666.6, global_f64: 666.6,
+        table: new WebAssembly.Table({ initial: 10, maximum: 20, element: 'anyfunc' }),
+        memory: new WebAssembly.Memory({ initial: 1, maximum: 2 }),
+    };
+    const registered = { spectest: spectest };
+    const definitions = {};
+    const instances = {};
+    let current = null;
+    const hostRefs = new Map();
+    const hostIds = new WeakMap();
+    const hostRef = function(id) {
+        if (!hostRefs.has(id)) {
+            const ref = { hostref: id };
+            hostRefs.set(id, ref);
+            hostIds.set(ref, id);
+        }
+        return hostRefs.get(id);
+    };
+    const bytes = function(literal) {
+        const bytes = new Uint8Array(literal.length);
+        for (let i = 0; i < literal.length; i++) {
+            bytes[i] = literal.charCodeAt(i);
+        }
+        return bytes;
+    };
+    const f32 = function(bits) {
+        const view = new DataView(new ArrayBuffer(4));
+        view.setUint32(0, bits);
+        return view.getFloat32(0);
+    };
+    const f64 = function(bits) {
+        const view = new DataView(new ArrayBuffer(8));
+        view.setBigUint64(0, bits);
+        return view.getFloat64(0);
+    };
+    // The memory of each module, as the budget of the page counts it
+    const memoryOf = new WeakMap();
+    const define = function(literal, memory, name) {
+        const module = new WebAssembly.Module(bytes(literal));
+        memoryOf.set(module, memory);
+        if (name) {
+            definitions[name] = module;
+        }
+        return module;
+    };
+    // The imports of an instance of a module, and what to give back if it is
+    // not created; replaced under a memory budget
+    let admit = function(module) {
+        return registered;
+    };
+    let release = function(module) {};
+    const instance = function(module) {
+        const imports = admit(module);
+        try {
+            return new WebAssembly.Instance(module, imports);
+        } catch (error) {
+            release(module);
+            throw error;
+        }
+    };
+    const instantiate = function(module, name) {
+        current = instance(module).exports;
+        if (name) {
+            instances[name] = current;
+        }
+        return current;
+    };
+    const exportsOf = function(name) {
+        const exports = name ? instances[name] : current;
+        if (!exports) {
+            throw new Error(name ? 'no module ' + name : 'no module instantiated');
+        }
+        return exports;
+    };
+    const invoke = function(name, field, args) {
+        const exported = exportsOf(name)[field];
+        if (typeof exported !== 'function') {
+            throw new Error('no exported function ' + field);
+        }
+        return exported.apply(null, args);
+    };
+    const get = function(name, field) {
+        const exported = exportsOf(name)[field];
+        if (!(exported instanceof WebAssembly.Global)) {
+            throw new Error('no exported global ' + field);
+        }
+        return exported.value;
+    };
+    const show = function(value) {
+        if (value instanceof Error) {
+            return value.name + ': ' + value.message;
+        }
+        if (typeof value === 'bigint') {
+            return value + 'n';
+        }
+        if (typeof value === 'number') {
+            return Object.is(value, -0) ? '-0' : String(value);
+        }
+        if (typeof value === 'function') {
+            return 'a function';
+        }
+        if (hostIds.has(value)) {
+            return 'ref.extern ' + hostIds.get(value);
+        }
+        return value === null ? 'null' : typeof value;
+    };
+    const matcher = function(text, test) {
+        return { text: text, test: test };
+    };
+    const expect = {
+        value: function(text, value) {
+            return matcher(text, function(actual) { return Object.is(actual, value); });
+        },
+        nan: function(text) {
+            return matcher(text, function(actual) { return typeof actual === 'number' && Number.isNaN(actual); });
+        },
+        nonNull: function(text) {
+            return matcher(text, function(actual) { return actual !== null && actual !== undefined; });
+        },
+        func: function(text) {
+            return matcher(text, function(actual) { return typeof actual === 'function'; });
+        },
+        i31: function(text) {
+            return matcher(text, function(actual) { return typeof actual === 'number'; });
+        },
+        either: function(text, matchers) {
+            return matcher(text, function(actual) {
+                return matchers.some(function(matcher) { return matcher.test(actual); });
+            });
+        },
+    };
+    const expectValues = function(actual, expected) {
+        const values = expected.length === 1 ? [actual] : actual === undefined ? [] : Array.from(actual);
+        if (values.length !== expected.length) {
+            return 'expected ' + expected.length + ' results, got ' + values.length;
+        }
+        for (let i = 0; i < expected.length; i++) {
+            if (!expected[i].test(values[i])) {
+                return 'expected ' + expected[i].text + ', got ' + show(values[i]);
+            }
+        }
+        return null;
+    };
+    const expectThrow = function(run, test, what, message) {
+        let value;
+        try {
+            value = run();
+        } catch (error) {
+            return test(error) ? null : 'expected ' + what + ' "' + message + '", got ' + show(error);
+        }
+        return 'expected ' + what + ' "' + message + '", got ' + show(value);
+    };
+    const isTrap = function(error) {
+        return error instanceof WebAssembly.RuntimeError;
+    };
+    const isExhaustion = function(error) {
+        return error instanceof RangeError || (error instanceof Error && error.name === 'InternalError');
+    };
+    const isException = function(error) {
+        return typeof WebAssembly.Exception === 'function' ? error instanceof WebAssembly.Exception :
+            !(error instanceof Error);
+    };
+    const isLinkError = function(error) {
+        return error instanceof WebAssembly.LinkError;
+    };
+    const skip = function(reason) {
+        return { skipped: reason };
+    };
+    const check = function(line, column, kind, step) {
+        let outcome;
+        let message = '';
+        try {
+            const failure = step();
+            if (failure && failure.skipped) {
+                outcome = 'skipped';
+                message = failure.skipped;
+            } else {
+                outcome = failure ? 'failed' : 'passed';
+                message = failure || '';
+            }
+        } catch (error) {
+            outcome = 'failed';
+            message = show(error);
+        }
+        results[outcome] += 1;
+        results.directives.push({ line: line, column: column, kind: kind, outcome: outcome, message: message });
+    };"#;
+
+/// Helpers of the steps under the memory budget of the page, after those of
+/// [`budget::loader_js`]: instances count as declared, with what
+/// `memory.grow` was granted them, and are not created past the budget
+const MEMORY_BUDGET: &str = r#"
+    let memoryHeld = 0;
+    window.__wasmMemoryUse = window.__wasmMemoryUse || {};
+    window.__wasmMemoryUse[wastFile] = () => memoryHeld;
+    admit = function(module) {
+        const memory = memoryOf.get(module);
+        const quotaError = memoryQuotaError(memoryHeld + memory.initial);
+        if (quotaError) {
+            throw new Error(quotaError);
+        }
+        memoryHeld += memory.initial;
+        const growth = memoryGrowth(memory.pages, () => memoryHeld, function(bytes) {
+            memoryHeld += bytes;
+        });
+        return Object.assign({ '__wasm_budget': growth }, registered);
+    };
+    release = function(module) {
+        memoryHeld -= memoryOf.get(module).initial;
+    };"#;
+
+/// Something of a directive JavaScript cannot express
+struct Unsupported(String);
+
+fn unsupported(what: &str) -> Unsupported {
+    Unsupported(format!("{} cannot be run from JavaScript", what))
+}
+
+fn directive_kind(directive: &WastDirective) -> &'static str {
+    match directive {
+        WastDirective::Module(_) => "module",
+        WastDirective::ModuleDefinition(_) => "module definition",
+        WastDirective::ModuleInstance { .. } => "module instance",
+        WastDirective::AssertMalformed { .. } => "assert_malformed",
+        WastDirective::AssertInvalid { .. } => "assert_invalid",
+        WastDirective::Register { .. } => "register",
+        WastDirective::Invoke(_) => "invoke",
+        WastDirective::AssertTrap { .. } => "assert_trap",
+        WastDirective::AssertReturn { .. } => "assert_return",
+        WastDirective::AssertExhaustion { .. } => "assert_exhaustion",
+        WastDirective::AssertUnlinkable { .. } => "assert_unlinkable",
+        WastDirective::AssertException { .. } => "assert_exception",
+        WastDirective::AssertSuspension { .. } => "assert_suspension",
+        WastDirective::Thread(_) => "thread",
+        WastDirective::Wait { .. } => "wait",
+    }
+}
+
+/// The body of the function running `directive`, returning a failure
+/// message, or nothing if it passed
+fn step(directive: WastDirective, options: &CompileOptions) -> Result<String, Unsupported> {
+    Ok(match directive {
+        WastDirective::Module(mut module) => {
+            let name = module.name();
+            match encode_quoted(&mut module)?.and_then(|binary| admitted(binary, options)) {
+                Ok(module) => format!(
+                    "current = null; instantiate(define({}, {}), {});",
+                    module,
+                    id(name),
+                    id(name)
+                ),
+                Err(error) => format!("current = null; return {};", string(&error)),
+            }
+        },
+        WastDirective::ModuleDefinition(mut module) => {
+            let name = module.name();
+            match encode_quoted(&mut module)?.and_then(|binary| admitted(binary, options)) {
+                Ok(module) => format!("define({}, {});", module, id(name)),
+                Err(error) => format!("return {};", string(&error)),
+            }
+        },
+        WastDirective::ModuleInstance {
+            instance, module, ..
+        } => format!(
+            "const module = definitions[{}]; if (!module) {{ return 'no module definition ' + {}; }} instantiate(module, {});",
+            id(module),
+            id(module),
+            id(instance)
+        ),
+        WastDirective::AssertMalformed {
+            mut module,
+            message,
+            ..
+        }
+        | WastDirective::AssertInvalid {
+            mut module,
+            message,
+            ..
+        } => match encode_quoted(&mut module)? {
+            // Rejected by the text format or the proposals enabled already
+            Err(_) => "return null;".to_owned(),
+            Ok(binary) if validate(&binary, options).is_err() => "return null;".to_owned(),
+            Ok(binary) => format!(
+                "return WebAssembly.validate(bytes({})) ? 'expected a rejected module \"' + {} + '\"' : null;",
+                embed::latin1_literal(&binary),
+                string(message)
+            ),
+        },
+        WastDirective::Register { name, module, .. } => {
+            format!("registered[{}] = exportsOf({});", string(name), id(module))
+        },
+        WastDirective::Invoke(invoke) => format!("{};", invocation(&invoke)?),
+        WastDirective::AssertReturn { exec, results, .. } => {
+            let expected = results
+                .iter()
+                .map(expectation)
+                .collect::<Result<Vec<_>, _>>()?;
+            format!(
+                "return expectValues({}, [{}]);",
+                execution(exec, options)?,
+                expected.join(", ")
+            )
+        },
+        WastDirective::AssertTrap { exec, message, .. } => {
+            throwing(exec, "isTrap", "a trap", message, options)?
+        },
+        WastDirective::AssertExhaustion { call, message, .. } => throwing(
+            WastExecute::Invoke(call),
+            "isExhaustion",
+            "exhaustion",
+            message,
+            options,
+        )?,
+        WastDirective::AssertException { exec, .. } => {
+            throwing(exec, "isException", "an exception", "", options)?
+        },
+        WastDirective::AssertUnlinkable {
+            mut module,
+            message,
+            ..
+        } => match module
+            .encode()
+            .map_err(|error| error.to_string())
+            .and_then(|binary| admitted(binary, options))
+        {
+            Ok(module) => format!(
+                "return expectThrow(function() {{ return instance(define({}, null)); }}, isLinkError, 'a link error', {});",
+                module,
+                string(message)
+            ),
+            Err(error) => format!("return {};", string(&error)),
+        },
+        WastDirective::AssertSuspension { .. } => return Err(unsupported("stack switching")),
+        WastDirective::Thread(_) | WastDirective::Wait { .. } => {
+            return Err(unsupported("a thread"));
+        },
+    })
+}
+
+/// The binary of a module, or why it does not encode
+fn encode_quoted(module: &mut QuoteWat) -> Result<Result<Vec<u8>, String>, Unsupported> {
+    if matches!(
+        module,
+        QuoteWat::QuoteComponent(..) | QuoteWat::Wat(Wat::Component(_))
+    ) {
+        return Err(unsupported("a component"));
+    }
+    Ok(module.encode().map_err(|error| error.message()))
+}
+
+/// The module `binary` as the arguments of `define` before its name, as a
+/// script of the page would load it: valid with the proposals enabled, not
+/// using shared memory the origin may not, and with its memories under the
+/// budget of the page; or why it is refused
+fn admitted(mut binary: Vec<u8>, options: &CompileOptions) -> Result<String, String> {
+    validate(&binary, options)?;
+    if options.threads_denied && capabilities::uses_shared_memory(&binary) {
+        return Err(
+            "module uses shared memory or threads, which the origin has no permission for"
+                .to_owned(),
+        );
+    }
+    let memory = match options.memory_budget {
+        Some(limit) => {
+            budget::cap_memories(&mut binary, limit)
+                .and_then(|_| budget::guard_growth(&mut binary))
+                .map_err(|error| error.to_string())?;
+            format!(
+                "{{ initial: {}, pages: {} }}",
+                budget::initial_bytes(&binary),
+                serde_json::to_string(&budget::page_bytes(&binary)).unwrap_or_default()
+            )
+        },
+        None => "null".to_owned(),
+    };
+    Ok(format!("{}, {}", embed::latin1_literal(&binary), memory))
+}
+
+/// Whether `binary` is valid with the proposals the options enable
+fn validate(binary: &[u8], options: &CompileOptions) -> Result<(), String> {
+    Validator::new_with_features(options.effective_features())
+        .validate_all(binary)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// Script returning a failure unless `exec` throws an error passing `test`
+fn throwing(
+    exec: WastExecute,
+    test: &str,
+    what: &str,
+    message: &str,
+    options: &CompileOptions,
+) -> Result<String, Unsupported> {
+    Ok(format!(
+        "return expectThrow(function() {{ return {}; }}, {}, '{}', {});",
+        execution(exec, options)?,
+        test,
+        what,
+        string(message)
+    ))
+}
+
+/// Expression performing `exec`, evaluating to its results
+fn execution(exec: WastExecute, options: &CompileOptions) -> Result<String, Unsupported> {
+    Ok(match exec {
+        WastExecute::Invoke(invoke) => invocation(&invoke)?,
+        WastExecute::Get { module, global, .. } => {
+            format!("get({}, {})", id(module), string(global))
+        },
+        WastExecute::Wat(Wat::Component(_)) => return Err(unsupported("a component")),
+        WastExecute::Wat(mut module) => match module
+            .encode()
+            .map_err(|error| error.message())
+            .and_then(|binary| admitted(binary, options))
+        {
+            Ok(module) => format!("instance(define({}, null))", module),
+            Err(error) => format!("(function() {{ throw new Error({}); }})()", string(&error)),
+        },
+    })
+}
+
+fn invocation(invoke: &WastInvoke) -> Result<String, Unsupported> {
+    let args = invoke
+        .args
+        .iter()
+        .map(argument)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!(
+        "invoke({}, {}, [{}])",
+        id(invoke.module),
+        string(invoke.name),
+        args.join(", ")
+    ))
+}
+
+fn argument(arg: &WastArg) -> Result<String, Unsupported> {
+    let WastArg::Core(arg) = arg else {
+        return Err(unsupported("a component value"));
+    };
+    Ok(match arg {
+        WastArgCore::I32(value) => value.to_string(),
+        WastArgCore::I64(value) => format!("{}n", value),
+        WastArgCore::F32(value) => format!("f32({:#x})", value.bits),
+        WastArgCore::F64(value) => format!("f64({:#x}n)", value.bits),
+        WastArgCore::V128(_) => return Err(unsupported("a v128 value")),
+        WastArgCore::RefNull(_) => "null".to_owned(),
+        WastArgCore::RefExtern(id) | WastArgCore::RefHost(id) => format!("hostRef({})", id),
+    })
+}
+
+/// A matcher of the runtime for the result `ret`
+fn expectation(ret: &WastRet) -> Result<String, Unsupported> {
+    match ret {
+        WastRet::Core(ret) => core_expectation(ret),
+        _ => Err(unsupported("a component value")),
+    }
+}
+
+fn core_expectation(ret: &WastRetCore) -> Result<String, Unsupported> {
+    let text = string(&describe(ret));
+    Ok(match ret {
+        WastRetCore::I32(value) => format!("expect.value({}, {})", text, value),
+        WastRetCore::I64(value) => format!("expect.value({}, {}n)", text, value),
+        WastRetCore::F32(NanPattern::Value(value)) => {
+            format!("expect.value({}, f32({:#x}))", text, value.bits)
+        },
+        WastRetCore::F64(NanPattern::Value(value)) => {
+            format!("expect.value({}, f64({:#x}n))", text, value.bits)
+        },
+        WastRetCore::F32(_) | WastRetCore::F64(_) => format!("expect.nan({})", text),
+        WastRetCore::V128(_) => return Err(unsupported("a v128 value")),
+        WastRetCore::RefNull(_) => format!("expect.value({}, null)", text),
+        WastRetCore::RefExtern(Some(id)) | WastRetCore::RefHost(id) => {
+            format!("expect.value({}, hostRef({}))", text, id)
+        },
+        WastRetCore::RefFunc(_) => format!("expect.func({})", text),
+        WastRetCore::RefI31 | WastRetCore::RefI31Shared => format!("expect.i31({})", text),
+        WastRetCore::RefExtern(None)
+        | WastRetCore::RefAny
+        | WastRetCore::RefEq
+        | WastRetCore::RefArray
+        | WastRetCore::RefStruct => format!("expect.nonNull({})", text),
+        WastRetCore::Either(alternatives) => {
+            let matchers = alternatives
+                .iter()
+                .map(core_expectation)
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("expect.either({}, [{}])", text, matchers.join(", "))
+        },
+    })
+}
+
+/// The result `ret` as written in a script, for failure messages
+fn describe(ret: &WastRetCore) -> String {
+    match ret {
+        WastRetCore::I32(value) => format!("i32.const {}", value),
+        WastRetCore::I64(value) => format!("i64.const {}", value),
+        WastRetCore::F32(NanPattern::Value(value)) => {
+            format!("f32.const {}", f32::from_bits(value.bits))
+        },
+        WastRetCore::F64(NanPattern::Value(value)) => {
+            format!("f64.const {}", f64::from_bits(value.bits))
+        },
+        WastRetCore::F32(NanPattern::CanonicalNan) => "f32.const nan:canonical".to_owned(),
+        WastRetCore::F32(NanPattern::ArithmeticNan) => "f32.const nan:arithmetic".to_owned(),
+        WastRetCore::F64(NanPattern::CanonicalNan) => "f64.const nan:canonical".to_owned(),
+        WastRetCore::F64(NanPattern::ArithmeticNan) => "f64.const nan:arithmetic".to_owned(),
+        WastRetCore::V128(_) => "v128.const".to_owned(),
+        WastRetCore::RefNull(Some(HeapType::Abstract { ty, .. })) => {
+            format!("ref.null {}", heap_type(*ty))
+        },
+        WastRetCore::RefNull(_) => "ref.null".to_owned(),
+        WastRetCore::RefExtern(Some(id)) => format!("ref.extern {}", id),
+        WastRetCore::RefExtern(None) => "ref.extern".to_owned(),
+        WastRetCore::RefHost(id) => format!("ref.host {}", id),
+        WastRetCore::RefFunc(_) => "ref.func".to_owned(),
+        WastRetCore::RefAny => "ref.any".to_owned(),
+        WastRetCore::RefEq => "ref.eq".to_owned(),
+        WastRetCore::RefArray => "ref.array".to_owned(),
+        WastRetCore::RefStruct => "ref.struct".to_owned(),
+        WastRetCore::RefI31 | WastRetCore::RefI31Shared => "ref.i31".to_owned(),
+        WastRetCore::Either(alternatives) => format!(
+            "either({})",
+            alternatives
+                .iter()
+                .map(describe)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn heap_type(ty: AbstractHeapType) -> &'static str {
+    match ty {
+        AbstractHeapType::Func => "func",
+        AbstractHeapType::Extern => "extern",
+        AbstractHeapType::Any => "any",
+        AbstractHeapType::Eq => "eq",
+        AbstractHeapType::Struct => "struct",
+        AbstractHeapType::Array => "array",
+        AbstractHeapType::I31 => "i31",
+        AbstractHeapType::None => "none",
+        AbstractHeapType::NoFunc => "nofunc",
+        AbstractHeapType::NoExtern => "noextern",
+        _ => "",
+    }
+}
+
+/// The name of a module as a JavaScript expression, `null` for the current
+/// one
+fn id(id: Option<Id>) -> String {
+    id.map_or_else(|| "null".to_owned(), |id| string(id.name()))
+}
+
+/// `text` as a JavaScript string literal
+fn string(text: &str) -> String {
+    embed::script_safe(&serde_json::to_string(text).unwrap_or_default())
+}
@@ -0,0 +1,148 @@
+// Copyright 2025 The Servo Project Developers.
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Lightweight helpers for reading WAT text
+//!
+//! The text-level passes (expression sugar, string interpolation) rewrite
+//! sources line by line and only need to understand module structure, not
+//! full WAT syntax: parenthesis depth, function boundaries and the
+//! declarations found in function headers.
+
+/// Minimal s-expression tree, used to read function and global headers
+#[derive(Debug)]
+pub(super) enum Sexpr {
+    Atom(String),
+    List(Vec<Sexpr>),
+}
+
+impl Sexpr {
+    pub(super) fn atom(&self) -> Option<&str> {
+        match self {
+            Sexpr::Atom(atom) => Some(atom),
+            Sexpr::List(_) => None,
+        }
+    }
+
+    /// Keyword of a list, e.g. `param` for `(param $x i32)`
+    pub(super) fn head(&self) -> Option<&str> {
+        match self {
+            Sexpr::List(items) => items.first().and_then(Sexpr::atom),
+            Sexpr::Atom(_) => None,
+        }
+    }
+
+    pub(super) fn items(&self) -> &[Sexpr] {
+        match self {
+            Sexpr::List(items) => items,
+            Sexpr::Atom(_) => &[],
+        }
+    }
+}
+
+/// Parse the first s-expression of `text`, closing any lists still open at
+/// the end so that a function header can be inspected without its body
+pub(super) fn parse_sexpr(text: &str) -> Option<Sexpr> {
+    let mut stack: Vec<Vec<Sexpr>> = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '(' if chars.peek().map(|&(_, c)| c) == Some(';') => {
+                // Block comment
+                let end = text[start..]
+                    .find(";)")
+                    .map_or(text.len(), |end| start + end + 2);
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            },
+            ';' if chars.peek().map(|&(_, c)| c) == Some(';') => {
+                // Line comment
+                while chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                    chars.next();
+                }
+            },
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = Sexpr::List(stack.pop()?);
+                match stack.last_mut() {
+                    Some(parent) => parent.push(list),
+                    None => return Some(list),
+                }
+            },
+            c if c.is_whitespace() => {},
+            _ => {
+                let mut end = start + c.len_utf8();
+                let in_string = c == '"';
+                while let Some(&(i, c)) = chars.peek() {
+                    if in_string {
+                        chars.next();
+                        end = i + c.len_utf8();
+                        if c == '"' {
+                            break;
+                        }
+                        continue;
+                    }
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    end = i + c.len_utf8();
+                }
+                stack
+                    .last_mut()?
+                    .push(Sexpr::Atom(text[start..end].to_string()));
+            },
+        }
+    }
+
+    // Auto-close unterminated lists
+    let mut closed = None;
+    while let Some(mut items) = stack.pop() {
+        if let Some(inner) = closed.take() {
+            items.push(inner);
+        }
+        closed = Some(Sexpr::List(items));
+    }
+    closed
+}
+
+/// Net parenthesis depth change of a line, ignoring strings and comments
+pub(super) fn paren_delta(line: &str) -> i32 {
+    let mut delta = 0;
+    let mut in_string = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            },
+            ';' if !in_string && chars.peek() == Some(&';') => break,
+            '(' if !in_string && chars.peek() == Some(&';') => {
+                // Skip an inline block comment
+                while let Some(c) = chars.next() {
+                    if c == ';' && chars.peek() == Some(&')') {
+                        chars.next();
+                        break;
+                    }
+                }
+            },
+            '(' if !in_string => delta += 1,
+            ')' if !in_string => delta -= 1,
+            _ => {},
+        }
+    }
+    delta
+}
+
+/// Lines that continue a function header rather than starting its body
+pub(super) fn is_header_line(trimmed: &str) -> bool {
+    ["(param", "(result", "(local", "(export", "(import", "(type"]
+        .iter()
+        .any(|keyword| trimmed.starts_with(keyword))
+}
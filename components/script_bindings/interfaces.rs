@@ -102,6 +102,10 @@ pub trait TestBindingHelpers {
     fn condition_unsatisfied(cx: JSContext, global: HandleObject) -> bool;
 }
 
+pub trait WatCompilerHelpers {
+    fn is_enabled(cx: JSContext, global: HandleObject) -> bool;
+}
+
 pub trait WebGL2RenderingContextHelpers {
     fn is_webgl2_enabled(cx: JSContext, global: HandleObject) -> bool;
 }
@@ -188,6 +188,8 @@ pub struct TimelineMarker {
 pub enum TimelineMarkerType {
     Reflow,
     DOMEvent,
+    /// Phases of compiling and loading a WAT script
+    Wasm,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -115,6 +115,11 @@ pub enum ProfilerCategory {
 
     ScriptDatabaseAccessEvent = 0x81,
 
+    /// Compiling a WAT or WASM script that was not in the compilation cache.
+    ScriptWasmCompile = 0x82,
+    /// Serving a WAT or WASM script from the compilation cache.
+    ScriptWasmCacheHit = 0x83,
+
     /// Web performance metrics.
     TimeToFirstPaint = 0x90,
     TimeToFirstContentfulPaint = 0x91,
@@ -164,6 +169,8 @@ impl ProfilerCategory {
             ProfilerCategory::ScriptHistoryEvent => "ScriptHistoryEvent",
             ProfilerCategory::ScriptPortMessage => "ScriptPortMessage",
             ProfilerCategory::ScriptWebGPUMsg => "ScriptWebGPUMsg",
+            ProfilerCategory::ScriptWasmCompile => "ScriptWasmCompile",
+            ProfilerCategory::ScriptWasmCacheHit => "ScriptWasmCacheHit",
             ProfilerCategory::TimeToFirstPaint => "TimeToFirstPaint",
             ProfilerCategory::TimeToFirstContentfulPaint => "TimeToFirstContentfulPaint",
             ProfilerCategory::TimeToLargestContentfulPaint => "TimeToLargestContentfulPaint",